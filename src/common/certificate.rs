@@ -51,17 +51,10 @@ impl Certificate {
     }
 
     fn type_code_to_sigtype(type_code: usize) -> Option<SignatureType> {
-        match type_code {
-            0 => Some(SignatureType::DSA_SHA1),
-            1 => Some(SignatureType::ECDSA_SHA256_P256),
-            2 => Some(SignatureType::ECDSA_SHA384_P384),
-            3 => Some(SignatureType::ECDSA_SHA512_P521),
-            4 => Some(SignatureType::RSA_SHA256_2048),
-            5 => Some(SignatureType::RSA_SHA384_3072),
-            6 => Some(SignatureType::RSA_SHA512_4096),
-            7 => Some(SignatureType::EdDSA_SHA512_Ed25519),
-            8 => Some(SignatureType::EdDSA_SHA512_Ed25519ph),
-            _ => None
+        if type_code <= 0xFFFF {
+            SignatureType::from_code(type_code as u16)
+        } else {
+            None
         }
     }
 
@@ -0,0 +1,157 @@
+use rand;
+use rand::Rng;
+use common::i2p_base64::{ToI2pBase64, FromI2pBase64};
+use common::i2p_hash::{Hash256, Hashable256};
+use common::signature::{SignatureType, SigningPublicKey};
+
+
+/// The length of the encryption public key at the start of a `Destination`.
+const PUBLIC_KEY_LENGTH: usize = 256;
+
+/// The length of the area holding the signing public key. Shorter keys are
+/// right-aligned in it behind random padding; longer keys continue in the key
+/// certificate.
+const SIGNING_KEY_AREA_LENGTH: usize = 128;
+
+const CERTIFICATE_NULL: u8 = 0;
+const CERTIFICATE_KEY: u8 = 5;
+
+/// The length of a `Destination` with a null certificate, the shortest there is.
+pub const I2P_DESTINATION_MIN_LENGTH: usize = PUBLIC_KEY_LENGTH + SIGNING_KEY_AREA_LENGTH + 3;
+
+/// A `Destination` is the identity of an I2P client endpoint: an encryption public
+/// key, a signing public key and a certificate. Destinations with signature types
+/// other than DSA_SHA1 carry a key certificate naming the signature type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Destination {
+    bytes: Vec<u8>
+}
+
+impl Destination {
+    /// Creates a destination from an encryption public key and a signing public key.
+    /// Unused space in the key area is filled with random padding.
+    pub fn new(public_key: &[u8; PUBLIC_KEY_LENGTH], signing_key: &SigningPublicKey) -> Destination {
+        let key = signing_key.as_ref();
+        let sigtype = signing_key.signature_type();
+
+        let mut bytes = public_key.to_vec();
+        if key.len() <= SIGNING_KEY_AREA_LENGTH {
+            let mut padding = vec![0x00; SIGNING_KEY_AREA_LENGTH - key.len()];
+            rand::thread_rng().fill_bytes(&mut padding);
+            bytes.extend_from_slice(&padding);
+            bytes.extend_from_slice(key);
+        } else {
+            bytes.extend_from_slice(&key[..SIGNING_KEY_AREA_LENGTH]);
+        }
+
+        if sigtype == SignatureType::DSA_SHA1 {
+            bytes.extend_from_slice(&[CERTIFICATE_NULL, 0x00, 0x00]);
+        } else {
+            let excess = if key.len() > SIGNING_KEY_AREA_LENGTH { &key[SIGNING_KEY_AREA_LENGTH..] } else { &[][..] };
+            bytes.push(CERTIFICATE_KEY);
+            bytes.extend_from_slice(&((4 + excess.len()) as u16).to_be_bytes());
+            bytes.extend_from_slice(&sigtype.code().to_be_bytes());
+            bytes.extend_from_slice(&[0x00, 0x00]);
+            bytes.extend_from_slice(excess);
+        }
+
+        Destination {
+            bytes
+        }
+    }
+
+    /// Parses a destination at the start of `bytes`. Returns the destination and the
+    /// number of bytes it took, or `None` if the bytes are too short.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Destination, usize)> {
+        if bytes.len() < I2P_DESTINATION_MIN_LENGTH {
+            return None;
+        }
+        let offset = PUBLIC_KEY_LENGTH + SIGNING_KEY_AREA_LENGTH;
+        let certificate_length = ((bytes[offset + 1] as usize) << 8) | (bytes[offset + 2] as usize);
+        let length = I2P_DESTINATION_MIN_LENGTH + certificate_length;
+        if bytes.len() < length {
+            return None;
+        }
+
+        let destination = Destination {
+            bytes: bytes[..length].to_vec()
+        };
+
+        Some((destination, length))
+    }
+
+    /// Parses the textual form of a destination, which is its bytes in the I2P base64
+    /// alphabet. Trailing bytes, such as the private keys that follow a destination
+    /// in SAM and key files, are refused.
+    pub fn from_i2p_base64(text: &str) -> Option<Destination> {
+        let bytes = text.from_i2p_base64()?;
+        match Destination::from_bytes(&bytes) {
+            Some((destination, length)) if length == bytes.len() => Some(destination),
+            _ => None
+        }
+    }
+
+    /// Returns the textual form of the destination.
+    pub fn to_i2p_base64(&self) -> String {
+        self.bytes.to_i2p_base64()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.bytes[..PUBLIC_KEY_LENGTH]
+    }
+
+    fn certificate(&self) -> (u8, &[u8]) {
+        let offset = PUBLIC_KEY_LENGTH + SIGNING_KEY_AREA_LENGTH;
+        (self.bytes[offset], &self.bytes[I2P_DESTINATION_MIN_LENGTH..])
+    }
+
+    /// Returns the signature type, or `None` if the certificate names one we do not
+    /// know.
+    pub fn signature_type(&self) -> Option<SignatureType> {
+        match self.certificate() {
+            (CERTIFICATE_NULL, _) => Some(SignatureType::DSA_SHA1),
+            (CERTIFICATE_KEY, payload) if payload.len() >= 4 => {
+                SignatureType::from_code(((payload[0] as u16) << 8) | (payload[1] as u16))
+            }
+            _ => None
+        }
+    }
+
+    /// Returns the signing public key, which signs the destination's LeaseSets and
+    /// I2CP session configurations.
+    pub fn signing_public_key(&self) -> Option<SigningPublicKey> {
+        let sigtype = self.signature_type()?;
+        let length = sigtype.public_key_length();
+        let area = &self.bytes[PUBLIC_KEY_LENGTH..PUBLIC_KEY_LENGTH + SIGNING_KEY_AREA_LENGTH];
+
+        if length <= SIGNING_KEY_AREA_LENGTH {
+            SigningPublicKey::from_bytes(sigtype, &area[SIGNING_KEY_AREA_LENGTH - length..])
+        } else {
+            let mut key = area.to_vec();
+            key.extend_from_slice(self.certificate().1.get(4..4 + length - SIGNING_KEY_AREA_LENGTH)?);
+            SigningPublicKey::from_bytes(sigtype, &key)
+        }
+    }
+}
+
+impl AsRef<[u8]> for Destination {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+}
+
+/// The hash of a destination identifies it in `.b32.i2p` addresses and the network
+/// database.
+impl Hashable256 for Destination {
+    fn hash_sha256(&self) -> Hash256 {
+        self.bytes.hash_sha256()
+    }
+}
@@ -0,0 +1,57 @@
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use common::signature::{Signature, SignatureType, SignatureVerifier, SessionSigner, SigningPublicKey};
+
+
+/// An `Ed25519Verifier` checks `EdDSA_SHA512_Ed25519` signatures, which every current
+/// router signs its RouterInfo with. Signatures of any other type are rejected.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Ed25519Verifier;
+
+impl SignatureVerifier for Ed25519Verifier {
+    fn verify(&self, key: &SigningPublicKey, message: &[u8], signature: &Signature) -> bool {
+        if key.signature_type() != SignatureType::EdDSA_SHA512_Ed25519
+            || signature.signature_type() != SignatureType::EdDSA_SHA512_Ed25519 {
+            return false;
+        }
+        let mut key_bytes = [0x00; 32];
+        key_bytes.copy_from_slice(key.as_ref());
+        let key = match VerifyingKey::from_bytes(&key_bytes) {
+            Ok(key) => key,
+            Err(_) => return false
+        };
+        let signature = match ed25519_dalek::Signature::from_slice(signature.as_ref()) {
+            Ok(signature) => signature,
+            Err(_) => return false
+        };
+
+        key.verify(message, &signature).is_ok()
+    }
+}
+
+/// An `Ed25519Signer` signs with an `EdDSA_SHA512_Ed25519` private key, such as a
+/// router's identity key.
+#[derive(Clone)]
+pub struct Ed25519Signer {
+    key: SigningKey
+}
+
+impl Ed25519Signer {
+    /// Creates a signer from the 32 byte seed of a private key.
+    pub fn new(private_key: &[u8; 32]) -> Ed25519Signer {
+        Ed25519Signer {
+            key: SigningKey::from_bytes(private_key)
+        }
+    }
+
+    /// Returns the public key that verifies this signer's signatures.
+    pub fn public_key(&self) -> SigningPublicKey {
+        SigningPublicKey::from_bytes(SignatureType::EdDSA_SHA512_Ed25519, self.key.verifying_key().as_bytes()).unwrap()
+    }
+}
+
+impl SessionSigner for Ed25519Signer {
+    fn sign(&self, message: &[u8]) -> Signature {
+        let signature = self.key.sign(message);
+        Signature::from_bytes(SignatureType::EdDSA_SHA512_Ed25519, &signature.to_bytes()).unwrap()
+    }
+}
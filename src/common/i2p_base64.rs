@@ -0,0 +1,78 @@
+use rustc_serialize::base64::{ToBase64, FromBase64};
+use rustc_serialize::base64;
+
+
+/// The I2P base64 alphabet is the standard base64 alphabet with `+` replaced by `-`
+/// and `/` replaced by `~`. It is used for file names in the network database and
+/// for the textual representation of destinations.
+fn config() -> base64::Config {
+    base64::Config {
+        char_set: base64::CharacterSet::Standard,
+        newline: base64::Newline::LF,
+        pad: true,
+        line_length: None
+    }
+}
+
+/// The `ToI2pBase64` trait converts a value into a string in the I2P base64 alphabet.
+pub trait ToI2pBase64 {
+    fn to_i2p_base64(&self) -> String;
+}
+
+/// The `FromI2pBase64` trait decodes a string in the I2P base64 alphabet. It returns
+/// `None` when the input contains characters from the standard alphabet or is malformed.
+pub trait FromI2pBase64 {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_i2p_base64(&self) -> Option<Vec<u8>>;
+}
+
+impl ToI2pBase64 for [u8] {
+    fn to_i2p_base64(&self) -> String {
+        self.to_base64(config())
+            .chars()
+            .map(|ch| match ch {
+                '+' => '-',
+                '/' => '~',
+                _   => ch
+            })
+            .collect()
+    }
+}
+
+impl FromI2pBase64 for str {
+    fn from_i2p_base64(&self) -> Option<Vec<u8>> {
+        if self.contains('+') || self.contains('/') {
+            return None;
+        }
+
+        let standard: String = self.chars()
+            .map(|ch| match ch {
+                '-' => '+',
+                '~' => '/',
+                _   => ch
+            })
+            .collect();
+
+        standard.from_base64().ok()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ToI2pBase64, FromI2pBase64};
+
+
+    #[test]
+    fn test_encoding_should_use_the_i2p_alphabet() {
+        let bytes: [u8; 3] = [0xFB, 0xFF, 0xBF];
+
+        assert_eq!(bytes.to_i2p_base64(), "-~-~");
+    }
+
+    #[test]
+    fn test_decoding_should_reject_the_standard_alphabet() {
+        assert!("+/+/".from_i2p_base64().is_none());
+        assert_eq!("-~-~".from_i2p_base64(), Some(vec![0xFB, 0xFF, 0xBF]));
+    }
+}
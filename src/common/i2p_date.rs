@@ -69,10 +69,23 @@ impl I2pDate {
         }
     }
 
+    /// Returns the current time as an `I2pDate`.
+    pub fn now() -> I2pDate {
+        let now = utc::UTC::now();
+        let milliseconds = (now.timestamp() as u64) * 1000 + (now.timestamp_subsec_millis() as u64);
+
+        I2pDate::new_unchecked(I2pInt64::new(milliseconds))
+    }
+
     pub fn len(&self) -> usize {
         I2P_DATE_LENGTH_BYTES
     }
 
+    /// Returns the number of milliseconds since the UNIX epoch.
+    pub fn to_u64(&self) -> u64 {
+        self.milliseconds.to_u64()
+    }
+
     pub fn to_bytes_be(&self) -> Vec<u8> {
         self.milliseconds.to_bytes_be()
     }
@@ -2,6 +2,7 @@ use std::fmt;
 use std::fmt::Write;
 use rustc_serialize::base64::ToBase64;
 use rustc_serialize::base64;
+use sha2::{Digest, Sha256};
 
 
 const I2P_SHA256_HASH_LENGTH: usize = 32;
@@ -24,6 +25,16 @@ impl Hashable256 for Hash256 {
     }
 }
 
+/// Hashes bytes with SHA256.
+impl Hashable256 for [u8] {
+    fn hash_sha256(&self) -> Hash256 {
+        let mut hash = [0x00; I2P_SHA256_HASH_LENGTH];
+        hash.copy_from_slice(&Sha256::digest(self));
+
+        Hash256::new(hash)
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,71 @@
+use common::i2p_date::I2pDate;
+use common::i2p_hash::Hash256;
+use common::i2p_integer::{I2pInt32, I2pInt64};
+
+
+/// The length of a serialized `Lease`.
+pub const I2P_LEASE_LENGTH: usize = 44;
+
+/// A `Lease` authorizes a tunnel to receive messages for a destination until its
+/// end date. It names the gateway router of an inbound tunnel and the tunnel ID at
+/// that gateway.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lease {
+    pub gateway: Hash256,
+    pub tunnel_id: I2pInt32,
+    pub end_date: I2pDate
+}
+
+impl Lease {
+    pub fn new(gateway: Hash256, tunnel_id: I2pInt32, end_date: I2pDate) -> Lease {
+        Lease {
+            gateway,
+            tunnel_id,
+            end_date
+        }
+    }
+
+    /// Serializes the lease: the gateway hash, the tunnel ID and the end date.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(I2P_LEASE_LENGTH);
+        bytes.extend_from_slice(self.gateway.as_ref());
+        bytes.extend_from_slice(&self.tunnel_id.to_bytes_be());
+        bytes.extend_from_slice(&self.end_date.to_u64().to_be_bytes());
+
+        bytes
+    }
+
+    /// Parses a serialized lease. Returns `None` if the bytes are not a lease.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Lease> {
+        if bytes.len() != I2P_LEASE_LENGTH {
+            return None;
+        }
+
+        let mut gateway = [0x00; 32];
+        gateway.copy_from_slice(&bytes[0..32]);
+        let tunnel_id = I2pInt32::from_bytes_be(&bytes[32..36])?;
+        let end_date = I2pDate::new(I2pInt64::from_bytes_be(&bytes[36..44])?).ok()?;
+
+        Some(Lease::new(Hash256::from(gateway), tunnel_id, end_date))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use common::{Hash256, I2pDate, I2pInt32, I2pInt64};
+    use super::Lease;
+
+
+    #[test]
+    fn test_lease_should_survive_a_serialization_round_trip() {
+        let end_date = I2pDate::new(I2pInt64::new(1_500_000_000_000)).unwrap();
+        let lease = Lease::new(Hash256::from([0x07; 32]), I2pInt32::new(1234), end_date);
+
+        let bytes = lease.to_bytes();
+
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(Lease::from_bytes(&bytes), Some(lease));
+        assert_eq!(Lease::from_bytes(&bytes[1..]), None);
+    }
+}
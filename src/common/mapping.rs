@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::btree_map;
+use std::str;
+use common::i2p_string::{I2pStringError, I2P_MAX_STRING_LENGTH};
+
+
+/// A `Mapping` is a set of key/value string pairs, used for router and session
+/// options. It is serialized as a two byte size followed by `key=value;` entries,
+/// where keys and values are I2P strings. Entries are kept sorted by key, as signed
+/// structures such as the I2CP SessionConfig require.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mapping {
+    entries: BTreeMap<String, String>
+}
+
+impl Mapping {
+    pub fn new() -> Mapping {
+        Mapping {
+            entries: BTreeMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|value| value.as_str())
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<'_, String, String> {
+        self.entries.iter()
+    }
+
+    /// Inserts an entry, replacing any previous value for the key. Keys and values
+    /// are limited to 255 bytes.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<(), I2pStringError> {
+        for string in &[key, value] {
+            if string.len() > I2P_MAX_STRING_LENGTH {
+                return Err(I2pStringError::NotEnoughCapacity(string.len(), I2P_MAX_STRING_LENGTH));
+            }
+        }
+        self.entries.insert(key.to_string(), value.to_string());
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// Returns the entries as a `HashMap`, the form option parsers take.
+    pub fn to_hash_map(&self) -> HashMap<String, String> {
+        self.entries.iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+    }
+
+    /// Serializes the mapping, sorted by key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries = Vec::new();
+        for (key, value) in self.entries.iter() {
+            entries.push(key.len() as u8);
+            entries.extend_from_slice(key.as_bytes());
+            entries.push(b'=');
+            entries.push(value.len() as u8);
+            entries.extend_from_slice(value.as_bytes());
+            entries.push(b';');
+        }
+
+        let mut bytes = (entries.len() as u16).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&entries);
+
+        bytes
+    }
+
+    /// Parses a serialized mapping at the start of `bytes`. Returns the mapping and
+    /// the number of bytes it took, or `None` if the bytes are not a mapping.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Mapping, usize)> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        let size = ((bytes[0] as usize) << 8) | (bytes[1] as usize);
+        let data = bytes.get(2..2 + size)?;
+
+        let mut mapping = Mapping::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let (key, next) = read_string(data, offset)?;
+            if data.get(next) != Some(&b'=') {
+                return None;
+            }
+            let (value, next) = read_string(data, next + 1)?;
+            if data.get(next) != Some(&b';') {
+                return None;
+            }
+
+            mapping.entries.insert(key.to_string(), value.to_string());
+            offset = next + 1;
+        }
+
+        Some((mapping, 2 + size))
+    }
+}
+
+fn read_string(data: &[u8], offset: usize) -> Option<(&str, usize)> {
+    let length = *data.get(offset)? as usize;
+    let bytes = data.get(offset + 1..offset + 1 + length)?;
+
+    Some((str::from_utf8(bytes).ok()?, offset + 1 + length))
+}
+
+impl<'a> From<&'a HashMap<String, String>> for Mapping {
+    fn from(map: &'a HashMap<String, String>) -> Mapping {
+        Mapping {
+            entries: map.iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+        }
+    }
+}
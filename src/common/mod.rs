@@ -1,5 +1,6 @@
 pub use self::i2p_string::I2pString;
 pub use self::i2p_string::I2P_MAX_STRING_LENGTH;
+pub use self::i2p_string::I2pStringError;
 pub use self::i2p_date::I2pDate;
 pub use self::i2p_integer::I2pInt8;
 pub use self::i2p_integer::I2pInt16;
@@ -12,17 +13,29 @@ pub use self::i2p_integer::I2pInt64;
 pub use self::public_key::PublicKey;
 pub use self::private_key::PrivateKey;
 pub use self::i2p_hash::Hash256;
+pub use self::i2p_hash::Hashable256;
+pub use self::i2p_base64::{ToI2pBase64, FromI2pBase64};
 pub use self::session_key::SessionKey;
 pub use self::session_tag::SessionTag;
+pub use self::signature::SignatureType;
 pub use self::signature::SigningPublicKey;
 pub use self::signature::SigningPrivateKey;
 pub use self::signature::Signature;
+pub use self::signature::{SignatureVerifier, SessionSigner};
+pub use self::ed25519::{Ed25519Verifier, Ed25519Signer};
 pub use self::certificate::Certificate;
+pub use self::lease::{Lease, I2P_LEASE_LENGTH};
+pub use self::mapping::Mapping;
+pub use self::destination::{Destination, I2P_DESTINATION_MIN_LENGTH};
+pub use self::router_identity::RouterIdentity;
+pub use self::router_address::RouterAddress;
+pub use self::router_info::RouterInfo;
 
 
 mod i2p_integer;
 mod i2p_date;
 mod i2p_string;
+mod i2p_base64;
 
 #[macro_use]
 mod simple_data_structure;
@@ -33,4 +46,11 @@ mod private_key;
 mod session_key;
 mod session_tag;
 mod signature;
+mod ed25519;
 mod certificate;
+mod lease;
+mod mapping;
+mod destination;
+mod router_identity;
+mod router_address;
+mod router_info;
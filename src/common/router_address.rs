@@ -0,0 +1,80 @@
+use std::str;
+use common::i2p_string::{I2pStringError, I2P_MAX_STRING_LENGTH};
+use common::mapping::Mapping;
+
+
+/// The length of the expiration date, which is always zero.
+const EXPIRATION_LENGTH: usize = 8;
+
+/// A `RouterAddress` tells other routers how to reach a router over one transport:
+/// its cost, the transport style such as `NTCP2` or `SSU2`, and the transport's
+/// options, such as its host, port and static key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouterAddress {
+    cost: u8,
+    transport_style: String,
+    options: Mapping
+}
+
+impl RouterAddress {
+    /// Creates an address. The transport style is limited to the length of an I2P
+    /// string.
+    pub fn new(cost: u8, transport_style: &str, options: Mapping) -> Result<RouterAddress, I2pStringError> {
+        if transport_style.len() > I2P_MAX_STRING_LENGTH {
+            return Err(I2pStringError::NotEnoughCapacity(transport_style.len(), I2P_MAX_STRING_LENGTH));
+        }
+
+        Ok(RouterAddress {
+            cost,
+            transport_style: transport_style.to_string(),
+            options
+        })
+    }
+
+    /// Returns the relative cost of the address, where lower is preferred.
+    pub fn cost(&self) -> u8 {
+        self.cost
+    }
+
+    pub fn transport_style(&self) -> &str {
+        &self.transport_style
+    }
+
+    pub fn options(&self) -> &Mapping {
+        &self.options
+    }
+
+    /// Returns the value of an option, such as `host` or `port`.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.get(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.cost];
+        bytes.extend_from_slice(&[0x00; EXPIRATION_LENGTH]);
+        bytes.push(self.transport_style.len() as u8);
+        bytes.extend_from_slice(self.transport_style.as_bytes());
+        bytes.extend_from_slice(&self.options.to_bytes());
+
+        bytes
+    }
+
+    /// Parses an address at the start of `bytes`. Returns the address and the number
+    /// of bytes it took, or `None` if the bytes are not an address.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(RouterAddress, usize)> {
+        let cost = *bytes.first()?;
+        let offset = 1 + EXPIRATION_LENGTH;
+        let style_length = *bytes.get(offset)? as usize;
+        let style = bytes.get(offset + 1..offset + 1 + style_length)?;
+        let offset = offset + 1 + style_length;
+        let (options, length) = Mapping::from_bytes(&bytes[offset..])?;
+
+        let address = RouterAddress {
+            cost,
+            transport_style: str::from_utf8(style).ok()?.to_string(),
+            options
+        };
+
+        Some((address, offset + length))
+    }
+}
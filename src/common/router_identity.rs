@@ -0,0 +1,63 @@
+use common::destination::Destination;
+use common::i2p_hash::{Hash256, Hashable256};
+use common::signature::{SignatureType, SigningPublicKey};
+
+
+/// A `RouterIdentity` is the identity of an I2P router: an encryption public key, a
+/// signing public key and a certificate. It has the same layout as a `Destination`,
+/// and its hash is the router's address in the network database.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RouterIdentity {
+    keys: Destination
+}
+
+impl RouterIdentity {
+    /// Creates a router identity from an encryption public key and a signing public
+    /// key. Unused space in the key area is filled with random padding.
+    pub fn new(public_key: &[u8; 256], signing_key: &SigningPublicKey) -> RouterIdentity {
+        RouterIdentity {
+            keys: Destination::new(public_key, signing_key)
+        }
+    }
+
+    /// Parses a router identity at the start of `bytes`. Returns the identity and the
+    /// number of bytes it took, or `None` if the bytes are too short.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(RouterIdentity, usize)> {
+        Destination::from_bytes(bytes).map(|(keys, length)| (RouterIdentity { keys }, length))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.keys.public_key()
+    }
+
+    /// Returns the signature type, or `None` if the certificate names one we do not
+    /// know.
+    pub fn signature_type(&self) -> Option<SignatureType> {
+        self.keys.signature_type()
+    }
+
+    /// Returns the signing public key, which signs the router's RouterInfo.
+    pub fn signing_public_key(&self) -> Option<SigningPublicKey> {
+        self.keys.signing_public_key()
+    }
+}
+
+impl AsRef<[u8]> for RouterIdentity {
+    fn as_ref(&self) -> &[u8] {
+        self.keys.as_ref()
+    }
+}
+
+impl Hashable256 for RouterIdentity {
+    fn hash_sha256(&self) -> Hash256 {
+        self.keys.hash_sha256()
+    }
+}
@@ -0,0 +1,166 @@
+use common::i2p_date::I2pDate;
+use common::i2p_hash::{Hash256, Hashable256};
+use common::i2p_integer::I2pInt64;
+use common::mapping::Mapping;
+use common::router_address::RouterAddress;
+use common::router_identity::RouterIdentity;
+use common::signature::{Signature, SignatureVerifier, SessionSigner};
+
+
+const DATE_LENGTH: usize = 8;
+
+/// A `RouterInfo` is what a router publishes about itself in the network database:
+/// its identity, the addresses it can be reached at and its capabilities, signed with
+/// the identity's signing key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouterInfo {
+    identity: RouterIdentity,
+    published: I2pDate,
+    addresses: Vec<RouterAddress>,
+    peers: Vec<Hash256>,
+    options: Mapping,
+    signature: Signature
+}
+
+impl RouterInfo {
+    /// Creates a router info and signs it with `signer`, which must hold the private
+    /// key of the identity's signing key. At most 255 addresses are kept.
+    pub fn new<S>(identity: RouterIdentity,
+                  published: I2pDate,
+                  addresses: Vec<RouterAddress>,
+                  options: Mapping,
+                  signer: &S) -> RouterInfo where S: SessionSigner {
+
+        let mut router_info = RouterInfo {
+            identity,
+            published,
+            addresses,
+            peers: Vec::new(),
+            options,
+            signature: Signature::default()
+        };
+        router_info.addresses.truncate(u8::MAX as usize);
+        router_info.signature = signer.sign(&router_info.signed_bytes());
+
+        router_info
+    }
+
+    pub fn identity(&self) -> &RouterIdentity {
+        &self.identity
+    }
+
+    pub fn published(&self) -> I2pDate {
+        self.published
+    }
+
+    pub fn addresses(&self) -> &[RouterAddress] {
+        &self.addresses
+    }
+
+    /// Returns the cheapest address for a transport style, such as `NTCP2`.
+    pub fn address(&self, transport_style: &str) -> Option<&RouterAddress> {
+        self.addresses.iter()
+            .filter(|address| address.transport_style() == transport_style)
+            .min_by_key(|address| address.cost())
+    }
+
+    /// Returns the peer hashes of the restricted route, which is empty in practice.
+    pub fn peers(&self) -> &[Hash256] {
+        &self.peers
+    }
+
+    pub fn options(&self) -> &Mapping {
+        &self.options
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Checks the signature against the identity's signing key.
+    pub fn verify<V>(&self, verifier: &V) -> bool where V: SignatureVerifier {
+        match self.identity.signing_public_key() {
+            Some(key) => verifier.verify(&key, &self.signed_bytes(), &self.signature),
+            None => false
+        }
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.identity.as_ref().to_vec();
+        bytes.extend_from_slice(&self.published.to_bytes_be());
+        bytes.push(self.addresses.len() as u8);
+        for address in &self.addresses {
+            bytes.extend_from_slice(&address.to_bytes());
+        }
+        bytes.push(self.peers.len() as u8);
+        for peer in &self.peers {
+            bytes.extend_from_slice(peer.as_ref());
+        }
+        bytes.extend_from_slice(&self.options.to_bytes());
+
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.signed_bytes();
+        bytes.extend_from_slice(self.signature.as_ref());
+
+        bytes
+    }
+
+    /// Parses a router info at the start of `bytes`. Returns the router info and the
+    /// number of bytes it took, or `None` if the bytes are not a router info. The
+    /// signature is not checked.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(RouterInfo, usize)> {
+        let (identity, mut offset) = RouterIdentity::from_bytes(bytes)?;
+        let published = I2pInt64::from_bytes_be(bytes.get(offset..offset + DATE_LENGTH)?)?;
+        let published = I2pDate::new(published).ok()?;
+        offset += DATE_LENGTH;
+
+        let count = *bytes.get(offset)?;
+        offset += 1;
+        let mut addresses = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (address, length) = RouterAddress::from_bytes(&bytes[offset..])?;
+            addresses.push(address);
+            offset += length;
+        }
+
+        // Restricted routes were never used, but the peer hashes are kept since they
+        // are covered by the signature.
+        let count = *bytes.get(offset)?;
+        offset += 1;
+        let mut peers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut peer = [0x00; 32];
+            peer.copy_from_slice(bytes.get(offset..offset + 32)?);
+            peers.push(Hash256::from(peer));
+            offset += 32;
+        }
+        let (options, length) = Mapping::from_bytes(bytes.get(offset..)?)?;
+        offset += length;
+
+        let sigtype = identity.signature_type()?;
+        let signature = bytes.get(offset..offset + sigtype.signature_length())?;
+        let signature = Signature::from_bytes(sigtype, signature)?;
+        offset += sigtype.signature_length();
+
+        let router_info = RouterInfo {
+            identity,
+            published,
+            addresses,
+            peers,
+            options,
+            signature
+        };
+
+        Some((router_info, offset))
+    }
+}
+
+/// A router info is keyed in the network database by the hash of its identity.
+impl Hashable256 for RouterInfo {
+    fn hash_sha256(&self) -> Hash256 {
+        self.identity.hash_sha256()
+    }
+}
@@ -31,6 +31,53 @@ pub enum SignatureType {
 }
 
 impl SignatureType {
+    /// Returns the signature type for a type code, as used in key certificates
+    /// and SU3 headers.
+    pub fn from_code(type_code: u16) -> Option<SignatureType> {
+        match type_code {
+            0 => Some(SignatureType::DSA_SHA1),
+            1 => Some(SignatureType::ECDSA_SHA256_P256),
+            2 => Some(SignatureType::ECDSA_SHA384_P384),
+            3 => Some(SignatureType::ECDSA_SHA512_P521),
+            4 => Some(SignatureType::RSA_SHA256_2048),
+            5 => Some(SignatureType::RSA_SHA384_3072),
+            6 => Some(SignatureType::RSA_SHA512_4096),
+            7 => Some(SignatureType::EdDSA_SHA512_Ed25519),
+            8 => Some(SignatureType::EdDSA_SHA512_Ed25519ph),
+            _ => None
+        }
+    }
+
+    /// Returns the type code of the signature type.
+    pub fn code(&self) -> u16 {
+        match *self {
+            SignatureType::DSA_SHA1               => 0,
+            SignatureType::ECDSA_SHA256_P256      => 1,
+            SignatureType::ECDSA_SHA384_P384      => 2,
+            SignatureType::ECDSA_SHA512_P521      => 3,
+            SignatureType::RSA_SHA256_2048        => 4,
+            SignatureType::RSA_SHA384_3072        => 5,
+            SignatureType::RSA_SHA512_4096        => 6,
+            SignatureType::EdDSA_SHA512_Ed25519   => 7,
+            SignatureType::EdDSA_SHA512_Ed25519ph => 8
+        }
+    }
+
+    /// Returns the length in bytes of a signing public key of this type.
+    pub fn public_key_length(&self) -> usize {
+        SigningPublicKey::signing_length(*self)
+    }
+
+    /// Returns the length in bytes of a signing private key of this type.
+    pub fn private_key_length(&self) -> usize {
+        SigningPrivateKey::signing_length(*self)
+    }
+
+    /// Returns the length in bytes of a signature of this type.
+    pub fn signature_length(&self) -> usize {
+        Signature::signing_length(*self)
+    }
+
     /// Determines whether the SignatureType is represented as
     /// little endian. Not all signature types are transmitted and stored in
     /// network byte order. In particular, EdDSA_SHA512_Ed25519 and EdDSA_SHA512_Ed25519ph
@@ -46,6 +93,19 @@ impl SignatureType {
     }
 }
 
+/// The `SignatureVerifier` trait checks a signature over a message. Implementations
+/// are responsible for hashing the message as required by the signature type, for
+/// example SHA512 for `RSA_SHA512_4096`.
+pub trait SignatureVerifier {
+    fn verify(&self, key: &SigningPublicKey, message: &[u8], signature: &Signature) -> bool;
+}
+
+/// The `SessionSigner` trait signs with a private signing key, such as that of a
+/// router identity or a client's destination.
+pub trait SessionSigner {
+    fn sign(&self, message: &[u8]) -> Signature;
+}
+
 /// The macro invocation chain occurs as follows:
 /// ```
 /// data_structure_def!(TypeName);
@@ -92,7 +152,9 @@ macro_rules! data_structure_impl {
                 }
             }
 
-            fn from_bytes(sigtype: SignatureType, bytes: &[u8]) -> Option<$TYPE_NAME> {
+            /// Creates a `$TYPE_NAME` from its bytes. Returns `None` when the length
+            /// of `bytes` does not match the signature type.
+            pub fn from_bytes(sigtype: SignatureType, bytes: &[u8]) -> Option<$TYPE_NAME> {
                 if Self::signing_length(sigtype) == bytes.len() {
                     let data: Vec<u8> = bytes.iter().cloned().collect();
 
@@ -112,6 +174,10 @@ macro_rules! data_structure_impl {
                 self.data.len()
             }
 
+            pub fn signature_type(&self) -> SignatureType {
+                self.sigtype
+            }
+
             fn as_slice(&self) -> &[u8] {
                 self.data.as_ref()
             }
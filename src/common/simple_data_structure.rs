@@ -68,6 +68,12 @@ macro_rules! simple_data_structure {
             }
         }
 
+        impl ::std::hash::Hash for $TYPE_NAME {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                self.data.hash(state);
+            }
+        }
+
         impl From<[u8; $ARRAY_LENGTH]> for $TYPE_NAME {
             fn from(data: [u8; $ARRAY_LENGTH]) -> $TYPE_NAME {
                 $TYPE_NAME::new(data)
@@ -100,6 +106,12 @@ macro_rules! simple_data_structure {
             }
         }
 
+        impl fmt::Debug for $TYPE_NAME {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($TYPE_NAME), self)
+            }
+        }
+
         impl fmt::LowerHex for $TYPE_NAME {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 let mut output = String::new();
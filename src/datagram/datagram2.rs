@@ -0,0 +1,144 @@
+use common::{Destination, Hash256, I2pDate, Mapping, Signature, SignatureType};
+use common::{SessionSigner, SignatureVerifier};
+use streaming::OfflineSignature;
+
+
+/// The low four bits of the flags of Datagram2 and Datagram3 hold the version.
+pub const VERSION_MASK: u16 = 0x000f;
+pub const DATAGRAM2_VERSION: u16 = 2;
+pub const DATAGRAM3_VERSION: u16 = 3;
+/// Options follow the flags.
+pub const FLAG_OPTIONS: u16 = 0x0010;
+/// An offline signature follows the options. Datagram2 only.
+pub const FLAG_OFFLINE_SIGNATURE: u16 = 0x0020;
+
+/// A `Datagram2` is a repliable datagram whose signature also covers the hash of
+/// the recipient, so it cannot be replayed to another destination. It may carry
+/// options and be signed with a transient key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram2 {
+    pub from: Destination,
+    pub options: Mapping,
+    pub offline_signature: Option<OfflineSignature>,
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>
+}
+
+impl Datagram2 {
+    /// Creates an unsigned datagram without options.
+    pub fn new(from: Destination, payload: Vec<u8>) -> Datagram2 {
+        Datagram2 {
+            from,
+            options: Mapping::new(),
+            offline_signature: None,
+            payload,
+            signature: Vec::new()
+        }
+    }
+
+    /// Returns the flags, derived from the fields that are set.
+    pub fn flags(&self) -> u16 {
+        let mut flags = DATAGRAM2_VERSION;
+        if !self.options.is_empty() {
+            flags |= FLAG_OPTIONS;
+        }
+        if self.offline_signature.is_some() {
+            flags |= FLAG_OFFLINE_SIGNATURE;
+        }
+        flags
+    }
+
+    /// Returns the type of the key that signs: the transient key with an offline
+    /// signature, and otherwise the source's.
+    fn signature_type(&self) -> Option<SignatureType> {
+        match self.offline_signature {
+            Some(ref offline) => Some(offline.transient_key.signature_type()),
+            None => self.from.signature_type()
+        }
+    }
+
+    /// The flags, options, offline signature and payload.
+    fn body(&self) -> Vec<u8> {
+        let mut bytes = self.flags().to_be_bytes().to_vec();
+        if !self.options.is_empty() {
+            bytes.extend_from_slice(&self.options.to_bytes());
+        }
+        if let Some(ref offline) = self.offline_signature {
+            bytes.extend_from_slice(&offline.to_bytes());
+        }
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Returns the bytes the signature covers: the recipient's hash and the body.
+    pub fn signed_bytes(&self, target: &Hash256) -> Vec<u8> {
+        let mut bytes = target.as_ref().to_vec();
+        bytes.extend_from_slice(&self.body());
+        bytes
+    }
+
+    /// Signs the datagram for the recipient `target`. Returns `false` if the
+    /// signature type is unknown.
+    pub fn sign<S: SessionSigner>(&mut self, target: &Hash256, signer: &S) -> bool {
+        if self.signature_type().is_none() {
+            return false;
+        }
+        self.signature = signer.sign(&self.signed_bytes(target)).as_ref().to_vec();
+
+        true
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.from.as_ref().to_vec();
+        bytes.extend_from_slice(&self.body());
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Datagram2> {
+        let (from, mut offset) = Destination::from_bytes(bytes)?;
+        let flags = u16::from_be_bytes([*bytes.get(offset)?, *bytes.get(offset + 1)?]);
+        if flags & VERSION_MASK != DATAGRAM2_VERSION {
+            return None;
+        }
+        offset += 2;
+
+        let mut datagram = Datagram2::new(from, Vec::new());
+        if flags & FLAG_OPTIONS != 0 {
+            let (options, length) = Mapping::from_bytes(&bytes[offset..])?;
+            datagram.options = options;
+            offset += length;
+        }
+        if flags & FLAG_OFFLINE_SIGNATURE != 0 {
+            let (offline, length) = OfflineSignature::from_bytes(&bytes[offset..], datagram.from.signature_type()?)?;
+            datagram.offline_signature = Some(offline);
+            offset += length;
+        }
+
+        let signature_start = bytes.len().checked_sub(datagram.signature_type()?.signature_length())?;
+        if signature_start < offset {
+            return None;
+        }
+        datagram.payload = bytes[offset..signature_start].to_vec();
+        datagram.signature = bytes[signature_start..].to_vec();
+
+        Some(datagram)
+    }
+
+    /// Verifies that the source, or its transient key, signed the datagram for
+    /// `target`.
+    pub fn verify<V: SignatureVerifier>(&self, verifier: &V, target: &Hash256, now: I2pDate) -> bool {
+        let key = match self.offline_signature {
+            Some(ref offline) if offline.verify(verifier, &self.from, now) => offline.transient_key.clone(),
+            Some(_) => return false,
+            None => match self.from.signing_public_key() {
+                Some(key) => key,
+                None => return false
+            }
+        };
+        match Signature::from_bytes(key.signature_type(), &self.signature) {
+            Some(signature) => verifier.verify(&key, &self.signed_bytes(target), &signature),
+            None => false
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use common::{Destination, Hash256, Hashable256, Mapping};
+use datagram::datagram2::{DATAGRAM3_VERSION, FLAG_OPTIONS, VERSION_MASK};
+
+
+const HASH_LENGTH: usize = 32;
+
+/// A `Datagram3` is a repliable datagram that is not signed. It names its source
+/// by hash, which the recipient looks up to answer, so the source cannot be
+/// trusted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram3 {
+    pub from: Hash256,
+    pub options: Mapping,
+    pub payload: Vec<u8>
+}
+
+impl Datagram3 {
+    pub fn new(from: &Destination, payload: Vec<u8>) -> Datagram3 {
+        Datagram3 {
+            from: from.hash_sha256(),
+            options: Mapping::new(),
+            payload
+        }
+    }
+
+    pub fn flags(&self) -> u16 {
+        if self.options.is_empty() { DATAGRAM3_VERSION } else { DATAGRAM3_VERSION | FLAG_OPTIONS }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.from.as_ref().to_vec();
+        bytes.extend_from_slice(&self.flags().to_be_bytes());
+        if !self.options.is_empty() {
+            bytes.extend_from_slice(&self.options.to_bytes());
+        }
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Datagram3> {
+        if bytes.len() < HASH_LENGTH + 2 {
+            return None;
+        }
+        let mut from = [0x00; HASH_LENGTH];
+        from.copy_from_slice(&bytes[..HASH_LENGTH]);
+        let flags = u16::from_be_bytes([bytes[HASH_LENGTH], bytes[HASH_LENGTH + 1]]);
+        if flags & VERSION_MASK != DATAGRAM3_VERSION {
+            return None;
+        }
+
+        let mut offset = HASH_LENGTH + 2;
+        let mut options = Mapping::new();
+        if flags & FLAG_OPTIONS != 0 {
+            let (mapping, length) = Mapping::from_bytes(&bytes[offset..])?;
+            options = mapping;
+            offset += length;
+        }
+
+        Some(Datagram3 {
+            from: Hash256::from(from),
+            options,
+            payload: bytes[offset..].to_vec()
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use i2cp::{I2cpPayload, PROTOCOL_DATAGRAM, PROTOCOL_DATAGRAM2, PROTOCOL_DATAGRAM3, PROTOCOL_RAW};
+use datagram::datagram2::Datagram2;
+use datagram::datagram3::Datagram3;
+use datagram::repliable::Datagram1;
+
+
+/// A `Datagram` is one of the datagram formats, told apart by the protocol number
+/// in the I2CP payload that carries it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Datagram {
+    Repliable(Datagram1),
+    /// The payload alone, without a source.
+    Raw(Vec<u8>),
+    Datagram2(Datagram2),
+    Datagram3(Datagram3)
+}
+
+impl Datagram {
+    pub fn protocol(&self) -> u8 {
+        match *self {
+            Datagram::Repliable(_) => PROTOCOL_DATAGRAM,
+            Datagram::Raw(_) => PROTOCOL_RAW,
+            Datagram::Datagram2(_) => PROTOCOL_DATAGRAM2,
+            Datagram::Datagram3(_) => PROTOCOL_DATAGRAM3
+        }
+    }
+
+    /// Returns the application data.
+    pub fn payload(&self) -> &[u8] {
+        match *self {
+            Datagram::Repliable(ref datagram) => &datagram.payload,
+            Datagram::Raw(ref payload) => payload,
+            Datagram::Datagram2(ref datagram) => &datagram.payload,
+            Datagram::Datagram3(ref datagram) => &datagram.payload
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            Datagram::Repliable(ref datagram) => datagram.to_bytes(),
+            Datagram::Raw(ref payload) => payload.clone(),
+            Datagram::Datagram2(ref datagram) => datagram.to_bytes(),
+            Datagram::Datagram3(ref datagram) => datagram.to_bytes()
+        }
+    }
+
+    /// Wraps the datagram in an I2CP payload between two ports.
+    pub fn to_payload(&self, source_port: u16, destination_port: u16) -> I2cpPayload {
+        I2cpPayload::new(self.protocol(), source_port, destination_port, self.to_bytes())
+    }
+
+    /// Reads the datagram an I2CP payload carries. Returns `None` if the protocol
+    /// is not a datagram protocol or the datagram is malformed.
+    pub fn from_payload(payload: &I2cpPayload) -> Option<Datagram> {
+        match payload.protocol {
+            PROTOCOL_DATAGRAM => Datagram1::from_bytes(&payload.data).map(Datagram::Repliable),
+            PROTOCOL_RAW => Some(Datagram::Raw(payload.data.clone())),
+            PROTOCOL_DATAGRAM2 => Datagram2::from_bytes(&payload.data).map(Datagram::Datagram2),
+            PROTOCOL_DATAGRAM3 => Datagram3::from_bytes(&payload.data).map(Datagram::Datagram3),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub use self::message::Datagram;
+pub use self::repliable::{Datagram1, MAX_DATAGRAM1_LENGTH};
+pub use self::datagram2::{Datagram2, VERSION_MASK, DATAGRAM2_VERSION, DATAGRAM3_VERSION};
+pub use self::datagram2::{FLAG_OPTIONS, FLAG_OFFLINE_SIGNATURE};
+pub use self::datagram3::Datagram3;
+
+
+mod message;
+mod repliable;
+mod datagram2;
+mod datagram3;
@@ -0,0 +1,69 @@
+use common::{Destination, Hashable256, SessionSigner, Signature, SignatureType, SignatureVerifier};
+
+
+/// The largest repliable datagram, source and signature included.
+pub const MAX_DATAGRAM1_LENGTH: usize = 31744;
+
+/// A `Datagram1` is a repliable datagram: the payload with the sender's destination
+/// and its signature, so the recipient can answer and trust the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram1 {
+    pub from: Destination,
+    pub signature: Vec<u8>,
+    pub payload: Vec<u8>
+}
+
+impl Datagram1 {
+    /// Returns the bytes a sender with the given signature type signs: the SHA256
+    /// hash of the payload for DSA_SHA1, and the payload itself otherwise.
+    pub fn signed_bytes(signature_type: SignatureType, payload: &[u8]) -> Vec<u8> {
+        match signature_type {
+            SignatureType::DSA_SHA1 => payload.hash_sha256().as_ref().to_vec(),
+            _ => payload.to_vec()
+        }
+    }
+
+    /// Signs a payload as `from`. Returns `None` if the destination's signature type
+    /// is unknown.
+    pub fn sign<S: SessionSigner>(from: Destination, payload: Vec<u8>, signer: &S) -> Option<Datagram1> {
+        let signature_type = from.signature_type()?;
+        let signature = signer.sign(&Datagram1::signed_bytes(signature_type, &payload));
+
+        Some(Datagram1 {
+            from,
+            signature: signature.as_ref().to_vec(),
+            payload
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.from.as_ref().to_vec();
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Datagram1> {
+        let (from, length) = Destination::from_bytes(bytes)?;
+        let signature_end = length + from.signature_type()?.signature_length();
+        let signature = bytes.get(length..signature_end)?.to_vec();
+
+        Some(Datagram1 {
+            from,
+            signature,
+            payload: bytes[signature_end..].to_vec()
+        })
+    }
+
+    /// Verifies that the source signed the payload.
+    pub fn verify<V: SignatureVerifier>(&self, verifier: &V) -> bool {
+        let key = match self.from.signing_public_key() {
+            Some(key) => key,
+            None => return false
+        };
+        match Signature::from_bytes(key.signature_type(), &self.signature) {
+            Some(signature) => verifier.verify(&key, &Datagram1::signed_bytes(key.signature_type(), &self.payload), &signature),
+            None => false
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use common::{Hash256, I2pDate, I2pInt32, I2pInt64};
+use garlic::error::GarlicError;
+use i2np::{I2npMessage, MessageType};
+use tunnel::TunnelId;
+
+
+/// Each block starts with a one byte type and a two byte big endian size.
+pub const BLOCK_HEADER_LENGTH: usize = 3;
+
+/// The short I2NP header of a clove: the type, the message ID and the expiration in
+/// seconds.
+pub const I2NP_SHORT_HEADER_LENGTH: usize = 9;
+
+const DATE_TIME_LENGTH: usize = 4;
+
+const BLOCK_DATE_TIME: u8 = 0;
+const BLOCK_GARLIC_CLOVE: u8 = 11;
+const BLOCK_PADDING: u8 = 254;
+
+const DELIVERY_LOCAL: u8 = 0;
+const DELIVERY_DESTINATION: u8 = 1;
+const DELIVERY_ROUTER: u8 = 2;
+const DELIVERY_TUNNEL: u8 = 3;
+const DELIVERY_SHIFT: u8 = 5;
+
+/// Where the router that decrypts a garlic message delivers a clove.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryInstructions {
+    /// To the router or destination that decrypted the garlic message.
+    Local,
+    /// To the destination with this hash.
+    Destination(Hash256),
+    /// To the router with this hash.
+    Router(Hash256),
+    /// To a tunnel, given by its gateway router and tunnel ID.
+    Tunnel(Hash256, TunnelId)
+}
+
+impl DeliveryInstructions {
+    fn encoded_len(&self) -> usize {
+        match *self {
+            DeliveryInstructions::Local => 1,
+            DeliveryInstructions::Destination(_) | DeliveryInstructions::Router(_) => 33,
+            DeliveryInstructions::Tunnel(_, _) => 37
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match *self {
+            DeliveryInstructions::Local => {
+                buf.push(DELIVERY_LOCAL << DELIVERY_SHIFT);
+            }
+            DeliveryInstructions::Destination(ref hash) => {
+                buf.push(DELIVERY_DESTINATION << DELIVERY_SHIFT);
+                buf.extend_from_slice(hash.as_ref());
+            }
+            DeliveryInstructions::Router(ref hash) => {
+                buf.push(DELIVERY_ROUTER << DELIVERY_SHIFT);
+                buf.extend_from_slice(hash.as_ref());
+            }
+            DeliveryInstructions::Tunnel(ref gateway, tunnel_id) => {
+                buf.push(DELIVERY_TUNNEL << DELIVERY_SHIFT);
+                buf.extend_from_slice(gateway.as_ref());
+                buf.extend_from_slice(&tunnel_id.to_bytes_be());
+            }
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<DeliveryInstructions, GarlicError> {
+        let hash = |data: &[u8]| {
+            let mut hash = [0x00; 32];
+            hash.copy_from_slice(&data[1..33]);
+            Hash256::from(hash)
+        };
+        let delivery = (data[0] >> DELIVERY_SHIFT) & 0x03;
+        if delivery != DELIVERY_LOCAL && data.len() < 33 {
+            return Err(GarlicError::InvalidBlock);
+        }
+
+        let instructions = match delivery {
+            DELIVERY_LOCAL => DeliveryInstructions::Local,
+            DELIVERY_DESTINATION => DeliveryInstructions::Destination(hash(data)),
+            DELIVERY_ROUTER => DeliveryInstructions::Router(hash(data)),
+            _ => {
+                if data.len() < 37 {
+                    return Err(GarlicError::InvalidBlock);
+                }
+                DeliveryInstructions::Tunnel(hash(data), I2pInt32::new(read_u32(&data[33..37]) as u64))
+            }
+        };
+
+        Ok(instructions)
+    }
+}
+
+/// A `GarlicClove` is one I2NP message inside a garlic message, with instructions
+/// for where to deliver it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GarlicClove {
+    pub delivery: DeliveryInstructions,
+    pub message: I2npMessage
+}
+
+impl GarlicClove {
+    pub fn new(delivery: DeliveryInstructions, message: I2npMessage) -> GarlicClove {
+        GarlicClove {
+            delivery,
+            message
+        }
+    }
+}
+
+/// A `Block` is one unit of the decrypted payload of an ECIES-X25519-AEAD-Ratchet
+/// garlic message. Blocks this module does not interpret are kept as `Unknown`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// The current time in seconds since the UNIX epoch.
+    DateTime(u32),
+    GarlicClove(GarlicClove),
+    /// Random padding. It must be the last block.
+    Padding(Vec<u8>),
+    Unknown(u8, Vec<u8>)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+impl Block {
+    /// Returns the length of the block in bytes, including its header.
+    pub fn encoded_len(&self) -> usize {
+        BLOCK_HEADER_LENGTH + match *self {
+            Block::DateTime(_) => DATE_TIME_LENGTH,
+            Block::GarlicClove(ref clove) => {
+                clove.delivery.encoded_len() + I2NP_SHORT_HEADER_LENGTH + clove.message.payload.len()
+            }
+            Block::Padding(ref padding) => padding.len(),
+            Block::Unknown(_, ref data) => data.len()
+        }
+    }
+
+    fn type_code(&self) -> u8 {
+        match *self {
+            Block::DateTime(_) => BLOCK_DATE_TIME,
+            Block::GarlicClove(_) => BLOCK_GARLIC_CLOVE,
+            Block::Padding(_) => BLOCK_PADDING,
+            Block::Unknown(code, _) => code
+        }
+    }
+
+    /// Appends the encoded block to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.type_code());
+        buf.extend_from_slice(&((self.encoded_len() - BLOCK_HEADER_LENGTH) as u16).to_be_bytes());
+
+        match *self {
+            Block::DateTime(timestamp) => {
+                buf.extend_from_slice(&timestamp.to_be_bytes());
+            }
+            Block::GarlicClove(ref clove) => {
+                clove.delivery.encode(buf);
+                let expiration = ((clove.message.expiration.to_u64() + 500) / 1000) as u32;
+                buf.push(clove.message.message_type.code());
+                buf.extend_from_slice(&clove.message.message_id.to_be_bytes());
+                buf.extend_from_slice(&expiration.to_be_bytes());
+                buf.extend_from_slice(&clove.message.payload);
+            }
+            Block::Padding(ref data) | Block::Unknown(_, ref data) => {
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+
+    fn decode(code: u8, data: &[u8]) -> Result<Block, GarlicError> {
+        let block = match code {
+            BLOCK_DATE_TIME if data.len() == DATE_TIME_LENGTH => Block::DateTime(read_u32(data)),
+            BLOCK_GARLIC_CLOVE if !data.is_empty() => {
+                let delivery = DeliveryInstructions::decode(data)?;
+                let header = &data[delivery.encoded_len()..];
+                if header.len() < I2NP_SHORT_HEADER_LENGTH {
+                    return Err(GarlicError::InvalidBlock);
+                }
+
+                let seconds = read_u32(&header[5..9]) as u64;
+                let expiration = match I2pDate::new(I2pInt64::new(seconds * 1000)) {
+                    Ok(expiration) => expiration,
+                    Err(_) => return Err(GarlicError::InvalidBlock)
+                };
+                let message = I2npMessage::new(MessageType::from_code(header[0]),
+                                               read_u32(&header[1..5]),
+                                               expiration,
+                                               header[I2NP_SHORT_HEADER_LENGTH..].to_vec());
+                Block::GarlicClove(GarlicClove::new(delivery, message))
+            }
+            BLOCK_PADDING => Block::Padding(data.to_vec()),
+            BLOCK_DATE_TIME | BLOCK_GARLIC_CLOVE => return Err(GarlicError::InvalidBlock),
+            _ => Block::Unknown(code, data.to_vec())
+        };
+
+        Ok(block)
+    }
+}
+
+/// Encodes a sequence of blocks into a garlic payload.
+pub fn encode_blocks(blocks: &[Block]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(blocks.iter().map(|block| block.encoded_len()).sum());
+    for block in blocks {
+        block.encode(&mut payload);
+    }
+
+    payload
+}
+
+/// Decodes a garlic payload into its blocks. Padding must be the last block.
+pub fn decode_blocks(payload: &[u8]) -> Result<Vec<Block>, GarlicError> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset < payload.len() {
+        if let Some(&Block::Padding(_)) = blocks.last() {
+            return Err(GarlicError::InvalidBlock);
+        }
+        if payload.len() - offset < BLOCK_HEADER_LENGTH {
+            return Err(GarlicError::InvalidBlock);
+        }
+
+        let code = payload[offset];
+        let size = ((payload[offset + 1] as usize) << 8) | (payload[offset + 2] as usize);
+        let start = offset + BLOCK_HEADER_LENGTH;
+        if payload.len() - start < size {
+            return Err(GarlicError::InvalidBlock);
+        }
+
+        blocks.push(Block::decode(code, &payload[start..start + size])?);
+        offset = start + size;
+    }
+
+    Ok(blocks)
+}
@@ -0,0 +1,112 @@
+use rsa::BigUint;
+
+
+/// The Montgomery coefficient `A` of Curve25519.
+const MONTGOMERY_A: u32 = 486_662;
+
+/// Returns the field prime 2^255 - 19.
+fn prime() -> BigUint {
+    (BigUint::from(1u32) << 255usize) - BigUint::from(19u32)
+}
+
+fn add(a: &BigUint, b: &BigUint) -> BigUint {
+    (a + b) % prime()
+}
+
+fn sub(a: &BigUint, b: &BigUint) -> BigUint {
+    let p = prime();
+    (a + &p - (b % &p)) % p
+}
+
+fn mul(a: &BigUint, b: &BigUint) -> BigUint {
+    (a * b) % prime()
+}
+
+fn invert(a: &BigUint) -> BigUint {
+    let p = prime();
+    a.modpow(&(&p - BigUint::from(2u32)), &p)
+}
+
+/// Returns whether `a` is a square in the field, counting zero as one.
+fn is_square(a: &BigUint) -> bool {
+    let p = prime();
+    let legendre = a.modpow(&((&p - BigUint::from(1u32)) >> 1usize), &p);
+    legendre <= BigUint::from(1u32)
+}
+
+/// Returns the smaller square root of `a`, or `None` if it has none. The prime is 5
+/// modulo 8, so a root is `a^((p+3)/8)`, possibly times the root of -1.
+fn sqrt(a: &BigUint) -> Option<BigUint> {
+    let p = prime();
+    let a = a % &p;
+    let mut root = a.modpow(&((&p + BigUint::from(3u32)) >> 3usize), &p);
+    if mul(&root, &root) != a {
+        let sqrt_minus_one = BigUint::from(2u32).modpow(&((&p - BigUint::from(1u32)) >> 2usize), &p);
+        root = mul(&root, &sqrt_minus_one);
+        if mul(&root, &root) != a {
+            return None;
+        }
+    }
+    let other = sub(&BigUint::from(0u32), &root);
+
+    Some(if other < root { other } else { root })
+}
+
+fn from_bytes(bytes: &[u8; 32]) -> BigUint {
+    BigUint::from_bytes_le(bytes) % prime()
+}
+
+fn to_bytes(value: &BigUint) -> [u8; 32] {
+    let mut bytes = [0x00; 32];
+    let le = value.to_bytes_le();
+    bytes[..le.len()].copy_from_slice(&le);
+
+    bytes
+}
+
+/// Encodes an X25519 public key as an Elligator2 representative, which cannot be told
+/// apart from random bytes. Only about half of all keys have one; `None` is returned
+/// for the others. `random` picks one of the two representatives with its low bit,
+/// and fills the two unused high bits of the result with its high bits.
+pub fn encode_elligator2(public_key: &[u8; 32], random: u8) -> Option<[u8; 32]> {
+    let a = BigUint::from(MONTGOMERY_A);
+    let u = from_bytes(public_key);
+    let u_plus_a = add(&u, &a);
+    let zero = BigUint::from(0u32);
+    if u == zero || u_plus_a == zero {
+        return None;
+    }
+    // A point has a representative exactly when -2u(u + A) is a square.
+    let two = BigUint::from(2u32);
+    if !is_square(&sub(&zero, &mul(&mul(&two, &u), &u_plus_a))) {
+        return None;
+    }
+
+    // Either root maps back to `u`: one through `w = u`, the other through `w = -u - A`.
+    let square = if random & 0x01 == 0 {
+        sub(&zero, &mul(&u, &invert(&mul(&two, &u_plus_a))))
+    } else {
+        sub(&zero, &mul(&u_plus_a, &invert(&mul(&two, &u))))
+    };
+    let mut representative = to_bytes(&sqrt(&square)?);
+    representative[31] |= random & 0xC0;
+
+    Some(representative)
+}
+
+/// Decodes an Elligator2 representative into the X25519 public key it stands for.
+pub fn decode_elligator2(representative: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = *representative;
+    bytes[31] &= 0x3F;
+    let r = from_bytes(&bytes);
+    let a = BigUint::from(MONTGOMERY_A);
+    let one = BigUint::from(1u32);
+    let zero = BigUint::from(0u32);
+
+    // w = -A / (1 + 2r^2), which is never a division by zero since 2 is not a square.
+    let w = sub(&zero, &mul(&a, &invert(&add(&one, &mul(&BigUint::from(2u32), &mul(&r, &r))))));
+    let curve = add(&mul(&mul(&w, &w), &add(&w, &a)), &w);
+    let u = if is_square(&curve) { w } else { sub(&sub(&zero, &w), &a) };
+
+    to_bytes(&u)
+}
@@ -0,0 +1,37 @@
+use std::error;
+use std::fmt;
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GarlicError {
+    /// A garlic payload does not follow the block format.
+    InvalidBlock,
+    /// The destination uses an encryption type we cannot encrypt for.
+    UnsupportedEncryption(u16),
+}
+
+impl fmt::Display for GarlicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GarlicError::InvalidBlock => {
+                writeln!(f, "Error: The garlic payload is malformed.")
+            }
+            GarlicError::UnsupportedEncryption(encryption_type) => {
+                writeln!(f, "Error: The encryption type {} is not supported.", encryption_type)
+            }
+        }
+    }
+}
+
+impl error::Error for GarlicError {
+    fn description(&self) -> &str {
+        match *self {
+            GarlicError::InvalidBlock => "The garlic payload is malformed.",
+            GarlicError::UnsupportedEncryption(_) => "The encryption type is not supported.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
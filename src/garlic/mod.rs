@@ -0,0 +1,14 @@
+pub use self::error::GarlicError;
+pub use self::clove::{Block, DeliveryInstructions, GarlicClove, encode_blocks, decode_blocks};
+pub use self::router::{MessageRouter, MessageStatus, RetryPolicy, RemoteLeaseSet};
+pub use self::router::{LeaseSetLookup, GarlicEncryptor, GarlicSender, check_encryption_type};
+pub use self::router::ENCRYPTION_TYPE_ECIES_X25519;
+pub use self::ratchet::{RatchetEncryptor, RatchetPeer, PROTOCOL_NAME, TAG_LENGTH};
+pub use self::elligator2::{encode_elligator2, decode_elligator2};
+
+
+mod error;
+mod clove;
+mod router;
+mod ratchet;
+mod elligator2;
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use rand;
+use rand::Rng;
+use x25519_dalek::{PublicKey, StaticSecret};
+use common::Hash256;
+use garlic::elligator2::{encode_elligator2, decode_elligator2};
+use garlic::router::GarlicEncryptor;
+use transport::noise::{self, SymmetricState, KEY_LENGTH};
+
+
+/// The Noise protocol name of ECIES-X25519-AEAD-Ratchet.
+pub const PROTOCOL_NAME: &[u8] = b"Noise_IKelg2+hs2_25519_ChaChaPoly_SHA256";
+
+/// The length of a session tag.
+pub const TAG_LENGTH: usize = 8;
+
+const MAC_LENGTH: usize = 16;
+
+/// A New Session message is the ephemeral key, the encrypted static key and the
+/// encrypted payload.
+const NEW_SESSION_OVERHEAD: usize = KEY_LENGTH + KEY_LENGTH + MAC_LENGTH + MAC_LENGTH;
+
+/// A New Session Reply message is a tag, the ephemeral key, an empty key section and
+/// the encrypted payload.
+const NEW_SESSION_REPLY_OVERHEAD: usize = TAG_LENGTH + KEY_LENGTH + MAC_LENGTH + MAC_LENGTH;
+
+/// The number of tags of each tag set we recognize ahead of the last one used.
+const TAG_WINDOW: u32 = 32;
+
+/// The last index of a tag set. We do not ratchet to a new tag set, so a session
+/// ends after this many messages in one direction.
+const MAX_TAG_INDEX: u32 = 65_535;
+
+/// A `TagSet` derives the session tags and message keys of one direction of a
+/// session, by the session tag and symmetric key ratchets.
+struct TagSet {
+    tag_chain_key: [u8; 32],
+    tag_constant: [u8; 32],
+    key_chain_key: [u8; 32],
+    next_index: u32
+}
+
+impl TagSet {
+    /// Starts a tag set from a root key and a tag set key, DH_INITIALIZE in the
+    /// specification.
+    fn new(root_key: &[u8; 32], key: &[u8; 32]) -> TagSet {
+        let (_, chain_key) = noise::hkdf(root_key, key, b"KDFDHRatchetStep");
+        let (tag_chain_key, key_chain_key) = noise::hkdf(&chain_key, &[], b"TagAndKeyGenKeys");
+        let (tag_chain_key, tag_constant) = noise::hkdf(&tag_chain_key, &[], b"STInitialization");
+
+        TagSet {
+            tag_chain_key,
+            tag_constant,
+            key_chain_key,
+            next_index: 0
+        }
+    }
+
+    /// Returns the index, tag and key of the next message, or `None` once the tag
+    /// set is used up.
+    fn next(&mut self) -> Option<(u32, [u8; TAG_LENGTH], [u8; 32])> {
+        if self.next_index > MAX_TAG_INDEX {
+            return None;
+        }
+        let (tag_chain_key, tag_data) = noise::hkdf(&self.tag_chain_key, &self.tag_constant, b"SessionTagKeyGen");
+        self.tag_chain_key = tag_chain_key;
+        let (key_chain_key, key) = noise::hkdf(&self.key_chain_key, &[], b"SymmetricRatchet");
+        self.key_chain_key = key_chain_key;
+
+        let mut tag = [0x00; TAG_LENGTH];
+        tag.copy_from_slice(&tag_data[..TAG_LENGTH]);
+        let index = self.next_index;
+        self.next_index += 1;
+
+        Some((index, tag, key))
+    }
+}
+
+/// The peer a decrypted message came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RatchetPeer {
+    /// A destination we started a session with, replying on that session.
+    Destination(Hash256),
+    /// A peer that started a session with us, known by its static key. Replies go
+    /// through `RatchetEncryptor::reply`.
+    StaticKey([u8; 32])
+}
+
+/// Who a receiving tag set belongs to.
+enum TagOwner {
+    /// The New Session Reply tags of a New Session message we sent. The ephemeral
+    /// key, chaining key and hash of that message complete the reply.
+    NewSessionReply { destination: Hash256, ephemeral: StaticSecret, chain_key: [u8; 32], hash: [u8; 32] },
+    /// Existing Session messages on a session we started.
+    Outbound { destination: Hash256 },
+    /// Existing Session messages on a session a peer started, answering one of our
+    /// New Session Replies. `reply` is our sending tag set for that answer.
+    Inbound { static_key: [u8; 32], reply: Option<TagSet> }
+}
+
+struct ReceiveTagSet {
+    owner: TagOwner,
+    tags: TagSet
+}
+
+struct OutboundSession {
+    remote_key: [u8; 32],
+    send: Option<TagSet>
+}
+
+/// A session a peer started: the state after its latest New Session message, from
+/// which each New Session Reply is built, and our sending tag set once the peer has
+/// answered a reply.
+struct InboundSession {
+    remote_ephemeral: [u8; 32],
+    chain_key: [u8; 32],
+    hash: [u8; 32],
+    reply_tags: TagSet,
+    send: Option<TagSet>
+}
+
+/// A `RatchetEncryptor` runs ECIES-X25519-AEAD-Ratchet sessions for one destination.
+/// A session starts with New Session messages, bound to our static key, until the
+/// remote answers with a New Session Reply; both sides then send Existing Session
+/// messages, each found by its session tag. Sessions without a static key, the next
+/// DH ratchet and the NextKey, ACK and termination blocks are not supported.
+pub struct RatchetEncryptor {
+    static_key: StaticSecret,
+    public_key: [u8; 32],
+    outbound: HashMap<Hash256, OutboundSession>,
+    inbound: HashMap<[u8; 32], InboundSession>,
+    receive_sets: HashMap<u64, ReceiveTagSet>,
+    tags: HashMap<[u8; TAG_LENGTH], (u64, u32, [u8; 32])>,
+    next_set_id: u64
+}
+
+fn diffie_hellman(private_key: &StaticSecret, public_key: &[u8; 32]) -> [u8; 32] {
+    private_key.diffie_hellman(&PublicKey::from(*public_key)).to_bytes()
+}
+
+/// Returns an ephemeral key whose public key has an Elligator2 representative, and
+/// that representative.
+fn elligator2_ephemeral() -> (StaticSecret, [u8; 32], [u8; 32]) {
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut key = [0x00; 32];
+        rng.fill_bytes(&mut key);
+        let secret = StaticSecret::from(key);
+        let public_key = PublicKey::from(&secret).to_bytes();
+        if let Some(representative) = encode_elligator2(&public_key, rng.gen()) {
+            return (secret, public_key, representative);
+        }
+    }
+}
+
+/// Returns the sending and receiving tag sets and the payload key of a New Session
+/// Reply, from the chaining key after its key section.
+fn split(chain_key: &[u8; 32]) -> (TagSet, TagSet, [u8; 32]) {
+    let (key_ab, key_ba) = noise::hkdf(chain_key, &[], &[]);
+    let (payload_key, _) = noise::hkdf(&key_ba, &[], b"AttachPayloadKDF");
+
+    (TagSet::new(chain_key, &key_ab), TagSet::new(chain_key, &key_ba), payload_key)
+}
+
+/// Returns the tag set a New Session Reply takes its tag from.
+fn reply_tags(chain_key: &[u8; 32]) -> TagSet {
+    let (tag_set_key, _) = noise::hkdf(chain_key, &[], b"SessionReplyTags");
+
+    TagSet::new(chain_key, &tag_set_key)
+}
+
+impl RatchetEncryptor {
+    /// Creates an encryptor from the X25519 private key of our LeaseSet.
+    pub fn new(private_key: &[u8; 32]) -> RatchetEncryptor {
+        let static_key = StaticSecret::from(*private_key);
+        let public_key = PublicKey::from(&static_key).to_bytes();
+
+        RatchetEncryptor {
+            static_key,
+            public_key,
+            outbound: HashMap::new(),
+            inbound: HashMap::new(),
+            receive_sets: HashMap::new(),
+            tags: HashMap::new(),
+            next_set_id: 0
+        }
+    }
+
+    /// Returns the X25519 public key to publish in our LeaseSet.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    /// Starts recognizing the tags of a receiving tag set.
+    fn add_receive_set(&mut self, owner: TagOwner, tags: TagSet) -> u64 {
+        let id = self.next_set_id;
+        self.next_set_id += 1;
+        self.receive_sets.insert(id, ReceiveTagSet { owner, tags });
+        for _ in 0..TAG_WINDOW {
+            self.extend_receive_set(id);
+        }
+
+        id
+    }
+
+    /// Recognizes one more tag of a receiving tag set.
+    fn extend_receive_set(&mut self, id: u64) {
+        if let Some(set) = self.receive_sets.get_mut(&id) {
+            if let Some((index, tag, key)) = set.tags.next() {
+                self.tags.insert(tag, (id, index, key));
+            }
+        }
+    }
+
+    /// Forgets the receiving tag sets `remove` picks.
+    fn remove_receive_sets<F>(&mut self, remove: F) where F: Fn(u64, &TagOwner) -> bool {
+        let removed: Vec<u64> = self.receive_sets.iter()
+            .filter(|&(id, set)| remove(*id, &set.owner))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in removed.iter() {
+            self.receive_sets.remove(id);
+        }
+        self.tags.retain(|_, &mut (id, _, _)| !removed.contains(&id));
+    }
+
+    /// Builds a New Session message to `remote_key`, and starts recognizing the tags
+    /// of its reply.
+    fn new_session(&mut self, destination: &Hash256, remote_key: &[u8; 32], payload: &[u8]) -> Vec<u8> {
+        let (ephemeral, ephemeral_public, representative) = elligator2_ephemeral();
+        let mut state = SymmetricState::new(PROTOCOL_NAME, remote_key);
+        state.mix_hash(&ephemeral_public);
+        state.mix_key(&diffie_hellman(&ephemeral, remote_key));
+        let static_key = state.encrypt_and_hash(0, &self.public_key);
+        state.mix_key(&diffie_hellman(&self.static_key, remote_key));
+        let payload = state.encrypt_and_hash(0, payload);
+
+        let chain_key = *state.chaining_key();
+        let owner = TagOwner::NewSessionReply {
+            destination: destination.clone(),
+            ephemeral,
+            chain_key,
+            hash: *state.hash()
+        };
+        self.add_receive_set(owner, reply_tags(&chain_key));
+
+        let mut message = representative.to_vec();
+        message.extend_from_slice(&static_key);
+        message.extend_from_slice(&payload);
+
+        message
+    }
+
+    /// Builds an Existing Session message with the next tag and key of `tags`.
+    fn existing_session(tags: &mut TagSet, payload: &[u8]) -> Option<Vec<u8>> {
+        let (index, tag, key) = tags.next()?;
+        let mut message = tag.to_vec();
+        message.extend_from_slice(&noise::encrypt(&key, index as u64, &tag, payload));
+
+        Some(message)
+    }
+
+    /// Encrypts a reply to a peer that started a session with us: a New Session
+    /// Reply until the peer has answered one, then Existing Session messages.
+    /// Returns `None` if the peer has no session with us or the session is used up.
+    pub fn reply(&mut self, static_key: &[u8; 32], payload: &[u8]) -> Option<Vec<u8>> {
+        let (tag, chain_key, hash, remote_ephemeral) = {
+            let session = self.inbound.get_mut(static_key)?;
+            if let Some(ref mut send) = session.send {
+                return RatchetEncryptor::existing_session(send, payload);
+            }
+            let (_, tag, _) = session.reply_tags.next()?;
+            (tag, session.chain_key, session.hash, session.remote_ephemeral)
+        };
+
+        let (ephemeral, ephemeral_public, representative) = elligator2_ephemeral();
+        let hash = noise::sha256(&[&hash, &tag]);
+        let hash = noise::sha256(&[&hash, &ephemeral_public]);
+        let (chain_key, _) = noise::hkdf(&chain_key, &diffie_hellman(&ephemeral, &remote_ephemeral), &[]);
+        let (chain_key, key) = noise::hkdf(&chain_key, &diffie_hellman(&ephemeral, static_key), &[]);
+        let key_section = noise::encrypt(&key, 0, &hash, &[]);
+        let hash = noise::sha256(&[&hash, &key_section]);
+
+        let (receive, send, payload_key) = split(&chain_key);
+        self.add_receive_set(TagOwner::Inbound { static_key: *static_key, reply: Some(send) }, receive);
+
+        let mut message = tag.to_vec();
+        message.extend_from_slice(&representative);
+        message.extend_from_slice(&key_section);
+        message.extend_from_slice(&noise::encrypt(&payload_key, 0, &hash, payload));
+
+        Some(message)
+    }
+
+    /// Decrypts a garlic message sent to us. Returns the peer it came from and the
+    /// payload, or `None` if the message is not for any of our sessions.
+    pub fn decrypt(&mut self, message: &[u8]) -> Option<(RatchetPeer, Vec<u8>)> {
+        if message.len() >= TAG_LENGTH + MAC_LENGTH {
+            let mut tag = [0x00; TAG_LENGTH];
+            tag.copy_from_slice(&message[..TAG_LENGTH]);
+            if let Some(&(id, index, key)) = self.tags.get(&tag) {
+                return self.decrypt_tagged(id, index, &key, message);
+            }
+        }
+
+        self.decrypt_new_session(message)
+    }
+
+    fn decrypt_new_session(&mut self, message: &[u8]) -> Option<(RatchetPeer, Vec<u8>)> {
+        if message.len() < NEW_SESSION_OVERHEAD {
+            return None;
+        }
+        let mut representative = [0x00; 32];
+        representative.copy_from_slice(&message[..KEY_LENGTH]);
+        let remote_ephemeral = decode_elligator2(&representative);
+
+        let mut state = SymmetricState::new(PROTOCOL_NAME, &self.public_key);
+        state.mix_hash(&remote_ephemeral);
+        state.mix_key(&diffie_hellman(&self.static_key, &remote_ephemeral));
+        let static_key = state.decrypt_and_hash(0, &message[KEY_LENGTH..2 * KEY_LENGTH + MAC_LENGTH])?;
+        let mut remote_key = [0x00; 32];
+        remote_key.copy_from_slice(&static_key);
+        state.mix_key(&diffie_hellman(&self.static_key, &remote_key));
+        let payload = state.decrypt_and_hash(0, &message[2 * KEY_LENGTH + MAC_LENGTH..])?;
+
+        // A new New Session message restarts the session, and with it the replies.
+        self.remove_receive_sets(|_, owner| match *owner {
+            TagOwner::Inbound { ref static_key, .. } => *static_key == remote_key,
+            _ => false
+        });
+        let chain_key = *state.chaining_key();
+        self.inbound.insert(remote_key, InboundSession {
+            remote_ephemeral,
+            chain_key,
+            hash: *state.hash(),
+            reply_tags: reply_tags(&chain_key),
+            send: None
+        });
+
+        Some((RatchetPeer::StaticKey(remote_key), payload))
+    }
+
+    fn decrypt_tagged(&mut self, id: u64, index: u32, key: &[u8; 32], message: &[u8]) -> Option<(RatchetPeer, Vec<u8>)> {
+        let peer = match self.receive_sets.get(&id)?.owner {
+            TagOwner::NewSessionReply { .. } => None,
+            TagOwner::Outbound { ref destination } => Some(RatchetPeer::Destination(destination.clone())),
+            TagOwner::Inbound { static_key, .. } => Some(RatchetPeer::StaticKey(static_key))
+        };
+        let tag = &message[..TAG_LENGTH];
+        let result = match peer {
+            None => self.decrypt_new_session_reply(id, message),
+            Some(peer) => {
+                let payload = noise::decrypt(key, index as u64, tag, &message[TAG_LENGTH..])?;
+                if let RatchetPeer::StaticKey(ref static_key) = peer {
+                    self.confirm_inbound(id, static_key);
+                }
+                Some((peer, payload))
+            }
+        };
+
+        // Each tag is good for one message.
+        if result.is_some() {
+            self.tags.remove(tag);
+            self.extend_receive_set(id);
+        }
+
+        result
+    }
+
+    fn decrypt_new_session_reply(&mut self, id: u64, message: &[u8]) -> Option<(RatchetPeer, Vec<u8>)> {
+        if message.len() < NEW_SESSION_REPLY_OVERHEAD {
+            return None;
+        }
+        let (destination, receive, send, payload) = match self.receive_sets[&id].owner {
+            TagOwner::NewSessionReply { ref destination, ref ephemeral, ref chain_key, ref hash } => {
+                let mut representative = [0x00; 32];
+                representative.copy_from_slice(&message[TAG_LENGTH..TAG_LENGTH + KEY_LENGTH]);
+                let remote_ephemeral = decode_elligator2(&representative);
+                let key_section = &message[TAG_LENGTH + KEY_LENGTH..TAG_LENGTH + KEY_LENGTH + MAC_LENGTH];
+
+                let hash = noise::sha256(&[hash, &message[..TAG_LENGTH]]);
+                let hash = noise::sha256(&[&hash, &remote_ephemeral]);
+                let (chain_key, _) = noise::hkdf(chain_key, &diffie_hellman(ephemeral, &remote_ephemeral), &[]);
+                let (chain_key, key) = noise::hkdf(&chain_key, &diffie_hellman(&self.static_key, &remote_ephemeral), &[]);
+                noise::decrypt(&key, 0, &hash, key_section)?;
+                let hash = noise::sha256(&[&hash, key_section]);
+
+                let (send, receive, payload_key) = split(&chain_key);
+                let payload = noise::decrypt(&payload_key, 0, &hash, &message[NEW_SESSION_REPLY_OVERHEAD - MAC_LENGTH..])?;
+
+                (destination.clone(), receive, send, payload)
+            }
+            _ => return None
+        };
+
+        // The first reply sets up the session; the replies to our other New Session
+        // messages are no longer needed.
+        let established = self.outbound.get(&destination).is_none_or(|session| session.send.is_some());
+        if !established {
+            self.remove_receive_sets(|other, owner| match *owner {
+                TagOwner::NewSessionReply { destination: ref owner, .. } => other != id && *owner == destination,
+                _ => false
+            });
+            self.outbound.get_mut(&destination).unwrap().send = Some(send);
+            self.add_receive_set(TagOwner::Outbound { destination: destination.clone() }, receive);
+        }
+
+        Some((RatchetPeer::Destination(destination), payload))
+    }
+
+    /// The peer answered one of our New Session Replies: reply on the tag set of
+    /// that reply from now on, and forget the others.
+    fn confirm_inbound(&mut self, id: u64, static_key: &[u8; 32]) {
+        let reply = match self.receive_sets.get_mut(&id).map(|set| &mut set.owner) {
+            Some(&mut TagOwner::Inbound { ref mut reply, .. }) => reply.take(),
+            _ => None
+        };
+        if let Some(reply) = reply {
+            if let Some(session) = self.inbound.get_mut(static_key) {
+                session.send = Some(reply);
+            }
+            self.remove_receive_sets(|other, owner| match *owner {
+                TagOwner::Inbound { static_key: ref owner, .. } => other != id && owner == static_key,
+                _ => false
+            });
+        }
+    }
+}
+
+impl GarlicEncryptor for RatchetEncryptor {
+    /// Sends New Session messages until the destination replies, then Existing
+    /// Session messages. A new encryption key in its LeaseSet starts a new session.
+    fn encrypt(&mut self, destination: &Hash256, public_key: &[u8; 32], payload: &[u8]) -> Option<Vec<u8>> {
+        let restart = self.outbound.get(destination).is_none_or(|session| session.remote_key != *public_key);
+        if restart {
+            self.outbound.insert(destination.clone(), OutboundSession { remote_key: *public_key, send: None });
+            self.remove_receive_sets(|_, owner| match *owner {
+                TagOwner::NewSessionReply { destination: ref owner, .. } | TagOwner::Outbound { destination: ref owner } => {
+                    owner == destination
+                }
+                _ => false
+            });
+        }
+
+        if let Some(ref mut send) = self.outbound.get_mut(destination).unwrap().send {
+            return RatchetEncryptor::existing_session(send, payload);
+        }
+
+        Some(self.new_session(destination, public_key, payload))
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use rand;
+use rand::Rng;
+use common::{Hash256, I2pDate, I2pInt64, Lease};
+use garlic::clove::{Block, DeliveryInstructions, GarlicClove, encode_blocks};
+use garlic::error::GarlicError;
+use i2np::{I2npMessage, MessageType};
+use tunnel::{ClientTunnels, PooledTunnel};
+
+
+/// The encryption type of ECIES-X25519-AEAD-Ratchet, the only one we build garlic
+/// payloads for.
+pub const ENCRYPTION_TYPE_ECIES_X25519: u16 = 4;
+
+/// The DatabaseStore type byte of a LeaseSet2.
+const STORE_TYPE_LEASE_SET2: u8 = 3;
+
+/// The `LeaseSetLookup` trait finds the current LeaseSet of a remote destination,
+/// from the local network database or a lookup.
+pub trait LeaseSetLookup {
+    fn lookup(&mut self, destination: &Hash256) -> Option<RemoteLeaseSet>;
+}
+
+/// The part of a remote LeaseSet needed to reach its destination.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteLeaseSet {
+    pub leases: Vec<Lease>,
+    pub encryption_type: u16,
+    /// The X25519 public key garlic messages are encrypted to.
+    pub public_key: [u8; 32]
+}
+
+/// The `GarlicEncryptor` trait encrypts a garlic payload for a destination, using
+/// an existing ratchet session or starting a new one with the public key of its
+/// LeaseSet.
+pub trait GarlicEncryptor {
+    fn encrypt(&mut self, destination: &Hash256, public_key: &[u8; 32], payload: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// The `GarlicSender` trait sends a garlic message out through one of our outbound
+/// tunnels to the gateway of a lease.
+pub trait GarlicSender {
+    fn send(&mut self, outbound: &PooledTunnel, lease: &Lease, message: I2npMessage) -> bool;
+}
+
+/// The status of a message handed to the `MessageRouter`, reported to its callback
+/// each time it changes. Every status but `Accepted` is final.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageStatus {
+    /// The message was sent and we are waiting for its acknowledgement.
+    Accepted,
+    /// The destination acknowledged the message.
+    Delivered,
+    /// The LeaseSet of the destination could not be found.
+    NoLeaseSet,
+    /// The LeaseSet of the destination has no lease that has not expired.
+    NoLeases,
+    /// We have no outbound tunnel to send through or inbound tunnel for the
+    /// acknowledgement.
+    NoTunnels,
+    /// The destination uses an encryption type we do not support, or encryption
+    /// failed.
+    EncryptionFailed,
+    /// The outbound tunnel refused the message.
+    SendFailed,
+    /// The message expired before it was acknowledged.
+    Expired
+}
+
+impl MessageStatus {
+    pub fn is_final(&self) -> bool {
+        *self != MessageStatus::Accepted
+    }
+}
+
+/// How often and how long the router tries to deliver a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of times a message is sent before giving up.
+    pub max_attempts: u32,
+    /// The time to wait for an acknowledgement before sending again.
+    pub ack_timeout: u64
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            ack_timeout: 8 * 1000
+        }
+    }
+}
+
+struct OutboundMessage {
+    destination: Hash256,
+    payload: Vec<u8>,
+    expiration: u64,
+    attempts: u32,
+    accepted: bool,
+    ack_id: u32,
+    retry_at: u64,
+    callback: Box<dyn FnMut(MessageStatus)>
+}
+
+fn date(milliseconds: u64) -> I2pDate {
+    I2pDate::new(I2pInt64::new(milliseconds)).unwrap()
+}
+
+/// A `MessageRouter` delivers client payloads to remote destinations. Each attempt
+/// looks up the LeaseSet of the destination, picks a lease and a pair of our own
+/// tunnels, and sends a garlic message holding the payload, our LeaseSet, and a
+/// DeliveryStatus clove routed back to us through the inbound tunnel. Messages that
+/// are not acknowledged in time are sent again, with fresh choices, until the retry
+/// policy gives up or they expire.
+pub struct MessageRouter<L, E, S> {
+    lookup: L,
+    encryptor: E,
+    sender: S,
+    policy: RetryPolicy,
+    lease_set: Option<(Hash256, Vec<u8>)>,
+    messages: HashMap<u32, OutboundMessage>,
+    next_id: u32
+}
+
+impl<L, E, S> MessageRouter<L, E, S> where L: LeaseSetLookup, E: GarlicEncryptor, S: GarlicSender {
+    pub fn new(lookup: L, encryptor: E, sender: S, policy: RetryPolicy) -> MessageRouter<L, E, S> {
+        MessageRouter {
+            lookup,
+            encryptor,
+            sender,
+            policy,
+            lease_set: None,
+            messages: HashMap::new(),
+            next_id: 1
+        }
+    }
+
+    /// Sets our own serialized LeaseSet2 and its key, bundled with every message so
+    /// the destination can reply without a lookup.
+    pub fn set_lease_set(&mut self, key: Hash256, lease_set: Vec<u8>) {
+        self.lease_set = Some((key, lease_set));
+    }
+
+    /// Returns the number of messages waiting for an acknowledgement.
+    pub fn pending(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Sends a payload to a destination. The callback is told of every status
+    /// change. Returns the local ID of the message.
+    pub fn send<F>(&mut self, destination: Hash256, payload: Vec<u8>, expiration: I2pDate, now: I2pDate,
+                   tunnels: &ClientTunnels, callback: F) -> u32
+        where F: FnMut(MessageStatus) + 'static
+    {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let message = OutboundMessage {
+            destination,
+            payload,
+            expiration: expiration.to_u64(),
+            attempts: 0,
+            accepted: false,
+            ack_id: rand::thread_rng().gen(),
+            retry_at: 0,
+            callback: Box::new(callback)
+        };
+        self.messages.insert(id, message);
+        self.attempt(id, now, tunnels);
+
+        id
+    }
+
+    /// Makes one delivery attempt. A failed attempt is retried later unless it was
+    /// the last one or its failure cannot be fixed by retrying.
+    fn attempt(&mut self, id: u32, now: I2pDate, tunnels: &ClientTunnels) {
+        let status = {
+            let message = self.messages.get_mut(&id).unwrap();
+            message.attempts += 1;
+            message.retry_at = now.to_u64() + self.policy.ack_timeout;
+
+            let result = MessageRouter::<L, E, S>::garlic(&mut self.lookup, &mut self.encryptor, self.lease_set.as_ref(),
+                                                           message, now, tunnels);
+            match result {
+                Ok((outbound, lease, garlic)) => {
+                    if self.sender.send(outbound, &lease, garlic) {
+                        MessageStatus::Accepted
+                    } else {
+                        MessageStatus::SendFailed
+                    }
+                }
+                Err(status) => status
+            }
+        };
+
+        let last = {
+            let message = &self.messages[&id];
+            message.attempts >= self.policy.max_attempts || now.to_u64() >= message.expiration
+        };
+        match status {
+            MessageStatus::Accepted => {
+                let message = self.messages.get_mut(&id).unwrap();
+                if !message.accepted {
+                    message.accepted = true;
+                    (message.callback)(MessageStatus::Accepted);
+                }
+            }
+            MessageStatus::EncryptionFailed => self.finish(id, status),
+            _ if last => self.finish(id, status),
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self, id: u32, status: MessageStatus) {
+        if let Some(mut message) = self.messages.remove(&id) {
+            (message.callback)(status);
+        }
+    }
+
+    /// Builds the garlic message for an attempt, or the status explaining why it
+    /// cannot be sent.
+    fn garlic<'t>(lookup: &mut L, encryptor: &mut E, lease_set: Option<&(Hash256, Vec<u8>)>, message: &OutboundMessage,
+                  now: I2pDate, tunnels: &'t ClientTunnels) -> Result<(&'t PooledTunnel, Lease, I2npMessage), MessageStatus> {
+        let remote = lookup.lookup(&message.destination).ok_or(MessageStatus::NoLeaseSet)?;
+        check_encryption_type(remote.encryption_type).map_err(|_| MessageStatus::EncryptionFailed)?;
+
+        let leases: Vec<&Lease> = remote.leases.iter().filter(|lease| lease.end_date > now).collect();
+        if leases.is_empty() {
+            return Err(MessageStatus::NoLeases);
+        }
+        let lease = leases[rand::thread_rng().gen_range(0, leases.len())].clone();
+        let outbound = tunnels.outbound().select().ok_or(MessageStatus::NoTunnels)?;
+        let inbound = tunnels.inbound().select().ok_or(MessageStatus::NoTunnels)?;
+
+        let expiration = date(message.expiration);
+        let mut data = (message.payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&message.payload);
+        let mut status = message.ack_id.to_be_bytes().to_vec();
+        status.extend_from_slice(&now.to_u64().to_be_bytes());
+
+        let mut blocks = vec![Block::DateTime((now.to_u64() / 1000) as u32)];
+        blocks.push(Block::GarlicClove(GarlicClove::new(
+            DeliveryInstructions::Destination(message.destination.clone()),
+            I2npMessage::new(MessageType::Data, rand::thread_rng().gen(), expiration, data))));
+        if let Some(lease_set) = lease_set {
+            let mut store = lease_set.0.as_ref().to_vec();
+            store.push(STORE_TYPE_LEASE_SET2);
+            store.extend_from_slice(&[0x00; 4]);
+            store.extend_from_slice(&lease_set.1);
+            blocks.push(Block::GarlicClove(GarlicClove::new(
+                DeliveryInstructions::Local,
+                I2npMessage::new(MessageType::DatabaseStore, rand::thread_rng().gen(), expiration, store))));
+        }
+        blocks.push(Block::GarlicClove(GarlicClove::new(
+            DeliveryInstructions::Tunnel(inbound.gateway.clone(), inbound.gateway_id),
+            I2npMessage::new(MessageType::DeliveryStatus, message.ack_id, expiration, status))));
+
+        let encrypted = encryptor.encrypt(&message.destination, &remote.public_key, &encode_blocks(&blocks))
+            .ok_or(MessageStatus::EncryptionFailed)?;
+        // The body of a Garlic message is the length of the ciphertext, then the
+        // ciphertext.
+        let mut body = (encrypted.len() as u32).to_be_bytes().to_vec();
+        body.extend_from_slice(&encrypted);
+        let garlic = I2npMessage::new(MessageType::Garlic, rand::thread_rng().gen(), expiration, body);
+
+        Ok((outbound, lease, garlic))
+    }
+
+    /// Handles a DeliveryStatus message that came back through an inbound tunnel.
+    /// Returns `true` if it acknowledges one of our messages.
+    pub fn handle_delivery_status(&mut self, message: &I2npMessage) -> bool {
+        if message.message_type != MessageType::DeliveryStatus || message.payload.len() < 4 {
+            return false;
+        }
+        let ack_id = message.payload[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32));
+
+        let id = match self.messages.iter().find(|&(_, pending)| pending.ack_id == ack_id) {
+            Some((id, _)) => *id,
+            None => return false
+        };
+        self.finish(id, MessageStatus::Delivered);
+
+        true
+    }
+
+    /// Expires messages past their expiration and sends again those that were not
+    /// acknowledged in time.
+    pub fn expire(&mut self, now: I2pDate, tunnels: &ClientTunnels) {
+        let mut expired = Vec::new();
+        let mut retries = Vec::new();
+        for (id, message) in self.messages.iter() {
+            if now.to_u64() >= message.expiration {
+                expired.push(*id);
+            } else if now.to_u64() >= message.retry_at {
+                retries.push(*id);
+            }
+        }
+
+        for id in expired {
+            self.finish(id, MessageStatus::Expired);
+        }
+        for id in retries {
+            if self.messages[&id].attempts >= self.policy.max_attempts {
+                self.finish(id, MessageStatus::Expired);
+            } else {
+                self.attempt(id, now, tunnels);
+            }
+        }
+    }
+}
+
+/// Checks that a destination's encryption type is one we can build garlic for.
+pub fn check_encryption_type(encryption_type: u16) -> Result<(), GarlicError> {
+    if encryption_type == ENCRYPTION_TYPE_ECIES_X25519 {
+        Ok(())
+    } else {
+        Err(GarlicError::UnsupportedEncryption(encryption_type))
+    }
+}
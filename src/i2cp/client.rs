@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use common::{Destination, I2pDate, Lease, Mapping, SessionSigner};
+use i2cp::error::I2cpError;
+use i2cp::message::{I2cpMessage, HostQuery, PROTOCOL_BYTE, HOST_REPLY_SUCCESS};
+use i2cp::session::{SessionConfig, SessionState};
+
+
+/// The client version sent in GetDate.
+pub const CLIENT_VERSION: &str = "0.9.62";
+
+/// The session option asking the router to send MessagePayload directly instead of
+/// announcing each message with ReceiveMessageBegin, which this client does not answer.
+pub const FAST_RECEIVE_OPTION: &str = "i2cp.fastReceive";
+
+/// A SendMessageExpires flag asking the router not to bundle our LeaseSet.
+pub const FLAG_NO_LEASE_SET: u16 = 0x0100;
+
+/// Something the router told the client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientEvent {
+    /// A message arrived for the session.
+    Payload { message_id: u32, payload: Vec<u8> },
+    /// The status of a message sent with a nonce.
+    Status { message_id: u32, status: u8, nonce: u32 },
+    /// The tunnels are ready and the router wants a LeaseSet for these leases,
+    /// which the application signs and sends with `create_lease_set2`.
+    LeaseSetRequested(Vec<Lease>)
+}
+
+/// An `I2cpClient` runs one session on a router.
+pub struct I2cpClient {
+    stream: TcpStream,
+    session_id: u16,
+    router_version: String,
+    events: VecDeque<ClientEvent>,
+    next_request_id: u32
+}
+
+impl I2cpClient {
+    /// Connects to a router and creates a session for the destination, signing
+    /// its SessionConfig with `signer`. `i2cp.fastReceive` is always enabled.
+    pub fn connect<A, S>(address: A, destination: Destination, options: Mapping, signer: &S) -> Result<I2cpClient, I2cpError>
+        where A: ToSocketAddrs, S: SessionSigner
+    {
+        let mut options = options;
+        options.insert(FAST_RECEIVE_OPTION, "true").expect("The option is shorter than an I2P string.");
+
+        let mut stream = TcpStream::connect(address)?;
+        stream.write_all(&[PROTOCOL_BYTE])?;
+
+        I2cpMessage::GetDate { version: CLIENT_VERSION.to_string(), options: None }.write_to(&mut stream)?;
+        let (date, router_version) = match I2cpMessage::read_from(&mut stream)? {
+            I2cpMessage::SetDate { date, version } => (date, version),
+            I2cpMessage::Disconnect(reason) => return Err(I2cpError::Disconnected(reason)),
+            other => return Err(I2cpError::UnexpectedMessage(other.type_code()))
+        };
+
+        // Sign with the router's clock, which is what it checks the date against.
+        let signature = signer.sign(&SessionConfig::signed_bytes(&destination, &options, date));
+        let config = SessionConfig::new(destination, options, date, signature);
+        I2cpMessage::CreateSession(config).write_to(&mut stream)?;
+
+        let mut client = I2cpClient {
+            stream,
+            session_id: 0,
+            router_version,
+            events: VecDeque::new(),
+            next_request_id: 1
+        };
+
+        // The router may ask for a LeaseSet before it reports the session status.
+        loop {
+            match I2cpMessage::read_from(&mut client.stream)? {
+                I2cpMessage::SessionStatus { session_id, state: SessionState::Created } => {
+                    client.session_id = session_id;
+                    return Ok(client);
+                }
+                I2cpMessage::SessionStatus { state, .. } => return Err(I2cpError::SessionFailed(state)),
+                message => {
+                    if let Some(event) = client.event(message)? {
+                        client.events.push_back(event);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+
+    /// Returns the version the router reported.
+    pub fn router_version(&self) -> &str {
+        &self.router_version
+    }
+
+    /// Sends a message. With a nonce other than zero the router reports its status
+    /// as `ClientEvent::Status`.
+    pub fn send(&mut self, destination: &Destination, payload: Vec<u8>, nonce: u32) -> Result<(), I2cpError> {
+        let message = I2cpMessage::SendMessage {
+            session_id: self.session_id,
+            destination: destination.clone(),
+            payload,
+            nonce
+        };
+
+        message.write_to(&mut self.stream)
+    }
+
+    /// Sends a message the router drops if it cannot be sent before `expiration`.
+    pub fn send_expires(&mut self, destination: &Destination, payload: Vec<u8>, nonce: u32, flags: u16,
+                        expiration: I2pDate) -> Result<(), I2cpError> {
+        let message = I2cpMessage::SendMessageExpires {
+            session_id: self.session_id,
+            destination: destination.clone(),
+            payload,
+            nonce,
+            flags,
+            expiration
+        };
+
+        message.write_to(&mut self.stream)
+    }
+
+    /// Sends the signed LeaseSet2 store the router asked for.
+    pub fn create_lease_set2(&mut self, data: Vec<u8>) -> Result<(), I2cpError> {
+        I2cpMessage::CreateLeaseSet2 { session_id: self.session_id, data }.write_to(&mut self.stream)
+    }
+
+    /// Resolves a host name to a destination. Events that arrive while waiting for
+    /// the reply are kept for `receive`.
+    pub fn lookup(&mut self, name: &str, timeout: u32) -> Result<Option<Destination>, I2cpError> {
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        let lookup = I2cpMessage::HostLookup {
+            session_id: self.session_id,
+            request_id,
+            timeout,
+            query: HostQuery::Name(name.to_string())
+        };
+        lookup.write_to(&mut self.stream)?;
+
+        loop {
+            match I2cpMessage::read_from(&mut self.stream)? {
+                I2cpMessage::HostReply { request_id: id, result, destination, .. } if id == request_id => {
+                    return Ok(if result == HOST_REPLY_SUCCESS { destination } else { None });
+                }
+                message => {
+                    if let Some(event) = self.event(message)? {
+                        self.events.push_back(event);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits for the next event from the router.
+    pub fn receive(&mut self) -> Result<ClientEvent, I2cpError> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            let message = I2cpMessage::read_from(&mut self.stream)?;
+            if let Some(event) = self.event(message)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Turns a message from the router into an event. Messages that are not events,
+    /// such as stray replies, are skipped.
+    fn event(&mut self, message: I2cpMessage) -> Result<Option<ClientEvent>, I2cpError> {
+        let event = match message {
+            I2cpMessage::MessagePayload { message_id, payload, .. } => ClientEvent::Payload { message_id, payload },
+            I2cpMessage::MessageStatus { message_id, status, nonce, .. } => ClientEvent::Status { message_id, status, nonce },
+            I2cpMessage::RequestVariableLeaseSet { leases, .. } => ClientEvent::LeaseSetRequested(leases),
+            I2cpMessage::SessionStatus { state: SessionState::Destroyed, .. } => {
+                return Err(I2cpError::SessionFailed(SessionState::Destroyed));
+            }
+            I2cpMessage::Disconnect(reason) => return Err(I2cpError::Disconnected(reason)),
+            _ => return Ok(None)
+        };
+
+        Ok(Some(event))
+    }
+
+    /// Destroys the session and closes the connection.
+    pub fn close(mut self) -> Result<(), I2cpError> {
+        I2cpMessage::DestroySession { session_id: self.session_id }.write_to(&mut self.stream)?;
+        I2cpMessage::Disconnect("client closed".to_string()).write_to(&mut self.stream)
+    }
+}
@@ -0,0 +1,77 @@
+use std::error;
+use std::fmt;
+use std::io;
+use i2cp::session::SessionState;
+
+
+#[derive(Debug)]
+pub enum I2cpError {
+    Io(io::Error),
+    /// The connection did not start with the I2CP protocol byte.
+    InvalidProtocol(u8),
+    /// A message is longer than we accept.
+    MessageTooLarge(usize),
+    /// A message body does not match its type.
+    InvalidMessage(u8),
+    /// The router did not create the session.
+    SessionFailed(SessionState),
+    /// The router sent a message we did not expect at this point.
+    UnexpectedMessage(u8),
+    /// The router closed the connection, with its reason.
+    Disconnected(String),
+}
+
+impl fmt::Display for I2cpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            I2cpError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred on an I2CP connection: {}", err)
+            }
+            I2cpError::InvalidProtocol(byte) => {
+                writeln!(f, "Error: The connection started with {:#04x} instead of the I2CP protocol byte.", byte)
+            }
+            I2cpError::MessageTooLarge(length) => {
+                writeln!(f, "Error: An I2CP message of {} bytes is too large.", length)
+            }
+            I2cpError::InvalidMessage(message_type) => {
+                writeln!(f, "Error: An I2CP message of type {} is malformed.", message_type)
+            }
+            I2cpError::SessionFailed(state) => {
+                writeln!(f, "Error: The router did not create the session: {:?}.", state)
+            }
+            I2cpError::UnexpectedMessage(message_type) => {
+                writeln!(f, "Error: The router sent an unexpected I2CP message of type {}.", message_type)
+            }
+            I2cpError::Disconnected(ref reason) => {
+                writeln!(f, "Error: The router closed the I2CP connection: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for I2cpError {
+    fn description(&self) -> &str {
+        match *self {
+            I2cpError::Io(_) => "An I/O error occurred on an I2CP connection.",
+            I2cpError::InvalidProtocol(_) => "The connection did not start with the I2CP protocol byte.",
+            I2cpError::MessageTooLarge(_) => "An I2CP message is too large.",
+            I2cpError::InvalidMessage(_) => "An I2CP message is malformed.",
+            I2cpError::SessionFailed(_) => "The router did not create the session.",
+            I2cpError::UnexpectedMessage(_) => "The router sent an unexpected I2CP message.",
+            I2cpError::Disconnected(_) => "The router closed the I2CP connection.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            I2cpError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for I2cpError {
+    fn from(err: io::Error) -> I2cpError {
+        I2cpError::Io(err)
+    }
+}
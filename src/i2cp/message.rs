@@ -0,0 +1,397 @@
+use std::io::{Read, Write};
+use std::str;
+use common::{Destination, Hash256, I2pDate, I2pInt64, Lease, Mapping, I2P_LEASE_LENGTH};
+use i2cp::error::I2cpError;
+use i2cp::session::{SessionConfig, SessionState};
+
+
+/// The byte a client sends before its first message.
+pub const PROTOCOL_BYTE: u8 = 0x2A;
+
+/// Refuse messages with bodies larger than this many bytes.
+pub const MAX_MESSAGE_LENGTH: usize = 256 * 1024;
+
+/// The number of limits in a BandwidthLimits message.
+pub const BANDWIDTH_LIMITS: usize = 16;
+
+const CREATE_SESSION: u8 = 1;
+const DESTROY_SESSION: u8 = 3;
+const CREATE_LEASE_SET: u8 = 4;
+const SEND_MESSAGE: u8 = 5;
+const GET_BANDWIDTH_LIMITS: u8 = 8;
+const SESSION_STATUS: u8 = 20;
+const MESSAGE_STATUS: u8 = 22;
+const BANDWIDTH_LIMITS_MESSAGE: u8 = 23;
+const DISCONNECT: u8 = 30;
+const MESSAGE_PAYLOAD: u8 = 31;
+const GET_DATE: u8 = 32;
+const SET_DATE: u8 = 33;
+const SEND_MESSAGE_EXPIRES: u8 = 36;
+const REQUEST_VARIABLE_LEASE_SET: u8 = 37;
+const HOST_LOOKUP: u8 = 38;
+const HOST_REPLY: u8 = 39;
+const CREATE_LEASE_SET2: u8 = 41;
+
+const LOOKUP_HASH: u8 = 0;
+const LOOKUP_HOST: u8 = 1;
+
+/// The status codes of a MessageStatus message.
+pub const STATUS_ACCEPTED: u8 = 1;
+pub const STATUS_GUARANTEED_SUCCESS: u8 = 4;
+pub const STATUS_GUARANTEED_FAILURE: u8 = 5;
+pub const STATUS_BAD_SESSION: u8 = 10;
+pub const STATUS_MESSAGE_EXPIRED: u8 = 14;
+pub const STATUS_NO_LOCAL_TUNNELS: u8 = 16;
+pub const STATUS_NO_LEASESET: u8 = 21;
+
+/// The result code of a successful HostReply.
+pub const HOST_REPLY_SUCCESS: u8 = 0;
+/// The result code of a HostReply for a name or hash that was not found.
+pub const HOST_REPLY_FAILURE: u8 = 1;
+
+/// What a HostLookup asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostQuery {
+    Hash(Hash256),
+    Name(String)
+}
+
+/// An `I2cpMessage` is a message between an I2CP client and a router.
+#[derive(Clone, Debug, PartialEq)]
+pub enum I2cpMessage {
+    CreateSession(SessionConfig),
+    DestroySession { session_id: u16 },
+    /// A signed LeaseSet with the private keys for it, kept opaque; its layout
+    /// depends on the destination's key types.
+    CreateLeaseSet { session_id: u16, data: Vec<u8> },
+    SendMessage { session_id: u16, destination: Destination, payload: Vec<u8>, nonce: u32 },
+    GetBandwidthLimits,
+    SessionStatus { session_id: u16, state: SessionState },
+    MessageStatus { session_id: u16, message_id: u32, status: u8, size: u32, nonce: u32 },
+    BandwidthLimits(Vec<u32>),
+    Disconnect(String),
+    MessagePayload { session_id: u16, message_id: u32, payload: Vec<u8> },
+    GetDate { version: String, options: Option<Mapping> },
+    SetDate { date: I2pDate, version: String },
+    SendMessageExpires { session_id: u16, destination: Destination, payload: Vec<u8>, nonce: u32, flags: u16, expiration: I2pDate },
+    /// Asks the client to sign a LeaseSet for these leases.
+    RequestVariableLeaseSet { session_id: u16, leases: Vec<Lease> },
+    /// `timeout` is in milliseconds.
+    HostLookup { session_id: u16, request_id: u32, timeout: u32, query: HostQuery },
+    HostReply { session_id: u16, request_id: u32, result: u8, destination: Option<Destination> },
+    /// A LeaseSet2 store with the private keys for it, kept opaque.
+    CreateLeaseSet2 { session_id: u16, data: Vec<u8> },
+    Unknown(u8, Vec<u8>)
+}
+
+fn push_string(buf: &mut Vec<u8>, string: &str) {
+    let bytes = &string.as_bytes()[..string.len().min(255)];
+    buf.push(bytes.len() as u8);
+    buf.extend_from_slice(bytes);
+}
+
+fn push_payload(buf: &mut Vec<u8>, payload: &[u8]) {
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(payload);
+}
+
+/// A `Reader` walks through a message body. Every read returns `None` once the body
+/// is exhausted.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn uint(&mut self, length: usize) -> Option<u64> {
+        Some(self.bytes(length)?.iter().fold(0, |value, byte| (value << 8) | (*byte as u64)))
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.uint(1).map(|value| value as u8)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.uint(2).map(|value| value as u16)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.uint(4).map(|value| value as u32)
+    }
+
+    fn date(&mut self, length: usize) -> Option<I2pDate> {
+        I2pDate::new(I2pInt64::new(self.uint(length)?)).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u8()? as usize;
+        str::from_utf8(self.bytes(length)?).ok().map(|string| string.to_string())
+    }
+
+    fn payload(&mut self) -> Option<Vec<u8>> {
+        let length = self.u32()? as usize;
+        self.bytes(length).map(|bytes| bytes.to_vec())
+    }
+
+    fn destination(&mut self) -> Option<Destination> {
+        let (destination, length) = Destination::from_bytes(&self.data[self.offset..])?;
+        self.offset += length;
+        Some(destination)
+    }
+
+    fn mapping(&mut self) -> Option<Mapping> {
+        let (mapping, length) = Mapping::from_bytes(&self.data[self.offset..])?;
+        self.offset += length;
+        Some(mapping)
+    }
+
+    fn rest(&mut self) -> Vec<u8> {
+        let rest = self.data[self.offset..].to_vec();
+        self.offset = self.data.len();
+        rest
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+}
+
+impl I2cpMessage {
+    pub fn type_code(&self) -> u8 {
+        match *self {
+            I2cpMessage::CreateSession(_) => CREATE_SESSION,
+            I2cpMessage::DestroySession { .. } => DESTROY_SESSION,
+            I2cpMessage::CreateLeaseSet { .. } => CREATE_LEASE_SET,
+            I2cpMessage::SendMessage { .. } => SEND_MESSAGE,
+            I2cpMessage::GetBandwidthLimits => GET_BANDWIDTH_LIMITS,
+            I2cpMessage::SessionStatus { .. } => SESSION_STATUS,
+            I2cpMessage::MessageStatus { .. } => MESSAGE_STATUS,
+            I2cpMessage::BandwidthLimits(_) => BANDWIDTH_LIMITS_MESSAGE,
+            I2cpMessage::Disconnect(_) => DISCONNECT,
+            I2cpMessage::MessagePayload { .. } => MESSAGE_PAYLOAD,
+            I2cpMessage::GetDate { .. } => GET_DATE,
+            I2cpMessage::SetDate { .. } => SET_DATE,
+            I2cpMessage::SendMessageExpires { .. } => SEND_MESSAGE_EXPIRES,
+            I2cpMessage::RequestVariableLeaseSet { .. } => REQUEST_VARIABLE_LEASE_SET,
+            I2cpMessage::HostLookup { .. } => HOST_LOOKUP,
+            I2cpMessage::HostReply { .. } => HOST_REPLY,
+            I2cpMessage::CreateLeaseSet2 { .. } => CREATE_LEASE_SET2,
+            I2cpMessage::Unknown(code, _) => code
+        }
+    }
+
+    /// Encodes the message body, without the length and type header.
+    pub fn body(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match *self {
+            I2cpMessage::CreateSession(ref config) => buf.extend_from_slice(&config.to_bytes()),
+            I2cpMessage::DestroySession { session_id } => buf.extend_from_slice(&session_id.to_be_bytes()),
+            I2cpMessage::CreateLeaseSet { session_id, ref data } | I2cpMessage::CreateLeaseSet2 { session_id, ref data } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(data);
+            }
+            I2cpMessage::SendMessage { session_id, ref destination, ref payload, nonce } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(destination.as_ref());
+                push_payload(&mut buf, payload);
+                buf.extend_from_slice(&nonce.to_be_bytes());
+            }
+            I2cpMessage::GetBandwidthLimits => {}
+            I2cpMessage::SessionStatus { session_id, state } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.push(state.code());
+            }
+            I2cpMessage::MessageStatus { session_id, message_id, status, size, nonce } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(&message_id.to_be_bytes());
+                buf.push(status);
+                buf.extend_from_slice(&size.to_be_bytes());
+                buf.extend_from_slice(&nonce.to_be_bytes());
+            }
+            I2cpMessage::BandwidthLimits(ref limits) => {
+                for i in 0..BANDWIDTH_LIMITS {
+                    buf.extend_from_slice(&limits.get(i).cloned().unwrap_or(0).to_be_bytes());
+                }
+            }
+            I2cpMessage::Disconnect(ref reason) => push_string(&mut buf, reason),
+            I2cpMessage::MessagePayload { session_id, message_id, ref payload } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(&message_id.to_be_bytes());
+                push_payload(&mut buf, payload);
+            }
+            I2cpMessage::GetDate { ref version, ref options } => {
+                push_string(&mut buf, version);
+                if let Some(ref options) = *options {
+                    buf.extend_from_slice(&options.to_bytes());
+                }
+            }
+            I2cpMessage::SetDate { date, ref version } => {
+                buf.extend_from_slice(&date.to_u64().to_be_bytes());
+                push_string(&mut buf, version);
+            }
+            I2cpMessage::SendMessageExpires { session_id, ref destination, ref payload, nonce, flags, expiration } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(destination.as_ref());
+                push_payload(&mut buf, payload);
+                buf.extend_from_slice(&nonce.to_be_bytes());
+                buf.extend_from_slice(&flags.to_be_bytes());
+                buf.extend_from_slice(&expiration.to_u64().to_be_bytes()[2..]);
+            }
+            I2cpMessage::RequestVariableLeaseSet { session_id, ref leases } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.push(leases.len() as u8);
+                for lease in leases {
+                    buf.extend_from_slice(&lease.to_bytes());
+                }
+            }
+            I2cpMessage::HostLookup { session_id, request_id, timeout, ref query } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(&request_id.to_be_bytes());
+                buf.extend_from_slice(&timeout.to_be_bytes());
+                match *query {
+                    HostQuery::Hash(ref hash) => {
+                        buf.push(LOOKUP_HASH);
+                        buf.extend_from_slice(hash.as_ref());
+                    }
+                    HostQuery::Name(ref name) => {
+                        buf.push(LOOKUP_HOST);
+                        push_string(&mut buf, name);
+                    }
+                }
+            }
+            I2cpMessage::HostReply { session_id, request_id, result, ref destination } => {
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.extend_from_slice(&request_id.to_be_bytes());
+                buf.push(result);
+                if let Some(ref destination) = *destination {
+                    buf.extend_from_slice(destination.as_ref());
+                }
+            }
+            I2cpMessage::Unknown(_, ref body) => buf.extend_from_slice(body)
+        }
+
+        buf
+    }
+
+    /// Decodes a message body of the given type.
+    pub fn decode(message_type: u8, body: &[u8]) -> Result<I2cpMessage, I2cpError> {
+        let mut reader = Reader { data: body, offset: 0 };
+        I2cpMessage::decode_body(message_type, &mut reader).ok_or(I2cpError::InvalidMessage(message_type))
+    }
+
+    fn decode_body(message_type: u8, reader: &mut Reader) -> Option<I2cpMessage> {
+        let message = match message_type {
+            CREATE_SESSION => I2cpMessage::CreateSession(SessionConfig::from_bytes(reader.data)?.0),
+            DESTROY_SESSION => I2cpMessage::DestroySession { session_id: reader.u16()? },
+            CREATE_LEASE_SET => I2cpMessage::CreateLeaseSet { session_id: reader.u16()?, data: reader.rest() },
+            CREATE_LEASE_SET2 => I2cpMessage::CreateLeaseSet2 { session_id: reader.u16()?, data: reader.rest() },
+            SEND_MESSAGE => I2cpMessage::SendMessage {
+                session_id: reader.u16()?,
+                destination: reader.destination()?,
+                payload: reader.payload()?,
+                nonce: reader.u32()?
+            },
+            GET_BANDWIDTH_LIMITS => I2cpMessage::GetBandwidthLimits,
+            SESSION_STATUS => I2cpMessage::SessionStatus {
+                session_id: reader.u16()?,
+                state: SessionState::from_code(reader.u8()?)
+            },
+            MESSAGE_STATUS => I2cpMessage::MessageStatus {
+                session_id: reader.u16()?,
+                message_id: reader.u32()?,
+                status: reader.u8()?,
+                size: reader.u32()?,
+                nonce: reader.u32()?
+            },
+            BANDWIDTH_LIMITS_MESSAGE => {
+                I2cpMessage::BandwidthLimits((0..BANDWIDTH_LIMITS).map(|_| reader.u32()).collect::<Option<Vec<u32>>>()?)
+            }
+            DISCONNECT => I2cpMessage::Disconnect(reader.string()?),
+            MESSAGE_PAYLOAD => I2cpMessage::MessagePayload {
+                session_id: reader.u16()?,
+                message_id: reader.u32()?,
+                payload: reader.payload()?
+            },
+            GET_DATE => {
+                let version = reader.string()?;
+                let options = if reader.is_empty() { None } else { Some(reader.mapping()?) };
+                I2cpMessage::GetDate { version, options }
+            }
+            SET_DATE => I2cpMessage::SetDate {
+                date: reader.date(8)?,
+                version: reader.string()?
+            },
+            SEND_MESSAGE_EXPIRES => I2cpMessage::SendMessageExpires {
+                session_id: reader.u16()?,
+                destination: reader.destination()?,
+                payload: reader.payload()?,
+                nonce: reader.u32()?,
+                flags: reader.u16()?,
+                expiration: reader.date(6)?
+            },
+            REQUEST_VARIABLE_LEASE_SET => {
+                let session_id = reader.u16()?;
+                let count = reader.u8()? as usize;
+                let mut leases = Vec::with_capacity(count);
+                for _ in 0..count {
+                    leases.push(Lease::from_bytes(reader.bytes(I2P_LEASE_LENGTH)?)?);
+                }
+                I2cpMessage::RequestVariableLeaseSet { session_id, leases }
+            }
+            HOST_LOOKUP => {
+                let (session_id, request_id, timeout) = (reader.u16()?, reader.u32()?, reader.u32()?);
+                let query = match reader.u8()? {
+                    LOOKUP_HASH => {
+                        let mut hash = [0x00; 32];
+                        hash.copy_from_slice(reader.bytes(32)?);
+                        HostQuery::Hash(Hash256::from(hash))
+                    }
+                    LOOKUP_HOST => HostQuery::Name(reader.string()?),
+                    _ => return None
+                };
+                I2cpMessage::HostLookup { session_id, request_id, timeout, query }
+            }
+            HOST_REPLY => {
+                let (session_id, request_id, result) = (reader.u16()?, reader.u32()?, reader.u8()?);
+                let destination = if result == HOST_REPLY_SUCCESS { Some(reader.destination()?) } else { None };
+                I2cpMessage::HostReply { session_id, request_id, result, destination }
+            }
+            _ => I2cpMessage::Unknown(message_type, reader.rest())
+        };
+
+        Some(message)
+    }
+
+    /// Writes the message with its four byte length and one byte type.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), I2cpError> {
+        let body = self.body();
+        let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+        frame.push(self.type_code());
+        frame.extend_from_slice(&body);
+        writer.write_all(&frame)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads the next message.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<I2cpMessage, I2cpError> {
+        let mut header = [0x00; 5];
+        reader.read_exact(&mut header)?;
+        let length = header[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as usize));
+        if length > MAX_MESSAGE_LENGTH {
+            return Err(I2cpError::MessageTooLarge(length));
+        }
+
+        let mut body = vec![0x00; length];
+        reader.read_exact(&mut body)?;
+
+        I2cpMessage::decode(header[4], &body)
+    }
+}
@@ -0,0 +1,18 @@
+pub use self::error::I2cpError;
+pub use self::message::{I2cpMessage, HostQuery, PROTOCOL_BYTE, MAX_MESSAGE_LENGTH, BANDWIDTH_LIMITS};
+pub use self::message::{STATUS_ACCEPTED, STATUS_GUARANTEED_SUCCESS, STATUS_GUARANTEED_FAILURE, STATUS_BAD_SESSION};
+pub use self::message::{STATUS_MESSAGE_EXPIRED, STATUS_NO_LOCAL_TUNNELS, STATUS_NO_LEASESET};
+pub use self::message::{HOST_REPLY_SUCCESS, HOST_REPLY_FAILURE};
+pub use self::payload::{I2cpPayload, PROTOCOL_ANY, PROTOCOL_STREAMING, PROTOCOL_DATAGRAM, PROTOCOL_RAW};
+pub use self::payload::{PROTOCOL_DATAGRAM2, PROTOCOL_DATAGRAM3};
+pub use self::session::{SessionConfig, SessionState, MAX_CONFIG_CLOCK_SKEW_MILLISECONDS};
+pub use self::client::{I2cpClient, ClientEvent, CLIENT_VERSION, FLAG_NO_LEASE_SET, FAST_RECEIVE_OPTION};
+pub use self::server::{I2cpServer, I2cpConnection, I2cpWriter, I2cpBackend, DEFAULT_I2CP_PORT, ROUTER_VERSION};
+
+
+mod error;
+mod message;
+mod payload;
+mod session;
+mod server;
+mod client;
@@ -0,0 +1,71 @@
+use std::io::{Read, Write};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+
+/// The protocol numbers carried in the gzip header of a payload.
+pub const PROTOCOL_ANY: u8 = 0;
+pub const PROTOCOL_STREAMING: u8 = 6;
+pub const PROTOCOL_DATAGRAM: u8 = 17;
+pub const PROTOCOL_RAW: u8 = 18;
+pub const PROTOCOL_DATAGRAM2: u8 = 19;
+pub const PROTOCOL_DATAGRAM3: u8 = 20;
+
+const GZIP_HEADER_LENGTH: usize = 10;
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Payloads shorter than this are stored rather than compressed.
+const MIN_COMPRESSED_LENGTH: usize = 66;
+
+/// An `I2cpPayload` is the data of a SendMessage or MessagePayload message. It is
+/// a gzip stream whose modification time field holds the source and destination
+/// ports, and whose OS field holds the protocol, so one session can serve several
+/// applications.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I2cpPayload {
+    pub protocol: u8,
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub data: Vec<u8>
+}
+
+impl I2cpPayload {
+    pub fn new(protocol: u8, source_port: u16, destination_port: u16, data: Vec<u8>) -> I2cpPayload {
+        I2cpPayload {
+            protocol,
+            source_port,
+            destination_port,
+            data
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let level = if self.data.len() < MIN_COMPRESSED_LENGTH { Compression::none() } else { Compression::default() };
+        let mut encoder = GzEncoder::new(Vec::new(), level);
+        encoder.write_all(&self.data).unwrap();
+        let mut bytes = encoder.finish().unwrap();
+
+        bytes[4..6].copy_from_slice(&self.source_port.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.destination_port.to_be_bytes());
+        bytes[9] = self.protocol;
+
+        bytes
+    }
+
+    /// Decompresses a payload. Returns `None` if it is not a valid gzip stream.
+    pub fn from_bytes(bytes: &[u8]) -> Option<I2cpPayload> {
+        if bytes.len() < GZIP_HEADER_LENGTH || bytes[..3] != GZIP_MAGIC {
+            return None;
+        }
+        let mut data = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut data).ok()?;
+
+        Some(I2cpPayload {
+            protocol: bytes[9],
+            source_port: u16::from_be_bytes([bytes[4], bytes[5]]),
+            destination_port: u16::from_be_bytes([bytes[6], bytes[7]]),
+            data
+        })
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use common::{Destination, I2pDate, SignatureVerifier};
+use i2cp::error::I2cpError;
+use i2cp::message::{I2cpMessage, HostQuery, PROTOCOL_BYTE};
+use i2cp::message::{HOST_REPLY_FAILURE, HOST_REPLY_SUCCESS, STATUS_BAD_SESSION, STATUS_MESSAGE_EXPIRED};
+use i2cp::session::{SessionConfig, SessionState};
+
+
+/// The port routers listen for I2CP clients on by default.
+pub const DEFAULT_I2CP_PORT: u16 = 7654;
+
+/// The router version reported in SetDate. Clients enable protocol features by it.
+pub const ROUTER_VERSION: &str = "0.9.62";
+
+/// How long a new client has to send the protocol byte.
+const PROTOCOL_BYTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `I2cpBackend` trait is the router behind an I2CP server: it runs the tunnels
+/// and the message routing for each session.
+pub trait I2cpBackend {
+    /// Starts a session for a verified config. The writer lets the backend send
+    /// MessagePayload, MessageStatus and RequestVariableLeaseSet messages to the
+    /// client later. Returns `false` to refuse the session.
+    fn create_session(&mut self, session_id: u16, config: &SessionConfig, writer: I2cpWriter) -> bool;
+
+    fn destroy_session(&mut self, session_id: u16);
+
+    /// Publishes the LeaseSet the client signed, from CreateLeaseSet or, if
+    /// `lease_set2` is set, from CreateLeaseSet2.
+    fn create_lease_set(&mut self, session_id: u16, data: &[u8], lease_set2: bool);
+
+    /// Queues a message for sending and returns the status to report to the
+    /// client, normally `STATUS_ACCEPTED`. The final status is reported later
+    /// through the session's writer.
+    fn send_message(&mut self, session_id: u16, message_id: u32, destination: &Destination, payload: &[u8],
+                    expiration: Option<I2pDate>) -> u8;
+
+    /// Resolves a host name or destination hash.
+    fn lookup(&mut self, session_id: u16, query: &HostQuery) -> Option<Destination>;
+
+    /// Returns the inbound and outbound limits reported in BandwidthLimits.
+    fn bandwidth_limits(&self) -> Vec<u32>;
+}
+
+/// An `I2cpWriter` sends messages to a connected client. It can be cloned and used
+/// from other threads while the connection is being served.
+#[derive(Clone)]
+pub struct I2cpWriter {
+    stream: Arc<Mutex<TcpStream>>
+}
+
+impl I2cpWriter {
+    pub fn send(&self, message: &I2cpMessage) -> Result<(), I2cpError> {
+        let mut stream = self.stream.lock().unwrap();
+        message.write_to(&mut *stream)
+    }
+
+    /// Delivers a message received for a session.
+    pub fn deliver(&self, session_id: u16, message_id: u32, payload: Vec<u8>) -> Result<(), I2cpError> {
+        self.send(&I2cpMessage::MessagePayload { session_id, message_id, payload })
+    }
+}
+
+/// An `I2cpServer` accepts I2CP client connections.
+pub struct I2cpServer {
+    listener: TcpListener
+}
+
+impl I2cpServer {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<I2cpServer, I2cpError> {
+        Ok(I2cpServer {
+            listener: TcpListener::bind(address)?
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts the next client. Its protocol byte is read when the connection is
+    /// served, so a client that sends nothing does not hold up the others.
+    pub fn accept(&self) -> Result<I2cpConnection, I2cpError> {
+        let (stream, _) = self.listener.accept()?;
+        let writer = I2cpWriter {
+            stream: Arc::new(Mutex::new(stream.try_clone()?))
+        };
+
+        Ok(I2cpConnection {
+            stream,
+            writer,
+            sessions: HashMap::new(),
+            next_session_id: 0,
+            next_message_id: 1
+        })
+    }
+}
+
+/// An `I2cpConnection` is one connected client, which may run several sessions.
+pub struct I2cpConnection {
+    stream: TcpStream,
+    writer: I2cpWriter,
+    sessions: HashMap<u16, Destination>,
+    next_session_id: u16,
+    next_message_id: u32
+}
+
+impl I2cpConnection {
+    pub fn writer(&self) -> I2cpWriter {
+        self.writer.clone()
+    }
+
+    /// Returns the number of open sessions.
+    pub fn sessions(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Reads the protocol byte the client starts with, waiting at most
+    /// `PROTOCOL_BYTE_TIMEOUT`.
+    fn read_protocol_byte(&mut self) -> Result<(), I2cpError> {
+        let mut protocol = [0x00; 1];
+        self.stream.set_read_timeout(Some(PROTOCOL_BYTE_TIMEOUT))?;
+        self.stream.read_exact(&mut protocol)?;
+        self.stream.set_read_timeout(None)?;
+        if protocol[0] != PROTOCOL_BYTE {
+            return Err(I2cpError::InvalidProtocol(protocol[0]));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the protocol byte, then handles messages until the client disconnects
+    /// or closes the connection, and destroys its remaining sessions.
+    pub fn serve<B, V>(&mut self, backend: &mut B, verifier: &V) -> Result<(), I2cpError>
+        where B: I2cpBackend, V: SignatureVerifier
+    {
+        self.read_protocol_byte()?;
+        let result = loop {
+            let message = match I2cpMessage::read_from(&mut self.stream) {
+                Ok(message) => message,
+                Err(I2cpError::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+                Err(err) => break Err(err)
+            };
+            match self.handle(message, backend, verifier, I2pDate::now()) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err)
+            }
+        };
+
+        for (session_id, _) in self.sessions.drain() {
+            backend.destroy_session(session_id);
+        }
+
+        result
+    }
+
+    /// Handles a single message from the client. Returns `false` if the client
+    /// disconnected.
+    pub fn handle<B, V>(&mut self, message: I2cpMessage, backend: &mut B, verifier: &V, now: I2pDate) -> Result<bool, I2cpError>
+        where B: I2cpBackend, V: SignatureVerifier
+    {
+        match message {
+            I2cpMessage::GetDate { .. } => {
+                self.writer.send(&I2cpMessage::SetDate { date: now, version: ROUTER_VERSION.to_string() })?;
+            }
+            I2cpMessage::GetBandwidthLimits => {
+                self.writer.send(&I2cpMessage::BandwidthLimits(backend.bandwidth_limits()))?;
+            }
+            I2cpMessage::CreateSession(config) => {
+                // Ids wrap around, so skip those of sessions still open.
+                while self.sessions.contains_key(&self.next_session_id) {
+                    self.next_session_id = self.next_session_id.wrapping_add(1);
+                }
+                let session_id = self.next_session_id;
+                let state = if !config.verify(verifier, now) {
+                    SessionState::Invalid
+                } else if backend.create_session(session_id, &config, self.writer.clone()) {
+                    self.sessions.insert(session_id, config.destination.clone());
+                    self.next_session_id = self.next_session_id.wrapping_add(1);
+                    SessionState::Created
+                } else {
+                    SessionState::Refused
+                };
+                self.writer.send(&I2cpMessage::SessionStatus { session_id, state })?;
+            }
+            I2cpMessage::DestroySession { session_id } => {
+                if self.sessions.remove(&session_id).is_some() {
+                    backend.destroy_session(session_id);
+                }
+                self.writer.send(&I2cpMessage::SessionStatus { session_id, state: SessionState::Destroyed })?;
+            }
+            I2cpMessage::CreateLeaseSet { session_id, data } if self.sessions.contains_key(&session_id) => {
+                backend.create_lease_set(session_id, &data, false);
+            }
+            I2cpMessage::CreateLeaseSet2 { session_id, data } if self.sessions.contains_key(&session_id) => {
+                backend.create_lease_set(session_id, &data, true);
+            }
+            message @ I2cpMessage::SendMessage { .. } | message @ I2cpMessage::SendMessageExpires { .. } => {
+                self.send_message(backend, message, now)?;
+            }
+            I2cpMessage::HostLookup { session_id, request_id, ref query, .. } => {
+                let destination = if self.sessions.contains_key(&session_id) { backend.lookup(session_id, query) } else { None };
+                let result = if destination.is_some() { HOST_REPLY_SUCCESS } else { HOST_REPLY_FAILURE };
+                self.writer.send(&I2cpMessage::HostReply { session_id, request_id, result, destination })?;
+            }
+            I2cpMessage::Disconnect(_) => return Ok(false),
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Hands a SendMessage or SendMessageExpires to the backend unless its session
+    /// is unknown or it expired before `now`, and reports the status if the client
+    /// gave a nonce.
+    fn send_message<B: I2cpBackend>(&mut self, backend: &mut B, message: I2cpMessage, now: I2pDate) -> Result<(), I2cpError> {
+        let (session_id, destination, payload, nonce, expiration) = match message {
+            I2cpMessage::SendMessage { session_id, destination, payload, nonce } => {
+                (session_id, destination, payload, nonce, None)
+            }
+            I2cpMessage::SendMessageExpires { session_id, destination, payload, nonce, expiration, .. } => {
+                (session_id, destination, payload, nonce, Some(expiration))
+            }
+            _ => return Ok(())
+        };
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        let status = match expiration {
+            _ if !self.sessions.contains_key(&session_id) => STATUS_BAD_SESSION,
+            Some(expiration) if expiration < now => STATUS_MESSAGE_EXPIRED,
+            _ => backend.send_message(session_id, message_id, &destination, &payload, expiration)
+        };
+
+        // A nonce of zero asks the router not to report the status.
+        if nonce != 0 {
+            let size = payload.len() as u32;
+            self.writer.send(&I2cpMessage::MessageStatus { session_id, message_id, status, size, nonce })?;
+        }
+
+        Ok(())
+    }
+}
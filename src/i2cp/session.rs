@@ -0,0 +1,114 @@
+use common::{Destination, I2pDate, I2pInt64, Mapping, Signature, SignatureVerifier};
+
+
+/// The largest difference between the date of a SessionConfig and our clock.
+pub const MAX_CONFIG_CLOCK_SKEW_MILLISECONDS: u64 = 30 * 1000;
+
+/// The state reported in a SessionStatus message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    Destroyed,
+    Created,
+    Updated,
+    Invalid,
+    Refused,
+    Unknown(u8)
+}
+
+impl SessionState {
+    pub fn from_code(code: u8) -> SessionState {
+        match code {
+            0 => SessionState::Destroyed,
+            1 => SessionState::Created,
+            2 => SessionState::Updated,
+            3 => SessionState::Invalid,
+            4 => SessionState::Refused,
+            _ => SessionState::Unknown(code)
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            SessionState::Destroyed     => 0,
+            SessionState::Created       => 1,
+            SessionState::Updated       => 2,
+            SessionState::Invalid       => 3,
+            SessionState::Refused       => 4,
+            SessionState::Unknown(code) => code
+        }
+    }
+}
+
+/// A `SessionConfig` is the signed request of a client to create or reconfigure a
+/// session: its destination, its options and the current date, signed with the
+/// destination's signing key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+    pub destination: Destination,
+    pub options: Mapping,
+    pub date: I2pDate,
+    pub signature: Signature
+}
+
+impl SessionConfig {
+    pub fn new(destination: Destination, options: Mapping, date: I2pDate, signature: Signature) -> SessionConfig {
+        SessionConfig {
+            destination,
+            options,
+            date,
+            signature
+        }
+    }
+
+    /// Returns the bytes covered by the signature: the destination, the options
+    /// and the date.
+    pub fn signed_bytes(destination: &Destination, options: &Mapping, date: I2pDate) -> Vec<u8> {
+        let mut bytes = destination.as_ref().to_vec();
+        bytes.extend_from_slice(&options.to_bytes());
+        bytes.extend_from_slice(&date.to_u64().to_be_bytes());
+
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = SessionConfig::signed_bytes(&self.destination, &self.options, self.date);
+        bytes.extend_from_slice(self.signature.as_ref());
+
+        bytes
+    }
+
+    /// Parses a session config at the start of `bytes`. Returns the config and the
+    /// number of bytes it took.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(SessionConfig, usize)> {
+        let (destination, mut offset) = Destination::from_bytes(bytes)?;
+        let (options, length) = Mapping::from_bytes(&bytes[offset..])?;
+        offset += length;
+
+        let date = bytes.get(offset..offset + 8)?.iter().fold(0, |value, byte| (value << 8) | (*byte as u64));
+        let date = I2pDate::new(I2pInt64::new(date)).ok()?;
+        offset += 8;
+
+        let sigtype = destination.signature_type()?;
+        let signature = Signature::from_bytes(sigtype, bytes.get(offset..offset + sigtype.signature_length())?)?;
+        offset += sigtype.signature_length();
+
+        Some((SessionConfig::new(destination, options, date, signature), offset))
+    }
+
+    /// Checks the signature against the destination's signing key, and that the date
+    /// is within 30 seconds of `now`.
+    pub fn verify<V: SignatureVerifier>(&self, verifier: &V, now: I2pDate) -> bool {
+        let skew = if self.date > now { self.date.to_u64() - now.to_u64() } else { now.to_u64() - self.date.to_u64() };
+        if skew > MAX_CONFIG_CLOCK_SKEW_MILLISECONDS {
+            return false;
+        }
+
+        match self.destination.signing_public_key() {
+            Some(key) => {
+                let signed = SessionConfig::signed_bytes(&self.destination, &self.options, self.date);
+                verifier.verify(&key, &signed, &self.signature)
+            }
+            None => false
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use common::I2pDate;
+
+
+/// The type of an I2NP message, as carried in the first byte of its header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    DatabaseStore,
+    DatabaseLookup,
+    DatabaseSearchReply,
+    DeliveryStatus,
+    Garlic,
+    TunnelData,
+    TunnelGateway,
+    Data,
+    TunnelBuild,
+    TunnelBuildReply,
+    VariableTunnelBuild,
+    VariableTunnelBuildReply,
+    ShortTunnelBuild,
+    OutboundTunnelBuildReply,
+    Unknown(u8)
+}
+
+impl MessageType {
+    pub fn from_code(code: u8) -> MessageType {
+        match code {
+            1  => MessageType::DatabaseStore,
+            2  => MessageType::DatabaseLookup,
+            3  => MessageType::DatabaseSearchReply,
+            10 => MessageType::DeliveryStatus,
+            11 => MessageType::Garlic,
+            18 => MessageType::TunnelData,
+            19 => MessageType::TunnelGateway,
+            20 => MessageType::Data,
+            21 => MessageType::TunnelBuild,
+            22 => MessageType::TunnelBuildReply,
+            23 => MessageType::VariableTunnelBuild,
+            24 => MessageType::VariableTunnelBuildReply,
+            25 => MessageType::ShortTunnelBuild,
+            26 => MessageType::OutboundTunnelBuildReply,
+            _  => MessageType::Unknown(code)
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            MessageType::DatabaseStore            => 1,
+            MessageType::DatabaseLookup           => 2,
+            MessageType::DatabaseSearchReply      => 3,
+            MessageType::DeliveryStatus           => 10,
+            MessageType::Garlic                   => 11,
+            MessageType::TunnelData               => 18,
+            MessageType::TunnelGateway            => 19,
+            MessageType::Data                     => 20,
+            MessageType::TunnelBuild              => 21,
+            MessageType::TunnelBuildReply         => 22,
+            MessageType::VariableTunnelBuild      => 23,
+            MessageType::VariableTunnelBuildReply => 24,
+            MessageType::ShortTunnelBuild         => 25,
+            MessageType::OutboundTunnelBuildReply => 26,
+            MessageType::Unknown(code)            => code
+        }
+    }
+}
+
+/// An `I2npMessage` is a message exchanged between routers. Each transport encodes
+/// the header in its own way; the payload is carried unchanged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I2npMessage {
+    pub message_type: MessageType,
+    pub message_id: u32,
+    pub expiration: I2pDate,
+    pub payload: Vec<u8>
+}
+
+impl I2npMessage {
+    pub fn new(message_type: MessageType, message_id: u32, expiration: I2pDate, payload: Vec<u8>) -> I2npMessage {
+        I2npMessage {
+            message_type,
+            message_id,
+            expiration,
+            payload
+        }
+    }
+
+    /// Determines whether the message has expired at `now`.
+    pub fn is_expired(&self, now: I2pDate) -> bool {
+        self.expiration < now
+    }
+}
@@ -0,0 +1,4 @@
+pub use self::message::{I2npMessage, MessageType};
+
+
+mod message;
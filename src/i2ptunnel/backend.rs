@@ -0,0 +1,34 @@
+use std::io;
+use common::{Destination, Hash256, Mapping};
+use i2ptunnel::keys::PrivateKeyFile;
+use streaming::ByteStream;
+
+
+/// The `ClientBackend` trait is the session behind client tunnels: it resolves
+/// names and opens streaming connections from the tunnel's destination. It is
+/// shared by the threads serving clients, so it takes `&self`.
+pub trait ClientBackend: Send + Sync {
+    /// Looks up a host name such as `example.i2p` in the address book.
+    fn lookup(&self, name: &str) -> Option<Destination>;
+
+    /// Finds the destination with a hash, from its LeaseSet in the network database.
+    fn lookup_hash(&self, hash: &Hash256) -> Option<Destination>;
+
+    /// Opens a streaming connection to a port of a destination.
+    fn connect(&self, destination: &Destination, port: u16) -> io::Result<Box<dyn ByteStream>>;
+}
+
+/// The `ServerBackend` trait is the session behind a server tunnel: it publishes
+/// the tunnel's destination and accepts the streaming connections made to it.
+pub trait ServerBackend: Send + Sync {
+    /// Publishes a LeaseSet for the destination of `keys`, with the I2CP options
+    /// of its tunnels.
+    fn publish(&self, keys: &PrivateKeyFile, options: &Mapping) -> io::Result<()>;
+
+    /// Waits for the next streaming connection and returns it with the
+    /// destination of the client that opened it.
+    fn accept(&self) -> io::Result<(Destination, Box<dyn ByteStream>)>;
+
+    /// Ends the session, so a waiting `accept` returns an error.
+    fn close(&self);
+}
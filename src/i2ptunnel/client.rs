@@ -0,0 +1,82 @@
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use i2ptunnel::backend::ClientBackend;
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::naming::resolve;
+use streaming::pipe;
+
+
+/// A `ClientTunnel` makes one destination reachable at a local port: every
+/// connection to the port becomes a streaming connection to the destination.
+pub struct ClientTunnel<B> {
+    listener: TcpListener,
+    backend: Arc<B>,
+    target: String,
+    port: u16
+}
+
+impl<B> ClientTunnel<B> where B: ClientBackend + 'static {
+    /// Listens on `address` for connections to port `port` of the I2P host `target`.
+    pub fn bind<A: ToSocketAddrs>(address: A, target: &str, port: u16, backend: Arc<B>) -> io::Result<ClientTunnel<B>> {
+        Ok(ClientTunnel {
+            listener: TcpListener::bind(address)?,
+            backend,
+            target: target.to_string(),
+            port
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts the next local connection.
+    pub fn accept(&self) -> io::Result<ClientTunnelConnection<B>> {
+        let (client, _) = self.listener.accept()?;
+
+        Ok(ClientTunnelConnection {
+            client,
+            backend: self.backend.clone(),
+            target: self.target.clone(),
+            port: self.port
+        })
+    }
+
+    /// Serves each connection on its own thread until accepting one fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let connection = self.accept()?;
+            thread::spawn(move || connection.serve());
+        }
+    }
+}
+
+/// A `ClientTunnelConnection` is one local connection to a `ClientTunnel`.
+pub struct ClientTunnelConnection<B> {
+    client: TcpStream,
+    backend: Arc<B>,
+    target: String,
+    port: u16
+}
+
+impl<B> ClientTunnelConnection<B> where B: ClientBackend {
+    /// Connects to the destination and copies data both ways. On failure the
+    /// local connection is closed.
+    pub fn serve(self) -> Result<(), TunnelError> {
+        let stream = resolve(&*self.backend, &self.target).and_then(|destination| {
+            self.backend.connect(&destination, self.port).map_err(|_| TunnelError::Unreachable(self.target.clone()))
+        });
+        match stream {
+            Ok(stream) => {
+                pipe(self.client, Vec::new(), stream)?;
+                Ok(())
+            }
+            Err(err) => {
+                let _ = self.client.shutdown(Shutdown::Both);
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use common::Mapping;
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::server::{ServerTunnelConfig, ServerType};
+
+
+/// The interface client tunnels listen on when the configuration names none.
+pub const DEFAULT_INTERFACE: &str = "127.0.0.1";
+/// The host of a server tunnel's service when the configuration names none.
+pub const DEFAULT_TARGET_HOST: &str = "127.0.0.1";
+
+/// The streaming option that limits how many connections one client may open a
+/// minute. Server tunnels enforce it themselves.
+const MAX_CONNS_PER_MINUTE: &str = "i2p.streaming.maxConnsPerMinute";
+/// The keys of `tunnels.conf` sections that are I2CP or streaming options.
+const I2PD_OPTION_PREFIXES: [&str; 4] = ["inbound.", "outbound.", "i2cp.", "i2p."];
+
+/// The kinds of tunnel a configuration can define.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TunnelType {
+    /// Forwards a local port to one destination. Java's `ircclient` tunnels are
+    /// loaded as this type, without its IRC filtering.
+    Client,
+    HttpProxy,
+    Socks,
+    Server,
+    HttpServer,
+    IrcServer
+}
+
+impl TunnelType {
+    /// Returns the type with its name in `i2ptunnel.config`.
+    fn from_java_name(name: &str) -> Option<TunnelType> {
+        match name {
+            "client" | "ircclient" => Some(TunnelType::Client),
+            "httpclient" | "connectclient" => Some(TunnelType::HttpProxy),
+            "sockstunnel" => Some(TunnelType::Socks),
+            "server" => Some(TunnelType::Server),
+            "httpserver" => Some(TunnelType::HttpServer),
+            "ircserver" => Some(TunnelType::IrcServer),
+            _ => None
+        }
+    }
+
+    /// Returns the type with its name in i2pd's `tunnels.conf`.
+    fn from_i2pd_name(name: &str) -> Option<TunnelType> {
+        match name {
+            "client" => Some(TunnelType::Client),
+            "httpproxy" => Some(TunnelType::HttpProxy),
+            "socks" => Some(TunnelType::Socks),
+            "server" => Some(TunnelType::Server),
+            "http" => Some(TunnelType::HttpServer),
+            "irc" => Some(TunnelType::IrcServer),
+            _ => None
+        }
+    }
+
+    /// Whether tunnels of this type listen on a local port.
+    pub fn is_client(&self) -> bool {
+        match *self {
+            TunnelType::Client | TunnelType::HttpProxy | TunnelType::Socks => true,
+            TunnelType::Server | TunnelType::HttpServer | TunnelType::IrcServer => false
+        }
+    }
+
+    fn server_type(&self) -> Option<ServerType> {
+        match *self {
+            TunnelType::Server => Some(ServerType::Tcp),
+            TunnelType::HttpServer => Some(ServerType::Http),
+            TunnelType::IrcServer => Some(ServerType::Irc),
+            _ => None
+        }
+    }
+}
+
+/// A `TunnelDefinition` is one tunnel of a configuration file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TunnelDefinition {
+    pub name: String,
+    pub tunnel_type: TunnelType,
+    /// The interface client tunnels listen on.
+    pub interface: String,
+    /// The port client tunnels listen on.
+    pub listen_port: Option<u16>,
+    /// The I2P host of a client tunnel, the outproxy of a proxy, or the host of a
+    /// server tunnel's service.
+    pub target: Option<String>,
+    /// The port on a client tunnel's destination, or of a server tunnel's service.
+    pub target_port: Option<u16>,
+    pub key_file: Option<PathBuf>,
+    /// The Host header of an HTTP server tunnel.
+    pub host_override: Option<String>,
+    /// The WEBIRC password of an IRC server tunnel.
+    pub webirc_password: Option<String>,
+    pub start_on_load: bool,
+    /// The I2CP and streaming options of the tunnel's session.
+    pub options: Mapping
+}
+
+impl TunnelDefinition {
+    pub fn new(name: &str, tunnel_type: TunnelType) -> TunnelDefinition {
+        TunnelDefinition {
+            name: name.to_string(),
+            tunnel_type,
+            interface: DEFAULT_INTERFACE.to_string(),
+            listen_port: None,
+            target: None,
+            target_port: None,
+            key_file: None,
+            host_override: None,
+            webirc_password: None,
+            start_on_load: true,
+            options: Mapping::new()
+        }
+    }
+
+    /// Checks that the definition has what its type needs: a listen port for
+    /// client tunnels, a destination for plain clients, and a service port and
+    /// key file for servers.
+    pub fn validate(&self) -> Result<(), TunnelError> {
+        let missing = |what: &str| Err(invalid(&format!("the tunnel {:?} has no {}", self.name, what)));
+        if self.name.is_empty() {
+            return Err(invalid("a tunnel has no name"));
+        }
+        if self.tunnel_type.is_client() && self.listen_port.is_none() {
+            return missing("listen port");
+        }
+        if self.tunnel_type == TunnelType::Client && self.target.is_none() {
+            return missing("destination");
+        }
+        if !self.tunnel_type.is_client() {
+            if self.target_port.is_none() {
+                return missing("target port");
+            }
+            if self.key_file.is_none() {
+                return missing("private key file");
+            }
+        }
+        if let Some(value) = self.options.get(MAX_CONNS_PER_MINUTE) {
+            if value.parse::<usize>().is_err() {
+                return Err(invalid(&format!("the tunnel {:?} has an invalid {} {:?}", self.name, MAX_CONNS_PER_MINUTE, value)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configuration of a server tunnel, resolving its service's
+    /// address. Returns `None` for client tunnels.
+    pub fn server_config(&self) -> Result<Option<ServerTunnelConfig>, TunnelError> {
+        let server_type = match self.tunnel_type.server_type() {
+            Some(server_type) => server_type,
+            None => return Ok(None)
+        };
+        let host = self.target.as_deref().unwrap_or(DEFAULT_TARGET_HOST);
+        let port = self.target_port.unwrap_or(0);
+        let target: SocketAddr = (host, port).to_socket_addrs()?.next()
+            .ok_or_else(|| invalid(&format!("the target host {:?} of the tunnel {:?} has no address", host, self.name)))?;
+
+        let mut config = ServerTunnelConfig::new(server_type, target);
+        config.max_new_per_client = self.options.get(MAX_CONNS_PER_MINUTE)
+            .and_then(|value| value.parse().ok())
+            .filter(|max| *max > 0);
+        config.throttle_period = Duration::from_secs(60);
+        config.host = self.host_override.clone();
+        config.webirc_password = self.webirc_password.clone();
+
+        Ok(Some(config))
+    }
+}
+
+fn invalid(reason: &str) -> TunnelError {
+    TunnelError::InvalidConfig(reason.to_string())
+}
+
+fn parse_port(name: &str, key: &str, value: &str) -> Result<u16, TunnelError> {
+    value.trim().parse().map_err(|_| invalid(&format!("the {} of the tunnel {:?} is not a port: {:?}", key, name, value)))
+}
+
+fn insert_option(definition: &mut TunnelDefinition, key: &str, value: &str) -> Result<(), TunnelError> {
+    definition.options.insert(key, value)
+        .map_err(|_| invalid(&format!("the option {} of the tunnel {:?} is too long", key, definition.name)))
+}
+
+/// Whether a properties line ends with an unescaped backslash.
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|ch| *ch == '\\').count() % 2 == 1
+}
+
+/// Splits a properties line at the first unescaped `=`, `:` or whitespace.
+fn split_property(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' => escaped = true,
+            '=' | ':' => return (&line[..i], line[i + 1..].trim()),
+            ch if ch.is_whitespace() => {
+                let rest = line[i..].trim_start();
+                let rest = rest.strip_prefix(&['=', ':'][..]).unwrap_or(rest);
+                return (&line[..i], rest.trim());
+            }
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn unescape(text: &str, line: usize) -> Result<String, TunnelError> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('f') => unescaped.push('\x0c'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let ch = u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32)
+                    .ok_or_else(|| invalid(&format!("line {} has an invalid \\u escape", line)))?;
+                unescaped.push(ch);
+            }
+            Some(ch) => unescaped.push(ch),
+            None => {}
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Reads Java properties into key/value pairs, in order. Supports `#` and `!`
+/// comments, line continuations and escapes.
+fn read_properties(text: &str) -> Result<Vec<(String, String)>, TunnelError> {
+    let mut properties = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let mut logical = line.trim_start().to_string();
+        if logical.is_empty() || logical.starts_with('#') || logical.starts_with('!') {
+            continue;
+        }
+        while continues(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some((_, next)) => logical.push_str(next.trim_start()),
+                None => break
+            }
+        }
+        let (key, value) = split_property(&logical);
+        properties.push((unescape(key, number + 1)?, unescape(value, number + 1)?));
+    }
+    Ok(properties)
+}
+
+/// Splits a `host[:port]` target, such as the `targetDestination` of a Java
+/// client tunnel.
+fn split_target(target: &str) -> (String, Option<u16>) {
+    if let Some((host, port)) = target.rsplit_once(':') {
+        if let Ok(port) = port.parse() {
+            return (host.to_string(), Some(port));
+        }
+    }
+    (target.to_string(), None)
+}
+
+/// A `TunnelConfig` holds the tunnels read from a configuration file, and a
+/// warning for each tunnel that was skipped because its type is not supported.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TunnelConfig {
+    pub definitions: Vec<TunnelDefinition>,
+    pub warnings: Vec<String>
+}
+
+fn unsupported(name: &str, type_name: &str) -> String {
+    format!("skipped the tunnel {:?} of the unsupported type {:?}", name, type_name)
+}
+
+/// Parses Java I2P's `i2ptunnel.config`, where each tunnel is a set of
+/// `tunnel.N.*` properties. Unknown properties are ignored, and tunnels of
+/// unsupported types are skipped with a warning.
+pub fn parse_i2ptunnel_config(text: &str) -> Result<TunnelConfig, TunnelError> {
+    let mut tunnels: BTreeMap<u32, Vec<(String, String)>> = BTreeMap::new();
+    for (key, value) in read_properties(text)? {
+        let rest = match key.strip_prefix("tunnel.") {
+            Some(rest) => rest,
+            None => continue
+        };
+        if let Some((index, property)) = rest.split_once('.') {
+            if let Ok(index) = index.parse() {
+                tunnels.entry(index).or_default().push((property.to_string(), value));
+            }
+        }
+    }
+
+    let mut config = TunnelConfig::default();
+    for (index, properties) in tunnels {
+        let get = |name: &str| properties.iter().rev().find(|property| property.0 == name).map(|property| property.1.as_str());
+        let name = get("name").map(|name| name.to_string()).unwrap_or_else(|| format!("tunnel.{}", index));
+        let type_name = get("type").ok_or_else(|| invalid(&format!("the tunnel {:?} has no type", name)))?;
+        let tunnel_type = match TunnelType::from_java_name(type_name) {
+            Some(tunnel_type) => tunnel_type,
+            None => {
+                config.warnings.push(unsupported(&name, type_name));
+                continue;
+            }
+        };
+
+        let mut definition = TunnelDefinition::new(&name, tunnel_type);
+        for (key, value) in &properties {
+            match key.as_str() {
+                "interface" => definition.interface = value.clone(),
+                "listenPort" => definition.listen_port = Some(parse_port(&name, key, value)?),
+                "targetHost" => definition.target = Some(value.clone()),
+                "targetPort" => definition.target_port = Some(parse_port(&name, key, value)?),
+                "privKeyFile" => definition.key_file = Some(PathBuf::from(value)),
+                "spoofedHost" => definition.host_override = Some(value.clone()),
+                "startOnLoad" => definition.start_on_load = value == "true",
+                // Both list destinations, of which the first is used.
+                "targetDestination" | "proxyList" => {
+                    let first = value.split(',').map(|item| item.trim()).find(|item| !item.is_empty());
+                    if let Some(first) = first {
+                        let (host, port) = split_target(first);
+                        definition.target = Some(host);
+                        if key == "targetDestination" {
+                            definition.target_port = port;
+                        }
+                    }
+                }
+                _ => {
+                    if let Some(option) = key.strip_prefix("option.") {
+                        insert_option(&mut definition, option, value)?;
+                    }
+                }
+            }
+        }
+        if definition.options.get("ircserver.method") == Some("webirc") {
+            definition.webirc_password = definition.options.get("ircserver.webircPassword").map(|password| password.to_string());
+        }
+        config.definitions.push(definition);
+    }
+
+    Ok(config)
+}
+
+/// Parses i2pd's `tunnels.conf`, where each tunnel is an INI section. Keys that
+/// are I2CP or streaming options become the tunnel's options, and other unknown
+/// keys are ignored. Tunnels of unsupported types are skipped with a warning.
+pub fn parse_tunnels_conf(text: &str) -> Result<TunnelConfig, TunnelError> {
+    let mut config = TunnelConfig::default();
+    let mut section: Option<(String, Vec<(String, String)>)> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            if let Some((name, properties)) = section.take() {
+                i2pd_definition(&name, &properties, &mut config)?;
+            }
+            section = Some((line[1..line.len() - 1].trim().to_string(), Vec::new()));
+            continue;
+        }
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| invalid(&format!("line {} is not a section or key = value", number + 1)))?;
+        match section {
+            Some((_, ref mut properties)) => properties.push((key.trim().to_string(), value.trim().to_string())),
+            None => return Err(invalid(&format!("line {} is outside a section", number + 1)))
+        }
+    }
+    if let Some((name, properties)) = section {
+        i2pd_definition(&name, &properties, &mut config)?;
+    }
+
+    Ok(config)
+}
+
+/// Adds the tunnel of an i2pd section to `config`, or a warning if its type is
+/// not supported.
+fn i2pd_definition(name: &str, properties: &[(String, String)], config: &mut TunnelConfig) -> Result<(), TunnelError> {
+    let type_name = properties.iter().rev().find(|property| property.0 == "type")
+        .map(|property| property.1.as_str())
+        .ok_or_else(|| invalid(&format!("the tunnel {:?} has no type", name)))?;
+    let tunnel_type = match TunnelType::from_i2pd_name(type_name) {
+        Some(tunnel_type) => tunnel_type,
+        None => {
+            config.warnings.push(unsupported(name, type_name));
+            return Ok(());
+        }
+    };
+
+    let mut definition = TunnelDefinition::new(name, tunnel_type);
+    for (key, value) in properties {
+        match key.as_str() {
+            "type" => {}
+            "address" => definition.interface = value.clone(),
+            // A client's listen port, or a server's service port.
+            "port" if tunnel_type.is_client() => definition.listen_port = Some(parse_port(name, key, value)?),
+            "port" => definition.target_port = Some(parse_port(name, key, value)?),
+            "destination" | "outproxy" => definition.target = Some(value.clone()),
+            "destinationport" => definition.target_port = Some(parse_port(name, key, value)?),
+            "host" => definition.target = Some(value.clone()),
+            "keys" => definition.key_file = Some(PathBuf::from(value)),
+            "hostoverride" => definition.host_override = Some(value.clone()),
+            "webircpassword" => definition.webirc_password = Some(value.clone()),
+            _ => {
+                if I2PD_OPTION_PREFIXES.iter().any(|prefix| key.starts_with(prefix)) {
+                    insert_option(&mut definition, key, value)?;
+                }
+            }
+        }
+    }
+    config.definitions.push(definition);
+
+    Ok(())
+}
+
+/// Validates each definition, and that no two share a name or a listen address.
+pub fn validate_definitions(definitions: &[TunnelDefinition]) -> Result<(), TunnelError> {
+    let mut names = HashSet::new();
+    let mut listeners = HashSet::new();
+    for definition in definitions {
+        definition.validate()?;
+        if !names.insert(definition.name.as_str()) {
+            return Err(invalid(&format!("more than one tunnel is named {:?}", definition.name)));
+        }
+        if let Some(port) = definition.listen_port.filter(|port| *port != 0) {
+            if definition.tunnel_type.is_client() && !listeners.insert((definition.interface.as_str(), port)) {
+                return Err(invalid(&format!("more than one tunnel listens on {}:{}", definition.interface, port)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads and validates a configuration file in either format: i2pd's INI format
+/// when a line opens a section, and Java properties otherwise. Relative key files
+/// are resolved against the file's directory.
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<TunnelConfig, TunnelError> {
+    let text = fs::read_to_string(path.as_ref())?;
+    let is_ini = text.lines().any(|line| line.trim_start().starts_with('['));
+    let mut config = if is_ini { parse_tunnels_conf(&text)? } else { parse_i2ptunnel_config(&text)? };
+
+    if let Some(directory) = path.as_ref().parent() {
+        for definition in &mut config.definitions {
+            if let Some(ref mut key_file) = definition.key_file {
+                if key_file.is_relative() {
+                    *key_file = directory.join(&*key_file);
+                }
+            }
+        }
+    }
+    validate_definitions(&config.definitions)?;
+
+    Ok(config)
+}
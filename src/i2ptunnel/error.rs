@@ -0,0 +1,83 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum TunnelError {
+    Io(io::Error),
+    /// A client sent a request the tunnel cannot parse.
+    InvalidRequest(String),
+    /// A host name is not in the address book, or a `.b32.i2p` address has no
+    /// destination we can find.
+    UnknownHost(String),
+    /// A destination was found, but no streaming connection could be opened to it.
+    Unreachable(String),
+    /// A request is for a host outside I2P, and no outproxy is configured.
+    NoOutproxy(String),
+    /// A client asked for a command or feature the tunnel does not implement.
+    Unsupported(String),
+    /// A private key file is truncated or names an unknown signature type.
+    InvalidKeyFile(String),
+    /// A tunnel configuration cannot be parsed, or defines an invalid tunnel.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TunnelError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred in a tunnel: {}", err)
+            }
+            TunnelError::InvalidRequest(ref reason) => {
+                writeln!(f, "Error: A tunnel client sent an invalid request: {}", reason)
+            }
+            TunnelError::UnknownHost(ref host) => {
+                writeln!(f, "Error: The host {} could not be resolved to a destination.", host)
+            }
+            TunnelError::Unreachable(ref host) => {
+                writeln!(f, "Error: The destination of {} could not be reached.", host)
+            }
+            TunnelError::NoOutproxy(ref host) => {
+                writeln!(f, "Error: The host {} is outside I2P and no outproxy is configured.", host)
+            }
+            TunnelError::Unsupported(ref feature) => {
+                writeln!(f, "Error: A tunnel client asked for {}, which is not supported.", feature)
+            }
+            TunnelError::InvalidKeyFile(ref path) => {
+                writeln!(f, "Error: The private key file {} is invalid.", path)
+            }
+            TunnelError::InvalidConfig(ref reason) => {
+                writeln!(f, "Error: The tunnel configuration is invalid: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for TunnelError {
+    fn description(&self) -> &str {
+        match *self {
+            TunnelError::Io(_) => "An I/O error occurred in a tunnel.",
+            TunnelError::InvalidRequest(_) => "A tunnel client sent an invalid request.",
+            TunnelError::UnknownHost(_) => "A host could not be resolved to a destination.",
+            TunnelError::Unreachable(_) => "A destination could not be reached.",
+            TunnelError::NoOutproxy(_) => "A host is outside I2P and no outproxy is configured.",
+            TunnelError::Unsupported(_) => "A tunnel client asked for something that is not supported.",
+            TunnelError::InvalidKeyFile(_) => "A private key file is invalid.",
+            TunnelError::InvalidConfig(_) => "A tunnel configuration is invalid.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            TunnelError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for TunnelError {
+    fn from(err: io::Error) -> TunnelError {
+        TunnelError::Io(err)
+    }
+}
@@ -0,0 +1,336 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use i2ptunnel::backend::ClientBackend;
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::naming::{is_i2p_host, resolve};
+use streaming::{ByteStream, pipe};
+
+
+/// The port HTTP proxies listen on by default.
+pub const DEFAULT_HTTP_PROXY_PORT: u16 = 4444;
+
+/// The headers that identify the browser or the page it came from. They are
+/// removed from every request.
+pub const STRIPPED_HEADERS: [&str; 3] = ["User-Agent", "Referer", "X-Forwarded-For"];
+
+/// The headers meant for the proxy itself, which are not forwarded.
+const PROXY_HEADERS: [&str; 2] = ["Proxy-Connection", "Proxy-Authorization"];
+
+const DEFAULT_HTTP_PORT: u16 = 80;
+const DEFAULT_HTTPS_PORT: u16 = 443;
+
+const MAX_LINE_LENGTH: usize = 8192;
+const MAX_HEADERS: usize = 100;
+
+const CONNECT_ESTABLISHED: &[u8] = b"HTTP/1.1 200 Connection established\r\n\r\n";
+
+/// An `HttpRequest` is the request line and headers of an HTTP request. The body,
+/// if any, follows on the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>
+}
+
+/// Reads a line ending with a line feed, without the line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, TunnelError> {
+    let mut line = Vec::new();
+    reader.by_ref().take(MAX_LINE_LENGTH as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Err(TunnelError::InvalidRequest("the connection closed before the request ended".to_string()));
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(TunnelError::InvalidRequest("a line of the request is too long".to_string()));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|_| TunnelError::InvalidRequest("the request is not UTF-8".to_string()))
+}
+
+/// Splits `host[:port]` into the host and the port, if one is given.
+fn parse_authority(authority: &str) -> Option<(String, Option<u16>)> {
+    // Credentials are never forwarded to the host they name.
+    let authority = authority.rsplit('@').next()?;
+    let (host, port) = match authority.rfind(':') {
+        Some(colon) if !authority[colon..].contains(']') => (&authority[..colon], Some(authority[colon + 1..].parse().ok()?)),
+        _ => (authority, None)
+    };
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((host.to_string(), port))
+}
+
+impl HttpRequest {
+    /// Reads the request line and headers.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<HttpRequest, TunnelError> {
+        let line = read_line(reader)?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 || !parts[2].starts_with("HTTP/") {
+            return Err(TunnelError::InvalidRequest(format!("the request line {:?} is malformed", line)));
+        }
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(TunnelError::InvalidRequest("the request has too many headers".to_string()));
+            }
+            let colon = line.find(':')
+                .ok_or_else(|| TunnelError::InvalidRequest(format!("the header {:?} is malformed", line)))?;
+            headers.push((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()));
+        }
+
+        Ok(HttpRequest {
+            method: parts[0].to_string(),
+            target: parts[1].to_string(),
+            version: parts[2].to_string(),
+            headers
+        })
+    }
+
+    /// Returns the first value of a header. Names are compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|header| header.0.eq_ignore_ascii_case(name))
+            .map(|header| header.1.as_str())
+    }
+
+    /// Replaces every value of a header with one value.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|header| !header.0.eq_ignore_ascii_case(name));
+    }
+
+    /// Splits an absolute `http://` target into the host, the port if one is given,
+    /// and the path.
+    pub fn url(&self) -> Option<(String, Option<u16>, String)> {
+        let scheme = "http://";
+        if !self.target.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme)) {
+            return None;
+        }
+        let rest = &self.target[scheme.len()..];
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/")
+        };
+        let (host, port) = parse_authority(authority)?;
+
+        Some((host, port, path.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut text = format!("{} {} {}\r\n", self.method, self.target, self.version);
+        for (name, value) in self.headers.iter() {
+            text.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        text.push_str("\r\n");
+
+        text.into_bytes()
+    }
+
+    /// Removes the headers that identify the client or are meant for the proxy.
+    fn strip(&mut self) {
+        for name in STRIPPED_HEADERS.iter().chain(PROXY_HEADERS.iter()) {
+            self.remove_header(name);
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch)
+        }
+    }
+    escaped
+}
+
+/// Returns the response that explains an error to the browser.
+pub fn error_page(err: &TunnelError) -> Vec<u8> {
+    let (status, title, message) = match *err {
+        TunnelError::InvalidRequest(ref reason) => (
+            "400 Bad Request", "Bad Request",
+            format!("The proxy could not understand the request: {}.", escape_html(reason))
+        ),
+        TunnelError::UnknownHost(ref host) => (
+            "404 Not Found", "Website Unknown",
+            format!("The host <b>{}</b> is not in the address book. Check the spelling, add the host to \
+                     the address book, or use its .b32.i2p address.", escape_html(host))
+        ),
+        TunnelError::Unreachable(ref host) => (
+            "504 Gateway Timeout", "Website Unreachable",
+            format!("The destination of <b>{}</b> could not be reached. The site may be offline, or the \
+                     tunnels to it are not built yet; try again in a few minutes.", escape_html(host))
+        ),
+        TunnelError::NoOutproxy(ref host) => (
+            "503 Service Unavailable", "Outproxy Not Configured",
+            format!("The host <b>{}</b> is outside I2P, and this proxy has no outproxy to reach it.", escape_html(host))
+        ),
+        TunnelError::Unsupported(ref feature) => (
+            "501 Not Implemented", "Not Implemented",
+            format!("The proxy does not support {}.", escape_html(feature))
+        ),
+        TunnelError::Io(_) | TunnelError::InvalidKeyFile(_) | TunnelError::InvalidConfig(_) => (
+            "500 Internal Server Error", "Proxy Error",
+            "The proxy failed while handling the request.".to_string()
+        )
+    };
+    let body = format!("<html><head><title>{0}</title></head><body><h1>{0}</h1><p>{1}</p></body></html>\n", title, message);
+
+    format!("HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, body.len(), body).into_bytes()
+}
+
+/// An `HttpProxy` is a client tunnel that browsers use as their HTTP proxy. It
+/// sends requests for `.i2p` hosts over streaming connections to their
+/// destinations, and other requests to an outproxy, if one is set.
+pub struct HttpProxy<B> {
+    listener: TcpListener,
+    backend: Arc<B>,
+    outproxy: Option<String>
+}
+
+impl<B> HttpProxy<B> where B: ClientBackend + 'static {
+    pub fn bind<A: ToSocketAddrs>(address: A, backend: Arc<B>) -> io::Result<HttpProxy<B>> {
+        Ok(HttpProxy {
+            listener: TcpListener::bind(address)?,
+            backend,
+            outproxy: None
+        })
+    }
+
+    /// Sets the I2P host that requests for hosts outside I2P go to.
+    pub fn set_outproxy(&mut self, outproxy: Option<&str>) {
+        self.outproxy = outproxy.map(|outproxy| outproxy.to_string());
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts the next browser connection.
+    pub fn accept(&self) -> io::Result<HttpProxyConnection<B>> {
+        let (client, _) = self.listener.accept()?;
+
+        Ok(HttpProxyConnection {
+            client,
+            backend: self.backend.clone(),
+            outproxy: self.outproxy.clone()
+        })
+    }
+
+    /// Serves each connection on its own thread until accepting one fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let connection = self.accept()?;
+            thread::spawn(move || connection.serve());
+        }
+    }
+}
+
+/// What a request turns into: the stream to forward it on, the bytes to send on the
+/// stream first, and the reply to send the browser first.
+type Forward = (Box<dyn ByteStream>, Vec<u8>, Option<&'static [u8]>);
+
+/// An `HttpProxyConnection` serves one request. The connection to the site is
+/// closed after the response, so a browser opens a new connection for the next
+/// request, which may be for another host.
+pub struct HttpProxyConnection<B> {
+    client: TcpStream,
+    backend: Arc<B>,
+    outproxy: Option<String>
+}
+
+impl<B> HttpProxyConnection<B> where B: ClientBackend {
+    /// Reads the request, forwards it, and copies the response back. On failure the
+    /// browser gets an error page.
+    pub fn serve(self) -> Result<(), TunnelError> {
+        let mut reader = BufReader::new(self.client.try_clone()?);
+        match HttpRequest::read(&mut reader).and_then(|request| self.forward(request)) {
+            Ok((stream, request, reply)) => {
+                if let Some(reply) = reply {
+                    (&self.client).write_all(reply)?;
+                }
+                // Whatever the browser sent after the headers, such as a body, follows.
+                let mut pending = request;
+                pending.extend_from_slice(reader.buffer());
+                pipe(self.client, pending, stream)?;
+
+                Ok(())
+            }
+            Err(err) => {
+                let _ = (&self.client).write_all(&error_page(&err));
+                let _ = self.client.shutdown(Shutdown::Both);
+
+                Err(err)
+            }
+        }
+    }
+
+    fn forward(&self, mut request: HttpRequest) -> Result<Forward, TunnelError> {
+        if request.method.eq_ignore_ascii_case("CONNECT") {
+            let (host, port) = parse_authority(&request.target)
+                .ok_or_else(|| TunnelError::InvalidRequest(format!("the CONNECT target {:?} is malformed", request.target)))?;
+            if is_i2p_host(&host) {
+                let stream = self.connect(&host, port.unwrap_or(DEFAULT_HTTPS_PORT))?;
+                return Ok((stream, Vec::new(), Some(CONNECT_ESTABLISHED)));
+            }
+
+            // The outproxy answers the CONNECT itself.
+            request.strip();
+            let stream = self.connect_outproxy(&host)?;
+            return Ok((stream, request.to_bytes(), None));
+        }
+
+        let (host, port, path) = request.url()
+            .ok_or_else(|| TunnelError::InvalidRequest(format!("the target {:?} is not an http:// URL", request.target)))?;
+        request.strip();
+        request.set_header("Connection", "close");
+        if !is_i2p_host(&host) {
+            let stream = self.connect_outproxy(&host)?;
+            return Ok((stream, request.to_bytes(), None));
+        }
+
+        request.target = path;
+        match port {
+            Some(port) => request.set_header("Host", &format!("{}:{}", host, port)),
+            None => request.set_header("Host", &host)
+        }
+        let stream = self.connect(&host, port.unwrap_or(DEFAULT_HTTP_PORT))?;
+
+        Ok((stream, request.to_bytes(), None))
+    }
+
+    fn connect(&self, host: &str, port: u16) -> Result<Box<dyn ByteStream>, TunnelError> {
+        let destination = resolve(&*self.backend, host)?;
+        self.backend.connect(&destination, port).map_err(|_| TunnelError::Unreachable(host.to_string()))
+    }
+
+    fn connect_outproxy(&self, host: &str) -> Result<Box<dyn ByteStream>, TunnelError> {
+        match self.outproxy {
+            Some(ref outproxy) => self.connect(outproxy, 0),
+            None => Err(TunnelError::NoOutproxy(host.to_string()))
+        }
+    }
+}
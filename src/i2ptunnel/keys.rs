@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use common::{Destination, PrivateKey, SigningPrivateKey};
+use i2ptunnel::error::TunnelError;
+
+
+const PRIVATE_KEY_LENGTH: usize = 256;
+
+/// A `PrivateKeyFile` holds the keys of a server tunnel's destination the way Java
+/// I2P and i2pd store them: the destination, then its private encryption key, then
+/// its private signing key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivateKeyFile {
+    pub destination: Destination,
+    pub private_key: PrivateKey,
+    pub signing_private_key: SigningPrivateKey
+}
+
+impl PrivateKeyFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.destination.as_ref().to_vec();
+        bytes.extend_from_slice(self.private_key.as_ref());
+        bytes.extend_from_slice(self.signing_private_key.as_ref());
+        bytes
+    }
+
+    /// Parses the keys. Anything after the signing key, such as an offline
+    /// signature section, is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Option<PrivateKeyFile> {
+        let (destination, length) = Destination::from_bytes(bytes)?;
+        let signature_type = destination.signature_type()?;
+        let mut private_key = [0x00; PRIVATE_KEY_LENGTH];
+        private_key.copy_from_slice(bytes.get(length..length + PRIVATE_KEY_LENGTH)?);
+        let signing_start = length + PRIVATE_KEY_LENGTH;
+        let signing_key = bytes.get(signing_start..signing_start + signature_type.private_key_length())?;
+
+        Some(PrivateKeyFile {
+            signing_private_key: SigningPrivateKey::from_bytes(signature_type, signing_key)?,
+            private_key: PrivateKey::from(private_key),
+            destination
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PrivateKeyFile, TunnelError> {
+        let mut bytes = Vec::new();
+        File::open(path.as_ref())?.read_to_end(&mut bytes)?;
+
+        PrivateKeyFile::from_bytes(&bytes).ok_or_else(|| TunnelError::InvalidKeyFile(path.as_ref().display().to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        File::create(path)?.write_all(&self.to_bytes())
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+use i2ptunnel::backend::{ClientBackend, ServerBackend};
+use i2ptunnel::client::ClientTunnel;
+use i2ptunnel::config::{TunnelConfig, TunnelDefinition, TunnelType, load_config};
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::http_proxy::HttpProxy;
+use i2ptunnel::keys::PrivateKeyFile;
+use i2ptunnel::server::ServerTunnel;
+use i2ptunnel::socks::SocksProxy;
+
+
+/// The `TunnelFactory` trait opens the sessions behind configured tunnels, with
+/// each definition's I2CP options and, if it names one, key file.
+pub trait TunnelFactory {
+    type Client: ClientBackend + 'static;
+    type Server: ServerBackend + 'static;
+
+    fn client_backend(&self, definition: &TunnelDefinition) -> io::Result<Self::Client>;
+
+    fn server_backend(&self, definition: &TunnelDefinition) -> io::Result<Self::Server>;
+}
+
+/// A `RunningTunnel` is a tunnel whose connections are accepted on a thread.
+struct RunningTunnel {
+    definition: TunnelDefinition,
+    local_addr: Option<SocketAddr>,
+    stopping: Arc<AtomicBool>,
+    /// Wakes the thread from a blocked accept, so it sees `stopping`.
+    wake: Box<dyn Fn() + Send>,
+    thread: JoinHandle<()>
+}
+
+impl RunningTunnel {
+    /// Stops accepting connections. Connections already open continue.
+    fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        (self.wake)();
+        let _ = self.thread.join();
+    }
+}
+
+/// Accepts connections on a thread, serving each on its own, until accepting
+/// fails or the tunnel is stopped.
+fn accept_until_stopped<A>(stopping: Arc<AtomicBool>, accept: A) -> JoinHandle<()>
+    where A: Fn() -> io::Result<Box<dyn FnOnce() + Send>> + Send + 'static {
+    thread::spawn(move || {
+        while let Ok(serve) = accept() {
+            if stopping.load(Ordering::SeqCst) {
+                break;
+            }
+            thread::spawn(serve);
+        }
+    })
+}
+
+/// Returns a function that wakes a listener by connecting to it.
+fn connect_to(address: SocketAddr) -> Box<dyn Fn() + Send> {
+    let mut address = address;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST)
+        });
+    }
+    Box::new(move || { let _ = TcpStream::connect(address); })
+}
+
+/// A `TunnelManager` runs the tunnels of a configuration file, in either
+/// `i2ptunnel.config` or `tunnels.conf` format, and applies changes to the file.
+pub struct TunnelManager<F: TunnelFactory> {
+    path: PathBuf,
+    factory: F,
+    modified: Option<SystemTime>,
+    warnings: Vec<String>,
+    running: BTreeMap<String, RunningTunnel>
+}
+
+impl<F> TunnelManager<F> where F: TunnelFactory {
+    /// Creates a manager for the file at `path`. Nothing runs until `reload`.
+    pub fn new<P: AsRef<Path>>(path: P, factory: F) -> TunnelManager<F> {
+        TunnelManager {
+            path: path.as_ref().to_path_buf(),
+            factory,
+            modified: None,
+            warnings: Vec::new(),
+            running: BTreeMap::new()
+        }
+    }
+
+    /// Loads the file and brings the running tunnels in line with it: tunnels that
+    /// were removed or changed are stopped, and new or changed tunnels that start
+    /// on load are started. An invalid file changes nothing. A tunnel that fails to
+    /// start does not stop the others; the first such error is returned. Tunnels of
+    /// unsupported types are skipped, and reported by `warnings`.
+    pub fn reload(&mut self) -> Result<(), TunnelError> {
+        self.modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        let TunnelConfig { definitions, warnings } = load_config(&self.path)?;
+        self.warnings = warnings;
+
+        let stale: Vec<String> = self.running.iter()
+            .filter(|&(name, tunnel)| !definitions.iter().any(|definition| definition.name == *name && *definition == tunnel.definition))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            self.stop(&name);
+        }
+
+        let mut result = Ok(());
+        for definition in definitions {
+            if definition.start_on_load && !self.running.contains_key(&definition.name) {
+                if let Err(err) = self.start(definition) {
+                    result = result.and(Err(err));
+                }
+            }
+        }
+        result
+    }
+
+    /// Reloads the file if it changed since it was last loaded. Returns whether
+    /// it was reloaded.
+    pub fn reload_if_changed(&mut self) -> Result<bool, TunnelError> {
+        let modified = fs::metadata(&self.path)?.modified()?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.reload()?;
+
+        Ok(true)
+    }
+
+    /// Starts a tunnel, first stopping a running tunnel of the same name.
+    pub fn start(&mut self, definition: TunnelDefinition) -> Result<(), TunnelError> {
+        definition.validate()?;
+        self.stop(&definition.name);
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let listen = (definition.interface.as_str(), definition.listen_port.unwrap_or(0));
+        let (local_addr, wake, thread) = match definition.tunnel_type {
+            TunnelType::Client => {
+                let backend = Arc::new(self.factory.client_backend(&definition)?);
+                let target = definition.target.as_deref().unwrap_or_default();
+                let tunnel = ClientTunnel::bind(listen, target, definition.target_port.unwrap_or(0), backend)?;
+                let local_addr = tunnel.local_addr()?;
+                let thread = accept_until_stopped(stopping.clone(), move || {
+                    let connection = tunnel.accept()?;
+                    Ok(Box::new(move || { let _ = connection.serve(); }))
+                });
+                (Some(local_addr), connect_to(local_addr), thread)
+            }
+            TunnelType::HttpProxy => {
+                let backend = Arc::new(self.factory.client_backend(&definition)?);
+                let mut proxy = HttpProxy::bind(listen, backend)?;
+                proxy.set_outproxy(definition.target.as_deref());
+                let local_addr = proxy.local_addr()?;
+                let thread = accept_until_stopped(stopping.clone(), move || {
+                    let connection = proxy.accept()?;
+                    Ok(Box::new(move || { let _ = connection.serve(); }))
+                });
+                (Some(local_addr), connect_to(local_addr), thread)
+            }
+            TunnelType::Socks => {
+                let backend = Arc::new(self.factory.client_backend(&definition)?);
+                let mut proxy = SocksProxy::bind(listen, backend)?;
+                proxy.set_outproxy(definition.target.as_deref());
+                let local_addr = proxy.local_addr()?;
+                let thread = accept_until_stopped(stopping.clone(), move || {
+                    let connection = proxy.accept()?;
+                    Ok(Box::new(move || { let _ = connection.serve(); }))
+                });
+                (Some(local_addr), connect_to(local_addr), thread)
+            }
+            TunnelType::Server | TunnelType::HttpServer | TunnelType::IrcServer => {
+                let config = definition.server_config()?
+                    .ok_or_else(|| TunnelError::InvalidConfig(format!("the tunnel {:?} is not a server", definition.name)))?;
+                let key_file = definition.key_file.as_ref()
+                    .ok_or_else(|| TunnelError::InvalidConfig(format!("the tunnel {:?} has no private key file", definition.name)))?;
+                let keys = PrivateKeyFile::load(key_file)?;
+                let backend = Arc::new(self.factory.server_backend(&definition)?);
+                let tunnel = ServerTunnel::start(&keys, &definition.options, config, backend.clone())?;
+                let thread = accept_until_stopped(stopping.clone(), move || {
+                    let connection = tunnel.accept()?;
+                    Ok(Box::new(move || { let _ = connection.serve(); }))
+                });
+                let wake: Box<dyn Fn() + Send> = Box::new(move || backend.close());
+                (None, wake, thread)
+            }
+        };
+
+        self.running.insert(definition.name.clone(), RunningTunnel { definition, local_addr, stopping, wake, thread });
+        Ok(())
+    }
+
+    /// Stops a tunnel. Returns `false` if no tunnel of that name is running.
+    pub fn stop(&mut self, name: &str) -> bool {
+        match self.running.remove(name) {
+            Some(tunnel) => {
+                tunnel.stop();
+                true
+            }
+            None => false
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        let names: Vec<String> = self.running.keys().cloned().collect();
+        for name in names {
+            self.stop(&name);
+        }
+    }
+
+    /// Returns the warnings of the last reload, one for each tunnel that was skipped.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Returns the names of the running tunnels, in order.
+    pub fn running(&self) -> Vec<&str> {
+        self.running.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Returns the address a running client tunnel listens on.
+    pub fn local_addr(&self, name: &str) -> Option<SocketAddr> {
+        self.running.get(name).and_then(|tunnel| tunnel.local_addr)
+    }
+}
+
+impl<F> Drop for TunnelManager<F> where F: TunnelFactory {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
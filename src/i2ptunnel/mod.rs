@@ -0,0 +1,25 @@
+pub use self::error::TunnelError;
+pub use self::backend::{ClientBackend, ServerBackend};
+pub use self::naming::{b32_address, b32_hash, is_i2p_host, resolve};
+pub use self::http_proxy::{HttpProxy, HttpProxyConnection, HttpRequest, error_page};
+pub use self::http_proxy::{DEFAULT_HTTP_PROXY_PORT, STRIPPED_HEADERS};
+pub use self::socks::{SocksProxy, SocksConnection, DEFAULT_SOCKS_PORT};
+pub use self::client::{ClientTunnel, ClientTunnelConnection};
+pub use self::keys::PrivateKeyFile;
+pub use self::server::{ServerTunnel, ServerConnection, ServerTunnelConfig, ServerType};
+pub use self::server::{DEST_HASH_HEADER, DEST_B32_HEADER, DEST_B64_HEADER, DEFAULT_THROTTLE_PERIOD};
+pub use self::config::{TunnelConfig, TunnelDefinition, TunnelType, DEFAULT_INTERFACE, DEFAULT_TARGET_HOST};
+pub use self::config::{load_config, parse_i2ptunnel_config, parse_tunnels_conf, validate_definitions};
+pub use self::manager::{TunnelFactory, TunnelManager};
+
+
+mod error;
+mod backend;
+mod naming;
+mod http_proxy;
+mod socks;
+mod client;
+mod keys;
+mod server;
+mod config;
+mod manager;
@@ -0,0 +1,89 @@
+use common::{Destination, Hash256, Hashable256};
+use i2ptunnel::backend::ClientBackend;
+use i2ptunnel::error::TunnelError;
+
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// The length of the base32 label of a `.b32.i2p` address for a 32 byte hash.
+const B32_LABEL_LENGTH: usize = 52;
+
+const B32_SUFFIX: &str = ".b32.i2p";
+const I2P_SUFFIX: &str = ".i2p";
+
+/// Returns the `.b32.i2p` address of a destination: the lowercase base32 form of its
+/// hash, without padding.
+pub fn b32_address(destination: &Destination) -> String {
+    let hash = destination.hash_sha256();
+    let mut address = String::with_capacity(B32_LABEL_LENGTH + B32_SUFFIX.len());
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in hash.as_ref() {
+        buffer = (buffer << 8) | (*byte as u32);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            address.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        address.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    address.push_str(B32_SUFFIX);
+
+    address
+}
+
+/// Decodes the hash in a `.b32.i2p` address. Returns `None` for other names, and
+/// for the longer addresses of encrypted LeaseSets.
+pub fn b32_hash(host: &str) -> Option<Hash256> {
+    let host = host.to_ascii_lowercase();
+    if !host.ends_with(B32_SUFFIX) {
+        return None;
+    }
+    let label = &host[..host.len() - B32_SUFFIX.len()];
+    if label.len() != B32_LABEL_LENGTH {
+        return None;
+    }
+
+    let mut hash = [0x00; 32];
+    let mut length = 0;
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for ch in label.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&c| c == ch)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            if length < hash.len() {
+                hash[length] = (buffer >> bits) as u8;
+                length += 1;
+            }
+        }
+    }
+
+    Some(Hash256::from(hash))
+}
+
+/// Determines whether a host is inside I2P, so it needs no outproxy.
+pub fn is_i2p_host(host: &str) -> bool {
+    host.to_ascii_lowercase().ends_with(I2P_SUFFIX)
+}
+
+/// Resolves a host to a destination: a `.b32.i2p` address by its hash, another
+/// `.i2p` name with the address book, and a destination in base64, with or without
+/// `.i2p`, as itself.
+pub fn resolve<B: ClientBackend + ?Sized>(backend: &B, host: &str) -> Result<Destination, TunnelError> {
+    let lowercase = host.to_ascii_lowercase();
+    let destination = if let Some(hash) = b32_hash(&lowercase) {
+        backend.lookup_hash(&hash)
+    } else if lowercase.ends_with(I2P_SUFFIX) {
+        // Base64 is case sensitive, so a destination written as a host keeps its case.
+        backend.lookup(&lowercase).or_else(|| Destination::from_i2p_base64(&host[..host.len() - I2P_SUFFIX.len()]))
+    } else {
+        Destination::from_i2p_base64(host)
+    };
+
+    destination.ok_or_else(|| TunnelError::UnknownHost(host.to_string()))
+}
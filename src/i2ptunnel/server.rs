@@ -0,0 +1,282 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::{BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use common::{Destination, Hash256, Hashable256, Mapping, ToI2pBase64};
+use i2ptunnel::backend::ServerBackend;
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::http_proxy::HttpRequest;
+use i2ptunnel::keys::PrivateKeyFile;
+use i2ptunnel::naming::b32_address;
+use streaming::{ByteStream, pipe};
+
+
+/// The headers an HTTP server tunnel adds to name the client: the Base64 of its
+/// destination's hash, its `.b32.i2p` address, and its full destination in Base64.
+/// Headers with these names sent by the client are removed first.
+pub const DEST_HASH_HEADER: &str = "X-I2P-DestHash";
+pub const DEST_B32_HEADER: &str = "X-I2P-DestB32";
+pub const DEST_B64_HEADER: &str = "X-I2P-DestB64";
+/// How long the connections counted against `max_new_per_client` are remembered.
+pub const DEFAULT_THROTTLE_PERIOD: Duration = Duration::from_secs(60);
+
+/// The kinds of server tunnel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerType {
+    /// Copies bytes between the client and the service unchanged.
+    Tcp,
+    /// Rewrites the request head, adding the `X-I2P-Dest*` headers and setting
+    /// the Host header.
+    Http,
+    /// Sends a WEBIRC line first, so the IRC server shows the client's
+    /// `.b32.i2p` address as its host.
+    Irc
+}
+
+/// How a `ServerTunnel` reaches its service and limits its clients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerTunnelConfig {
+    pub server_type: ServerType,
+    /// The address of the local service.
+    pub target: SocketAddr,
+    /// The most connections one client may have open at once.
+    pub max_active_per_client: Option<usize>,
+    /// The most connections one client may open in each `throttle_period`.
+    pub max_new_per_client: Option<usize>,
+    pub throttle_period: Duration,
+    /// The Host header of HTTP requests. The target address when `None`.
+    pub host: Option<String>,
+    /// The password of the WEBIRC line. IRC tunnels send none when `None`.
+    pub webirc_password: Option<String>
+}
+
+impl ServerTunnelConfig {
+    /// Creates a configuration without client limits.
+    pub fn new(server_type: ServerType, target: SocketAddr) -> ServerTunnelConfig {
+        ServerTunnelConfig {
+            server_type,
+            target,
+            max_active_per_client: None,
+            max_new_per_client: None,
+            throttle_period: DEFAULT_THROTTLE_PERIOD,
+            host: None,
+            webirc_password: None
+        }
+    }
+}
+
+/// Returns a plain text response with a status line.
+fn status_page(status: &str, message: &str) -> Vec<u8> {
+    format!("HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, message.len(), message).into_bytes()
+}
+
+/// `ClientLimits` counts the connections of each client, by destination hash.
+#[derive(Default)]
+struct ClientLimits {
+    active: HashMap<Hash256, usize>,
+    recent: HashMap<Hash256, VecDeque<Instant>>
+}
+
+impl ClientLimits {
+    /// Counts a new connection from `client` if the limits allow it.
+    fn admit(&mut self, client: &Hash256, config: &ServerTunnelConfig, now: Instant) -> bool {
+        if let Some(max) = config.max_active_per_client {
+            if self.active.get(client).cloned().unwrap_or(0) >= max {
+                return false;
+            }
+        }
+        if let Some(max) = config.max_new_per_client {
+            let period = config.throttle_period;
+            for starts in self.recent.values_mut() {
+                while starts.front().is_some_and(|start| now.duration_since(*start) >= period) {
+                    starts.pop_front();
+                }
+            }
+            self.recent.retain(|_, starts| !starts.is_empty());
+
+            let starts = self.recent.entry(client.clone()).or_default();
+            if starts.len() >= max {
+                return false;
+            }
+            starts.push_back(now);
+        }
+        *self.active.entry(client.clone()).or_insert(0) += 1;
+
+        true
+    }
+
+    fn release(&mut self, client: &Hash256) {
+        if let Some(active) = self.active.get_mut(client) {
+            *active -= 1;
+            if *active == 0 {
+                self.active.remove(client);
+            }
+        }
+    }
+}
+
+/// A `ClientSlot` is one admitted connection. The client's count of open
+/// connections goes down when it is dropped.
+struct ClientSlot {
+    client: Hash256,
+    limits: Arc<Mutex<ClientLimits>>
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        if let Ok(mut limits) = self.limits.lock() {
+            limits.release(&self.client);
+        }
+    }
+}
+
+/// A `ServerTunnel` makes a local service reachable as an I2P destination. It
+/// publishes the destination's LeaseSet, accepts streaming connections to it and
+/// proxies each to the service.
+pub struct ServerTunnel<B> {
+    backend: Arc<B>,
+    destination: Destination,
+    config: Arc<ServerTunnelConfig>,
+    limits: Arc<Mutex<ClientLimits>>
+}
+
+impl<B> ServerTunnel<B> where B: ServerBackend + 'static {
+    /// Publishes the destination of `keys` with the I2CP `options`.
+    pub fn start(keys: &PrivateKeyFile, options: &Mapping, config: ServerTunnelConfig,
+                 backend: Arc<B>) -> io::Result<ServerTunnel<B>> {
+        backend.publish(keys, options)?;
+
+        Ok(ServerTunnel {
+            backend,
+            destination: keys.destination.clone(),
+            config: Arc::new(config),
+            limits: Arc::new(Mutex::new(ClientLimits::default()))
+        })
+    }
+
+    pub fn destination(&self) -> &Destination {
+        &self.destination
+    }
+
+    pub fn config(&self) -> &ServerTunnelConfig {
+        &self.config
+    }
+
+    /// Accepts the next connection within the client limits. Connections over a
+    /// limit are closed, after a 429 response for HTTP tunnels.
+    pub fn accept(&self) -> io::Result<ServerConnection> {
+        loop {
+            let (client, mut stream) = self.backend.accept()?;
+            let hash = client.hash_sha256();
+            let admitted = self.limits.lock()
+                .map(|mut limits| limits.admit(&hash, &self.config, Instant::now()))
+                .unwrap_or(false);
+            if admitted {
+                return Ok(ServerConnection {
+                    client,
+                    stream,
+                    config: self.config.clone(),
+                    _slot: ClientSlot { client: hash, limits: self.limits.clone() }
+                });
+            }
+
+            if self.config.server_type == ServerType::Http {
+                let _ = stream.write_all(&status_page("429 Too Many Requests", "Too many connections, try again later.\n"));
+            }
+            let _ = stream.shutdown_write();
+        }
+    }
+
+    /// Serves each connection on its own thread until accepting one fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let connection = self.accept()?;
+            thread::spawn(move || connection.serve());
+        }
+    }
+}
+
+/// A `ServerConnection` is one client's streaming connection to a server tunnel.
+pub struct ServerConnection {
+    client: Destination,
+    stream: Box<dyn ByteStream>,
+    config: Arc<ServerTunnelConfig>,
+    _slot: ClientSlot
+}
+
+impl ServerConnection {
+    /// Returns the destination of the client.
+    pub fn client(&self) -> &Destination {
+        &self.client
+    }
+
+    /// Connects to the service and copies between it and the client until both
+    /// are done.
+    pub fn serve(mut self) -> Result<(), TunnelError> {
+        let local = match TcpStream::connect(self.config.target) {
+            Ok(local) => local,
+            Err(err) => {
+                if self.config.server_type == ServerType::Http {
+                    let _ = self.stream.write_all(&status_page("503 Service Unavailable", "The service is not running.\n"));
+                }
+                let _ = self.stream.shutdown_write();
+                return Err(err.into());
+            }
+        };
+
+        let pending = match self.config.server_type {
+            ServerType::Tcp => Vec::new(),
+            ServerType::Http => match self.rewrite_request() {
+                Ok(pending) => pending,
+                Err(err) => {
+                    let _ = self.stream.write_all(&status_page("400 Bad Request", "The request is malformed.\n"));
+                    let _ = self.stream.shutdown_write();
+                    return Err(err);
+                }
+            },
+            ServerType::Irc => self.webirc_line(&local)?
+        };
+        (&local).write_all(&pending)?;
+        pipe(local, Vec::new(), self.stream)?;
+
+        Ok(())
+    }
+
+    /// Reads the request head and returns it rewritten, followed by whatever the
+    /// client sent after it. The service closes the connection after responding,
+    /// so every request gets the headers.
+    fn rewrite_request(&self) -> Result<Vec<u8>, TunnelError> {
+        let mut reader = BufReader::new(self.stream.try_clone()?);
+        let mut request = HttpRequest::read(&mut reader)?;
+        for name in &[DEST_HASH_HEADER, DEST_B32_HEADER, DEST_B64_HEADER] {
+            request.remove_header(name);
+        }
+        request.set_header(DEST_HASH_HEADER, &self.client.hash_sha256().as_ref().to_i2p_base64());
+        request.set_header(DEST_B32_HEADER, &b32_address(&self.client));
+        request.set_header(DEST_B64_HEADER, &self.client.to_i2p_base64());
+        match self.config.host {
+            Some(ref host) => request.set_header("Host", host),
+            None => request.set_header("Host", &self.config.target.to_string())
+        }
+        request.set_header("Connection", "close");
+
+        let mut pending = request.to_bytes();
+        pending.extend_from_slice(reader.buffer());
+        Ok(pending)
+    }
+
+    /// Returns the WEBIRC line that tells the IRC server the client's address.
+    fn webirc_line(&self, local: &TcpStream) -> io::Result<Vec<u8>> {
+        match self.config.webirc_password {
+            Some(ref password) => {
+                let line = format!("WEBIRC {} cgiirc {} {}\r\n", password, b32_address(&self.client), local.local_addr()?.ip());
+                Ok(line.into_bytes())
+            }
+            None => Ok(Vec::new())
+        }
+    }
+}
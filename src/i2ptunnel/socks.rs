@@ -0,0 +1,337 @@
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use i2ptunnel::backend::ClientBackend;
+use i2ptunnel::error::TunnelError;
+use i2ptunnel::naming::{is_i2p_host, resolve};
+use streaming::{ByteStream, pipe};
+
+
+/// The port SOCKS proxies listen on by default, as in i2pd.
+pub const DEFAULT_SOCKS_PORT: u16 = 4447;
+
+const SOCKS4_VERSION: u8 = 4;
+const SOCKS5_VERSION: u8 = 5;
+
+const COMMAND_CONNECT: u8 = 1;
+
+const SOCKS4_GRANTED: u8 = 90;
+const SOCKS4_REJECTED: u8 = 91;
+
+const METHOD_NO_AUTHENTICATION: u8 = 0x00;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+/// The reply codes of SOCKS5.
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_FAILURE: u8 = 0x01;
+const REPLY_NOT_ALLOWED: u8 = 0x02;
+const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+const REPLY_CONNECTION_REFUSED: u8 = 0x05;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+/// The longest user ID or host name a SOCKS4 request may carry.
+const MAX_SOCKS4_STRING_LENGTH: usize = 255;
+
+/// Where a client asks to connect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Target {
+    Domain(String),
+    Ip(IpAddr)
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Domain(ref host) => write!(f, "{}", host),
+            Target::Ip(ref ip) => write!(f, "{}", ip)
+        }
+    }
+}
+
+impl Target {
+    /// Returns the address in the form of a SOCKS5 request.
+    fn to_socks5_bytes(&self) -> Vec<u8> {
+        match *self {
+            Target::Domain(ref host) => {
+                let mut bytes = vec![ADDRESS_DOMAIN, host.len() as u8];
+                bytes.extend_from_slice(host.as_bytes());
+                bytes
+            }
+            Target::Ip(IpAddr::V4(ref ip)) => {
+                let mut bytes = vec![ADDRESS_IPV4];
+                bytes.extend_from_slice(&ip.octets());
+                bytes
+            }
+            Target::Ip(IpAddr::V6(ref ip)) => {
+                let mut bytes = vec![ADDRESS_IPV6];
+                bytes.extend_from_slice(&ip.octets());
+                bytes
+            }
+        }
+    }
+}
+
+fn read_u8<R: Read + ?Sized>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0x00; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u16<R: Read + ?Sized>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0x00; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+/// Reads a string ending with a zero byte, as in SOCKS4 requests.
+fn read_socks4_string<R: Read>(reader: &mut R) -> Result<String, TunnelError> {
+    let mut bytes = Vec::new();
+    loop {
+        match read_u8(reader)? {
+            0x00 => break,
+            _ if bytes.len() == MAX_SOCKS4_STRING_LENGTH => {
+                return Err(TunnelError::InvalidRequest("a SOCKS4 string is too long".to_string()));
+            }
+            byte => bytes.push(byte)
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| TunnelError::InvalidRequest("a SOCKS4 string is not UTF-8".to_string()))
+}
+
+/// Reads the address of a SOCKS5 request or reply.
+fn read_socks5_address<R: Read + ?Sized>(reader: &mut R, address_type: u8) -> Result<Option<Target>, TunnelError> {
+    let target = match address_type {
+        ADDRESS_IPV4 => {
+            let mut octets = [0x00; 4];
+            reader.read_exact(&mut octets)?;
+            Target::Ip(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        ADDRESS_DOMAIN => {
+            let mut host = vec![0x00; read_u8(reader)? as usize];
+            reader.read_exact(&mut host)?;
+            let host = String::from_utf8(host)
+                .map_err(|_| TunnelError::InvalidRequest("a SOCKS5 host name is not UTF-8".to_string()))?;
+            Target::Domain(host)
+        }
+        ADDRESS_IPV6 => {
+            let mut octets = [0x00; 16];
+            reader.read_exact(&mut octets)?;
+            Target::Ip(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => return Ok(None)
+    };
+
+    Ok(Some(target))
+}
+
+/// Returns the SOCKS5 reply code for a failure.
+fn reply_code(err: &TunnelError) -> u8 {
+    match *err {
+        TunnelError::UnknownHost(_) => REPLY_HOST_UNREACHABLE,
+        TunnelError::Unreachable(_) => REPLY_CONNECTION_REFUSED,
+        TunnelError::NoOutproxy(_) => REPLY_NOT_ALLOWED,
+        TunnelError::Unsupported(_) => REPLY_COMMAND_NOT_SUPPORTED,
+        TunnelError::InvalidRequest(_) | TunnelError::Io(_)
+        | TunnelError::InvalidKeyFile(_) | TunnelError::InvalidConfig(_) => REPLY_FAILURE
+    }
+}
+
+/// A `SocksProxy` is a client tunnel for applications that speak SOCKS 4a or 5.
+/// Connections to `.i2p` hosts become streaming connections to their
+/// destinations, and others go through a SOCKS outproxy, if one is set.
+pub struct SocksProxy<B> {
+    listener: TcpListener,
+    backend: Arc<B>,
+    outproxy: Option<String>
+}
+
+impl<B> SocksProxy<B> where B: ClientBackend + 'static {
+    pub fn bind<A: ToSocketAddrs>(address: A, backend: Arc<B>) -> io::Result<SocksProxy<B>> {
+        Ok(SocksProxy {
+            listener: TcpListener::bind(address)?,
+            backend,
+            outproxy: None
+        })
+    }
+
+    /// Sets the I2P host of a SOCKS5 server that connections outside I2P go to.
+    pub fn set_outproxy(&mut self, outproxy: Option<&str>) {
+        self.outproxy = outproxy.map(|outproxy| outproxy.to_string());
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts the next client.
+    pub fn accept(&self) -> io::Result<SocksConnection<B>> {
+        let (client, _) = self.listener.accept()?;
+
+        Ok(SocksConnection {
+            client,
+            backend: self.backend.clone(),
+            outproxy: self.outproxy.clone()
+        })
+    }
+
+    /// Serves each connection on its own thread until accepting one fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let connection = self.accept()?;
+            thread::spawn(move || connection.serve());
+        }
+    }
+}
+
+/// A `SocksConnection` serves the one connect request of a client. Only
+/// `CONNECT` is supported, and SOCKS5 only without authentication.
+pub struct SocksConnection<B> {
+    client: TcpStream,
+    backend: Arc<B>,
+    outproxy: Option<String>
+}
+
+impl<B> SocksConnection<B> where B: ClientBackend {
+    /// Reads the request, connects, and copies data both ways. On failure the
+    /// client gets the reply for the error.
+    pub fn serve(mut self) -> Result<(), TunnelError> {
+        let result = match read_u8(&mut self.client)? {
+            SOCKS4_VERSION => self.serve_socks4(),
+            SOCKS5_VERSION => self.serve_socks5(),
+            version => Err(TunnelError::Unsupported(format!("SOCKS version {}", version)))
+        };
+        match result {
+            Ok(stream) => {
+                pipe(self.client, Vec::new(), stream)?;
+                Ok(())
+            }
+            Err(err) => {
+                let _ = self.client.shutdown(Shutdown::Both);
+                Err(err)
+            }
+        }
+    }
+
+    fn serve_socks4(&mut self) -> Result<Box<dyn ByteStream>, TunnelError> {
+        let command = read_u8(&mut self.client)?;
+        let port = read_u16(&mut self.client)?;
+        let mut ip = [0x00; 4];
+        self.client.read_exact(&mut ip)?;
+        read_socks4_string(&mut self.client)?;
+        // SOCKS 4a: an address of 0.0.0.x means a host name follows the user ID.
+        let target = if ip[..3] == [0x00, 0x00, 0x00] && ip[3] != 0x00 {
+            Target::Domain(read_socks4_string(&mut self.client)?)
+        } else {
+            Target::Ip(IpAddr::V4(Ipv4Addr::from(ip)))
+        };
+
+        let result = if command == COMMAND_CONNECT {
+            self.open(&target, port)
+        } else {
+            Err(TunnelError::Unsupported(format!("the SOCKS4 command {}", command)))
+        };
+        let status = if result.is_ok() { SOCKS4_GRANTED } else { SOCKS4_REJECTED };
+        let mut reply = vec![0x00, status];
+        reply.extend_from_slice(&port.to_be_bytes());
+        reply.extend_from_slice(&ip);
+        self.client.write_all(&reply)?;
+
+        result
+    }
+
+    fn serve_socks5(&mut self) -> Result<Box<dyn ByteStream>, TunnelError> {
+        let mut methods = vec![0x00; read_u8(&mut self.client)? as usize];
+        self.client.read_exact(&mut methods)?;
+        if !methods.contains(&METHOD_NO_AUTHENTICATION) {
+            self.client.write_all(&[SOCKS5_VERSION, METHOD_NONE_ACCEPTABLE])?;
+            return Err(TunnelError::Unsupported("SOCKS5 authentication".to_string()));
+        }
+        self.client.write_all(&[SOCKS5_VERSION, METHOD_NO_AUTHENTICATION])?;
+
+        let mut header = [0x00; 4];
+        self.client.read_exact(&mut header)?;
+        if header[0] != SOCKS5_VERSION {
+            return Err(TunnelError::InvalidRequest(format!("the SOCKS5 request has version {}", header[0])));
+        }
+        let target = match read_socks5_address(&mut self.client, header[3])? {
+            Some(target) => target,
+            None => {
+                self.reply5(REPLY_ADDRESS_NOT_SUPPORTED)?;
+                return Err(TunnelError::Unsupported(format!("the SOCKS5 address type {}", header[3])));
+            }
+        };
+        let port = read_u16(&mut self.client)?;
+
+        let result = if header[1] == COMMAND_CONNECT {
+            self.open(&target, port)
+        } else {
+            Err(TunnelError::Unsupported(format!("the SOCKS5 command {}", header[1])))
+        };
+        match result {
+            Ok(_) => self.reply5(REPLY_SUCCEEDED)?,
+            Err(ref err) => self.reply5(reply_code(err))?
+        }
+
+        result
+    }
+
+    /// Sends a SOCKS5 reply. The bound address is not meaningful in I2P, so it is
+    /// all zeros.
+    fn reply5(&mut self, code: u8) -> io::Result<()> {
+        self.client.write_all(&[SOCKS5_VERSION, code, 0x00, ADDRESS_IPV4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+    }
+
+    fn open(&self, target: &Target, port: u16) -> Result<Box<dyn ByteStream>, TunnelError> {
+        match *target {
+            Target::Domain(ref host) if is_i2p_host(host) => self.connect(host, port),
+            _ => self.connect_outproxy(target, port)
+        }
+    }
+
+    fn connect(&self, host: &str, port: u16) -> Result<Box<dyn ByteStream>, TunnelError> {
+        let destination = resolve(&*self.backend, host)?;
+        self.backend.connect(&destination, port).map_err(|_| TunnelError::Unreachable(host.to_string()))
+    }
+
+    /// Connects to the outproxy and asks it, as a SOCKS5 client, to connect to the
+    /// target.
+    fn connect_outproxy(&self, target: &Target, port: u16) -> Result<Box<dyn ByteStream>, TunnelError> {
+        let outproxy = match self.outproxy {
+            Some(ref outproxy) => outproxy,
+            None => return Err(TunnelError::NoOutproxy(target.to_string()))
+        };
+        let mut stream = self.connect(outproxy, 0)?;
+
+        stream.write_all(&[SOCKS5_VERSION, 1, METHOD_NO_AUTHENTICATION])?;
+        let mut choice = [0x00; 2];
+        stream.read_exact(&mut choice)?;
+        if choice != [SOCKS5_VERSION, METHOD_NO_AUTHENTICATION] {
+            return Err(TunnelError::Unreachable(outproxy.clone()));
+        }
+
+        let mut request = vec![SOCKS5_VERSION, COMMAND_CONNECT, 0x00];
+        request.extend_from_slice(&target.to_socks5_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut header = [0x00; 4];
+        stream.read_exact(&mut header)?;
+        if header[1] != REPLY_SUCCEEDED {
+            return Err(TunnelError::Unreachable(target.to_string()));
+        }
+        read_socks5_address(&mut *stream, header[3])?
+            .ok_or_else(|| TunnelError::Unreachable(outproxy.clone()))?;
+        read_u16(&mut *stream)?;
+
+        Ok(stream)
+    }
+}
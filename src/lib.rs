@@ -3,9 +3,37 @@ extern crate chrono;
 extern crate rand;
 extern crate quickcheck;
 extern crate rustc_serialize;
+extern crate flate2;
+extern crate sha2;
+extern crate rsa;
+extern crate x509_cert;
+extern crate aes;
+extern crate cbc;
+extern crate chacha20;
+extern crate chacha20poly1305;
+extern crate hmac;
+extern crate x25519_dalek;
+extern crate ed25519_dalek;
+extern crate rustls;
+extern crate webpki_roots;
+#[cfg(test)]
+extern crate rcgen;
 
 
 pub mod common;
+pub mod netdb;
+pub mod su3;
+pub mod reseed;
+pub mod i2np;
+pub mod transport;
+pub mod tunnel;
+pub mod peer;
+pub mod garlic;
+pub mod i2cp;
+pub mod sam;
+pub mod streaming;
+pub mod datagram;
+pub mod i2ptunnel;
 mod serialize;
 
 
@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+use common::{Hash256, Hashable256, I2pDate};
+use netdb::store::{NetDbEntry, NetDbError, NetDbStore};
+
+
+/// The number of closest floodfills a newly received store is flooded to.
+pub const FLOOD_PEERS: usize = 3;
+
+/// The number of closer peers returned in a search reply.
+pub const SEARCH_REPLY_PEERS: usize = 3;
+
+/// The reply a floodfill sends to a lookup: either the entry itself, carried in a
+/// `DatabaseStore`, or the hashes of closer floodfills, carried in a `DatabaseSearchReply`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LookupReply<T> {
+    Found(T),
+    Closer(Vec<Hash256>)
+}
+
+/// The `FloodfillTransport` trait delivers netDb messages to other routers. Lookups
+/// are sent without waiting, so several can be in flight, and their replies are
+/// collected with `receive_reply`.
+pub trait FloodfillTransport<T> {
+    /// Sends a `DatabaseStore` to `peer` and reports whether it was acknowledged
+    /// before its timeout.
+    fn send_store(&mut self, peer: &Hash256, key: &Hash256, entry: &T) -> bool;
+
+    /// Sends a `DatabaseLookup` to `peer`, asking it not to return the peers in `exclude`.
+    /// Returns `false` if it could not be sent.
+    fn send_lookup(&mut self, peer: &Hash256, key: &Hash256, exclude: &[Hash256]) -> bool;
+
+    /// Waits up to `timeout` for the reply to a lookup sent earlier, and returns it
+    /// with the peer that sent it.
+    fn receive_reply(&mut self, timeout: Duration) -> Option<(Hash256, LookupReply<T>)>;
+}
+
+/// Settings for iterative lookups.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LookupConfig {
+    /// The number of lookups in flight at once, α in Kademlia. Each reply or timeout
+    /// frees a slot for the closest peer not yet queried.
+    pub parallelism: usize,
+    /// The total number of peers queried before giving up.
+    pub max_peers: usize,
+    /// The time a peer has to reply before its slot goes to another peer.
+    pub query_timeout: Duration,
+    /// The time after which the lookup gives up.
+    pub timeout: Duration
+}
+
+impl Default for LookupConfig {
+    fn default() -> LookupConfig {
+        LookupConfig {
+            parallelism: 3,
+            max_peers: 8,
+            query_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(15)
+        }
+    }
+}
+
+/// Computes the Kademlia distance between two keys.
+pub fn xor_distance(a: &Hash256, b: &Hash256) -> [u8; 32] {
+    let mut distance = [0x00; 32];
+    for (i, (x, y)) in a.as_ref().iter().zip(b.as_ref().iter()).enumerate() {
+        distance[i] = x ^ y;
+    }
+
+    distance
+}
+
+/// Returns the routing key of `key` on the UTC day of `now`: the SHA256 hash of the
+/// key followed by the date as `yyyyMMdd`. Routing keys change at midnight UTC.
+pub fn routing_key(key: &Hash256, now: I2pDate) -> Hash256 {
+    let mut data = key.as_ref().to_vec();
+    data.extend_from_slice(now.to_datetime().format("%Y%m%d").to_string().as_bytes());
+
+    data.as_slice().hash_sha256()
+}
+
+/// Compares two peers by their distance to `key`.
+fn closer_to(key: &Hash256, a: &Hash256, b: &Hash256) -> Ordering {
+    xor_distance(key, a).cmp(&xor_distance(key, b))
+}
+
+/// Returns up to `count` peers closest to the routing key `key`, nearest first,
+/// skipping any peer in `exclude`.
+pub fn closest_peers(key: &Hash256, peers: &[Hash256], count: usize, exclude: &[Hash256]) -> Vec<Hash256> {
+    let mut candidates: Vec<Hash256> = peers.iter()
+        .filter(|peer| !exclude.contains(peer))
+        .cloned()
+        .collect();
+    candidates.sort_by(|a, b| closer_to(key, a, b));
+    candidates.dedup();
+    candidates.truncate(count);
+
+    candidates
+}
+
+/// A `Floodfill` keeps the network database in a `NetDbStore` along with the known
+/// floodfill peers, and implements store flooding, lookup replies and iterative
+/// lookups. Distances are measured between the routing keys of the day.
+pub struct Floodfill<T> {
+    local: Hash256,
+    floodfills: Vec<Hash256>,
+    store: NetDbStore<T>
+}
+
+impl<T> Floodfill<T> where T: NetDbEntry + Clone {
+    pub fn new(local: Hash256, store: NetDbStore<T>) -> Floodfill<T> {
+        Floodfill {
+            local,
+            floodfills: Vec::new(),
+            store
+        }
+    }
+
+    pub fn local(&self) -> &Hash256 {
+        &self.local
+    }
+
+    /// Adds a floodfill peer. Our own hash is never added.
+    pub fn add_floodfill(&mut self, peer: Hash256) {
+        if peer != self.local && !self.floodfills.contains(&peer) {
+            self.floodfills.push(peer);
+        }
+    }
+
+    pub fn floodfills(&self) -> &[Hash256] {
+        self.floodfills.as_ref()
+    }
+
+    pub fn store(&self) -> &NetDbStore<T> {
+        &self.store
+    }
+
+    pub fn get(&self, key: &Hash256) -> Option<&T> {
+        self.store.get(key)
+    }
+
+    /// Returns up to `count` known floodfills closest to `key` on the day of `now`,
+    /// nearest first.
+    pub fn closest_floodfills(&self, key: &Hash256, now: I2pDate, count: usize, exclude: &[Hash256]) -> Vec<Hash256> {
+        closest_peers(&routing_key(key, now), &self.floodfills, count, exclude)
+    }
+
+    /// Validates and stores an entry received in a `DatabaseStore`. An entry replaces
+    /// a stored one only if it was published later.
+    pub fn handle_store(&mut self, key: &Hash256, entry: T, now: I2pDate) -> Result<bool, NetDbError> {
+        if entry.hash_sha256() != *key {
+            return Err(NetDbError::HashMismatch);
+        }
+        self.store.validate(&entry, now)?;
+
+        let newer = match self.store.get(key) {
+            Some(stored) => entry.published() > stored.published(),
+            None => true
+        };
+        if newer {
+            self.store.insert(entry, now)?;
+        }
+
+        Ok(newer)
+    }
+
+    /// Answers a `DatabaseLookup` from the local store, or with the closest known
+    /// floodfills when the entry is not stored here.
+    pub fn handle_lookup(&self, key: &Hash256, now: I2pDate, exclude: &[Hash256]) -> LookupReply<T> {
+        match self.store.get(key) {
+            Some(entry) => LookupReply::Found(entry.clone()),
+            None => LookupReply::Closer(self.closest_floodfills(key, now, SEARCH_REPLY_PEERS, exclude))
+        }
+    }
+
+    /// Sends the stored entry for `key` to the floodfills closest to it. Returns the
+    /// peers that acknowledged the store.
+    pub fn flood<R>(&self, key: &Hash256, now: I2pDate, transport: &mut R) -> Vec<Hash256>
+        where R: FloodfillTransport<T>
+    {
+        let entry = match self.store.get(key) {
+            Some(entry) => entry,
+            None => return Vec::new()
+        };
+
+        self.closest_floodfills(key, now, FLOOD_PEERS, &[])
+            .into_iter()
+            .filter(|peer| transport.send_store(peer, key, entry))
+            .collect()
+    }
+
+    /// Stores a local entry and floods it. Returns the peers that acknowledged the store.
+    pub fn publish<R>(&mut self, entry: T, now: I2pDate, transport: &mut R) -> Result<Vec<Hash256>, NetDbError>
+        where R: FloodfillTransport<T>
+    {
+        let key = entry.hash_sha256();
+        self.handle_store(&key, entry, now)?;
+
+        Ok(self.flood(&key, now, transport))
+    }
+
+    /// Looks an entry up iteratively. Up to `config.parallelism` of the closest
+    /// floodfills not yet queried are queried at once, and the closer peers in their
+    /// search replies become candidates for the following queries. A query that is
+    /// not answered within `config.query_timeout` gives its slot to the next peer,
+    /// though a late reply is still used. A found entry is validated and stored
+    /// locally.
+    pub fn lookup<R>(&mut self, key: &Hash256, now: I2pDate, config: &LookupConfig, transport: &mut R) -> Option<T>
+        where R: FloodfillTransport<T>
+    {
+        if let Some(entry) = self.store.get(key) {
+            return Some(entry.clone());
+        }
+
+        let routing_key = routing_key(key, now);
+        let deadline = Instant::now() + config.timeout;
+        let mut candidates = self.floodfills.clone();
+        let mut queried: Vec<Hash256> = Vec::new();
+        let mut pending: Vec<(Hash256, Instant)> = Vec::new();
+
+        loop {
+            let started = Instant::now();
+            if started >= deadline {
+                break;
+            }
+            pending.retain(|&(_, expires)| started < expires);
+            while pending.len() < config.parallelism && queried.len() < config.max_peers {
+                let peer = match closest_peers(&routing_key, &candidates, 1, &queried).pop() {
+                    Some(peer) => peer,
+                    None => break
+                };
+                queried.push(peer.clone());
+                if transport.send_lookup(&peer, key, &queried) {
+                    pending.push((peer, started + config.query_timeout));
+                }
+            }
+            let expires = match pending.iter().map(|&(_, expires)| expires).min() {
+                Some(expires) => expires.min(deadline),
+                None => break
+            };
+
+            let (peer, reply) = match transport.receive_reply(expires.saturating_duration_since(started)) {
+                Some((peer, reply)) if queried.contains(&peer) => (peer, reply),
+                _ => continue
+            };
+            pending.retain(|(waiting, _)| *waiting != peer);
+            match reply {
+                LookupReply::Found(entry) => {
+                    if let Ok(true) = self.handle_store(key, entry.clone(), now) {
+                        return Some(entry);
+                    }
+                }
+                LookupReply::Closer(peers) => {
+                    for closer in peers {
+                        if closer != self.local && !candidates.contains(&closer) {
+                            candidates.push(closer);
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Verifies a store by looking the entry up from a floodfill that was not sent
+    /// the store. The store is verified if that floodfill returns an entry published
+    /// no earlier than ours within `config.query_timeout`.
+    pub fn verify_store<R>(&self, key: &Hash256, now: I2pDate, stored_to: &[Hash256], config: &LookupConfig,
+                           transport: &mut R) -> bool where R: FloodfillTransport<T>
+    {
+        let published = match self.store.get(key) {
+            Some(entry) => entry.published(),
+            None => return false
+        };
+
+        let mut exclude = stored_to.to_vec();
+        exclude.push(self.local.clone());
+        for peer in self.closest_floodfills(key, now, FLOOD_PEERS, &exclude) {
+            if !transport.send_lookup(&peer, key, &exclude) {
+                continue;
+            }
+            let expires = Instant::now() + config.query_timeout;
+            while let Some((from, reply)) = transport.receive_reply(expires.saturating_duration_since(Instant::now())) {
+                match reply {
+                    LookupReply::Found(ref entry) if from == peer => return entry.published() >= published,
+                    _ if from == peer => break,
+                    _ => {}
+                }
+            }
+        }
+
+        false
+    }
+}
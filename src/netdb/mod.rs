@@ -0,0 +1,13 @@
+pub use self::store::NetDbEntry;
+pub use self::store::NetDbStore;
+pub use self::store::NetDbError;
+pub use self::floodfill::Floodfill;
+pub use self::floodfill::FloodfillTransport;
+pub use self::floodfill::LookupReply;
+pub use self::floodfill::LookupConfig;
+pub use self::floodfill::{xor_distance, closest_peers, routing_key};
+pub(crate) use self::store::write_atomically;
+
+
+mod store;
+mod floodfill;
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use common::{Ed25519Verifier, Hash256, Hashable256, I2pDate, RouterInfo};
+use common::{ToI2pBase64, FromI2pBase64};
+
+
+const ROUTER_INFO_PREFIX: &str = "routerInfo-";
+const ROUTER_INFO_SUFFIX: &str = ".dat";
+const TEMPORARY_SUFFIX: &str = ".tmp";
+const SUBDIRECTORY_PREFIX: &str = "r";
+
+/// Router infos older than this many milliseconds are considered expired. This matches
+/// the expiration used by the Java router for its on-disk network database.
+pub const DEFAULT_MAX_AGE_MILLISECONDS: u64 = 27 * 60 * 60 * 1000;
+
+/// Router infos published further in the future than this many milliseconds are rejected.
+pub const DEFAULT_MAX_CLOCK_SKEW_MILLISECONDS: u64 = 2 * 60 * 1000;
+
+
+#[derive(Debug)]
+pub enum NetDbError {
+    Io(io::Error),
+    InvalidEntry,
+    HashMismatch,
+    InvalidSignature,
+    Expired,
+    PublishedInFuture,
+}
+
+impl fmt::Display for NetDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetDbError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred in the network database: {}", err)
+            }
+            NetDbError::InvalidEntry => {
+                writeln!(f, "Error: The network database entry could not be parsed.")
+            }
+            NetDbError::HashMismatch => {
+                writeln!(f, "Error: The entry hash does not match its file name.")
+            }
+            NetDbError::InvalidSignature => {
+                writeln!(f, "Error: The network database entry has an invalid signature.")
+            }
+            NetDbError::Expired => {
+                writeln!(f, "Error: The network database entry has expired.")
+            }
+            NetDbError::PublishedInFuture => {
+                writeln!(f, "Error: The network database entry was published in the future.")
+            }
+        }
+    }
+}
+
+impl error::Error for NetDbError {
+    fn description(&self) -> &str {
+        match *self {
+            NetDbError::Io(_) => "An I/O error occurred in the network database.",
+            NetDbError::InvalidEntry => "The network database entry could not be parsed.",
+            NetDbError::HashMismatch => "The entry hash does not match its file name.",
+            NetDbError::InvalidSignature => "The network database entry has an invalid signature.",
+            NetDbError::Expired => "The network database entry has expired.",
+            NetDbError::PublishedInFuture => "The network database entry was published in the future.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            NetDbError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for NetDbError {
+    fn from(err: io::Error) -> NetDbError {
+        NetDbError::Io(err)
+    }
+}
+
+/// The `NetDbEntry` trait describes a signed, dated structure stored in the network
+/// database, such as a `RouterInfo`. The store is keyed by the SHA256 hash of each entry.
+pub trait NetDbEntry: Hashable256 + Sized {
+    /// Parses an entry from the contents of a `.dat` file.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+
+    /// Returns the serialized form of the entry, as written to disk.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Returns the date the entry was published.
+    fn published(&self) -> I2pDate;
+
+    /// Verifies the signature of the entry against its embedded signing key.
+    fn verify_signature(&self) -> bool;
+}
+
+/// Router infos are stored as written by the Java router and i2pd. Only
+/// `EdDSA_SHA512_Ed25519` signatures are accepted, the type every current router uses.
+impl NetDbEntry for RouterInfo {
+    fn from_bytes(bytes: &[u8]) -> Option<RouterInfo> {
+        match RouterInfo::from_bytes(bytes) {
+            Some((router_info, length)) if length == bytes.len() => Some(router_info),
+            _ => None
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        RouterInfo::to_bytes(self)
+    }
+
+    fn published(&self) -> I2pDate {
+        RouterInfo::published(self)
+    }
+
+    fn verify_signature(&self) -> bool {
+        self.verify(&Ed25519Verifier)
+    }
+}
+
+/// A `NetDbStore` is an on-disk network database using the directory layout shared
+/// by the Java router and i2pd. Each entry lives in `rX/routerInfo-<hash>.dat`, where
+/// `<hash>` is the I2P base64 encoding of the entry hash and `X` is its first character.
+pub struct NetDbStore<T> {
+    root: PathBuf,
+    max_age: u64,
+    max_clock_skew: u64,
+    entries: HashMap<Hash256, T>
+}
+
+impl<T> NetDbStore<T> where T: NetDbEntry {
+    /// Creates an empty store rooted at the `netDb` directory `root`. No files are read
+    /// until `load` is called.
+    pub fn new<P: AsRef<Path>>(root: P) -> NetDbStore<T> {
+        NetDbStore {
+            root: root.as_ref().to_path_buf(),
+            max_age: DEFAULT_MAX_AGE_MILLISECONDS,
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW_MILLISECONDS,
+            entries: HashMap::new()
+        }
+    }
+
+    /// Sets the age in milliseconds after which entries expire.
+    pub fn set_max_age(&mut self, milliseconds: u64) {
+        self.max_age = milliseconds;
+    }
+
+    /// Sets how far in the future, in milliseconds, an entry may have been published.
+    pub fn set_max_clock_skew(&mut self, milliseconds: u64) {
+        self.max_clock_skew = milliseconds;
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, hash: &Hash256) -> Option<&T> {
+        self.entries.get(hash)
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn hashes(&self) -> Vec<Hash256> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Returns the path of the file holding the entry with the given hash.
+    pub fn path_for(&self, hash: &Hash256) -> PathBuf {
+        let encoded = hash.as_ref().to_i2p_base64();
+        let subdirectory = format!("{}{}", SUBDIRECTORY_PREFIX, &encoded[0..1]);
+        let file_name = format!("{}{}{}", ROUTER_INFO_PREFIX, encoded, ROUTER_INFO_SUFFIX);
+
+        self.root.join(subdirectory).join(file_name)
+    }
+
+    /// Checks that an entry is correctly signed and neither expired nor published
+    /// in the future relative to `now`.
+    pub fn validate(&self, entry: &T, now: I2pDate) -> Result<(), NetDbError> {
+        validate(entry, now, self.max_age, self.max_clock_skew)
+    }
+
+    /// Reads every `routerInfo-*.dat` file under the root directory into the store.
+    /// Files that fail to parse, do not match their file name, or fail validation are
+    /// skipped and left on disk. Returns the number of entries loaded.
+    pub fn load(&mut self, now: I2pDate) -> Result<usize, NetDbError> {
+        if !self.root.is_dir() {
+            return Ok(0);
+        }
+
+        let mut loaded = 0;
+        for subdirectory in fs::read_dir(&self.root)? {
+            let subdirectory = subdirectory?.path();
+            if !is_subdirectory(&subdirectory) {
+                continue;
+            }
+
+            for file in fs::read_dir(&subdirectory)? {
+                let path = file?.path();
+                let hash = match hash_from_path(&path) {
+                    Some(hash) => hash,
+                    None => continue
+                };
+
+                if let Ok(entry) = self.read_entry(&path, &hash) {
+                    if self.validate(&entry, now).is_ok() {
+                        self.entries.insert(hash, entry);
+                        loaded += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    fn read_entry(&self, path: &Path, hash: &Hash256) -> Result<T, NetDbError> {
+        let mut bytes = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+        let entry = match T::from_bytes(&bytes) {
+            Some(entry) => entry,
+            None => return Err(NetDbError::InvalidEntry)
+        };
+        if entry.hash_sha256() != *hash {
+            return Err(NetDbError::HashMismatch);
+        }
+
+        Ok(entry)
+    }
+
+    /// Validates an entry and writes it to disk, replacing any older copy. The file
+    /// is written to a temporary name first and then renamed into place, so readers
+    /// never observe a partially written entry.
+    pub fn insert(&mut self, entry: T, now: I2pDate) -> Result<(), NetDbError> {
+        self.validate(&entry, now)?;
+
+        let hash = entry.hash_sha256();
+        let path = self.path_for(&hash);
+        write_atomically(&path, &entry.to_bytes())?;
+        self.entries.insert(hash, entry);
+
+        Ok(())
+    }
+
+    /// Removes an entry from the store and deletes its file.
+    pub fn remove(&mut self, hash: &Hash256) -> Result<Option<T>, NetDbError> {
+        let path = self.path_for(hash);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(NetDbError::Io(err))
+        }
+
+        Ok(self.entries.remove(hash))
+    }
+
+    /// Removes every expired entry from the store and from disk. Returns the number
+    /// of entries removed.
+    pub fn prune(&mut self, now: I2pDate) -> Result<usize, NetDbError> {
+        let max_age = self.max_age;
+        let expired: Vec<Hash256> = self.entries.iter()
+            .filter(|&(_, entry)| entry.published().to_u64().saturating_add(max_age) < now.to_u64())
+            .map(|(hash, _)| hash.clone())
+            .collect();
+
+        for hash in expired.iter() {
+            self.remove(hash)?;
+        }
+
+        Ok(expired.len())
+    }
+}
+
+/// Checks that an entry is correctly signed, no older than `max_age` milliseconds and
+/// published no more than `max_clock_skew` milliseconds after `now`.
+pub fn validate<T: NetDbEntry>(entry: &T, now: I2pDate, max_age: u64, max_clock_skew: u64) -> Result<(), NetDbError> {
+    let published = entry.published().to_u64();
+    let now = now.to_u64();
+
+    if published > now.saturating_add(max_clock_skew) {
+        return Err(NetDbError::PublishedInFuture);
+    }
+    if published.saturating_add(max_age) < now {
+        return Err(NetDbError::Expired);
+    }
+    if !entry.verify_signature() {
+        return Err(NetDbError::InvalidSignature);
+    }
+
+    Ok(())
+}
+
+fn is_subdirectory(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false
+    };
+
+    path.is_dir() && name.starts_with(SUBDIRECTORY_PREFIX) && name.len() == 2
+}
+
+fn hash_from_path(path: &Path) -> Option<Hash256> {
+    let name = path.file_name().and_then(|name| name.to_str())?;
+    if !name.starts_with(ROUTER_INFO_PREFIX) || !name.ends_with(ROUTER_INFO_SUFFIX) {
+        return None;
+    }
+
+    let encoded = &name[ROUTER_INFO_PREFIX.len()..(name.len() - ROUTER_INFO_SUFFIX.len())];
+    let bytes = encoded.from_i2p_base64()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+
+    let mut data = [0x00; 32];
+    data.copy_from_slice(&bytes);
+
+    Some(Hash256::from(data))
+}
+
+/// Writes a file under a temporary name and then renames it into place, so that a
+/// crash never leaves a partly written file behind. Creates missing directories.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(TEMPORARY_SUFFIX);
+    let temporary = PathBuf::from(temporary);
+
+    {
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&temporary, path)
+}
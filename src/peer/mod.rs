@@ -0,0 +1,6 @@
+pub use self::profile::{PeerProfile, ProfileStore};
+pub use self::selector::{PeerCandidate, PeerSelector, PeerTier, MAX_CONSECUTIVE_FAILURES};
+
+
+mod profile;
+mod selector;
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use common::{Hash256, I2pDate, I2pInt64};
+use common::{ToI2pBase64, FromI2pBase64};
+use netdb::write_atomically;
+
+
+const PROFILE_PREFIX: &str = "profile-";
+const PROFILE_SUFFIX: &str = ".txt";
+
+/// The weight of a new latency sample in the moving average, in percent.
+const LATENCY_WEIGHT: u64 = 25;
+
+/// A `PeerProfile` is what we have learned about a peer from the tunnels we asked it
+/// to join and the messages it answered.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerProfile {
+    /// The number of tunnel builds the peer agreed to.
+    pub build_successes: u64,
+    /// The number of tunnel builds the peer rejected or never answered.
+    pub build_failures: u64,
+    /// The number of consecutive build failures since the last success.
+    pub consecutive_failures: u64,
+    /// The moving average of the peer's round trip time in milliseconds.
+    pub latency: Option<u64>,
+    /// The last time, in milliseconds since the epoch, we heard from the peer.
+    pub last_seen: u64
+}
+
+impl PeerProfile {
+    /// Returns the fraction of builds the peer agreed to. A peer we know nothing
+    /// about starts at one half.
+    pub fn capacity(&self) -> f64 {
+        (self.build_successes as f64 + 1.0) / ((self.build_successes + self.build_failures) as f64 + 2.0)
+    }
+
+    fn record_build(&mut self, success: bool, now: I2pDate) {
+        if success {
+            self.build_successes += 1;
+            self.consecutive_failures = 0;
+            self.last_seen = now.to_u64();
+        } else {
+            self.build_failures += 1;
+            self.consecutive_failures += 1;
+        }
+    }
+
+    fn record_latency(&mut self, milliseconds: u64, now: I2pDate) {
+        self.latency = Some(match self.latency {
+            Some(latency) => (latency * (100 - LATENCY_WEIGHT) + milliseconds * LATENCY_WEIGHT) / 100,
+            None => milliseconds
+        });
+        self.last_seen = now.to_u64();
+    }
+
+    /// Returns the last time we heard from the peer, if we ever did.
+    pub fn last_seen(&self) -> Option<I2pDate> {
+        I2pDate::new(I2pInt64::new(self.last_seen)).ok()
+    }
+
+    /// Serializes the profile as `key=value` lines.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("buildSuccesses={}\nbuildFailures={}\nconsecutiveFailures={}\nlastSeen={}\n",
+                               self.build_successes, self.build_failures, self.consecutive_failures, self.last_seen);
+        if let Some(latency) = self.latency {
+            text.push_str(&format!("latency={}\n", latency));
+        }
+
+        text
+    }
+
+    /// Parses a profile written by `to_text`. Unknown keys are ignored; a malformed
+    /// value makes the whole profile invalid.
+    pub fn from_text(text: &str) -> Option<PeerProfile> {
+        let mut profile = PeerProfile::default();
+        for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = (parts.next()?, parts.next()?.parse::<u64>().ok()?);
+            match key {
+                "buildSuccesses" => profile.build_successes = value,
+                "buildFailures" => profile.build_failures = value,
+                "consecutiveFailures" => profile.consecutive_failures = value,
+                "latency" => profile.latency = Some(value),
+                "lastSeen" => profile.last_seen = value,
+                _ => {}
+            }
+        }
+
+        Some(profile)
+    }
+}
+
+/// A `ProfileStore` keeps a profile for every peer we have dealt with, and persists
+/// them as one text file per peer so they survive restarts.
+pub struct ProfileStore {
+    root: PathBuf,
+    profiles: HashMap<Hash256, PeerProfile>
+}
+
+impl ProfileStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> ProfileStore {
+        ProfileStore {
+            root: root.as_ref().to_path_buf(),
+            profiles: HashMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    pub fn get(&self, peer: &Hash256) -> Option<&PeerProfile> {
+        self.profiles.get(peer)
+    }
+
+    pub fn peers(&self) -> Vec<Hash256> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Records whether a peer agreed to join a tunnel.
+    pub fn record_build(&mut self, peer: &Hash256, success: bool, now: I2pDate) {
+        self.profiles.entry(peer.clone()).or_default().record_build(success, now);
+    }
+
+    /// Records a round trip time measured to the peer, such as a tunnel build reply
+    /// or a netDb lookup.
+    pub fn record_latency(&mut self, peer: &Hash256, milliseconds: u64, now: I2pDate) {
+        self.profiles.entry(peer.clone()).or_default().record_latency(milliseconds, now);
+    }
+
+    /// Returns the path of the file holding the profile of a peer.
+    pub fn path_for(&self, peer: &Hash256) -> PathBuf {
+        self.root.join(format!("{}{}{}", PROFILE_PREFIX, peer.as_ref().to_i2p_base64(), PROFILE_SUFFIX))
+    }
+
+    /// Reads every profile file under the root directory. Files that fail to parse
+    /// are skipped. Returns the number of profiles loaded.
+    pub fn load(&mut self) -> io::Result<usize> {
+        if !self.root.is_dir() {
+            return Ok(0);
+        }
+
+        let mut loaded = 0;
+        for file in fs::read_dir(&self.root)? {
+            let path = file?.path();
+            let peer = match peer_from_path(&path) {
+                Some(peer) => peer,
+                None => continue
+            };
+
+            let mut text = String::new();
+            if fs::File::open(&path)?.read_to_string(&mut text).is_err() {
+                continue;
+            }
+            if let Some(profile) = PeerProfile::from_text(&text) {
+                self.profiles.insert(peer, profile);
+                loaded += 1;
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Writes every profile to disk. Each file is written under a temporary name and
+    /// then renamed into place.
+    pub fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        for (peer, profile) in self.profiles.iter() {
+            write_atomically(&self.path_for(peer), profile.to_text().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the peers we have not heard from since `cutoff` and deletes their
+    /// files. Returns the number of profiles removed.
+    pub fn prune(&mut self, cutoff: I2pDate) -> io::Result<usize> {
+        let stale: Vec<Hash256> = self.profiles.iter()
+            .filter(|&(_, profile)| profile.last_seen < cutoff.to_u64())
+            .map(|(peer, _)| peer.clone())
+            .collect();
+
+        for peer in stale.iter() {
+            self.profiles.remove(peer);
+            match fs::remove_file(self.path_for(peer)) {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err)
+            }
+        }
+
+        Ok(stale.len())
+    }
+}
+
+fn peer_from_path(path: &Path) -> Option<Hash256> {
+    let name = path.file_name().and_then(|name| name.to_str())?;
+    if !name.starts_with(PROFILE_PREFIX) || !name.ends_with(PROFILE_SUFFIX) {
+        return None;
+    }
+
+    let bytes = name[PROFILE_PREFIX.len()..(name.len() - PROFILE_SUFFIX.len())].from_i2p_base64()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+
+    let mut data = [0x00; 32];
+    data.copy_from_slice(&bytes);
+
+    Some(Hash256::from(data))
+}
@@ -0,0 +1,138 @@
+use std::cmp::Ordering;
+use std::net::Ipv4Addr;
+use rand;
+use rand::Rng;
+use common::Hash256;
+use peer::profile::{PeerProfile, ProfileStore};
+
+
+/// Peers that failed this many builds in a row are not selected.
+pub const MAX_CONSECUTIVE_FAILURES: u64 = 5;
+
+/// The tier a tunnel wants its hops from. Exploratory tunnels may use any peer that
+/// is not failing; client tunnels want high capacity or fast peers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PeerTier {
+    /// The high capacity peers with the lowest latency.
+    Fast,
+    /// The peers that agree to at least the median fraction of builds.
+    HighCapacity,
+    /// Every peer that is not failing.
+    Any
+}
+
+/// A `PeerCandidate` is a router that could be a hop, with what its RouterInfo says
+/// about where it is and who runs it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCandidate {
+    pub hash: Hash256,
+    pub ipv4: Option<Ipv4Addr>,
+    /// The declared family of the router, if it publishes one.
+    pub family: Option<String>
+}
+
+impl PeerCandidate {
+    pub fn new(hash: Hash256, ipv4: Option<Ipv4Addr>, family: Option<String>) -> PeerCandidate {
+        PeerCandidate {
+            hash,
+            ipv4,
+            family
+        }
+    }
+
+    /// Determines whether two peers share a /16 IPv4 network or a family, in which
+    /// case they may be run by the same operator and should not be in the same tunnel.
+    pub fn is_related(&self, other: &PeerCandidate) -> bool {
+        let same_network = match (self.ipv4, other.ipv4) {
+            (Some(a), Some(b)) => a.octets()[0..2] == b.octets()[0..2],
+            _ => false
+        };
+        let same_family = match (self.family.as_ref(), other.family.as_ref()) {
+            (Some(a), Some(b)) => a == b,
+            _ => false
+        };
+
+        same_network || same_family
+    }
+}
+
+/// A `PeerSelector` sorts peers into tiers from their profiles and picks diverse
+/// hops for tunnel builds.
+pub struct PeerSelector<'a> {
+    profiles: &'a ProfileStore
+}
+
+impl<'a> PeerSelector<'a> {
+    pub fn new(profiles: &'a ProfileStore) -> PeerSelector<'a> {
+        PeerSelector {
+            profiles
+        }
+    }
+
+    fn profile(&self, peer: &Hash256) -> PeerProfile {
+        self.profiles.get(peer).cloned().unwrap_or_default()
+    }
+
+    /// Returns the candidates in a tier, best first.
+    pub fn tier<'c>(&self, candidates: &'c [PeerCandidate], tier: PeerTier) -> Vec<&'c PeerCandidate> {
+        let mut peers: Vec<&PeerCandidate> = candidates.iter()
+            .filter(|peer| self.profile(&peer.hash).consecutive_failures < MAX_CONSECUTIVE_FAILURES)
+            .collect();
+        if tier == PeerTier::Any || peers.is_empty() {
+            return peers;
+        }
+
+        peers.sort_by(|a, b| self.compare_capacity(b, a));
+        let median = self.profile(&peers[(peers.len() - 1) / 2].hash).capacity();
+        peers.retain(|peer| self.profile(&peer.hash).capacity() >= median);
+        if tier == PeerTier::HighCapacity {
+            return peers;
+        }
+
+        peers.retain(|peer| self.profile(&peer.hash).latency.is_some());
+        peers.sort_by_key(|peer| self.profile(&peer.hash).latency);
+        let fast = peers.len().div_ceil(2);
+        peers.truncate(fast);
+
+        peers
+    }
+
+    fn compare_capacity(&self, a: &PeerCandidate, b: &PeerCandidate) -> Ordering {
+        self.profile(&a.hash).capacity().partial_cmp(&self.profile(&b.hash).capacity()).unwrap_or(Ordering::Equal)
+    }
+
+    /// Picks `length` hops at random, preferring peers of the requested tier and
+    /// falling back to the wider tiers when it has too few. No two hops share a /16
+    /// network or a family, and peers in `exclude` are never picked. Returns `None`
+    /// if there are not enough unrelated peers.
+    pub fn select(&self, candidates: &[PeerCandidate], length: usize, tier: PeerTier, exclude: &[Hash256]) -> Option<Vec<Hash256>> {
+        let tiers: &[PeerTier] = match tier {
+            PeerTier::Fast => &[PeerTier::Fast, PeerTier::HighCapacity, PeerTier::Any],
+            PeerTier::HighCapacity => &[PeerTier::HighCapacity, PeerTier::Any],
+            PeerTier::Any => &[PeerTier::Any]
+        };
+
+        let mut rng = rand::thread_rng();
+        let mut hops: Vec<&PeerCandidate> = Vec::with_capacity(length);
+        for tier in tiers {
+            let mut peers = self.tier(candidates, *tier);
+            rng.shuffle(&mut peers);
+
+            for peer in peers {
+                if hops.len() == length {
+                    break;
+                }
+                if exclude.contains(&peer.hash) || hops.iter().any(|hop| hop.hash == peer.hash || hop.is_related(peer)) {
+                    continue;
+                }
+                hops.push(peer);
+            }
+        }
+
+        if hops.len() < length {
+            return None;
+        }
+
+        Some(hops.into_iter().map(|hop| hop.hash.clone()).collect())
+    }
+}
@@ -0,0 +1,102 @@
+use std::error;
+use std::fmt;
+use std::io;
+use netdb::NetDbError;
+use su3::Su3Error;
+
+
+#[derive(Debug)]
+pub enum ReseedError {
+    Io(io::Error),
+    UnsupportedSource(String),
+    /// The reseed server answered with a status other than 200.
+    HttpStatus(u16),
+    InvalidResponse,
+    /// The TLS connection to the reseed server could not be set up.
+    Tls(rustls::Error),
+    Su3(Su3Error),
+    NetDb(NetDbError),
+    /// Too few sources returned a valid bundle. The first field is the number of valid
+    /// bundles, the second field is the number required.
+    NotEnoughSources(usize, usize),
+}
+
+impl fmt::Display for ReseedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReseedError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred while reseeding: {}", err)
+            }
+            ReseedError::UnsupportedSource(ref source) => {
+                writeln!(f, "Error: Unsupported reseed source: {}", source)
+            }
+            ReseedError::HttpStatus(status) => {
+                writeln!(f, "Error: The reseed server returned HTTP status {}.", status)
+            }
+            ReseedError::InvalidResponse => {
+                writeln!(f, "Error: The reseed server returned an invalid HTTP response.")
+            }
+            ReseedError::Tls(ref err) => {
+                writeln!(f, "Error: A TLS error occurred while reseeding: {}", err)
+            }
+            ReseedError::Su3(ref err) => {
+                writeln!(f, "Error: Invalid reseed bundle: {}", err)
+            }
+            ReseedError::NetDb(ref err) => {
+                writeln!(f, "Error: Could not store reseed data: {}", err)
+            }
+            ReseedError::NotEnoughSources(have, need) => {
+                writeln!(f, "Error: Only {} reseed sources returned a valid bundle; need {}.", have, need)
+            }
+        }
+    }
+}
+
+impl error::Error for ReseedError {
+    fn description(&self) -> &str {
+        match *self {
+            ReseedError::Io(_) => "An I/O error occurred while reseeding.",
+            ReseedError::UnsupportedSource(_) => "Unsupported reseed source.",
+            ReseedError::HttpStatus(_) => "The reseed server returned an error status.",
+            ReseedError::InvalidResponse => "The reseed server returned an invalid HTTP response.",
+            ReseedError::Tls(_) => "A TLS error occurred while reseeding.",
+            ReseedError::Su3(_) => "Invalid reseed bundle.",
+            ReseedError::NetDb(_) => "Could not store reseed data.",
+            ReseedError::NotEnoughSources(_, _) => "Too few reseed sources returned a valid bundle.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ReseedError::Io(ref err) => Some(err),
+            ReseedError::Tls(ref err) => Some(err),
+            ReseedError::Su3(ref err) => Some(err),
+            ReseedError::NetDb(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ReseedError {
+    fn from(err: io::Error) -> ReseedError {
+        ReseedError::Io(err)
+    }
+}
+
+impl From<rustls::Error> for ReseedError {
+    fn from(err: rustls::Error) -> ReseedError {
+        ReseedError::Tls(err)
+    }
+}
+
+impl From<Su3Error> for ReseedError {
+    fn from(err: Su3Error) -> ReseedError {
+        ReseedError::Su3(err)
+    }
+}
+
+impl From<NetDbError> for ReseedError {
+    fn from(err: NetDbError) -> ReseedError {
+        ReseedError::NetDb(err)
+    }
+}
@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use rustls::pki_types::ServerName;
+use reseed::error::ReseedError;
+
+
+/// The name of the reseed bundle on reseed servers and in reseed directories.
+pub const RESEED_FILE_NAME: &str = "i2pseeds.su3";
+
+/// Reseed servers only answer clients that look like the Java router.
+const USER_AGENT: &str = "Wget/1.11.4";
+
+/// Refuse bundles larger than this many bytes.
+const MAX_BUNDLE_LENGTH: u64 = 1024 * 1024;
+
+/// The `ReseedFetcher` trait retrieves the raw bytes of a reseed bundle from a source.
+pub trait ReseedFetcher {
+    fn fetch(&self, source: &str) -> Result<Vec<u8>, ReseedError>;
+}
+
+/// A `FileFetcher` reads bundles from the local file system. A source is either the
+/// path of an SU3 file or a directory holding `i2pseeds.su3`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FileFetcher;
+
+impl ReseedFetcher for FileFetcher {
+    fn fetch(&self, source: &str) -> Result<Vec<u8>, ReseedError> {
+        let path = Path::new(source);
+        let path = if path.is_dir() { path.join(RESEED_FILE_NAME) } else { path.to_path_buf() };
+
+        let mut bytes = Vec::new();
+        fs::File::open(path)?.take(MAX_BUNDLE_LENGTH).read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+/// An `HttpFetcher` downloads bundles over HTTPS or plain HTTP. A source is either the
+/// URL of an SU3 file or the URL of a reseed server, to which `i2pseeds.su3` is
+/// appended. Server certificates are checked against the Mozilla root certificates
+/// unless other roots are given with `with_root_certificates`.
+#[derive(Clone, Debug)]
+pub struct HttpFetcher {
+    timeout: Duration,
+    tls: Arc<ClientConfig>
+}
+
+impl HttpFetcher {
+    pub fn new(timeout: Duration) -> HttpFetcher {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+        HttpFetcher::with_root_certificates(timeout, roots)
+    }
+
+    /// Creates a fetcher that trusts only `roots`, such as the self-signed certificates
+    /// some reseed servers use.
+    pub fn with_root_certificates(timeout: Duration, roots: RootCertStore) -> HttpFetcher {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let tls = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("The ring provider supports the default protocol versions.")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        HttpFetcher {
+            timeout,
+            tls: Arc::new(tls)
+        }
+    }
+
+    /// Splits an `http://` or `https://` URL into whether it uses TLS, its host, port
+    /// and path.
+    fn parse_url(source: &str) -> Result<(bool, String, u16, String), ReseedError> {
+        let unsupported = || ReseedError::UnsupportedSource(source.to_string());
+        let (tls, rest) = if let Some(rest) = source.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = source.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(unsupported());
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/")
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(index) => match authority[index + 1..].parse() {
+                Ok(port) => (&authority[..index], port),
+                Err(_) => return Err(unsupported())
+            },
+            None => (authority, if tls { 443 } else { 80 })
+        };
+        if host.is_empty() {
+            return Err(unsupported());
+        }
+
+        let path = if path.ends_with(".su3") {
+            path.to_string()
+        } else if path.ends_with('/') {
+            format!("{}{}", path, RESEED_FILE_NAME)
+        } else {
+            format!("{}/{}", path, RESEED_FILE_NAME)
+        };
+
+        Ok((tls, host.to_string(), port, path))
+    }
+
+    /// Sends a GET request for `path` and returns the body of a 200 response.
+    fn get<S: Read + Write>(&self, mut stream: S, host: &str, path: &str) -> Result<Vec<u8>, ReseedError> {
+        // HTTP/1.0 keeps the response unchunked and closes the connection after it.
+        write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: {}\r\nConnection: close\r\n\r\n",
+               path, host, USER_AGENT)?;
+        stream.flush()?;
+
+        // Many servers close TLS connections without a close_notify. A bundle cut short
+        // that way fails its signature check, so the missing alert is not an error here.
+        let mut response = Vec::new();
+        match stream.take(MAX_BUNDLE_LENGTH + 64 * 1024).read_to_end(&mut response) {
+            Ok(_) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(err) => return Err(err.into())
+        }
+
+        let header_end = match response.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(index) => index,
+            None => return Err(ReseedError::InvalidResponse)
+        };
+        let header = match str::from_utf8(&response[..header_end]) {
+            Ok(header) => header,
+            Err(_) => return Err(ReseedError::InvalidResponse)
+        };
+        let status = header.lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok());
+
+        match status {
+            Some(200) => Ok(response[header_end + 4..].to_vec()),
+            Some(status) => Err(ReseedError::HttpStatus(status)),
+            None => Err(ReseedError::InvalidResponse)
+        }
+    }
+
+    /// Connects to the first address of `host` that answers within the timeout.
+    fn connect(&self, host: &str, port: u16) -> Result<TcpStream, ReseedError> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "The host has no addresses.");
+        for address in (host, port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(error) => last_error = error
+            }
+        }
+
+        Err(last_error.into())
+    }
+}
+
+impl Default for HttpFetcher {
+    fn default() -> HttpFetcher {
+        HttpFetcher::new(Duration::from_secs(30))
+    }
+}
+
+impl ReseedFetcher for HttpFetcher {
+    fn fetch(&self, source: &str) -> Result<Vec<u8>, ReseedError> {
+        let (tls, host, port, path) = HttpFetcher::parse_url(source)?;
+        let stream = self.connect(&host, port)?;
+
+        if !tls {
+            return self.get(stream, &host, &path);
+        }
+
+        let server_name = match ServerName::try_from(host.clone()) {
+            Ok(server_name) => server_name,
+            Err(_) => return Err(ReseedError::UnsupportedSource(source.to_string()))
+        };
+        let connection = ClientConnection::new(self.tls.clone(), server_name)?;
+
+        self.get(StreamOwned::new(connection, stream), &host, &path)
+    }
+}
@@ -0,0 +1,8 @@
+pub use self::error::ReseedError;
+pub use self::fetcher::{ReseedFetcher, FileFetcher, HttpFetcher};
+pub use self::reseeder::Reseeder;
+
+
+mod error;
+mod fetcher;
+mod reseeder;
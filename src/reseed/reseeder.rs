@@ -0,0 +1,124 @@
+use rand;
+use rand::Rng;
+use std::collections::HashMap;
+use common::{Hash256, I2pDate, SignatureVerifier};
+use netdb::{NetDbEntry, NetDbStore};
+use reseed::error::ReseedError;
+use reseed::fetcher::ReseedFetcher;
+use su3::{Su3File, CertificateStore};
+
+
+/// The number of sources that must return a valid bundle by default.
+pub const DEFAULT_MIN_SOURCES: usize = 2;
+
+/// The number of bundles a router info must appear in by default.
+pub const DEFAULT_MIN_AGREEMENT: usize = 2;
+
+/// A `Reseeder` bootstraps the network database from reseed bundles. It tries the
+/// configured sources in random order until `min_sources` of them have returned a
+/// correctly signed reseed bundle, and only then stores the router infos that appear
+/// in at least `min_agreement` of those bundles. As long as `min_agreement` is more
+/// than one, a single compromised source cannot seed the network database on its own.
+pub struct Reseeder<F, V> {
+    sources: Vec<String>,
+    min_sources: usize,
+    min_agreement: usize,
+    fetcher: F,
+    certificates: CertificateStore,
+    verifier: V
+}
+
+impl<F, V> Reseeder<F, V> where F: ReseedFetcher, V: SignatureVerifier {
+    pub fn new(sources: Vec<String>, fetcher: F, certificates: CertificateStore, verifier: V) -> Reseeder<F, V> {
+        Reseeder {
+            sources,
+            min_sources: DEFAULT_MIN_SOURCES,
+            min_agreement: DEFAULT_MIN_AGREEMENT,
+            fetcher,
+            certificates,
+            verifier
+        }
+    }
+
+    /// Sets the number of sources that must return a valid bundle.
+    pub fn set_min_sources(&mut self, min_sources: usize) {
+        self.min_sources = min_sources;
+    }
+
+    /// Sets the number of bundles a router info must appear in to be kept. It is
+    /// capped at `min_sources`, since no more bundles than that are fetched.
+    pub fn set_min_agreement(&mut self, min_agreement: usize) {
+        self.min_agreement = min_agreement;
+    }
+
+    pub fn sources(&self) -> &[String] {
+        self.sources.as_ref()
+    }
+
+    /// Fetches, verifies and unpacks the bundle from a single source.
+    pub fn fetch_bundle<T: NetDbEntry>(&self, source: &str) -> Result<Vec<T>, ReseedError> {
+        let bytes = self.fetcher.fetch(source)?;
+        let su3 = Su3File::from_bytes(bytes)?;
+        su3.verify(&self.certificates, &self.verifier)?;
+
+        Ok(su3.router_infos()?)
+    }
+
+    /// Fetches bundles until `min_sources` sources have returned a valid one, and
+    /// returns the router infos that appear in at least `min_agreement` of those
+    /// bundles, without duplicates.
+    pub fn fetch<T: NetDbEntry>(&self) -> Result<Vec<T>, ReseedError> {
+        let mut sources = self.sources.clone();
+        rand::thread_rng().shuffle(&mut sources);
+
+        let mut valid_sources = 0;
+        let mut router_infos: Vec<T> = Vec::new();
+        let mut votes: HashMap<Hash256, usize> = HashMap::new();
+        for source in sources.iter() {
+            if valid_sources >= self.min_sources {
+                break;
+            }
+
+            if let Ok(bundle) = self.fetch_bundle::<T>(source) {
+                valid_sources += 1;
+                let mut seen: Vec<Hash256> = Vec::new();
+                for router_info in bundle {
+                    let hash = router_info.hash_sha256();
+                    if seen.contains(&hash) {
+                        continue;
+                    }
+                    seen.push(hash.clone());
+
+                    let count = votes.entry(hash).or_insert(0);
+                    *count += 1;
+                    if *count == 1 {
+                        router_infos.push(router_info);
+                    }
+                }
+            }
+        }
+
+        if valid_sources < self.min_sources {
+            return Err(ReseedError::NotEnoughSources(valid_sources, self.min_sources));
+        }
+
+        let min_agreement = self.min_agreement.min(self.min_sources);
+        router_infos.retain(|router_info| votes[&router_info.hash_sha256()] >= min_agreement);
+
+        Ok(router_infos)
+    }
+
+    /// Reseeds the network database. Router infos that fail validation are skipped.
+    /// Returns the number of router infos stored.
+    pub fn reseed<T: NetDbEntry>(&self, store: &mut NetDbStore<T>, now: I2pDate) -> Result<usize, ReseedError> {
+        let mut stored = 0;
+        for router_info in self.fetch::<T>()? {
+            if store.validate(&router_info, now).is_ok() {
+                store.insert(router_info, now)?;
+                stored += 1;
+            }
+        }
+
+        Ok(stored)
+    }
+}
@@ -0,0 +1,751 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use common::{Destination, FromI2pBase64, SignatureType};
+use sam::command::{SamCommand, SamResult, SessionStyle, parse_signature_type};
+use sam::error::SamError;
+use streaming::{ByteStream, pipe};
+
+
+/// The port SAM bridges listen for clients on by default.
+pub const DEFAULT_SAM_PORT: u16 = 7656;
+
+/// The port SAM bridges receive datagrams to send on by default.
+pub const DEFAULT_SAM_UDP_PORT: u16 = 7655;
+
+/// The highest SAM version the bridge speaks.
+pub const SAM_VERSION: &str = "3.3";
+
+/// The largest datagram payload accepted for sending.
+pub const MAX_DATAGRAM_LENGTH: usize = 32768;
+
+/// SAM 3.2 added the ports to the line announcing an accepted stream.
+const PORTS_VERSION: (u8, u8) = (3, 2);
+
+/// The keys of a session: its destination, and the private keys that follow the
+/// destination in the SAM text form, which is what clients store to reuse it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SamKeys {
+    pub destination: Destination,
+    pub private_keys: String
+}
+
+impl SamKeys {
+    /// Parses the private keys of the SAM text form, which start with the
+    /// destination.
+    pub fn from_private_keys(private_keys: &str) -> Option<SamKeys> {
+        let bytes = private_keys.from_i2p_base64()?;
+        match Destination::from_bytes(&bytes) {
+            Some((destination, length)) if length < bytes.len() => {
+                Some(SamKeys { destination, private_keys: private_keys.to_string() })
+            }
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone)]
+enum SinkTarget {
+    Udp(Arc<UdpSocket>, SocketAddr),
+    Control(Arc<Mutex<TcpStream>>)
+}
+
+/// A `DatagramSink` delivers the datagrams a session receives to the client, to
+/// the UDP port it gave in `PORT` or otherwise on its control socket. Stream
+/// sessions have a sink that discards everything.
+#[derive(Clone)]
+pub struct DatagramSink {
+    target: Option<SinkTarget>
+}
+
+impl DatagramSink {
+    /// Delivers a datagram. Repliable datagrams carry the destination of their
+    /// sender; raw datagrams have none.
+    pub fn deliver(&self, from: Option<&Destination>, payload: &[u8]) -> io::Result<()> {
+        match self.target {
+            None => Ok(()),
+            Some(SinkTarget::Udp(ref socket, address)) => {
+                let mut datagram = match from {
+                    Some(from) => format!("{}\n", from.to_i2p_base64()).into_bytes(),
+                    None => Vec::new()
+                };
+                datagram.extend_from_slice(payload);
+                socket.send_to(&datagram, address).map(|_| ())
+            }
+            Some(SinkTarget::Control(ref stream)) => {
+                let header = match from {
+                    Some(from) => SamCommand::new("DATAGRAM", Some("RECEIVED")).arg("DESTINATION", &from.to_i2p_base64()),
+                    None => SamCommand::new("RAW", Some("RECEIVED"))
+                };
+                let header = header.arg("SIZE", &payload.len().to_string());
+
+                let mut stream = stream.lock().unwrap();
+                writeln!(stream, "{}", header)?;
+                stream.write_all(payload)
+            }
+        }
+    }
+}
+
+/// A stream accepted by a session, with the destination of the peer and the
+/// ports of the streaming layer.
+pub struct AcceptedStream {
+    pub peer: Destination,
+    pub from_port: u16,
+    pub to_port: u16,
+    pub stream: Box<dyn ByteStream>
+}
+
+/// The `SamBackend` trait is the router behind a SAM bridge: it runs the I2CP
+/// sessions, the streaming connections and the datagrams of each SAM session. It
+/// is shared by all connections, and `accept` blocks, so it takes `&self` and does
+/// its own locking.
+pub trait SamBackend: Send + Sync {
+    /// Creates new keys.
+    fn generate(&self, signature_type: SignatureType) -> Option<SamKeys>;
+
+    /// Starts a session with the private keys from `DESTINATION`, or with new keys
+    /// of `signature_type` for a `TRANSIENT` one. `options` holds the remaining
+    /// arguments, such as the I2CP tunnel options. Returns the session's keys.
+    fn create_session(&self, id: &str, style: SessionStyle, private_keys: Option<&str>, signature_type: SignatureType,
+                      options: &HashMap<String, String>, sink: DatagramSink) -> Result<SamKeys, SamResult>;
+
+    /// Adds a subsession sharing the destination of a `PRIMARY` session.
+    fn add_subsession(&self, primary: &str, id: &str, style: SessionStyle, options: &HashMap<String, String>,
+                      sink: DatagramSink) -> Result<(), SamResult>;
+
+    /// Stops a session or subsession.
+    fn remove_session(&self, id: &str);
+
+    /// Opens a stream from a session to a destination.
+    fn connect(&self, id: &str, destination: &Destination, options: &HashMap<String, String>)
+               -> Result<Box<dyn ByteStream>, SamResult>;
+
+    /// Waits for the next stream to a session.
+    fn accept(&self, id: &str) -> Result<AcceptedStream, SamResult>;
+
+    /// Sends a datagram from a session, repliable or raw according to its style.
+    fn send_datagram(&self, id: &str, destination: &Destination, payload: &[u8], options: &HashMap<String, String>)
+                     -> Result<(), SamResult>;
+
+    /// Resolves a host name or `.b32.i2p` address.
+    fn lookup(&self, name: &str) -> Option<Destination>;
+}
+
+struct SessionEntry {
+    style: SessionStyle,
+    /// `None` while the backend starts the session. The entry reserves the ID, so
+    /// that two clients cannot create sessions with the same one.
+    destination: Option<Destination>,
+    /// The `PRIMARY` session of a subsession.
+    primary: Option<String>
+}
+
+type Sessions = Arc<Mutex<HashMap<String, SessionEntry>>>;
+
+/// A failed command: the result to report and a message for the client.
+type Failure = (SamResult, &'static str);
+
+fn status(verb: &str, result: SamResult) -> SamCommand {
+    SamCommand::new(verb, Some("STATUS")).arg("RESULT", result.as_str())
+}
+
+fn failure_status(verb: &str, failure: Failure) -> SamCommand {
+    status(verb, failure.0).arg("MESSAGE", failure.1)
+}
+
+/// Parses a version such as `3.1`. A missing minor version is zero.
+fn parse_version(version: &str) -> Option<(u8, u8)> {
+    let mut parts = version.splitn(2, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0
+    };
+
+    Some((major, minor))
+}
+
+/// Returns the arguments of a command other than the ones the bridge handles.
+fn options(command: &SamCommand, known: &[&str]) -> HashMap<String, String> {
+    command.args.iter()
+        .filter(|arg| !known.contains(&arg.0.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Parses a destination in base64, or resolves it as a host name.
+fn resolve<B: SamBackend>(backend: &B, name: &str) -> Option<Destination> {
+    Destination::from_i2p_base64(name).or_else(|| backend.lookup(name))
+}
+
+/// Returns the line announcing an accepted stream.
+fn peer_line(accepted: &AcceptedStream, ports: bool) -> String {
+    if ports {
+        format!("{} FROM_PORT={} TO_PORT={}", accepted.peer.to_i2p_base64(), accepted.from_port, accepted.to_port)
+    } else {
+        accepted.peer.to_i2p_base64()
+    }
+}
+
+/// Hands the streams accepted by a session to a local TCP server, each on its own
+/// connection, until the `FORWARD` is stopped.
+fn forward_streams<B: SamBackend>(backend: &B, id: &str, address: SocketAddr, silent: bool, ports: bool, stopped: &AtomicBool) {
+    while !stopped.load(Ordering::SeqCst) {
+        let accepted = match backend.accept(id) {
+            Ok(accepted) => accepted,
+            Err(_) => return
+        };
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let mut local = match TcpStream::connect(address) {
+            Ok(local) => local,
+            Err(_) => continue
+        };
+        if !silent && writeln!(local, "{}", peer_line(&accepted, ports)).is_err() {
+            continue;
+        }
+        thread::spawn(move || pipe(local, Vec::new(), accepted.stream));
+    }
+}
+
+/// Sends a datagram received on the UDP port. Each datagram starts with a line
+/// `3.x ID DESTINATION [OPTIONS]` followed by the payload. Datagrams for unknown
+/// sessions are dropped.
+fn forward_datagram<B: SamBackend>(backend: &B, sessions: &Sessions, datagram: &[u8]) -> Option<()> {
+    let newline = datagram.iter().position(|&byte| byte == b'\n')?;
+    let header = str::from_utf8(&datagram[..newline]).ok()?;
+    let mut words = header.split_whitespace();
+    if !words.next()?.starts_with("3.") {
+        return None;
+    }
+    let id = words.next()?;
+    let destination = words.next()?;
+    let options = words
+        .filter_map(|word| word.find('=').map(|index| (word[..index].to_string(), word[index + 1..].to_string())))
+        .collect();
+
+    match sessions.lock().unwrap().get(id).filter(|entry| entry.destination.is_some())?.style {
+        SessionStyle::Datagram | SessionStyle::Raw => {}
+        _ => return None
+    }
+    let destination = resolve(backend, destination)?;
+
+    backend.send_datagram(id, &destination, &datagram[newline + 1..], &options).ok()
+}
+
+/// A `SamBridge` accepts SAM clients and maps their commands onto a `SamBackend`.
+pub struct SamBridge<B> {
+    listener: TcpListener,
+    udp: Arc<UdpSocket>,
+    backend: Arc<B>,
+    sessions: Sessions
+}
+
+impl<B> SamBridge<B> where B: SamBackend + 'static {
+    /// Binds the TCP port for clients and the UDP port for datagrams to send.
+    pub fn bind<A, U>(address: A, udp_address: U, backend: Arc<B>) -> Result<SamBridge<B>, SamError>
+        where A: ToSocketAddrs, U: ToSocketAddrs
+    {
+        Ok(SamBridge {
+            listener: TcpListener::bind(address)?,
+            udp: Arc::new(UdpSocket::bind(udp_address)?),
+            backend,
+            sessions: Arc::new(Mutex::new(HashMap::new()))
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn udp_addr(&self) -> io::Result<SocketAddr> {
+        self.udp.local_addr()
+    }
+
+    /// Accepts the next client.
+    pub fn accept(&self) -> Result<SamConnection<B>, SamError> {
+        let (stream, _) = self.listener.accept()?;
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+        Ok(SamConnection {
+            reader: BufReader::new(stream),
+            writer,
+            udp: self.udp.clone(),
+            backend: self.backend.clone(),
+            sessions: self.sessions.clone(),
+            version: None,
+            session: None,
+            closed: Arc::new(AtomicBool::new(false))
+        })
+    }
+
+    /// Sends the datagrams received on the UDP port from a background thread.
+    pub fn forward_datagrams(&self) -> io::Result<thread::JoinHandle<()>> {
+        let udp = self.udp.clone();
+        let backend = self.backend.clone();
+        let sessions = self.sessions.clone();
+
+        Ok(thread::spawn(move || {
+            let mut buf = vec![0x00; 65536];
+            while let Ok((length, _)) = udp.recv_from(&mut buf) {
+                forward_datagram(&*backend, &sessions, &buf[..length]);
+            }
+        }))
+    }
+
+    /// Forwards datagrams and serves each client on its own thread until accepting
+    /// a client fails.
+    pub fn run(&self) -> Result<(), SamError> {
+        self.forward_datagrams()?;
+        loop {
+            let connection = self.accept()?;
+            thread::spawn(move || connection.serve());
+        }
+    }
+}
+
+/// What a connection does after a command.
+enum Flow {
+    Continue,
+    Close,
+    /// The socket now carries the data of a stream.
+    Stream(Box<dyn ByteStream>)
+}
+
+/// A `SamConnection` is one client socket. It starts with `HELLO VERSION`, and then
+/// either controls a session, or is handed over to a single stream by `STREAM
+/// CONNECT` or `STREAM ACCEPT`.
+pub struct SamConnection<B> {
+    reader: BufReader<TcpStream>,
+    writer: Arc<Mutex<TcpStream>>,
+    udp: Arc<UdpSocket>,
+    backend: Arc<B>,
+    sessions: Sessions,
+    version: Option<(u8, u8)>,
+    /// The session created on this socket, which ends when the socket closes.
+    session: Option<String>,
+    closed: Arc<AtomicBool>
+}
+
+impl<B> SamConnection<B> where B: SamBackend + 'static {
+    /// Returns the negotiated version.
+    pub fn version(&self) -> Option<(u8, u8)> {
+        self.version
+    }
+
+    /// Handles commands until the client disconnects, then removes the session
+    /// created on this socket and its subsessions, and stops its forwards.
+    pub fn serve(mut self) -> Result<(), SamError> {
+        let result = self.serve_commands();
+        self.close();
+
+        result
+    }
+
+    fn serve_commands(&mut self) -> Result<(), SamError> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end_matches(['\r', '\n'].as_ref());
+
+            if self.version.is_some() && (line == "PING" || line.starts_with("PING ")) {
+                self.reply_line(&format!("PONG{}", &line["PING".len()..]))?;
+                continue;
+            }
+            let command = match SamCommand::parse(line) {
+                Some(command) => command,
+                None if line.trim().is_empty() => continue,
+                None => return Err(SamError::InvalidCommand(line.to_string()))
+            };
+            if self.version.is_none() && !command.is("HELLO", "VERSION") {
+                return Err(SamError::InvalidCommand(line.to_string()));
+            }
+
+            match self.handle(&command)? {
+                Flow::Continue => {}
+                Flow::Close => return Ok(()),
+                Flow::Stream(stream) => {
+                    let pending = self.reader.buffer().to_vec();
+                    self.reader.consume(pending.len());
+                    return Ok(pipe(self.reader.get_ref().try_clone()?, pending, stream)?);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let action = command.action.as_deref().unwrap_or("");
+        match (command.verb.as_str(), action) {
+            ("HELLO", "VERSION") => self.hello(command),
+            ("SESSION", "CREATE") => {
+                let reply = match self.create_session(command) {
+                    Ok(keys) => status("SESSION", SamResult::Ok).arg("DESTINATION", &keys.private_keys),
+                    Err(failure) => failure_status("SESSION", failure)
+                };
+                self.reply(&reply)?;
+                Ok(Flow::Continue)
+            }
+            ("SESSION", "ADD") | ("SESSION", "REMOVE") => {
+                let result = if action == "ADD" { self.add_subsession(command) } else { self.remove_subsession(command) };
+                let reply = match result {
+                    Ok(id) => status("SESSION", SamResult::Ok).arg("ID", &id),
+                    Err(failure) => failure_status("SESSION", failure)
+                };
+                self.reply(&reply)?;
+                Ok(Flow::Continue)
+            }
+            ("STREAM", "CONNECT") => self.connect(command),
+            ("STREAM", "ACCEPT") => self.accept(command),
+            ("STREAM", "FORWARD") => self.forward(command),
+            ("DATAGRAM", "SEND") | ("RAW", "SEND") => self.send_datagram(command),
+            ("NAMING", "LOOKUP") => self.lookup(command),
+            ("DEST", "GENERATE") => self.generate(command),
+            ("QUIT", _) | ("STOP", _) | ("EXIT", _) => Ok(Flow::Close),
+            _ => {
+                self.reply(&failure_status(&command.verb, (SamResult::I2pError, "Unknown command")))?;
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    fn reply(&self, command: &SamCommand) -> Result<(), SamError> {
+        self.reply_line(&command.to_string())
+    }
+
+    fn reply_line(&self, line: &str) -> Result<(), SamError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line)?;
+
+        Ok(())
+    }
+
+    fn has_ports(&self) -> bool {
+        self.version.is_some_and(|version| version >= PORTS_VERSION)
+    }
+
+    /// Agrees on the highest version within the client's `MIN` and `MAX`.
+    fn hello(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let ours = parse_version(SAM_VERSION).unwrap();
+        let min = command.get("MIN").map_or(Some((0, 0)), parse_version);
+        let max = command.get("MAX").map_or(Some(ours), parse_version);
+        let version = match (min, max) {
+            (Some(min), Some(max)) => Some(cmp::min(max, ours)).filter(|&version| version >= min && version >= (3, 0)),
+            _ => None
+        };
+
+        let reply = SamCommand::new("HELLO", Some("REPLY"));
+        match version {
+            Some((major, minor)) => {
+                self.version = Some((major, minor));
+                self.reply(&reply.arg("RESULT", SamResult::Ok.as_str()).arg("VERSION", &format!("{}.{}", major, minor)))?;
+                Ok(Flow::Continue)
+            }
+            None => {
+                self.reply(&reply.arg("RESULT", SamResult::NoVersion.as_str()))?;
+                Ok(Flow::Close)
+            }
+        }
+    }
+
+    /// Returns the sink for a datagram session's `PORT` and `HOST`.
+    fn sink(&self, style: SessionStyle, command: &SamCommand) -> Result<DatagramSink, Failure> {
+        let target = match (style, command.get("PORT")) {
+            (SessionStyle::Datagram, Some(port)) | (SessionStyle::Raw, Some(port)) => {
+                let port = port.parse::<u16>().map_err(|_| (SamResult::I2pError, "Invalid PORT"))?;
+                let host = command.get("HOST").unwrap_or("127.0.0.1");
+                let address = (host, port).to_socket_addrs().ok()
+                    .and_then(|mut addresses| addresses.next())
+                    .ok_or((SamResult::I2pError, "Invalid HOST"))?;
+                Some(SinkTarget::Udp(self.udp.clone(), address))
+            }
+            (SessionStyle::Datagram, None) | (SessionStyle::Raw, None) => Some(SinkTarget::Control(self.writer.clone())),
+            _ => None
+        };
+
+        Ok(DatagramSink { target })
+    }
+
+    fn create_session(&mut self, command: &SamCommand) -> Result<SamKeys, Failure> {
+        if self.session.is_some() {
+            return Err((SamResult::I2pError, "The socket already has a session"));
+        }
+        let id = command.get("ID").ok_or((SamResult::I2pError, "Missing ID"))?;
+        let style = command.get("STYLE").and_then(SessionStyle::from_name).ok_or((SamResult::I2pError, "Invalid STYLE"))?;
+        let private_keys = match command.get("DESTINATION") {
+            Some("TRANSIENT") => None,
+            Some(private_keys) => Some(private_keys),
+            None => return Err((SamResult::I2pError, "Missing DESTINATION"))
+        };
+        let signature_type = match command.get("SIGNATURE_TYPE") {
+            Some(value) => parse_signature_type(value).ok_or((SamResult::I2pError, "Invalid SIGNATURE_TYPE"))?,
+            None => SignatureType::DSA_SHA1
+        };
+        let sink = self.sink(style, command)?;
+        let options = options(command, &["STYLE", "ID", "DESTINATION", "SIGNATURE_TYPE", "PORT", "HOST"]);
+        self.reserve(id, style, None)?;
+        let keys = match self.backend.create_session(id, style, private_keys, signature_type, &options, sink) {
+            Ok(keys) => keys,
+            Err(result) => {
+                self.sessions.lock().unwrap().remove(id);
+                return Err((result, "The session was not created"));
+            }
+        };
+
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(id) {
+            entry.destination = Some(keys.destination.clone());
+        }
+        self.session = Some(id.to_string());
+
+        Ok(keys)
+    }
+
+    /// Reserves a session ID before the backend starts the session.
+    fn reserve(&self, id: &str, style: SessionStyle, primary: Option<String>) -> Result<(), Failure> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(id) {
+            return Err((SamResult::DuplicatedId, "The ID is in use"));
+        }
+        sessions.insert(id.to_string(), SessionEntry { style, destination: None, primary });
+
+        Ok(())
+    }
+
+    /// Returns the ID and destination of the `PRIMARY` session on this socket.
+    fn primary(&self) -> Result<(String, Destination), Failure> {
+        let sessions = self.sessions.lock().unwrap();
+        match self.session.as_ref().and_then(|id| sessions.get(id).map(|entry| (id, entry))) {
+            Some((id, &SessionEntry { style: SessionStyle::Primary, destination: Some(ref destination), .. })) => {
+                Ok((id.clone(), destination.clone()))
+            }
+            _ => Err((SamResult::I2pError, "The socket has no PRIMARY session"))
+        }
+    }
+
+    fn add_subsession(&mut self, command: &SamCommand) -> Result<String, Failure> {
+        let (primary, destination) = self.primary()?;
+        let id = command.get("ID").ok_or((SamResult::I2pError, "Missing ID"))?;
+        let style = match command.get("STYLE").and_then(SessionStyle::from_name) {
+            Some(SessionStyle::Primary) | None => return Err((SamResult::I2pError, "Invalid STYLE")),
+            Some(style) => style
+        };
+        let sink = self.sink(style, command)?;
+        let options = options(command, &["STYLE", "ID", "PORT", "HOST"]);
+        self.reserve(id, style, Some(primary.clone()))?;
+        if let Err(result) = self.backend.add_subsession(&primary, id, style, &options, sink) {
+            self.sessions.lock().unwrap().remove(id);
+            return Err((result, "The subsession was not added"));
+        }
+
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(id) {
+            entry.destination = Some(destination);
+        }
+
+        Ok(id.to_string())
+    }
+
+    fn remove_subsession(&mut self, command: &SamCommand) -> Result<String, Failure> {
+        let (primary, _) = self.primary()?;
+        let id = command.get("ID").ok_or((SamResult::I2pError, "Missing ID"))?;
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions.get(id) {
+                Some(entry) if entry.primary.as_ref() == Some(&primary) && entry.destination.is_some() => {}
+                _ => return Err((SamResult::InvalidId, "Unknown subsession"))
+            }
+            sessions.remove(id);
+        }
+        self.backend.remove_session(id);
+
+        Ok(id.to_string())
+    }
+
+    /// Returns the ID of a command's session, if it is a stream session.
+    fn stream_session(&self, command: &SamCommand) -> Result<String, Failure> {
+        let id = command.get("ID").ok_or((SamResult::I2pError, "Missing ID"))?;
+        match self.sessions.lock().unwrap().get(id) {
+            Some(entry) if entry.style == SessionStyle::Stream && entry.destination.is_some() => Ok(id.to_string()),
+            _ => Err((SamResult::InvalidId, "Unknown stream session"))
+        }
+    }
+
+    fn open_stream(&self, command: &SamCommand) -> Result<Box<dyn ByteStream>, Failure> {
+        let id = self.stream_session(command)?;
+        let destination = command.get("DESTINATION").ok_or((SamResult::I2pError, "Missing DESTINATION"))?;
+        let destination = resolve(&*self.backend, destination).ok_or((SamResult::InvalidKey, "Invalid DESTINATION"))?;
+        let options = options(command, &["ID", "DESTINATION", "SILENT"]);
+
+        self.backend.connect(&id, &destination, &options)
+            .map_err(|result| (result, "The stream was not opened"))
+    }
+
+    /// Opens a stream and hands the socket over to it. A socket whose `CONNECT`
+    /// failed is closed. With `SILENT=true`, nothing is written on the socket.
+    fn connect(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let silent = command.get("SILENT") == Some("true");
+        match self.open_stream(command) {
+            Ok(stream) => {
+                if !silent {
+                    self.reply(&status("STREAM", SamResult::Ok))?;
+                }
+                Ok(Flow::Stream(stream))
+            }
+            Err(failure) => {
+                if !silent {
+                    self.reply(&failure_status("STREAM", failure))?;
+                }
+                Ok(Flow::Close)
+            }
+        }
+    }
+
+    /// Waits for a stream and hands the socket over to it, after a line with the
+    /// destination of the peer.
+    fn accept(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let silent = command.get("SILENT") == Some("true");
+        let id = match self.stream_session(command) {
+            Ok(id) => id,
+            Err(failure) => {
+                self.reply(&failure_status("STREAM", failure))?;
+                return Ok(Flow::Close);
+            }
+        };
+        if !silent {
+            self.reply(&status("STREAM", SamResult::Ok))?;
+        }
+
+        match self.backend.accept(&id) {
+            Ok(accepted) => {
+                if !silent {
+                    self.reply_line(&peer_line(&accepted, self.has_ports()))?;
+                }
+                Ok(Flow::Stream(accepted.stream))
+            }
+            Err(_) => Ok(Flow::Close)
+        }
+    }
+
+    /// Forwards the streams of a session to `HOST:PORT` until this socket closes.
+    fn forward(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let target = self.stream_session(command).and_then(|id| {
+            let port = command.get("PORT").and_then(|port| port.parse::<u16>().ok())
+                .ok_or((SamResult::I2pError, "Invalid PORT"))?;
+            let host = command.get("HOST").unwrap_or("127.0.0.1");
+            let address = (host, port).to_socket_addrs().ok()
+                .and_then(|mut addresses| addresses.next())
+                .ok_or((SamResult::I2pError, "Invalid HOST"))?;
+            Ok((id, address))
+        });
+
+        let (id, address) = match target {
+            Ok(target) => target,
+            Err(failure) => {
+                self.reply(&failure_status("STREAM", failure))?;
+                return Ok(Flow::Close);
+            }
+        };
+        self.reply(&status("STREAM", SamResult::Ok))?;
+
+        let backend = self.backend.clone();
+        let stopped = self.closed.clone();
+        let silent = command.get("SILENT") == Some("true");
+        let ports = self.has_ports();
+        thread::spawn(move || forward_streams(&*backend, &id, address, silent, ports, &stopped));
+
+        Ok(Flow::Continue)
+    }
+
+    /// Sends the datagram that follows the command on the socket. There is no
+    /// reply; datagrams for unknown sessions or destinations are dropped.
+    fn send_datagram(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let size = match command.get("SIZE").and_then(|size| size.parse::<usize>().ok()) {
+            Some(size) if size <= MAX_DATAGRAM_LENGTH => size,
+            _ => return Err(SamError::InvalidCommand(command.to_string()))
+        };
+        let mut payload = vec![0x00; size];
+        self.reader.read_exact(&mut payload)?;
+
+        let style = if command.verb == "RAW" { SessionStyle::Raw } else { SessionStyle::Datagram };
+        let id = command.get("ID").unwrap_or("");
+        let known = self.sessions.lock().unwrap().get(id)
+            .is_some_and(|entry| entry.style == style && entry.destination.is_some());
+        let destination = command.get("DESTINATION").and_then(|destination| resolve(&*self.backend, destination));
+        if let (true, Some(destination)) = (known, destination) {
+            let options = options(command, &["ID", "DESTINATION", "SIZE"]);
+            let _ = self.backend.send_datagram(id, &destination, &payload, &options);
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    /// Resolves a name. `ME` is the destination of the session on this socket.
+    fn lookup(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let name = command.get("NAME").unwrap_or("");
+        let destination = if name == "ME" {
+            let sessions = self.sessions.lock().unwrap();
+            self.session.as_ref().and_then(|id| sessions.get(id)).and_then(|entry| entry.destination.clone())
+        } else {
+            resolve(&*self.backend, name)
+        };
+
+        let reply = SamCommand::new("NAMING", Some("REPLY"));
+        let reply = match destination {
+            Some(destination) => reply.arg("RESULT", SamResult::Ok.as_str())
+                .arg("NAME", name)
+                .arg("VALUE", &destination.to_i2p_base64()),
+            None => reply.arg("RESULT", SamResult::KeyNotFound.as_str()).arg("NAME", name)
+        };
+        self.reply(&reply)?;
+
+        Ok(Flow::Continue)
+    }
+
+    fn generate(&mut self, command: &SamCommand) -> Result<Flow, SamError> {
+        let signature_type = command.get("SIGNATURE_TYPE").map_or(Some(SignatureType::DSA_SHA1), parse_signature_type);
+        let reply = SamCommand::new("DEST", Some("REPLY"));
+        let reply = match signature_type.and_then(|signature_type| self.backend.generate(signature_type)) {
+            Some(keys) => reply.arg("PUB", &keys.destination.to_i2p_base64()).arg("PRIV", &keys.private_keys),
+            None => reply.arg("RESULT", SamResult::I2pError.as_str()).arg("MESSAGE", "Keys were not generated")
+        };
+        self.reply(&reply)?;
+
+        Ok(Flow::Continue)
+    }
+
+    /// Stops the forwards of this socket and removes its session and subsessions.
+    fn close(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let primary = match self.session.take() {
+            Some(primary) => primary,
+            None => return
+        };
+
+        let mut removed: Vec<String> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids: Vec<String> = sessions.iter()
+                .filter(|&(_, entry)| entry.primary.as_ref() == Some(&primary))
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in ids.iter() {
+                sessions.remove(id);
+            }
+            sessions.remove(&primary);
+            ids
+        };
+        removed.push(primary);
+
+        for id in removed.iter() {
+            self.backend.remove_session(id);
+        }
+    }
+}
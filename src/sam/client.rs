@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use rand;
+use rand::Rng;
+use common::{Destination, SignatureType};
+use sam::bridge::{SamKeys, DEFAULT_SAM_PORT, DEFAULT_SAM_UDP_PORT, SAM_VERSION};
+use sam::command::{SamCommand, SamResult, SessionStyle};
+use sam::error::SamError;
+
+
+/// The oldest version the client speaks. SAM 3.1 added `SIGNATURE_TYPE`.
+pub const MIN_SAM_VERSION: &str = "3.1";
+
+/// The largest datagram, header included, the bridge forwards over UDP.
+const MAX_UDP_DATAGRAM_LENGTH: usize = 65536;
+
+fn bridge_address<A: ToSocketAddrs>(bridge: A) -> Result<SocketAddr, SamError> {
+    match bridge.to_socket_addrs()?.next() {
+        Some(address) => Ok(address),
+        None => Err(SamError::Io(io::Error::new(io::ErrorKind::InvalidInput, "The bridge has no address")))
+    }
+}
+
+/// A `Control` is a socket to the bridge on which the version has been agreed.
+struct Control {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream
+}
+
+impl Control {
+    fn connect(bridge: SocketAddr) -> Result<Control, SamError> {
+        let writer = TcpStream::connect(bridge)?;
+        let mut control = Control {
+            reader: BufReader::new(writer.try_clone()?),
+            writer
+        };
+        let hello = SamCommand::new("HELLO", Some("VERSION"))
+            .arg("MIN", MIN_SAM_VERSION)
+            .arg("MAX", SAM_VERSION);
+        control.command(&hello, "HELLO", "REPLY")?;
+
+        Ok(control)
+    }
+
+    fn read_line(&mut self) -> Result<String, SamError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(SamError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "The bridge closed the connection")));
+        }
+
+        Ok(line.trim_end_matches(['\r', '\n'].as_ref()).to_string())
+    }
+
+    /// Sends a command and reads its reply, which must have the verb and action and
+    /// a `RESULT` of `OK`.
+    fn command(&mut self, command: &SamCommand, verb: &str, action: &str) -> Result<SamCommand, SamError> {
+        writeln!(self.writer, "{}", command)?;
+        let line = self.read_line()?;
+        let reply = match SamCommand::parse(&line) {
+            Some(ref reply) if reply.is(verb, action) => reply.clone(),
+            _ => return Err(SamError::InvalidReply(line))
+        };
+
+        match reply.result() {
+            Some(SamResult::Ok) => Ok(reply),
+            Some(result) => Err(SamError::Failed(result, reply.get("MESSAGE").unwrap_or("").to_string())),
+            None => Err(SamError::InvalidReply(line))
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<Destination, SamError> {
+        let reply = self.command(&SamCommand::new("NAMING", Some("LOOKUP")).arg("NAME", name), "NAMING", "REPLY")?;
+        match reply.get("VALUE").and_then(Destination::from_i2p_base64) {
+            Some(destination) => Ok(destination),
+            None => Err(SamError::InvalidReply(reply.to_string()))
+        }
+    }
+}
+
+/// Creates new keys with the bridge at `bridge`.
+pub fn generate_keys<A: ToSocketAddrs>(bridge: A, signature_type: SignatureType) -> Result<SamKeys, SamError> {
+    let mut control = Control::connect(bridge_address(bridge)?)?;
+    let command = SamCommand::new("DEST", Some("GENERATE")).arg("SIGNATURE_TYPE", &signature_type.code().to_string());
+    let reply = control.command(&command, "DEST", "REPLY")?;
+
+    match reply.get("PRIV").and_then(SamKeys::from_private_keys) {
+        Some(ref keys) if reply.get("PUB") == Some(keys.destination.to_i2p_base64().as_str()) => Ok(keys.clone()),
+        _ => Err(SamError::InvalidReply(reply.to_string()))
+    }
+}
+
+/// Resolves a host name or `.b32.i2p` address with the bridge at `bridge`.
+pub fn lookup<A: ToSocketAddrs>(bridge: A, name: &str) -> Result<Destination, SamError> {
+    Control::connect(bridge_address(bridge)?)?.lookup(name)
+}
+
+/// A `Session` is a SAM session on a bridge, which lives as long as its control
+/// socket. Datagram sessions receive their datagrams on a UDP socket of their own
+/// and send them through the UDP port of the bridge, which Java I2P and i2pd both
+/// support.
+pub struct Session {
+    bridge: SocketAddr,
+    udp_bridge: SocketAddr,
+    id: String,
+    style: SessionStyle,
+    keys: SamKeys,
+    control: Control,
+    udp: Option<UdpSocket>
+}
+
+impl Session {
+    /// Creates a session on the bridge at its default address.
+    pub fn create(style: SessionStyle, keys: Option<&SamKeys>, options: &HashMap<String, String>) -> Result<Session, SamError> {
+        Session::create_at(("127.0.0.1", DEFAULT_SAM_PORT), style, keys, options)
+    }
+
+    /// Creates a session with the keys, or with transient keys if there are none.
+    /// `options` may hold `SIGNATURE_TYPE` and I2CP options such as
+    /// `inbound.length`.
+    pub fn create_at<A: ToSocketAddrs>(bridge: A, style: SessionStyle, keys: Option<&SamKeys>,
+                                       options: &HashMap<String, String>) -> Result<Session, SamError> {
+        let bridge = bridge_address(bridge)?;
+        let mut control = Control::connect(bridge)?;
+        let id = format!("rusti2p-{:08x}", rand::thread_rng().gen::<u32>());
+
+        let mut command = SamCommand::new("SESSION", Some("CREATE"))
+            .arg("STYLE", style.as_str())
+            .arg("ID", &id)
+            .arg("DESTINATION", keys.map_or("TRANSIENT", |keys| keys.private_keys.as_str()));
+        let udp = match style {
+            SessionStyle::Datagram | SessionStyle::Raw => {
+                let host = control.writer.local_addr()?.ip();
+                let udp = UdpSocket::bind((host, 0))?;
+                command = command.arg("HOST", &host.to_string()).arg("PORT", &udp.local_addr()?.port().to_string());
+                Some(udp)
+            }
+            _ => None
+        };
+        let mut options: Vec<_> = options.iter().collect();
+        options.sort();
+        for (key, value) in options {
+            command = command.arg(key, value);
+        }
+
+        let reply = control.command(&command, "SESSION", "STATUS")?;
+        let keys = match reply.get("DESTINATION").and_then(SamKeys::from_private_keys) {
+            Some(keys) => keys,
+            None => return Err(SamError::InvalidReply(reply.to_string()))
+        };
+
+        Ok(Session {
+            bridge,
+            udp_bridge: SocketAddr::new(bridge.ip(), DEFAULT_SAM_UDP_PORT),
+            id,
+            style,
+            keys,
+            control,
+            udp
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn style(&self) -> SessionStyle {
+        self.style
+    }
+
+    /// Returns the keys of the session, which can be saved to create it again with
+    /// the same destination.
+    pub fn keys(&self) -> &SamKeys {
+        &self.keys
+    }
+
+    pub fn destination(&self) -> &Destination {
+        &self.keys.destination
+    }
+
+    /// Sets the UDP address datagrams are sent to, when the bridge does not use
+    /// its default UDP port.
+    pub fn set_udp_bridge(&mut self, udp_bridge: SocketAddr) {
+        self.udp_bridge = udp_bridge;
+    }
+
+    /// Resolves a host name or `.b32.i2p` address.
+    pub fn lookup(&mut self, name: &str) -> Result<Destination, SamError> {
+        self.control.lookup(name)
+    }
+
+    /// Opens a stream to a destination. See `Stream::connect`.
+    pub fn connect(&self, destination: &str) -> Result<Stream, SamError> {
+        Stream::connect(self, destination)
+    }
+
+    /// Waits for the next stream to the session.
+    pub fn accept(&self) -> Result<Stream, SamError> {
+        Stream::accept(self)
+    }
+
+    /// Returns an iterator over the streams to the session, for accept loops.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming {
+            session: self
+        }
+    }
+
+    fn udp(&self) -> Result<&UdpSocket, SamError> {
+        match self.udp {
+            Some(ref udp) => Ok(udp),
+            None => Err(SamError::Failed(SamResult::I2pError, format!("A {} session has no datagrams", self.style.as_str())))
+        }
+    }
+
+    /// Sends a datagram, repliable or raw according to the style of the session.
+    pub fn send_to(&self, payload: &[u8], destination: &Destination) -> Result<(), SamError> {
+        let mut datagram = format!("3.0 {} {}\n", self.id, destination.to_i2p_base64()).into_bytes();
+        datagram.extend_from_slice(payload);
+        self.udp()?.send_to(&datagram, self.udp_bridge)?;
+
+        Ok(())
+    }
+
+    /// Receives a datagram into `buf`, truncating it if `buf` is too short. Returns
+    /// its length and, for a repliable datagram, the destination of its sender.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Option<Destination>), SamError> {
+        let mut datagram = vec![0x00; MAX_UDP_DATAGRAM_LENGTH];
+        let (length, _) = self.udp()?.recv_from(&mut datagram)?;
+        datagram.truncate(length);
+
+        // Repliable datagrams start with a line holding the sender and, since SAM
+        // 3.2, its ports.
+        let (payload, from) = if self.style == SessionStyle::Datagram {
+            let newline = match datagram.iter().position(|&byte| byte == b'\n') {
+                Some(newline) => newline,
+                None => return Err(SamError::InvalidReply(String::from_utf8_lossy(&datagram).into_owned()))
+            };
+            let header = String::from_utf8_lossy(&datagram[..newline]).into_owned();
+            match header.split_whitespace().next().and_then(Destination::from_i2p_base64) {
+                Some(from) => (&datagram[newline + 1..], Some(from)),
+                None => return Err(SamError::InvalidReply(header))
+            }
+        } else {
+            (&datagram[..], None)
+        };
+
+        let length = payload.len().min(buf.len());
+        buf[..length].copy_from_slice(&payload[..length]);
+
+        Ok((length, from))
+    }
+
+    /// Sets how long `recv_from` waits for a datagram.
+    pub fn set_datagram_timeout(&self, timeout: Option<Duration>) -> Result<(), SamError> {
+        Ok(self.udp()?.set_read_timeout(timeout)?)
+    }
+}
+
+/// An iterator over the streams accepted by a session. It never ends; each failed
+/// accept is returned as an error.
+pub struct Incoming<'a> {
+    session: &'a Session
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = Result<Stream, SamError>;
+
+    fn next(&mut self) -> Option<Result<Stream, SamError>> {
+        Some(self.session.accept())
+    }
+}
+
+/// A `Stream` is a streaming connection through the bridge, carried by a socket of
+/// its own.
+pub struct Stream {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    peer: Option<Destination>
+}
+
+impl Stream {
+    /// Opens a stream from a session to a destination, given in base64, as a
+    /// `.b32.i2p` address or as a host name for the bridge to resolve.
+    pub fn connect(session: &Session, destination: &str) -> Result<Stream, SamError> {
+        let mut control = Control::connect(session.bridge)?;
+        let command = SamCommand::new("STREAM", Some("CONNECT"))
+            .arg("ID", &session.id)
+            .arg("DESTINATION", destination)
+            .arg("SILENT", "false");
+        control.command(&command, "STREAM", "STATUS")?;
+
+        Ok(Stream {
+            reader: control.reader,
+            writer: control.writer,
+            peer: Destination::from_i2p_base64(destination)
+        })
+    }
+
+    /// Waits for the next stream to a session.
+    pub fn accept(session: &Session) -> Result<Stream, SamError> {
+        let mut control = Control::connect(session.bridge)?;
+        let command = SamCommand::new("STREAM", Some("ACCEPT"))
+            .arg("ID", &session.id)
+            .arg("SILENT", "false");
+        control.command(&command, "STREAM", "STATUS")?;
+
+        let line = control.read_line()?;
+        let peer = match line.split_whitespace().next().and_then(Destination::from_i2p_base64) {
+            Some(peer) => peer,
+            None => return Err(SamError::InvalidReply(line))
+        };
+
+        Ok(Stream {
+            reader: control.reader,
+            writer: control.writer,
+            peer: Some(peer)
+        })
+    }
+
+    /// Returns the destination of the peer, if it is known. It is not when a
+    /// stream was opened to a name.
+    pub fn peer(&self) -> Option<&Destination> {
+        self.peer.as_ref()
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    /// Closes the sending half of the stream.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.writer.shutdown(Shutdown::Write)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+use common::SignatureType;
+
+
+/// The result codes of SAM replies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SamResult {
+    Ok,
+    CantReachPeer,
+    DuplicatedDest,
+    DuplicatedId,
+    I2pError,
+    InvalidId,
+    InvalidKey,
+    KeyNotFound,
+    NoVersion,
+    PeerNotFound,
+    Timeout
+}
+
+impl SamResult {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SamResult::Ok             => "OK",
+            SamResult::CantReachPeer  => "CANT_REACH_PEER",
+            SamResult::DuplicatedDest => "DUPLICATED_DEST",
+            SamResult::DuplicatedId   => "DUPLICATED_ID",
+            SamResult::I2pError       => "I2P_ERROR",
+            SamResult::InvalidId      => "INVALID_ID",
+            SamResult::InvalidKey     => "INVALID_KEY",
+            SamResult::KeyNotFound    => "KEY_NOT_FOUND",
+            SamResult::NoVersion      => "NOVERSION",
+            SamResult::PeerNotFound   => "PEER_NOT_FOUND",
+            SamResult::Timeout        => "TIMEOUT"
+        }
+    }
+
+    pub fn from_name(result: &str) -> Option<SamResult> {
+        let result = match result {
+            "OK"              => SamResult::Ok,
+            "CANT_REACH_PEER" => SamResult::CantReachPeer,
+            "DUPLICATED_DEST" => SamResult::DuplicatedDest,
+            "DUPLICATED_ID"   => SamResult::DuplicatedId,
+            "I2P_ERROR"       => SamResult::I2pError,
+            "INVALID_ID"      => SamResult::InvalidId,
+            "INVALID_KEY"     => SamResult::InvalidKey,
+            "KEY_NOT_FOUND"   => SamResult::KeyNotFound,
+            "NOVERSION"       => SamResult::NoVersion,
+            "PEER_NOT_FOUND"  => SamResult::PeerNotFound,
+            "TIMEOUT"         => SamResult::Timeout,
+            _ => return None
+        };
+
+        Some(result)
+    }
+}
+
+/// The style of a SAM session.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionStyle {
+    Stream,
+    /// Repliable, signed datagrams.
+    Datagram,
+    /// Anonymous datagrams.
+    Raw,
+    /// A session holding subsessions of the other styles that share its destination.
+    Primary
+}
+
+impl SessionStyle {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SessionStyle::Stream   => "STREAM",
+            SessionStyle::Datagram => "DATAGRAM",
+            SessionStyle::Raw      => "RAW",
+            SessionStyle::Primary  => "PRIMARY"
+        }
+    }
+
+    /// Parses a style. `MASTER` is the name of `PRIMARY` before SAM 3.3.
+    pub fn from_name(style: &str) -> Option<SessionStyle> {
+        match style {
+            "STREAM" => Some(SessionStyle::Stream),
+            "DATAGRAM" => Some(SessionStyle::Datagram),
+            "RAW" => Some(SessionStyle::Raw),
+            "PRIMARY" | "MASTER" => Some(SessionStyle::Primary),
+            _ => None
+        }
+    }
+}
+
+/// Parses a `SIGNATURE_TYPE` value, given either as a number or as a name such as
+/// `EdDSA_SHA512_Ed25519`.
+pub fn parse_signature_type(value: &str) -> Option<SignatureType> {
+    if let Ok(code) = value.parse::<u16>() {
+        return SignatureType::from_code(code);
+    }
+
+    (0..9).filter_map(SignatureType::from_code)
+        .find(|sigtype| format!("{:?}", sigtype).eq_ignore_ascii_case(value))
+}
+
+/// A `SamCommand` is one line of the SAM protocol: a verb, an optional action, and
+/// `KEY=VALUE` arguments. Values containing spaces are double quoted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SamCommand {
+    pub verb: String,
+    pub action: Option<String>,
+    pub args: Vec<(String, String)>
+}
+
+impl SamCommand {
+    pub fn new(verb: &str, action: Option<&str>) -> SamCommand {
+        SamCommand {
+            verb: verb.to_string(),
+            action: action.map(|action| action.to_string()),
+            args: Vec::new()
+        }
+    }
+
+    /// Adds an argument.
+    pub fn arg(mut self, key: &str, value: &str) -> SamCommand {
+        self.args.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns the value of the first argument with the key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.args.iter().find(|arg| arg.0 == key).map(|arg| arg.1.as_str())
+    }
+
+    /// Determines whether the command has this verb and action.
+    pub fn is(&self, verb: &str, action: &str) -> bool {
+        self.verb == verb && self.action.as_deref() == Some(action)
+    }
+
+    /// Returns the `RESULT` of a reply, which is `OK` when absent.
+    pub fn result(&self) -> Option<SamResult> {
+        match self.get("RESULT") {
+            Some(result) => SamResult::from_name(result),
+            None => Some(SamResult::Ok)
+        }
+    }
+
+    /// Parses a line, without its newline. Returns `None` for an empty line or an
+    /// unterminated quote.
+    pub fn parse(line: &str) -> Option<SamCommand> {
+        let tokens = tokenize(line.trim_end_matches(['\r', '\n'].as_ref()))?;
+        let mut tokens = tokens.into_iter();
+        let verb = tokens.next()?;
+
+        let mut command = SamCommand { verb, action: None, args: Vec::new() };
+        for (i, token) in tokens.enumerate() {
+            match token.find('=') {
+                Some(index) => command.args.push((token[..index].to_string(), token[index + 1..].to_string())),
+                None if i == 0 => command.action = Some(token),
+                None => command.args.push((token, String::new()))
+            }
+        }
+
+        Some(command)
+    }
+}
+
+/// Splits a line on spaces, keeping quoted strings together and removing their
+/// quotes. A backslash escapes the next character inside quotes.
+fn tokenize(line: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => token.push(chars.next()?),
+            ' ' | '\t' if !quoted => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+            }
+            _ => token.push(c)
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    if tokens.is_empty() { None } else { Some(tokens) }
+}
+
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains([' ', '\t', '"', '\\'].as_ref()) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for SamCommand {
+    /// Formats the command as a line, without its newline.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.verb)?;
+        if let Some(ref action) = self.action {
+            write!(f, " {}", action)?;
+        }
+        for (key, value) in self.args.iter() {
+            write!(f, " {}={}", key, quote(value))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::error;
+use std::fmt;
+use std::io;
+use sam::command::SamResult;
+
+
+#[derive(Debug)]
+pub enum SamError {
+    Io(io::Error),
+    /// A line could not be parsed as a SAM command.
+    InvalidCommand(String),
+    /// The bridge sent a reply we did not expect, or could not parse.
+    InvalidReply(String),
+    /// The bridge refused a command, with its result and message.
+    Failed(SamResult, String),
+}
+
+impl fmt::Display for SamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SamError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred on a SAM connection: {}", err)
+            }
+            SamError::InvalidCommand(ref line) => {
+                writeln!(f, "Error: The SAM command {:?} is malformed.", line)
+            }
+            SamError::InvalidReply(ref line) => {
+                writeln!(f, "Error: The SAM bridge sent an unexpected reply: {:?}", line)
+            }
+            SamError::Failed(result, ref message) => {
+                writeln!(f, "Error: The SAM bridge refused the command with {}: {}", result.as_str(), message)
+            }
+        }
+    }
+}
+
+impl error::Error for SamError {
+    fn description(&self) -> &str {
+        match *self {
+            SamError::Io(_) => "An I/O error occurred on a SAM connection.",
+            SamError::InvalidCommand(_) => "A SAM command is malformed.",
+            SamError::InvalidReply(_) => "The SAM bridge sent an unexpected reply.",
+            SamError::Failed(..) => "The SAM bridge refused a command.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            SamError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SamError {
+    fn from(err: io::Error) -> SamError {
+        SamError::Io(err)
+    }
+}
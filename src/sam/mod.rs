@@ -0,0 +1,11 @@
+pub use self::error::SamError;
+pub use self::command::{SamCommand, SamResult, SessionStyle, parse_signature_type};
+pub use self::bridge::{SamBridge, SamConnection, SamBackend, SamKeys, DatagramSink, AcceptedStream};
+pub use self::bridge::{DEFAULT_SAM_PORT, DEFAULT_SAM_UDP_PORT, SAM_VERSION, MAX_DATAGRAM_LENGTH};
+pub use self::client::{Session, Stream, Incoming, generate_keys, lookup, MIN_SAM_VERSION};
+
+
+mod error;
+mod command;
+mod bridge;
+mod client;
@@ -0,0 +1,617 @@
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+use common::{Destination, I2pDate, SessionSigner, SignatureVerifier};
+use streaming::error::StreamingError;
+use streaming::packet::{Packet, FLAG_CLOSE, FLAG_NO_ACK, FLAG_RESET, FLAG_SYNCHRONIZE};
+
+
+/// The largest payload of a packet by default, which keeps a packet and its
+/// garlic wrapping within a tunnel message or two.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1730;
+
+/// A packet counts as lost after this many NACKs, and is resent without waiting
+/// for its timeout.
+pub const FAST_RETRANSMIT_THRESHOLD: u32 = 2;
+
+/// The NACK count is a single byte.
+const MAX_NACKS: usize = 255;
+
+/// The parameters of a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamConfig {
+    /// The largest payload we send, and announce in our SYN.
+    pub max_packet_size: usize,
+    /// The number of packets in flight when the connection starts.
+    pub initial_window: u32,
+    pub max_window: u32,
+    pub initial_rto: Duration,
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+    /// The connection times out when a packet is still unacknowledged after this
+    /// many retransmissions.
+    pub max_retransmissions: u32,
+    /// The number of bytes written but not yet sent that the connection buffers.
+    pub send_buffer: usize,
+    /// The number of bytes received but not yet read that the connection buffers,
+    /// counting packets that arrived out of order.
+    pub receive_buffer: usize
+}
+
+impl Default for StreamConfig {
+    fn default() -> StreamConfig {
+        StreamConfig {
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            initial_window: 6,
+            max_window: 128,
+            initial_rto: Duration::from_secs(9),
+            min_rto: Duration::from_millis(100),
+            max_rto: Duration::from_secs(45),
+            max_retransmissions: 8,
+            send_buffer: 64 * 1024,
+            receive_buffer: 64 * 1024
+        }
+    }
+}
+
+/// An `RttEstimator` keeps the smoothed round trip time and its variation as in
+/// RFC 6298, and derives the retransmission timeout from them.
+#[derive(Clone, Debug)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    min_rto: Duration,
+    max_rto: Duration
+}
+
+impl RttEstimator {
+    pub fn new(initial_rto: Duration, min_rto: Duration, max_rto: Duration) -> RttEstimator {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::from_millis(0),
+            rto: initial_rto,
+            min_rto,
+            max_rto
+        }
+    }
+
+    /// Returns the smoothed round trip time, once there has been a sample.
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Adds the round trip time of a packet that was sent once.
+    pub fn sample(&mut self, rtt: Duration) {
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2;
+                rtt
+            }
+            Some(srtt) => {
+                let deviation = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar * 3 / 4 + deviation / 4;
+                srtt * 7 / 8 + rtt / 8
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto = cmp::max(self.min_rto, cmp::min(self.max_rto, srtt + self.rttvar * 4));
+    }
+
+    /// Doubles the timeout after a retransmission timeout.
+    pub fn backoff(&mut self) {
+        self.rto = cmp::min(self.max_rto, self.rto * 2);
+    }
+}
+
+/// The state of a connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Our SYN has not been answered.
+    Connecting,
+    Established,
+    /// Both sides have closed, and our CLOSE was acknowledged.
+    Closed,
+    Reset,
+    TimedOut
+}
+
+struct Sent {
+    packet: Packet,
+    sent_at: Instant,
+    transmissions: u32,
+    nacked: u32
+}
+
+/// Determines whether a packet must be signed: the SYNs, which open the
+/// connection, and the packets that close or reset it.
+fn needs_signature(packet: &Packet) -> bool {
+    packet.has_flag(FLAG_SYNCHRONIZE | FLAG_CLOSE | FLAG_RESET)
+}
+
+/// A `Connection` is the state machine of one stream, independent of how packets
+/// travel. Data written is cut into packets as the congestion window allows, and
+/// `poll` returns the packets to send, including retransmissions and acks.
+/// Packets received are passed to `receive`, and their data is read in order.
+/// SYNs carry the sender's destination and are signed with its key, as are the
+/// packets that close or reset the connection; such packets from the peer are
+/// dropped unless they verify.
+pub struct Connection<S, V> {
+    config: StreamConfig,
+    state: ConnectionState,
+    local_id: u32,
+    remote_id: u32,
+    from: Destination,
+    peer: Option<Destination>,
+    signer: S,
+    verifier: V,
+    max_payload: usize,
+
+    send_buffer: VecDeque<u8>,
+    next_sequence: u32,
+    syn_sent: bool,
+    close_requested: bool,
+    close_sent: bool,
+    unacked: BTreeMap<u32, Sent>,
+    fast_retransmits: BTreeSet<u32>,
+    window: u32,
+    window_acks: u32,
+    threshold: u32,
+    rtt: RttEstimator,
+    retransmissions: u64,
+
+    next_expected: u32,
+    highest_received: Option<u32>,
+    out_of_order: BTreeMap<u32, Packet>,
+    received: VecDeque<u8>,
+    remote_closed: bool,
+    ack_pending: bool
+}
+
+impl<S, V> Connection<S, V> where S: SessionSigner, V: SignatureVerifier {
+    fn new(local_id: u32, from: Destination, signer: S, verifier: V, config: StreamConfig) -> Connection<S, V> {
+        Connection {
+            state: ConnectionState::Connecting,
+            local_id,
+            remote_id: 0,
+            from,
+            peer: None,
+            signer,
+            verifier,
+            max_payload: config.max_packet_size,
+            send_buffer: VecDeque::new(),
+            next_sequence: 0,
+            syn_sent: false,
+            close_requested: false,
+            close_sent: false,
+            unacked: BTreeMap::new(),
+            fast_retransmits: BTreeSet::new(),
+            window: config.initial_window,
+            window_acks: 0,
+            threshold: config.max_window,
+            rtt: RttEstimator::new(config.initial_rto, config.min_rto, config.max_rto),
+            retransmissions: 0,
+            next_expected: 0,
+            highest_received: None,
+            out_of_order: BTreeMap::new(),
+            received: VecDeque::new(),
+            remote_closed: false,
+            ack_pending: false,
+            config
+        }
+    }
+
+    /// Opens a connection from `from`, whose packets `signer` signs. The first
+    /// packet polled is its SYN, which carries `from` so the peer knows who
+    /// connects.
+    pub fn connect(local_id: u32, from: Destination, signer: S, verifier: V, config: StreamConfig) -> Connection<S, V> {
+        Connection::new(local_id, from, signer, verifier, config)
+    }
+
+    /// Accepts the connection a SYN opens. The first packet polled is our SYN in
+    /// reply. Fails if the SYN is not signed by the destination it carries.
+    pub fn accept(local_id: u32, syn: Packet, from: Destination, signer: S, verifier: V, config: StreamConfig,
+                  now: Instant) -> Result<Connection<S, V>, StreamingError> {
+        if !Connection::<S, V>::is_valid_syn(&syn, &verifier) {
+            return Err(StreamingError::InvalidSignature);
+        }
+        let mut connection = Connection::new(local_id, from, signer, verifier, config);
+        connection.remote_id = syn.receive_stream_id;
+        connection.state = ConnectionState::Established;
+        connection.receive(syn, now);
+
+        Ok(connection)
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn local_id(&self) -> u32 {
+        self.local_id
+    }
+
+    /// Returns the peer's stream ID, or zero before it is known.
+    pub fn remote_id(&self) -> u32 {
+        self.remote_id
+    }
+
+    /// Returns the peer's destination, from its SYN.
+    pub fn peer(&self) -> Option<&Destination> {
+        self.peer.as_ref()
+    }
+
+    /// Determines whether a packet is a SYN signed by the destination it carries.
+    pub fn is_valid_syn(syn: &Packet, verifier: &V) -> bool {
+        match syn.from {
+            Some(ref from) if syn.is_syn() => syn.verify(verifier, from, I2pDate::now()),
+            _ => false
+        }
+    }
+
+    /// Checks the signature of a packet that must be signed. A SYN is signed by
+    /// the destination it carries, which must be the peer's once that is known;
+    /// other packets by the peer.
+    fn verify(&mut self, packet: &Packet) -> bool {
+        if !needs_signature(packet) {
+            return true;
+        }
+        let signer = match (packet.is_syn(), packet.from.as_ref(), self.peer.as_ref()) {
+            (true, Some(from), Some(peer)) if from != peer => return false,
+            (true, Some(from), _) => from.clone(),
+            (false, _, Some(peer)) => peer.clone(),
+            _ => return false
+        };
+        if !packet.verify(&self.verifier, &signer, I2pDate::now()) {
+            return false;
+        }
+        self.peer = Some(signer);
+
+        true
+    }
+
+    /// Returns the congestion window in packets.
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Returns the number of packets resent.
+    pub fn retransmissions(&self) -> u64 {
+        self.retransmissions
+    }
+
+    /// Returns the number of packets sent and not yet acknowledged.
+    pub fn in_flight(&self) -> usize {
+        self.unacked.len()
+    }
+
+    fn is_finished(&self) -> bool {
+        match self.state {
+            ConnectionState::Closed | ConnectionState::Reset | ConnectionState::TimedOut => true,
+            ConnectionState::Connecting | ConnectionState::Established => false
+        }
+    }
+
+    /// Buffers data to send. Returns the number of bytes taken, which is zero when
+    /// the buffer is full or the connection no longer sends.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        if self.close_requested || self.is_finished() {
+            return 0;
+        }
+        let length = cmp::min(data.len(), self.config.send_buffer.saturating_sub(self.send_buffer.len()));
+        self.send_buffer.extend(data[..length].iter());
+
+        length
+    }
+
+    /// Determines whether `write` can no longer take data.
+    pub fn is_write_closed(&self) -> bool {
+        self.close_requested || self.is_finished()
+    }
+
+    /// Reads the data received in order.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let length = cmp::min(buf.len(), self.received.len());
+        for (byte, received) in buf.iter_mut().zip(self.received.drain(..length)) {
+            *byte = received;
+        }
+
+        length
+    }
+
+    /// Returns the number of bytes ready to read.
+    pub fn available(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Determines whether everything the peer will send has been read.
+    pub fn is_eof(&self) -> bool {
+        self.received.is_empty() && (self.remote_closed || self.is_finished())
+    }
+
+    /// Determines whether everything written, and our CLOSE if we closed, has been
+    /// acknowledged.
+    pub fn is_flushed(&self) -> bool {
+        self.send_buffer.is_empty() && self.unacked.is_empty() && self.close_sent == self.close_requested
+    }
+
+    /// Sends a CLOSE once the data written has been sent. The peer can still send.
+    pub fn close(&mut self) {
+        self.close_requested = true;
+    }
+
+    /// Aborts the connection and returns the RESET to send, if the peer is known.
+    pub fn reset(&mut self) -> Option<Packet> {
+        self.state = ConnectionState::Reset;
+        self.send_buffer.clear();
+        self.unacked.clear();
+        if self.remote_id == 0 {
+            return None;
+        }
+
+        let mut packet = Packet::new(self.remote_id, self.local_id, 0, FLAG_RESET);
+        self.seal(&mut packet);
+        Some(packet)
+    }
+
+    /// Returns when the oldest packet in flight times out.
+    pub fn next_timeout(&self) -> Option<Instant> {
+        self.unacked.values().map(|sent| sent.sent_at + self.rtt.rto()).min()
+    }
+
+    /// Handles a packet from the peer. Packets that must be signed and do not
+    /// verify are dropped.
+    pub fn receive(&mut self, packet: Packet, now: Instant) {
+        if !self.verify(&packet) {
+            return;
+        }
+        if self.is_finished() {
+            // The peer resends its CLOSE until our ack arrives.
+            if self.state == ConnectionState::Closed && !packet.is_ack_only() {
+                self.ack_pending = true;
+            }
+            return;
+        }
+        if packet.has_flag(FLAG_RESET) {
+            self.state = ConnectionState::Reset;
+            return;
+        }
+        if self.state == ConnectionState::Connecting {
+            if packet.receive_stream_id == 0 {
+                return;
+            }
+            self.remote_id = packet.receive_stream_id;
+            self.state = ConnectionState::Established;
+        }
+        if let Some(size) = packet.max_packet_size {
+            self.max_payload = cmp::max(1, cmp::min(self.max_payload, size as usize));
+        }
+
+        if !packet.has_flag(FLAG_NO_ACK) {
+            self.process_ack(&packet, now);
+        }
+        if !packet.is_ack_only() {
+            self.process_data(packet);
+        }
+
+        if self.close_sent && self.unacked.is_empty() && self.remote_closed {
+            self.state = ConnectionState::Closed;
+        }
+    }
+
+    fn process_ack(&mut self, packet: &Packet, now: Instant) {
+        let acked: Vec<u32> = self.unacked.range(..=packet.ack_through)
+            .map(|(&sequence, _)| sequence)
+            .filter(|sequence| !packet.nacks.contains(sequence))
+            .collect();
+        for sequence in acked {
+            let sent = self.unacked.remove(&sequence).unwrap();
+            self.fast_retransmits.remove(&sequence);
+            // Karn's algorithm: a resent packet's ack could be for either copy.
+            if sent.transmissions == 1 {
+                self.rtt.sample(now.saturating_duration_since(sent.sent_at));
+            }
+            self.grow_window();
+        }
+
+        for nack in packet.nacks.iter() {
+            if let Some(sent) = self.unacked.get_mut(nack) {
+                sent.nacked += 1;
+                if sent.nacked == FAST_RETRANSMIT_THRESHOLD {
+                    self.fast_retransmits.insert(*nack);
+                }
+            }
+        }
+    }
+
+    /// Grows the window by a packet per ack in slow start, and by a packet per
+    /// window of acks after that.
+    fn grow_window(&mut self) {
+        if self.window < self.threshold {
+            self.window += 1;
+        } else {
+            self.window_acks += 1;
+            if self.window_acks >= self.window {
+                self.window += 1;
+                self.window_acks = 0;
+            }
+        }
+        self.window = cmp::min(self.window, self.config.max_window);
+    }
+
+    /// Returns the bytes buffered for reading, including those that arrived out of
+    /// order.
+    fn buffered(&self) -> usize {
+        self.received.len() + self.out_of_order.values().map(|packet| packet.payload.len()).sum::<usize>()
+    }
+
+    /// Buffers the data of a packet. Packets beyond our window, or that do not fit in
+    /// the receive buffer, are dropped; the peer resends them.
+    fn process_data(&mut self, packet: Packet) {
+        self.ack_pending = true;
+        let sequence = packet.sequence_number;
+        if sequence < self.next_expected || self.remote_closed || self.out_of_order.contains_key(&sequence) {
+            return;
+        }
+        if sequence - self.next_expected >= self.config.max_window
+            || self.buffered() + packet.payload.len() > self.config.receive_buffer {
+            return;
+        }
+
+        self.highest_received = Some(self.highest_received.map_or(sequence, |highest| cmp::max(highest, sequence)));
+        self.out_of_order.insert(sequence, packet);
+        while let Some(packet) = self.out_of_order.remove(&self.next_expected) {
+            self.received.extend(packet.payload.iter());
+            self.next_expected += 1;
+            if packet.has_flag(FLAG_CLOSE) {
+                self.remote_closed = true;
+                self.out_of_order.clear();
+                break;
+            }
+        }
+    }
+
+    /// Sets the acknowledgement fields of a packet about to be sent.
+    fn fill_ack(&self, packet: &mut Packet) {
+        packet.flags &= !FLAG_NO_ACK;
+        packet.nacks.clear();
+        packet.resend_delay = cmp::min(self.rtt.rto().as_secs(), 255) as u8;
+
+        let highest = match self.highest_received {
+            Some(highest) => highest,
+            None => {
+                packet.ack_through = 0;
+                packet.flags |= FLAG_NO_ACK;
+                return;
+            }
+        };
+        let nacks: Vec<u32> = (self.next_expected..highest)
+            .filter(|sequence| !self.out_of_order.contains_key(sequence))
+            .take(MAX_NACKS + 1)
+            .collect();
+
+        if nacks.len() <= MAX_NACKS {
+            packet.ack_through = highest;
+            packet.nacks = nacks;
+        } else if self.next_expected > 0 {
+            // Too many gaps to list: acknowledge only what arrived in order.
+            packet.ack_through = self.next_expected - 1;
+        } else {
+            packet.flags |= FLAG_NO_ACK;
+        }
+    }
+
+    /// Sets the acknowledgement fields of a packet about to be sent, and signs it if
+    /// it must be signed.
+    fn seal(&self, packet: &mut Packet) {
+        self.fill_ack(packet);
+        if needs_signature(packet) {
+            packet.sign_as(&self.signer, &self.from);
+        }
+    }
+
+    fn ack(&self) -> Packet {
+        let mut packet = Packet::new(self.remote_id, self.local_id, 0, 0);
+        self.fill_ack(&mut packet);
+        packet
+    }
+
+    fn retransmit(&mut self, sequence: u32, now: Instant) -> Option<Packet> {
+        let mut packet = {
+            let sent = self.unacked.get_mut(&sequence)?;
+            sent.transmissions += 1;
+            sent.sent_at = now;
+            sent.nacked = 0;
+            sent.packet.clone()
+        };
+        // A SYN sent before the peer's ID was known can now carry it.
+        packet.send_stream_id = self.remote_id;
+        self.seal(&mut packet);
+        self.retransmissions += 1;
+
+        Some(packet)
+    }
+
+    /// Returns the packets to send at `now`: retransmissions of packets that timed
+    /// out or were NACKed, new packets as the window allows, and an ack if nothing
+    /// else carries one.
+    pub fn poll(&mut self, now: Instant) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        if self.is_finished() {
+            if self.state == ConnectionState::Closed && self.ack_pending {
+                packets.push(self.ack());
+                self.ack_pending = false;
+            }
+            return packets;
+        }
+
+        let rto = self.rtt.rto();
+        let expired: Vec<u32> = self.unacked.iter()
+            .filter(|&(_, sent)| now >= sent.sent_at + rto)
+            .map(|(&sequence, _)| sequence)
+            .collect();
+        if !expired.is_empty() {
+            if expired.iter().any(|sequence| self.unacked[sequence].transmissions > self.config.max_retransmissions) {
+                self.state = ConnectionState::TimedOut;
+                return packets;
+            }
+            self.rtt.backoff();
+            self.threshold = cmp::max(self.window / 2, 2);
+            self.window = self.threshold;
+            self.window_acks = 0;
+        }
+
+        let mut resend: BTreeSet<u32> = expired.into_iter().collect();
+        resend.append(&mut self.fast_retransmits);
+        for sequence in resend {
+            packets.extend(self.retransmit(sequence, now));
+        }
+
+        let window = if self.state == ConnectionState::Connecting { 1 } else { self.window };
+        while (self.unacked.len() as u32) < window {
+            let closing = self.close_requested && !self.close_sent && self.send_buffer.len() <= self.max_payload;
+            if self.syn_sent && self.send_buffer.is_empty() && !closing {
+                break;
+            }
+
+            let mut flags = 0;
+            if !self.syn_sent {
+                flags |= FLAG_SYNCHRONIZE;
+            }
+            if closing {
+                flags |= FLAG_CLOSE;
+                self.close_sent = true;
+            }
+            let mut packet = Packet::new(self.remote_id, self.local_id, self.next_sequence, flags);
+            let length = cmp::min(self.send_buffer.len(), self.max_payload);
+            packet.payload = self.send_buffer.drain(..length).collect();
+            if !self.syn_sent {
+                packet.from = Some(self.from.clone());
+                packet.max_packet_size = Some(cmp::min(self.config.max_packet_size, u16::MAX as usize) as u16);
+                self.syn_sent = true;
+            }
+            self.seal(&mut packet);
+
+            self.unacked.insert(self.next_sequence, Sent { packet: packet.clone(), sent_at: now, transmissions: 1, nacked: 0 });
+            self.next_sequence += 1;
+            packets.push(packet);
+        }
+
+        if packets.is_empty() && self.ack_pending && self.remote_id != 0 {
+            packets.push(self.ack());
+        }
+        if !packets.is_empty() {
+            self.ack_pending = false;
+        }
+
+        packets
+    }
+}
@@ -0,0 +1,77 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum StreamingError {
+    Io(io::Error),
+    /// A packet is truncated or its options do not match its flags.
+    InvalidPacket,
+    /// A SYN is not signed by the destination it carries.
+    InvalidSignature,
+    /// The peer reset the connection.
+    Reset,
+    /// A packet was not acknowledged after the largest number of retransmissions,
+    /// or no SYN arrived while accepting.
+    TimedOut,
+}
+
+impl fmt::Display for StreamingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamingError::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred on a streaming connection: {}", err)
+            }
+            StreamingError::InvalidPacket => {
+                writeln!(f, "Error: A streaming packet is malformed.")
+            }
+            StreamingError::InvalidSignature => {
+                writeln!(f, "Error: A streaming SYN is not signed by the destination it carries.")
+            }
+            StreamingError::Reset => {
+                writeln!(f, "Error: The peer reset the streaming connection.")
+            }
+            StreamingError::TimedOut => {
+                writeln!(f, "Error: The streaming connection timed out.")
+            }
+        }
+    }
+}
+
+impl error::Error for StreamingError {
+    fn description(&self) -> &str {
+        match *self {
+            StreamingError::Io(_) => "An I/O error occurred on a streaming connection.",
+            StreamingError::InvalidPacket => "A streaming packet is malformed.",
+            StreamingError::InvalidSignature => "A streaming SYN is not signed by the destination it carries.",
+            StreamingError::Reset => "The peer reset the streaming connection.",
+            StreamingError::TimedOut => "The streaming connection timed out.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            StreamingError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for StreamingError {
+    fn from(err: io::Error) -> StreamingError {
+        StreamingError::Io(err)
+    }
+}
+
+impl From<StreamingError> for io::Error {
+    fn from(err: StreamingError) -> io::Error {
+        match err {
+            StreamingError::Io(err) => err,
+            StreamingError::InvalidPacket => io::Error::new(io::ErrorKind::InvalidData, "malformed streaming packet"),
+            StreamingError::InvalidSignature => io::Error::new(io::ErrorKind::InvalidData, "invalid streaming signature"),
+            StreamingError::Reset => io::Error::new(io::ErrorKind::ConnectionReset, "streaming connection reset"),
+            StreamingError::TimedOut => io::Error::new(io::ErrorKind::TimedOut, "streaming connection timed out")
+        }
+    }
+}
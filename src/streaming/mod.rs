@@ -0,0 +1,16 @@
+pub use self::error::StreamingError;
+pub use self::packet::{Packet, OfflineSignature, MIN_PACKET_LENGTH};
+pub use self::packet::{FLAG_SYNCHRONIZE, FLAG_CLOSE, FLAG_RESET, FLAG_SIGNATURE_INCLUDED, FLAG_SIGNATURE_REQUESTED};
+pub use self::packet::{FLAG_FROM_INCLUDED, FLAG_DELAY_REQUESTED, FLAG_MAX_PACKET_SIZE_INCLUDED, FLAG_PROFILE_INTERACTIVE};
+pub use self::packet::{FLAG_ECHO, FLAG_NO_ACK, FLAG_OFFLINE_SIGNATURE};
+pub use self::connection::{Connection, ConnectionState, RttEstimator, StreamConfig};
+pub use self::connection::{DEFAULT_MAX_PACKET_SIZE, FAST_RETRANSMIT_THRESHOLD};
+pub use self::stream::{Stream, StreamHandle, PacketChannel, POLL_INTERVAL};
+pub use self::socket::{ByteStream, pipe};
+
+
+mod error;
+mod packet;
+mod connection;
+mod stream;
+mod socket;
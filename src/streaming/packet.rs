@@ -0,0 +1,354 @@
+use common::{Destination, I2pDate, Signature, SignatureType, SigningPublicKey};
+use common::{SessionSigner, SignatureVerifier};
+use streaming::error::StreamingError;
+
+
+/// The packet starts a connection. Both the first packet and its reply carry it.
+pub const FLAG_SYNCHRONIZE: u16 = 0x0001;
+/// The sender will send no more data.
+pub const FLAG_CLOSE: u16 = 0x0002;
+/// The connection is aborted.
+pub const FLAG_RESET: u16 = 0x0004;
+pub const FLAG_SIGNATURE_INCLUDED: u16 = 0x0008;
+pub const FLAG_SIGNATURE_REQUESTED: u16 = 0x0010;
+pub const FLAG_FROM_INCLUDED: u16 = 0x0020;
+pub const FLAG_DELAY_REQUESTED: u16 = 0x0040;
+pub const FLAG_MAX_PACKET_SIZE_INCLUDED: u16 = 0x0080;
+pub const FLAG_PROFILE_INTERACTIVE: u16 = 0x0100;
+pub const FLAG_ECHO: u16 = 0x0200;
+/// The `ack_through` field is not valid.
+pub const FLAG_NO_ACK: u16 = 0x0400;
+pub const FLAG_OFFLINE_SIGNATURE: u16 = 0x0800;
+
+/// The flags whose presence follows from the options set on a packet.
+const OPTION_FLAGS: u16 = FLAG_DELAY_REQUESTED | FLAG_FROM_INCLUDED | FLAG_MAX_PACKET_SIZE_INCLUDED
+    | FLAG_OFFLINE_SIGNATURE | FLAG_SIGNATURE_INCLUDED;
+
+/// The length of a packet without NACKs, options or payload.
+pub const MIN_PACKET_LENGTH: usize = 22;
+
+/// An `OfflineSignature` lets a destination whose signing key is kept offline
+/// authorize a transient key until `expires`, in seconds since the UNIX epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfflineSignature {
+    pub expires: u32,
+    pub transient_key: SigningPublicKey,
+    /// The signature by the destination over the expiration, type and key.
+    pub signature: Vec<u8>
+}
+
+impl OfflineSignature {
+    /// Returns the bytes the destination signs.
+    pub fn signed_bytes(expires: u32, transient_key: &SigningPublicKey) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&expires.to_be_bytes());
+        bytes.extend_from_slice(&transient_key.signature_type().code().to_be_bytes());
+        bytes.extend_from_slice(transient_key.as_ref());
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = OfflineSignature::signed_bytes(self.expires, &self.transient_key);
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    /// Reads an offline signature made by a destination with the given signature
+    /// type, and returns it with its length.
+    pub fn from_bytes(bytes: &[u8], signature_type: SignatureType) -> Option<(OfflineSignature, usize)> {
+        if bytes.len() < 6 {
+            return None;
+        }
+        let expires = read_u32(&bytes[0..4]);
+        let transient_type = SignatureType::from_code(read_u16(&bytes[4..6]))?;
+        let key_end = 6 + transient_type.public_key_length();
+        let end = key_end + signature_type.signature_length();
+        if bytes.len() < end {
+            return None;
+        }
+        let transient_key = SigningPublicKey::from_bytes(transient_type, &bytes[6..key_end])?;
+
+        Some((OfflineSignature { expires, transient_key, signature: bytes[key_end..end].to_vec() }, end))
+    }
+
+    /// Verifies the signature of the destination and that the transient key has not
+    /// expired.
+    pub fn verify<V: SignatureVerifier>(&self, verifier: &V, destination: &Destination, now: I2pDate) -> bool {
+        if (self.expires as u64) * 1000 < now.to_u64() {
+            return false;
+        }
+        let (key, signature) = match destination.signing_public_key() {
+            Some(key) => match Signature::from_bytes(key.signature_type(), &self.signature) {
+                Some(signature) => (key, signature),
+                None => return false
+            },
+            None => return false
+        };
+
+        verifier.verify(&key, &OfflineSignature::signed_bytes(self.expires, &self.transient_key), &signature)
+    }
+}
+
+/// A `Packet` is the unit of the streaming protocol. Data packets are numbered
+/// from zero in each direction; a packet with sequence number zero and without
+/// `FLAG_SYNCHRONIZE` only acknowledges.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    /// The stream ID chosen by the recipient, zero in the first SYN.
+    pub send_stream_id: u32,
+    /// The stream ID chosen by the sender.
+    pub receive_stream_id: u32,
+    pub sequence_number: u32,
+    /// The highest sequence number received. Everything up to it is acknowledged
+    /// except the `nacks`.
+    pub ack_through: u32,
+    pub nacks: Vec<u32>,
+    /// How many seconds the sender waits before resending.
+    pub resend_delay: u8,
+    /// The flags that do not announce an option. The option flags are derived from
+    /// the options that are set.
+    pub flags: u16,
+    pub delay_requested: Option<u16>,
+    pub from: Option<Destination>,
+    pub max_packet_size: Option<u16>,
+    pub offline_signature: Option<OfflineSignature>,
+    pub signature: Option<Vec<u8>>,
+    pub payload: Vec<u8>
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 8) | (bytes[1] as u16)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+/// Returns the next `length` bytes of the options.
+fn take<'a>(options: &'a [u8], offset: &mut usize, length: usize) -> Result<&'a [u8], StreamingError> {
+    let field = options.get(*offset..*offset + length).ok_or(StreamingError::InvalidPacket)?;
+    *offset += length;
+
+    Ok(field)
+}
+
+impl Packet {
+    pub fn new(send_stream_id: u32, receive_stream_id: u32, sequence_number: u32, flags: u16) -> Packet {
+        Packet {
+            send_stream_id,
+            receive_stream_id,
+            sequence_number,
+            ack_through: 0,
+            nacks: Vec::new(),
+            resend_delay: 0,
+            flags: flags & !OPTION_FLAGS,
+            delay_requested: None,
+            from: None,
+            max_packet_size: None,
+            offline_signature: None,
+            signature: None,
+            payload: Vec::new()
+        }
+    }
+
+    /// Returns the flags as they are sent, including the option flags.
+    pub fn flags(&self) -> u16 {
+        let mut flags = self.flags & !OPTION_FLAGS;
+        for &(present, flag) in &[(self.delay_requested.is_some(), FLAG_DELAY_REQUESTED),
+                                  (self.from.is_some(), FLAG_FROM_INCLUDED),
+                                  (self.max_packet_size.is_some(), FLAG_MAX_PACKET_SIZE_INCLUDED),
+                                  (self.offline_signature.is_some(), FLAG_OFFLINE_SIGNATURE),
+                                  (self.signature.is_some(), FLAG_SIGNATURE_INCLUDED)] {
+            if present {
+                flags |= flag;
+            }
+        }
+
+        flags
+    }
+
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags() & flag != 0
+    }
+
+    pub fn is_syn(&self) -> bool {
+        self.has_flag(FLAG_SYNCHRONIZE)
+    }
+
+    /// Determines whether the packet only acknowledges, and carries no sequence
+    /// number of its own.
+    pub fn is_ack_only(&self) -> bool {
+        self.sequence_number == 0 && !self.is_syn()
+    }
+
+    /// Returns the signature type of the packet's signature: the transient key's
+    /// with an offline signature, and otherwise the sender's.
+    fn signature_type(&self) -> Option<SignatureType> {
+        match self.offline_signature {
+            Some(ref offline) => Some(offline.transient_key.signature_type()),
+            None => self.from.as_ref().and_then(|from| from.signature_type())
+        }
+    }
+
+    fn options(&self) -> Vec<u8> {
+        let mut options = Vec::new();
+        if let Some(delay) = self.delay_requested {
+            options.extend_from_slice(&delay.to_be_bytes());
+        }
+        if let Some(ref from) = self.from {
+            options.extend_from_slice(from.as_ref());
+        }
+        if let Some(size) = self.max_packet_size {
+            options.extend_from_slice(&size.to_be_bytes());
+        }
+        if let Some(ref offline) = self.offline_signature {
+            options.extend_from_slice(&offline.to_bytes());
+        }
+        if let Some(ref signature) = self.signature {
+            options.extend_from_slice(signature);
+        }
+
+        options
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let options = self.options();
+        let mut bytes = Vec::with_capacity(MIN_PACKET_LENGTH + 4 * self.nacks.len() + options.len() + self.payload.len());
+        bytes.extend_from_slice(&self.send_stream_id.to_be_bytes());
+        bytes.extend_from_slice(&self.receive_stream_id.to_be_bytes());
+        bytes.extend_from_slice(&self.sequence_number.to_be_bytes());
+        bytes.extend_from_slice(&self.ack_through.to_be_bytes());
+        bytes.push(self.nacks.len() as u8);
+        for nack in self.nacks.iter() {
+            bytes.extend_from_slice(&nack.to_be_bytes());
+        }
+        bytes.push(self.resend_delay);
+        bytes.extend_from_slice(&self.flags().to_be_bytes());
+        bytes.extend_from_slice(&(options.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&options);
+        bytes.extend_from_slice(&self.payload);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Packet, StreamingError> {
+        if bytes.len() < MIN_PACKET_LENGTH {
+            return Err(StreamingError::InvalidPacket);
+        }
+        let nack_count = bytes[16] as usize;
+        let offset = 17 + 4 * nack_count;
+        if bytes.len() < offset + 5 {
+            return Err(StreamingError::InvalidPacket);
+        }
+
+        let flags = read_u16(&bytes[offset + 1..offset + 3]);
+        let option_size = read_u16(&bytes[offset + 3..offset + 5]) as usize;
+        let options_start = offset + 5;
+        if bytes.len() < options_start + option_size {
+            return Err(StreamingError::InvalidPacket);
+        }
+
+        let mut packet = Packet::new(read_u32(&bytes[0..4]), read_u32(&bytes[4..8]), read_u32(&bytes[8..12]), flags);
+        packet.ack_through = read_u32(&bytes[12..16]);
+        packet.nacks = (0..nack_count).map(|i| read_u32(&bytes[17 + 4 * i..])).collect();
+        packet.resend_delay = bytes[offset];
+        packet.read_options(flags, &bytes[options_start..options_start + option_size])?;
+        packet.payload = bytes[options_start + option_size..].to_vec();
+
+        Ok(packet)
+    }
+
+    /// Reads the options in the order of their flags. The signature comes last and
+    /// takes the rest of the option data.
+    fn read_options(&mut self, flags: u16, options: &[u8]) -> Result<(), StreamingError> {
+        let mut offset = 0;
+
+        if flags & FLAG_DELAY_REQUESTED != 0 {
+            self.delay_requested = Some(read_u16(take(options, &mut offset, 2)?));
+        }
+        if flags & FLAG_FROM_INCLUDED != 0 {
+            let (from, length) = Destination::from_bytes(&options[offset..]).ok_or(StreamingError::InvalidPacket)?;
+            offset += length;
+            self.from = Some(from);
+        }
+        if flags & FLAG_MAX_PACKET_SIZE_INCLUDED != 0 {
+            self.max_packet_size = Some(read_u16(take(options, &mut offset, 2)?));
+        }
+        if flags & FLAG_OFFLINE_SIGNATURE != 0 {
+            let signature_type = self.from.as_ref()
+                .and_then(|from| from.signature_type())
+                .ok_or(StreamingError::InvalidPacket)?;
+            let (offline, length) = OfflineSignature::from_bytes(&options[offset..], signature_type)
+                .ok_or(StreamingError::InvalidPacket)?;
+            offset += length;
+            self.offline_signature = Some(offline);
+        }
+        if flags & FLAG_SIGNATURE_INCLUDED != 0 {
+            self.signature = Some(options[offset..].to_vec());
+        } else if offset != options.len() {
+            return Err(StreamingError::InvalidPacket);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the bytes a signature covers: the packet with the signature field
+    /// filled with zeros.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut packet = self.clone();
+        if let Some(ref mut signature) = packet.signature {
+            for byte in signature.iter_mut() {
+                *byte = 0x00;
+            }
+        }
+
+        packet.to_bytes()
+    }
+
+    /// Signs the packet with the key of `from`, or with the transient key of the
+    /// offline signature. Returns `false` if neither is set.
+    pub fn sign<S: SessionSigner>(&mut self, signer: &S) -> bool {
+        match self.signature_type() {
+            Some(signature_type) => self.sign_with_type(signer, signature_type),
+            None => false
+        }
+    }
+
+    /// Signs the packet as `from`, the destination of the connection, which packets
+    /// such as a CLOSE do not carry. An offline signature's transient key takes
+    /// precedence as in `sign`.
+    pub fn sign_as<S: SessionSigner>(&mut self, signer: &S, from: &Destination) -> bool {
+        match self.signature_type().or_else(|| from.signature_type()) {
+            Some(signature_type) => self.sign_with_type(signer, signature_type),
+            None => false
+        }
+    }
+
+    fn sign_with_type<S: SessionSigner>(&mut self, signer: &S, signature_type: SignatureType) -> bool {
+        let length = signature_type.signature_length();
+        self.signature = Some(vec![0x00; length]);
+        let signature = signer.sign(&self.signed_bytes());
+        self.signature = Some(signature.as_ref().to_vec());
+
+        true
+    }
+
+    /// Verifies the signature against `from`, which is the packet's own FROM or
+    /// the destination of the connection.
+    pub fn verify<V: SignatureVerifier>(&self, verifier: &V, from: &Destination, now: I2pDate) -> bool {
+        let key = match self.offline_signature {
+            Some(ref offline) if offline.verify(verifier, from, now) => offline.transient_key.clone(),
+            Some(_) => return false,
+            None => match from.signing_public_key() {
+                Some(key) => key,
+                None => return false
+            }
+        };
+        let signature = match self.signature.as_ref().and_then(|signature| Signature::from_bytes(key.signature_type(), signature)) {
+            Some(signature) => signature,
+            None => return false
+        };
+
+        verifier.verify(&key, &self.signed_bytes(), &signature)
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+
+/// The `ByteStream` trait is a streaming connection to a remote destination, as
+/// handed to the clients of the SAM bridge and of I2PTunnel.
+pub trait ByteStream: Read + Write + Send {
+    fn try_clone(&self) -> io::Result<Box<dyn ByteStream>>;
+
+    /// Closes the sending half, so the peer reads the end of the stream.
+    fn shutdown_write(&self) -> io::Result<()>;
+}
+
+impl ByteStream for TcpStream {
+    fn try_clone(&self) -> io::Result<Box<dyn ByteStream>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+}
+
+/// Copies between a local socket and a stream in both directions until both are
+/// done. When one side ends, the other's sending half is closed, so each side
+/// reads the end of the data before the connection closes. `pending` is sent to
+/// the stream first, such as data the client sent before the stream was opened.
+pub fn pipe(local: TcpStream, pending: Vec<u8>, mut stream: Box<dyn ByteStream>) -> io::Result<()> {
+    let mut upload_source = local.try_clone()?;
+    let mut upload_sink = stream.try_clone()?;
+    let upload = thread::spawn(move || {
+        let result = upload_sink.write_all(&pending)
+            .and_then(|_| io::copy(&mut upload_source, &mut upload_sink));
+        let _ = upload_sink.shutdown_write();
+        result
+    });
+
+    let mut local = local;
+    let download = io::copy(&mut stream, &mut local);
+    let _ = local.shutdown(Shutdown::Write);
+    let _ = upload.join();
+
+    download.map(|_| ())
+}
@@ -0,0 +1,317 @@
+use std::cmp;
+use std::io;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand;
+use rand::Rng;
+use common::{Destination, SessionSigner, SignatureVerifier};
+use streaming::connection::{Connection, ConnectionState, StreamConfig};
+use streaming::error::StreamingError;
+use streaming::packet::Packet;
+use streaming::socket::ByteStream;
+
+
+/// How long a stream waits for a packet before it polls its connection again.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A `PacketChannel` carries the packets of one stream to the peer, such as
+/// the I2CP messages between two destinations. It may lose, duplicate or reorder
+/// packets.
+pub trait PacketChannel {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Waits up to `timeout` for a packet, and returns `None` if none arrived.
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A `Stream` runs a `Connection` over a `PacketChannel`, and reads and writes
+/// like a TCP stream. It only makes progress while one of its methods runs.
+pub struct Stream<C, S, V> {
+    channel: C,
+    connection: Connection<S, V>
+}
+
+impl<C, S, V> Stream<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    /// Opens a stream from `from`, whose packets `signer` signs, and waits until
+    /// the peer answers its SYN.
+    pub fn connect(channel: C, from: Destination, signer: S, verifier: V, config: StreamConfig)
+        -> Result<Stream<C, S, V>, StreamingError>
+    {
+        let local_id = loop {
+            let id = rand::thread_rng().gen::<u32>();
+            if id != 0 {
+                break id;
+            }
+        };
+        let mut stream = Stream {
+            channel,
+            connection: Connection::connect(local_id, from, signer, verifier, config)
+        };
+        while stream.connection.state() == ConnectionState::Connecting {
+            stream.pump()?;
+        }
+
+        Ok(stream)
+    }
+
+    /// Waits up to `timeout` for a signed SYN and accepts the stream it opens.
+    /// SYNs that do not verify are ignored.
+    pub fn accept(mut channel: C, from: Destination, signer: S, verifier: V, config: StreamConfig, timeout: Duration)
+        -> Result<Stream<C, S, V>, StreamingError>
+    {
+        let deadline = Instant::now() + timeout;
+        let syn = loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(StreamingError::TimedOut);
+            }
+            if let Some(bytes) = channel.receive(cmp::min(POLL_INTERVAL, deadline - now))? {
+                match Packet::from_bytes(&bytes) {
+                    Ok(packet) if packet.send_stream_id == 0 && Connection::<S, V>::is_valid_syn(&packet, &verifier) => {
+                        break packet;
+                    }
+                    _ => {}
+                }
+            }
+        };
+
+        let local_id = loop {
+            let id = rand::thread_rng().gen::<u32>();
+            if id != 0 && id != syn.receive_stream_id {
+                break id;
+            }
+        };
+        let mut stream = Stream {
+            channel,
+            connection: Connection::accept(local_id, syn, from, signer, verifier, config, Instant::now())?
+        };
+        stream.send_pending()?;
+
+        Ok(stream)
+    }
+
+    /// Returns the destination the peer announced in its SYN.
+    pub fn peer(&self) -> Option<&Destination> {
+        self.connection.peer()
+    }
+
+    pub fn connection(&self) -> &Connection<S, V> {
+        &self.connection
+    }
+
+    /// Sends our CLOSE after the data written, and waits until it is
+    /// acknowledged. The peer's data can still be read.
+    pub fn close(&mut self) -> Result<(), StreamingError> {
+        self.connection.close();
+        while !self.connection.is_flushed() {
+            self.pump()?;
+        }
+
+        Ok(())
+    }
+
+    /// Aborts the stream.
+    pub fn reset(&mut self) -> Result<(), StreamingError> {
+        if let Some(packet) = self.connection.reset() {
+            self.channel.send(&packet.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), StreamingError> {
+        match self.connection.state() {
+            ConnectionState::Reset => Err(StreamingError::Reset),
+            ConnectionState::TimedOut => Err(StreamingError::TimedOut),
+            _ => Ok(())
+        }
+    }
+
+    fn send_pending(&mut self) -> Result<(), StreamingError> {
+        for packet in self.connection.poll(Instant::now()) {
+            self.channel.send(&packet.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, bytes: &[u8]) {
+        let packet = match Packet::from_bytes(bytes) {
+            Ok(packet) => packet,
+            Err(_) => return
+        };
+        // A SYN the peer resends before it learned our ID is still ours.
+        let resent_syn = packet.is_syn() && packet.send_stream_id == 0
+            && packet.receive_stream_id == self.connection.remote_id();
+        if packet.send_stream_id == self.connection.local_id() || resent_syn {
+            self.connection.receive(packet, Instant::now());
+        }
+    }
+
+    /// Sends what the connection has to send, then handles the packets that arrive
+    /// until the next retransmission is due or the poll interval has passed.
+    fn pump(&mut self) -> Result<(), StreamingError> {
+        self.send_pending()?;
+
+        let now = Instant::now();
+        let wait = match self.connection.next_timeout() {
+            Some(timeout) => cmp::min(POLL_INTERVAL, timeout.saturating_duration_since(now)),
+            None => POLL_INTERVAL
+        };
+        if let Some(bytes) = self.channel.receive(wait)? {
+            self.handle(&bytes);
+            while let Some(bytes) = self.channel.receive(Duration::from_millis(0))? {
+                self.handle(&bytes);
+            }
+        }
+
+        self.send_pending()?;
+        self.check()
+    }
+
+    /// Reads the data that has arrived. Returns `None` if there is none yet and the
+    /// stream must be pumped.
+    fn try_read(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        if self.connection.available() > 0 {
+            let length = self.connection.read(buf);
+            self.send_pending()?;
+            return Ok(Some(length));
+        }
+        if self.connection.is_eof() {
+            self.check()?;
+            self.send_pending()?;
+            return Ok(Some(0));
+        }
+
+        Ok(None)
+    }
+
+    /// Buffers data to send. Returns `None` if the send buffer is full and the
+    /// stream must be pumped.
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<Option<usize>> {
+        if self.connection.is_write_closed() {
+            self.check()?;
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The stream is closed for writing"));
+        }
+        let length = self.connection.write(buf);
+        if length > 0 || buf.is_empty() {
+            self.send_pending()?;
+            return Ok(Some(length));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<C, S, V> Read for Stream<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(length) = self.try_read(buf)? {
+                return Ok(length);
+            }
+            self.pump()?;
+        }
+    }
+}
+
+impl<C, S, V> Write for Stream<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            if let Some(length) = self.try_write(buf)? {
+                return Ok(length);
+            }
+            self.pump()?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.connection.is_flushed() {
+            self.pump()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A `StreamHandle` shares a `Stream` between the threads that read and write it,
+/// so the SAM bridge and I2PTunnel can use it as a `ByteStream`. A blocked call
+/// holds the stream for at most one poll interval at a time, so a reader waiting
+/// for data does not hold up a writer.
+pub struct StreamHandle<C, S, V> {
+    stream: Arc<Mutex<Stream<C, S, V>>>
+}
+
+impl<C, S, V> StreamHandle<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    pub fn new(stream: Stream<C, S, V>) -> StreamHandle<C, S, V> {
+        StreamHandle {
+            stream: Arc::new(Mutex::new(stream))
+        }
+    }
+}
+
+impl<C, S, V> Clone for StreamHandle<C, S, V> {
+    fn clone(&self) -> StreamHandle<C, S, V> {
+        StreamHandle {
+            stream: self.stream.clone()
+        }
+    }
+}
+
+impl<C, S, V> Read for StreamHandle<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut stream = self.stream.lock().unwrap();
+            if let Some(length) = stream.try_read(buf)? {
+                return Ok(length);
+            }
+            stream.pump()?;
+        }
+    }
+}
+
+impl<C, S, V> Write for StreamHandle<C, S, V> where C: PacketChannel, S: SessionSigner, V: SignatureVerifier {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut stream = self.stream.lock().unwrap();
+            if let Some(length) = stream.try_write(buf)? {
+                return Ok(length);
+            }
+            stream.pump()?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            let mut stream = self.stream.lock().unwrap();
+            if stream.connection.is_flushed() {
+                return Ok(());
+            }
+            stream.pump()?;
+        }
+    }
+}
+
+impl<C, S, V> ByteStream for StreamHandle<C, S, V>
+    where C: PacketChannel + Send + 'static, S: SessionSigner + Send + 'static, V: SignatureVerifier + Send + 'static
+{
+    fn try_clone(&self) -> io::Result<Box<dyn ByteStream>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    /// Sends our CLOSE once the data written has been sent, without waiting for it
+    /// to be acknowledged; the threads still reading or flushing see to that.
+    fn shutdown_write(&self) -> io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        stream.connection.close();
+        stream.send_pending()?;
+
+        Ok(())
+    }
+}
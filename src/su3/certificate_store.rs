@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use rsa::RsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use x509_cert::Certificate;
+use x509_cert::der::{DecodePem, Encode};
+use common::{SignatureType, SigningPublicKey};
+use su3::error::Su3Error;
+use su3::verifier::RSA_PUBLIC_EXPONENT;
+
+
+const CERTIFICATE_SUFFIX: &str = ".crt";
+
+/// Reads the RSA signing key from a PEM encoded X.509 certificate, such as the
+/// reseed certificates shipped with the Java router. The signature type follows
+/// from the key size.
+pub fn signing_key_from_certificate(pem: &str) -> Result<SigningPublicKey, Su3Error> {
+    let invalid = |reason: &str| Su3Error::InvalidCertificate(reason.to_string());
+    let certificate = Certificate::from_pem(pem.as_bytes()).map_err(|_| invalid("not a PEM encoded X.509 certificate"))?;
+    let public_key_info = certificate.tbs_certificate.subject_public_key_info.to_der()
+        .map_err(|_| invalid("the public key cannot be encoded"))?;
+    let public_key = RsaPublicKey::from_public_key_der(&public_key_info).map_err(|_| invalid("not an RSA key"))?;
+    if *public_key.e() != RSA_PUBLIC_EXPONENT.into() {
+        return Err(invalid("the public exponent is not 65537"));
+    }
+
+    let signature_type = match public_key.size() {
+        256 => SignatureType::RSA_SHA256_2048,
+        384 => SignatureType::RSA_SHA384_3072,
+        512 => SignatureType::RSA_SHA512_4096,
+        _ => return Err(invalid("unsupported RSA key size"))
+    };
+    let mut modulus = public_key.n().to_bytes_be();
+    while modulus.len() < public_key.size() {
+        modulus.insert(0, 0x00);
+    }
+
+    SigningPublicKey::from_bytes(signature_type, &modulus).ok_or_else(|| invalid("unsupported RSA key size"))
+}
+
+
+/// A `CertificateStore` maps the signer IDs found in SU3 files, such as
+/// `zzz@mail.i2p`, to the public keys trusted to sign them.
+#[derive(Clone, Debug, Default)]
+pub struct CertificateStore {
+    signers: HashMap<String, SigningPublicKey>
+}
+
+impl CertificateStore {
+    pub fn new() -> CertificateStore {
+        CertificateStore {
+            signers: HashMap::new()
+        }
+    }
+
+    /// Trusts `key` for files signed by `signer_id`, replacing any previous key.
+    pub fn insert(&mut self, signer_id: &str, key: SigningPublicKey) {
+        self.signers.insert(signer_id.to_string(), key);
+    }
+
+    /// Trusts the key of a PEM encoded X.509 certificate for files signed by `signer_id`.
+    pub fn insert_certificate(&mut self, signer_id: &str, pem: &str) -> Result<(), Su3Error> {
+        let key = signing_key_from_certificate(pem)?;
+        self.insert(signer_id, key);
+
+        Ok(())
+    }
+
+    /// Loads every `.crt` file in `directory`. Each file is named after its signer ID
+    /// with `@` written as `_at_`, as in `zzz_at_mail.i2p.crt`. Returns the number
+    /// of certificates loaded.
+    pub fn load_directory<P: AsRef<Path>>(&mut self, directory: P) -> Result<usize, Su3Error> {
+        let mut loaded = 0;
+        for file in fs::read_dir(directory)? {
+            let path = file?.path();
+            let signer_id = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.ends_with(CERTIFICATE_SUFFIX) => {
+                    name[..name.len() - CERTIFICATE_SUFFIX.len()].replace("_at_", "@")
+                }
+                _ => continue
+            };
+
+            let pem = fs::read_to_string(&path)?;
+            let key = signing_key_from_certificate(&pem)
+                .map_err(|_| Su3Error::InvalidCertificate(path.display().to_string()))?;
+            self.insert(&signer_id, key);
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    pub fn remove(&mut self, signer_id: &str) -> Option<SigningPublicKey> {
+        self.signers.remove(signer_id)
+    }
+
+    pub fn get(&self, signer_id: &str) -> Option<&SigningPublicKey> {
+        self.signers.get(signer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signers.is_empty()
+    }
+}
@@ -0,0 +1,113 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum Su3Error {
+    Io(io::Error),
+    /// The file is shorter than its header says. The first field is the needed number
+    /// of bytes, the second field is the available amount of bytes.
+    Truncated(usize, usize),
+    BadMagicNumber,
+    UnsupportedFormatVersion(u8),
+    UnknownSignatureType(u16),
+    SignatureLengthMismatch,
+    VersionTooShort(usize),
+    InvalidUtf8,
+    UnknownSigner(String),
+    SignatureTypeMismatch,
+    InvalidSignature,
+    UnexpectedContent,
+    InvalidZip,
+    UnsupportedCompression(u16),
+    /// A signer certificate could not be read. The field says which or why.
+    InvalidCertificate(String),
+}
+
+impl fmt::Display for Su3Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Su3Error::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred reading an SU3 file: {}", err)
+            }
+            Su3Error::Truncated(need, have) => {
+                writeln!(f, "Error: The SU3 file is truncated. Need: {} bytes; Have: {} bytes.", need, have)
+            }
+            Su3Error::BadMagicNumber => {
+                writeln!(f, "Error: The file does not start with the SU3 magic number.")
+            }
+            Su3Error::UnsupportedFormatVersion(version) => {
+                writeln!(f, "Error: Unsupported SU3 file format version: {}.", version)
+            }
+            Su3Error::UnknownSignatureType(code) => {
+                writeln!(f, "Error: Unknown SU3 signature type: {}.", code)
+            }
+            Su3Error::SignatureLengthMismatch => {
+                writeln!(f, "Error: The SU3 signature length does not match its signature type.")
+            }
+            Su3Error::VersionTooShort(length) => {
+                writeln!(f, "Error: The SU3 version field is {} bytes; it must be at least 16.", length)
+            }
+            Su3Error::InvalidUtf8 => {
+                writeln!(f, "Error: The SU3 version or signer ID is not valid UTF-8.")
+            }
+            Su3Error::UnknownSigner(ref signer) => {
+                writeln!(f, "Error: No certificate for the SU3 signer: {}.", signer)
+            }
+            Su3Error::SignatureTypeMismatch => {
+                writeln!(f, "Error: The signer certificate does not match the SU3 signature type.")
+            }
+            Su3Error::InvalidSignature => {
+                writeln!(f, "Error: The SU3 signature is invalid.")
+            }
+            Su3Error::UnexpectedContent => {
+                writeln!(f, "Error: The SU3 file does not contain a zip of reseed data.")
+            }
+            Su3Error::InvalidZip => {
+                writeln!(f, "Error: The SU3 content is not a valid zip file.")
+            }
+            Su3Error::UnsupportedCompression(method) => {
+                writeln!(f, "Error: Unsupported zip compression method: {}.", method)
+            }
+            Su3Error::InvalidCertificate(ref reason) => {
+                writeln!(f, "Error: Invalid signer certificate: {}.", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for Su3Error {
+    fn description(&self) -> &str {
+        match *self {
+            Su3Error::Io(_) => "An I/O error occurred reading an SU3 file.",
+            Su3Error::Truncated(_, _) => "The SU3 file is truncated.",
+            Su3Error::BadMagicNumber => "The file does not start with the SU3 magic number.",
+            Su3Error::UnsupportedFormatVersion(_) => "Unsupported SU3 file format version.",
+            Su3Error::UnknownSignatureType(_) => "Unknown SU3 signature type.",
+            Su3Error::SignatureLengthMismatch => "The SU3 signature length does not match its signature type.",
+            Su3Error::VersionTooShort(_) => "The SU3 version field is shorter than 16 bytes.",
+            Su3Error::InvalidUtf8 => "The SU3 version or signer ID is not valid UTF-8.",
+            Su3Error::UnknownSigner(_) => "No certificate for the SU3 signer.",
+            Su3Error::SignatureTypeMismatch => "The signer certificate does not match the SU3 signature type.",
+            Su3Error::InvalidSignature => "The SU3 signature is invalid.",
+            Su3Error::UnexpectedContent => "The SU3 file does not contain a zip of reseed data.",
+            Su3Error::InvalidZip => "The SU3 content is not a valid zip file.",
+            Su3Error::UnsupportedCompression(_) => "Unsupported zip compression method.",
+            Su3Error::InvalidCertificate(_) => "Invalid signer certificate.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Su3Error::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Su3Error {
+    fn from(err: io::Error) -> Su3Error {
+        Su3Error::Io(err)
+    }
+}
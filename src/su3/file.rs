@@ -0,0 +1,248 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+use common::{Signature, SignatureType, SignatureVerifier};
+use netdb::NetDbEntry;
+use su3::certificate_store::CertificateStore;
+use su3::error::Su3Error;
+use su3::zip;
+use su3::zip::ZipEntry;
+
+
+const SU3_MAGIC_NUMBER: &[u8] = b"I2Psu3";
+const SU3_FORMAT_VERSION: u8 = 0;
+const SU3_HEADER_LENGTH: usize = 40;
+const SU3_MIN_VERSION_LENGTH: usize = 16;
+
+const ROUTER_INFO_PREFIX: &str = "routerInfo-";
+const ROUTER_INFO_SUFFIX: &str = ".dat";
+
+/// The format of the content of an SU3 file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Zip,
+    Xml,
+    Html,
+    XmlGz,
+    TxtGz,
+    Dmg,
+    Exe,
+    Unknown(u8)
+}
+
+impl FileType {
+    pub fn from_code(code: u8) -> FileType {
+        match code {
+            0 => FileType::Zip,
+            1 => FileType::Xml,
+            2 => FileType::Html,
+            3 => FileType::XmlGz,
+            4 => FileType::TxtGz,
+            5 => FileType::Dmg,
+            6 => FileType::Exe,
+            _ => FileType::Unknown(code)
+        }
+    }
+}
+
+/// What the content of an SU3 file is used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContentType {
+    Unknown,
+    RouterUpdate,
+    Plugin,
+    Reseed,
+    News,
+    Blocklist,
+    Other(u8)
+}
+
+impl ContentType {
+    pub fn from_code(code: u8) -> ContentType {
+        match code {
+            0 => ContentType::Unknown,
+            1 => ContentType::RouterUpdate,
+            2 => ContentType::Plugin,
+            3 => ContentType::Reseed,
+            4 => ContentType::News,
+            5 => ContentType::Blocklist,
+            _ => ContentType::Other(code)
+        }
+    }
+}
+
+/// An `Su3File` is a signed container used for reseed bundles, router updates and
+/// news feeds. The signature covers every byte of the file before it.
+#[derive(Clone, Debug)]
+pub struct Su3File {
+    file_type: FileType,
+    content_type: ContentType,
+    version: String,
+    signer_id: String,
+    signature: Signature,
+    content_start: usize,
+    content_end: usize,
+    data: Vec<u8>
+}
+
+fn field(bytes: &[u8], start: usize, length: usize) -> Result<&[u8], Su3Error> {
+    let end = start.saturating_add(length);
+    match bytes.get(start..end) {
+        Some(field) => Ok(field),
+        None => Err(Su3Error::Truncated(end, bytes.len()))
+    }
+}
+
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |value, byte| (value << 8) | (*byte as u64))
+}
+
+impl Su3File {
+    /// Parses an SU3 file. The signature is not checked; call `verify` for that.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Su3File, Su3Error> {
+        let header = field(&data, 0, SU3_HEADER_LENGTH)?;
+        if &header[0..6] != SU3_MAGIC_NUMBER {
+            return Err(Su3Error::BadMagicNumber);
+        }
+        if header[7] != SU3_FORMAT_VERSION {
+            return Err(Su3Error::UnsupportedFormatVersion(header[7]));
+        }
+
+        let type_code = big_endian(&header[8..10]) as u16;
+        let signature_type = match SignatureType::from_code(type_code) {
+            Some(signature_type) => signature_type,
+            None => return Err(Su3Error::UnknownSignatureType(type_code))
+        };
+        let signature_length = big_endian(&header[10..12]) as usize;
+        let version_length = header[13] as usize;
+        let signer_id_length = header[15] as usize;
+        let content_length = big_endian(&header[16..24]) as usize;
+        let file_type = FileType::from_code(header[25]);
+        let content_type = ContentType::from_code(header[27]);
+
+        if version_length < SU3_MIN_VERSION_LENGTH {
+            return Err(Su3Error::VersionTooShort(version_length));
+        }
+
+        let version_bytes = field(&data, SU3_HEADER_LENGTH, version_length)?;
+        let version = match str::from_utf8(version_bytes) {
+            Ok(version) => version.trim_end_matches('\0').to_string(),
+            Err(_) => return Err(Su3Error::InvalidUtf8)
+        };
+        let signer_id_start = SU3_HEADER_LENGTH + version_length;
+        let signer_id = match str::from_utf8(field(&data, signer_id_start, signer_id_length)?) {
+            Ok(signer_id) => signer_id.to_string(),
+            Err(_) => return Err(Su3Error::InvalidUtf8)
+        };
+
+        let content_start = signer_id_start + signer_id_length;
+        let content_end = content_start.saturating_add(content_length);
+        let signature_bytes = field(&data, content_end, signature_length)?;
+        let signature = match Signature::from_bytes(signature_type, signature_bytes) {
+            Some(signature) => signature,
+            None => return Err(Su3Error::SignatureLengthMismatch)
+        };
+
+        Ok(Su3File {
+            file_type,
+            content_type,
+            version,
+            signer_id,
+            signature,
+            content_start,
+            content_end,
+            data
+        })
+    }
+
+    /// Reads and parses an SU3 file from disk.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Su3File, Su3Error> {
+        let mut data = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut data)?;
+
+        Su3File::from_bytes(data)
+    }
+
+    pub fn signature_type(&self) -> SignatureType {
+        self.signature.signature_type()
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    pub fn version(&self) -> &str {
+        self.version.as_str()
+    }
+
+    pub fn signer_id(&self) -> &str {
+        self.signer_id.as_str()
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.data[self.content_start..self.content_end]
+    }
+
+    /// Returns the bytes covered by the signature: the header, version, signer ID
+    /// and content.
+    pub fn signed_bytes(&self) -> &[u8] {
+        &self.data[0..self.content_end]
+    }
+
+    /// Checks the signature against the key trusted for the signer ID.
+    pub fn verify<V>(&self, certificates: &CertificateStore, verifier: &V) -> Result<(), Su3Error>
+        where V: SignatureVerifier
+    {
+        let key = match certificates.get(&self.signer_id) {
+            Some(key) => key,
+            None => return Err(Su3Error::UnknownSigner(self.signer_id.clone()))
+        };
+        if key.signature_type() != self.signature_type() {
+            return Err(Su3Error::SignatureTypeMismatch);
+        }
+
+        if verifier.verify(key, self.signed_bytes(), &self.signature) {
+            Ok(())
+        } else {
+            Err(Su3Error::InvalidSignature)
+        }
+    }
+
+    /// Extracts the files of a zipped SU3 file.
+    pub fn zip_entries(&self) -> Result<Vec<ZipEntry>, Su3Error> {
+        if self.file_type != FileType::Zip {
+            return Err(Su3Error::UnexpectedContent);
+        }
+
+        zip::read_zip(self.content())
+    }
+
+    /// Extracts and parses the `routerInfo-*.dat` files of a reseed bundle. Files
+    /// that do not parse are skipped; they are validated again when stored in the
+    /// network database.
+    pub fn router_infos<T: NetDbEntry>(&self) -> Result<Vec<T>, Su3Error> {
+        if self.content_type != ContentType::Reseed {
+            return Err(Su3Error::UnexpectedContent);
+        }
+
+        let router_infos = self.zip_entries()?
+            .into_iter()
+            .filter(|entry| {
+                let name = entry.name.rsplit('/').next().unwrap_or("");
+                name.starts_with(ROUTER_INFO_PREFIX) && name.ends_with(ROUTER_INFO_SUFFIX)
+            })
+            .filter_map(|entry| T::from_bytes(&entry.data))
+            .collect();
+
+        Ok(router_infos)
+    }
+}
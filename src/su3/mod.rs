@@ -0,0 +1,12 @@
+pub use self::error::Su3Error;
+pub use self::file::{Su3File, FileType, ContentType};
+pub use self::certificate_store::{CertificateStore, signing_key_from_certificate};
+pub use self::verifier::{RsaVerifier, RSA_PUBLIC_EXPONENT};
+pub use self::zip::ZipEntry;
+
+
+mod error;
+mod file;
+mod certificate_store;
+mod zip;
+mod verifier;
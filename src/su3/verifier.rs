@@ -0,0 +1,33 @@
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use common::{Signature, SignatureType, SignatureVerifier, SigningPublicKey};
+
+
+/// The public exponent of every RSA signing key. I2P keys carry only the modulus.
+pub const RSA_PUBLIC_EXPONENT: u32 = 65_537;
+
+/// An `RsaVerifier` checks the PKCS #1 v1.5 RSA signatures that reseed servers and
+/// router updates are signed with, `RSA_SHA512_4096` in practice. Signatures of any
+/// other type are rejected.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RsaVerifier;
+
+impl SignatureVerifier for RsaVerifier {
+    fn verify(&self, key: &SigningPublicKey, message: &[u8], signature: &Signature) -> bool {
+        if signature.signature_type() != key.signature_type() {
+            return false;
+        }
+        let (scheme, hashed) = match key.signature_type() {
+            SignatureType::RSA_SHA256_2048 => (Pkcs1v15Sign::new::<Sha256>(), Sha256::digest(message).to_vec()),
+            SignatureType::RSA_SHA384_3072 => (Pkcs1v15Sign::new::<Sha384>(), Sha384::digest(message).to_vec()),
+            SignatureType::RSA_SHA512_4096 => (Pkcs1v15Sign::new::<Sha512>(), Sha512::digest(message).to_vec()),
+            _ => return false
+        };
+        let public_key = match RsaPublicKey::new(BigUint::from_bytes_be(key.as_ref()), BigUint::from(RSA_PUBLIC_EXPONENT)) {
+            Ok(public_key) => public_key,
+            Err(_) => return false
+        };
+
+        public_key.verify(scheme, &hashed, signature.as_ref()).is_ok()
+    }
+}
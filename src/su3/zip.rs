@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::io::Read;
+use flate2::Crc;
+use flate2::read::DeflateDecoder;
+use su3::error::Su3Error;
+
+
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const END_OF_CENTRAL_DIRECTORY_LENGTH: usize = 22;
+const CENTRAL_DIRECTORY_HEADER_LENGTH: usize = 46;
+const LOCAL_HEADER_LENGTH: usize = 30;
+const MAX_COMMENT_LENGTH: usize = 0xFFFF;
+
+/// The largest file extracted from an archive. Reseed bundles hold router infos of a
+/// few kilobytes each, so anything near this size is not a bundle.
+const MAX_ENTRY_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The most data extracted from an archive, over all of its files.
+const MAX_TOTAL_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The most files in an archive. Reseed bundles hold a hundred or so.
+const MAX_ENTRIES: usize = 1024;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// A file extracted from a zip archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub data: Vec<u8>
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, Su3Error> {
+    match bytes.get(offset..offset + 2) {
+        Some(field) => Ok((field[0] as u16) | ((field[1] as u16) << 8)),
+        None => Err(Su3Error::InvalidZip)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Su3Error> {
+    match bytes.get(offset..offset + 4) {
+        Some(field) => {
+            Ok((field[0] as u32) | ((field[1] as u32) << 8) | ((field[2] as u32) << 16) | ((field[3] as u32) << 24))
+        }
+        None => Err(Su3Error::InvalidZip)
+    }
+}
+
+fn find_end_of_central_directory(bytes: &[u8]) -> Result<usize, Su3Error> {
+    if bytes.len() < END_OF_CENTRAL_DIRECTORY_LENGTH {
+        return Err(Su3Error::InvalidZip);
+    }
+
+    let last = bytes.len() - END_OF_CENTRAL_DIRECTORY_LENGTH;
+    let first = last.saturating_sub(MAX_COMMENT_LENGTH);
+    for offset in (first..last + 1).rev() {
+        if read_u32(bytes, offset)? == END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+            return Ok(offset);
+        }
+    }
+
+    Err(Su3Error::InvalidZip)
+}
+
+/// Extracts every file from a zip archive held in memory. Only stored and deflated
+/// entries are supported, which covers the archives produced by reseed servers.
+/// Archives with too many files, too much data in total, or several directory
+/// entries for the same file data are refused.
+pub fn read_zip(bytes: &[u8]) -> Result<Vec<ZipEntry>, Su3Error> {
+    let end = find_end_of_central_directory(bytes)?;
+    let entry_count = read_u16(bytes, end + 10)? as usize;
+    let mut offset = read_u32(bytes, end + 16)? as usize;
+    if entry_count > MAX_ENTRIES {
+        return Err(Su3Error::InvalidZip);
+    }
+
+    let mut entries = Vec::with_capacity(entry_count);
+    let mut local_headers = HashSet::new();
+    let mut total_length = 0;
+    for _ in 0..entry_count {
+        if read_u32(bytes, offset)? != CENTRAL_DIRECTORY_SIGNATURE {
+            return Err(Su3Error::InvalidZip);
+        }
+        let method = read_u16(bytes, offset + 10)?;
+        let crc = read_u32(bytes, offset + 16)?;
+        let compressed_length = read_u32(bytes, offset + 20)? as usize;
+        let length = read_u32(bytes, offset + 24)? as usize;
+        let name_length = read_u16(bytes, offset + 28)? as usize;
+        let extra_length = read_u16(bytes, offset + 30)? as usize;
+        let comment_length = read_u16(bytes, offset + 32)? as usize;
+        let local_header = read_u32(bytes, offset + 42)? as usize;
+        total_length += length;
+        if length > MAX_ENTRY_LENGTH || total_length > MAX_TOTAL_LENGTH || !local_headers.insert(local_header) {
+            return Err(Su3Error::InvalidZip);
+        }
+
+        let name_start = offset + CENTRAL_DIRECTORY_HEADER_LENGTH;
+        let name = match bytes.get(name_start..name_start + name_length) {
+            Some(name) => String::from_utf8_lossy(name).into_owned(),
+            None => return Err(Su3Error::InvalidZip)
+        };
+        offset = name_start + name_length + extra_length + comment_length;
+
+        if read_u32(bytes, local_header)? != LOCAL_HEADER_SIGNATURE {
+            return Err(Su3Error::InvalidZip);
+        }
+        let data_start = local_header + LOCAL_HEADER_LENGTH
+            + read_u16(bytes, local_header + 26)? as usize
+            + read_u16(bytes, local_header + 28)? as usize;
+        let compressed = match bytes.get(data_start..data_start + compressed_length) {
+            Some(compressed) => compressed,
+            None => return Err(Su3Error::InvalidZip)
+        };
+
+        let data = match method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => {
+                // Reading one byte past the declared length is enough to tell that the
+                // entry inflates to more than it claims.
+                let mut data = Vec::with_capacity(length);
+                if DeflateDecoder::new(compressed).take(length as u64 + 1).read_to_end(&mut data).is_err() {
+                    return Err(Su3Error::InvalidZip);
+                }
+                data
+            }
+            _ => return Err(Su3Error::UnsupportedCompression(method))
+        };
+
+        let mut checksum = Crc::new();
+        checksum.update(&data);
+        if data.len() != length || checksum.sum() != crc {
+            return Err(Su3Error::InvalidZip);
+        }
+
+        // Directories carry no data.
+        if !name.ends_with('/') {
+            entries.push(ZipEntry { name, data });
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,53 @@
+use common::{Destination, SignatureType, SigningPublicKey, ToI2pBase64, I2P_DESTINATION_MIN_LENGTH};
+
+
+#[test]
+fn test_ed25519_destination_should_carry_a_key_certificate() {
+    let key = SigningPublicKey::from_bytes(SignatureType::EdDSA_SHA512_Ed25519, &[0x07; 32]).unwrap();
+    let destination = Destination::new(&[0x01; 256], &key);
+
+    assert_eq!(destination.len(), I2P_DESTINATION_MIN_LENGTH + 4);
+    assert_eq!(&destination.as_ref()[384..391], &[0x05, 0x00, 0x04, 0x00, 0x07, 0x00, 0x00]);
+    assert_eq!(destination.signature_type(), Some(SignatureType::EdDSA_SHA512_Ed25519));
+    assert_eq!(destination.signing_public_key(), Some(key));
+    assert_eq!(destination.public_key(), &[0x01; 256][..]);
+}
+
+#[test]
+fn test_long_signing_keys_should_continue_in_the_certificate() {
+    let key = SigningPublicKey::from_bytes(SignatureType::ECDSA_SHA512_P521, &[0x09; 132]).unwrap();
+    let destination = Destination::new(&[0x01; 256], &key);
+
+    assert_eq!(destination.len(), I2P_DESTINATION_MIN_LENGTH + 8);
+    assert_eq!(destination.signing_public_key(), Some(key));
+}
+
+#[test]
+fn test_destination_should_parse_from_the_front_of_a_buffer() {
+    let key = SigningPublicKey::from_bytes(SignatureType::DSA_SHA1, &[0x03; 128]).unwrap();
+    let destination = Destination::new(&[0x01; 256], &key);
+    let mut bytes = destination.as_ref().to_vec();
+    bytes.extend_from_slice(&[0xAA; 10]);
+
+    assert_eq!(Destination::from_bytes(&bytes), Some((destination.clone(), I2P_DESTINATION_MIN_LENGTH)));
+    assert_eq!(destination.signature_type(), Some(SignatureType::DSA_SHA1));
+    assert_eq!(Destination::from_bytes(&bytes[..386]), None);
+
+    bytes[385] = 0x01;
+    assert_eq!(Destination::from_bytes(&bytes[..I2P_DESTINATION_MIN_LENGTH + 10]), None);
+}
+
+#[test]
+fn test_destination_should_round_trip_through_i2p_base64() {
+    let key = SigningPublicKey::from_bytes(SignatureType::EdDSA_SHA512_Ed25519, &[0x07; 32]).unwrap();
+    let destination = Destination::new(&[0xFB; 256], &key);
+    let text = destination.to_i2p_base64();
+
+    assert!(!text.contains('+') && !text.contains('/'));
+    assert_eq!(Destination::from_i2p_base64(&text), Some(destination.clone()));
+
+    let mut with_private_keys = destination.as_ref().to_vec();
+    with_private_keys.extend_from_slice(&[0x00; 33]);
+    assert_eq!(Destination::from_i2p_base64(&with_private_keys.to_i2p_base64()), None);
+    assert_eq!(Destination::from_i2p_base64(&text.replace('-', "+")), None);
+}
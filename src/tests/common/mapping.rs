@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use common::Mapping;
+
+
+#[test]
+fn test_mapping_should_round_trip_sorted_by_key() {
+    let mut mapping = Mapping::new();
+    mapping.insert("inbound.length", "2").unwrap();
+    mapping.insert("i2cp.leaseSetEncType", "4").unwrap();
+    mapping.insert("empty", "").unwrap();
+
+    let bytes = mapping.to_bytes();
+    assert_eq!(&bytes[0..2], &[0x00, (bytes.len() - 2) as u8]);
+    assert_eq!(&bytes[2..11], b"\x05empty=\x00;");
+    assert_eq!(Mapping::from_bytes(&bytes), Some((mapping.clone(), bytes.len())));
+    assert_eq!(mapping.get("inbound.length"), Some("2"));
+    assert_eq!(mapping.to_hash_map().len(), 3);
+}
+
+#[test]
+fn test_mapping_should_reject_malformed_bytes() {
+    assert_eq!(Mapping::from_bytes(&[0x00]), None);
+    assert_eq!(Mapping::from_bytes(&[0x00, 0x05, 0x01, b'a', b'=', 0x01]), None);
+    assert_eq!(Mapping::from_bytes(&[0x00, 0x06, 0x01, b'a', b':', 0x01, b'b', b';']), None);
+    assert_eq!(Mapping::from_bytes(&[0x00, 0x00, 0xFF]), Some((Mapping::new(), 2)));
+}
+
+#[test]
+fn test_mapping_should_limit_string_lengths() {
+    let mut mapping = Mapping::new();
+    let long = "x".repeat(256);
+
+    assert!(mapping.insert(&long, "value").is_err());
+    assert!(mapping.insert("key", &long).is_err());
+    assert!(mapping.is_empty());
+
+    let mut map = HashMap::new();
+    map.insert("key".to_string(), "value".to_string());
+    assert_eq!(Mapping::from(&map).get("key"), Some("value"));
+}
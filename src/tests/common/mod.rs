@@ -1,3 +1,7 @@
 mod i2p_string;
 mod i2p_date;
 mod i2p_integer;
+mod mapping;
+mod destination;
+mod i2p_hash;
+mod router_info;
@@ -0,0 +1,99 @@
+use common::{Ed25519Signer, Hash256, Hashable256, Mapping, SessionSigner, RouterAddress, RouterIdentity, RouterInfo};
+use netdb::NetDbEntry;
+use tests::su3::{FakeSigner, FakeVerifier, fake_key};
+use tests::util::date;
+
+
+fn address(cost: u8, style: &str, port: &str) -> RouterAddress {
+    let mut options = Mapping::new();
+    options.insert("host", "127.0.0.1").unwrap();
+    options.insert("port", port).unwrap();
+
+    RouterAddress::new(cost, style, options).unwrap()
+}
+
+fn router_info() -> RouterInfo {
+    let identity = RouterIdentity::new(&[0x01; 256], &fake_key());
+    let addresses = vec![address(10, "NTCP2", "9000"), address(5, "NTCP2", "9001"), address(3, "SSU2", "9002")];
+    let mut options = Mapping::new();
+    options.insert("caps", "XfR").unwrap();
+
+    RouterInfo::new(identity, date(1_539_302_400_000), addresses, options, &FakeSigner)
+}
+
+#[test]
+fn test_router_address_should_round_trip() {
+    let address = address(10, "NTCP2", "9000");
+    let bytes = address.to_bytes();
+
+    assert_eq!(&bytes[..10], &[10, 0, 0, 0, 0, 0, 0, 0, 0, 5]);
+    assert_eq!(RouterAddress::from_bytes(&bytes), Some((address, bytes.len())));
+    assert_eq!(RouterAddress::from_bytes(&bytes[..bytes.len() - 1]), None);
+    assert!(RouterAddress::new(0, &"x".repeat(256), Mapping::new()).is_err());
+}
+
+#[test]
+fn test_router_info_should_round_trip_and_verify() {
+    let router_info = router_info();
+    let mut bytes = router_info.to_bytes();
+    bytes.extend_from_slice(&[0xAA; 4]);
+
+    let (parsed, length) = RouterInfo::from_bytes(&bytes).unwrap();
+    assert_eq!(length, bytes.len() - 4);
+    assert_eq!(parsed, router_info);
+    assert!(parsed.verify(&FakeVerifier));
+    assert_eq!(parsed.options().get("caps"), Some("XfR"));
+    assert_eq!(parsed.address("NTCP2").and_then(|address| address.option("port")), Some("9001"));
+    assert_eq!(parsed.address("SSU"), None);
+}
+
+#[test]
+fn test_router_info_should_fail_verification_when_modified() {
+    let mut bytes = router_info().to_bytes();
+    let length = bytes.len();
+    bytes[length - 66] ^= 0x01;
+
+    let (parsed, _) = RouterInfo::from_bytes(&bytes).unwrap();
+    assert!(!parsed.verify(&FakeVerifier));
+    assert_eq!(RouterInfo::from_bytes(&bytes[..length - 1]), None);
+}
+
+#[test]
+fn test_router_info_should_keep_restricted_route_peers() {
+    let router_info = router_info();
+    let bytes = router_info.to_bytes();
+    let signed_length = bytes.len() - router_info.signature().as_ref().len();
+    let peers_offset = signed_length - router_info.options().to_bytes().len() - 1;
+    let mut signed = bytes[..peers_offset].to_vec();
+    signed.push(0x01);
+    signed.extend_from_slice(&[0xAB; 32]);
+    signed.extend_from_slice(&bytes[peers_offset + 1..signed_length]);
+    let mut with_peers = signed.clone();
+    with_peers.extend_from_slice(FakeSigner.sign(&signed).as_ref());
+
+    let (parsed, length) = RouterInfo::from_bytes(&with_peers).unwrap();
+    assert_eq!(length, with_peers.len());
+    assert_eq!(parsed.peers(), &[Hash256::from([0xAB; 32])]);
+    assert!(parsed.verify(&FakeVerifier));
+    assert_eq!(parsed.to_bytes(), with_peers);
+}
+
+#[test]
+fn test_router_info_should_be_a_network_database_entry() {
+    let signer = Ed25519Signer::new(&[0x07; 32]);
+    let identity = RouterIdentity::new(&[0x01; 256], &signer.public_key());
+    let signed = RouterInfo::new(identity.clone(), date(1_539_302_400_000), vec![address(10, "NTCP2", "9000")],
+                                      Mapping::new(), &signer);
+    let mut bytes = NetDbEntry::to_bytes(&signed);
+
+    let parsed = <RouterInfo as NetDbEntry>::from_bytes(&bytes).unwrap();
+    assert!(parsed.verify_signature());
+    assert_eq!(parsed.hash_sha256(), identity.hash_sha256());
+
+    let length = bytes.len();
+    bytes[length - 70] ^= 0x01;
+    assert!(!<RouterInfo as NetDbEntry>::from_bytes(&bytes).unwrap().verify_signature());
+    bytes.push(0x00);
+    assert_eq!(<RouterInfo as NetDbEntry>::from_bytes(&bytes), None);
+    assert!(!router_info().verify_signature());
+}
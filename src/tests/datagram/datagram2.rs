@@ -0,0 +1,57 @@
+use common::{Hash256, Hashable256, Mapping};
+use datagram::{Datagram2, DATAGRAM2_VERSION, FLAG_OFFLINE_SIGNATURE, FLAG_OPTIONS};
+use streaming::OfflineSignature;
+use tests::su3::FakeSigner;
+use tests::i2cp::message::destination;
+use tests::su3::{FakeVerifier, fake_key, fake_signature};
+use tests::util::date;
+
+
+fn target() -> Hash256 {
+    Hash256::from([0x42; 32])
+}
+
+
+#[test]
+fn test_datagram2_should_bind_its_signature_to_the_recipient() {
+    let mut datagram = Datagram2::new(destination(), b"get_peers".to_vec());
+    assert_eq!(datagram.flags(), DATAGRAM2_VERSION);
+    assert!(datagram.sign(&target(), &FakeSigner));
+
+    let received = Datagram2::from_bytes(&datagram.to_bytes()).unwrap();
+    assert_eq!(received, datagram);
+    assert!(received.verify(&FakeVerifier, &target(), date(1000)));
+    assert!(!received.verify(&FakeVerifier, &destination().hash_sha256(), date(1000)));
+}
+
+#[test]
+fn test_datagram2_should_carry_options_and_offline_signatures() {
+    let expires = 1_700_000_000;
+    let mut datagram = Datagram2::new(destination(), b"announce".to_vec());
+    datagram.options.insert("reply.port", "6881").unwrap();
+    datagram.offline_signature = Some(OfflineSignature {
+        expires,
+        transient_key: fake_key(),
+        signature: fake_signature(&OfflineSignature::signed_bytes(expires, &fake_key()))
+    });
+    assert_eq!(datagram.flags(), DATAGRAM2_VERSION | FLAG_OPTIONS | FLAG_OFFLINE_SIGNATURE);
+    assert!(datagram.sign(&target(), &FakeSigner));
+
+    let received = Datagram2::from_bytes(&datagram.to_bytes()).unwrap();
+    assert_eq!(received.options.get("reply.port"), Some("6881"));
+    assert_eq!(received.payload, b"announce");
+    assert!(received.verify(&FakeVerifier, &target(), date((expires as u64 - 1) * 1000)));
+    assert!(!received.verify(&FakeVerifier, &target(), date((expires as u64 + 1) * 1000)));
+}
+
+#[test]
+fn test_datagram2_should_reject_other_versions() {
+    let mut datagram = Datagram2::new(destination(), Vec::new());
+    datagram.options = Mapping::new();
+    datagram.sign(&target(), &FakeSigner);
+    let mut bytes = datagram.to_bytes();
+    let flags = destination().len() + 1;
+    bytes[flags] = 0x03;
+    assert_eq!(Datagram2::from_bytes(&bytes), None);
+    assert_eq!(Datagram2::from_bytes(&bytes[..destination().len() + 2]), None);
+}
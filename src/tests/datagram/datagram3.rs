@@ -0,0 +1,44 @@
+use common::Hashable256;
+use datagram::{Datagram, Datagram1, Datagram2, Datagram3, DATAGRAM3_VERSION, FLAG_OPTIONS};
+use i2cp::{I2cpPayload, PROTOCOL_DATAGRAM3, PROTOCOL_RAW, PROTOCOL_STREAMING};
+use tests::su3::FakeSigner;
+use tests::i2cp::message::destination;
+
+
+#[test]
+fn test_datagram3_should_name_its_source_by_hash() {
+    let mut datagram = Datagram3::new(&destination(), b"dns query".to_vec());
+    assert_eq!(datagram.from, destination().hash_sha256());
+    assert_eq!(datagram.flags(), DATAGRAM3_VERSION);
+    assert_eq!(datagram.to_bytes().len(), 32 + 2 + 9);
+    assert_eq!(Datagram3::from_bytes(&datagram.to_bytes()), Some(datagram.clone()));
+
+    datagram.options.insert("ttl", "60").unwrap();
+    assert_eq!(datagram.flags(), DATAGRAM3_VERSION | FLAG_OPTIONS);
+    assert_eq!(Datagram3::from_bytes(&datagram.to_bytes()), Some(datagram));
+    assert_eq!(Datagram3::from_bytes(&[0x00; 33]), None);
+}
+
+#[test]
+fn test_datagrams_should_travel_in_i2cp_payloads() {
+    let mut datagram2 = Datagram2::new(destination(), b"two".to_vec());
+    datagram2.sign(&destination().hash_sha256(), &FakeSigner);
+    let datagrams = vec![
+        Datagram::Repliable(Datagram1::sign(destination(), b"one".to_vec(), &FakeSigner).unwrap()),
+        Datagram::Raw(b"raw".to_vec()),
+        Datagram::Datagram2(datagram2),
+        Datagram::Datagram3(Datagram3::new(&destination(), b"three".to_vec()))
+    ];
+
+    for datagram in datagrams {
+        let bytes = datagram.to_payload(5353, 53).to_bytes();
+        let payload = I2cpPayload::from_bytes(&bytes).unwrap();
+        assert_eq!((payload.source_port, payload.destination_port), (5353, 53));
+        assert_eq!(Datagram::from_payload(&payload), Some(datagram));
+    }
+
+    let raw = Datagram::Raw(b"raw".to_vec()).to_payload(0, 0);
+    assert_eq!(raw.protocol, PROTOCOL_RAW);
+    assert_eq!(Datagram::from_payload(&I2cpPayload::new(PROTOCOL_STREAMING, 0, 0, Vec::new())), None);
+    assert_eq!(Datagram::from_payload(&I2cpPayload::new(PROTOCOL_DATAGRAM3, 0, 0, vec![0x00; 8])), None);
+}
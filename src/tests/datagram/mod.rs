@@ -0,0 +1,3 @@
+mod repliable;
+mod datagram2;
+mod datagram3;
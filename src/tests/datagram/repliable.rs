@@ -0,0 +1,39 @@
+use common::{Destination, Hashable256, SignatureType, SigningPublicKey};
+use datagram::Datagram1;
+use tests::su3::FakeSigner;
+use tests::i2cp::message::destination;
+use tests::su3::FakeVerifier;
+
+
+#[test]
+fn test_repliable_datagram_should_be_signed_and_verified() {
+    let datagram = Datagram1::sign(destination(), b"ping".to_vec(), &FakeSigner).unwrap();
+    let bytes = datagram.to_bytes();
+    assert_eq!(bytes.len(), destination().len() + 64 + 4);
+
+    let received = Datagram1::from_bytes(&bytes).unwrap();
+    assert_eq!(received, datagram);
+    assert!(received.verify(&FakeVerifier));
+
+    let mut tampered = received.clone();
+    tampered.payload = b"pong".to_vec();
+    assert!(!tampered.verify(&FakeVerifier));
+    assert_eq!(Datagram1::from_bytes(&bytes[..destination().len() + 10]), None);
+}
+
+#[test]
+fn test_dsa_senders_should_sign_the_payload_hash() {
+    assert_eq!(Datagram1::signed_bytes(SignatureType::EdDSA_SHA512_Ed25519, b"data"), b"data".to_vec());
+    assert_eq!(
+        Datagram1::signed_bytes(SignatureType::DSA_SHA1, b"data"),
+        b"data"[..].hash_sha256().as_ref().to_vec()
+    );
+
+    let key = SigningPublicKey::from_bytes(SignatureType::DSA_SHA1, &[0x03; 128]).unwrap();
+    let mut bytes = Destination::new(&[0x01; 256], &key).as_ref().to_vec();
+    bytes.extend_from_slice(&[0x02; 40]);
+    bytes.extend_from_slice(b"payload");
+    let datagram = Datagram1::from_bytes(&bytes).unwrap();
+    assert_eq!(datagram.signature, vec![0x02; 40]);
+    assert_eq!(datagram.payload, b"payload");
+}
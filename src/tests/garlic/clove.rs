@@ -0,0 +1,42 @@
+use common::{Hash256, I2pInt32};
+use garlic::{Block, DeliveryInstructions, GarlicClove, GarlicError, encode_blocks, decode_blocks};
+use i2np::{I2npMessage, MessageType};
+use tests::util::date;
+
+
+fn clove(delivery: DeliveryInstructions) -> Block {
+    Block::GarlicClove(GarlicClove::new(delivery, I2npMessage::new(MessageType::Data, 7, date(1_600_000_000_000), vec![1, 2, 3])))
+}
+
+
+#[test]
+fn test_cloves_should_round_trip_with_every_delivery_type() {
+    let blocks = vec![Block::DateTime(1_600_000_000),
+                      clove(DeliveryInstructions::Local),
+                      clove(DeliveryInstructions::Destination(Hash256::from([0x01; 32]))),
+                      clove(DeliveryInstructions::Router(Hash256::from([0x02; 32]))),
+                      clove(DeliveryInstructions::Tunnel(Hash256::from([0x03; 32]), I2pInt32::new(99))),
+                      Block::Unknown(7, vec![0xAA]),
+                      Block::Padding(vec![0x00; 5])];
+
+    let payload = encode_blocks(&blocks);
+    assert_eq!(payload.len(), blocks.iter().map(|block| block.encoded_len()).sum::<usize>());
+    assert_eq!(decode_blocks(&payload).unwrap(), blocks);
+}
+
+#[test]
+fn test_clove_should_use_the_ratchet_layout() {
+    let payload = encode_blocks(&[clove(DeliveryInstructions::Tunnel(Hash256::from([0x03; 32]), I2pInt32::new(99)))]);
+
+    assert_eq!(&payload[0..4], &[11, 0, 49, 0x60]);
+    assert_eq!(&payload[36..41], &[0, 0, 0, 99, 20]);
+    assert_eq!(&payload[49..], &[1, 2, 3]);
+}
+
+#[test]
+fn test_malformed_blocks_should_be_rejected() {
+    assert_eq!(decode_blocks(&[11, 0, 2, 0x20, 0x00]), Err(GarlicError::InvalidBlock));
+    assert_eq!(decode_blocks(&[0, 0, 3, 1, 2, 3]), Err(GarlicError::InvalidBlock));
+    assert_eq!(decode_blocks(&[254, 0, 0, 0, 0, 4, 0, 0, 0, 0]), Err(GarlicError::InvalidBlock));
+    assert_eq!(decode_blocks(&[0, 0, 4, 1]), Err(GarlicError::InvalidBlock));
+}
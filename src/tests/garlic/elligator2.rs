@@ -0,0 +1,42 @@
+use rand;
+use rand::Rng;
+use x25519_dalek::{PublicKey, StaticSecret};
+use garlic::{decode_elligator2, encode_elligator2};
+
+
+#[test]
+fn test_decode_should_match_the_curve25519_dalek_vector() {
+    let mut representative = [0x00; 32];
+    for (index, byte) in representative.iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+
+    assert_eq!(decode_elligator2(&representative), [
+        0x5f, 0x35, 0x20, 0x00, 0x1c, 0x6c, 0x99, 0x36, 0xa3, 0x12, 0x06, 0xaf, 0xe7, 0xc7, 0xac, 0x22,
+        0x4e, 0x88, 0x61, 0x61, 0x9b, 0xf9, 0x88, 0x72, 0x44, 0x49, 0x15, 0x89, 0x9d, 0x95, 0xf4, 0x6e
+    ]);
+    assert_eq!(decode_elligator2(&[0x00; 32]), [0x00; 32]);
+}
+
+#[test]
+fn test_encode_should_round_trip_about_half_of_all_keys() {
+    let mut rng = rand::thread_rng();
+    let mut encoded = 0;
+    for _ in 0..64 {
+        let mut key = [0x00; 32];
+        rng.fill_bytes(&mut key);
+        let public_key = PublicKey::from(&StaticSecret::from(key)).to_bytes();
+
+        if let Some(representative) = encode_elligator2(&public_key, 0xC0) {
+            encoded += 1;
+            assert_eq!(representative[31] & 0xC0, 0xC0);
+            assert_eq!(decode_elligator2(&representative), public_key);
+            let other = encode_elligator2(&public_key, 0x01).unwrap();
+            assert_eq!(decode_elligator2(&other), public_key);
+        } else {
+            assert_eq!(encode_elligator2(&public_key, 0x01), None);
+        }
+    }
+
+    assert!(encoded > 8 && encoded < 56);
+}
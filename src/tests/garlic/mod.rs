@@ -0,0 +1,4 @@
+mod clove;
+mod router;
+mod ratchet;
+mod elligator2;
@@ -0,0 +1,74 @@
+use common::Hash256;
+use garlic::{GarlicEncryptor, RatchetEncryptor, RatchetPeer, TAG_LENGTH};
+
+
+const MAC_LENGTH: usize = 16;
+
+fn bob() -> Hash256 {
+    Hash256::from([0x0B; 32])
+}
+
+
+#[test]
+fn test_sessions_should_move_from_new_session_to_existing_session_messages() {
+    let mut alice = RatchetEncryptor::new(&[0x0A; 32]);
+    let mut bob_encryptor = RatchetEncryptor::new(&[0x0B; 32]);
+    let bob_key = bob_encryptor.public_key();
+    let alice_key = RatchetPeer::StaticKey(alice.public_key());
+
+    // Alice sends New Session messages until Bob replies.
+    let first = alice.encrypt(&bob(), &bob_key, b"one").unwrap();
+    let second = alice.encrypt(&bob(), &bob_key, b"two").unwrap();
+    assert_eq!(first.len(), 32 + 32 + MAC_LENGTH + 3 + MAC_LENGTH);
+    assert_ne!(first[..32], second[..32]);
+    assert_eq!(bob_encryptor.decrypt(&first), Some((alice_key.clone(), b"one".to_vec())));
+    assert_eq!(bob_encryptor.decrypt(&second), Some((alice_key.clone(), b"two".to_vec())));
+
+    // Bob answers with a New Session Reply, which sets up the session.
+    let reply = bob_encryptor.reply(&alice.public_key(), b"three").unwrap();
+    assert_eq!(reply.len(), TAG_LENGTH + 32 + MAC_LENGTH + 5 + MAC_LENGTH);
+    assert_eq!(alice.decrypt(&reply), Some((RatchetPeer::Destination(bob()), b"three".to_vec())));
+    assert_eq!(alice.decrypt(&reply), None);
+
+    // Both sides now send Existing Session messages.
+    let existing = alice.encrypt(&bob(), &bob_key, b"four").unwrap();
+    assert_eq!(existing.len(), TAG_LENGTH + 4 + MAC_LENGTH);
+    assert_eq!(bob_encryptor.decrypt(&existing), Some((alice_key.clone(), b"four".to_vec())));
+    let answer = bob_encryptor.reply(&alice.public_key(), b"five").unwrap();
+    assert_eq!(answer.len(), TAG_LENGTH + 4 + MAC_LENGTH);
+    assert_eq!(alice.decrypt(&answer), Some((RatchetPeer::Destination(bob()), b"five".to_vec())));
+
+    // Tags are used once, and may arrive out of order.
+    let sixth = alice.encrypt(&bob(), &bob_key, b"six").unwrap();
+    let seventh = alice.encrypt(&bob(), &bob_key, b"seven").unwrap();
+    assert_eq!(bob_encryptor.decrypt(&seventh), Some((alice_key.clone(), b"seven".to_vec())));
+    assert_eq!(bob_encryptor.decrypt(&sixth), Some((alice_key, b"six".to_vec())));
+    assert_eq!(bob_encryptor.decrypt(&sixth), None);
+}
+
+#[test]
+fn test_messages_should_only_decrypt_for_their_recipient() {
+    let mut alice = RatchetEncryptor::new(&[0x0A; 32]);
+    let mut bob_encryptor = RatchetEncryptor::new(&[0x0B; 32]);
+    let mut eve = RatchetEncryptor::new(&[0x0E; 32]);
+    let bob_key = bob_encryptor.public_key();
+
+    let message = alice.encrypt(&bob(), &bob_key, b"secret").unwrap();
+    assert_eq!(eve.decrypt(&message), None);
+    let mut modified = message.clone();
+    modified[40] ^= 0x01;
+    assert_eq!(bob_encryptor.decrypt(&modified), None);
+    assert!(bob_encryptor.decrypt(&message).is_some());
+
+    assert_eq!(eve.reply(&alice.public_key(), b"hello"), None);
+    let reply = bob_encryptor.reply(&alice.public_key(), b"hello").unwrap();
+    let mut modified = reply.clone();
+    modified[50] ^= 0x01;
+    assert_eq!(alice.decrypt(&modified), None);
+    assert!(alice.decrypt(&reply).is_some());
+
+    // A new key in Bob's LeaseSet starts a new session.
+    let restarted = alice.encrypt(&bob(), &eve.public_key(), b"again").unwrap();
+    assert_eq!(restarted.len(), 32 + 32 + MAC_LENGTH + 5 + MAC_LENGTH);
+    assert_eq!(eve.decrypt(&restarted).map(|(_, payload)| payload), Some(b"again".to_vec()));
+}
@@ -0,0 +1,159 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use common::{Hash256, I2pInt32, Lease};
+use garlic::{Block, DeliveryInstructions, GarlicEncryptor, GarlicSender, LeaseSetLookup, MessageRouter};
+use garlic::{MessageStatus, RemoteLeaseSet, RetryPolicy, decode_blocks, ENCRYPTION_TYPE_ECIES_X25519};
+use i2np::{I2npMessage, MessageType};
+use tunnel::{ClientTunnels, PoolSettings, PooledTunnel};
+use tests::util::date;
+
+
+/// Knows a single destination, 0x0D, with one lease ending at `lease_end`.
+struct FakeLookup {
+    encryption_type: u16,
+    lease_end: u64
+}
+
+impl LeaseSetLookup for FakeLookup {
+    fn lookup(&mut self, destination: &Hash256) -> Option<RemoteLeaseSet> {
+        if *destination != Hash256::from([0x0D; 32]) {
+            return None;
+        }
+        Some(RemoteLeaseSet {
+            leases: vec![Lease::new(Hash256::from([0x0E; 32]), I2pInt32::new(5), date(self.lease_end))],
+            encryption_type: self.encryption_type,
+            public_key: [0x0F; 32]
+        })
+    }
+}
+
+/// Leaves the payload in the clear so the tests can read it.
+struct PlainEncryptor;
+
+impl GarlicEncryptor for PlainEncryptor {
+    fn encrypt(&mut self, _: &Hash256, _: &[u8; 32], payload: &[u8]) -> Option<Vec<u8>> {
+        Some(payload.to_vec())
+    }
+}
+
+#[derive(Clone, Default)]
+struct RecordingSender {
+    sent: Rc<RefCell<Vec<(Lease, I2npMessage)>>>
+}
+
+impl GarlicSender for RecordingSender {
+    fn send(&mut self, _: &PooledTunnel, lease: &Lease, message: I2npMessage) -> bool {
+        self.sent.borrow_mut().push((lease.clone(), message));
+        true
+    }
+}
+
+fn tunnels() -> ClientTunnels {
+    let mut tunnels = ClientTunnels::new(PoolSettings::default(), PoolSettings::default());
+    tunnels.inbound_mut().tunnel_built(PooledTunnel::new(Hash256::from([0x01; 32]), I2pInt32::new(11), vec![], date(900_000)));
+    tunnels.outbound_mut().tunnel_built(PooledTunnel::new(Hash256::from([0x02; 32]), I2pInt32::new(22), vec![], date(900_000)));
+
+    tunnels
+}
+
+fn message_router(encryption_type: u16, sender: RecordingSender) -> MessageRouter<FakeLookup, PlainEncryptor, RecordingSender> {
+    let lookup = FakeLookup { encryption_type, lease_end: 600_000 };
+    MessageRouter::new(lookup, PlainEncryptor, sender, RetryPolicy { max_attempts: 2, ack_timeout: 1_000 })
+}
+
+fn recorder() -> (Rc<RefCell<Vec<MessageStatus>>>, impl FnMut(MessageStatus)) {
+    let statuses = Rc::new(RefCell::new(Vec::new()));
+    let recorded = statuses.clone();
+
+    (statuses, move |status| recorded.borrow_mut().push(status))
+}
+
+
+#[test]
+fn test_send_should_wrap_payload_lease_set_and_ack_in_garlic() {
+    let sender = RecordingSender::default();
+    let mut router = message_router(ENCRYPTION_TYPE_ECIES_X25519, sender.clone());
+    router.set_lease_set(Hash256::from([0x0A; 32]), vec![0xAB; 10]);
+    let (statuses, callback) = recorder();
+
+    router.send(Hash256::from([0x0D; 32]), b"hello".to_vec(), date(60_000), date(1_000), &tunnels(), callback);
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::Accepted]);
+
+    let (lease, garlic) = sender.sent.borrow_mut().pop().unwrap();
+    assert_eq!(lease.gateway, Hash256::from([0x0E; 32]));
+    assert_eq!(garlic.message_type, MessageType::Garlic);
+    let length = garlic.payload[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as usize));
+    assert_eq!(length, garlic.payload.len() - 4);
+    let blocks = decode_blocks(&garlic.payload[4..]).unwrap();
+    assert_eq!(blocks.len(), 4);
+    let cloves: Vec<_> = blocks.into_iter().filter_map(|block| match block {
+        Block::GarlicClove(clove) => Some(clove),
+        _ => None
+    }).collect();
+    assert_eq!(cloves[0].delivery, DeliveryInstructions::Destination(Hash256::from([0x0D; 32])));
+    assert_eq!(cloves[0].message.payload, b"\x00\x00\x00\x05hello".to_vec());
+    assert_eq!(cloves[1].delivery, DeliveryInstructions::Local);
+    assert_eq!(cloves[1].message.message_type, MessageType::DatabaseStore);
+    assert_eq!(cloves[2].delivery, DeliveryInstructions::Tunnel(Hash256::from([0x01; 32]), I2pInt32::new(11)));
+
+    let ack = cloves[2].message.clone();
+    assert!(router.handle_delivery_status(&ack));
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::Accepted, MessageStatus::Delivered]);
+    assert_eq!(router.pending(), 0);
+}
+
+#[test]
+fn test_unacknowledged_messages_should_be_retried_then_expire() {
+    let sender = RecordingSender::default();
+    let mut router = message_router(ENCRYPTION_TYPE_ECIES_X25519, sender.clone());
+    let (statuses, callback) = recorder();
+    router.send(Hash256::from([0x0D; 32]), vec![1], date(60_000), date(1_000), &tunnels(), callback);
+
+    router.expire(date(1_500), &tunnels());
+    assert_eq!(sender.sent.borrow().len(), 1);
+    router.expire(date(2_500), &tunnels());
+    assert_eq!(sender.sent.borrow().len(), 2);
+    router.expire(date(4_000), &tunnels());
+
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::Accepted, MessageStatus::Expired]);
+    assert_eq!(router.pending(), 0);
+}
+
+#[test]
+fn test_a_late_ack_should_match_after_a_retry() {
+    let sender = RecordingSender::default();
+    let mut router = message_router(ENCRYPTION_TYPE_ECIES_X25519, sender.clone());
+    let (statuses, callback) = recorder();
+    router.send(Hash256::from([0x0D; 32]), vec![1], date(60_000), date(1_000), &tunnels(), callback);
+    router.expire(date(2_500), &tunnels());
+    assert_eq!(sender.sent.borrow().len(), 2);
+
+    // The acknowledgement of the first attempt arrives after the second was sent.
+    let first = sender.sent.borrow()[0].1.clone();
+    let ack = decode_blocks(&first.payload[4..]).unwrap().into_iter().filter_map(|block| match block {
+        Block::GarlicClove(ref clove) if clove.message.message_type == MessageType::DeliveryStatus => Some(clove.message.clone()),
+        _ => None
+    }).next().unwrap();
+    assert!(router.handle_delivery_status(&ack));
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::Accepted, MessageStatus::Delivered]);
+}
+
+#[test]
+fn test_undeliverable_messages_should_report_why() {
+    let mut router = message_router(ENCRYPTION_TYPE_ECIES_X25519, RecordingSender::default());
+    let (statuses, callback) = recorder();
+    router.send(Hash256::from([0x0F; 32]), vec![1], date(60_000), date(1_000), &tunnels(), callback);
+    router.expire(date(2_500), &tunnels());
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::NoLeaseSet]);
+
+    let no_tunnels = ClientTunnels::new(PoolSettings::default(), PoolSettings::default());
+    let (statuses, callback) = recorder();
+    router.send(Hash256::from([0x0D; 32]), vec![1], date(60_000), date(1_000), &no_tunnels, callback);
+    router.expire(date(2_500), &no_tunnels);
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::NoTunnels]);
+
+    let mut router = message_router(0, RecordingSender::default());
+    let (statuses, callback) = recorder();
+    router.send(Hash256::from([0x0D; 32]), vec![1], date(60_000), date(1_000), &tunnels(), callback);
+    assert_eq!(*statuses.borrow(), vec![MessageStatus::EncryptionFailed]);
+}
@@ -0,0 +1,119 @@
+use std::net::SocketAddr;
+use std::thread;
+use common::{Destination, Hash256, I2pDate, I2pInt32, I2pInt64, Lease, Mapping};
+use i2cp::{ClientEvent, HostQuery, I2cpBackend, I2cpClient, I2cpError, I2cpMessage, I2cpServer, I2cpWriter};
+use i2cp::{SessionConfig, SessionState, FAST_RECEIVE_OPTION, FLAG_NO_LEASE_SET, ROUTER_VERSION, STATUS_ACCEPTED};
+use tests::su3::{FakeSigner, FakeVerifier};
+use super::message::destination;
+
+
+/// A router that asks for a LeaseSet as soon as a session starts, delivers every
+/// message back to its sender, and knows one host name. Like the Java router, it
+/// only sends MessagePayload directly to sessions with `i2cp.fastReceive=true`.
+struct FakeRouter {
+    writer: Option<I2cpWriter>,
+    fast_receive: bool
+}
+
+impl I2cpBackend for FakeRouter {
+    fn create_session(&mut self, session_id: u16, config: &SessionConfig, writer: I2cpWriter) -> bool {
+        self.fast_receive = config.options.get(FAST_RECEIVE_OPTION) == Some("true");
+        let leases = vec![Lease::new(Hash256::from([0x04; 32]), I2pInt32::new(8), I2pDate::max_value())];
+        writer.send(&I2cpMessage::RequestVariableLeaseSet { session_id, leases }).unwrap();
+        self.writer = Some(writer);
+        true
+    }
+
+    fn destroy_session(&mut self, _: u16) {
+        self.writer = None;
+    }
+
+    fn create_lease_set(&mut self, _: u16, _: &[u8], _: bool) {}
+
+    fn send_message(&mut self, session_id: u16, message_id: u32, _: &Destination, payload: &[u8], _: Option<I2pDate>) -> u8 {
+        if self.fast_receive {
+            self.writer.as_ref().unwrap().deliver(session_id, message_id, payload.to_vec()).unwrap();
+        }
+        STATUS_ACCEPTED
+    }
+
+    fn lookup(&mut self, _: u16, query: &HostQuery) -> Option<Destination> {
+        match *query {
+            HostQuery::Name(ref name) if name == "echo.i2p" => Some(destination()),
+            _ => None
+        }
+    }
+
+    fn bandwidth_limits(&self) -> Vec<u32> {
+        vec![0; 16]
+    }
+}
+
+fn fake_router() -> (SocketAddr, thread::JoinHandle<bool>) {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let router = thread::spawn(move || {
+        let mut connection = server.accept().unwrap();
+        let mut backend = FakeRouter { writer: None, fast_receive: false };
+        connection.serve(&mut backend, &FakeVerifier).unwrap();
+        backend.writer.is_none()
+    });
+
+    (address, router)
+}
+
+/// Options without `i2cp.fastReceive`, which the client has to add itself.
+fn options() -> Mapping {
+    let mut options = Mapping::new();
+    options.insert("inbound.quantity", "3").unwrap();
+    options
+}
+
+
+#[test]
+fn test_client_should_open_a_session_and_exchange_messages() {
+    let (address, router) = fake_router();
+    let mut client = I2cpClient::connect(address, destination(), options(), &FakeSigner).unwrap();
+    assert_eq!(client.router_version(), ROUTER_VERSION);
+
+    client.send(&destination(), b"one".to_vec(), 5).unwrap();
+    let expiration = I2pDate::new(I2pInt64::new(I2pDate::now().to_u64() + 60_000)).unwrap();
+    client.send_expires(&destination(), b"two".to_vec(), 0, FLAG_NO_LEASE_SET, expiration).unwrap();
+
+    match client.receive().unwrap() {
+        ClientEvent::LeaseSetRequested(leases) => assert_eq!(leases.len(), 1),
+        other => panic!("{:?}", other)
+    }
+    client.create_lease_set2(vec![3]).unwrap();
+    assert_eq!(client.receive().unwrap(), ClientEvent::Payload { message_id: 1, payload: b"one".to_vec() });
+    assert_eq!(client.receive().unwrap(), ClientEvent::Status { message_id: 1, status: STATUS_ACCEPTED, nonce: 5 });
+
+    assert_eq!(client.lookup("echo.i2p", 5_000).unwrap(), Some(destination()));
+    assert_eq!(client.lookup("missing.i2p", 5_000).unwrap(), None);
+    assert_eq!(client.receive().unwrap(), ClientEvent::Payload { message_id: 2, payload: b"two".to_vec() });
+
+    client.close().unwrap();
+    assert!(router.join().unwrap());
+}
+
+#[test]
+fn test_client_should_report_a_refused_session() {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let router = thread::spawn(move || {
+        let mut connection = server.accept().unwrap();
+        connection.serve(&mut FakeRouter { writer: None, fast_receive: false }, &FakeVerifier).unwrap();
+    });
+
+    // A destination whose key does not match the signer fails verification.
+    let mut bytes = destination().as_ref().to_vec();
+    bytes[383] ^= 0xFF;
+    let (stranger, _) = Destination::from_bytes(&bytes).unwrap();
+
+    match I2cpClient::connect(address, stranger, options(), &FakeSigner) {
+        Err(I2cpError::SessionFailed(SessionState::Invalid)) => {}
+        Err(err) => panic!("{}", err),
+        Ok(_) => panic!("the session should not have been created")
+    }
+    router.join().unwrap();
+}
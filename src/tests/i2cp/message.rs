@@ -0,0 +1,93 @@
+use common::{Destination, Hash256, I2pDate, I2pInt32, Lease, Mapping, Signature};
+use i2cp::{HostQuery, I2cpError, I2cpMessage, SessionConfig, SessionState};
+use i2cp::{HOST_REPLY_FAILURE, HOST_REPLY_SUCCESS, STATUS_ACCEPTED};
+use tests::su3::{FakeVerifier, fake_key, fake_signature};
+use tests::util::date;
+
+
+/// An Ed25519 destination signed for by `fake_key`, with zero padding so every
+/// call returns the same destination.
+pub fn destination() -> Destination {
+    let mut bytes = vec![0x01; 256];
+    bytes.extend_from_slice(&[0x00; 96]);
+    bytes.extend_from_slice(fake_key().as_ref());
+    bytes.extend_from_slice(&[0x05, 0x00, 0x04, 0x00, 0x07, 0x00, 0x00]);
+
+    Destination::from_bytes(&bytes).unwrap().0
+}
+
+pub fn session_config(date: I2pDate) -> SessionConfig {
+    let mut options = Mapping::new();
+    options.insert("inbound.quantity", "3").unwrap();
+    let destination = destination();
+    let signed = SessionConfig::signed_bytes(&destination, &options, date);
+    let signature = Signature::from_bytes(fake_key().signature_type(), &fake_signature(&signed)).unwrap();
+
+    SessionConfig::new(destination, options, date, signature)
+}
+
+fn round_trip(message: I2cpMessage) {
+    let mut bytes = Vec::new();
+    message.write_to(&mut bytes).unwrap();
+
+    assert_eq!(bytes.len(), 5 + message.body().len());
+    assert_eq!(bytes[4], message.type_code());
+    assert_eq!(I2cpMessage::read_from(&mut &bytes[..]).unwrap(), message);
+}
+
+
+#[test]
+fn test_messages_should_round_trip() {
+    let messages = vec![
+        I2cpMessage::CreateSession(session_config(date(1_500_000_000_000))),
+        I2cpMessage::DestroySession { session_id: 3 },
+        I2cpMessage::CreateLeaseSet2 { session_id: 3, data: vec![3, 1, 2] },
+        I2cpMessage::SendMessage { session_id: 1, destination: destination(), payload: vec![9; 20], nonce: 7 },
+        I2cpMessage::SendMessageExpires { session_id: 1, destination: destination(), payload: vec![], nonce: 0,
+                                          flags: 0x0100, expiration: date(1_500_000_000_000) },
+        I2cpMessage::GetBandwidthLimits,
+        I2cpMessage::BandwidthLimits((0..16).collect()),
+        I2cpMessage::SessionStatus { session_id: 2, state: SessionState::Created },
+        I2cpMessage::MessageStatus { session_id: 2, message_id: 5, status: STATUS_ACCEPTED, size: 20, nonce: 7 },
+        I2cpMessage::MessagePayload { session_id: 2, message_id: 5, payload: vec![1, 2, 3] },
+        I2cpMessage::GetDate { version: "0.9.62".to_string(), options: None },
+        I2cpMessage::GetDate { version: "0.9.62".to_string(), options: Some(Mapping::new()) },
+        I2cpMessage::SetDate { date: date(42), version: "0.9.62".to_string() },
+        I2cpMessage::RequestVariableLeaseSet { session_id: 2, leases: vec![
+            Lease::new(Hash256::from([0x04; 32]), I2pInt32::new(8), date(900))] },
+        I2cpMessage::HostLookup { session_id: 2, request_id: 9, timeout: 10_000, query: HostQuery::Name("example.i2p".to_string()) },
+        I2cpMessage::HostLookup { session_id: 2, request_id: 9, timeout: 10_000, query: HostQuery::Hash(Hash256::from([0x05; 32])) },
+        I2cpMessage::HostReply { session_id: 2, request_id: 9, result: HOST_REPLY_SUCCESS, destination: Some(destination()) },
+        I2cpMessage::HostReply { session_id: 2, request_id: 9, result: HOST_REPLY_FAILURE, destination: None },
+        I2cpMessage::Disconnect("bye".to_string()),
+        I2cpMessage::Unknown(99, vec![1])
+    ];
+
+    for message in messages {
+        round_trip(message);
+    }
+}
+
+#[test]
+fn test_session_config_should_verify_signature_and_date() {
+    let config = session_config(date(1_500_000_000_000));
+    assert!(config.verify(&FakeVerifier, date(1_500_000_010_000)));
+    assert!(!config.verify(&FakeVerifier, date(1_500_000_040_000)));
+
+    let mut tampered = config.clone();
+    tampered.options.insert("inbound.quantity", "4").unwrap();
+    assert!(!tampered.verify(&FakeVerifier, date(1_500_000_000_000)));
+}
+
+#[test]
+fn test_malformed_messages_should_be_rejected() {
+    match I2cpMessage::decode(5, &[0x00, 0x01, 0x02]) {
+        Err(I2cpError::InvalidMessage(5)) => {}
+        other => panic!("{:?}", other)
+    }
+    match I2cpMessage::read_from(&mut &[0x10, 0x00, 0x00, 0x00, 20][..]) {
+        Err(I2cpError::MessageTooLarge(_)) => {}
+        other => panic!("{:?}", other)
+    }
+    assert!(I2cpMessage::decode(38, &[0, 1, 0, 0, 0, 9, 0, 0, 0, 0, 7]).is_err());
+}
@@ -0,0 +1,27 @@
+use i2cp::{I2cpPayload, PROTOCOL_DATAGRAM, PROTOCOL_STREAMING};
+
+
+#[test]
+fn test_payload_should_carry_ports_and_protocol_in_the_gzip_header() {
+    let payload = I2cpPayload::new(PROTOCOL_DATAGRAM, 53, 1053, b"query".to_vec());
+    let bytes = payload.to_bytes();
+    assert_eq!(&bytes[..4], &[0x1f, 0x8b, 0x08, 0x00]);
+    assert_eq!(&bytes[4..8], &[0x00, 53, 0x04, 0x1d]);
+    assert_eq!(bytes[9], PROTOCOL_DATAGRAM);
+    assert_eq!(I2cpPayload::from_bytes(&bytes), Some(payload));
+
+    let large = I2cpPayload::new(PROTOCOL_STREAMING, 0, 80, vec![b'x'; 4096]);
+    let bytes = large.to_bytes();
+    assert!(bytes.len() < 4096);
+    assert_eq!(I2cpPayload::from_bytes(&bytes), Some(large));
+}
+
+#[test]
+fn test_payload_should_reject_data_that_is_not_gzip() {
+    assert_eq!(I2cpPayload::from_bytes(b"plain text payload"), None);
+
+    let mut bytes = I2cpPayload::new(PROTOCOL_DATAGRAM, 1, 2, b"data".to_vec()).to_bytes();
+    let length = bytes.len();
+    bytes[length - 8] ^= 0xff;
+    assert_eq!(I2cpPayload::from_bytes(&bytes), None);
+}
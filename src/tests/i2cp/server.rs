@@ -0,0 +1,159 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+use common::{Destination, I2pDate};
+use i2cp::{HostQuery, I2cpBackend, I2cpError, I2cpMessage, I2cpServer, I2cpWriter, SessionConfig, SessionState};
+use i2cp::{HOST_REPLY_SUCCESS, PROTOCOL_BYTE, ROUTER_VERSION, STATUS_ACCEPTED, STATUS_BAD_SESSION};
+use i2cp::STATUS_MESSAGE_EXPIRED;
+use tests::su3::FakeVerifier;
+use tests::util::date;
+use super::message::{destination, session_config};
+
+
+/// Accepts every session and echoes every message back to its sender.
+struct EchoBackend {
+    writers: Vec<(u16, I2cpWriter)>,
+    lease_sets: usize
+}
+
+impl I2cpBackend for EchoBackend {
+    fn create_session(&mut self, session_id: u16, config: &SessionConfig, writer: I2cpWriter) -> bool {
+        assert_eq!(config.options.get("inbound.quantity"), Some("3"));
+        self.writers.push((session_id, writer));
+        true
+    }
+
+    fn destroy_session(&mut self, session_id: u16) {
+        self.writers.retain(|&(id, _)| id != session_id);
+    }
+
+    fn create_lease_set(&mut self, _: u16, _: &[u8], lease_set2: bool) {
+        assert!(lease_set2);
+        self.lease_sets += 1;
+    }
+
+    fn send_message(&mut self, session_id: u16, message_id: u32, _: &Destination, payload: &[u8], _: Option<I2pDate>) -> u8 {
+        let writer = &self.writers.iter().find(|&&(id, _)| id == session_id).unwrap().1;
+        writer.deliver(session_id, message_id, payload.to_vec()).unwrap();
+        STATUS_ACCEPTED
+    }
+
+    fn lookup(&mut self, _: u16, query: &HostQuery) -> Option<Destination> {
+        match *query {
+            HostQuery::Name(ref name) if name == "echo.i2p" => Some(destination()),
+            _ => None
+        }
+    }
+
+    fn bandwidth_limits(&self) -> Vec<u32> {
+        vec![256; 16]
+    }
+}
+
+fn exchange(stream: &mut TcpStream, message: I2cpMessage) -> I2cpMessage {
+    message.write_to(stream).unwrap();
+    I2cpMessage::read_from(stream).unwrap()
+}
+
+
+#[test]
+fn test_server_should_serve_a_scripted_client() {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let mut connection = server.accept().unwrap();
+        let mut backend = EchoBackend { writers: Vec::new(), lease_sets: 0 };
+        connection.serve(&mut backend, &FakeVerifier).unwrap();
+        done.send((backend.writers.len(), backend.lease_sets)).unwrap();
+    });
+
+    let mut client = TcpStream::connect(address).unwrap();
+    client.write_all(&[PROTOCOL_BYTE]).unwrap();
+
+    match exchange(&mut client, I2cpMessage::GetDate { version: "0.9.62".to_string(), options: None }) {
+        I2cpMessage::SetDate { version, .. } => assert_eq!(version, ROUTER_VERSION),
+        other => panic!("{:?}", other)
+    }
+    assert_eq!(exchange(&mut client, I2cpMessage::GetBandwidthLimits), I2cpMessage::BandwidthLimits(vec![256; 16]));
+    assert_eq!(exchange(&mut client, I2cpMessage::CreateSession(session_config(I2pDate::now()))),
+               I2cpMessage::SessionStatus { session_id: 0, state: SessionState::Created });
+    assert_eq!(exchange(&mut client, I2cpMessage::CreateSession(session_config(I2pDate::min_value()))),
+               I2cpMessage::SessionStatus { session_id: 1, state: SessionState::Invalid });
+    I2cpMessage::CreateLeaseSet2 { session_id: 0, data: vec![3] }.write_to(&mut client).unwrap();
+
+    let send = I2cpMessage::SendMessage { session_id: 0, destination: destination(), payload: b"ping".to_vec(), nonce: 1 };
+    assert_eq!(exchange(&mut client, send),
+               I2cpMessage::MessagePayload { session_id: 0, message_id: 1, payload: b"ping".to_vec() });
+    assert_eq!(I2cpMessage::read_from(&mut client).unwrap(),
+               I2cpMessage::MessageStatus { session_id: 0, message_id: 1, status: STATUS_ACCEPTED, size: 4, nonce: 1 });
+
+    let send = I2cpMessage::SendMessage { session_id: 9, destination: destination(), payload: vec![], nonce: 2 };
+    match exchange(&mut client, send) {
+        I2cpMessage::MessageStatus { status, .. } => assert_eq!(status, STATUS_BAD_SESSION),
+        other => panic!("{:?}", other)
+    }
+
+    let lookup = I2cpMessage::HostLookup { session_id: 0, request_id: 4, timeout: 1000, query: HostQuery::Name("echo.i2p".to_string()) };
+    assert_eq!(exchange(&mut client, lookup),
+               I2cpMessage::HostReply { session_id: 0, request_id: 4, result: HOST_REPLY_SUCCESS, destination: Some(destination()) });
+
+    I2cpMessage::Disconnect("done".to_string()).write_to(&mut client).unwrap();
+    assert_eq!(finished.recv().unwrap(), (0, 1));
+}
+
+#[test]
+fn test_server_should_reject_other_protocols() {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+    let mut connection = server.accept().unwrap();
+    let mut backend = EchoBackend { writers: Vec::new(), lease_sets: 0 };
+    match connection.serve(&mut backend, &FakeVerifier) {
+        Err(I2cpError::InvalidProtocol(b'G')) => {}
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_server_should_accept_past_a_silent_client() {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let _silent = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    client.write_all(&[PROTOCOL_BYTE]).unwrap();
+
+    server.accept().unwrap();
+    let mut connection = server.accept().unwrap();
+    thread::spawn(move || {
+        let mut backend = EchoBackend { writers: Vec::new(), lease_sets: 0 };
+        connection.serve(&mut backend, &FakeVerifier).unwrap();
+    });
+    assert_eq!(exchange(&mut client, I2cpMessage::GetBandwidthLimits), I2cpMessage::BandwidthLimits(vec![256; 16]));
+}
+
+#[test]
+fn test_server_should_expire_messages_at_the_given_time() {
+    let server = I2cpServer::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    let mut connection = server.accept().unwrap();
+    let mut backend = EchoBackend { writers: Vec::new(), lease_sets: 0 };
+    let now = date(1_000_000);
+    connection.handle(I2cpMessage::CreateSession(session_config(now)), &mut backend, &FakeVerifier, now).unwrap();
+    assert_eq!(I2cpMessage::read_from(&mut client).unwrap(),
+               I2cpMessage::SessionStatus { session_id: 0, state: SessionState::Created });
+
+    let send = |nonce| I2cpMessage::SendMessageExpires {
+        session_id: 0, destination: destination(), payload: b"ping".to_vec(), nonce, flags: 0, expiration: date(1_500_000)
+    };
+    connection.handle(send(1), &mut backend, &FakeVerifier, now).unwrap();
+    assert_eq!(I2cpMessage::read_from(&mut client).unwrap(),
+               I2cpMessage::MessagePayload { session_id: 0, message_id: 1, payload: b"ping".to_vec() });
+    assert_eq!(I2cpMessage::read_from(&mut client).unwrap(),
+               I2cpMessage::MessageStatus { session_id: 0, message_id: 1, status: STATUS_ACCEPTED, size: 4, nonce: 1 });
+
+    connection.handle(send(2), &mut backend, &FakeVerifier, date(2_000_000)).unwrap();
+    assert_eq!(I2cpMessage::read_from(&mut client).unwrap(),
+               I2cpMessage::MessageStatus { session_id: 0, message_id: 2, status: STATUS_MESSAGE_EXPIRED, size: 4, nonce: 2 });
+}
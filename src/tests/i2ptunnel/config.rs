@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use i2ptunnel::{ServerType, TunnelConfig, TunnelError, TunnelType};
+use i2ptunnel::{load_config, parse_i2ptunnel_config, parse_tunnels_conf, validate_definitions};
+use tests::util::temp_path;
+
+
+const I2PTUNNEL_CONFIG: &str = "\
+# Tunnels from a Java router
+tunnel.0.name=I2P HTTP Proxy
+tunnel.0.type=httpclient
+tunnel.0.interface=127.0.0.1
+tunnel.0.listenPort=4444
+tunnel.0.proxyList=false.i2p, other.i2p
+tunnel.0.privKeyFile=i2ptunnel0-privKeys.dat
+tunnel.0.startOnLoad=true
+tunnel.0.option.inbound.length=3
+tunnel.0.option.outbound.nickname = shared clients
+tunnel.1.name=Irc2P
+tunnel.1.type=ircclient
+tunnel.1.listenPort=6668
+tunnel.1.targetDestination=irc.postman.i2p:6667,irc.echelon.i2p:6667
+tunnel.1.startOnLoad=false
+tunnel.2.name=I2P webserver
+tunnel.2.type=httpserver
+tunnel.2.targetHost=127.0.0.1
+tunnel.2.targetPort=7658
+tunnel.2.spoofedHost=mysite.i2p
+tunnel.2.privKeyFile=/var/lib/i2p/eepsite/eepPriv.dat
+tunnel.2.option.i2p.streaming.maxConnsPerMinute=5
+tunnel.2.option.i2cp.description=A \\
+    long description
+tunnel.3.name=IRC server
+tunnel.3.type=ircserver
+tunnel.3.targetPort=6667
+tunnel.3.privKeyFile=irc.dat
+tunnel.3.option.ircserver.method=webirc
+tunnel.3.option.ircserver.webircPassword=secret
+";
+
+const TUNNELS_CONF: &str = "\
+; Tunnels from i2pd
+[IRC-ILITA]
+type = client
+address = 127.0.0.1
+port = 6668
+destination = irc.ilita.i2p
+destinationport = 6667
+keys = irc-keys.dat
+inbound.length = 2
+
+[SOCKS]
+type = socks
+address = 0.0.0.0
+port = 4447
+outproxy = exit.i2p
+
+# A website
+[anon-website]
+type = http
+host = 127.0.0.1
+port = 8080
+keys = anon-website.dat
+hostoverride = example.i2p
+i2p.streaming.maxConnsPerMinute = 10
+signaturetype = 7
+";
+
+fn invalid(result: Result<TunnelConfig, TunnelError>) -> String {
+    match result {
+        Err(TunnelError::InvalidConfig(reason)) => reason,
+        other => panic!("{:?}", other)
+    }
+}
+
+
+#[test]
+fn test_i2ptunnel_config_should_parse_java_tunnels() {
+    let TunnelConfig { definitions, warnings } = parse_i2ptunnel_config(I2PTUNNEL_CONFIG).unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(definitions.len(), 4);
+    validate_definitions(&definitions).unwrap();
+
+    let proxy = &definitions[0];
+    assert_eq!((proxy.name.as_str(), proxy.tunnel_type), ("I2P HTTP Proxy", TunnelType::HttpProxy));
+    assert_eq!((proxy.interface.as_str(), proxy.listen_port), ("127.0.0.1", Some(4444)));
+    assert_eq!(proxy.target.as_deref(), Some("false.i2p"));
+    assert_eq!(proxy.key_file, Some(PathBuf::from("i2ptunnel0-privKeys.dat")));
+    assert_eq!(proxy.options.get("inbound.length"), Some("3"));
+    assert_eq!(proxy.options.get("outbound.nickname"), Some("shared clients"));
+    assert!(proxy.start_on_load);
+
+    let irc = &definitions[1];
+    assert_eq!(irc.tunnel_type, TunnelType::Client);
+    assert_eq!((irc.target.as_deref(), irc.target_port), (Some("irc.postman.i2p"), Some(6667)));
+    assert!(!irc.start_on_load);
+
+    let website = &definitions[2];
+    assert_eq!(website.tunnel_type, TunnelType::HttpServer);
+    assert_eq!(website.options.get("i2cp.description"), Some("A long description"));
+    let config = website.server_config().unwrap().unwrap();
+    assert_eq!(config.server_type, ServerType::Http);
+    assert_eq!(config.target, "127.0.0.1:7658".parse().unwrap());
+    assert_eq!(config.host.as_deref(), Some("mysite.i2p"));
+    assert_eq!((config.max_new_per_client, config.throttle_period), (Some(5), Duration::from_secs(60)));
+
+    let irc_server = &definitions[3];
+    assert_eq!(irc_server.webirc_password.as_deref(), Some("secret"));
+    assert_eq!(irc_server.server_config().unwrap().unwrap().target, "127.0.0.1:6667".parse().unwrap());
+    assert!(proxy.server_config().unwrap().is_none());
+}
+
+#[test]
+fn test_tunnels_conf_should_parse_i2pd_tunnels() {
+    let TunnelConfig { definitions, warnings } = parse_tunnels_conf(TUNNELS_CONF).unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(definitions.len(), 3);
+    validate_definitions(&definitions).unwrap();
+
+    let client = &definitions[0];
+    assert_eq!((client.name.as_str(), client.tunnel_type), ("IRC-ILITA", TunnelType::Client));
+    assert_eq!(client.listen_port, Some(6668));
+    assert_eq!((client.target.as_deref(), client.target_port), (Some("irc.ilita.i2p"), Some(6667)));
+    assert_eq!(client.key_file, Some(PathBuf::from("irc-keys.dat")));
+    assert_eq!(client.options.get("inbound.length"), Some("2"));
+
+    let socks = &definitions[1];
+    assert_eq!(socks.tunnel_type, TunnelType::Socks);
+    assert_eq!((socks.interface.as_str(), socks.listen_port), ("0.0.0.0", Some(4447)));
+    assert_eq!(socks.target.as_deref(), Some("exit.i2p"));
+
+    let website = &definitions[2];
+    assert_eq!(website.tunnel_type, TunnelType::HttpServer);
+    assert_eq!((website.listen_port, website.target_port), (None, Some(8080)));
+    assert_eq!(website.host_override.as_deref(), Some("example.i2p"));
+    assert_eq!(website.options.get("i2p.streaming.maxConnsPerMinute"), Some("10"));
+    assert_eq!(website.options.get("signaturetype"), None);
+}
+
+#[test]
+fn test_configs_should_reject_invalid_tunnels() {
+    assert!(invalid(parse_i2ptunnel_config("tunnel.0.name=x\n")).contains("no type"));
+    assert!(invalid(parse_i2ptunnel_config("tunnel.0.type=client\ntunnel.0.listenPort=port\n")).contains("not a port"));
+    assert!(invalid(parse_tunnels_conf("type = client\n")).contains("outside a section"));
+    assert!(invalid(parse_tunnels_conf("[x]\ntype client\n")).contains("line 2"));
+
+    let check = |text: &str| invalid(parse_tunnels_conf(text).and_then(|config| {
+        validate_definitions(&config.definitions).map(|_| config)
+    }));
+    assert!(check("[x]\ntype = client\nport = 1\n").contains("no destination"));
+    assert!(check("[x]\ntype = socks\n").contains("no listen port"));
+    assert!(check("[x]\ntype = server\nport = 80\n").contains("no private key file"));
+    assert!(check("[x]\ntype = server\nkeys = a.dat\n").contains("no target port"));
+    assert!(check("[x]\ntype = socks\nport = 1\n[y]\ntype = httpproxy\nport = 1\n").contains("127.0.0.1:1"));
+    assert!(check("[x]\ntype = socks\nport = 1\n[x]\ntype = socks\nport = 2\n").contains("named"));
+    assert!(check("[x]\ntype = server\nport = 80\nkeys = a.dat\ni2p.streaming.maxConnsPerMinute = many\n").contains("maxConnsPerMinute"));
+}
+
+#[test]
+fn test_configs_should_skip_tunnels_of_unsupported_types() {
+    let config = parse_i2ptunnel_config("tunnel.0.name=x\ntunnel.0.type=streamrclient\n\
+                                         tunnel.1.name=y\ntunnel.1.type=httpbidirserver\n\
+                                         tunnel.2.name=z\ntunnel.2.type=sockstunnel\ntunnel.2.listenPort=9050\n").unwrap();
+    assert_eq!(config.definitions.len(), 1);
+    assert_eq!((config.definitions[0].name.as_str(), config.definitions[0].tunnel_type), ("z", TunnelType::Socks));
+    assert_eq!(config.warnings, vec![
+        "skipped the tunnel \"x\" of the unsupported type \"streamrclient\"".to_string(),
+        "skipped the tunnel \"y\" of the unsupported type \"httpbidirserver\"".to_string(),
+    ]);
+
+    let config = parse_tunnels_conf("[x]\ntype = udpclient\n[y]\ntype = socks\nport = 1\n[z]\ntype = httpbidir\n").unwrap();
+    assert_eq!(config.definitions.len(), 1);
+    assert_eq!(config.definitions[0].name, "y");
+    assert_eq!(config.warnings.len(), 2);
+    assert!(config.warnings[1].contains("\"httpbidir\""));
+}
+
+#[test]
+fn test_load_config_should_detect_the_format_and_resolve_key_files() {
+    let dir = temp_path("tunnels");
+    fs::create_dir_all(&dir).unwrap();
+    let java = dir.join("i2ptunnel.config");
+    fs::write(&java, I2PTUNNEL_CONFIG).unwrap();
+    let definitions = load_config(&java).unwrap().definitions;
+    assert_eq!(definitions[0].key_file, Some(dir.join("i2ptunnel0-privKeys.dat")));
+    assert_eq!(definitions[2].key_file, Some(PathBuf::from("/var/lib/i2p/eepsite/eepPriv.dat")));
+
+    let i2pd = dir.join("tunnels.conf");
+    fs::write(&i2pd, TUNNELS_CONF).unwrap();
+    let definitions = load_config(&i2pd).unwrap().definitions;
+    assert_eq!(definitions[2].key_file, Some(dir.join("anon-website.dat")));
+
+    fs::write(&i2pd, "[x]\ntype = socks\n").unwrap();
+    assert!(invalid(load_config(&i2pd)).contains("no listen port"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,204 @@
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use common::{Destination, Hash256, Hashable256};
+use i2ptunnel::{ClientBackend, HttpProxy, HttpRequest, TunnelError, error_page};
+use streaming::ByteStream;
+use tests::su3::fake_key;
+
+
+/// Builds a destination whose encryption key is filled with `byte`.
+pub fn peer(byte: u8) -> Destination {
+    let mut bytes = vec![byte; 256];
+    bytes.extend_from_slice(&[0x00; 96]);
+    bytes.extend_from_slice(fake_key().as_ref());
+    bytes.extend_from_slice(&[0x05, 0x00, 0x04, 0x00, 0x07, 0x00, 0x00]);
+
+    Destination::from_bytes(&bytes).unwrap().0
+}
+
+/// Knows `site.i2p` and `outproxy.i2p`, which both lead to a local server, and
+/// `down.i2p`, which cannot be reached. Records every connection.
+pub struct FakeBackend {
+    server: SocketAddr,
+    pub connections: Mutex<Vec<(Destination, u16)>>
+}
+
+impl FakeBackend {
+    pub fn new(server: SocketAddr) -> FakeBackend {
+        FakeBackend { server, connections: Mutex::new(Vec::new()) }
+    }
+}
+
+impl ClientBackend for FakeBackend {
+    fn lookup(&self, name: &str) -> Option<Destination> {
+        match name {
+            "site.i2p" => Some(peer(0x01)),
+            "outproxy.i2p" => Some(peer(0x02)),
+            "down.i2p" => Some(peer(0x03)),
+            _ => None
+        }
+    }
+
+    fn lookup_hash(&self, hash: &Hash256) -> Option<Destination> {
+        (1..4).map(peer).find(|destination| destination.hash_sha256() == *hash)
+    }
+
+    fn connect(&self, destination: &Destination, port: u16) -> io::Result<Box<dyn ByteStream>> {
+        if *destination == peer(0x03) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no leases"));
+        }
+        self.connections.lock().unwrap().push((destination.clone(), port));
+        Ok(Box::new(TcpStream::connect(self.server)?))
+    }
+}
+
+/// Starts a server that reads a request head and answers with it as the body.
+pub fn start_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", head.len(), head);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    address
+}
+
+fn start(outproxy: Option<&str>) -> (Arc<FakeBackend>, SocketAddr) {
+    let backend = Arc::new(FakeBackend::new(start_echo_server()));
+    let mut proxy = HttpProxy::bind("127.0.0.1:0", backend.clone()).unwrap();
+    proxy.set_outproxy(outproxy);
+    let address = proxy.local_addr().unwrap();
+    thread::spawn(move || proxy.run());
+    (backend, address)
+}
+
+fn exchange(proxy: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+
+#[test]
+fn test_request_should_parse_headers_and_url() {
+    let text = "GET http://Site.i2p:8080/a/b?c HTTP/1.1\r\nHost: site.i2p\r\nuser-agent: Browser\r\n\r\nbody";
+    let mut reader = Cursor::new(text.as_bytes());
+    let mut request = HttpRequest::read(&mut reader).unwrap();
+    assert_eq!((request.method.as_str(), request.version.as_str()), ("GET", "HTTP/1.1"));
+    assert_eq!(request.header("User-Agent"), Some("Browser"));
+    assert_eq!(request.url(), Some(("Site.i2p".to_string(), Some(8080), "/a/b?c".to_string())));
+
+    request.set_header("Host", "other.i2p");
+    request.remove_header("USER-AGENT");
+    assert_eq!(request.to_bytes(), b"GET http://Site.i2p:8080/a/b?c HTTP/1.1\r\nHost: other.i2p\r\n\r\n".to_vec());
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "body");
+
+    for malformed in ["GET /\r\n\r\n", "GET / HTTP/1.1\r\nno colon\r\n\r\n", "GET / HTTP/1.1\r\n"].iter() {
+        match HttpRequest::read(&mut Cursor::new(malformed.as_bytes())) {
+            Err(TunnelError::InvalidRequest(_)) => {}
+            other => panic!("{:?}", other)
+        }
+    }
+    let relative = HttpRequest::read(&mut Cursor::new(&b"GET /index.html HTTP/1.1\r\n\r\n"[..])).unwrap();
+    assert_eq!(relative.url(), None);
+    let multibyte = HttpRequest::read(&mut Cursor::new("GET ééééé HTTP/1.1\r\n\r\n".as_bytes())).unwrap();
+    assert_eq!(multibyte.url(), None);
+}
+
+#[test]
+fn test_proxy_should_forward_i2p_requests_without_identifying_headers() {
+    let (backend, proxy) = start(None);
+    let response = exchange(proxy, "GET http://site.i2p/page HTTP/1.1\r\nHost: site.i2p\r\nUser-Agent: Browser/1.0\r\n\
+                                    Referer: http://secret.i2p/\r\nX-Forwarded-For: 10.0.0.1\r\nAccept: */*\r\n\
+                                    Proxy-Connection: keep-alive\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    let forwarded = response.split_once("\r\n\r\n").unwrap().1;
+    assert!(forwarded.starts_with("GET /page HTTP/1.1\r\n"));
+    assert!(forwarded.contains("Accept: */*\r\n"));
+    assert!(forwarded.contains("Host: site.i2p\r\n"));
+    assert!(forwarded.contains("Connection: close\r\n"));
+    for header in ["User-Agent", "Referer", "X-Forwarded-For", "Proxy-Connection"].iter() {
+        assert!(!forwarded.contains(header), "{} was forwarded", header);
+    }
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x01), 80)]);
+
+    let b32 = ::i2ptunnel::b32_address(&peer(0x01));
+    let response = exchange(proxy, &format!("GET http://{}:8080/ HTTP/1.1\r\n\r\n", b32));
+    assert!(response.contains(&format!("Host: {}:8080\r\n", b32)));
+    assert_eq!(backend.connections.lock().unwrap()[1], (peer(0x01), 8080));
+}
+
+#[test]
+fn test_proxy_should_tunnel_connect_requests() {
+    let (backend, proxy) = start(None);
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(b"CONNECT site.i2p:443 HTTP/1.1\r\nHost: site.i2p:443\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "HTTP/1.1 200 Connection established\r\n");
+    reader.read_line(&mut line).unwrap();
+
+    stream.write_all(b"tls hello\r\n\r\n").unwrap();
+    let mut response = String::new();
+    reader.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("tls hello\r\n"));
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x01), 443)]);
+}
+
+#[test]
+fn test_proxy_should_send_other_hosts_to_the_outproxy() {
+    let (backend, proxy) = start(Some("outproxy.i2p"));
+    let response = exchange(proxy, "GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nUser-Agent: Browser\r\n\r\n");
+    let forwarded = response.split_once("\r\n\r\n").unwrap().1;
+    assert!(forwarded.starts_with("GET http://example.com/ HTTP/1.1\r\n"));
+    assert!(!forwarded.contains("User-Agent"));
+
+    let response = exchange(proxy, "CONNECT example.com:443 HTTP/1.1\r\n\r\n");
+    assert!(response.contains("CONNECT example.com:443 HTTP/1.1\r\n"));
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x02), 0), (peer(0x02), 0)]);
+}
+
+#[test]
+fn test_proxy_should_explain_failures_with_error_pages() {
+    let (backend, proxy) = start(None);
+    let response = exchange(proxy, "GET http://nowhere.i2p/ HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.contains("Website Unknown"));
+    assert!(response.contains("<b>nowhere.i2p</b>"));
+
+    let response = exchange(proxy, "GET http://down.i2p/ HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+    assert!(response.contains("Website Unreachable"));
+
+    let response = exchange(proxy, "GET http://example.com/ HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+    let response = exchange(proxy, "GET /relative HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(backend.connections.lock().unwrap().is_empty());
+
+    let page = String::from_utf8(error_page(&TunnelError::UnknownHost("<script>.i2p".to_string()))).unwrap();
+    assert!(page.contains("&lt;script&gt;.i2p"));
+    let length: usize = page.lines().find(|line| line.starts_with("Content-Length: ")).unwrap()[16..].parse().unwrap();
+    assert_eq!(length, page.split_once("\r\n\r\n").unwrap().1.len());
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use common::Destination;
+use i2ptunnel::{TunnelDefinition, TunnelError, TunnelFactory, TunnelManager};
+use tests::i2ptunnel::http_proxy::{peer, FakeBackend};
+use tests::i2ptunnel::server::{keys, open, start_line_echo, FakeServerBackend};
+use tests::util::temp_path;
+
+
+/// The sending end of a fake server session's connections.
+type Incoming = Sender<(Destination, TcpStream)>;
+
+/// Opens fake sessions whose client connections all lead to `service`. Records
+/// the tunnels it opens sessions for, and keeps the sender of each server session.
+struct FakeFactory {
+    service: SocketAddr,
+    opened: Arc<Mutex<Vec<String>>>,
+    servers: Arc<Mutex<HashMap<String, Incoming>>>
+}
+
+impl TunnelFactory for FakeFactory {
+    type Client = FakeBackend;
+    type Server = FakeServerBackend;
+
+    fn client_backend(&self, definition: &TunnelDefinition) -> io::Result<FakeBackend> {
+        self.opened.lock().unwrap().push(definition.name.clone());
+        Ok(FakeBackend::new(self.service))
+    }
+
+    fn server_backend(&self, definition: &TunnelDefinition) -> io::Result<FakeServerBackend> {
+        self.opened.lock().unwrap().push(definition.name.clone());
+        let (backend, sender) = FakeServerBackend::new();
+        self.servers.lock().unwrap().insert(definition.name.clone(), sender);
+        Ok(backend)
+    }
+}
+
+fn echo(mut stream: TcpStream, text: &str) -> String {
+    stream.write_all(text.as_bytes()).unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    line
+}
+
+/// Writes the configuration, making sure its modification time changes.
+fn write_config(path: &PathBuf, text: &str) {
+    thread::sleep(Duration::from_millis(20));
+    fs::write(path, text).unwrap();
+}
+
+
+#[test]
+fn test_manager_should_start_stop_and_reload_tunnels() {
+    let dir = temp_path("manager");
+    fs::create_dir_all(&dir).unwrap();
+    keys().save(dir.join("web.dat")).unwrap();
+    let service = start_line_echo();
+    let path = dir.join("tunnels.conf");
+    write_config(&path, &format!("[site]\ntype = client\nport = 0\ndestination = site.i2p\n\
+                                  [web]\ntype = server\nport = {}\nkeys = web.dat\n\
+                                  [later]\ntype = socks\nport = 0\n\
+                                  [udp]\ntype = udpclient\nport = 0\n", service.port()));
+
+    let opened = Arc::new(Mutex::new(Vec::new()));
+    let servers = Arc::new(Mutex::new(HashMap::new()));
+    let factory = FakeFactory { service, opened: opened.clone(), servers: servers.clone() };
+    let mut manager = TunnelManager::new(&path, factory);
+    manager.reload().unwrap();
+    assert_eq!(manager.running(), vec!["later", "site", "web"]);
+    assert_eq!(manager.warnings().len(), 1);
+    assert!(!manager.reload_if_changed().unwrap());
+
+    let site = manager.local_addr("site").unwrap();
+    assert_eq!(echo(TcpStream::connect(site).unwrap(), "hello\n"), "hello\n");
+    let web = servers.lock().unwrap()["web"].clone();
+    assert_eq!(echo(open(&web, peer(0x01)), "served\n"), "served\n");
+    assert_eq!(manager.local_addr("web"), None);
+
+    // The changed tunnel restarts, the removed one stops, and the unchanged one
+    // keeps running.
+    write_config(&path, "[site]\ntype = client\nport = 0\ndestination = site.i2p\ndestinationport = 81\n\
+                         [later]\ntype = socks\nport = 0\n");
+    assert!(manager.reload_if_changed().unwrap());
+    assert_eq!(manager.running(), vec!["later", "site"]);
+    assert!(manager.warnings().is_empty());
+    assert!(web.send((peer(0x01), TcpStream::connect(service).unwrap())).is_err());
+    assert_eq!(*opened.lock().unwrap(), vec!["site", "web", "later", "site"]);
+    let site = manager.local_addr("site").unwrap();
+    assert_eq!(echo(TcpStream::connect(site).unwrap(), "again\n"), "again\n");
+
+    // An invalid configuration leaves the tunnels running.
+    write_config(&path, "[site]\ntype = client\n");
+    match manager.reload_if_changed() {
+        Err(TunnelError::InvalidConfig(_)) => {}
+        other => panic!("{:?}", other)
+    }
+    assert_eq!(manager.running(), vec!["later", "site"]);
+    assert!(!manager.reload_if_changed().unwrap());
+
+    assert!(manager.stop("site"));
+    assert!(!manager.stop("site"));
+    assert!(TcpStream::connect(site).is_err());
+    manager.stop_all();
+    assert!(manager.running().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_manager_should_report_tunnels_that_fail_to_start() {
+    let dir = temp_path("manager");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("i2ptunnel.config");
+    write_config(&path, "tunnel.0.name=web\ntunnel.0.type=httpserver\ntunnel.0.targetPort=80\n\
+                         tunnel.0.privKeyFile=missing.dat\n\
+                         tunnel.1.name=proxy\ntunnel.1.type=httpclient\ntunnel.1.listenPort=0\n");
+
+    let factory = FakeFactory {
+        service: start_line_echo(),
+        opened: Arc::new(Mutex::new(Vec::new())),
+        servers: Arc::new(Mutex::new(HashMap::new()))
+    };
+    let mut manager = TunnelManager::new(&path, factory);
+    match manager.reload() {
+        Err(TunnelError::Io(_)) => {}
+        other => panic!("{:?}", other)
+    }
+    assert_eq!(manager.running(), vec!["proxy"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
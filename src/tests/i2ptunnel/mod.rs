@@ -0,0 +1,6 @@
+mod naming;
+pub mod http_proxy;
+mod socks;
+pub mod server;
+mod config;
+mod manager;
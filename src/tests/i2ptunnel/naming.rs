@@ -0,0 +1,39 @@
+use common::Hashable256;
+use i2ptunnel::{TunnelError, b32_address, b32_hash, is_i2p_host, resolve};
+use super::http_proxy::{FakeBackend, peer};
+
+
+#[test]
+fn test_b32_addresses_should_round_trip_the_destination_hash() {
+    let destination = peer(0x07);
+    let address = b32_address(&destination);
+    assert_eq!(address.len(), 52 + ".b32.i2p".len());
+    assert!(address.ends_with(".b32.i2p"));
+    assert!(address.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'.'));
+    assert_eq!(b32_hash(&address), Some(destination.hash_sha256()));
+    assert_eq!(b32_hash(&address.to_ascii_uppercase()), Some(destination.hash_sha256()));
+
+    assert_eq!(b32_hash("example.i2p"), None);
+    assert_eq!(b32_hash(&address[1..]), None);
+    assert_eq!(b32_hash(&format!("1{}", &address[1..])), None);
+}
+
+#[test]
+fn test_hosts_should_resolve_by_address_book_hash_or_base64() {
+    let backend = FakeBackend::new("127.0.0.1:9".parse().unwrap());
+    assert!(is_i2p_host("Site.I2P"));
+    assert!(!is_i2p_host("example.com"));
+
+    assert_eq!(resolve(&backend, "site.i2p").unwrap(), peer(0x01));
+    assert_eq!(resolve(&backend, "SITE.i2p").unwrap(), peer(0x01));
+    assert_eq!(resolve(&backend, &b32_address(&peer(0x01))).unwrap(), peer(0x01));
+    let base64 = peer(0x05).to_i2p_base64();
+    assert_eq!(resolve(&backend, &base64).unwrap(), peer(0x05));
+    assert_eq!(resolve(&backend, &format!("{}.i2p", base64)).unwrap(), peer(0x05));
+
+    match resolve(&backend, "nowhere.i2p") {
+        Err(TunnelError::UnknownHost(host)) => assert_eq!(host, "nowhere.i2p"),
+        other => panic!("{:?}", other)
+    }
+    assert!(resolve(&backend, &b32_address(&peer(0x09))).is_err());
+}
@@ -0,0 +1,259 @@
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use common::{Destination, Hashable256, Mapping, ToI2pBase64};
+use i2ptunnel::{PrivateKeyFile, ServerBackend, ServerTunnel, ServerTunnelConfig, ServerType, TunnelError};
+use streaming::ByteStream;
+use i2ptunnel::{b32_address, DEST_B32_HEADER, DEST_B64_HEADER, DEST_HASH_HEADER};
+use tests::i2ptunnel::http_proxy::{peer, start_echo_server};
+use tests::util::temp_path;
+
+
+/// Hands the tunnel the connections that `open` makes, and records what it
+/// publishes.
+pub struct FakeServerBackend {
+    incoming: Mutex<Receiver<(Destination, TcpStream)>>,
+    pub published: Mutex<Vec<(Destination, Mapping)>>,
+    pub closed: AtomicBool
+}
+
+impl FakeServerBackend {
+    pub fn new() -> (FakeServerBackend, Sender<(Destination, TcpStream)>) {
+        let (sender, receiver) = channel();
+        let backend = FakeServerBackend {
+            incoming: Mutex::new(receiver),
+            published: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false)
+        };
+        (backend, sender)
+    }
+}
+
+impl ServerBackend for FakeServerBackend {
+    fn publish(&self, keys: &PrivateKeyFile, options: &Mapping) -> io::Result<()> {
+        self.published.lock().unwrap().push((keys.destination.clone(), options.clone()));
+        Ok(())
+    }
+
+    fn accept(&self) -> io::Result<(Destination, Box<dyn ByteStream>)> {
+        let incoming = self.incoming.lock().unwrap();
+        while !self.closed.load(Ordering::SeqCst) {
+            match incoming.recv_timeout(Duration::from_millis(10)) {
+                Ok((client, stream)) => return Ok((client, Box::new(stream))),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotConnected, "session closed"))
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn keys() -> PrivateKeyFile {
+    let mut bytes = peer(0x09).as_ref().to_vec();
+    bytes.extend_from_slice(&[0x11; 256]);
+    bytes.extend_from_slice(&[0x22; 32]);
+    PrivateKeyFile::from_bytes(&bytes).unwrap()
+}
+
+fn start(config: ServerTunnelConfig) -> (Arc<FakeServerBackend>, Sender<(Destination, TcpStream)>) {
+    let (backend, sender) = FakeServerBackend::new();
+    let backend = Arc::new(backend);
+    let tunnel = ServerTunnel::start(&keys(), &Mapping::new(), config, backend.clone()).unwrap();
+    thread::spawn(move || tunnel.run());
+    (backend, sender)
+}
+
+/// Opens a connection to the tunnel as `client` and returns the client's end.
+pub fn open(sender: &Sender<(Destination, TcpStream)>, client: Destination) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    sender.send((client, listener.accept().unwrap().0)).unwrap();
+    stream
+}
+
+/// Starts a service that sends back every line it reads.
+pub fn start_line_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    stream.write_all(line.as_bytes()).unwrap();
+                    line.clear();
+                }
+            });
+        }
+    });
+    address
+}
+
+
+#[test]
+fn test_private_key_file_should_round_trip() {
+    let keys = keys();
+    assert_eq!(keys.destination, peer(0x09));
+    assert_eq!(keys.private_key.as_ref(), &[0x11; 256][..]);
+    assert_eq!(keys.signing_private_key.as_ref(), &[0x22; 32][..]);
+
+    let mut bytes = keys.to_bytes();
+    assert_eq!(PrivateKeyFile::from_bytes(&bytes), Some(keys.clone()));
+    bytes.pop();
+    assert_eq!(PrivateKeyFile::from_bytes(&bytes), None);
+
+    let path = temp_path("keys");
+    keys.save(&path).unwrap();
+    assert_eq!(PrivateKeyFile::load(&path).unwrap(), keys);
+    fs::write(&path, &bytes).unwrap();
+    match PrivateKeyFile::load(&path) {
+        Err(TunnelError::InvalidKeyFile(_)) => {}
+        other => panic!("{:?}", other)
+    }
+    fs::remove_file(&path).unwrap();
+    match PrivateKeyFile::load(&path) {
+        Err(TunnelError::Io(_)) => {}
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_tcp_tunnel_should_publish_and_proxy() {
+    let (backend, sender) = start(ServerTunnelConfig::new(ServerType::Tcp, start_line_echo()));
+    assert_eq!(backend.published.lock().unwrap()[0].0, peer(0x09));
+
+    let stream = open(&sender, peer(0x01));
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    for text in ["hello\n", "again\n"].iter() {
+        (&stream).write_all(text.as_bytes()).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, *text);
+    }
+}
+
+#[test]
+fn test_http_tunnel_should_name_the_client() {
+    let service = start_echo_server();
+    let (_, sender) = start(ServerTunnelConfig::new(ServerType::Http, service));
+    let mut stream = open(&sender, peer(0x01));
+    stream.write_all(b"GET /page HTTP/1.1\r\nHost: site.i2p\r\nX-I2P-DestB32: spoofed.b32.i2p\r\n\
+                       Connection: keep-alive\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let forwarded = response.split_once("\r\n\r\n").unwrap().1;
+
+    let client = peer(0x01);
+    assert!(forwarded.starts_with("GET /page HTTP/1.1\r\n"));
+    assert!(forwarded.contains(&format!("Host: {}\r\n", service)));
+    assert!(forwarded.contains("Connection: close\r\n"));
+    assert!(forwarded.contains(&format!("{}: {}\r\n", DEST_HASH_HEADER, client.hash_sha256().as_ref().to_i2p_base64())));
+    assert!(forwarded.contains(&format!("{}: {}\r\n", DEST_B32_HEADER, b32_address(&client))));
+    assert!(forwarded.contains(&format!("{}: {}\r\n", DEST_B64_HEADER, client.to_i2p_base64())));
+    assert!(!forwarded.contains("spoofed"));
+
+    let mut config = ServerTunnelConfig::new(ServerType::Http, service);
+    config.host = Some("site.i2p".to_string());
+    let (_, sender) = start(config);
+    let mut stream = open(&sender, peer(0x01));
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.contains("Host: site.i2p\r\n"));
+}
+
+#[test]
+fn test_http_tunnel_should_answer_when_the_service_is_down() {
+    let unused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let (_, sender) = start(ServerTunnelConfig::new(ServerType::Http, unused));
+    let mut stream = open(&sender, peer(0x01));
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+}
+
+#[test]
+fn test_irc_tunnel_should_send_webirc_first() {
+    let mut config = ServerTunnelConfig::new(ServerType::Irc, start_line_echo());
+    config.webirc_password = Some("secret".to_string());
+    let (_, sender) = start(config);
+    let stream = open(&sender, peer(0x01));
+    (&stream).write_all(b"NICK anon\r\n").unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("WEBIRC secret cgiirc {} 127.0.0.1\r\n", b32_address(&peer(0x01))));
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "NICK anon\r\n");
+}
+
+#[test]
+fn test_tunnel_should_limit_open_connections_per_client() {
+    let mut config = ServerTunnelConfig::new(ServerType::Http, start_line_echo());
+    config.max_active_per_client = Some(1);
+    let (_, sender) = start(config);
+
+    let first = open(&sender, peer(0x01));
+    let mut refused = open(&sender, peer(0x01));
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+
+    // Another client is not affected.
+    let mut other = open(&sender, peer(0x02));
+    other.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(other).read_line(&mut line).unwrap();
+    assert_eq!(line, "GET / HTTP/1.1\r\n");
+
+    // Once the first connection ends, the client may connect again.
+    (&first).write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    first.shutdown(Shutdown::Write).unwrap();
+    let mut echoed = String::new();
+    (&first).read_to_string(&mut echoed).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let mut again = open(&sender, peer(0x01));
+    again.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(again).read_line(&mut line).unwrap();
+    assert_eq!(line, "GET / HTTP/1.1\r\n");
+}
+
+#[test]
+fn test_tunnel_should_throttle_new_connections_per_client() {
+    let mut config = ServerTunnelConfig::new(ServerType::Tcp, start_line_echo());
+    config.max_new_per_client = Some(2);
+    config.throttle_period = Duration::from_millis(300);
+    let (_, sender) = start(config);
+
+    let echo = |client: Destination| {
+        let mut stream = open(&sender, client);
+        let _ = stream.write_all(b"ping\n");
+        let _ = stream.shutdown(Shutdown::Write);
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    };
+    assert_eq!(echo(peer(0x01)), "ping\n");
+    assert_eq!(echo(peer(0x01)), "ping\n");
+    assert_eq!(echo(peer(0x01)), "");
+    assert_eq!(echo(peer(0x02)), "ping\n");
+
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(echo(peer(0x01)), "ping\n");
+}
@@ -0,0 +1,162 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use i2ptunnel::SocksProxy;
+use super::http_proxy::{FakeBackend, peer, start_echo_server};
+
+
+fn start(server: SocketAddr, outproxy: Option<&str>) -> (Arc<FakeBackend>, SocketAddr) {
+    let backend = Arc::new(FakeBackend::new(server));
+    let mut proxy = SocksProxy::bind("127.0.0.1:0", backend.clone()).unwrap();
+    proxy.set_outproxy(outproxy);
+    let address = proxy.local_addr().unwrap();
+    thread::spawn(move || proxy.run());
+    (backend, address)
+}
+
+/// Sends a head through an open tunnel and returns what the echo server answers.
+fn echo(mut stream: TcpStream) -> String {
+    stream.write_all(b"PING\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn socks5_connect(proxy: SocketAddr, address: &[u8], port: u16) -> (TcpStream, [u8; 10]) {
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(&[0x05, 0x02, 0x02, 0x00]).unwrap();
+    let mut choice = [0x00; 2];
+    stream.read_exact(&mut choice).unwrap();
+    assert_eq!(choice, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    request.extend_from_slice(address);
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).unwrap();
+    let mut reply = [0x00; 10];
+    stream.read_exact(&mut reply).unwrap();
+    (stream, reply)
+}
+
+fn domain(host: &str) -> Vec<u8> {
+    let mut address = vec![0x03, host.len() as u8];
+    address.extend_from_slice(host.as_bytes());
+    address
+}
+
+/// Starts a SOCKS5 server that records the target it is asked for, accepts it,
+/// and then echoes like the echo server.
+fn start_outproxy() -> (SocketAddr, Arc<Mutex<Vec<u8>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let requested = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requested.clone();
+    let echo = start_echo_server();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut greeting = [0x00; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[0x05, 0x00]).unwrap();
+            let mut header = [0x00; 5];
+            stream.read_exact(&mut header).unwrap();
+            let mut rest = vec![0x00; header[4] as usize + 2];
+            stream.read_exact(&mut rest).unwrap();
+            *recorded.lock().unwrap() = [&header[..], &rest[..]].concat();
+            stream.write_all(&[0x05, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x50]).unwrap();
+
+            let upstream = TcpStream::connect(echo).unwrap();
+            thread::spawn(move || ::streaming::pipe(stream, Vec::new(), Box::new(upstream)));
+        }
+    });
+    (address, requested)
+}
+
+
+#[test]
+fn test_socks5_should_connect_to_i2p_hosts() {
+    let (backend, proxy) = start(start_echo_server(), None);
+    let (stream, reply) = socks5_connect(proxy, &domain("site.i2p"), 6667);
+    assert_eq!(reply, [0x05, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    assert!(echo(stream).ends_with("PING\r\n"));
+
+    let b32 = ::i2ptunnel::b32_address(&peer(0x01));
+    let (stream, reply) = socks5_connect(proxy, &domain(&b32), 22);
+    assert_eq!(reply[1], 0x00);
+    drop(stream);
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x01), 6667), (peer(0x01), 22)]);
+}
+
+#[test]
+fn test_socks4a_should_connect_to_i2p_hosts() {
+    let (backend, proxy) = start(start_echo_server(), None);
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(b"\x04\x01\x1a\x0b\x00\x00\x00\x01user\x00site.i2p\x00").unwrap();
+    let mut reply = [0x00; 8];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, [0x00, 90, 0x1a, 0x0b, 0x00, 0x00, 0x00, 0x01]);
+    assert!(echo(stream).ends_with("PING\r\n"));
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x01), 6667)]);
+
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(b"\x04\x01\x00\x50\x00\x00\x00\x01\x00nowhere.i2p\x00").unwrap();
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply[1], 91);
+}
+
+#[test]
+fn test_socks_should_send_clearnet_hosts_to_the_outproxy() {
+    let (outproxy, requested) = start_outproxy();
+    let (backend, proxy) = start(outproxy, Some("outproxy.i2p"));
+    let (stream, reply) = socks5_connect(proxy, &domain("example.com"), 443);
+    assert_eq!(reply[1], 0x00);
+    assert!(echo(stream).ends_with("PING\r\n"));
+    assert_eq!(*requested.lock().unwrap(), [&[0x05, 0x01, 0x00, 0x03, 11][..], b"example.com", &[0x01, 0xbb][..]].concat());
+    assert_eq!(*backend.connections.lock().unwrap(), vec![(peer(0x02), 0)]);
+
+    let (_, proxy) = start(start_echo_server(), None);
+    let (_, reply) = socks5_connect(proxy, &[0x01, 93, 184, 216, 34], 80);
+    assert_eq!(reply[1], 0x02);
+}
+
+#[test]
+fn test_socks_should_reject_unsupported_requests() {
+    let (backend, proxy) = start(start_echo_server(), None);
+
+    let (_, reply) = socks5_connect(proxy, &domain("nowhere.i2p"), 80);
+    assert_eq!(reply[1], 0x04);
+    let (_, reply) = socks5_connect(proxy, &domain("down.i2p"), 80);
+    assert_eq!(reply[1], 0x05);
+
+    // BIND and UDP ASSOCIATE are not supported.
+    for command in [0x02, 0x03].iter() {
+        let mut stream = TcpStream::connect(proxy).unwrap();
+        stream.write_all(&[0x05, 0x01, 0x00]).unwrap();
+        let mut choice = [0x00; 2];
+        stream.read_exact(&mut choice).unwrap();
+        stream.write_all(&[&[0x05, *command, 0x00][..], &domain("site.i2p"), &[0x00, 0x50][..]].concat()).unwrap();
+        let mut reply = [0x00; 10];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[1], 0x07);
+    }
+
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x09]).unwrap();
+    let mut reply = [0x00; 12];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply[3], 0x08);
+
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(&[0x05, 0x01, 0x02]).unwrap();
+    let mut choice = [0x00; 2];
+    stream.read_exact(&mut choice).unwrap();
+    assert_eq!(choice, [0x05, 0xff]);
+
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream.write_all(b"\x04\x02\x00\x50\x00\x00\x00\x01\x00site.i2p\x00").unwrap();
+    let mut reply = [0x00; 8];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply[1], 91);
+    assert!(backend.connections.lock().unwrap().is_empty());
+}
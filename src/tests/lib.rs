@@ -1,4 +1,17 @@
 extern crate quickcheck;
 extern crate rand;
 
+pub mod util;
 mod common;
+pub mod netdb;
+pub mod su3;
+mod reseed;
+mod transport;
+mod tunnel;
+mod peer;
+mod garlic;
+pub mod i2cp;
+mod sam;
+mod streaming;
+mod datagram;
+mod i2ptunnel;
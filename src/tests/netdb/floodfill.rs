@@ -0,0 +1,294 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use common::{Hash256, Hashable256};
+use netdb::{Floodfill, FloodfillTransport, LookupConfig, LookupReply, NetDbStore};
+use netdb::{closest_peers, routing_key};
+use super::FakeEntry;
+use tests::util::{date, temp_path};
+
+
+const NOW: u64 = 100 * 60 * 60 * 1000;
+
+fn hash(byte: u8) -> Hash256 {
+    Hash256::from([byte; 32])
+}
+
+/// Returns the peers closest to `key` today, nearest first.
+fn closest(key: u8, peers: &[u8], count: usize, exclude: &[u8]) -> Vec<Hash256> {
+    let peers: Vec<Hash256> = peers.iter().map(|peer| hash(*peer)).collect();
+    let exclude: Vec<Hash256> = exclude.iter().map(|peer| hash(*peer)).collect();
+
+    closest_peers(&routing_key(&hash(key), date(NOW)), &peers, count, &exclude)
+}
+
+/// A floodfill whose store lives in its own directory under `root`.
+fn floodfill(root: &Path, byte: u8, peers: &[u8]) -> Floodfill<FakeEntry> {
+    let mut node = Floodfill::new(hash(byte), NetDbStore::new(root.join(format!("{:02x}", byte))));
+    for peer in peers {
+        node.add_floodfill(hash(*peer));
+    }
+
+    node
+}
+
+/// A network of floodfills that delivers messages by direct calls. Replies to
+/// lookups are queued until received, and peers that are not in the network never
+/// reply. Their stores are deleted when it is dropped.
+struct FakeNetwork {
+    root: PathBuf,
+    nodes: HashMap<Hash256, Floodfill<FakeEntry>>,
+    replies: VecDeque<(Hash256, LookupReply<FakeEntry>)>,
+    lookups: usize,
+    in_flight: usize,
+    max_in_flight: usize
+}
+
+impl FakeNetwork {
+    fn new() -> FakeNetwork {
+        FakeNetwork {
+            root: temp_path("floodfill"),
+            nodes: HashMap::new(),
+            replies: VecDeque::new(),
+            lookups: 0,
+            in_flight: 0,
+            max_in_flight: 0
+        }
+    }
+
+    fn node(&self, byte: u8, peers: &[u8]) -> Floodfill<FakeEntry> {
+        floodfill(&self.root, byte, peers)
+    }
+
+    fn add(&mut self, node: Floodfill<FakeEntry>) {
+        self.nodes.insert(node.local().clone(), node);
+    }
+}
+
+impl Drop for FakeNetwork {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+impl FloodfillTransport<FakeEntry> for FakeNetwork {
+    fn send_store(&mut self, peer: &Hash256, key: &Hash256, entry: &FakeEntry) -> bool {
+        match self.nodes.get_mut(peer) {
+            Some(node) => node.handle_store(key, entry.clone(), date(NOW)).is_ok(),
+            None => false
+        }
+    }
+
+    fn send_lookup(&mut self, peer: &Hash256, key: &Hash256, exclude: &[Hash256]) -> bool {
+        self.lookups += 1;
+        if let Some(node) = self.nodes.get(peer) {
+            self.replies.push_back((peer.clone(), node.handle_lookup(key, date(NOW), exclude)));
+            self.in_flight += 1;
+            self.max_in_flight = cmp::max(self.max_in_flight, self.in_flight);
+        }
+        true
+    }
+
+    fn receive_reply(&mut self, timeout: Duration) -> Option<(Hash256, LookupReply<FakeEntry>)> {
+        match self.replies.pop_front() {
+            Some(reply) => {
+                self.in_flight -= 1;
+                Some(reply)
+            }
+            None => {
+                thread::sleep(timeout);
+                None
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_routing_key_should_hash_the_key_with_the_utc_date() {
+    let start = 1_709_164_800_000;
+    let mut data = [0x11; 32].to_vec();
+    data.extend_from_slice(b"20240229");
+
+    assert_eq!(routing_key(&hash(0x11), date(start)), data.as_slice().hash_sha256());
+    assert_eq!(routing_key(&hash(0x11), date(start + 86_399_999)), data.as_slice().hash_sha256());
+    assert!(routing_key(&hash(0x11), date(start + 86_400_000)) != data.as_slice().hash_sha256());
+    assert!(routing_key(&hash(0x11), date(start - 1)) != data.as_slice().hash_sha256());
+}
+
+#[test]
+fn test_closest_peers_should_be_ordered_by_xor_distance() {
+    let peers = vec![hash(0x10), hash(0x13), hash(0x11), hash(0x12), hash(0x70)];
+
+    let closest = closest_peers(&hash(0x11), &peers, 3, &[hash(0x10)]);
+
+    assert_eq!(closest, vec![hash(0x11), hash(0x13), hash(0x12)]);
+}
+
+#[test]
+fn test_publish_should_flood_to_the_closest_floodfills() {
+    let peers = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17];
+    let mut network = FakeNetwork::new();
+    for peer in peers.iter() {
+        let node = network.node(*peer, &[]);
+        network.add(node);
+    }
+    let mut local = network.node(0x00, &peers);
+
+    let stored_to = local.publish(FakeEntry::new(0x11, NOW, true), date(NOW), &mut network).unwrap();
+
+    let expected = closest(0x11, &peers, 3, &[]);
+    assert_eq!(stored_to, expected);
+    assert!(local.store().path_for(&hash(0x11)).is_file());
+    for peer in peers.iter() {
+        assert_eq!(network.nodes[&hash(*peer)].get(&hash(0x11)).is_some(), expected.contains(&hash(*peer)));
+    }
+}
+
+#[test]
+fn test_store_should_reject_an_entry_under_the_wrong_key() {
+    let network = FakeNetwork::new();
+    let mut local = network.node(0x00, &[]);
+
+    assert!(local.handle_store(&hash(0x02), FakeEntry::new(0x01, NOW, true), date(NOW)).is_err());
+    assert!(local.handle_store(&hash(0x01), FakeEntry::new(0x01, NOW, false), date(NOW)).is_err());
+    assert!(local.store().is_empty());
+}
+
+#[test]
+fn test_store_should_keep_the_newest_entry() {
+    let network = FakeNetwork::new();
+    let mut local = network.node(0x00, &[]);
+
+    assert!(local.handle_store(&hash(0x01), FakeEntry::new(0x01, NOW - 10, true), date(NOW)).unwrap());
+    assert!(!local.handle_store(&hash(0x01), FakeEntry::new(0x01, NOW - 20, true), date(NOW)).unwrap());
+    assert!(local.handle_store(&hash(0x01), FakeEntry::new(0x01, NOW, true), date(NOW)).unwrap());
+    assert_eq!(local.get(&hash(0x01)), Some(&FakeEntry::new(0x01, NOW, true)));
+}
+
+#[test]
+fn test_lookup_reply_should_return_closer_peers_when_not_found() {
+    let network = FakeNetwork::new();
+    let local = network.node(0x00, &[0x10, 0x11, 0x12, 0x13]);
+
+    let reply = local.handle_lookup(&hash(0x11), date(NOW), &[hash(0x11)]);
+
+    assert_eq!(reply, LookupReply::Closer(closest(0x11, &[0x10, 0x12, 0x13], 3, &[])));
+}
+
+#[test]
+fn test_iterative_lookup_should_follow_search_replies() {
+    let mut network = FakeNetwork::new();
+    let mut client = network.node(0x00, &[0x70]);
+    let mut holder = network.node(0x11, &[]);
+    holder.handle_store(&hash(0x22), FakeEntry::new(0x22, NOW, true), date(NOW)).unwrap();
+    let (first, second) = (network.node(0x70, &[0x30]), network.node(0x30, &[0x11]));
+    network.add(first);
+    network.add(second);
+    network.add(holder);
+
+    let found = client.lookup(&hash(0x22), date(NOW), &LookupConfig::default(), &mut network);
+
+    assert_eq!(found, Some(FakeEntry::new(0x22, NOW, true)));
+    assert!(client.get(&hash(0x22)).is_some());
+    assert_eq!(network.lookups, 3);
+}
+
+#[test]
+fn test_iterative_lookup_should_stop_after_max_peers() {
+    let peers: Vec<u8> = (0x10..0x20).collect();
+    let mut network = FakeNetwork::new();
+    for peer in peers.iter() {
+        let node = network.node(*peer, &peers);
+        network.add(node);
+    }
+    let mut client = network.node(0x00, &peers);
+    let config = LookupConfig { parallelism: 2, max_peers: 5, ..LookupConfig::default() };
+
+    let found = client.lookup(&hash(0x22), date(NOW), &config, &mut network);
+
+    assert!(found.is_none());
+    assert_eq!(network.lookups, 5);
+}
+
+#[test]
+fn test_iterative_lookup_should_query_peers_in_parallel() {
+    let peers: Vec<u8> = (0x10..0x20).collect();
+    let mut network = FakeNetwork::new();
+    for peer in peers.iter() {
+        let node = network.node(*peer, &peers);
+        network.add(node);
+    }
+    let mut client = network.node(0x00, &peers);
+    let config = LookupConfig { parallelism: 3, max_peers: 8, ..LookupConfig::default() };
+
+    assert!(client.lookup(&hash(0x22), date(NOW), &config, &mut network).is_none());
+
+    assert_eq!(network.max_in_flight, 3);
+    assert_eq!(network.lookups, 8);
+}
+
+#[test]
+fn test_iterative_lookup_should_move_on_from_silent_peers() {
+    let mut network = FakeNetwork::new();
+    let mut holder = network.node(0x11, &[]);
+    holder.handle_store(&hash(0x22), FakeEntry::new(0x22, NOW, true), date(NOW)).unwrap();
+    network.add(holder);
+    // The peers closer than the holder are silent, so the holder is only queried
+    // once their queries time out.
+    let pool: Vec<u8> = (0x30..0x90).chain(Some(0x11)).collect();
+    let ordered: Vec<u8> = closest(0x22, &pool, pool.len(), &[]).iter().map(|peer| peer.as_ref()[0]).collect();
+    let silent: Vec<u8> = ordered.iter().cloned().take_while(|peer| *peer != 0x11).take(4).collect();
+    assert!(silent.len() >= 2);
+    let mut client = network.node(0x00, &silent);
+    client.add_floodfill(hash(0x11));
+    let config = LookupConfig {
+        parallelism: 2,
+        query_timeout: Duration::from_millis(50),
+        timeout: Duration::from_secs(5),
+        ..LookupConfig::default()
+    };
+
+    let started = Instant::now();
+    let found = client.lookup(&hash(0x22), date(NOW), &config, &mut network);
+
+    assert_eq!(found, Some(FakeEntry::new(0x22, NOW, true)));
+    assert_eq!(network.lookups, silent.len() + 1);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_iterative_lookup_should_give_up_at_the_deadline() {
+    let mut network = FakeNetwork::new();
+    let mut client = network.node(0x00, &[0x30, 0x31, 0x32]);
+    let config = LookupConfig { timeout: Duration::from_millis(100), ..LookupConfig::default() };
+
+    let started = Instant::now();
+    assert!(client.lookup(&hash(0x22), date(NOW), &config, &mut network).is_none());
+
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_secs(1));
+}
+
+#[test]
+fn test_verify_store_should_query_a_floodfill_not_stored_to() {
+    let peers = [0x10, 0x11, 0x12, 0x13];
+    let mut network = FakeNetwork::new();
+    for peer in peers.iter() {
+        let node = network.node(*peer, &[]);
+        network.add(node);
+    }
+    let mut local = network.node(0x00, &peers);
+    let entry = FakeEntry::new(0x11, NOW, true);
+    let stored_to = local.publish(entry.clone(), date(NOW), &mut network).unwrap();
+
+    let config = LookupConfig::default();
+    assert!(!local.verify_store(&hash(0x11), date(NOW), &stored_to, &config, &mut network));
+
+    let other = peers.iter().map(|peer| hash(*peer)).find(|peer| !stored_to.contains(peer)).unwrap();
+    network.send_store(&other, &hash(0x11), &entry);
+    assert!(local.verify_store(&hash(0x11), date(NOW), &stored_to, &config, &mut network));
+}
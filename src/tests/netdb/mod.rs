@@ -0,0 +1,65 @@
+use common::{Hash256, Hashable256, I2pDate, I2pInt64};
+use netdb::NetDbEntry;
+use serialize::{Serialize, Deserialize};
+
+mod store;
+mod floodfill;
+
+
+/// A stand-in for a `RouterInfo`: a hash, a publication date and a signature flag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeEntry {
+    hash: [u8; 32],
+    published: I2pDate,
+    signed: bool
+}
+
+impl FakeEntry {
+    pub fn new(byte: u8, published: u64, signed: bool) -> FakeEntry {
+        FakeEntry {
+            hash: [byte; 32],
+            published: I2pDate::new(I2pInt64::new(published)).unwrap(),
+            signed
+        }
+    }
+}
+
+impl Hashable256 for FakeEntry {
+    fn hash_sha256(&self) -> Hash256 {
+        Hash256::from(self.hash)
+    }
+}
+
+impl NetDbEntry for FakeEntry {
+    fn from_bytes(bytes: &[u8]) -> Option<FakeEntry> {
+        if bytes.len() != 41 {
+            return None;
+        }
+
+        let mut hash = [0x00; 32];
+        hash.copy_from_slice(&bytes[0..32]);
+        let published = match <I2pDate as Deserialize>::deserialize(&bytes[32..40]) {
+            Ok(date) => date,
+            Err(_) => return None
+        };
+
+        Some(FakeEntry { hash, published, signed: bytes[40] == 1 })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0x00; 41];
+        bytes[0..32].copy_from_slice(&self.hash);
+        Serialize::serialize(&self.published, &mut bytes[32..40]).unwrap();
+        bytes[40] = if self.signed { 1 } else { 0 };
+
+        bytes
+    }
+
+    fn published(&self) -> I2pDate {
+        self.published
+    }
+
+    fn verify_signature(&self) -> bool {
+        self.signed
+    }
+}
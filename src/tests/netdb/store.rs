@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::PathBuf;
+use common::Hash256;
+use netdb::NetDbStore;
+use super::FakeEntry;
+use tests::util::{date, temp_path};
+
+
+const HOUR: u64 = 60 * 60 * 1000;
+
+
+#[test]
+fn test_path_should_follow_the_java_router_layout() {
+    let store: NetDbStore<FakeEntry> = NetDbStore::new("netDb");
+    let path = store.path_for(&Hash256::from([0xFF; 32]));
+
+    let expected = "netDb/r~/routerInfo-~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~8=.dat";
+    assert_eq!(path, PathBuf::from(expected));
+}
+
+#[test]
+fn test_inserted_entries_should_load_into_a_new_store() {
+    let root = temp_path("netdb").join("netDb");
+    let now = 10 * HOUR;
+    let mut store = NetDbStore::new(&root);
+    store.insert(FakeEntry::new(0x01, now, true), date(now)).unwrap();
+    store.insert(FakeEntry::new(0x02, now, true), date(now)).unwrap();
+
+    let mut reloaded: NetDbStore<FakeEntry> = NetDbStore::new(&root);
+    let loaded = reloaded.load(date(now)).unwrap();
+
+    assert_eq!(loaded, 2);
+    assert!(reloaded.contains(&Hash256::from([0x01; 32])));
+    assert!(reloaded.contains(&Hash256::from([0x02; 32])));
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_insert_should_reject_invalid_entries() {
+    let root = temp_path("netdb").join("netDb");
+    let now = 100 * HOUR;
+    let mut store = NetDbStore::new(&root);
+
+    assert!(store.insert(FakeEntry::new(0x01, now, false), date(now)).is_err());
+    assert!(store.insert(FakeEntry::new(0x02, now - 28 * HOUR, true), date(now)).is_err());
+    assert!(store.insert(FakeEntry::new(0x03, now + HOUR, true), date(now)).is_err());
+    assert!(store.is_empty());
+    assert!(!root.exists());
+}
+
+#[test]
+fn test_load_should_skip_expired_and_misnamed_files() {
+    let root = temp_path("netdb").join("netDb");
+    let now = 100 * HOUR;
+    let mut store = NetDbStore::new(&root);
+    store.insert(FakeEntry::new(0x01, now - 20 * HOUR, true), date(now)).unwrap();
+    store.insert(FakeEntry::new(0x02, now, true), date(now)).unwrap();
+    let misnamed = store.path_for(&Hash256::from([0x03; 32]));
+    fs::create_dir_all(misnamed.parent().unwrap()).unwrap();
+    fs::copy(store.path_for(&Hash256::from([0x02; 32])), &misnamed).unwrap();
+
+    let mut reloaded: NetDbStore<FakeEntry> = NetDbStore::new(&root);
+    let loaded = reloaded.load(date(now + 10 * HOUR)).unwrap();
+
+    assert_eq!(loaded, 1);
+    assert!(reloaded.contains(&Hash256::from([0x02; 32])));
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_prune_should_delete_expired_entries_from_disk() {
+    let root = temp_path("netdb").join("netDb");
+    let now = 100 * HOUR;
+    let mut store = NetDbStore::new(&root);
+    store.insert(FakeEntry::new(0x01, now - 20 * HOUR, true), date(now)).unwrap();
+    store.insert(FakeEntry::new(0x02, now, true), date(now)).unwrap();
+    let expired_path = store.path_for(&Hash256::from([0x01; 32]));
+
+    let pruned = store.prune(date(now + 10 * HOUR)).unwrap();
+
+    assert_eq!(pruned, 1);
+    assert_eq!(store.len(), 1);
+    assert!(!expired_path.exists());
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,2 @@
+mod profile;
+mod selector;
@@ -0,0 +1,57 @@
+use std::fs;
+use common::Hash256;
+use peer::{PeerProfile, ProfileStore};
+use tests::util::{date, temp_path};
+
+
+#[test]
+fn test_builds_should_update_capacity_and_failures() {
+    let mut store = ProfileStore::new("peerProfiles");
+    let peer = Hash256::from([0x01; 32]);
+    store.record_build(&peer, true, date(10));
+    store.record_build(&peer, false, date(20));
+    store.record_build(&peer, false, date(30));
+
+    let profile = store.get(&peer).unwrap();
+    assert_eq!((profile.build_successes, profile.build_failures, profile.consecutive_failures), (1, 2, 2));
+    assert_eq!(profile.capacity(), 0.4);
+    assert_eq!(profile.last_seen(), Some(date(10)));
+    assert_eq!(PeerProfile::default().capacity(), 0.5);
+}
+
+#[test]
+fn test_latency_should_be_a_moving_average() {
+    let mut store = ProfileStore::new("peerProfiles");
+    let peer = Hash256::from([0x01; 32]);
+    store.record_latency(&peer, 400, date(10));
+    store.record_latency(&peer, 800, date(20));
+
+    assert_eq!(store.get(&peer).unwrap().latency, Some(500));
+    assert_eq!(store.get(&peer).unwrap().last_seen(), Some(date(20)));
+}
+
+#[test]
+fn test_profiles_should_survive_a_restart() {
+    let root = temp_path("profiles").join("peerProfiles");
+    let mut store = ProfileStore::new(&root);
+    store.record_build(&Hash256::from([0x01; 32]), true, date(10));
+    store.record_latency(&Hash256::from([0x02; 32]), 250, date(10));
+    store.save().unwrap();
+    fs::write(root.join("profile-garbage.txt"), "buildSuccesses=1\n").unwrap();
+
+    let mut reloaded = ProfileStore::new(&root);
+    assert_eq!(reloaded.load().unwrap(), 2);
+    assert_eq!(reloaded.get(&Hash256::from([0x01; 32])), store.get(&Hash256::from([0x01; 32])));
+    assert_eq!(reloaded.get(&Hash256::from([0x02; 32])).unwrap().latency, Some(250));
+
+    assert_eq!(reloaded.prune(date(5_000)).unwrap(), 2);
+    assert!(!reloaded.path_for(&Hash256::from([0x01; 32])).exists());
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_malformed_profiles_should_be_rejected() {
+    assert_eq!(PeerProfile::from_text("buildSuccesses=many\n"), None);
+    assert_eq!(PeerProfile::from_text("latency\n"), None);
+    assert_eq!(PeerProfile::from_text("unknown=1\nlatency=7\n").unwrap().latency, Some(7));
+}
@@ -0,0 +1,83 @@
+use std::net::Ipv4Addr;
+use common::Hash256;
+use peer::{PeerCandidate, PeerSelector, PeerTier, ProfileStore};
+use tests::util::date;
+
+
+fn candidate(id: u8, ip: [u8; 4], family: Option<&str>) -> PeerCandidate {
+    PeerCandidate::new(Hash256::from([id; 32]), Some(Ipv4Addr::from(ip)), family.map(|family| family.to_string()))
+}
+
+/// Peers 1 to 6, each in its own /16. Peers 1 to 3 agree to every build and peer 1
+/// answers fastest; peers 4 to 6 reject most builds.
+fn profiled_peers() -> (ProfileStore, Vec<PeerCandidate>) {
+    let mut store = ProfileStore::new("peerProfiles");
+    let candidates: Vec<PeerCandidate> = (1..7).map(|id| candidate(id, [10, id, 0, 1], None)).collect();
+    for id in 1..7 {
+        let peer = Hash256::from([id; 32]);
+        for _ in 0..4 {
+            store.record_build(&peer, id <= 3, date(1));
+        }
+        store.record_latency(&peer, id as u64 * 100, date(1));
+    }
+
+    (store, candidates)
+}
+
+
+#[test]
+fn test_tiers_should_follow_capacity_and_latency() {
+    let (store, candidates) = profiled_peers();
+    let selector = PeerSelector::new(&store);
+
+    let hashes = |peers: Vec<&PeerCandidate>| peers.into_iter().map(|peer| peer.hash.as_ref()[0]).collect::<Vec<u8>>();
+    let mut high_capacity = hashes(selector.tier(&candidates, PeerTier::HighCapacity));
+    high_capacity.sort();
+    assert_eq!(high_capacity, vec![1, 2, 3]);
+    assert_eq!(hashes(selector.tier(&candidates, PeerTier::Fast)), vec![1, 2]);
+    assert_eq!(selector.tier(&candidates, PeerTier::Any).len(), 6);
+}
+
+#[test]
+fn test_select_should_prefer_the_tier_and_fall_back() {
+    let (store, candidates) = profiled_peers();
+    let selector = PeerSelector::new(&store);
+
+    let hops = selector.select(&candidates, 2, PeerTier::Fast, &[]).unwrap();
+    assert!(hops.contains(&Hash256::from([1; 32])) && hops.contains(&Hash256::from([2; 32])));
+
+    let hops = selector.select(&candidates, 4, PeerTier::HighCapacity, &[Hash256::from([2; 32])]).unwrap();
+    assert!(hops.contains(&Hash256::from([1; 32])) && hops.contains(&Hash256::from([3; 32])));
+    assert!(!hops.contains(&Hash256::from([2; 32])));
+    assert_eq!(selector.select(&candidates, 7, PeerTier::Any, &[]), None);
+}
+
+#[test]
+fn test_select_should_not_pick_related_peers() {
+    let store = ProfileStore::new("peerProfiles");
+    let selector = PeerSelector::new(&store);
+    let candidates = vec![candidate(1, [10, 1, 0, 1], None),
+                          candidate(2, [10, 1, 200, 7], None),
+                          candidate(3, [10, 2, 0, 1], Some("acme")),
+                          candidate(4, [10, 3, 0, 1], Some("acme"))];
+
+    assert!(candidates[0].is_related(&candidates[1]));
+    assert!(candidates[2].is_related(&candidates[3]));
+    assert!(!candidates[0].is_related(&candidates[2]));
+    for _ in 0..20 {
+        assert_eq!(selector.select(&candidates, 2, PeerTier::Any, &[]).unwrap().len(), 2);
+        assert_eq!(selector.select(&candidates, 3, PeerTier::Any, &[]), None);
+    }
+}
+
+#[test]
+fn test_failing_peers_should_not_be_selected() {
+    let mut store = ProfileStore::new("peerProfiles");
+    for _ in 0..5 {
+        store.record_build(&Hash256::from([1; 32]), false, date(1));
+    }
+    let selector = PeerSelector::new(&store);
+    let candidates = vec![candidate(1, [10, 1, 0, 1], None), candidate(2, [10, 2, 0, 1], None)];
+
+    assert_eq!(selector.select(&candidates, 1, PeerTier::Any, &[]), Some(vec![Hash256::from([2; 32])]));
+}
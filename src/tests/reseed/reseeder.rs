@@ -0,0 +1,242 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use rcgen::{self, CertifiedKey};
+use rustls::{self, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use common::{Ed25519Signer, Hash256, Hashable256, I2pDate, I2pInt64};
+use common::{Mapping, RouterIdentity, RouterInfo};
+use netdb::{NetDbEntry, NetDbStore};
+use reseed::{Reseeder, ReseedError, ReseedFetcher, FileFetcher, HttpFetcher};
+use su3::CertificateStore;
+use tests::netdb::FakeEntry;
+use tests::su3::{FakeVerifier, fake_key, su3_file, zip_archive};
+use tests::util::{date, temp_path};
+
+
+const NOW: u64 = 100 * 60 * 60 * 1000;
+
+fn bundle(bytes: &[u8]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = bytes.iter().map(|byte| FakeEntry::new(*byte, NOW, true).to_bytes()).collect();
+    let names: Vec<String> = bytes.iter().map(|byte| format!("routerInfo-{:02x}.dat", byte)).collect();
+    let files: Vec<(&str, &[u8])> = names.iter()
+        .map(|name| name.as_str())
+        .zip(entries.iter().map(|entry| entry.as_slice()))
+        .collect();
+
+    su3_file("reseed@example.i2p", &zip_archive(&files, true))
+}
+
+/// Answers a single request with `body` for `/i2pseeds.su3`, and a 404 for any other path.
+fn serve<S: Read + Write>(mut stream: S, body: &[u8]) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0x00; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    if request.starts_with(b"GET /i2pseeds.su3 HTTP/1.0\r\n") {
+        write!(stream, "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())?;
+        stream.write_all(body)?;
+    } else {
+        stream.write_all(b"HTTP/1.0 404 Not Found\r\n\r\n")?;
+    }
+    stream.flush()
+}
+
+/// Serves `body` over plain HTTP to a single client.
+fn http_stand_in(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, &body).unwrap();
+    });
+
+    format!("http://{}/", address)
+}
+
+/// Serves `body` over HTTPS to a single client, with a self-signed certificate for
+/// `localhost`. Returns the source URL and the certificate.
+fn https_stand_in(body: Vec<u8>) -> (String, CertificateDer<'static>) {
+    let CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let certificate = cert.der().clone();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate.clone()], key)
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let connection = ServerConnection::new(Arc::new(config)).unwrap();
+        // A client that rejects the certificate closes the connection mid-handshake.
+        let _ = serve(StreamOwned::new(connection, stream), &body);
+    });
+
+    (format!("https://localhost:{}/", port), certificate)
+}
+
+fn certificates() -> CertificateStore {
+    let mut certificates = CertificateStore::new();
+    certificates.insert("reseed@example.i2p", fake_key());
+
+    certificates
+}
+
+
+#[test]
+fn test_reseed_should_store_router_infos_from_agreeing_http_sources() {
+    let sources = vec![http_stand_in(bundle(&[0x01, 0x02])), http_stand_in(bundle(&[0x02, 0x03]))];
+    let reseeder = Reseeder::new(sources, HttpFetcher::default(), certificates(), FakeVerifier);
+    let root = temp_path("reseed");
+    let mut store: NetDbStore<FakeEntry> = NetDbStore::new(root.join("netDb"));
+
+    let stored = reseeder.reseed(&mut store, I2pDate::new(I2pInt64::new(NOW)).unwrap()).unwrap();
+
+    assert_eq!(stored, 1);
+    assert!(store.contains(&Hash256::from([0x02; 32])));
+    assert!(!store.contains(&Hash256::from([0x01; 32])));
+    assert!(!store.contains(&Hash256::from([0x03; 32])));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_reseed_should_drop_router_infos_from_a_single_source() {
+    let sources = vec![
+        http_stand_in(bundle(&[0x01, 0x02, 0x04])),
+        http_stand_in(bundle(&[0x02, 0x03, 0x04])),
+        http_stand_in(bundle(&[0x02, 0x05, 0x05])),
+    ];
+    let mut reseeder = Reseeder::new(sources, HttpFetcher::default(), certificates(), FakeVerifier);
+    reseeder.set_min_sources(3);
+
+    let mut hashes: Vec<Hash256> = reseeder.fetch::<FakeEntry>().unwrap()
+        .iter()
+        .map(|router_info| router_info.hash_sha256())
+        .collect();
+    hashes.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+    assert_eq!(hashes, vec![Hash256::from([0x02; 32]), Hash256::from([0x04; 32])]);
+}
+
+#[test]
+fn test_reseed_should_fail_when_too_few_sources_are_valid() {
+    let mut unsigned = bundle(&[0x02]);
+    let last = unsigned.len() - 1;
+    unsigned[last] ^= 0xFF;
+    let sources = vec![
+        http_stand_in(bundle(&[0x01])),
+        http_stand_in(unsigned),
+        "http://127.0.0.1:1/".to_string(),
+    ];
+    let reseeder = Reseeder::new(sources, HttpFetcher::default(), certificates(), FakeVerifier);
+
+    match reseeder.fetch::<FakeEntry>() {
+        Err(ReseedError::NotEnoughSources(1, 2)) => {}
+        other => panic!("Expected too few valid sources, got: {:?}", other.map(|entries| entries.len()))
+    }
+}
+
+#[test]
+fn test_reseed_should_store_signed_router_infos() {
+    let signed = |seed: u8| {
+        let signer = Ed25519Signer::new(&[seed; 32]);
+        let identity = RouterIdentity::new(&[seed; 256], &signer.public_key());
+        RouterInfo::new(identity, date(NOW), Vec::new(), Mapping::new(), &signer)
+    };
+    let router_infos = vec![signed(0x01), signed(0x02)];
+    let entries: Vec<Vec<u8>> = router_infos.iter().map(|router_info| router_info.to_bytes()).collect();
+    let names: Vec<String> = (0..entries.len()).map(|index| format!("netDb/routerInfo-{}.dat", index)).collect();
+    let mut files: Vec<(&str, &[u8])> = names.iter()
+        .map(|name| name.as_str())
+        .zip(entries.iter().map(|entry| entry.as_slice()))
+        .collect();
+    let mut forged = entries[0].clone();
+    forged[200] ^= 0x01;
+    files.push(("routerInfo-forged.dat", &forged));
+
+    let directory = temp_path("reseed");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("i2pseeds.su3"), su3_file("reseed@example.i2p", &zip_archive(&files, true))).unwrap();
+    let mut reseeder = Reseeder::new(vec![directory.to_str().unwrap().to_string()],
+                                     FileFetcher, certificates(), FakeVerifier);
+    reseeder.set_min_sources(1);
+    let mut store: NetDbStore<RouterInfo> = NetDbStore::new(directory.join("netDb"));
+
+    let stored = reseeder.reseed(&mut store, date(NOW)).unwrap();
+
+    assert_eq!(stored, 2);
+    for router_info in router_infos {
+        let hash = router_info.hash_sha256();
+        assert_eq!(store.get(&hash), Some(&router_info));
+        assert!(store.path_for(&hash).exists());
+    }
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_file_fetcher_should_read_bundles_from_a_directory() {
+    let directory = temp_path("reseed");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("i2pseeds.su3"), bundle(&[0x01])).unwrap();
+    let mut reseeder = Reseeder::new(vec![directory.to_str().unwrap().to_string()],
+                                     FileFetcher, certificates(), FakeVerifier);
+    reseeder.set_min_sources(1);
+
+    let router_infos: Vec<FakeEntry> = reseeder.fetch().unwrap();
+
+    assert_eq!(router_infos, vec![FakeEntry::new(0x01, NOW, true)]);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_http_fetcher_should_fetch_https_sources() {
+    let (source, certificate) = https_stand_in(bundle(&[0x01]));
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate).unwrap();
+    let fetcher = HttpFetcher::with_root_certificates(Duration::from_secs(5), roots);
+
+    assert_eq!(fetcher.fetch(&source).unwrap(), bundle(&[0x01]));
+}
+
+#[test]
+fn test_http_fetcher_should_reject_untrusted_certificates() {
+    let (source, _) = https_stand_in(bundle(&[0x01]));
+
+    assert!(HttpFetcher::default().fetch(&source).is_err());
+}
+
+#[test]
+fn test_http_fetcher_should_refuse_other_schemes() {
+    match HttpFetcher::default().fetch("ftp://reseed.example.i2p/") {
+        Err(ReseedError::UnsupportedSource(_)) => {}
+        other => panic!("Expected an unsupported source, got: {:?}", other.map(|bytes| bytes.len()))
+    }
+}
+
+#[test]
+fn test_http_fetcher_should_give_up_on_silent_servers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let source = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (_stream, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(10));
+    });
+    let fetcher = HttpFetcher::new(Duration::from_millis(200));
+    let start = Instant::now();
+
+    assert!(fetcher.fetch(&source).is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
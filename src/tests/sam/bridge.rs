@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use common::{Destination, SignatureType, ToI2pBase64};
+use sam::{AcceptedStream, DatagramSink, SamBackend, SamBridge, SamCommand, SamKeys, SamResult, SessionStyle};
+use streaming::ByteStream;
+use tests::i2cp::message::destination;
+
+
+pub fn keys() -> SamKeys {
+    let destination = destination();
+    let mut private_keys = destination.as_ref().to_vec();
+    private_keys.extend_from_slice(&[0x42; 64]);
+
+    SamKeys { destination, private_keys: private_keys.to_i2p_base64() }
+}
+
+/// Connects streams to an echo server, accepts the streams pushed by the test and
+/// records everything else. Creating the session `slow` takes a while.
+pub struct FakeBackend {
+    echo: SocketAddr,
+    pub creating: Mutex<Vec<String>>,
+    pub sessions: Mutex<Vec<(String, SessionStyle, SignatureType)>>,
+    pub sinks: Mutex<HashMap<String, DatagramSink>>,
+    pub removed: Mutex<Vec<String>>,
+    pub datagrams: Mutex<Vec<(String, Vec<u8>)>>,
+    incoming: Mutex<mpsc::Receiver<TcpStream>>
+}
+
+impl SamBackend for FakeBackend {
+    fn generate(&self, signature_type: SignatureType) -> Option<SamKeys> {
+        if signature_type == SignatureType::EdDSA_SHA512_Ed25519 { Some(keys()) } else { None }
+    }
+
+    fn create_session(&self, id: &str, style: SessionStyle, private_keys: Option<&str>, signature_type: SignatureType,
+                      options: &HashMap<String, String>, sink: DatagramSink) -> Result<SamKeys, SamResult> {
+        self.creating.lock().unwrap().push(id.to_string());
+        if id == "slow" {
+            thread::sleep(Duration::from_millis(300));
+        }
+        if private_keys.is_some() {
+            return Err(SamResult::InvalidKey);
+        }
+        assert_eq!(options.get("inbound.length").map(|length| length.as_str()), Some("1"));
+        self.sessions.lock().unwrap().push((id.to_string(), style, signature_type));
+        self.sinks.lock().unwrap().insert(id.to_string(), sink);
+        Ok(keys())
+    }
+
+    fn add_subsession(&self, _: &str, id: &str, style: SessionStyle, _: &HashMap<String, String>,
+                      sink: DatagramSink) -> Result<(), SamResult> {
+        self.sessions.lock().unwrap().push((id.to_string(), style, SignatureType::EdDSA_SHA512_Ed25519));
+        self.sinks.lock().unwrap().insert(id.to_string(), sink);
+        Ok(())
+    }
+
+    fn remove_session(&self, id: &str) {
+        self.removed.lock().unwrap().push(id.to_string());
+    }
+
+    fn connect(&self, _: &str, destination: &Destination, _: &HashMap<String, String>)
+               -> Result<Box<dyn ByteStream>, SamResult> {
+        assert_eq!(*destination, keys().destination);
+        Ok(Box::new(TcpStream::connect(self.echo).unwrap()))
+    }
+
+    fn accept(&self, _: &str) -> Result<AcceptedStream, SamResult> {
+        match self.incoming.lock().unwrap().recv() {
+            Ok(stream) => Ok(AcceptedStream { peer: keys().destination, from_port: 1234, to_port: 80, stream: Box::new(stream) }),
+            Err(_) => Err(SamResult::I2pError)
+        }
+    }
+
+    fn send_datagram(&self, id: &str, _: &Destination, payload: &[u8], _: &HashMap<String, String>)
+                     -> Result<(), SamResult> {
+        self.datagrams.lock().unwrap().push((id.to_string(), payload.to_vec()));
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<Destination> {
+        if name == "echo.i2p" { Some(keys().destination) } else { None }
+    }
+}
+
+fn echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            thread::spawn(move || io::copy(&mut stream, &mut writer));
+        }
+    });
+
+    address
+}
+
+/// Starts a bridge and returns its backend, TCP address and UDP address, and the
+/// sender of streams for the backend to accept.
+pub fn start() -> (Arc<FakeBackend>, SocketAddr, SocketAddr, mpsc::Sender<TcpStream>) {
+    let (sender, receiver) = mpsc::channel();
+    let backend = Arc::new(FakeBackend {
+        echo: echo_server(),
+        creating: Mutex::new(Vec::new()),
+        sessions: Mutex::new(Vec::new()),
+        sinks: Mutex::new(HashMap::new()),
+        removed: Mutex::new(Vec::new()),
+        datagrams: Mutex::new(Vec::new()),
+        incoming: Mutex::new(receiver)
+    });
+    let bridge = SamBridge::bind("127.0.0.1:0", "127.0.0.1:0", backend.clone()).unwrap();
+    let address = bridge.local_addr().unwrap();
+    let udp_address = bridge.udp_addr().unwrap();
+    thread::spawn(move || bridge.run());
+
+    (backend, address, udp_address, sender)
+}
+
+/// Waits up to a second for a condition that another thread makes true.
+pub fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+    for _ in 0..100 {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    false
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream
+}
+
+impl Client {
+    fn connect(address: SocketAddr) -> Client {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            stream
+        }
+    }
+
+    /// Connects and negotiates SAM 3.3.
+    fn hello(address: SocketAddr) -> Client {
+        let mut client = Client::connect(address);
+        assert_eq!(client.command("HELLO VERSION MIN=3.0 MAX=3.3").get("VERSION"), Some("3.3"));
+        client
+    }
+
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    fn command(&mut self, line: &str) -> SamCommand {
+        writeln!(self.stream, "{}", line).unwrap();
+        SamCommand::parse(&self.read_line()).unwrap()
+    }
+
+    fn is_closed(&mut self) -> bool {
+        self.read_line().is_empty()
+    }
+}
+
+
+#[test]
+fn test_bridge_should_negotiate_the_version() {
+    let (_, address, _, _) = start();
+
+    let mut client = Client::connect(address);
+    let reply = client.command("HELLO VERSION MIN=3.0 MAX=3.1");
+    assert!(reply.is("HELLO", "REPLY"));
+    assert_eq!(reply.get("VERSION"), Some("3.1"));
+    assert_eq!(client.command("PING 12345").to_string(), "PONG 12345");
+
+    let mut client = Client::connect(address);
+    assert_eq!(client.command("HELLO VERSION MIN=3.4 MAX=4.0").result(), Some(SamResult::NoVersion));
+    assert!(client.is_closed());
+
+    let mut client = Client::connect(address);
+    writeln!(client.stream, "NAMING LOOKUP NAME=echo.i2p").unwrap();
+    assert!(client.is_closed());
+}
+
+#[test]
+fn test_bridge_should_create_sessions_and_answer_lookups() {
+    let (backend, address, _, _) = start();
+    let public = keys().destination.to_i2p_base64();
+
+    let mut control = Client::hello(address);
+    let reply = control.command("SESSION CREATE STYLE=STREAM ID=web DESTINATION=TRANSIENT SIGNATURE_TYPE=EdDSA_SHA512_Ed25519 inbound.length=1");
+    assert!(reply.is("SESSION", "STATUS"));
+    assert_eq!(reply.result(), Some(SamResult::Ok));
+    assert_eq!(reply.get("DESTINATION"), Some(keys().private_keys.as_str()));
+    assert_eq!(backend.sessions.lock().unwrap()[0], ("web".to_string(), SessionStyle::Stream, SignatureType::EdDSA_SHA512_Ed25519));
+
+    assert_eq!(control.command("NAMING LOOKUP NAME=ME").get("VALUE"), Some(public.as_str()));
+    assert_eq!(control.command("NAMING LOOKUP NAME=echo.i2p").get("VALUE"), Some(public.as_str()));
+    assert_eq!(control.command("NAMING LOOKUP NAME=nowhere.i2p").result(), Some(SamResult::KeyNotFound));
+    assert_eq!(control.command("DEST GENERATE SIGNATURE_TYPE=7").get("PUB"), Some(public.as_str()));
+    assert_eq!(control.command("DEST GENERATE").result(), Some(SamResult::I2pError));
+    assert_eq!(control.command("SESSION CREATE STYLE=STREAM ID=other DESTINATION=TRANSIENT").result(), Some(SamResult::I2pError));
+
+    let mut second = Client::hello(address);
+    assert_eq!(second.command("SESSION CREATE STYLE=RAW ID=web DESTINATION=TRANSIENT").result(), Some(SamResult::DuplicatedId));
+    assert_eq!(second.command("SESSION CREATE STYLE=RAW ID=raw DESTINATION=AAAA").result(), Some(SamResult::InvalidKey));
+    assert_eq!(second.command("SESSION CREATE STYLE=SEQPACKET ID=raw DESTINATION=TRANSIENT").result(), Some(SamResult::I2pError));
+
+    drop(control);
+    assert!(eventually(|| *backend.removed.lock().unwrap() == vec!["web".to_string()]));
+}
+
+#[test]
+fn test_bridge_should_reserve_session_ids_while_sessions_start() {
+    let (backend, address, _, _) = start();
+    let mut first = Client::hello(address);
+    writeln!(first.stream, "SESSION CREATE STYLE=STREAM ID=slow DESTINATION=TRANSIENT inbound.length=1").unwrap();
+    assert!(eventually(|| backend.creating.lock().unwrap().len() == 1));
+
+    let mut second = Client::hello(address);
+    let reply = second.command("SESSION CREATE STYLE=STREAM ID=slow DESTINATION=TRANSIENT inbound.length=1");
+    assert_eq!(reply.result(), Some(SamResult::DuplicatedId));
+    assert_eq!(SamCommand::parse(&first.read_line()).unwrap().result(), Some(SamResult::Ok));
+    assert_eq!(backend.creating.lock().unwrap().len(), 1);
+
+    // A session that fails to start releases its ID.
+    let keys = keys().private_keys;
+    let reply = second.command(&format!("SESSION CREATE STYLE=STREAM ID=web DESTINATION={} inbound.length=1", keys));
+    assert_eq!(reply.result(), Some(SamResult::InvalidKey));
+    let reply = second.command("SESSION CREATE STYLE=STREAM ID=web DESTINATION=TRANSIENT inbound.length=1");
+    assert_eq!(reply.result(), Some(SamResult::Ok));
+}
+
+#[test]
+fn test_bridge_should_hand_sockets_to_streams() {
+    let (_, address, _, incoming) = start();
+    let mut control = Client::hello(address);
+    control.command("SESSION CREATE STYLE=STREAM ID=web DESTINATION=TRANSIENT inbound.length=1");
+
+    let mut stream = Client::hello(address);
+    assert_eq!(stream.command("STREAM CONNECT ID=web DESTINATION=echo.i2p").result(), Some(SamResult::Ok));
+    stream.stream.write_all(b"hello over i2p\n").unwrap();
+    assert_eq!(stream.read_line(), "hello over i2p\n");
+
+    let mut failed = Client::hello(address);
+    assert_eq!(failed.command("STREAM CONNECT ID=nobody DESTINATION=echo.i2p").result(), Some(SamResult::InvalidId));
+    assert!(failed.is_closed());
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    incoming.send(listener.accept().unwrap().0).unwrap();
+
+    let mut accepted = Client::hello(address);
+    assert_eq!(accepted.command("STREAM ACCEPT ID=web").result(), Some(SamResult::Ok));
+    assert_eq!(accepted.read_line(), format!("{} FROM_PORT=1234 TO_PORT=80\n", keys().destination.to_i2p_base64()));
+    peer.write_all(b"from the peer").unwrap();
+    let mut received = [0x00; 13];
+    accepted.reader.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"from the peer");
+}
+
+#[test]
+fn test_bridge_should_forward_accepted_streams() {
+    let (_, address, _, incoming) = start();
+    let mut control = Client::hello(address);
+    control.command("SESSION CREATE STYLE=STREAM ID=web DESTINATION=TRANSIENT inbound.length=1");
+
+    let local = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = local.local_addr().unwrap().port();
+    let mut forward = Client::hello(address);
+    assert_eq!(forward.command(&format!("STREAM FORWARD ID=web PORT={}", port)).result(), Some(SamResult::Ok));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    incoming.send(listener.accept().unwrap().0).unwrap();
+    peer.write_all(b"forwarded").unwrap();
+
+    let mut server = BufReader::new(local.accept().unwrap().0);
+    let mut line = String::new();
+    server.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("{} FROM_PORT=1234 TO_PORT=80\n", keys().destination.to_i2p_base64()));
+    let mut received = [0x00; 9];
+    server.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"forwarded");
+}
+
+#[test]
+fn test_bridge_should_carry_datagrams() {
+    let (backend, address, udp_address, _) = start();
+    let client_udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client_udp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let port = client_udp.local_addr().unwrap().port();
+    let public = keys().destination.to_i2p_base64();
+
+    let mut control = Client::hello(address);
+    let reply = control.command(&format!("SESSION CREATE STYLE=DATAGRAM ID=dg DESTINATION=TRANSIENT PORT={} inbound.length=1", port));
+    assert_eq!(reply.result(), Some(SamResult::Ok));
+
+    let mut datagram = format!("3.0 dg {} FROM_PORT=7\n", public).into_bytes();
+    datagram.extend_from_slice(b"ping");
+    client_udp.send_to(&datagram, udp_address).unwrap();
+    assert!(eventually(|| backend.datagrams.lock().unwrap().len() == 1));
+
+    write!(control.stream, "DATAGRAM SEND ID=dg DESTINATION=echo.i2p SIZE=4\npong").unwrap();
+    assert!(eventually(|| backend.datagrams.lock().unwrap().len() == 2));
+    assert_eq!(*backend.datagrams.lock().unwrap(), vec![("dg".to_string(), b"ping".to_vec()), ("dg".to_string(), b"pong".to_vec())]);
+
+    let sink = backend.sinks.lock().unwrap()["dg"].clone();
+    sink.deliver(Some(&keys().destination), b"reply").unwrap();
+    let mut buf = [0x00; 1024];
+    let (length, _) = client_udp.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..length], format!("{}\nreply", public).as_bytes());
+
+    let mut raw = Client::hello(address);
+    assert_eq!(raw.command("SESSION CREATE STYLE=RAW ID=raw DESTINATION=TRANSIENT inbound.length=1").result(), Some(SamResult::Ok));
+    let sink = backend.sinks.lock().unwrap()["raw"].clone();
+    sink.deliver(None, b"anonymous").unwrap();
+    assert_eq!(raw.read_line(), "RAW RECEIVED SIZE=9\n");
+    let mut received = [0x00; 9];
+    raw.reader.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"anonymous");
+}
+
+#[test]
+fn test_bridge_should_manage_subsessions() {
+    let (backend, address, _, _) = start();
+    let mut control = Client::hello(address);
+    assert_eq!(control.command("SESSION ADD STYLE=STREAM ID=sub").result(), Some(SamResult::I2pError));
+    assert_eq!(control.command("SESSION CREATE STYLE=PRIMARY ID=main DESTINATION=TRANSIENT inbound.length=1").result(), Some(SamResult::Ok));
+
+    let reply = control.command("SESSION ADD STYLE=STREAM ID=sub FROM_PORT=80");
+    assert_eq!(reply.result(), Some(SamResult::Ok));
+    assert_eq!(reply.get("ID"), Some("sub"));
+    assert_eq!(control.command("SESSION ADD STYLE=RAW ID=sub").result(), Some(SamResult::DuplicatedId));
+    assert_eq!(control.command("SESSION ADD STYLE=PRIMARY ID=nested").result(), Some(SamResult::I2pError));
+    assert_eq!(control.command("SESSION ADD STYLE=RAW ID=udp").result(), Some(SamResult::Ok));
+
+    let mut stream = Client::hello(address);
+    assert_eq!(stream.command("STREAM CONNECT ID=sub DESTINATION=echo.i2p").result(), Some(SamResult::Ok));
+    let mut stream = Client::hello(address);
+    assert_eq!(stream.command("STREAM CONNECT ID=main DESTINATION=echo.i2p").result(), Some(SamResult::InvalidId));
+
+    assert_eq!(control.command("SESSION REMOVE ID=sub").result(), Some(SamResult::Ok));
+    assert_eq!(control.command("SESSION REMOVE ID=sub").result(), Some(SamResult::InvalidId));
+    assert_eq!(*backend.removed.lock().unwrap(), vec!["sub".to_string()]);
+
+    drop(control);
+    assert!(eventually(|| backend.removed.lock().unwrap().len() == 3));
+    assert_eq!(backend.removed.lock().unwrap()[1..], ["udp".to_string(), "main".to_string()]);
+}
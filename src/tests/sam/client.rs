@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use common::SignatureType;
+use sam::{SamError, SamResult, Session, SessionStyle, generate_keys, lookup};
+use super::bridge::{eventually, keys, start};
+
+
+fn options() -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    options.insert("inbound.length".to_string(), "1".to_string());
+    options
+}
+
+fn failure<T>(result: Result<T, SamError>) -> Option<SamResult> {
+    match result {
+        Err(SamError::Failed(result, _)) => Some(result),
+        _ => None
+    }
+}
+
+
+#[test]
+fn test_client_should_create_sessions_and_resolve_names() {
+    let (backend, address, _, _) = start();
+
+    let mut session = Session::create_at(address, SessionStyle::Stream, None, &options()).unwrap();
+    assert_eq!(*session.keys(), keys());
+    assert_eq!(backend.sessions.lock().unwrap()[0].0, session.id());
+    assert_eq!(session.lookup("echo.i2p").unwrap(), keys().destination);
+    assert_eq!(failure(session.lookup("nowhere.i2p")), Some(SamResult::KeyNotFound));
+    assert_eq!(lookup(address, "echo.i2p").unwrap(), *session.destination());
+
+    assert_eq!(generate_keys(address, SignatureType::EdDSA_SHA512_Ed25519).unwrap(), keys());
+    assert_eq!(failure(generate_keys(address, SignatureType::DSA_SHA1)), Some(SamResult::I2pError));
+    assert_eq!(failure(Session::create_at(address, SessionStyle::Raw, Some(&keys()), &options())), Some(SamResult::InvalidKey));
+
+    let id = session.id().to_string();
+    drop(session);
+    assert!(eventually(|| *backend.removed.lock().unwrap() == vec![id.clone()]));
+}
+
+#[test]
+fn test_client_should_connect_and_accept_streams() {
+    let (_, address, _, incoming) = start();
+    let session = Session::create_at(address, SessionStyle::Stream, None, &options()).unwrap();
+
+    let mut stream = session.connect("echo.i2p").unwrap();
+    assert_eq!(stream.peer(), None);
+    stream.write_all(b"echo").unwrap();
+    let mut received = [0x00; 4];
+    stream.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"echo");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    incoming.send(listener.accept().unwrap().0).unwrap();
+
+    let mut accepted = session.incoming().next().unwrap().unwrap();
+    assert_eq!(accepted.peer(), Some(&keys().destination));
+    accepted.write_all(b"welcome").unwrap();
+    accepted.shutdown_write().unwrap();
+    let mut received = Vec::new();
+    peer.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"welcome");
+}
+
+#[test]
+fn test_client_should_send_and_receive_datagrams() {
+    let (backend, address, udp_address, _) = start();
+
+    let mut session = Session::create_at(address, SessionStyle::Datagram, None, &options()).unwrap();
+    session.set_udp_bridge(udp_address);
+    session.set_datagram_timeout(Some(Duration::from_secs(5))).unwrap();
+    session.send_to(b"query", &keys().destination).unwrap();
+    assert!(eventually(|| backend.datagrams.lock().unwrap().len() == 1));
+    assert_eq!(backend.datagrams.lock().unwrap()[0], (session.id().to_string(), b"query".to_vec()));
+
+    let sink = backend.sinks.lock().unwrap()[session.id()].clone();
+    sink.deliver(Some(&keys().destination), b"answer").unwrap();
+    let mut buf = [0x00; 3];
+    assert_eq!(session.recv_from(&mut buf).unwrap(), (3, Some(keys().destination)));
+    assert_eq!(&buf, b"ans");
+
+    let raw = Session::create_at(address, SessionStyle::Raw, None, &options()).unwrap();
+    raw.set_datagram_timeout(Some(Duration::from_secs(5))).unwrap();
+    let sink = backend.sinks.lock().unwrap()[raw.id()].clone();
+    sink.deliver(None, b"anonymous").unwrap();
+    let mut buf = [0x00; 64];
+    assert_eq!(raw.recv_from(&mut buf).unwrap(), (9, None));
+    assert_eq!(&buf[..9], b"anonymous");
+
+    let stream = Session::create_at(address, SessionStyle::Stream, None, &options()).unwrap();
+    assert_eq!(failure(stream.send_to(b"nowhere", &keys().destination)), Some(SamResult::I2pError));
+}
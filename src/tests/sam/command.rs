@@ -0,0 +1,56 @@
+use common::SignatureType;
+use sam::{SamCommand, SamResult, SessionStyle, parse_signature_type};
+
+
+#[test]
+fn test_command_should_parse_verb_action_and_arguments() {
+    let command = SamCommand::parse("SESSION CREATE STYLE=STREAM ID=test DESTINATION=abc== inbound.length=2\n").unwrap();
+
+    assert!(command.is("SESSION", "CREATE"));
+    assert_eq!(command.get("STYLE"), Some("STREAM"));
+    assert_eq!(command.get("DESTINATION"), Some("abc=="));
+    assert_eq!(command.get("inbound.length"), Some("2"));
+    assert_eq!(command.get("SIGNATURE_TYPE"), None);
+}
+
+#[test]
+fn test_command_should_parse_quoted_values() {
+    let command = SamCommand::parse(r#"NAMING REPLY RESULT=KEY_NOT_FOUND MESSAGE="no \"such\" name""#).unwrap();
+
+    assert_eq!(command.get("MESSAGE"), Some(r#"no "such" name"#));
+    assert_eq!(command.result(), Some(SamResult::KeyNotFound));
+    assert_eq!(SamCommand::parse(r#"HELLO VERSION MIN="3.0"#), None);
+    assert_eq!(SamCommand::parse("   \r\n"), None);
+}
+
+#[test]
+fn test_command_should_round_trip_through_its_line() {
+    let command = SamCommand::new("STREAM", Some("STATUS"))
+        .arg("RESULT", "I2P_ERROR")
+        .arg("MESSAGE", "a \"quoted\" message")
+        .arg("EMPTY", "");
+    let line = command.to_string();
+
+    assert!(line.starts_with("STREAM STATUS RESULT=I2P_ERROR MESSAGE=\""));
+    assert_eq!(SamCommand::parse(&line), Some(command));
+    assert_eq!(SamCommand::new("PING", None).to_string(), "PING");
+}
+
+#[test]
+fn test_result_should_default_to_ok() {
+    assert_eq!(SamCommand::parse("DEST REPLY PUB=a PRIV=b").unwrap().result(), Some(SamResult::Ok));
+    assert_eq!(SamResult::from_name(SamResult::DuplicatedDest.as_str()), Some(SamResult::DuplicatedDest));
+    assert_eq!(SamResult::from_name("UNKNOWN"), None);
+}
+
+#[test]
+fn test_style_and_signature_type_should_parse() {
+    assert_eq!(SessionStyle::from_name("MASTER"), Some(SessionStyle::Primary));
+    assert_eq!(SessionStyle::from_name(SessionStyle::Raw.as_str()), Some(SessionStyle::Raw));
+    assert_eq!(SessionStyle::from_name("stream"), None);
+
+    assert_eq!(parse_signature_type("7"), Some(SignatureType::EdDSA_SHA512_Ed25519));
+    assert_eq!(parse_signature_type("ecdsa_sha256_p256"), Some(SignatureType::ECDSA_SHA256_P256));
+    assert_eq!(parse_signature_type("42"), None);
+    assert_eq!(parse_signature_type("Ed448"), None);
+}
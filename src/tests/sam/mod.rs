@@ -0,0 +1,3 @@
+mod command;
+pub mod bridge;
+mod client;
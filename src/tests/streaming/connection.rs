@@ -0,0 +1,298 @@
+use std::time::{Duration, Instant};
+use streaming::{Connection, ConnectionState, Packet, RttEstimator, StreamConfig, StreamingError};
+use streaming::{FLAG_CLOSE, FLAG_NO_ACK, FLAG_RESET, FLAG_SYNCHRONIZE};
+use tests::i2cp::message::destination;
+use tests::su3::{FakeSigner, FakeVerifier};
+use tests::util::date;
+
+
+type TestConnection = Connection<FakeSigner, FakeVerifier>;
+
+
+fn config() -> StreamConfig {
+    StreamConfig {
+        max_packet_size: 4,
+        initial_window: 2,
+        initial_rto: Duration::from_secs(1),
+        max_retransmissions: 2,
+        ..StreamConfig::default()
+    }
+}
+
+/// Connects two connections at `now`, and returns them with the packets of the
+/// handshake delivered.
+fn established(now: Instant) -> (TestConnection, TestConnection) {
+    established_with(now, config())
+}
+
+/// Like `established`, with the server using `server_config`.
+fn established_with(now: Instant, server_config: StreamConfig) -> (TestConnection, TestConnection) {
+    let mut client = Connection::connect(1, destination(), FakeSigner, FakeVerifier, config());
+    let syn = client.poll(now).remove(0);
+    let mut server = Connection::accept(2, syn, destination(), FakeSigner, FakeVerifier, server_config, now).unwrap();
+    for packet in server.poll(now) {
+        client.receive(packet, now);
+    }
+    (client, server)
+}
+
+fn deliver(from: &mut TestConnection, to: &mut TestConnection, now: Instant) -> usize {
+    let packets = from.poll(now);
+    let count = packets.len();
+    for packet in packets {
+        to.receive(packet, now);
+    }
+    count
+}
+
+
+#[test]
+fn test_rtt_estimator_should_follow_samples() {
+    let mut rtt = RttEstimator::new(Duration::from_secs(3), Duration::from_millis(100), Duration::from_secs(10));
+    assert_eq!(rtt.srtt(), None);
+    assert_eq!(rtt.rto(), Duration::from_secs(3));
+
+    rtt.sample(Duration::from_millis(400));
+    assert_eq!(rtt.srtt(), Some(Duration::from_millis(400)));
+    assert_eq!(rtt.rto(), Duration::from_millis(1200));
+    rtt.sample(Duration::from_millis(800));
+    assert_eq!(rtt.srtt(), Some(Duration::from_millis(450)));
+    assert_eq!(rtt.rto(), Duration::from_millis(450 + 4 * 250));
+
+    rtt.backoff();
+    assert_eq!(rtt.rto(), Duration::from_millis(2900));
+    for _ in 0..4 {
+        rtt.backoff();
+    }
+    assert_eq!(rtt.rto(), Duration::from_secs(10));
+}
+
+#[test]
+fn test_connection_should_handshake() {
+    let now = Instant::now();
+    let mut client = Connection::connect(1, destination(), FakeSigner, FakeVerifier, config());
+    assert_eq!(client.state(), ConnectionState::Connecting);
+    client.write(b"hello");
+
+    // Only the SYN goes out before the peer answers.
+    let mut packets = client.poll(now);
+    assert_eq!(packets.len(), 1);
+    let syn = packets.remove(0);
+    assert_eq!(syn.flags & FLAG_SYNCHRONIZE, FLAG_SYNCHRONIZE);
+    assert_eq!(syn.flags & FLAG_NO_ACK, FLAG_NO_ACK);
+    assert_eq!((syn.send_stream_id, syn.receive_stream_id), (0, 1));
+    assert_eq!(syn.from, Some(destination()));
+    assert_eq!(syn.max_packet_size, Some(4));
+    assert_eq!(syn.payload, b"hell");
+
+    let mut server = Connection::accept(2, syn, destination(), FakeSigner, FakeVerifier, config(), now).unwrap();
+    assert_eq!(server.state(), ConnectionState::Established);
+    assert_eq!(server.remote_id(), 1);
+    let mut packets = server.poll(now + Duration::from_millis(200));
+    assert_eq!(packets.len(), 1);
+    let reply = packets.remove(0);
+    assert!(reply.is_syn());
+    assert_eq!((reply.send_stream_id, reply.receive_stream_id, reply.ack_through), (1, 2, 0));
+
+    client.receive(reply, now + Duration::from_millis(200));
+    assert_eq!(client.state(), ConnectionState::Established);
+    assert_eq!(client.remote_id(), 2);
+    assert_eq!(client.rtt().srtt(), Some(Duration::from_millis(200)));
+    assert_eq!(client.in_flight(), 0);
+
+    deliver(&mut client, &mut server, now);
+    let mut buf = [0x00; 16];
+    assert_eq!(server.read(&mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+}
+
+#[test]
+fn test_connection_should_reorder_and_fast_retransmit() {
+    let now = Instant::now();
+    let (mut client, mut server) = established(now);
+    deliver(&mut server, &mut client, now);
+    client.write(b"0123456789ab");
+    let window = client.window();
+    assert!(window >= 3);
+
+    let mut packets = client.poll(now);
+    assert_eq!(packets.len(), 3);
+    let lost = packets.remove(0);
+    for packet in packets {
+        server.receive(packet, now);
+    }
+    assert_eq!(server.available(), 0);
+
+    // Each ack NACKs the lost packet; the second triggers a resend.
+    let ack = server.poll(now).remove(0);
+    assert_eq!(ack.nacks, vec![lost.sequence_number]);
+    assert!(ack.is_ack_only());
+    client.receive(ack.clone(), now);
+    client.receive(ack, now);
+    assert_eq!(client.in_flight(), 1);
+
+    let resent = client.poll(now);
+    assert_eq!(resent.len(), 1);
+    assert_eq!(resent[0].payload, lost.payload);
+    assert_eq!(client.retransmissions(), 1);
+    server.receive(resent[0].clone(), now);
+
+    let mut buf = [0x00; 16];
+    assert_eq!(server.read(&mut buf), 12);
+    assert_eq!(&buf[..12], b"0123456789ab");
+    deliver(&mut server, &mut client, now);
+    assert_eq!(client.in_flight(), 0);
+    assert!(client.is_flushed());
+}
+
+#[test]
+fn test_connection_should_ignore_data_beyond_its_window_and_buffer() {
+    let now = Instant::now();
+    let (mut client, mut server) = established(now);
+    deliver(&mut server, &mut client, now);
+
+    let mut far = Packet::new(2, 1, 0xFFFF_FFFF, FLAG_NO_ACK);
+    far.payload = b"evil".to_vec();
+    server.receive(far, now);
+    let mut near = Packet::new(2, 1, 2, FLAG_NO_ACK);
+    near.payload = b"near".to_vec();
+    server.receive(near, now);
+
+    // Only the packet within the window is buffered and acknowledged.
+    let ack = server.poll(now).remove(0);
+    assert_eq!((ack.ack_through, ack.nacks.clone()), (2, vec![1]));
+    assert_eq!(server.available(), 0);
+
+    // With room for two packets, the third is resent once the data is read.
+    let (mut client, mut server) = established_with(now, StreamConfig { receive_buffer: 8, ..config() });
+    deliver(&mut server, &mut client, now);
+    client.write(b"0123456789ab");
+    for _ in 0..3 {
+        deliver(&mut client, &mut server, now);
+        deliver(&mut server, &mut client, now);
+    }
+    assert_eq!(server.available(), 8);
+
+    let mut buf = [0x00; 16];
+    assert_eq!(server.read(&mut buf), 8);
+    let later = now + Duration::from_secs(5);
+    deliver(&mut client, &mut server, later);
+    assert_eq!(server.read(&mut buf), 4);
+    assert_eq!(&buf[..4], b"89ab");
+}
+
+#[test]
+fn test_connection_should_back_off_and_time_out() {
+    let now = Instant::now();
+    let (mut client, mut server) = established(now);
+    deliver(&mut server, &mut client, now);
+    client.write(b"data");
+    assert_eq!(client.poll(now).len(), 1);
+    let rto = client.rtt().rto();
+    assert_eq!(client.next_timeout(), Some(now + rto));
+    assert!(client.poll(now + rto / 2).is_empty());
+
+    let window = client.window();
+    let resent = client.poll(now + rto);
+    assert_eq!(resent.len(), 1);
+    assert_eq!(resent[0].payload, b"data");
+    assert_eq!(client.rtt().rto(), rto * 2);
+    assert!(client.window() <= window);
+
+    let later = now + rto + rto * 2;
+    assert_eq!(client.poll(later).len(), 1);
+    assert_eq!(client.state(), ConnectionState::Established);
+    client.poll(later + rto * 4);
+    assert_eq!(client.state(), ConnectionState::TimedOut);
+    assert!(client.is_write_closed());
+    assert_eq!(client.write(b"more"), 0);
+}
+
+#[test]
+fn test_connection_should_close_both_ways() {
+    let now = Instant::now();
+    let (mut client, mut server) = established(now);
+    client.write(b"bye");
+    client.close();
+    assert!(client.is_write_closed());
+
+    let packets = client.poll(now);
+    assert_eq!(packets.len(), 1);
+    assert_eq!(packets[0].flags & FLAG_CLOSE, FLAG_CLOSE);
+    for packet in packets {
+        server.receive(packet, now);
+    }
+    let mut buf = [0x00; 8];
+    assert_eq!(server.read(&mut buf), 3);
+    assert!(server.is_eof());
+
+    server.write(b"ok");
+    server.close();
+    deliver(&mut server, &mut client, now);
+    assert!(client.is_flushed());
+    assert_eq!(client.state(), ConnectionState::Closed);
+    assert_eq!(client.read(&mut buf), 2);
+    assert!(client.is_eof());
+
+    deliver(&mut client, &mut server, now);
+    assert_eq!(server.state(), ConnectionState::Closed);
+}
+
+#[test]
+fn test_connection_should_reset() {
+    let now = Instant::now();
+    let (mut client, mut server) = established(now);
+    let reset = client.reset().unwrap();
+    assert_eq!(reset.flags & FLAG_RESET, FLAG_RESET);
+    assert_eq!(client.state(), ConnectionState::Reset);
+    assert!(client.poll(now).is_empty());
+
+    server.receive(reset, now);
+    assert_eq!(server.state(), ConnectionState::Reset);
+    assert!(server.is_eof());
+
+    let mut connecting = Connection::connect(3, destination(), FakeSigner, FakeVerifier, config());
+    assert_eq!(connecting.reset(), None);
+
+    // Packets for a connection that is gone are ignored.
+    server.receive(Packet::new(2, 1, 5, 0), now);
+    assert_eq!(server.available(), 0);
+}
+
+#[test]
+fn test_connection_should_sign_and_verify_syn_close_and_reset() {
+    let now = Instant::now();
+    let mut client = Connection::connect(1, destination(), FakeSigner, FakeVerifier, config());
+    let syn = client.poll(now).remove(0);
+    assert!(syn.verify(&FakeVerifier, &destination(), date(1000)));
+
+    let mut forged = syn.clone();
+    forged.payload = b"evil".to_vec();
+    match Connection::accept(2, forged, destination(), FakeSigner, FakeVerifier, config(), now) {
+        Err(StreamingError::InvalidSignature) => {}
+        _ => panic!("accepted a forged SYN")
+    }
+    let mut server = Connection::accept(2, syn, destination(), FakeSigner, FakeVerifier, config(), now).unwrap();
+    assert_eq!(server.peer(), Some(&destination()));
+
+    // The reply SYN is unanswered until it verifies.
+    let mut reply = server.poll(now).remove(0);
+    let signature = reply.signature.take();
+    client.receive(reply.clone(), now);
+    assert_eq!(client.state(), ConnectionState::Connecting);
+    reply.signature = signature;
+    client.receive(reply, now);
+    assert_eq!(client.state(), ConnectionState::Established);
+
+    client.close();
+    let close = client.poll(now).remove(0);
+    assert!(close.from.is_none());
+    assert!(close.verify(&FakeVerifier, &destination(), date(1000)));
+
+    let mut reset = Packet::new(2, 1, 0, FLAG_RESET);
+    server.receive(reset.clone(), now);
+    assert_eq!(server.state(), ConnectionState::Established);
+    assert!(reset.sign_as(&FakeSigner, &destination()));
+    server.receive(reset, now);
+    assert_eq!(server.state(), ConnectionState::Reset);
+}
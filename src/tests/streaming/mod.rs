@@ -0,0 +1,3 @@
+mod packet;
+mod connection;
+mod stream;
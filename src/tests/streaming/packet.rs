@@ -0,0 +1,99 @@
+use streaming::{OfflineSignature, Packet, StreamingError, MIN_PACKET_LENGTH};
+use streaming::{FLAG_CLOSE, FLAG_DELAY_REQUESTED, FLAG_FROM_INCLUDED, FLAG_MAX_PACKET_SIZE_INCLUDED};
+use streaming::{FLAG_OFFLINE_SIGNATURE, FLAG_SIGNATURE_INCLUDED, FLAG_SYNCHRONIZE};
+use tests::i2cp::message::destination;
+use tests::su3::{FakeSigner, FakeVerifier, fake_key, fake_signature};
+use tests::util::date;
+
+
+fn syn() -> Packet {
+    let mut packet = Packet::new(0, 0x0102_0304, 0, FLAG_SYNCHRONIZE);
+    packet.ack_through = 7;
+    packet.nacks = vec![3, 5];
+    packet.resend_delay = 9;
+    packet.delay_requested = Some(500);
+    packet.from = Some(destination());
+    packet.max_packet_size = Some(1730);
+    packet.payload = b"GET / HTTP/1.1\r\n\r\n".to_vec();
+    packet
+}
+
+
+#[test]
+fn test_packet_should_round_trip_with_options() {
+    let packet = syn();
+    let flags = packet.flags();
+    assert_eq!(flags, FLAG_SYNCHRONIZE | FLAG_DELAY_REQUESTED | FLAG_FROM_INCLUDED | FLAG_MAX_PACKET_SIZE_INCLUDED);
+    assert!(packet.is_syn());
+    assert!(!packet.is_ack_only());
+
+    let bytes = packet.to_bytes();
+    assert_eq!(&bytes[..4], &[0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(&bytes[4..8], &[0x01, 0x02, 0x03, 0x04]);
+    assert_eq!(bytes[16], 2);
+    assert_eq!(Packet::from_bytes(&bytes).unwrap(), packet);
+
+    let mut ack = Packet::new(7, 8, 0, 0);
+    ack.ack_through = 4;
+    assert!(ack.is_ack_only());
+    assert_eq!(ack.to_bytes().len(), MIN_PACKET_LENGTH);
+    assert_eq!(Packet::from_bytes(&ack.to_bytes()).unwrap(), ack);
+}
+
+#[test]
+fn test_packet_should_reject_truncated_packets() {
+    let bytes = syn().to_bytes();
+    match Packet::from_bytes(&bytes[..MIN_PACKET_LENGTH - 1]) {
+        Err(StreamingError::InvalidPacket) => {}
+        other => panic!("{:?}", other)
+    }
+    // The options claim a FROM destination that is cut off.
+    match Packet::from_bytes(&bytes[..MIN_PACKET_LENGTH + 8 + 20]) {
+        Err(StreamingError::InvalidPacket) => {}
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_packet_should_be_signed_and_verified() {
+    let mut packet = syn();
+    packet.flags |= FLAG_CLOSE;
+    assert!(packet.sign(&FakeSigner));
+    assert_eq!(packet.flags() & FLAG_SIGNATURE_INCLUDED, FLAG_SIGNATURE_INCLUDED);
+
+    let received = Packet::from_bytes(&packet.to_bytes()).unwrap();
+    assert_eq!(received, packet);
+    assert!(received.verify(&FakeVerifier, &destination(), date(1000)));
+
+    let mut tampered = received.clone();
+    tampered.payload.push(0x01);
+    assert!(!tampered.verify(&FakeVerifier, &destination(), date(1000)));
+
+    let mut unsigned = Packet::new(1, 2, 3, 0);
+    assert!(!unsigned.sign(&FakeSigner));
+    assert!(!unsigned.verify(&FakeVerifier, &destination(), date(1000)));
+}
+
+#[test]
+fn test_packet_should_verify_offline_signatures() {
+    let expires = 1_600_000_000;
+    let signed = OfflineSignature::signed_bytes(expires, &fake_key());
+    let mut packet = Packet::new(0, 9, 0, FLAG_SYNCHRONIZE);
+    packet.from = Some(destination());
+    packet.offline_signature = Some(OfflineSignature {
+        expires,
+        transient_key: fake_key(),
+        signature: fake_signature(&signed)
+    });
+    assert!(packet.sign(&FakeSigner));
+    assert_eq!(packet.flags() & FLAG_OFFLINE_SIGNATURE, FLAG_OFFLINE_SIGNATURE);
+
+    let received = Packet::from_bytes(&packet.to_bytes()).unwrap();
+    assert_eq!(received, packet);
+    assert!(received.verify(&FakeVerifier, &destination(), date((expires as u64 - 1) * 1000)));
+    assert!(!received.verify(&FakeVerifier, &destination(), date((expires as u64 + 1) * 1000)));
+
+    let mut forged = received.clone();
+    forged.offline_signature.as_mut().unwrap().expires += 1;
+    assert!(!forged.verify(&FakeVerifier, &destination(), date(1000)));
+}
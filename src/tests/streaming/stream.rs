@@ -0,0 +1,163 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use streaming::{PacketChannel, Stream, StreamConfig, StreamHandle, StreamingError, pipe};
+use tests::i2cp::message::destination;
+use tests::su3::{FakeSigner, FakeVerifier};
+
+
+/// One end of an in-memory channel that drops every `drop_every`th packet among
+/// the first `lossy` it sends.
+struct LossyChannel {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+    sent: usize,
+    drop_every: usize,
+    lossy: usize
+}
+
+impl PacketChannel for LossyChannel {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.sent += 1;
+        if self.sent <= self.lossy && self.sent.is_multiple_of(self.drop_every) {
+            return Ok(());
+        }
+        self.sender.send(packet.to_vec()).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+    }
+}
+
+fn lossy_pair(drop_every: usize, lossy: usize) -> (LossyChannel, LossyChannel) {
+    let (a_sender, b_receiver) = channel();
+    let (b_sender, a_receiver) = channel();
+    let a = LossyChannel { sender: a_sender, receiver: a_receiver, sent: 0, drop_every, lossy };
+    let b = LossyChannel { sender: b_sender, receiver: b_receiver, sent: 0, drop_every, lossy };
+    (a, b)
+}
+
+fn config() -> StreamConfig {
+    StreamConfig {
+        max_packet_size: 512,
+        initial_rto: Duration::from_millis(200),
+        min_rto: Duration::from_millis(50),
+        max_rto: Duration::from_secs(1),
+        ..StreamConfig::default()
+    }
+}
+
+fn connect(channel: LossyChannel, config: StreamConfig) -> Result<Stream<LossyChannel, FakeSigner, FakeVerifier>, StreamingError> {
+    Stream::connect(channel, destination(), FakeSigner, FakeVerifier, config)
+}
+
+fn accept(channel: LossyChannel) -> Result<Stream<LossyChannel, FakeSigner, FakeVerifier>, StreamingError> {
+    Stream::accept(channel, destination(), FakeSigner, FakeVerifier, config(), Duration::from_secs(10))
+}
+
+
+#[test]
+fn test_stream_should_transfer_over_lossy_channel() {
+    let (client_channel, server_channel) = lossy_pair(4, 40);
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+
+    let server = thread::spawn(move || {
+        let mut stream = accept(server_channel).unwrap();
+        assert_eq!(stream.peer(), Some(&destination()));
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        stream.write_all(&(received.len() as u32).to_be_bytes()).unwrap();
+        stream.close().unwrap();
+        (stream, received)
+    });
+
+    let mut stream = connect(client_channel, config()).unwrap();
+    stream.write_all(&data).unwrap();
+    stream.close().unwrap();
+    assert_eq!(stream.write(b"late").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).unwrap();
+    assert_eq!(reply, (data.len() as u32).to_be_bytes());
+
+    let (_server, received) = server.join().unwrap();
+    assert_eq!(received, data);
+    assert!(stream.connection().retransmissions() > 0);
+}
+
+#[test]
+fn test_stream_should_report_resets_and_timeouts() {
+    let (client_channel, server_channel) = lossy_pair(1, 0);
+    let server = thread::spawn(move || {
+        let mut stream = accept(server_channel).unwrap();
+        stream.write_all(b"go away").unwrap();
+        stream.flush().unwrap();
+        stream.reset().unwrap();
+        stream
+    });
+
+    // The client reads before joining, so the server's data is acknowledged while
+    // it flushes.
+    let mut stream = connect(client_channel, config()).unwrap();
+    let mut buf = [0x00; 7];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"go away");
+    assert_eq!(stream.read(&mut buf).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+    let _server = server.join().unwrap();
+
+    // A peer that never answers.
+    let (client_channel, _silent) = lossy_pair(1, 0);
+    let mut impatient = config();
+    impatient.max_retransmissions = 2;
+    match connect(client_channel, impatient) {
+        Err(StreamingError::TimedOut) => {}
+        Err(err) => panic!("{}", err),
+        Ok(_) => panic!("connected to nobody")
+    }
+
+    // Nobody connects.
+    let (server_channel, _silent) = lossy_pair(1, 0);
+    match Stream::accept(server_channel, destination(), FakeSigner, FakeVerifier, config(), Duration::from_millis(100)) {
+        Err(StreamingError::TimedOut) => {}
+        Err(err) => panic!("{}", err),
+        Ok(_) => panic!("accepted nobody")
+    }
+}
+
+#[test]
+fn test_stream_handle_should_serve_as_a_byte_stream() {
+    let (client_channel, server_channel) = lossy_pair(1, 0);
+    let server = thread::spawn(move || {
+        let mut stream = accept(server_channel).unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        stream.write_all(&received).unwrap();
+        stream.close().unwrap();
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut local = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (client, _) = listener.accept().unwrap();
+    // A clone keeps the channel open until the server has seen its CLOSE acked.
+    let handle = StreamHandle::new(connect(client_channel, config()).unwrap());
+    let kept = handle.clone();
+    let piping = thread::spawn(move || pipe(client, b"hello ".to_vec(), Box::new(handle)));
+
+    local.write_all(b"world").unwrap();
+    local.shutdown(Shutdown::Write).unwrap();
+    let mut echoed = Vec::new();
+    local.read_to_end(&mut echoed).unwrap();
+
+    assert_eq!(echoed, b"hello world");
+    piping.join().unwrap().unwrap();
+    server.join().unwrap();
+    drop(kept);
+}
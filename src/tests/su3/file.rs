@@ -0,0 +1,143 @@
+use common::SignatureType;
+use netdb::NetDbEntry;
+use su3::{Su3File, Su3Error, FileType, ContentType, CertificateStore};
+use tests::netdb::FakeEntry;
+use super::{FakeVerifier, fake_key, su3_file, zip_archive};
+
+
+fn certificates() -> CertificateStore {
+    let mut certificates = CertificateStore::new();
+    certificates.insert("reseed@example.i2p", fake_key());
+
+    certificates
+}
+
+
+#[test]
+fn test_header_fields_should_be_parsed() {
+    let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", b"content")).unwrap();
+
+    assert_eq!(su3.signature_type(), SignatureType::EdDSA_SHA512_Ed25519);
+    assert_eq!(su3.file_type(), FileType::Zip);
+    assert_eq!(su3.content_type(), ContentType::Reseed);
+    assert_eq!(su3.version(), "1539302400");
+    assert_eq!(su3.signer_id(), "reseed@example.i2p");
+    assert_eq!(su3.content(), b"content");
+}
+
+#[test]
+fn test_parse_should_reject_bad_magic_and_truncated_files() {
+    let mut bad_magic = su3_file("reseed@example.i2p", b"content");
+    bad_magic[0] = b'X';
+    let mut truncated = su3_file("reseed@example.i2p", b"content");
+    truncated.pop();
+
+    match Su3File::from_bytes(bad_magic) {
+        Err(Su3Error::BadMagicNumber) => {}
+        other => panic!("Expected a bad magic number error, got: {:?}", other)
+    }
+    match Su3File::from_bytes(truncated) {
+        Err(Su3Error::Truncated(_, _)) => {}
+        other => panic!("Expected a truncation error, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_verify_should_check_the_signer_and_the_signature() {
+    let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", b"content")).unwrap();
+    let unknown = Su3File::from_bytes(su3_file("unknown@example.i2p", b"content")).unwrap();
+    let mut tampered = su3_file("reseed@example.i2p", b"content");
+    tampered[76] ^= 0xFF;
+    let tampered = Su3File::from_bytes(tampered).unwrap();
+
+    assert!(su3.verify(&certificates(), &FakeVerifier).is_ok());
+    match unknown.verify(&certificates(), &FakeVerifier) {
+        Err(Su3Error::UnknownSigner(ref signer)) if signer == "unknown@example.i2p" => {}
+        other => panic!("Expected an unknown signer error, got: {:?}", other)
+    }
+    match tampered.verify(&certificates(), &FakeVerifier) {
+        Err(Su3Error::InvalidSignature) => {}
+        other => panic!("Expected an invalid signature error, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_router_infos_should_be_extracted_from_stored_and_deflated_zips() {
+    let first = FakeEntry::new(0x01, 1000, true);
+    let second = FakeEntry::new(0x02, 1000, true);
+    let first_bytes = first.to_bytes();
+    let second_bytes = second.to_bytes();
+    let files: Vec<(&str, &[u8])> = vec![
+        ("routerInfo-AQEB.dat", &first_bytes),
+        ("routerInfo-AgIC.dat", &second_bytes),
+        ("README.txt", b"not a router info"),
+    ];
+
+    for deflate in &[false, true] {
+        let archive = zip_archive(&files, *deflate);
+        let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", &archive)).unwrap();
+        let router_infos: Vec<FakeEntry> = su3.router_infos().unwrap();
+
+        assert_eq!(su3.zip_entries().unwrap().len(), 3);
+        assert_eq!(router_infos, vec![first.clone(), second.clone()]);
+    }
+}
+
+#[test]
+fn test_zip_entries_should_be_rejected_when_larger_than_declared() {
+    let data = vec![0x00; 100_000];
+    let files: Vec<(&str, &[u8])> = vec![("routerInfo-AQEB.dat", &data)];
+
+    // Declared lengths that are too small or too large, in the central directory.
+    for length in &[1_000, 64 * 1024 * 1024] {
+        let mut archive = zip_archive(&files, true);
+        let end = archive.len() - 22;
+        let directory = archive[end + 16..end + 20].iter().rev().fold(0, |value, byte| (value << 8) | *byte as usize);
+        archive[directory + 24..directory + 28].copy_from_slice(&(*length as u32).to_le_bytes());
+
+        let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", &archive)).unwrap();
+        match su3.zip_entries() {
+            Err(Su3Error::InvalidZip) => {}
+            other => panic!("Expected an invalid zip error, got: {:?}", other)
+        }
+    }
+}
+
+fn assert_invalid_zip(archive: &[u8]) {
+    let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", archive)).unwrap();
+    match su3.zip_entries() {
+        Err(Su3Error::InvalidZip) => {}
+        other => panic!("Expected an invalid zip error, got: {:?}", other.map(|entries| entries.len()))
+    }
+}
+
+#[test]
+fn test_zips_should_be_rejected_beyond_the_entry_count_and_total_length() {
+    let names: Vec<String> = (0..1025).map(|index| format!("routerInfo-{}.dat", index)).collect();
+    let mut files: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), &b"x"[..])).collect();
+    let archive = zip_archive(&files, false);
+    assert_invalid_zip(&archive);
+    files.pop();
+    let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", &zip_archive(&files, false))).unwrap();
+    assert_eq!(su3.zip_entries().unwrap().len(), 1024);
+
+    let data = vec![0x00; 9 * 1024 * 1024];
+    let files: Vec<(&str, &[u8])> = vec![("routerInfo-AQEB.dat", &data), ("routerInfo-AgIC.dat", &data)];
+    assert_invalid_zip(&zip_archive(&files, true));
+}
+
+#[test]
+fn test_zips_should_be_rejected_when_entries_share_a_local_header() {
+    let files: Vec<(&str, &[u8])> = vec![("routerInfo-AQEB.dat", b"first"), ("routerInfo-AgIC.dat", b"first")];
+    let mut archive = zip_archive(&files, false);
+    let su3 = Su3File::from_bytes(su3_file("reseed@example.i2p", &archive)).unwrap();
+    assert_eq!(su3.zip_entries().unwrap().len(), 2);
+
+    // Point the second directory entry at the local header of the first.
+    let end = archive.len() - 22;
+    let directory = archive[end + 16..end + 20].iter().rev().fold(0, |value, byte| (value << 8) | *byte as usize);
+    let second = directory + 46 + files[0].0.len();
+    archive[second + 42..second + 46].copy_from_slice(&0u32.to_le_bytes());
+
+    assert_invalid_zip(&archive);
+}
@@ -0,0 +1,125 @@
+use std::io::Write;
+use flate2::Crc;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use common::{SessionSigner, Signature, SignatureType, SignatureVerifier, SigningPublicKey};
+
+mod file;
+mod verifier;
+
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push(value as u8);
+    bytes.push((value >> 8) as u8);
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    push_u16(bytes, value as u16);
+    push_u16(bytes, (value >> 16) as u16);
+}
+
+/// Builds a zip archive holding `files`, deflating them when `deflate` is set.
+pub fn zip_archive(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for &(name, data) in files {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let (method, stored) = if deflate {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            (8, encoder.finish().unwrap())
+        } else {
+            (0, data.to_vec())
+        };
+        let offset = archive.len() as u32;
+
+        push_u32(&mut archive, 0x0403_4b50);
+        for value in &[20, 0, method, 0, 0] {
+            push_u16(&mut archive, *value);
+        }
+        push_u32(&mut archive, crc.sum());
+        push_u32(&mut archive, stored.len() as u32);
+        push_u32(&mut archive, data.len() as u32);
+        push_u16(&mut archive, name.len() as u16);
+        push_u16(&mut archive, 0);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&stored);
+
+        push_u32(&mut directory, 0x0201_4b50);
+        for value in &[20, 20, 0, method, 0, 0] {
+            push_u16(&mut directory, *value);
+        }
+        push_u32(&mut directory, crc.sum());
+        push_u32(&mut directory, stored.len() as u32);
+        push_u32(&mut directory, data.len() as u32);
+        for value in &[name.len() as u16, 0, 0, 0, 0] {
+            push_u16(&mut directory, *value);
+        }
+        push_u32(&mut directory, 0);
+        push_u32(&mut directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&directory);
+    push_u32(&mut archive, 0x0605_4b50);
+    for value in &[0, 0, files.len() as u16, files.len() as u16] {
+        push_u16(&mut archive, *value);
+    }
+    push_u32(&mut archive, directory.len() as u32);
+    push_u32(&mut archive, directory_offset);
+    push_u16(&mut archive, 0);
+
+    archive
+}
+
+/// The signature `FakeVerifier` accepts: every byte is the wrapping sum of the message.
+pub fn fake_signature(message: &[u8]) -> Vec<u8> {
+    let sum = message.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+    vec![sum; 64]
+}
+
+pub fn fake_key() -> SigningPublicKey {
+    SigningPublicKey::from_bytes(SignatureType::EdDSA_SHA512_Ed25519, &[0x01; 32]).unwrap()
+}
+
+/// Builds an Ed25519-signed reseed SU3 file around `content`.
+pub fn su3_file(signer_id: &str, content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"I2Psu3");
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x07, 0x00, 0x40, 0x00, 0x10, 0x00]);
+    bytes.push(signer_id.len() as u8);
+    for i in 0..8 {
+        bytes.push(((content.len() as u64) >> (56 - 8 * i)) as u8);
+    }
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x03]);
+    bytes.extend_from_slice(&[0x00; 12]);
+    let mut version = b"1539302400".to_vec();
+    version.resize(16, 0x00);
+    bytes.extend_from_slice(&version);
+    bytes.extend_from_slice(signer_id.as_bytes());
+    bytes.extend_from_slice(content);
+    let signature = fake_signature(&bytes);
+    bytes.extend_from_slice(&signature);
+
+    bytes
+}
+
+pub struct FakeVerifier;
+
+impl SignatureVerifier for FakeVerifier {
+    fn verify(&self, key: &SigningPublicKey, message: &[u8], signature: &Signature) -> bool {
+        *key == fake_key() && signature.as_ref() == fake_signature(message).as_slice()
+    }
+}
+
+pub struct FakeSigner;
+
+impl SessionSigner for FakeSigner {
+    fn sign(&self, message: &[u8]) -> Signature {
+        Signature::from_bytes(fake_key().signature_type(), &fake_signature(message)).unwrap()
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::PathBuf;
+use common::{SignatureType, SignatureVerifier};
+use su3::{CertificateStore, RsaVerifier, Su3Error, Su3File, signing_key_from_certificate};
+use tests::netdb::FakeEntry;
+use tests::util::temp_path;
+use super::fake_key;
+
+
+/// Holds `reseed.su3`, signed with the RSA 4096 key of `test_at_example.i2p.crt`.
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/su3/fixtures")
+}
+
+fn fixture_su3() -> Vec<u8> {
+    fs::read(fixtures().join("reseed.su3")).unwrap()
+}
+
+
+#[test]
+fn test_certificates_should_load_under_their_signer_ids() {
+    let mut certificates = CertificateStore::new();
+
+    assert_eq!(certificates.load_directory(fixtures()).unwrap(), 1);
+    let key = certificates.get("test@example.i2p").unwrap();
+    assert_eq!(key.signature_type(), SignatureType::RSA_SHA512_4096);
+    assert_eq!(key.as_ref().len(), 512);
+}
+
+#[test]
+fn test_rsa_verifier_should_verify_a_signed_su3_file() {
+    let mut certificates = CertificateStore::new();
+    certificates.load_directory(fixtures()).unwrap();
+
+    let su3 = Su3File::from_bytes(fixture_su3()).unwrap();
+    assert_eq!(su3.signature_type(), SignatureType::RSA_SHA512_4096);
+    su3.verify(&certificates, &RsaVerifier).unwrap();
+    let router_infos: Vec<FakeEntry> = su3.router_infos().unwrap();
+    assert_eq!(router_infos, vec![FakeEntry::new(0x05, 1_539_302_400_000, true)]);
+
+    // Flip a bit in the last byte of the content, just before the signature.
+    let mut tampered = fixture_su3();
+    let last = tampered.len() - 513;
+    tampered[last] ^= 0x01;
+    match Su3File::from_bytes(tampered).unwrap().verify(&certificates, &RsaVerifier) {
+        Err(Su3Error::InvalidSignature) => {}
+        other => panic!("Expected an invalid signature error, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_rsa_verifier_should_reject_other_signature_types() {
+    let su3 = Su3File::from_bytes(fixture_su3()).unwrap();
+
+    assert!(!RsaVerifier.verify(&fake_key(), su3.signed_bytes(), su3.signature()));
+}
+
+#[test]
+fn test_invalid_certificates_should_be_rejected() {
+    match signing_key_from_certificate("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n") {
+        Err(Su3Error::InvalidCertificate(_)) => {}
+        other => panic!("Expected an invalid certificate error, got: {:?}", other)
+    }
+
+    let directory = temp_path("certificates");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("bad_at_example.i2p.crt"), "not a certificate").unwrap();
+    fs::write(directory.join("README"), "ignored").unwrap();
+    match CertificateStore::new().load_directory(&directory) {
+        Err(Su3Error::InvalidCertificate(ref path)) if path.ends_with("bad_at_example.i2p.crt") => {}
+        other => panic!("Expected an invalid certificate error, got: {:?}", other)
+    }
+    fs::remove_dir_all(&directory).unwrap();
+}
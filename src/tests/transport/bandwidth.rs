@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+use transport::{BandwidthConfig, BandwidthLimiter, Direction, Priority, TokenBucket};
+
+
+fn config(rate: u64, share_percentage: u8) -> BandwidthConfig {
+    BandwidthConfig {
+        inbound_rate: rate,
+        inbound_burst: rate,
+        outbound_rate: rate,
+        outbound_burst: rate,
+        share_percentage
+    }
+}
+
+
+#[test]
+fn test_token_bucket_should_refill_at_its_rate_up_to_its_burst() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(1000, 2000, start);
+
+    assert!(bucket.try_consume(2000, start));
+    assert!(!bucket.try_consume(1, start));
+
+    // Three refills a third of a second apart must not lose the fractions.
+    for i in 1..4 {
+        bucket.available(start + Duration::from_nanos(333_333_334 * i));
+    }
+    assert_eq!(bucket.available(start + Duration::from_secs(1)), 1000);
+    assert_eq!(bucket.available(start + Duration::from_secs(60)), 2000);
+}
+
+#[test]
+fn test_high_priority_traffic_should_use_the_reserve() {
+    let now = Instant::now();
+    let mut limiter = BandwidthLimiter::new(config(10_000, 100), now);
+
+    assert!(limiter.request(Direction::Outbound, Priority::Normal, 9000, now));
+    assert!(!limiter.request(Direction::Outbound, Priority::Normal, 500, now));
+    assert!(limiter.request(Direction::Outbound, Priority::High, 1000, now));
+    assert!(limiter.request(Direction::Inbound, Priority::Normal, 9000, now));
+}
+
+#[test]
+fn test_participating_traffic_should_be_capped_by_the_share() {
+    let now = Instant::now();
+    let mut limiter = BandwidthLimiter::new(config(10_000, 50), now);
+
+    assert!(limiter.request(Direction::Outbound, Priority::Participating, 5000, now));
+    assert!(!limiter.request(Direction::Outbound, Priority::Participating, 1, now));
+    assert!(limiter.request(Direction::Outbound, Priority::Normal, 4000, now));
+
+    let later = now + Duration::from_secs(1);
+    assert!(limiter.request(Direction::Outbound, Priority::Participating, 5000, later));
+}
+
+#[test]
+fn test_caps_letter_should_follow_the_shared_bandwidth() {
+    let cases = [(11, 100, 'K'), (12, 100, 'L'), (60, 100, 'M'), (100, 100, 'N'),
+                 (200, 100, 'O'), (1000, 100, 'P'), (4000, 100, 'X'), (4000, 5, 'O')];
+
+    for &(kbps, share, letter) in cases.iter() {
+        assert_eq!(config(kbps * 1024, share).caps_letter(), letter);
+    }
+    assert_eq!(BandwidthConfig::default().caps_letter(), 'L');
+}
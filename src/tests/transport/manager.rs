@@ -0,0 +1,285 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use std::time::Instant;
+use common::{Ed25519Signer, Hash256, Hashable256, I2pDate, I2pInt64, Mapping, RouterAddress, RouterIdentity, RouterInfo};
+use i2np::{I2npMessage, MessageType};
+use netdb::NetDbStore;
+use transport::ssu2::{Introducer, introducer_options};
+use transport::{AddressBook, BandwidthConfig, BandwidthLimiter, PeerState, Transport, TransportAddress, TransportError, TransportManager, TransportStyle};
+use tests::util::{date, temp_path};
+
+
+#[derive(Clone, Debug, PartialEq)]
+enum Event {
+    Connect(TransportStyle, String),
+    Send(TransportStyle, u32)
+}
+
+struct MockTransport {
+    style: TransportStyle,
+    events: Rc<RefCell<Vec<Event>>>,
+    online: bool
+}
+
+impl Transport for MockTransport {
+    fn style(&self) -> TransportStyle {
+        self.style
+    }
+
+    fn connect(&mut self, _peer: &Hash256, address: &TransportAddress) -> bool {
+        let target = address.address.map(|address| address.to_string()).unwrap_or_else(|| "introducers".to_string());
+        self.events.borrow_mut().push(Event::Connect(self.style, target));
+        self.online
+    }
+
+    fn send(&mut self, _peer: &Hash256, message: &I2npMessage) -> bool {
+        self.events.borrow_mut().push(Event::Send(self.style, message.message_id));
+        true
+    }
+}
+
+struct MockBook(HashMap<Hash256, Vec<TransportAddress>>);
+
+impl AddressBook for MockBook {
+    fn addresses(&self, peer: &Hash256) -> Option<Vec<TransportAddress>> {
+        self.0.get(peer).cloned()
+    }
+}
+
+fn address(style: TransportStyle, cost: u8, host: &str) -> TransportAddress {
+    TransportAddress {
+        style,
+        cost,
+        address: Some(host.parse().unwrap()),
+        caps: String::new(),
+        introducers: 0
+    }
+}
+
+fn message(id: u32, expiration: u64) -> I2npMessage {
+    I2npMessage::new(MessageType::Data, id, I2pDate::new(I2pInt64::new(expiration)).unwrap(), vec![])
+}
+
+fn sized(message_type: MessageType, id: u32, length: usize) -> I2npMessage {
+    // The I2NP header counts 16 bytes against the limit.
+    I2npMessage::new(message_type, id, I2pDate::new(I2pInt64::new(5000)).unwrap(), vec![0; length - 16])
+}
+
+fn manager(addresses: Vec<TransportAddress>, ssu2_online: bool)
+    -> (TransportManager<MockBook>, Rc<RefCell<Vec<Event>>>, Hash256)
+{
+    let peer = Hash256::from([0x01; 32]);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let transports: Vec<Box<dyn Transport>> = vec![
+        Box::new(MockTransport { style: TransportStyle::Ntcp2, events: events.clone(), online: true }),
+        Box::new(MockTransport { style: TransportStyle::Ssu2, events: events.clone(), online: ssu2_online }),
+    ];
+    let mut book = HashMap::new();
+    book.insert(peer.clone(), addresses);
+
+    (TransportManager::new(MockBook(book), transports), events, peer)
+}
+
+
+#[test]
+fn test_messages_should_be_queued_until_the_cheapest_address_connects() {
+    let (mut manager, events, peer) = manager(vec![
+        address(TransportStyle::Ntcp2, 10, "10.0.0.1:1000"),
+        address(TransportStyle::Ssu2, 5, "10.0.0.1:2000"),
+        address(TransportStyle::Ssu2, 1, "[::1]:3000"),
+    ], true);
+    let now = date(1000);
+
+    manager.send(&peer, message(1, 5000), now).unwrap();
+    manager.send(&peer, message(2, 1500), now).unwrap();
+    assert_eq!(manager.state(&peer), PeerState::Connecting(TransportStyle::Ssu2));
+    assert_eq!(manager.queued(&peer), 2);
+
+    assert_eq!(manager.connection_established(&peer, TransportStyle::Ssu2, date(2000)), 1);
+    manager.send(&peer, message(3, 5000), date(2000)).unwrap();
+
+    assert_eq!(manager.state(&peer), PeerState::Connected(TransportStyle::Ssu2));
+    assert_eq!(*events.borrow(), vec![
+        Event::Connect(TransportStyle::Ssu2, "10.0.0.1:2000".to_string()),
+        Event::Send(TransportStyle::Ssu2, 1),
+        Event::Send(TransportStyle::Ssu2, 3),
+    ]);
+}
+
+#[test]
+fn test_failed_connections_should_fall_back_to_other_transports() {
+    let (mut manager, events, peer) = manager(vec![
+        address(TransportStyle::Ssu2, 3, "10.0.0.1:2000"),
+        address(TransportStyle::Ntcp2, 5, "10.0.0.1:1000"),
+        address(TransportStyle::Ntcp2, 8, "10.0.0.2:1000"),
+    ], false);
+
+    manager.send(&peer, message(1, 5000), date(1000)).unwrap();
+    assert_eq!(manager.state(&peer), PeerState::Connecting(TransportStyle::Ntcp2));
+
+    assert!(manager.connection_failed(&peer));
+    assert!(!manager.connection_failed(&peer));
+    assert_eq!(manager.state(&peer), PeerState::Unreachable);
+    assert_eq!(manager.queued(&peer), 0);
+    assert_eq!(*events.borrow(), vec![
+        Event::Connect(TransportStyle::Ssu2, "10.0.0.1:2000".to_string()),
+        Event::Connect(TransportStyle::Ntcp2, "10.0.0.1:1000".to_string()),
+        Event::Connect(TransportStyle::Ntcp2, "10.0.0.2:1000".to_string()),
+    ]);
+}
+
+#[test]
+fn test_introducer_addresses_should_only_be_used_as_a_last_resort() {
+    let mut firewalled = address(TransportStyle::Ssu2, 1, "10.0.0.1:2000");
+    firewalled.address = None;
+    firewalled.caps = "4".to_string();
+    firewalled.introducers = 2;
+    let (mut manager, events, peer) = manager(vec![firewalled, address(TransportStyle::Ntcp2, 10, "10.0.0.1:1000")], true);
+
+    manager.send(&peer, message(1, 5000), date(1000)).unwrap();
+
+    assert_eq!(events.borrow()[0], Event::Connect(TransportStyle::Ntcp2, "10.0.0.1:1000".to_string()));
+}
+
+#[test]
+fn test_unusable_addresses_and_expired_messages_should_be_rejected() {
+    let (mut manager, _events, peer) = manager(vec![address(TransportStyle::Ntcp2, 10, "[::1]:1000")], true);
+
+    assert_eq!(manager.send(&peer, message(1, 500), date(1000)), Err(TransportError::Expired));
+    assert_eq!(manager.send(&peer, message(2, 5000), date(1000)), Err(TransportError::NoAddress));
+
+    manager.set_ip_versions(true, true);
+    manager.set_max_queue_length(1);
+    manager.send(&peer, message(3, 5000), date(1000)).unwrap();
+    assert_eq!(manager.send(&peer, message(4, 5000), date(1000)), Err(TransportError::QueueFull));
+    assert_eq!(manager.expire(date(6000)), 1);
+}
+
+#[test]
+fn test_messages_beyond_the_bandwidth_limit_should_be_refused() {
+    let (mut manager, _events, peer) = manager(vec![address(TransportStyle::Ntcp2, 10, "10.0.0.1:1000")], true);
+    // Without refills, the buckets hold 1000 bytes, 100 of them reserved for high
+    // priority traffic, and participating traffic may use 500.
+    let config = BandwidthConfig {
+        inbound_rate: 0,
+        inbound_burst: 1000,
+        outbound_rate: 0,
+        outbound_burst: 1000,
+        share_percentage: 50
+    };
+    manager.set_bandwidth_limiter(BandwidthLimiter::new(config, Instant::now()));
+    let now = date(1000);
+
+    manager.send_participating(&peer, sized(MessageType::TunnelData, 1, 300), now).unwrap();
+    assert_eq!(manager.send_participating(&peer, sized(MessageType::TunnelData, 2, 300), now),
+               Err(TransportError::BandwidthExceeded));
+
+    manager.send(&peer, sized(MessageType::Data, 3, 584), now).unwrap();
+    assert_eq!(manager.send(&peer, sized(MessageType::Data, 4, 100), now), Err(TransportError::BandwidthExceeded));
+    manager.send(&peer, sized(MessageType::DatabaseStore, 5, 100), now).unwrap();
+    assert_eq!(manager.queued(&peer), 3);
+
+    assert!(manager.receive(&sized(MessageType::Data, 6, 900)));
+    assert!(!manager.receive(&sized(MessageType::Data, 7, 900)));
+    assert!(!manager.receive_participating(&sized(MessageType::TunnelData, 8, 100)));
+}
+
+#[test]
+fn test_refused_messages_should_not_spend_bandwidth() {
+    let (mut manager, _events, peer) = manager(vec![address(TransportStyle::Ntcp2, 10, "[::1]:1000")], true);
+    let config = BandwidthConfig {
+        inbound_rate: 0,
+        inbound_burst: 1000,
+        outbound_rate: 0,
+        outbound_burst: 1000,
+        share_percentage: 50
+    };
+    manager.set_bandwidth_limiter(BandwidthLimiter::new(config, Instant::now()));
+    manager.set_max_queue_length(1);
+    let now = date(1000);
+
+    assert_eq!(manager.send(&peer, sized(MessageType::Data, 1, 900), now), Err(TransportError::NoAddress));
+    assert_eq!(manager.send(&peer, sized(MessageType::Data, 2, 900), now), Err(TransportError::NoAddress));
+    manager.set_ip_versions(true, true);
+    manager.send(&peer, sized(MessageType::Data, 3, 100), now).unwrap();
+    assert_eq!(manager.send(&peer, sized(MessageType::Data, 4, 800), now), Err(TransportError::QueueFull));
+
+    manager.connection_established(&peer, TransportStyle::Ntcp2, now);
+    manager.send(&peer, sized(MessageType::Data, 5, 800), now).unwrap();
+}
+
+#[test]
+fn test_published_caps_should_match_the_configured_share() {
+    let (mut manager, _events, _peer) = manager(vec![], true);
+    let signer = Ed25519Signer::new(&[0x06; 32]);
+    let identity = RouterIdentity::new(&[0x06; 256], &signer.public_key());
+    let caps = |manager: &TransportManager<MockBook>| {
+        let router_info = manager.router_info(identity.clone(), date(1_000), vec![], &signer);
+        router_info.options().get("caps").map(str::to_string)
+    };
+
+    assert_eq!(caps(&manager), Some("L".to_string()));
+
+    let config = BandwidthConfig {
+        inbound_rate: 300 * 1024,
+        inbound_burst: 3000 * 1024,
+        outbound_rate: 200 * 1024,
+        outbound_burst: 2000 * 1024,
+        share_percentage: 50
+    };
+    manager.set_bandwidth_limiter(BandwidthLimiter::new(config, Instant::now()));
+    assert_eq!(caps(&manager), Some("N".to_string()));
+}
+
+fn router_address(cost: u8, style: &str, options: &[(&str, &str)]) -> RouterAddress {
+    let mut mapping = Mapping::new();
+    for &(key, value) in options {
+        mapping.insert(key, value).unwrap();
+    }
+    RouterAddress::new(cost, style, mapping).unwrap()
+}
+
+#[test]
+fn test_addresses_should_be_read_from_router_infos_in_the_network_database() {
+    let introducers = introducer_options(&[
+        Introducer { router: Hash256::from([0x0A; 32]), tag: 7, expiration: 1_539_303_000 },
+        Introducer { router: Hash256::from([0x0B; 32]), tag: 8, expiration: 1_539_303_000 },
+    ]);
+    let mut firewalled: Vec<(&str, &str)> = introducers.iter().map(|option| (option.0.as_str(), option.1.as_str())).collect();
+    firewalled.push(("caps", "4"));
+    let signer = Ed25519Signer::new(&[0x05; 32]);
+    let identity = RouterIdentity::new(&[0x05; 256], &signer.public_key());
+    let router_info = RouterInfo::new(identity.clone(), date(1_000), vec![
+        router_address(3, "SSU2", &firewalled),
+        router_address(10, "NTCP2", &[("host", "10.0.0.1"), ("port", "1000")]),
+        router_address(5, "NTCP2", &[("caps", "6")]),
+        router_address(1, "SSU", &[("host", "10.0.0.1"), ("port", "1001")]),
+    ], Mapping::new(), &signer);
+
+    let root = temp_path("manager").join("netDb");
+    let mut store: NetDbStore<RouterInfo> = NetDbStore::new(&root);
+    store.insert(router_info, date(1_000)).unwrap();
+    let peer = identity.hash_sha256();
+
+    let addresses = store.addresses(&peer).unwrap();
+    assert_eq!(addresses, vec![
+        TransportAddress { style: TransportStyle::Ssu2, cost: 3, address: None, caps: "4".to_string(), introducers: 2 },
+        TransportAddress { style: TransportStyle::Ntcp2, cost: 10, address: Some("10.0.0.1:1000".parse().unwrap()),
+                           caps: String::new(), introducers: 0 },
+        TransportAddress { style: TransportStyle::Ntcp2, cost: 5, address: None, caps: "6".to_string(), introducers: 0 },
+    ]);
+    assert_eq!(store.addresses(&Hash256::from([0x01; 32])), None);
+
+    // The published NTCP2 host is preferred over the introducers.
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let transports: Vec<Box<dyn Transport>> = vec![
+        Box::new(MockTransport { style: TransportStyle::Ntcp2, events: events.clone(), online: true }),
+        Box::new(MockTransport { style: TransportStyle::Ssu2, events: events.clone(), online: true }),
+    ];
+    let mut manager = TransportManager::new(store, transports);
+    manager.send(&peer, message(1, 5000), date(1_000)).unwrap();
+    assert_eq!(events.borrow()[0], Event::Connect(TransportStyle::Ntcp2, "10.0.0.1:1000".to_string()));
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
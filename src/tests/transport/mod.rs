@@ -0,0 +1,5 @@
+mod ntcp2;
+mod ssu2;
+mod bandwidth;
+mod manager;
+mod reachability;
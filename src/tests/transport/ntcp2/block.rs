@@ -0,0 +1,52 @@
+use common::{I2pDate, I2pInt64};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::{Block, Options, TerminationReason, Ntcp2Error};
+use transport::ntcp2::{encode_blocks, decode_blocks};
+
+
+fn message(payload: Vec<u8>) -> I2npMessage {
+    let expiration = I2pDate::new(I2pInt64::new(1_500_000_000_000)).unwrap();
+
+    I2npMessage::new(MessageType::TunnelData, 0xDEAD_BEEF, expiration, payload)
+}
+
+
+#[test]
+fn test_blocks_should_survive_an_encoding_round_trip() {
+    let options = Options { tmin: 1, tmax: 2, rmin: 3, rmax: 4, tdmy: 5, rdmy: 6, tdelay: 7, rdelay: 8 };
+    let blocks = vec![
+        Block::DateTime(1_500_000_000),
+        Block::Options(options),
+        Block::RouterInfo { flood: true, router_info: vec![0xAA; 40] },
+        Block::I2np(message(vec![0x01, 0x02, 0x03])),
+        Block::Termination { valid_frames: 42, reason: TerminationReason::IdleTimeout },
+        Block::Unknown(200, vec![0x55; 3]),
+        Block::Padding(vec![0x00; 17]),
+    ];
+
+    let payload = encode_blocks(&blocks);
+
+    assert_eq!(payload.len(), blocks.iter().map(|block| block.encoded_len()).sum::<usize>());
+    assert_eq!(decode_blocks(&payload).unwrap(), blocks);
+}
+
+#[test]
+fn test_i2np_block_should_use_the_short_header() {
+    let payload = encode_blocks(&[Block::I2np(message(vec![0x07]))]);
+
+    assert_eq!(payload, vec![3, 0, 10, 18, 0xDE, 0xAD, 0xBE, 0xEF, 0x59, 0x68, 0x2F, 0x00, 0x07]);
+}
+
+#[test]
+fn test_decoding_should_reject_padding_before_other_blocks_and_short_blocks() {
+    let misplaced = encode_blocks(&[Block::Padding(vec![0x00; 4]), Block::DateTime(1)]);
+    let truncated = vec![0x00, 0x00, 0x04, 0x01, 0x02];
+    let short_date = vec![0x00, 0x00, 0x02, 0x01, 0x02];
+
+    for payload in &[misplaced, truncated, short_date] {
+        match decode_blocks(payload) {
+            Err(Ntcp2Error::InvalidBlock) => {}
+            other => panic!("Expected an invalid block, got: {:?}", other)
+        }
+    }
+}
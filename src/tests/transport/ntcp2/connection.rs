@@ -0,0 +1,149 @@
+use std::net::{TcpListener, TcpStream};
+use common::{I2pDate, I2pInt64};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::{Block, CipherState, Connection, LengthObfuscator, SipKeys};
+use transport::ntcp2::{Ntcp2Error, TerminationReason, MAC_LENGTH};
+
+
+/// A stand-in for ChaCha20-Poly1305: XORs the payload with a nonce-derived byte and
+/// appends a checksum of the ciphertext and nonce as the MAC.
+struct FakeCipher {
+    nonce: u64
+}
+
+impl FakeCipher {
+    fn new() -> FakeCipher {
+        FakeCipher { nonce: 0 }
+    }
+
+    fn mac(nonce: u64, ciphertext: &[u8]) -> [u8; MAC_LENGTH] {
+        let sum = ciphertext.iter().fold(nonce as u8, |sum, byte| sum.wrapping_add(*byte));
+        [sum; MAC_LENGTH]
+    }
+}
+
+impl CipherState for FakeCipher {
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let mut frame: Vec<u8> = plaintext.iter().map(|byte| byte ^ (self.nonce as u8 ^ 0x5A)).collect();
+        let mac = FakeCipher::mac(self.nonce, &frame);
+        frame.extend_from_slice(&mac);
+        self.nonce += 1;
+
+        frame
+    }
+
+    fn decrypt(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let (ciphertext, mac) = frame.split_at(frame.len() - MAC_LENGTH);
+        if mac != FakeCipher::mac(self.nonce, ciphertext) {
+            return None;
+        }
+        let plaintext = ciphertext.iter().map(|byte| byte ^ (self.nonce as u8 ^ 0x5A)).collect();
+        self.nonce += 1;
+
+        Some(plaintext)
+    }
+}
+
+const ALICE_TO_BOB: SipKeys = SipKeys { k1: 1, k2: 2, iv: 3 };
+const BOB_TO_ALICE: SipKeys = SipKeys { k1: 4, k2: 5, iv: 6 };
+
+fn connection_pair() -> (Connection<TcpStream, FakeCipher>, Connection<TcpStream, FakeCipher>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let alice = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (bob, _) = listener.accept().unwrap();
+
+    let alice = Connection::new(alice, FakeCipher::new(), FakeCipher::new(), ALICE_TO_BOB, BOB_TO_ALICE);
+    let bob = Connection::new(bob, FakeCipher::new(), FakeCipher::new(), BOB_TO_ALICE, ALICE_TO_BOB);
+
+    (alice, bob)
+}
+
+fn message(id: u32, length: usize) -> I2npMessage {
+    let expiration = I2pDate::new(I2pInt64::new(2_000_000_000_000)).unwrap();
+
+    I2npMessage::new(MessageType::Data, id, expiration, vec![id as u8; length])
+}
+
+
+#[test]
+fn test_length_obfuscation_should_round_trip_and_change_every_frame() {
+    let mut sender = LengthObfuscator::new(ALICE_TO_BOB);
+    let mut receiver = LengthObfuscator::new(ALICE_TO_BOB);
+
+    let first = sender.obfuscate(1000);
+    let second = sender.obfuscate(1000);
+
+    assert!(first != second);
+    assert_eq!(receiver.deobfuscate(first), 1000);
+    assert_eq!(receiver.deobfuscate(second), 1000);
+}
+
+#[test]
+fn test_small_messages_should_be_coalesced_into_one_frame() {
+    let (mut alice, mut bob) = connection_pair();
+    let messages = vec![message(1, 100), message(2, 200), message(3, 300)];
+
+    let frames = alice.send_messages(&messages).unwrap();
+
+    assert_eq!(frames, 1);
+    assert_eq!(bob.receive_messages().unwrap(), messages);
+    assert_eq!(bob.stats().messages_received, 3);
+    assert_eq!(bob.stats().bytes_received, alice.stats().bytes_sent);
+}
+
+#[test]
+fn test_large_messages_should_be_split_across_frames() {
+    let (mut alice, mut bob) = connection_pair();
+    let messages = vec![message(1, 40_000), message(2, 40_000), message(3, 10)];
+
+    let frames = alice.send_messages(&messages).unwrap();
+
+    assert_eq!(frames, 2);
+    assert_eq!(bob.receive_messages().unwrap(), vec![messages[0].clone()]);
+    assert_eq!(bob.receive_messages().unwrap(), vec![messages[1].clone(), messages[2].clone()]);
+    assert_eq!(alice.stats().frames_sent, 2);
+    assert_eq!(bob.stats().frames_received, 2);
+}
+
+#[test]
+fn test_messages_too_large_for_a_frame_should_be_rejected() {
+    let (mut alice, _bob) = connection_pair();
+
+    match alice.send_messages(&[message(1, 70_000)]) {
+        Err(Ntcp2Error::FrameTooLarge(_)) => {}
+        other => panic!("Expected a frame too large error, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_termination_should_close_both_sides() {
+    let (mut alice, mut bob) = connection_pair();
+    alice.send_messages(&[message(1, 10)]).unwrap();
+    bob.receive_messages().unwrap();
+
+    bob.terminate(TerminationReason::IdleTimeout).unwrap();
+    let blocks = alice.receive_frame().unwrap();
+
+    assert_eq!(blocks, vec![Block::Termination { valid_frames: 1, reason: TerminationReason::IdleTimeout }]);
+    assert_eq!(alice.termination(), Some(TerminationReason::IdleTimeout));
+    match alice.send_messages(&[message(2, 10)]) {
+        Err(Ntcp2Error::Terminated(TerminationReason::IdleTimeout)) => {}
+        other => panic!("Expected a terminated connection, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_frames_failing_authentication_should_terminate_the_connection() {
+    let (mut alice, bob) = connection_pair();
+    alice.send_messages(&[message(1, 10)]).unwrap();
+    // Bob's cipher expects the first nonce; skipping it makes the frame fail.
+    let mut skipped = FakeCipher::new();
+    skipped.encrypt(&[]);
+    let mut bob = Connection::new(bob.into_inner(), FakeCipher::new(), skipped, BOB_TO_ALICE, ALICE_TO_BOB);
+
+    match bob.receive_messages() {
+        Err(Ntcp2Error::Decryption) => {}
+        other => panic!("Expected a decryption failure, got: {:?}", other)
+    }
+    assert_eq!(bob.termination(), Some(TerminationReason::DataPhaseAeadFailure));
+}
@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use rand;
+use rand::Rng;
+use common::{I2pDate, I2pInt64, Mapping, RouterIdentity, RouterInfo};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::{Block, Ntcp2Error, ReplayCache, StaticKeys, TerminationReason, accept, connect};
+use tests::su3::{FakeSigner, FakeVerifier, fake_key};
+use tests::util::date;
+
+
+const NOW: u64 = 1_539_302_400_000;
+
+struct Router {
+    keys: StaticKeys,
+    router_info: RouterInfo
+}
+
+/// Generates a router with fresh static keys whose RouterInfo publishes them.
+fn router(address: SocketAddr) -> Router {
+    let keys = StaticKeys::generate();
+    let router_info = router_info(&keys, address);
+
+    Router { keys, router_info }
+}
+
+fn router_info(keys: &StaticKeys, address: SocketAddr) -> RouterInfo {
+    let mut public_key = [0x00; 256];
+    rand::thread_rng().fill_bytes(&mut public_key);
+    let identity = RouterIdentity::new(&public_key, &fake_key());
+
+    RouterInfo::new(identity, date(NOW), vec![keys.router_address(10, address)], Mapping::new(), &FakeSigner)
+}
+
+/// Runs the handshake between Alice and Bob over loopback TCP, with Bob's clock at
+/// `bob_now`. Alice connects to the router described by `target`.
+fn handshake(alice: Router, bob: Router, target: RouterInfo, bob_now: u64)
+    -> (Result<(), Ntcp2Error>, Result<RouterInfo, Ntcp2Error>) {
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let responder = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        accept(stream, &bob.keys, &bob.router_info, &ReplayCache::new(date(bob_now)), &FakeVerifier, date(bob_now)).map(|(_, peer)| peer)
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    let initiator = connect(stream, &alice.keys, &alice.router_info, &target, date(NOW)).map(|_| ());
+
+    (initiator, responder.join().unwrap())
+}
+
+#[test]
+fn test_generated_routers_should_complete_the_handshake_and_exchange_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let bob = router(address);
+    let bob_info = bob.router_info.clone();
+    let alice_info = alice.router_info.clone();
+
+    let responder = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let (mut connection, peer) = accept(stream, &bob.keys, &bob.router_info, &ReplayCache::new(date(NOW)), &FakeVerifier, date(NOW)).unwrap();
+        let blocks = connection.receive_frame().unwrap();
+        connection.send_frame(&[Block::DateTime(7)]).unwrap();
+
+        (peer, blocks)
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    let mut connection = connect(stream, &alice.keys, &alice.router_info, &bob_info, date(NOW)).unwrap();
+    let expiration = I2pDate::new(I2pInt64::new(NOW + 60_000)).unwrap();
+    let message = I2npMessage::new(MessageType::Data, 42, expiration, b"hello bob".to_vec());
+    assert_eq!(connection.send_messages(std::slice::from_ref(&message)).unwrap(), 1);
+    assert_eq!(connection.receive_frame().unwrap(), vec![Block::DateTime(7)]);
+
+    let (peer, blocks) = responder.join().unwrap();
+    assert_eq!(peer, alice_info);
+    assert_eq!(blocks, vec![Block::I2np(message)]);
+}
+
+#[test]
+fn test_the_responder_should_refuse_a_skewed_clock() {
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let bob = router("127.0.0.1:2".parse().unwrap());
+    let target = bob.router_info.clone();
+
+    let (initiator, responder) = handshake(alice, bob, target, NOW + 120_000);
+
+    assert!(initiator.is_err());
+    match responder {
+        Err(Ntcp2Error::Handshake(TerminationReason::ClockSkew)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_the_responder_should_refuse_keys_obfuscated_for_another_router() {
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let bob = router("127.0.0.1:2".parse().unwrap());
+    let carol = router("127.0.0.1:3".parse().unwrap());
+
+    let (initiator, responder) = handshake(alice, bob, carol.router_info, NOW);
+
+    assert!(initiator.is_err());
+    match responder {
+        Err(Ntcp2Error::Handshake(TerminationReason::SessionRequestError)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_the_responder_should_refuse_a_router_info_for_another_static_key() {
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let bob = router("127.0.0.1:2".parse().unwrap());
+    let target = bob.router_info.clone();
+    let impostor = Router {
+        router_info: router_info(&StaticKeys::generate(), "127.0.0.1:1".parse().unwrap()),
+        keys: alice.keys
+    };
+
+    let (_, responder) = handshake(impostor, bob, target, NOW);
+
+    match responder {
+        Err(Ntcp2Error::Handshake(TerminationReason::StaticKeyMismatch)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+/// A stream that keeps a copy of every write.
+struct Recorder {
+    stream: TcpStream,
+    written: Rc<RefCell<Vec<Vec<u8>>>>
+}
+
+impl Read for Recorder {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buffer)
+    }
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buffer)?;
+        self.written.borrow_mut().push(buffer[..written].to_vec());
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[test]
+fn test_the_responder_should_refuse_a_replayed_session_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let bob = router(address);
+    let bob_info = bob.router_info.clone();
+
+    let responder = thread::spawn(move || {
+        let replays = ReplayCache::new(date(NOW));
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            results.push(accept(stream, &bob.keys, &bob.router_info, &replays, &FakeVerifier, date(NOW)).map(|_| ()));
+        }
+        results
+    });
+
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let stream = Recorder { stream: TcpStream::connect(address).unwrap(), written: written.clone() };
+    connect(stream, &alice.keys, &alice.router_info, &bob_info, date(NOW)).unwrap();
+    let session_request = written.borrow()[0].clone();
+    let mut replay = TcpStream::connect(address).unwrap();
+    replay.write_all(&session_request).unwrap();
+
+    let results = responder.join().unwrap();
+    assert!(results[0].is_ok());
+    match results[1] {
+        Err(Ntcp2Error::Replay) => {}
+        ref other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_connect_should_require_an_ntcp2_address() {
+    let alice = router("127.0.0.1:1".parse().unwrap());
+    let identity = RouterIdentity::new(&[0x01; 256], &fake_key());
+    let unreachable = RouterInfo::new(identity, date(NOW), Vec::new(), Mapping::new(), &FakeSigner);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+    match connect(stream, &alice.keys, &alice.router_info, &unreachable, date(NOW)) {
+        Err(Ntcp2Error::InvalidAddress) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ()))
+    }
+}
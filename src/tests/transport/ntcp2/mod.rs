@@ -0,0 +1,3 @@
+mod block;
+mod connection;
+mod handshake;
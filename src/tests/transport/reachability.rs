@@ -0,0 +1,29 @@
+use transport::{AddressPublication, Reachability, ReachabilityTracker};
+
+
+#[test]
+fn test_first_conclusive_result_should_be_taken_as_it_is() {
+    let mut tracker = ReachabilityTracker::new();
+
+    assert!(!tracker.record(Reachability::Unknown));
+    assert_eq!(tracker.publication(), None);
+    assert!(tracker.record(Reachability::Firewalled));
+    assert_eq!(tracker.publication(), Some(AddressPublication::Introducers));
+}
+
+#[test]
+fn test_known_state_should_change_only_after_consecutive_results() {
+    let mut tracker = ReachabilityTracker::new();
+    tracker.record(Reachability::Ok);
+
+    assert!(!tracker.record(Reachability::Firewalled));
+    assert!(!tracker.record(Reachability::Ok));
+    assert!(!tracker.record(Reachability::Firewalled));
+    assert!(!tracker.record(Reachability::Unknown));
+    assert!(tracker.record(Reachability::Firewalled));
+    assert_eq!(tracker.state(), Reachability::Firewalled);
+
+    tracker.set_confirmations(1);
+    assert!(tracker.record(Reachability::SymmetricNat));
+    assert_eq!(tracker.publication(), Some(AddressPublication::Introducers));
+}
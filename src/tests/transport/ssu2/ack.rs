@@ -0,0 +1,62 @@
+use transport::ssu2::{Ack, AckTracker, MAX_ACK_RANGES};
+
+
+#[test]
+fn test_ack_should_list_the_packets_it_acknowledges() {
+    let ack = Ack::new(10, 2, vec![(2, 1), (1, 2)]);
+
+    assert_eq!(ack.acked(), vec![10, 9, 8, 5, 3, 2]);
+    assert!(ack.contains(5));
+    assert!(!ack.contains(7));
+}
+
+#[test]
+fn test_ack_should_stop_at_packet_zero() {
+    let ack = Ack::new(1, 5, vec![(0, 10)]);
+
+    assert_eq!(ack.acked(), vec![1, 0]);
+}
+
+#[test]
+fn test_tracker_should_build_ranges_for_gaps() {
+    let mut tracker = AckTracker::new();
+    assert_eq!(tracker.ack(), None);
+
+    for number in &[0, 1, 2, 5, 8, 9, 10] {
+        assert!(tracker.receive(*number));
+    }
+    assert!(!tracker.receive(5));
+
+    let ack = tracker.ack().unwrap();
+    assert_eq!(ack, Ack::new(10, 2, vec![(2, 1), (2, 3)]));
+    assert_eq!(ack.acked(), vec![10, 9, 8, 5, 2, 1, 0]);
+}
+
+#[test]
+fn test_tracker_should_split_long_runs_and_limit_ranges() {
+    let mut tracker = AckTracker::new();
+    for number in 0..300 {
+        tracker.receive(number);
+    }
+    tracker.receive(900);
+
+    let ack = tracker.ack().unwrap();
+    assert_eq!(ack, Ack::new(900, 0, vec![(255, 0), (255, 0), (90, 255), (0, 45)]));
+    assert_eq!(ack.acked().len(), 301);
+
+    let mut tracker = AckTracker::new();
+    for number in 0..200 {
+        tracker.receive(2 * number);
+    }
+    assert_eq!(tracker.ack().unwrap().ranges.len(), MAX_ACK_RANGES);
+}
+
+#[test]
+fn test_tracker_should_forget_packets_outside_its_window() {
+    let mut tracker = AckTracker::new();
+    tracker.receive(0);
+    tracker.receive(5000);
+
+    assert!(!tracker.receive(1));
+    assert_eq!(tracker.ack().unwrap(), Ack::new(5000, 0, vec![]));
+}
@@ -0,0 +1,45 @@
+use common::{I2pDate, I2pInt64};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::TerminationReason;
+use transport::ssu2::{Ack, Block, Ssu2Error, encode_blocks, decode_blocks};
+
+
+#[test]
+fn test_blocks_should_survive_an_encoding_round_trip() {
+    let expiration = I2pDate::new(I2pInt64::new(1_500_000_000_000)).unwrap();
+    let blocks = vec![
+        Block::DateTime(1_500_000_000),
+        Block::I2np(I2npMessage::new(MessageType::Data, 7, expiration, vec![0x01; 10])),
+        Block::FirstFragment { message_type: MessageType::Garlic, message_id: 8, expiration, data: vec![0x02; 20] },
+        Block::FollowOnFragment { fragment: 3, last: true, message_id: 8, data: vec![0x03; 5] },
+        Block::Ack(Ack::new(100, 2, vec![(3, 4), (0, 1)])),
+        Block::PathChallenge(vec![0x04; 8]),
+        Block::PathResponse(vec![0x05; 8]),
+        Block::Termination { valid_frames: 9, reason: TerminationReason::IdleTimeout },
+        Block::Address("203.0.113.7:9000".parse().unwrap()),
+        Block::RouterInfo { flood: true, router_info: vec![0x07; 40] },
+        Block::Unknown(20, vec![0x06; 4]),
+        Block::Padding(vec![0x00; 13]),
+    ];
+
+    let payload = encode_blocks(&blocks);
+
+    assert_eq!(payload.len(), blocks.iter().map(|block| block.encoded_len()).sum::<usize>());
+    assert_eq!(decode_blocks(&payload).unwrap(), blocks);
+}
+
+#[test]
+fn test_decoding_should_reject_malformed_blocks() {
+    let misplaced = encode_blocks(&[Block::Padding(vec![0x00; 4]), Block::DateTime(1)]);
+    let odd_ack = vec![12, 0x00, 0x06, 0x00, 0x00, 0x00, 0x64, 0x00, 0x01];
+    let fragment_zero = vec![5, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x08];
+    let short_address = vec![13, 0x00, 0x04, 0x23, 0x28, 0x7f, 0x00];
+    let gzipped_router_info = vec![2, 0x00, 0x03, 0x02, 0x01, 0x00];
+
+    for payload in &[misplaced, odd_ack, fragment_zero, short_address, gzipped_router_info] {
+        match decode_blocks(payload) {
+            Err(Ssu2Error::InvalidBlock) => {}
+            other => panic!("Expected an invalid block, got: {:?}", other)
+        }
+    }
+}
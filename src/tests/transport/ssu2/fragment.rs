@@ -0,0 +1,61 @@
+use i2np::{I2npMessage, MessageType};
+use transport::ssu2::{Block, Reassembler, Ssu2Error, fragment};
+use tests::util::date;
+
+
+fn message(length: usize) -> I2npMessage {
+    let payload = (0..length).map(|i| i as u8).collect();
+
+    I2npMessage::new(MessageType::TunnelData, 42, date(1_500_000_000_000), payload)
+}
+
+
+#[test]
+fn test_small_messages_should_not_be_fragmented() {
+    let message = message(100);
+
+    assert_eq!(fragment(&message, 1000).unwrap(), vec![Block::I2np(message)]);
+}
+
+#[test]
+fn test_fragments_should_fit_and_reassemble_in_any_order() {
+    let message = message(1000);
+    let mut blocks = fragment(&message, 300).unwrap();
+
+    assert_eq!(blocks.len(), 4);
+    assert!(blocks.iter().all(|block| block.encoded_len() <= 300));
+    match blocks[3] {
+        Block::FollowOnFragment { fragment: 3, last: true, .. } => {}
+        ref other => panic!("Expected the last follow-on fragment, got: {:?}", other)
+    }
+
+    let mut reassembler = Reassembler::new();
+    blocks.reverse();
+    let last = blocks.pop().unwrap();
+    for block in blocks {
+        assert_eq!(reassembler.receive(block, date(1)), None);
+    }
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.receive(last, date(1)), Some(message));
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn test_messages_needing_too_many_fragments_should_be_rejected() {
+    match fragment(&message(20_000), 100) {
+        Err(Ssu2Error::MessageTooLarge(20_000)) => {}
+        other => panic!("Expected a message too large error, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_incomplete_messages_should_expire() {
+    let blocks = fragment(&message(1000), 300).unwrap();
+    let mut reassembler = Reassembler::new();
+    reassembler.set_timeout(1000);
+    reassembler.receive(blocks[0].clone(), date(1));
+
+    assert_eq!(reassembler.expire(date(1001)), 0);
+    assert_eq!(reassembler.expire(date(1002)), 1);
+    assert_eq!(reassembler.pending(), 0);
+}
@@ -0,0 +1,52 @@
+use transport::ssu2::{LongHeader, ShortHeader, mask_connection_id, mask_header, obfuscate_long_header, protect};
+use transport::ssu2::{LONG_HEADER_LENGTH, SHORT_HEADER_LENGTH};
+
+
+fn long_header() -> LongHeader {
+    LongHeader {
+        destination_id: 0x0102_0304_0506_0708,
+        packet_number: 42,
+        message_type: 10,
+        network_id: 2,
+        source_id: 0x1112_1314_1516_1718,
+        token: 99
+    }
+}
+
+#[test]
+fn test_headers_should_survive_an_encoding_round_trip() {
+    let long = long_header();
+    let short = ShortHeader { destination_id: 7, packet_number: 8, message_type: 6, flags: [0x01, 0x02, 0x03] };
+
+    assert_eq!(LongHeader::from_bytes(&long.to_bytes()), Some(long));
+    assert_eq!(ShortHeader::from_bytes(&short.to_bytes()), Some(short));
+}
+
+#[test]
+fn test_long_headers_of_other_versions_should_be_rejected() {
+    let mut bytes = long_header().to_bytes();
+    bytes[13] = 1;
+
+    assert_eq!(LongHeader::from_bytes(&bytes), None);
+}
+
+#[test]
+fn test_protected_headers_should_only_unmask_with_the_right_keys() {
+    let mut packet = long_header().to_bytes().to_vec();
+    packet.extend((0..40).map(|i| i as u8));
+    let original = packet.clone();
+
+    protect(&mut packet, &[0x01; 32], &[0x02; 32], LONG_HEADER_LENGTH);
+    assert_ne!(packet[..LONG_HEADER_LENGTH], original[..LONG_HEADER_LENGTH]);
+    assert_eq!(packet[LONG_HEADER_LENGTH..], original[LONG_HEADER_LENGTH..]);
+
+    let mut wrong = packet.clone();
+    mask_connection_id(&mut wrong, &[0x03; 32]);
+    assert_ne!(wrong[..8], original[..8]);
+
+    mask_connection_id(&mut packet, &[0x01; 32]);
+    mask_header(&mut packet, &[0x02; 32]);
+    assert_eq!(packet[..SHORT_HEADER_LENGTH], original[..SHORT_HEADER_LENGTH]);
+    obfuscate_long_header(&mut packet, &[0x02; 32], LONG_HEADER_LENGTH);
+    assert_eq!(packet, original);
+}
@@ -0,0 +1,8 @@
+mod block;
+mod ack;
+mod fragment;
+mod path;
+mod peer_test;
+mod relay;
+mod header;
+mod udp;
@@ -0,0 +1,25 @@
+use transport::ssu2::{Block, PathValidator};
+
+
+#[test]
+fn test_only_the_echoed_challenge_should_validate_the_address() {
+    let address = "127.0.0.1:12345".parse().unwrap();
+    let other = "127.0.0.1:23456".parse().unwrap();
+    let mut validator = PathValidator::new();
+
+    let data = match validator.challenge(address) {
+        Block::PathChallenge(data) => data,
+        other => panic!("Expected a path challenge, got: {:?}", other)
+    };
+    let response = match PathValidator::response(&data) {
+        Block::PathResponse(response) => response,
+        other => panic!("Expected a path response, got: {:?}", other)
+    };
+
+    assert!(!validator.validate(&address, &[0x00; 8]));
+    assert!(!validator.validate(&other, &response));
+    assert!(validator.is_pending(&address));
+    assert!(validator.validate(&address, &response));
+    assert!(!validator.is_pending(&address));
+    assert!(!validator.validate(&address, &response));
+}
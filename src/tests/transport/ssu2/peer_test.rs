@@ -0,0 +1,246 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use common::{Ed25519Signer, Ed25519Verifier, Hash256, SessionSigner, SigningPublicKey};
+use transport::Reachability;
+use transport::ssu2::{Block, PeerTest, PeerTester, PeerTestNetwork, BOB_REJECT_NO_CHARLIE};
+use transport::ssu2::{PEER_TEST_ACCEPT, CHARLIE_REJECT_SIGNATURE};
+use transport::ssu2::{encode_blocks, decode_blocks};
+use tests::util::date;
+
+
+/// How Alice's NAT treats her traffic.
+#[derive(Copy, Clone, PartialEq)]
+enum Nat {
+    None,
+    /// Inbound packets only pass from addresses Alice has sent to.
+    Cone,
+    /// Like `Cone`, but each destination sees Alice behind a different port.
+    Symmetric
+}
+
+enum Destination {
+    Router(Hash256),
+    Address(SocketAddr)
+}
+
+struct Envelope {
+    from: Hash256,
+    to: Destination,
+    test: PeerTest
+}
+
+/// An in-memory network of Alice, Bob and Charlie. Peer tests are queued as they
+/// are sent and delivered one at a time, with Alice's NAT applied to packets sent
+/// outside of sessions.
+struct SimulatedNetwork {
+    sender: Hash256,
+    queue: VecDeque<Envelope>,
+    addresses: HashMap<Hash256, SocketAddr>,
+    keys: HashMap<Hash256, SigningPublicKey>,
+    sessions: Vec<Hash256>
+}
+
+type Tester = PeerTester<Ed25519Signer, Ed25519Verifier>;
+
+impl PeerTestNetwork for SimulatedNetwork {
+    fn send_in_session(&mut self, peer: &Hash256, test: PeerTest) -> bool {
+        self.queue.push_back(Envelope { from: self.sender.clone(), to: Destination::Router(peer.clone()), test });
+        true
+    }
+
+    fn send_out_of_session(&mut self, address: SocketAddr, test: PeerTest) {
+        self.queue.push_back(Envelope { from: self.sender.clone(), to: Destination::Address(address), test });
+    }
+
+    fn address_of(&self, router: &Hash256) -> Option<SocketAddr> {
+        self.addresses.get(router).cloned()
+    }
+
+    fn choose_charlie(&self, exclude: &[Hash256]) -> Option<Hash256> {
+        self.sessions.iter().find(|router| !exclude.contains(router)).cloned()
+    }
+
+    fn signing_key(&self, router: &Hash256) -> Option<SigningPublicKey> {
+        self.keys.get(router).cloned()
+    }
+}
+
+fn hash(byte: u8) -> Hash256 {
+    Hash256::from([byte; 32])
+}
+
+fn signer(byte: u8) -> Ed25519Signer {
+    Ed25519Signer::new(&[byte; 32])
+}
+
+/// Creates the testers of Alice, Bob and Charlie, and a network that knows their
+/// addresses and keys.
+fn setup(charlies: Vec<Hash256>) -> (HashMap<Hash256, Tester>, SimulatedNetwork) {
+    let mut routers = HashMap::new();
+    let mut network = SimulatedNetwork {
+        sender: hash(1),
+        queue: VecDeque::new(),
+        addresses: HashMap::new(),
+        keys: HashMap::new(),
+        sessions: charlies
+    };
+    for &(byte, ip) in &[(1, "10.0.0.1:1000"), (2, "10.0.0.2:2000"), (3, "10.0.0.3:3000")] {
+        routers.insert(hash(byte), PeerTester::new(hash(byte), signer(byte), Ed25519Verifier));
+        network.addresses.insert(hash(byte), address(ip));
+        network.keys.insert(hash(byte), signer(byte).public_key());
+    }
+
+    (routers, network)
+}
+
+/// Takes the tests queued for `router` inside sessions.
+fn sent_to(network: &mut SimulatedNetwork, router: &Hash256) -> Vec<PeerTest> {
+    let (sent, rest): (Vec<Envelope>, Vec<Envelope>) = network.queue.drain(..).partition(|envelope| match envelope.to {
+        Destination::Router(ref to) => to == router,
+        Destination::Address(_) => false
+    });
+    network.queue = rest.into_iter().collect();
+
+    sent.into_iter().map(|envelope| envelope.test).collect()
+}
+
+fn address(text: &str) -> SocketAddr {
+    text.parse().unwrap()
+}
+
+/// Runs one test from Alice and returns her result.
+fn run(nat: Nat, charlies: Vec<Hash256>) -> Reachability {
+    let (alice, bob) = (hash(1), hash(2));
+    let alice_address = address("10.0.0.1:1000");
+    let alice_symmetric_address = address("10.0.0.1:1001");
+    let (mut routers, mut network) = setup(charlies);
+    let addresses = network.addresses.clone();
+    let mut contacted: Vec<SocketAddr> = Vec::new();
+    let now = date(1_500_000_000_000);
+
+    routers.get_mut(&alice).unwrap().start(&bob, alice_address, now, &mut network).unwrap();
+
+    while let Some(envelope) = network.queue.pop_front() {
+        // Each test goes through the block encoding, as it would on the wire.
+        let test = match decode_blocks(&encode_blocks(&[Block::PeerTest(envelope.test)])).unwrap().pop() {
+            Some(Block::PeerTest(test)) => test,
+            other => panic!("Expected a peer test, got: {:?}", other)
+        };
+        let result = match envelope.to {
+            Destination::Router(ref to) => {
+                network.sender = to.clone();
+                routers.get_mut(to).unwrap().handle_in_session(&envelope.from, test, now, &mut network)
+            }
+            Destination::Address(to) => {
+                let mut from = addresses[&envelope.from];
+                if envelope.from == alice {
+                    contacted.push(to);
+                    if nat == Nat::Symmetric {
+                        from = alice_symmetric_address;
+                    }
+                }
+                let to_router = addresses.iter().find(|&(_, address)| *address == to).map(|(router, _)| router.clone());
+                let to_router = match to_router {
+                    Some(router) => router,
+                    None if to == alice_symmetric_address => alice.clone(),
+                    None => continue
+                };
+                let blocked = to_router == alice && match nat {
+                    Nat::None => false,
+                    Nat::Cone => !contacted.contains(&from),
+                    Nat::Symmetric => !contacted.contains(&from) || to != alice_symmetric_address
+                };
+                if blocked {
+                    continue;
+                }
+                network.sender = to_router.clone();
+                routers.get_mut(&to_router).unwrap().handle_out_of_session(from, test, now, &mut network)
+            }
+        };
+
+        if let Some(result) = result {
+            return result;
+        }
+    }
+
+    match routers.get_mut(&alice).unwrap().expire(date(1_600_000_000_000)).pop() {
+        Some(result) => result,
+        None => panic!("Alice's test did not finish")
+    }
+}
+
+
+#[test]
+fn test_peer_test_should_survive_an_encoding_round_trip() {
+    let mut test = PeerTest::new(2, BOB_REJECT_NO_CHARLIE, 7, 1_500_000_000, Some(address("[::1]:4567")));
+    test.router = Some(hash(9));
+    test.signature = vec![0x0A; 64];
+
+    let payload = encode_blocks(&[Block::PeerTest(test.clone())]);
+
+    assert_eq!(decode_blocks(&payload).unwrap(), vec![Block::PeerTest(test)]);
+}
+
+#[test]
+fn test_reachable_alice_should_be_ok() {
+    assert_eq!(run(Nat::None, vec![hash(3)]), Reachability::Ok);
+}
+
+#[test]
+fn test_alice_behind_a_cone_nat_should_be_firewalled() {
+    assert_eq!(run(Nat::Cone, vec![hash(3)]), Reachability::Firewalled);
+}
+
+#[test]
+fn test_alice_behind_a_symmetric_nat_should_be_detected() {
+    assert_eq!(run(Nat::Symmetric, vec![hash(3)]), Reachability::SymmetricNat);
+}
+
+#[test]
+fn test_test_without_a_charlie_should_be_inconclusive() {
+    assert_eq!(run(Nat::None, vec![hash(1)]), Reachability::Unknown);
+}
+
+#[test]
+fn test_charlie_should_reject_tests_alice_did_not_sign() {
+    let (alice, bob, charlie) = (hash(1), hash(2), hash(3));
+    let (mut routers, mut network) = setup(vec![charlie.clone()]);
+    let now = date(1_500_000_000_000);
+    let mut test = PeerTest::new(2, PEER_TEST_ACCEPT, 7, 1_500_000_000, Some(address("10.0.0.1:1000")));
+    test.router = Some(alice.clone());
+    test.signature = signer(0xFF).sign(&test.signed_bytes(&bob, None)).as_ref().to_vec();
+
+    network.sender = charlie.clone();
+    routers.get_mut(&charlie).unwrap().handle_in_session(&bob, test, now, &mut network);
+
+    let replies = sent_to(&mut network, &bob);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].code, CHARLIE_REJECT_SIGNATURE);
+    assert!(network.queue.is_empty());
+}
+
+#[test]
+fn test_alice_should_ignore_replies_charlie_did_not_sign() {
+    let (alice, bob, charlie) = (hash(1), hash(2), hash(3));
+    let (mut routers, mut network) = setup(vec![charlie.clone()]);
+    let now = date(1_500_000_000_000);
+    let alice_address = address("10.0.0.1:1000");
+    let nonce = routers.get_mut(&alice).unwrap().start(&bob, alice_address, now, &mut network).unwrap();
+    network.queue.clear();
+    let mut reply = PeerTest::new(4, PEER_TEST_ACCEPT, nonce, 1_500_000_000, Some(alice_address));
+    reply.router = Some(charlie.clone());
+
+    reply.signature = signer(0xFF).sign(&reply.signed_bytes(&bob, Some(&alice))).as_ref().to_vec();
+    assert_eq!(routers.get_mut(&alice).unwrap().handle_in_session(&bob, reply.clone(), now, &mut network), None);
+    assert!(network.queue.is_empty());
+
+    reply.signature = signer(3).sign(&reply.signed_bytes(&bob, Some(&alice))).as_ref().to_vec();
+    assert_eq!(routers.get_mut(&alice).unwrap().handle_in_session(&bob, reply, now, &mut network), None);
+    match network.queue.pop_front() {
+        Some(Envelope { to: Destination::Address(to), test, .. }) => {
+            assert_eq!(to, network.addresses[&charlie]);
+            assert_eq!(test.message, 6);
+        }
+        _ => panic!("Expected message 6 to Charlie")
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::slice;
+use common::{Ed25519Signer, Ed25519Verifier, Hash256, I2pDate, SigningPublicKey};
+use transport::ssu2::{Block, RelayManager, RelayNetwork, RelayOutcome, RelayRequest, RelayResponse};
+use transport::ssu2::{Introducer, introducer_options, parse_introducers, encode_blocks, decode_blocks};
+use transport::ssu2::{MAX_INTRODUCERS, RELAY_ACCEPT, BOB_REJECT_TAG_NOT_FOUND, CHARLIE_REJECT_SIGNATURE};
+use tests::util::date;
+
+
+enum Destination {
+    Router(Hash256),
+    Address(SocketAddr)
+}
+
+type Router = RelayManager<Ed25519Signer, Ed25519Verifier>;
+
+/// An in-memory network that queues blocks as they are sent, and knows the signing
+/// keys of the routers on it.
+struct SimulatedNetwork {
+    sender: Hash256,
+    queue: VecDeque<(Hash256, Destination, Block)>,
+    keys: HashMap<Hash256, SigningPublicKey>,
+    hole_punches: bool
+}
+
+impl SimulatedNetwork {
+    fn new(sender: Hash256) -> SimulatedNetwork {
+        SimulatedNetwork { sender, queue: VecDeque::new(), keys: HashMap::new(), hole_punches: true }
+    }
+}
+
+impl RelayNetwork for SimulatedNetwork {
+    fn send_in_session(&mut self, peer: &Hash256, block: Block) -> bool {
+        self.queue.push_back((self.sender.clone(), Destination::Router(peer.clone()), block));
+        true
+    }
+
+    fn send_hole_punch(&mut self, address: SocketAddr, response: RelayResponse) {
+        if self.hole_punches {
+            self.queue.push_back((self.sender.clone(), Destination::Address(address), Block::RelayResponse(response)));
+        }
+    }
+
+    fn signing_key(&self, router: &Hash256) -> Option<SigningPublicKey> {
+        self.keys.get(router).cloned()
+    }
+}
+
+fn hash(byte: u8) -> Hash256 {
+    Hash256::from([byte; 32])
+}
+
+fn signer(byte: u8) -> Ed25519Signer {
+    Ed25519Signer::new(&[byte; 32])
+}
+
+/// Creates the router with hash `byte`, signing with its own key.
+fn router(byte: u8, network: &mut SimulatedNetwork) -> Router {
+    let signer = signer(byte);
+    network.keys.insert(hash(byte), signer.public_key());
+
+    RelayManager::new(hash(byte), signer, Ed25519Verifier)
+}
+
+/// Delivers queued blocks until the queue is empty, returning Alice's outcome.
+fn deliver(routers: &mut HashMap<Hash256, Router>, network: &mut SimulatedNetwork,
+           addresses: &HashMap<Hash256, SocketAddr>, now: I2pDate) -> Option<RelayOutcome> {
+    let mut outcome = None;
+    while let Some((from, to, block)) = network.queue.pop_front() {
+        let block = decode_blocks(&encode_blocks(&[block])).unwrap().pop().unwrap();
+        let result = match to {
+            Destination::Router(to) => {
+                network.sender = to.clone();
+                routers.get_mut(&to).unwrap().handle_in_session(&from, block, now, network)
+            }
+            Destination::Address(address) => {
+                let to = addresses.iter().find(|&(_, known)| *known == address).unwrap().0.clone();
+                match block {
+                    Block::RelayResponse(response) => {
+                        routers.get_mut(&to).unwrap().handle_hole_punch(addresses[&from], response, network)
+                    }
+                    other => panic!("Expected a hole punch, got: {:?}", other)
+                }
+            }
+        };
+        outcome = outcome.or(result);
+    }
+
+    outcome
+}
+
+fn setup() -> (HashMap<Hash256, Router>, SimulatedNetwork, HashMap<Hash256, SocketAddr>) {
+    let mut routers = HashMap::new();
+    let mut addresses = HashMap::new();
+    let mut network = SimulatedNetwork::new(hash(3));
+    for &(byte, address) in &[(1, "10.0.0.1:1000"), (2, "10.0.0.2:2000"), (3, "10.0.0.3:3000")] {
+        let address: SocketAddr = address.parse().unwrap();
+        let mut manager = router(byte, &mut network);
+        manager.set_address(Some(address));
+        routers.insert(hash(byte), manager);
+        addresses.insert(hash(byte), address);
+    }
+
+    (routers, network, addresses)
+}
+
+/// Has Charlie publish Bob as his introducer, returning it.
+fn introduce(routers: &mut HashMap<Hash256, Router>, network: &mut SimulatedNetwork,
+             addresses: &HashMap<Hash256, SocketAddr>, now: I2pDate) -> Introducer {
+    network.sender = hash(3);
+    routers.get_mut(&hash(3)).unwrap().request_tags(&[hash(2)], network);
+    deliver(routers, network, addresses, now);
+
+    routers[&hash(3)].introducers()[0].clone()
+}
+
+
+#[test]
+fn test_relay_blocks_should_survive_an_encoding_round_trip() {
+    let request = RelayRequest {
+        nonce: 1,
+        tag: 2,
+        timestamp: 3,
+        address: Some("10.0.0.1:1000".parse().unwrap()),
+        signature: vec![0x04; 64]
+    };
+    let mut accepted = RelayResponse::new(RELAY_ACCEPT, 5, 6, Some("[::1]:7".parse().unwrap()), Some(8));
+    accepted.signature = vec![0x09; 64];
+    let rejected = RelayResponse::new(BOB_REJECT_TAG_NOT_FOUND, 10, 11, None, None);
+    let blocks = vec![
+        Block::RelayRequest(request),
+        Block::RelayResponse(accepted),
+        Block::RelayResponse(rejected),
+        Block::RelayTagRequest,
+        Block::RelayTag(12),
+    ];
+
+    assert_eq!(decode_blocks(&encode_blocks(&blocks)).unwrap(), blocks);
+}
+
+#[test]
+fn test_introducer_options_should_round_trip() {
+    let introducers = vec![
+        Introducer { router: hash(1), tag: 100, expiration: 1_500_000_000 },
+        Introducer { router: hash(2), tag: 200, expiration: 1_500_000_100 },
+    ];
+
+    let options = introducer_options(&introducers);
+
+    assert_eq!(options[1], ("itag0".to_string(), "100".to_string()));
+    assert_eq!(parse_introducers(&options), introducers);
+}
+
+#[test]
+fn test_firewalled_router_should_be_introduced_through_its_introducer() {
+    let (alice, bob, charlie) = (hash(1), hash(2), hash(3));
+    let (mut routers, mut network, addresses) = setup();
+    let now = date(1_500_000_000_000);
+
+    network.sender = charlie.clone();
+    assert_eq!(routers.get_mut(&charlie).unwrap().request_tags(slice::from_ref(&bob), &mut network), 1);
+    deliver(&mut routers, &mut network, &addresses, now);
+
+    let introducer = routers[&charlie].introducers()[0].clone();
+    assert_eq!(introducer.router, bob);
+    assert_eq!(introducer.expiration, 1_500_000_000 + 3600);
+    assert_eq!(routers[&bob].tag_holder(introducer.tag), Some(&charlie));
+    assert!(routers.get_mut(&charlie).unwrap().take_changed());
+
+    network.sender = alice.clone();
+    routers.get_mut(&alice).unwrap().request_relay(&charlie, &introducer, addresses[&alice], now, &mut network).unwrap();
+    let outcome = deliver(&mut routers, &mut network, &addresses, now);
+
+    match outcome {
+        Some(RelayOutcome::Accepted { charlie: address, .. }) => assert_eq!(address, addresses[&charlie]),
+        other => panic!("Expected an accepted introduction, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_alice_should_complete_on_the_response_bob_forwards() {
+    let (alice, charlie) = (hash(1), hash(3));
+    let (mut routers, mut network, addresses) = setup();
+    let now = date(1_500_000_000_000);
+    let introducer = introduce(&mut routers, &mut network, &addresses, now);
+
+    network.hole_punches = false;
+    network.sender = alice.clone();
+    routers.get_mut(&alice).unwrap().request_relay(&charlie, &introducer, addresses[&alice], now, &mut network).unwrap();
+
+    match deliver(&mut routers, &mut network, &addresses, now) {
+        Some(RelayOutcome::Accepted { charlie: address, .. }) => assert_eq!(address, addresses[&charlie]),
+        other => panic!("Expected an accepted introduction, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_charlie_should_reject_requests_alice_did_not_sign() {
+    let (alice, charlie) = (hash(1), hash(3));
+    let (mut routers, mut network, addresses) = setup();
+    let now = date(1_500_000_000_000);
+    let introducer = introduce(&mut routers, &mut network, &addresses, now);
+
+    network.keys.insert(alice.clone(), signer(0xFF).public_key());
+    network.sender = alice.clone();
+    routers.get_mut(&alice).unwrap().request_relay(&charlie, &introducer, addresses[&alice], now, &mut network).unwrap();
+
+    assert_eq!(deliver(&mut routers, &mut network, &addresses, now), Some(RelayOutcome::Rejected(CHARLIE_REJECT_SIGNATURE)));
+}
+
+#[test]
+fn test_alice_should_ignore_responses_charlie_did_not_sign() {
+    let (alice, charlie) = (hash(1), hash(3));
+    let (mut routers, mut network, addresses) = setup();
+    let now = date(1_500_000_000_000);
+    let introducer = introduce(&mut routers, &mut network, &addresses, now);
+
+    network.sender = alice.clone();
+    routers.get_mut(&alice).unwrap().request_relay(&charlie, &introducer, addresses[&alice], now, &mut network).unwrap();
+    network.keys.insert(charlie.clone(), signer(0xFF).public_key());
+
+    assert_eq!(deliver(&mut routers, &mut network, &addresses, now), None);
+}
+
+#[test]
+fn test_relay_request_with_an_unknown_tag_should_be_rejected() {
+    let (alice, bob) = (hash(1), hash(2));
+    let (mut routers, mut network, addresses) = setup();
+    let now = date(1_500_000_000_000);
+    let introducer = Introducer { router: bob, tag: 42, expiration: 1_500_003_600 };
+
+    network.sender = alice.clone();
+    routers.get_mut(&alice).unwrap().request_relay(&hash(3), &introducer, addresses[&alice], now, &mut network).unwrap();
+
+    assert_eq!(deliver(&mut routers, &mut network, &addresses, now), Some(RelayOutcome::Rejected(BOB_REJECT_TAG_NOT_FOUND)));
+}
+
+#[test]
+fn test_introducers_should_be_limited_and_dropped_when_lost() {
+    let mut routers = HashMap::new();
+    let mut network = SimulatedNetwork::new(hash(0));
+    let candidates: Vec<Hash256> = (1..6).map(hash).collect();
+    for byte in 0..6 {
+        let manager = router(byte, &mut network);
+        routers.insert(hash(byte), manager);
+    }
+    let now = date(1_500_000_000_000);
+
+    assert_eq!(routers.get_mut(&hash(0)).unwrap().request_tags(&candidates, &mut network), MAX_INTRODUCERS);
+    deliver(&mut routers, &mut network, &HashMap::new(), now);
+    let charlie = routers.get_mut(&hash(0)).unwrap();
+    assert_eq!(charlie.introducers().len(), MAX_INTRODUCERS);
+    assert!(charlie.take_changed());
+
+    charlie.session_closed(&hash(1));
+    assert_eq!(charlie.introducers().len(), MAX_INTRODUCERS - 1);
+    assert!(charlie.take_changed());
+    assert!(!charlie.take_changed());
+
+    charlie.expire(date(1_500_003_600_000));
+    assert!(charlie.introducers().is_empty());
+    assert!(charlie.take_changed());
+}
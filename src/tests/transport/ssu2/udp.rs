@@ -0,0 +1,261 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+use rand;
+use rand::Rng;
+use common::{Hash256, Hashable256, I2pDate, I2pInt64, Mapping, RouterIdentity, RouterInfo};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::TerminationReason;
+use transport::ssu2::{Event, Ssu2Error, StaticKeys, UdpEndpoint};
+use tests::su3::FakeSigner;
+use tests::su3::{FakeVerifier, fake_key};
+use tests::util::date;
+
+
+const NOW: u64 = 1_539_302_400_000;
+
+/// Binds an endpoint on loopback whose RouterInfo publishes its address and keys.
+fn endpoint() -> (UdpEndpoint<FakeVerifier>, RouterInfo) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let keys = StaticKeys::generate();
+    let mut public_key = [0x00; 256];
+    rand::thread_rng().fill_bytes(&mut public_key);
+    let identity = RouterIdentity::new(&public_key, &fake_key());
+    let address = keys.router_address(10, socket.local_addr().unwrap());
+    let router_info = RouterInfo::new(identity, date(NOW), vec![address], Mapping::new(), &FakeSigner);
+
+    let endpoint = UdpEndpoint::new(socket, keys, router_info.clone(), FakeVerifier);
+    endpoint.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    (endpoint, router_info)
+}
+
+fn hash(router_info: &RouterInfo) -> Hash256 {
+    router_info.identity().hash_sha256()
+}
+
+/// Runs the TokenRequest, Retry, SessionRequest, SessionCreated and
+/// SessionConfirmed exchange, with Bob's clock at `bob_now`.
+fn handshake(alice: &mut UdpEndpoint<FakeVerifier>,
+             bob: &mut UdpEndpoint<FakeVerifier>,
+             bob_info: &RouterInfo,
+             bob_now: u64) -> Result<(Option<Event>, Option<Event>), Ssu2Error> {
+
+    alice.connect(bob_info, date(NOW))?;
+    assert!(bob.receive(date(bob_now))?.is_none());
+    assert!(alice.receive(date(NOW))?.is_none());
+    assert!(bob.receive(date(bob_now))?.is_none());
+    let alice_event = alice.receive(date(NOW))?;
+    let bob_event = bob.receive(date(bob_now))?;
+
+    Ok((alice_event, bob_event))
+}
+
+fn message(length: usize) -> I2npMessage {
+    let expiration = I2pDate::new(I2pInt64::new(NOW + 60_000)).unwrap();
+    let payload = (0..length).map(|i| i as u8).collect();
+
+    I2npMessage::new(MessageType::Data, 42, expiration, payload)
+}
+
+#[test]
+fn test_loopback_endpoints_should_establish_a_session_and_exchange_fragments() {
+    let (mut alice, alice_info) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+
+    match handshake(&mut alice, &mut bob, &bob_info, NOW).unwrap() {
+        (Some(Event::Established(ref peer)), Some(Event::Established(ref other)))
+            if *peer == bob_info && *other == alice_info => {}
+        other => panic!("unexpected events: {:?}", other)
+    }
+    assert!(alice.is_connected(&hash(&bob_info)));
+    assert!(bob.is_connected(&hash(&alice_info)));
+
+    let sent = message(3000);
+    let packets = alice.send_messages(&hash(&bob_info), std::slice::from_ref(&sent), date(NOW)).unwrap();
+    assert_eq!(packets, 3);
+    assert_eq!(alice.unacked(&hash(&bob_info)), 3);
+
+    let mut received = Vec::new();
+    for _ in 0..packets {
+        if let Some(event) = bob.receive(date(NOW)).unwrap() {
+            received.push(event);
+        }
+    }
+    match received.as_slice() {
+        [Event::Message { peer, message }] if *peer == hash(&alice_info) && *message == sent => {}
+        other => panic!("unexpected events: {:?}", other)
+    }
+
+    for _ in 0..packets {
+        assert!(alice.receive(date(NOW)).unwrap().is_none());
+    }
+    assert_eq!(alice.unacked(&hash(&bob_info)), 0);
+}
+
+#[test]
+fn test_the_responder_should_refuse_a_skewed_clock() {
+    let (mut alice, _) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+
+    match handshake(&mut alice, &mut bob, &bob_info, NOW + 120_000) {
+        Err(Ssu2Error::Handshake(TerminationReason::ClockSkew)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_unacknowledged_packets_should_be_retransmitted() {
+    let (mut alice, _) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+    handshake(&mut alice, &mut bob, &bob_info, NOW).unwrap();
+
+    alice.send_messages(&hash(&bob_info), &[message(10)], date(NOW)).unwrap();
+
+    assert_eq!(alice.retransmit(date(NOW + 100)).unwrap(), 0);
+    assert_eq!(alice.retransmit(date(NOW + 2000)).unwrap(), 1);
+    assert_eq!(alice.unacked(&hash(&bob_info)), 1);
+}
+
+#[test]
+fn test_sessions_should_be_terminated_after_too_many_retransmissions() {
+    let (mut alice, _) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+    handshake(&mut alice, &mut bob, &bob_info, NOW).unwrap();
+    alice.set_max_retransmissions(2);
+
+    alice.send_messages(&hash(&bob_info), &[message(10)], date(NOW)).unwrap();
+
+    assert_eq!(alice.retransmit(date(NOW + 1000)).unwrap(), 1);
+    assert_eq!(alice.retransmit(date(NOW + 2000)).unwrap(), 1);
+    assert_eq!(alice.retransmit(date(NOW + 3000)).unwrap(), 0);
+    match alice.receive(date(NOW + 3000)).unwrap() {
+        Some(Event::Terminated { peer, reason: TerminationReason::IdleTimeout }) if peer == hash(&bob_info) => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+    assert!(!alice.is_connected(&hash(&bob_info)));
+}
+
+#[test]
+fn test_sessions_should_be_terminated_before_packet_numbers_wrap() {
+    let (mut alice, alice_info) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+    handshake(&mut alice, &mut bob, &bob_info, NOW).unwrap();
+    alice.set_next_packet_number(&hash(&bob_info), u32::MAX - 1);
+
+    let sent = message(10);
+    alice.send_messages(&hash(&bob_info), std::slice::from_ref(&sent), date(NOW)).unwrap();
+    match alice.send_messages(&hash(&bob_info), &[message(10)], date(NOW)) {
+        Err(Ssu2Error::NoSession) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+
+    match alice.receive(date(NOW)).unwrap() {
+        Some(Event::Terminated { peer, reason: TerminationReason::NormalClose }) if peer == hash(&bob_info) => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+    match bob.receive(date(NOW)).unwrap() {
+        Some(Event::Message { ref message, .. }) if *message == sent => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+    match bob.receive(date(NOW)).unwrap() {
+        Some(Event::Terminated { peer, .. }) if peer == hash(&alice_info) => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+}
+
+#[test]
+fn test_closing_should_terminate_the_session_on_both_ends() {
+    let (mut alice, alice_info) = endpoint();
+    let (mut bob, bob_info) = endpoint();
+    handshake(&mut alice, &mut bob, &bob_info, NOW).unwrap();
+
+    alice.close(&hash(&bob_info), TerminationReason::NormalClose, date(NOW)).unwrap();
+
+    match bob.receive(date(NOW)).unwrap() {
+        Some(Event::Terminated { peer, reason: TerminationReason::NormalClose }) if peer == hash(&alice_info) => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+    assert!(!alice.is_connected(&hash(&bob_info)));
+    assert!(!bob.is_connected(&hash(&alice_info)));
+    match alice.send_messages(&hash(&bob_info), &[message(10)], date(NOW)) {
+        Err(Ssu2Error::NoSession) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_a_corrupt_session_created_should_not_abort_the_handshake() {
+    let (mut alice, _) = endpoint();
+    let bob_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let proxy = UdpSocket::bind("127.0.0.1:0").unwrap();
+    proxy.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    // Alice reaches Bob through a proxy that can tamper with his packets.
+    let keys = StaticKeys::generate();
+    let identity = RouterIdentity::new(&[0x02; 256], &fake_key());
+    let bob_info = RouterInfo::new(identity.clone(), date(NOW), vec![keys.router_address(10, bob_socket.local_addr().unwrap())],
+                                   Mapping::new(), &FakeSigner);
+    let published = RouterInfo::new(identity, date(NOW), vec![keys.router_address(10, proxy.local_addr().unwrap())],
+                                    Mapping::new(), &FakeSigner);
+    let mut bob = UdpEndpoint::new(bob_socket, keys, bob_info.clone(), FakeVerifier);
+    bob.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let (alice_address, bob_address) = (alice.local_addr().unwrap(), bob.local_addr().unwrap());
+    let forward = |to| {
+        let mut buf = [0x00; 2048];
+        let (length, _) = proxy.recv_from(&mut buf).unwrap();
+        proxy.send_to(&buf[..length], to).unwrap();
+    };
+
+    alice.connect(&published, date(NOW)).unwrap();
+    forward(bob_address);
+    assert!(bob.receive(date(NOW)).unwrap().is_none());
+    forward(alice_address);
+    assert!(alice.receive(date(NOW)).unwrap().is_none());
+    forward(bob_address);
+    assert!(bob.receive(date(NOW)).unwrap().is_none());
+
+    let mut buf = [0x00; 2048];
+    let (length, _) = proxy.recv_from(&mut buf).unwrap();
+    let mut corrupt = buf[..length].to_vec();
+    *corrupt.last_mut().unwrap() ^= 0xFF;
+    proxy.send_to(&corrupt, alice_address).unwrap();
+    assert!(alice.receive(date(NOW)).is_err());
+
+    proxy.send_to(&buf[..length], alice_address).unwrap();
+    match alice.receive(date(NOW)).unwrap() {
+        Some(Event::Established(ref peer)) if *peer == published => {}
+        other => panic!("unexpected event: {:?}", other)
+    }
+}
+
+#[test]
+fn test_outstanding_tokens_should_be_bounded_and_expire() {
+    let (mut bob, bob_info) = endpoint();
+    bob.set_max_issued_tokens(2);
+    let request_token = |bob: &mut UdpEndpoint<FakeVerifier>, now: u64| {
+        let (mut alice, _) = endpoint();
+        alice.connect(&bob_info, date(now)).unwrap();
+        assert!(bob.receive(date(now)).unwrap().is_none());
+    };
+
+    request_token(&mut bob, NOW);
+    request_token(&mut bob, NOW);
+    request_token(&mut bob, NOW);
+    assert_eq!(bob.issued_tokens(), 2);
+
+    // Once they expire, old tokens are dropped rather than the oldest live one.
+    request_token(&mut bob, NOW + 61_000);
+    assert_eq!(bob.issued_tokens(), 1);
+}
+
+#[test]
+fn test_connect_should_require_an_ssu2_address() {
+    let (mut alice, _) = endpoint();
+    let identity = RouterIdentity::new(&[0x01; 256], &fake_key());
+    let unreachable = RouterInfo::new(identity, date(NOW), Vec::new(), Mapping::new(), &FakeSigner);
+
+    match alice.connect(&unreachable, date(NOW)) {
+        Err(Ssu2Error::InvalidAddress) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
@@ -0,0 +1,28 @@
+use tunnel::DecayingBloomFilter;
+use tests::util::date;
+
+
+
+#[test]
+fn test_filter_should_detect_entries_added_before() {
+    let mut filter = DecayingBloomFilter::new(1 << 16, 1000, date(1));
+
+    for i in 0..100u32 {
+        assert!(!filter.add(&i.to_be_bytes(), date(1)));
+    }
+    for i in 0..100u32 {
+        assert!(filter.add(&i.to_be_bytes(), date(1)));
+    }
+}
+
+#[test]
+fn test_filter_should_forget_entries_after_two_periods() {
+    let mut filter = DecayingBloomFilter::new(1 << 16, 1000, date(1));
+    filter.add(b"first", date(1));
+
+    assert!(filter.add(b"first", date(1500)));
+    filter.add(b"second", date(1500));
+    // The rotation at 1500 keeps "first" and "second" in the previous array.
+    assert!(filter.add(b"second", date(2600)));
+    assert!(!filter.add(b"first", date(4700)));
+}
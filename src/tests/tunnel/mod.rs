@@ -0,0 +1,3 @@
+mod bloom;
+mod transit;
+mod pool;
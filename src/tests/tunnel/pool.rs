@@ -0,0 +1,181 @@
+use common::{Hash256, I2pDate, I2pInt32, Mapping};
+use i2np::{I2npMessage, MessageType};
+use tunnel::{ClientTunnels, PoolSettings, PooledTunnel, TestSender, TunnelBuilder, TunnelDirection, TunnelError, TunnelId, TunnelPool};
+use tunnel::{tunnel_expiration, TUNNEL_LIFETIME_MILLISECONDS};
+use tests::util::date;
+
+
+/// Records the builds started, refusing any beyond `capacity`.
+struct RecordingBuilder {
+    builds: Vec<(TunnelDirection, u8)>,
+    capacity: usize
+}
+
+impl RecordingBuilder {
+    fn new(capacity: usize) -> RecordingBuilder {
+        RecordingBuilder { builds: Vec::new(), capacity }
+    }
+}
+
+impl TunnelBuilder for RecordingBuilder {
+    fn build(&mut self, direction: TunnelDirection, length: u8) -> bool {
+        if self.builds.len() >= self.capacity {
+            return false;
+        }
+        self.builds.push((direction, length));
+        true
+    }
+}
+
+/// Records the test messages sent and the tunnels they were sent through.
+#[derive(Default)]
+struct RecordingSender {
+    sent: Vec<(TunnelId, TunnelId, I2npMessage)>
+}
+
+impl TestSender for RecordingSender {
+    fn send(&mut self, outbound: &PooledTunnel, _: &Hash256, gateway_id: TunnelId, message: I2npMessage) -> bool {
+        self.sent.push((outbound.gateway_id, gateway_id, message));
+        true
+    }
+}
+
+fn options(pairs: &[(&str, &str)]) -> Mapping {
+    let mut options = Mapping::new();
+    for &(key, value) in pairs {
+        options.insert(key, value).unwrap();
+    }
+    options
+}
+
+fn tunnel(id: u32, expiration: I2pDate) -> PooledTunnel {
+    PooledTunnel::new(Hash256::from([id as u8; 32]), I2pInt32::new(id as u64), vec![Hash256::from([id as u8; 32])], expiration)
+}
+
+
+#[test]
+fn test_settings_should_be_read_from_i2cp_options() {
+    let options = options(&[("inbound.length", "2"), ("inbound.quantity", "4"),
+                            ("inbound.lengthVariance", "-1"), ("outbound.backupQuantity", "1")]);
+
+    let inbound = PoolSettings::from_options(TunnelDirection::Inbound, &options).unwrap();
+    assert_eq!(inbound, PoolSettings { length: 2, quantity: 4, length_variance: -1, backup_quantity: 0 });
+
+    let outbound = PoolSettings::from_options(TunnelDirection::Outbound, &options).unwrap();
+    assert_eq!(outbound, PoolSettings { backup_quantity: 1, ..PoolSettings::default() });
+}
+
+#[test]
+fn test_settings_should_reject_invalid_options() {
+    let invalid = [("inbound.length", "8"), ("inbound.quantity", "0"), ("inbound.quantity", "two"),
+                   ("inbound.lengthVariance", "9"), ("inbound.backupQuantity", "17")];
+
+    for &(key, value) in invalid.iter() {
+        match PoolSettings::from_options(TunnelDirection::Inbound, &options(&[(key, value)])) {
+            Err(TunnelError::InvalidOption(option)) => assert_eq!(option, key),
+            result => panic!("{}={} gave {:?}", key, value, result.map(|_| ()))
+        }
+    }
+}
+
+#[test]
+fn test_length_variance_should_stay_in_range() {
+    let positive = PoolSettings { length: 2, length_variance: 2, ..PoolSettings::default() };
+    let negative = PoolSettings { length: 1, length_variance: -2, ..PoolSettings::default() };
+
+    for _ in 0..100 {
+        let length = positive.pick_length();
+        assert!((2..=4).contains(&length));
+        assert!(negative.pick_length() <= 3);
+    }
+}
+
+#[test]
+fn test_maintain_should_build_quantity_and_backups() {
+    let settings = PoolSettings { quantity: 2, backup_quantity: 1, ..PoolSettings::default() };
+    let mut pool = TunnelPool::new(TunnelDirection::Outbound, settings);
+    let mut builder = RecordingBuilder::new(10);
+
+    assert_eq!(pool.maintain(date(1), &mut builder), 3);
+    assert_eq!(builder.builds, vec![(TunnelDirection::Outbound, 3); 3]);
+    assert_eq!(pool.maintain(date(1), &mut builder), 0);
+
+    pool.build_failed();
+    assert_eq!(pool.maintain(date(1), &mut builder), 1);
+}
+
+#[test]
+fn test_maintain_should_replace_tunnels_before_they_expire() {
+    let mut pool = TunnelPool::new(TunnelDirection::Inbound, PoolSettings::default());
+    let mut builder = RecordingBuilder::new(10);
+    pool.maintain(date(1), &mut builder);
+    pool.tunnel_built(tunnel(1, tunnel_expiration(date(1))));
+    pool.tunnel_built(tunnel(2, tunnel_expiration(date(1))));
+    assert_eq!(pool.pending(), 0);
+
+    assert_eq!(pool.maintain(date(60_000), &mut builder), 0);
+    let late = date(TUNNEL_LIFETIME_MILLISECONDS - 60_000);
+    assert_eq!(pool.maintain(late, &mut builder), 2);
+    assert_eq!(pool.tunnels().len(), 2);
+
+    assert_eq!(pool.maintain(date(TUNNEL_LIFETIME_MILLISECONDS + 2), &mut builder), 0);
+    assert!(pool.tunnels().is_empty());
+}
+
+#[test]
+fn test_tests_should_retire_failing_tunnels_and_keep_passing_ones() {
+    let mut client = ClientTunnels::new(PoolSettings::default(), PoolSettings::default());
+    client.inbound_mut().tunnel_built(tunnel(1, date(1_000_000)));
+    client.outbound_mut().tunnel_built(tunnel(2, date(1_000_000)));
+    let mut sender = RecordingSender::default();
+
+    assert_eq!(client.test(date(1), &mut sender), 1);
+    let (outbound, inbound, message) = sender.sent.pop().unwrap();
+    assert_eq!((outbound, inbound), (I2pInt32::new(2), I2pInt32::new(1)));
+    assert_eq!(message.message_type, MessageType::DeliveryStatus);
+    assert_eq!(message.payload.len(), 12);
+    assert!(client.handle_delivery_status(&message));
+    assert!(!client.handle_delivery_status(&message));
+
+    client.test(date(1), &mut sender);
+    assert_eq!(client.expire_tests(date(20_000)), 0);
+    assert_eq!(client.inbound().tunnels()[0].failures(), 1);
+
+    client.test(date(20_000), &mut sender);
+    assert_eq!(client.expire_tests(date(40_000)), 2);
+    assert!(client.inbound().tunnels().is_empty());
+    assert!(client.outbound().tunnels().is_empty());
+}
+
+#[test]
+fn test_tests_should_tell_apart_tunnels_with_the_same_id_at_different_gateways() {
+    let mut pool = TunnelPool::new(TunnelDirection::Inbound, PoolSettings::default());
+    pool.tunnel_built(tunnel(1, date(1_000_000)));
+    pool.tunnel_built(PooledTunnel::new(Hash256::from([9; 32]), I2pInt32::new(1), vec![], date(1_000_000)));
+
+    assert!(!pool.record_test(&Hash256::from([9; 32]), I2pInt32::new(1), false));
+    assert!(pool.record_test(&Hash256::from([9; 32]), I2pInt32::new(1), false));
+    assert!(!pool.record_test(&Hash256::from([7; 32]), I2pInt32::new(1), false));
+
+    assert_eq!(pool.tunnels().len(), 1);
+    assert_eq!(pool.tunnels()[0].gateway, Hash256::from([1; 32]));
+    assert_eq!(pool.tunnels()[0].failures(), 0);
+}
+
+#[test]
+fn test_leases_should_come_from_inbound_tunnels() {
+    let client = {
+        let mut client = ClientTunnels::from_options(&options(&[("inbound.quantity", "3")])).unwrap();
+        client.inbound_mut().tunnel_built(tunnel(1, date(5_000)));
+        client.inbound_mut().tunnel_built(tunnel(2, date(9_000)));
+        client.inbound_mut().tunnel_built(tunnel(3, date(500)));
+        client.outbound_mut().tunnel_built(tunnel(4, date(9_000)));
+        client
+    };
+
+    let leases = client.leases(date(1_000));
+    assert_eq!(leases.len(), 2);
+    assert_eq!(leases[0].tunnel_id, I2pInt32::new(2));
+    assert_eq!(leases[0].end_date, date(9_000));
+    assert_eq!(leases[1].gateway, Hash256::from([1; 32]));
+}
@@ -0,0 +1,135 @@
+use common::{Hash256, I2pInt32, SessionKey};
+use i2np::{I2npMessage, MessageType};
+use tunnel::{AesCipher, BlockCipher, BuildReply, HopConfig, HopRole, TransitAction, TransitConfig, TransitManager, TunnelError};
+use tunnel::{TUNNEL_DATA_LENGTH, TUNNEL_LIFETIME_MILLISECONDS};
+use tests::util::date;
+
+
+/// A stand-in for AES that XORs the block with the first 16 bytes of the key.
+struct XorCipher;
+
+impl BlockCipher for XorCipher {
+    fn encrypt_block(&self, key: &SessionKey, block: &mut [u8; 16]) {
+        for (byte, key) in block.iter_mut().zip(key.as_ref().iter()) {
+            *byte ^= *key;
+        }
+    }
+}
+
+fn hop(receive_id: u32, role: HopRole) -> HopConfig {
+    HopConfig {
+        receive_id: I2pInt32::new(receive_id as u64),
+        next_id: I2pInt32::new(900),
+        next_router: Hash256::from([0x09; 32]),
+        layer_key: SessionKey::from([0x11; 32]),
+        iv_key: SessionKey::from([0x22; 32]),
+        role
+    }
+}
+
+fn tunnel_data(tunnel_id: u32, iv: u8) -> I2npMessage {
+    let mut payload = I2pInt32::new(tunnel_id as u64).to_bytes_be();
+    payload.extend_from_slice(&[iv; 16]);
+    payload.extend_from_slice(&[0x00; 1008]);
+
+    I2npMessage::new(MessageType::TunnelData, 1, date(2_000_000_000_000), payload)
+}
+
+fn manager(config: TransitConfig) -> TransitManager<XorCipher> {
+    TransitManager::new(config, XorCipher, date(1))
+}
+
+
+#[test]
+fn test_build_requests_should_respect_the_tunnel_and_bandwidth_limits() {
+    let mut transit = manager(TransitConfig { max_tunnels: 2, share_rate: 10_000, tunnel_bandwidth: 100 });
+
+    assert_eq!(transit.handle_build_request(hop(1, HopRole::Participant), date(1)), BuildReply::Accept);
+    assert_eq!(transit.handle_build_request(hop(1, HopRole::Participant), date(1)), BuildReply::Critical);
+    assert_eq!(transit.handle_build_request(hop(2, HopRole::Participant), date(1)), BuildReply::Accept);
+    assert_eq!(transit.handle_build_request(hop(3, HopRole::Participant), date(1)).code(), 30);
+
+    let mut transit = manager(TransitConfig { max_tunnels: 10, share_rate: 250, tunnel_bandwidth: 100 });
+    transit.handle_build_request(hop(1, HopRole::Participant), date(1));
+    transit.handle_build_request(hop(2, HopRole::Participant), date(1));
+    assert_eq!(transit.handle_build_request(hop(3, HopRole::Participant), date(1)), BuildReply::Bandwidth);
+    assert_eq!(transit.len(), 2);
+}
+
+#[test]
+fn test_participant_should_apply_its_layer_and_forward() {
+    let mut transit = manager(TransitConfig::default());
+    transit.handle_build_request(hop(7, HopRole::Participant), date(1));
+
+    let (router, message) = match transit.handle_tunnel_data(&tunnel_data(7, 0xAA), date(1)).unwrap() {
+        TransitAction::Forward { router, message } => (router, message),
+        other => panic!("Expected a forwarded message, got: {:?}", other)
+    };
+
+    assert_eq!(router, Hash256::from([0x09; 32]));
+    assert_eq!(message.message_type, MessageType::TunnelData);
+    assert_eq!(message.payload.len(), TUNNEL_DATA_LENGTH);
+    assert_eq!(message.payload[..4], [0x00, 0x00, 0x03, 0x84]);
+    // With the XOR cipher the IV is encrypted twice and comes back unchanged, and
+    // the first data block is the once encrypted IV encrypted with the layer key.
+    assert_eq!(message.payload[4..20], [0xAA; 16]);
+    assert_eq!(message.payload[20..36], [0xAA ^ 0x22 ^ 0x11; 16]);
+}
+
+#[test]
+fn test_outbound_endpoint_should_return_the_data() {
+    let mut transit = manager(TransitConfig::default());
+    transit.handle_build_request(hop(7, HopRole::OutboundEndpoint), date(1));
+
+    match transit.handle_tunnel_data(&tunnel_data(7, 0xAA), date(1)).unwrap() {
+        TransitAction::Endpoint { tunnel_id, data } => {
+            assert_eq!(tunnel_id, I2pInt32::new(7));
+            assert_eq!(data.len(), TUNNEL_DATA_LENGTH - 4);
+        }
+        other => panic!("Expected endpoint data, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_replays_unknown_tunnels_and_expired_tunnels_should_be_rejected() {
+    let mut transit = manager(TransitConfig::default());
+    transit.handle_build_request(hop(7, HopRole::Participant), date(1));
+
+    assert!(transit.handle_tunnel_data(&tunnel_data(7, 0xAA), date(1)).is_ok());
+    assert_eq!(transit.handle_tunnel_data(&tunnel_data(7, 0xAA), date(1)), Err(TunnelError::DuplicateIv));
+    assert_eq!(transit.handle_tunnel_data(&tunnel_data(8, 0xBB), date(1)), Err(TunnelError::UnknownTunnel(I2pInt32::new(8))));
+
+    let mut short = tunnel_data(7, 0xCC);
+    short.payload.pop();
+    assert_eq!(transit.handle_tunnel_data(&short, date(1)), Err(TunnelError::InvalidMessage));
+
+    assert_eq!(transit.expire(date(TUNNEL_LIFETIME_MILLISECONDS)), 0);
+    assert_eq!(transit.expire(date(TUNNEL_LIFETIME_MILLISECONDS + 1)), 1);
+    assert!(transit.is_empty());
+}
+
+#[test]
+fn test_aes_cipher_should_apply_a_tunnel_layer() {
+    // The IV key and IV are the AES-256 example of FIPS 197, so the first encryption
+    // of the IV gives its ciphertext. The other values were computed with OpenSSL.
+    let iv_key = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f];
+    let mut block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    AesCipher.encrypt_block(&SessionKey::from(iv_key), &mut block);
+    assert_eq!(block, [0x8e, 0xa2, 0xb7, 0xca, 0x51, 0x67, 0x45, 0xbf, 0xea, 0xfc, 0x49, 0x90, 0x4b, 0x49, 0x60, 0x89]);
+
+    let mut transit = TransitManager::new(TransitConfig::default(), AesCipher, date(1));
+    let mut config = hop(7, HopRole::Participant);
+    config.iv_key = SessionKey::from(iv_key);
+    transit.handle_build_request(config, date(1));
+    let mut message = tunnel_data(7, 0x00);
+    message.payload[4..20].copy_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+
+    let message = match transit.handle_tunnel_data(&message, date(1)).unwrap() {
+        TransitAction::Forward { message, .. } => message,
+        other => panic!("Expected a forwarded message, got: {:?}", other)
+    };
+
+    assert_eq!(message.payload[4..20], [0x66, 0x4a, 0x34, 0x55, 0xd8, 0xe9, 0xdb, 0xdb, 0x03, 0x15, 0x8b, 0x52, 0xb9, 0x3c, 0x28, 0x8a]);
+    assert_eq!(message.payload[20..36], [0xf4, 0x66, 0x72, 0x47, 0x4b, 0x77, 0xee, 0x7f, 0xa6, 0xe2, 0x43, 0xf9, 0x07, 0x67, 0x61, 0x18]);
+    assert_eq!(message.payload[36..52], [0xf7, 0xad, 0x66, 0x06, 0x87, 0xbc, 0xbf, 0x79, 0xe5, 0x08, 0x2d, 0x3b, 0x39, 0x80, 0x7a, 0xdf]);
+}
@@ -0,0 +1,16 @@
+use std::env;
+use std::path::PathBuf;
+use rand;
+use common::{I2pDate, I2pInt64};
+
+
+/// Returns the date `milliseconds` after the epoch.
+pub fn date(milliseconds: u64) -> I2pDate {
+    I2pDate::new(I2pInt64::new(milliseconds)).unwrap()
+}
+
+/// Returns a path in the system's temporary directory that no other test uses,
+/// such as `/tmp/rusti2p-netdb-1234`. Nothing is created.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rusti2p-{}-{}", name, rand::random::<u64>()))
+}
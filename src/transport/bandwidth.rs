@@ -0,0 +1,208 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+
+/// The share of each bucket's burst that only high priority traffic may use.
+const HIGH_PRIORITY_RESERVE_PERCENTAGE: u64 = 10;
+
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+
+/// The direction of traffic.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound
+}
+
+/// The priority class of traffic.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Tunnel builds and netDb traffic, which keep the router working.
+    High,
+    /// Traffic of our own clients.
+    Normal,
+    /// Traffic of tunnels we participate in for other routers.
+    Participating
+}
+
+/// A `TokenBucket` allows `rate` bytes per second on average, and bursts of up to
+/// `burst` bytes.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    rate: u64,
+    burst: u64,
+    tokens: u64,
+    updated: Instant
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    pub fn new(rate: u64, burst: u64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst,
+            updated: now
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+
+    /// Returns the tokens available at `now`.
+    pub fn available(&mut self, now: Instant) -> u64 {
+        self.refill(now);
+        self.tokens
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.updated || self.rate == 0 {
+            return;
+        }
+
+        let elapsed = now.duration_since(self.updated).as_nanos();
+        let added = elapsed * self.rate as u128 / NANOSECONDS_PER_SECOND;
+        let tokens = cmp::min(self.tokens as u128 + added, self.burst as u128) as u64;
+        if tokens == self.burst {
+            self.updated = now;
+        } else {
+            // Only advance by the time the whole tokens took, keeping the remainder.
+            let used = added * NANOSECONDS_PER_SECOND / self.rate as u128;
+            self.updated += Duration::from_nanos(used as u64);
+        }
+        self.tokens = tokens;
+    }
+
+    /// Takes `bytes` tokens if at least `reserve` tokens would remain.
+    fn take(&mut self, bytes: u64, reserve: u64, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < bytes.saturating_add(reserve) {
+            return false;
+        }
+        self.tokens -= bytes;
+
+        true
+    }
+
+    /// Takes `bytes` tokens if they are available.
+    pub fn try_consume(&mut self, bytes: u64, now: Instant) -> bool {
+        self.take(bytes, 0, now)
+    }
+}
+
+/// The settings of a `BandwidthLimiter`. Rates are in bytes per second, bursts in
+/// bytes, and the share is the percentage of the lower rate that tunnels we
+/// participate in may use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BandwidthConfig {
+    pub inbound_rate: u64,
+    pub inbound_burst: u64,
+    pub outbound_rate: u64,
+    pub outbound_burst: u64,
+    pub share_percentage: u8
+}
+
+impl Default for BandwidthConfig {
+    /// The defaults of the Java router: 96 KBps in, 40 KBps out, 80% shared, and
+    /// bursts of ten seconds at the full rate.
+    fn default() -> BandwidthConfig {
+        BandwidthConfig {
+            inbound_rate: 96 * 1024,
+            inbound_burst: 960 * 1024,
+            outbound_rate: 40 * 1024,
+            outbound_burst: 400 * 1024,
+            share_percentage: 80
+        }
+    }
+}
+
+impl BandwidthConfig {
+    /// Returns the bandwidth we share with other routers, in bytes per second.
+    pub fn share_rate(&self) -> u64 {
+        cmp::min(self.inbound_rate, self.outbound_rate) * cmp::min(self.share_percentage, 100) as u64 / 100
+    }
+
+    /// Returns the bandwidth class letter for the `caps` of our RouterInfo, which
+    /// advertises the bandwidth we share.
+    pub fn caps_letter(&self) -> char {
+        match self.share_rate() / 1024 {
+            0..=11 => 'K',
+            12..=47 => 'L',
+            48..=63 => 'M',
+            64..=127 => 'N',
+            128..=255 => 'O',
+            256..=1999 => 'P',
+            _ => 'X'
+        }
+    }
+}
+
+/// A `BandwidthLimiter` limits the traffic of all transport sessions together, with
+/// a token bucket for each direction. Part of every bucket is reserved for high
+/// priority traffic, and participating traffic must also fit in a second pair of
+/// buckets limited to the share rate, so that it cannot starve our own traffic.
+#[derive(Clone, Debug)]
+pub struct BandwidthLimiter {
+    config: BandwidthConfig,
+    inbound: TokenBucket,
+    outbound: TokenBucket,
+    participating_inbound: TokenBucket,
+    participating_outbound: TokenBucket
+}
+
+fn share_bucket(config: &BandwidthConfig, burst: u64, now: Instant) -> TokenBucket {
+    let share = cmp::min(config.share_percentage, 100) as u64;
+
+    TokenBucket::new(config.share_rate(), burst * share / 100, now)
+}
+
+impl BandwidthLimiter {
+    pub fn new(config: BandwidthConfig, now: Instant) -> BandwidthLimiter {
+        BandwidthLimiter {
+            config,
+            inbound: TokenBucket::new(config.inbound_rate, config.inbound_burst, now),
+            outbound: TokenBucket::new(config.outbound_rate, config.outbound_burst, now),
+            participating_inbound: share_bucket(&config, config.inbound_burst, now),
+            participating_outbound: share_bucket(&config, config.outbound_burst, now)
+        }
+    }
+
+    pub fn config(&self) -> &BandwidthConfig {
+        &self.config
+    }
+
+    /// Returns the bandwidth class letter for our RouterInfo.
+    pub fn caps_letter(&self) -> char {
+        self.config.caps_letter()
+    }
+
+    /// Asks to send or receive `bytes`. Returns `true` if the traffic is allowed now,
+    /// in which case the bytes are accounted for.
+    pub fn request(&mut self, direction: Direction, priority: Priority, bytes: u64, now: Instant) -> bool {
+        let (bucket, participating) = match direction {
+            Direction::Inbound => (&mut self.inbound, &mut self.participating_inbound),
+            Direction::Outbound => (&mut self.outbound, &mut self.participating_outbound)
+        };
+        let reserve = match priority {
+            Priority::High => 0,
+            Priority::Normal | Priority::Participating => bucket.burst() * HIGH_PRIORITY_RESERVE_PERCENTAGE / 100
+        };
+
+        if priority == Priority::Participating && participating.available(now) < bytes {
+            return false;
+        }
+        if !bucket.take(bytes, reserve, now) {
+            return false;
+        }
+        if priority == Priority::Participating {
+            participating.try_consume(bytes, now);
+        }
+
+        true
+    }
+}
@@ -0,0 +1,55 @@
+use std::error;
+use std::fmt;
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransportError {
+    /// The peer publishes no address any of our transports can use.
+    NoAddress,
+    /// Every usable address of the peer failed.
+    Unreachable,
+    /// The message expired before it could be sent.
+    Expired,
+    /// Too many messages are already queued for the peer.
+    QueueFull,
+    /// Sending the message now would exceed the bandwidth limit.
+    BandwidthExceeded,
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransportError::NoAddress => {
+                writeln!(f, "Error: The peer has no address our transports can use.")
+            }
+            TransportError::Unreachable => {
+                writeln!(f, "Error: The peer could not be reached on any of its addresses.")
+            }
+            TransportError::Expired => {
+                writeln!(f, "Error: The message expired before it could be sent.")
+            }
+            TransportError::QueueFull => {
+                writeln!(f, "Error: The queue of messages for the peer is full.")
+            }
+            TransportError::BandwidthExceeded => {
+                writeln!(f, "Error: Sending the message would exceed the bandwidth limit.")
+            }
+        }
+    }
+}
+
+impl error::Error for TransportError {
+    fn description(&self) -> &str {
+        match *self {
+            TransportError::NoAddress => "The peer has no address our transports can use.",
+            TransportError::Unreachable => "The peer could not be reached on any of its addresses.",
+            TransportError::Expired => "The message expired before it could be sent.",
+            TransportError::QueueFull => "The queue of messages for the peer is full.",
+            TransportError::BandwidthExceeded => "Sending the message would exceed the bandwidth limit.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+use common::{Hash256, I2pDate, Mapping, RouterAddress, RouterIdentity, RouterInfo, SessionSigner};
+use i2np::{I2npMessage, MessageType};
+use netdb::NetDbStore;
+use transport::bandwidth::{BandwidthConfig, BandwidthLimiter, Direction, Priority};
+use transport::error::TransportError;
+use transport::ssu2::parse_introducers;
+
+
+/// The most messages queued for a peer while we connect to it, by default.
+const DEFAULT_MAX_QUEUE_LENGTH: usize = 64;
+
+/// The extra cost of an address we can only reach through introducers.
+const INTRODUCER_COST: u32 = 10;
+
+/// The length of the standard I2NP header, counted with the payload against the
+/// bandwidth limit.
+const I2NP_HEADER_LENGTH: u64 = 16;
+
+/// The transport protocol of a router address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TransportStyle {
+    Ntcp2,
+    Ssu2
+}
+
+impl TransportStyle {
+    /// Reads the transport style as published in a `RouterAddress`.
+    pub fn from_name(name: &str) -> Option<TransportStyle> {
+        match name {
+            "NTCP2" => Some(TransportStyle::Ntcp2),
+            "SSU2" => Some(TransportStyle::Ssu2),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TransportStyle::Ntcp2 => "NTCP2",
+            TransportStyle::Ssu2 => "SSU2"
+        }
+    }
+}
+
+/// A `TransportAddress` is the part of a published `RouterAddress` that transport
+/// selection needs. `caps` may hold `4` and `6` for the IP versions a router without
+/// a published host supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransportAddress {
+    pub style: TransportStyle,
+    pub cost: u8,
+    pub address: Option<SocketAddr>,
+    pub caps: String,
+    pub introducers: usize
+}
+
+impl TransportAddress {
+    /// Reads an address published in a RouterInfo: its `host` and `port`, its
+    /// `caps`, and for SSU2 its `ihN` introducers. Returns `None` for transports we
+    /// do not run.
+    pub fn from_router_address(address: &RouterAddress) -> Option<TransportAddress> {
+        let style = TransportStyle::from_name(address.transport_style())?;
+        let host = address.option("host").and_then(|host| host.parse::<IpAddr>().ok());
+        let port = address.option("port").and_then(|port| port.parse::<u16>().ok()).filter(|port| *port != 0);
+        let introducers = match style {
+            TransportStyle::Ssu2 => {
+                let options: Vec<(String, String)> = address.options().iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                parse_introducers(&options).len()
+            }
+            TransportStyle::Ntcp2 => 0
+        };
+
+        Some(TransportAddress {
+            style,
+            cost: address.cost(),
+            address: match (host, port) {
+                (Some(host), Some(port)) => Some(SocketAddr::new(host, port)),
+                _ => None
+            },
+            caps: address.option("caps").unwrap_or_default().to_string(),
+            introducers
+        })
+    }
+
+    /// Returns the cost of connecting to the address, or `None` if we cannot
+    /// connect to it with the IP versions we have.
+    fn score(&self, ipv4: bool, ipv6: bool) -> Option<u32> {
+        match self.address {
+            Some(SocketAddr::V4(_)) if ipv4 => Some(self.cost as u32),
+            Some(SocketAddr::V6(_)) if ipv6 => Some(self.cost as u32),
+            Some(_) => None,
+            None if self.style == TransportStyle::Ssu2 && self.introducers > 0 => {
+                let reachable = (ipv4 && (self.caps.is_empty() || self.caps.contains('4')))
+                    || (ipv6 && self.caps.contains('6'));
+                if reachable { Some(self.cost as u32 + INTRODUCER_COST) } else { None }
+            }
+            None => None
+        }
+    }
+}
+
+/// Returns the priority of a message of our own: tunnel builds and netDb traffic
+/// keep the router working, everything else is client traffic.
+fn priority(message: &I2npMessage) -> Priority {
+    match message.message_type {
+        MessageType::DatabaseStore
+        | MessageType::DatabaseLookup
+        | MessageType::DatabaseSearchReply
+        | MessageType::TunnelBuild
+        | MessageType::TunnelBuildReply
+        | MessageType::VariableTunnelBuild
+        | MessageType::VariableTunnelBuildReply
+        | MessageType::ShortTunnelBuild
+        | MessageType::OutboundTunnelBuildReply => Priority::High,
+        _ => Priority::Normal
+    }
+}
+
+fn length(message: &I2npMessage) -> u64 {
+    I2NP_HEADER_LENGTH + message.payload.len() as u64
+}
+
+/// The `AddressBook` trait looks up the addresses a peer publishes in its RouterInfo.
+pub trait AddressBook {
+    fn addresses(&self, peer: &Hash256) -> Option<Vec<TransportAddress>>;
+}
+
+/// The network database is the address book of the router: a peer's addresses are
+/// those of its RouterInfo.
+impl AddressBook for NetDbStore<RouterInfo> {
+    fn addresses(&self, peer: &Hash256) -> Option<Vec<TransportAddress>> {
+        let router_info = self.get(peer)?;
+
+        Some(router_info.addresses().iter().filter_map(TransportAddress::from_router_address).collect())
+    }
+}
+
+/// The `Transport` trait is what the `TransportManager` needs from NTCP2 and SSU2.
+/// Connecting is asynchronous: the transport reports the outcome through
+/// `TransportManager::connection_established` and `connection_failed`.
+pub trait Transport {
+    fn style(&self) -> TransportStyle;
+
+    /// Starts connecting to the peer. Returns `false` if the attempt could not even
+    /// be started.
+    fn connect(&mut self, peer: &Hash256, address: &TransportAddress) -> bool;
+
+    /// Sends a message over an established session. Returns `false` if the session
+    /// is gone.
+    fn send(&mut self, peer: &Hash256, message: &I2npMessage) -> bool;
+}
+
+/// The connection state of a peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PeerState {
+    Disconnected,
+    Connecting(TransportStyle),
+    Connected(TransportStyle),
+    /// Every address failed on the last attempt.
+    Unreachable
+}
+
+struct Peer {
+    state: PeerState,
+    queue: VecDeque<I2npMessage>,
+    candidates: Vec<TransportAddress>
+}
+
+impl Peer {
+    fn new() -> Peer {
+        Peer {
+            state: PeerState::Disconnected,
+            queue: VecDeque::new(),
+            candidates: Vec::new()
+        }
+    }
+}
+
+/// A `TransportManager` sends I2NP messages to peers over whichever transport suits
+/// them. It reuses an established session when there is one. Otherwise it queues
+/// the message and connects to the cheapest usable address of the peer, falling
+/// back to the next address, possibly on another transport, when a connection
+/// fails. With a `BandwidthLimiter` set, messages beyond the bandwidth limits are
+/// refused.
+pub struct TransportManager<A> {
+    book: A,
+    transports: Vec<Box<dyn Transport>>,
+    peers: HashMap<Hash256, Peer>,
+    ipv4: bool,
+    ipv6: bool,
+    max_queue_length: usize,
+    limiter: Option<BandwidthLimiter>
+}
+
+impl<A> TransportManager<A> where A: AddressBook {
+    pub fn new(book: A, transports: Vec<Box<dyn Transport>>) -> TransportManager<A> {
+        TransportManager {
+            book,
+            transports,
+            peers: HashMap::new(),
+            ipv4: true,
+            ipv6: false,
+            max_queue_length: DEFAULT_MAX_QUEUE_LENGTH,
+            limiter: None
+        }
+    }
+
+    /// Sets which IP versions we can connect with. By default only IPv4.
+    pub fn set_ip_versions(&mut self, ipv4: bool, ipv6: bool) {
+        self.ipv4 = ipv4;
+        self.ipv6 = ipv6;
+    }
+
+    pub fn set_max_queue_length(&mut self, max_queue_length: usize) {
+        self.max_queue_length = max_queue_length;
+    }
+
+    /// Limits the traffic of all transports together. By default it is unlimited.
+    pub fn set_bandwidth_limiter(&mut self, limiter: BandwidthLimiter) {
+        self.limiter = Some(limiter);
+    }
+
+    pub fn bandwidth_limiter(&self) -> Option<&BandwidthLimiter> {
+        self.limiter.as_ref()
+    }
+
+    /// Creates our RouterInfo with the addresses of our transports. Its `caps`
+    /// advertise the bandwidth we share, by the default configuration when no
+    /// `BandwidthLimiter` is set.
+    pub fn router_info<S>(&self,
+                          identity: RouterIdentity,
+                          published: I2pDate,
+                          addresses: Vec<RouterAddress>,
+                          signer: &S) -> RouterInfo where S: SessionSigner {
+        let caps = match self.limiter {
+            Some(ref limiter) => limiter.caps_letter(),
+            None => BandwidthConfig::default().caps_letter()
+        };
+        let mut options = Mapping::new();
+        options.insert("caps", &caps.to_string()).expect("The caps option is short");
+
+        RouterInfo::new(identity, published, addresses, options, signer)
+    }
+
+    /// Accounts for `bytes` of traffic. Returns `false` if they exceed the limit.
+    fn allow(&mut self, direction: Direction, priority: Priority, bytes: u64) -> bool {
+        match self.limiter {
+            Some(ref mut limiter) => limiter.request(direction, priority, bytes, Instant::now()),
+            None => true
+        }
+    }
+
+    /// Accounts for a message a transport received from a peer. Returns `false` if
+    /// it exceeds the inbound limit, in which case the message should be dropped.
+    pub fn receive(&mut self, message: &I2npMessage) -> bool {
+        let priority = priority(message);
+        self.allow(Direction::Inbound, priority, length(message))
+    }
+
+    /// Accounts for a message received for a tunnel we participate in, which may
+    /// only use the bandwidth we share.
+    pub fn receive_participating(&mut self, message: &I2npMessage) -> bool {
+        self.allow(Direction::Inbound, Priority::Participating, length(message))
+    }
+
+    /// Returns the connection state of a peer.
+    pub fn state(&self, peer: &Hash256) -> PeerState {
+        match self.peers.get(peer) {
+            Some(peer) => peer.state,
+            None => PeerState::Disconnected
+        }
+    }
+
+    /// Returns the number of messages queued for a peer.
+    pub fn queued(&self, peer: &Hash256) -> usize {
+        self.peers.get(peer).map(|peer| peer.queue.len()).unwrap_or(0)
+    }
+
+    fn transport(&mut self, style: TransportStyle) -> Option<&mut Box<dyn Transport>> {
+        self.transports.iter_mut().find(|transport| transport.style() == style)
+    }
+
+    /// Returns the addresses of a peer we can connect to, cheapest first.
+    fn candidates(&self, peer: &Hash256) -> Vec<TransportAddress> {
+        let (ipv4, ipv6) = (self.ipv4, self.ipv6);
+        let mut candidates: Vec<(u32, TransportAddress)> = self.book.addresses(peer)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|address| self.transports.iter().any(|transport| transport.style() == address.style))
+            .filter_map(|address| address.score(ipv4, ipv6).map(|score| (score, address)))
+            .collect();
+        candidates.sort_by_key(|&(score, _)| score);
+
+        candidates.into_iter().map(|(_, address)| address).collect()
+    }
+
+    /// Sends a message to a peer, or queues it until a session is established.
+    pub fn send(&mut self, peer: &Hash256, message: I2npMessage, now: I2pDate) -> Result<(), TransportError> {
+        let priority = priority(&message);
+        self.send_with_priority(peer, message, priority, now)
+    }
+
+    /// Sends a message of a tunnel we participate in, which may only use the
+    /// bandwidth we share.
+    pub fn send_participating(&mut self, peer: &Hash256, message: I2npMessage, now: I2pDate)
+        -> Result<(), TransportError>
+    {
+        self.send_with_priority(peer, message, Priority::Participating, now)
+    }
+
+    fn send_with_priority(&mut self, peer: &Hash256, message: I2npMessage, priority: Priority, now: I2pDate)
+        -> Result<(), TransportError>
+    {
+        if message.is_expired(now) {
+            return Err(TransportError::Expired);
+        }
+
+        // Bandwidth is only charged for messages that are sent or queued.
+        let length = length(&message);
+        let mut charged = false;
+        if let PeerState::Connected(style) = self.state(peer) {
+            if !self.allow(Direction::Outbound, priority, length) {
+                return Err(TransportError::BandwidthExceeded);
+            }
+            if self.transport(style).map(|transport| transport.send(peer, &message)) == Some(true) {
+                return Ok(());
+            }
+            self.connection_closed(peer);
+            charged = true;
+        }
+
+        let connecting = match self.peers.get(peer) {
+            Some(entry) if entry.queue.len() >= self.max_queue_length => return Err(TransportError::QueueFull),
+            Some(entry) => matches!(entry.state, PeerState::Connecting(_)),
+            None => false
+        };
+        let candidates = if connecting { Vec::new() } else { self.candidates(peer) };
+        if !connecting && candidates.is_empty() {
+            self.peers.remove(peer);
+            return Err(TransportError::NoAddress);
+        }
+        if !charged && !self.allow(Direction::Outbound, priority, length) {
+            return Err(TransportError::BandwidthExceeded);
+        }
+
+        let entry = self.peers.entry(peer.clone()).or_insert_with(Peer::new);
+        entry.queue.push_back(message);
+        if connecting {
+            return Ok(());
+        }
+        entry.candidates = candidates;
+        if self.connect_next(peer) {
+            Ok(())
+        } else {
+            Err(TransportError::Unreachable)
+        }
+    }
+
+    /// Tries the remaining addresses of a peer until a connection attempt starts.
+    /// Marks the peer unreachable and drops its queue if none does.
+    fn connect_next(&mut self, peer: &Hash256) -> bool {
+        loop {
+            let candidate = match self.peers.get_mut(peer) {
+                Some(entry) if !entry.candidates.is_empty() => entry.candidates.remove(0),
+                Some(entry) => {
+                    entry.state = PeerState::Unreachable;
+                    entry.queue.clear();
+                    return false;
+                }
+                None => return false
+            };
+
+            let started = match self.transport(candidate.style) {
+                Some(transport) => transport.connect(peer, &candidate),
+                None => false
+            };
+            if started {
+                self.peers.get_mut(peer).unwrap().state = PeerState::Connecting(candidate.style);
+                return true;
+            }
+        }
+    }
+
+    /// Records a session established with a peer, by us or by the peer, and sends
+    /// the messages queued for it that have not expired. Returns the number sent.
+    pub fn connection_established(&mut self, peer: &Hash256, style: TransportStyle, now: I2pDate) -> usize {
+        let queue = {
+            let entry = self.peers.entry(peer.clone()).or_insert_with(Peer::new);
+            entry.state = PeerState::Connected(style);
+            entry.candidates.clear();
+            entry.queue.drain(..).collect::<Vec<_>>()
+        };
+
+        let mut sent = 0;
+        for message in queue.into_iter().filter(|message| !message.is_expired(now)) {
+            if self.transport(style).map(|transport| transport.send(peer, &message)) == Some(true) {
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    /// Records a failed connection attempt and falls back to the next address.
+    /// Returns `false` if the peer is now unreachable.
+    pub fn connection_failed(&mut self, peer: &Hash256) -> bool {
+        match self.state(peer) {
+            PeerState::Connecting(_) => self.connect_next(peer),
+            _ => false
+        }
+    }
+
+    /// Records that the session with a peer closed.
+    pub fn connection_closed(&mut self, peer: &Hash256) {
+        if let Some(entry) = self.peers.get_mut(peer) {
+            if let PeerState::Connected(_) = entry.state {
+                entry.state = PeerState::Disconnected;
+            }
+        }
+    }
+
+    /// Drops expired messages from every queue. Returns the number dropped.
+    pub fn expire(&mut self, now: I2pDate) -> usize {
+        let mut dropped = 0;
+        for entry in self.peers.values_mut() {
+            let before = entry.queue.len();
+            entry.queue.retain(|message| !message.is_expired(now));
+            dropped += before - entry.queue.len();
+        }
+
+        dropped
+    }
+}
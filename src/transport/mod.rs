@@ -0,0 +1,13 @@
+pub use self::bandwidth::{BandwidthLimiter, BandwidthConfig, TokenBucket, Direction, Priority};
+pub use self::error::TransportError;
+pub use self::manager::{TransportManager, Transport, TransportStyle, TransportAddress, AddressBook, PeerState};
+pub use self::reachability::{Reachability, ReachabilityTracker, AddressPublication};
+
+pub mod ntcp2;
+pub mod ssu2;
+
+mod bandwidth;
+mod error;
+mod manager;
+mod reachability;
+pub(crate) mod noise;
@@ -0,0 +1,117 @@
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+
+/// The length of an X25519 key, a SHA256 hash and a ChaCha20-Poly1305 key.
+pub const KEY_LENGTH: usize = 32;
+
+pub fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+
+    hasher.finalize().into()
+}
+
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+/// HKDF-SHA256 with 64 bytes of output, returned as two keys.
+pub fn hkdf(salt: &[u8], input: &[u8], info: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let prk = hmac_sha256(salt, &[input]);
+    let first = hmac_sha256(&prk, &[info, &[0x01]]);
+    let second = hmac_sha256(&prk, &[&first, info, &[0x02]]);
+
+    (first, second)
+}
+
+/// Builds the 12 byte Noise nonce: four zero bytes and a little endian counter.
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0x00; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+
+    Nonce::from(nonce)
+}
+
+/// Encrypts `plaintext` with ChaCha20-Poly1305 and appends the MAC.
+pub fn encrypt(key: &[u8; 32], counter: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload { msg: plaintext, aad: ad };
+
+    cipher.encrypt(&nonce(counter), payload).expect("ChaCha20-Poly1305 encryption does not fail")
+}
+
+/// Authenticates and decrypts `ciphertext`. Returns `None` if authentication fails.
+pub fn decrypt(key: &[u8; 32], counter: u64, ad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload { msg: ciphertext, aad: ad };
+
+    cipher.decrypt(&nonce(counter), payload).ok()
+}
+
+/// The `SymmetricState` of a Noise handshake: the chaining key, the handshake hash
+/// and the current cipher key. NTCP2 and SSU2 both run XK patterns over it.
+#[derive(Clone)]
+pub struct SymmetricState {
+    chaining_key: [u8; 32],
+    hash: [u8; 32],
+    key: [u8; 32]
+}
+
+impl SymmetricState {
+    /// Starts a handshake with the responder's static key, which the initiator knows
+    /// from the responder's RouterInfo. The prologue is empty.
+    pub fn new(protocol_name: &[u8], responder_static_key: &[u8; KEY_LENGTH]) -> SymmetricState {
+        let hash = sha256(&[protocol_name]);
+        let mut state = SymmetricState {
+            chaining_key: hash,
+            hash,
+            key: [0x00; 32]
+        };
+        state.mix_hash(&[]);
+        state.mix_hash(responder_static_key);
+
+        state
+    }
+
+    pub fn chaining_key(&self) -> &[u8; 32] {
+        &self.chaining_key
+    }
+
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        self.hash = sha256(&[&self.hash, data]);
+    }
+
+    pub fn mix_key(&mut self, shared_secret: &[u8; KEY_LENGTH]) {
+        let (chaining_key, key) = hkdf(&self.chaining_key, shared_secret, &[]);
+        self.chaining_key = chaining_key;
+        self.key = key;
+    }
+
+    pub fn encrypt_and_hash(&mut self, nonce: u64, plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = encrypt(&self.key, nonce, &self.hash, plaintext);
+        self.mix_hash(&ciphertext);
+
+        ciphertext
+    }
+
+    pub fn decrypt_and_hash(&mut self, nonce: u64, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let plaintext = decrypt(&self.key, nonce, &self.hash, ciphertext)?;
+        self.mix_hash(ciphertext);
+
+        Some(plaintext)
+    }
+}
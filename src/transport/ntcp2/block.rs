@@ -0,0 +1,301 @@
+use common::{I2pDate, I2pInt64};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::error::Ntcp2Error;
+
+
+/// Each block starts with a one byte type and a two byte big endian size.
+pub const BLOCK_HEADER_LENGTH: usize = 3;
+
+/// The I2NP block replaces the 16 byte I2NP header with a 9 byte short header:
+/// the type, the message ID and the expiration in seconds.
+pub const I2NP_SHORT_HEADER_LENGTH: usize = 9;
+
+const DATE_TIME_LENGTH: usize = 4;
+const OPTIONS_LENGTH: usize = 12;
+const TERMINATION_LENGTH: usize = 9;
+
+const BLOCK_DATE_TIME: u8 = 0;
+const BLOCK_OPTIONS: u8 = 1;
+const BLOCK_ROUTER_INFO: u8 = 2;
+const BLOCK_I2NP: u8 = 3;
+const BLOCK_TERMINATION: u8 = 4;
+const BLOCK_PADDING: u8 = 254;
+
+const ROUTER_INFO_FLAG_FLOOD: u8 = 0x01;
+
+/// The reason carried in a Termination block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    NormalClose,
+    TerminationReceived,
+    IdleTimeout,
+    RouterShutdown,
+    DataPhaseAeadFailure,
+    IncompatibleOptions,
+    IncompatibleSignatureType,
+    ClockSkew,
+    PaddingViolation,
+    AeadFramingError,
+    PayloadFormatError,
+    SessionRequestError,
+    SessionCreatedError,
+    SessionConfirmedError,
+    IntraFrameReadTimeout,
+    RouterInfoSignatureFailure,
+    StaticKeyMismatch,
+    Banned,
+    Other(u8)
+}
+
+impl TerminationReason {
+    pub fn from_code(code: u8) -> TerminationReason {
+        match code {
+            0  => TerminationReason::NormalClose,
+            1  => TerminationReason::TerminationReceived,
+            2  => TerminationReason::IdleTimeout,
+            3  => TerminationReason::RouterShutdown,
+            4  => TerminationReason::DataPhaseAeadFailure,
+            5  => TerminationReason::IncompatibleOptions,
+            6  => TerminationReason::IncompatibleSignatureType,
+            7  => TerminationReason::ClockSkew,
+            8  => TerminationReason::PaddingViolation,
+            9  => TerminationReason::AeadFramingError,
+            10 => TerminationReason::PayloadFormatError,
+            11 => TerminationReason::SessionRequestError,
+            12 => TerminationReason::SessionCreatedError,
+            13 => TerminationReason::SessionConfirmedError,
+            14 => TerminationReason::IntraFrameReadTimeout,
+            15 => TerminationReason::RouterInfoSignatureFailure,
+            16 => TerminationReason::StaticKeyMismatch,
+            17 => TerminationReason::Banned,
+            _  => TerminationReason::Other(code)
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            TerminationReason::NormalClose                => 0,
+            TerminationReason::TerminationReceived        => 1,
+            TerminationReason::IdleTimeout                => 2,
+            TerminationReason::RouterShutdown             => 3,
+            TerminationReason::DataPhaseAeadFailure       => 4,
+            TerminationReason::IncompatibleOptions        => 5,
+            TerminationReason::IncompatibleSignatureType  => 6,
+            TerminationReason::ClockSkew                  => 7,
+            TerminationReason::PaddingViolation           => 8,
+            TerminationReason::AeadFramingError           => 9,
+            TerminationReason::PayloadFormatError         => 10,
+            TerminationReason::SessionRequestError        => 11,
+            TerminationReason::SessionCreatedError        => 12,
+            TerminationReason::SessionConfirmedError      => 13,
+            TerminationReason::IntraFrameReadTimeout      => 14,
+            TerminationReason::RouterInfoSignatureFailure => 15,
+            TerminationReason::StaticKeyMismatch          => 16,
+            TerminationReason::Banned                     => 17,
+            TerminationReason::Other(code)                => code
+        }
+    }
+}
+
+/// The padding and dummy traffic parameters of an Options block. The `t` fields
+/// apply to traffic we send, the `r` fields to traffic we receive. Padding ratios
+/// are 4.4 fixed point numbers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub tmin: u8,
+    pub tmax: u8,
+    pub rmin: u8,
+    pub rmax: u8,
+    pub tdmy: u16,
+    pub rdmy: u16,
+    pub tdelay: u16,
+    pub rdelay: u16
+}
+
+/// A `Block` is one unit of the payload of an NTCP2 data phase frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// The current time in seconds since the UNIX epoch.
+    DateTime(u32),
+    Options(Options),
+    /// A serialized RouterInfo, and whether the receiver should flood it.
+    RouterInfo { flood: bool, router_info: Vec<u8> },
+    I2np(I2npMessage),
+    /// The number of valid frames received, and why the connection is closing.
+    Termination { valid_frames: u64, reason: TerminationReason },
+    /// Random padding. It must be the last block of a frame.
+    Padding(Vec<u8>),
+    Unknown(u8, Vec<u8>)
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 8) | (bytes[1] as u16)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+/// Converts an I2NP expiration to the whole seconds of the short header, rounding
+/// to the nearest second.
+fn expiration_seconds(expiration: I2pDate) -> u32 {
+    ((expiration.to_u64() + 500) / 1000) as u32
+}
+
+impl Block {
+    /// Returns the length of the block in bytes, including its header.
+    pub fn encoded_len(&self) -> usize {
+        BLOCK_HEADER_LENGTH + match *self {
+            Block::DateTime(_) => DATE_TIME_LENGTH,
+            Block::Options(_) => OPTIONS_LENGTH,
+            Block::RouterInfo { ref router_info, .. } => 1 + router_info.len(),
+            Block::I2np(ref message) => I2NP_SHORT_HEADER_LENGTH + message.payload.len(),
+            Block::Termination { .. } => TERMINATION_LENGTH,
+            Block::Padding(ref padding) => padding.len(),
+            Block::Unknown(_, ref data) => data.len()
+        }
+    }
+
+    fn type_code(&self) -> u8 {
+        match *self {
+            Block::DateTime(_) => BLOCK_DATE_TIME,
+            Block::Options(_) => BLOCK_OPTIONS,
+            Block::RouterInfo { .. } => BLOCK_ROUTER_INFO,
+            Block::I2np(_) => BLOCK_I2NP,
+            Block::Termination { .. } => BLOCK_TERMINATION,
+            Block::Padding(_) => BLOCK_PADDING,
+            Block::Unknown(code, _) => code
+        }
+    }
+
+    /// Appends the encoded block to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.type_code());
+        push_u16(buf, (self.encoded_len() - BLOCK_HEADER_LENGTH) as u16);
+
+        match *self {
+            Block::DateTime(timestamp) => {
+                buf.extend_from_slice(&timestamp.to_be_bytes());
+            }
+            Block::Options(ref options) => {
+                buf.extend_from_slice(&[options.tmin, options.tmax, options.rmin, options.rmax]);
+                for value in &[options.tdmy, options.rdmy, options.tdelay, options.rdelay] {
+                    push_u16(buf, *value);
+                }
+            }
+            Block::RouterInfo { flood, ref router_info } => {
+                buf.push(if flood { ROUTER_INFO_FLAG_FLOOD } else { 0x00 });
+                buf.extend_from_slice(router_info);
+            }
+            Block::I2np(ref message) => {
+                buf.push(message.message_type.code());
+                buf.extend_from_slice(&message.message_id.to_be_bytes());
+                buf.extend_from_slice(&expiration_seconds(message.expiration).to_be_bytes());
+                buf.extend_from_slice(&message.payload);
+            }
+            Block::Termination { valid_frames, reason } => {
+                buf.extend_from_slice(&valid_frames.to_be_bytes());
+                buf.push(reason.code());
+            }
+            Block::Padding(ref padding) => {
+                buf.extend_from_slice(padding);
+            }
+            Block::Unknown(_, ref data) => {
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+
+    fn decode(code: u8, data: &[u8]) -> Result<Block, Ntcp2Error> {
+        let block = match code {
+            BLOCK_DATE_TIME if data.len() == DATE_TIME_LENGTH => {
+                Block::DateTime(read_u32(data))
+            }
+            BLOCK_OPTIONS if data.len() >= OPTIONS_LENGTH => {
+                Block::Options(Options {
+                    tmin: data[0],
+                    tmax: data[1],
+                    rmin: data[2],
+                    rmax: data[3],
+                    tdmy: read_u16(&data[4..6]),
+                    rdmy: read_u16(&data[6..8]),
+                    tdelay: read_u16(&data[8..10]),
+                    rdelay: read_u16(&data[10..12])
+                })
+            }
+            BLOCK_ROUTER_INFO if !data.is_empty() => {
+                Block::RouterInfo {
+                    flood: data[0] & ROUTER_INFO_FLAG_FLOOD != 0,
+                    router_info: data[1..].to_vec()
+                }
+            }
+            BLOCK_I2NP if data.len() >= I2NP_SHORT_HEADER_LENGTH => {
+                let seconds = read_u32(&data[5..9]) as u64;
+                let expiration = match I2pDate::new(I2pInt64::new(seconds * 1000)) {
+                    Ok(expiration) => expiration,
+                    Err(_) => return Err(Ntcp2Error::InvalidBlock)
+                };
+                let message = I2npMessage::new(MessageType::from_code(data[0]),
+                                               read_u32(&data[1..5]),
+                                               expiration,
+                                               data[I2NP_SHORT_HEADER_LENGTH..].to_vec());
+                Block::I2np(message)
+            }
+            BLOCK_TERMINATION if data.len() >= TERMINATION_LENGTH => {
+                let valid_frames = data[0..8].iter().fold(0, |value, byte| (value << 8) | (*byte as u64));
+                Block::Termination {
+                    valid_frames,
+                    reason: TerminationReason::from_code(data[8])
+                }
+            }
+            BLOCK_PADDING => Block::Padding(data.to_vec()),
+            BLOCK_DATE_TIME | BLOCK_OPTIONS | BLOCK_ROUTER_INFO | BLOCK_I2NP | BLOCK_TERMINATION => {
+                return Err(Ntcp2Error::InvalidBlock);
+            }
+            _ => Block::Unknown(code, data.to_vec())
+        };
+
+        Ok(block)
+    }
+}
+
+/// Encodes a sequence of blocks into a frame payload.
+pub fn encode_blocks(blocks: &[Block]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(blocks.iter().map(|block| block.encoded_len()).sum());
+    for block in blocks {
+        block.encode(&mut payload);
+    }
+
+    payload
+}
+
+/// Decodes a frame payload into its blocks. Padding must be the last block.
+pub fn decode_blocks(payload: &[u8]) -> Result<Vec<Block>, Ntcp2Error> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset < payload.len() {
+        if let Some(&Block::Padding(_)) = blocks.last() {
+            return Err(Ntcp2Error::InvalidBlock);
+        }
+        if payload.len() - offset < BLOCK_HEADER_LENGTH {
+            return Err(Ntcp2Error::InvalidBlock);
+        }
+
+        let code = payload[offset];
+        let size = read_u16(&payload[offset + 1..offset + 3]) as usize;
+        let start = offset + BLOCK_HEADER_LENGTH;
+        if payload.len() - start < size {
+            return Err(Ntcp2Error::InvalidBlock);
+        }
+
+        blocks.push(Block::decode(code, &payload[start..start + size])?);
+        offset = start + size;
+    }
+
+    Ok(blocks)
+}
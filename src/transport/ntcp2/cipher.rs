@@ -0,0 +1,35 @@
+use transport::noise::{encrypt, decrypt};
+use transport::ntcp2::connection::CipherState;
+
+
+/// A `ChaChaCipher` is the ChaCha20-Poly1305 cipher state of one direction of the
+/// data phase. Frames have no associated data, and the nonce counts frames from zero.
+pub struct ChaChaCipher {
+    key: [u8; 32],
+    nonce: u64
+}
+
+impl ChaChaCipher {
+    pub fn new(key: [u8; 32]) -> ChaChaCipher {
+        ChaChaCipher {
+            key,
+            nonce: 0
+        }
+    }
+}
+
+impl CipherState for ChaChaCipher {
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let frame = encrypt(&self.key, self.nonce, &[], plaintext);
+        self.nonce += 1;
+
+        frame
+    }
+
+    fn decrypt(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let plaintext = decrypt(&self.key, self.nonce, &[], frame)?;
+        self.nonce += 1;
+
+        Some(plaintext)
+    }
+}
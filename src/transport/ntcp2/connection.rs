@@ -0,0 +1,211 @@
+use std::io::{Read, Write};
+use i2np::I2npMessage;
+use transport::ntcp2::block::{Block, TerminationReason};
+use transport::ntcp2::block::{encode_blocks, decode_blocks};
+use transport::ntcp2::error::Ntcp2Error;
+use transport::ntcp2::length::{LengthObfuscator, SipKeys};
+
+
+/// The length of the Poly1305 MAC at the end of every frame.
+pub const MAC_LENGTH: usize = 16;
+
+/// The largest frame, MAC included, that a two byte length can describe.
+pub const MAX_FRAME_LENGTH: usize = 65535;
+
+/// The largest payload that fits in a single frame.
+pub const MAX_PAYLOAD_LENGTH: usize = MAX_FRAME_LENGTH - MAC_LENGTH;
+
+/// The `CipherState` trait is the ChaCha20-Poly1305 cipher state for one direction of
+/// the data phase, keyed by the split of the handshake. Each call uses the next nonce.
+pub trait CipherState {
+    /// Encrypts a frame payload and appends the MAC.
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8>;
+
+    /// Authenticates and decrypts a frame. Returns `None` if authentication fails.
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Traffic counters for a single connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64
+}
+
+/// A `Connection` is an NTCP2 session in the data phase. Each frame is a two byte
+/// obfuscated length followed by an encrypted payload of blocks.
+pub struct Connection<S, C> {
+    stream: S,
+    send_cipher: C,
+    receive_cipher: C,
+    send_length: LengthObfuscator,
+    receive_length: LengthObfuscator,
+    stats: ConnectionStats,
+    termination: Option<TerminationReason>
+}
+
+impl<S, C> Connection<S, C> where S: Read + Write, C: CipherState {
+    /// Creates a connection from the keys derived at the end of the handshake.
+    pub fn new(stream: S, send_cipher: C, receive_cipher: C, send_keys: SipKeys, receive_keys: SipKeys) -> Connection<S, C> {
+        Connection {
+            stream,
+            send_cipher,
+            receive_cipher,
+            send_length: LengthObfuscator::new(send_keys),
+            receive_length: LengthObfuscator::new(receive_keys),
+            stats: ConnectionStats::default(),
+            termination: None
+        }
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats
+    }
+
+    /// Returns the reason the connection was terminated, if it was.
+    pub fn termination(&self) -> Option<TerminationReason> {
+        self.termination
+    }
+
+    fn check_open(&self) -> Result<(), Ntcp2Error> {
+        match self.termination {
+            Some(reason) => Err(Ntcp2Error::Terminated(reason)),
+            None => Ok(())
+        }
+    }
+
+    /// Encrypts and sends the blocks as a single frame.
+    pub fn send_frame(&mut self, blocks: &[Block]) -> Result<(), Ntcp2Error> {
+        self.check_open()?;
+        let payload = encode_blocks(blocks);
+        if payload.len() > MAX_PAYLOAD_LENGTH {
+            return Err(Ntcp2Error::FrameTooLarge(payload.len() + MAC_LENGTH));
+        }
+
+        let frame = self.send_cipher.encrypt(&payload);
+        let length = self.send_length.obfuscate(frame.len() as u16);
+        self.stream.write_all(&length)?;
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+
+        self.stats.frames_sent += 1;
+        self.stats.bytes_sent += (length.len() + frame.len()) as u64;
+        self.stats.messages_sent += blocks.iter().filter(|block| is_message(block)).count() as u64;
+
+        Ok(())
+    }
+
+    /// Sends I2NP messages, coalescing as many as fit into each frame. NTCP2 does
+    /// not fragment messages, so a message too large for a frame is an error. Returns
+    /// the number of frames sent.
+    pub fn send_messages(&mut self, messages: &[I2npMessage]) -> Result<usize, Ntcp2Error> {
+        let mut frames = 0;
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut length = 0;
+
+        for message in messages {
+            let block = Block::I2np(message.clone());
+            if block.encoded_len() > MAX_PAYLOAD_LENGTH {
+                return Err(Ntcp2Error::FrameTooLarge(block.encoded_len() + MAC_LENGTH));
+            }
+            if length + block.encoded_len() > MAX_PAYLOAD_LENGTH {
+                self.send_frame(&blocks)?;
+                frames += 1;
+                blocks.clear();
+                length = 0;
+            }
+
+            length += block.encoded_len();
+            blocks.push(block);
+        }
+
+        if !blocks.is_empty() {
+            self.send_frame(&blocks)?;
+            frames += 1;
+        }
+
+        Ok(frames)
+    }
+
+    /// Reads, authenticates and decodes the next frame. A frame that fails
+    /// authentication or decoding terminates the connection. A Termination block
+    /// from the peer is returned and marks the connection as terminated.
+    pub fn receive_frame(&mut self) -> Result<Vec<Block>, Ntcp2Error> {
+        self.check_open()?;
+
+        let mut length = [0x00; 2];
+        self.stream.read_exact(&mut length)?;
+        let length = self.receive_length.deobfuscate(length) as usize;
+        if length < MAC_LENGTH {
+            self.termination = Some(TerminationReason::AeadFramingError);
+            return Err(Ntcp2Error::Decryption);
+        }
+
+        let mut frame = vec![0x00; length];
+        self.stream.read_exact(&mut frame)?;
+        let payload = match self.receive_cipher.decrypt(&frame) {
+            Some(payload) => payload,
+            None => {
+                self.termination = Some(TerminationReason::DataPhaseAeadFailure);
+                return Err(Ntcp2Error::Decryption);
+            }
+        };
+        let blocks = match decode_blocks(&payload) {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                self.termination = Some(TerminationReason::PayloadFormatError);
+                return Err(err);
+            }
+        };
+
+        self.stats.frames_received += 1;
+        self.stats.bytes_received += (2 + length) as u64;
+        self.stats.messages_received += blocks.iter().filter(|block| is_message(block)).count() as u64;
+        for block in blocks.iter() {
+            if let Block::Termination { reason, .. } = *block {
+                self.termination = Some(reason);
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    /// Receives the I2NP messages of the next frame, discarding its other blocks.
+    pub fn receive_messages(&mut self) -> Result<Vec<I2npMessage>, Ntcp2Error> {
+        let messages = self.receive_frame()?
+            .into_iter()
+            .filter_map(|block| match block {
+                Block::I2np(message) => Some(message),
+                _ => None
+            })
+            .collect();
+
+        Ok(messages)
+    }
+
+    /// Sends a Termination block with the number of frames received and closes the
+    /// connection for further sending and receiving.
+    pub fn terminate(&mut self, reason: TerminationReason) -> Result<(), Ntcp2Error> {
+        let termination = Block::Termination {
+            valid_frames: self.stats.frames_received,
+            reason
+        };
+        self.send_frame(&[termination])?;
+        self.termination = Some(reason);
+
+        Ok(())
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+fn is_message(block: &Block) -> bool {
+    matches!(*block, Block::I2np(_))
+}
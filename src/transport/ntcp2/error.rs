@@ -0,0 +1,83 @@
+use std::error;
+use std::fmt;
+use std::io;
+use transport::ntcp2::block::TerminationReason;
+
+
+#[derive(Debug)]
+pub enum Ntcp2Error {
+    Io(io::Error),
+    /// A frame is longer than the 65535 bytes its length field allows.
+    FrameTooLarge(usize),
+    /// A frame failed authentication.
+    Decryption,
+    /// A frame payload does not follow the block format.
+    InvalidBlock,
+    /// The connection was terminated, by us or by the peer.
+    Terminated(TerminationReason),
+    /// The handshake failed, for the reason that a Termination block would carry.
+    Handshake(TerminationReason),
+    /// The peer's RouterInfo has no NTCP2 address with a static key and IV.
+    InvalidAddress,
+    /// A SessionRequest repeats an ephemeral key seen before.
+    Replay,
+}
+
+impl fmt::Display for Ntcp2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Ntcp2Error::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred on an NTCP2 connection: {}", err)
+            }
+            Ntcp2Error::FrameTooLarge(length) => {
+                writeln!(f, "Error: An NTCP2 frame of {} bytes is too large.", length)
+            }
+            Ntcp2Error::Decryption => {
+                writeln!(f, "Error: An NTCP2 frame failed authentication.")
+            }
+            Ntcp2Error::InvalidBlock => {
+                writeln!(f, "Error: An NTCP2 frame contains an invalid block.")
+            }
+            Ntcp2Error::Terminated(reason) => {
+                writeln!(f, "Error: The NTCP2 connection was terminated: {:?}.", reason)
+            }
+            Ntcp2Error::Handshake(reason) => {
+                writeln!(f, "Error: The NTCP2 handshake failed: {:?}.", reason)
+            }
+            Ntcp2Error::InvalidAddress => {
+                writeln!(f, "Error: The router has no usable NTCP2 address.")
+            }
+            Ntcp2Error::Replay => {
+                writeln!(f, "Error: The NTCP2 SessionRequest repeats an ephemeral key seen before.")
+            }
+        }
+    }
+}
+
+impl error::Error for Ntcp2Error {
+    fn description(&self) -> &str {
+        match *self {
+            Ntcp2Error::Io(_) => "An I/O error occurred on an NTCP2 connection.",
+            Ntcp2Error::FrameTooLarge(_) => "An NTCP2 frame is too large.",
+            Ntcp2Error::Decryption => "An NTCP2 frame failed authentication.",
+            Ntcp2Error::InvalidBlock => "An NTCP2 frame contains an invalid block.",
+            Ntcp2Error::Terminated(_) => "The NTCP2 connection was terminated.",
+            Ntcp2Error::Handshake(_) => "The NTCP2 handshake failed.",
+            Ntcp2Error::InvalidAddress => "The router has no usable NTCP2 address.",
+            Ntcp2Error::Replay => "The NTCP2 SessionRequest repeats an ephemeral key seen before.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Ntcp2Error::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Ntcp2Error {
+    fn from(err: io::Error) -> Ntcp2Error {
+        Ntcp2Error::Io(err)
+    }
+}
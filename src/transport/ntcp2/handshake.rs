@@ -0,0 +1,439 @@
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use aes::Aes256;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::cipher::block_padding::NoPadding;
+use rand;
+use rand::Rng;
+use x25519_dalek::{PublicKey, StaticSecret};
+use common::{FromI2pBase64, Hash256, Hashable256, I2pDate, Mapping, ToI2pBase64};
+use common::{RouterAddress, RouterInfo, SignatureVerifier};
+use transport::ntcp2::block::{Block, TerminationReason};
+use transport::ntcp2::block::{encode_blocks, decode_blocks};
+use transport::noise::{SymmetricState, KEY_LENGTH, hmac_sha256};
+use transport::ntcp2::cipher::ChaChaCipher;
+use transport::ntcp2::connection::{Connection, MAC_LENGTH, MAX_FRAME_LENGTH};
+use transport::ntcp2::error::Ntcp2Error;
+use transport::ntcp2::length::SipKeys;
+use tunnel::DecayingBloomFilter;
+
+
+/// The transport style of NTCP2 router addresses.
+pub const NTCP2_STYLE: &str = "NTCP2";
+
+/// The network ID of the main I2P network.
+pub const NETWORK_ID: u8 = 2;
+
+/// The largest difference in seconds between a peer's clock and ours that the
+/// handshake accepts.
+pub const MAX_CLOCK_SKEW: u64 = 60;
+
+/// The most padding accepted after a SessionRequest or SessionCreated message.
+pub const MAX_HANDSHAKE_PADDING: usize = 128;
+
+/// The most padding we add to each handshake message.
+const PADDING_LENGTH: usize = 64;
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XKaesobfse+hs2+hs3_25519_ChaChaPoly_SHA256";
+const PROTOCOL_VERSION: u8 = 2;
+
+const IV_LENGTH: usize = 16;
+
+/// SessionRequests are refused for their timestamp once they are older than the
+/// allowed clock skew on both sides, so their keys need not be remembered longer.
+const REPLAY_PERIOD_MILLISECONDS: u64 = 2 * MAX_CLOCK_SKEW * 1000;
+
+/// The number of bits in each array of the replay filter.
+const REPLAY_FILTER_BITS: usize = 1 << 20;
+const OPTIONS_LENGTH: usize = 16;
+
+/// SessionRequest and SessionCreated start with an obfuscated ephemeral key and
+/// encrypted options, followed by unencrypted padding.
+const MESSAGE_LENGTH: usize = KEY_LENGTH + OPTIONS_LENGTH + MAC_LENGTH;
+
+/// SessionConfirmed starts with the encrypted static key of the initiator.
+const STATIC_KEY_PART_LENGTH: usize = KEY_LENGTH + MAC_LENGTH;
+
+type Aes256CbcEncryptor = cbc::Encryptor<Aes256>;
+type Aes256CbcDecryptor = cbc::Decryptor<Aes256>;
+
+/// The `StaticKeys` of an NTCP2 address: the X25519 static key of the Noise
+/// handshake, and the IV peers use to obfuscate the ephemeral keys they send us.
+/// The public key and the IV are published in the `s` and `i` options.
+pub struct StaticKeys {
+    private_key: StaticSecret,
+    iv: [u8; IV_LENGTH]
+}
+
+impl StaticKeys {
+    pub fn new(private_key: [u8; KEY_LENGTH], iv: [u8; IV_LENGTH]) -> StaticKeys {
+        StaticKeys {
+            private_key: StaticSecret::from(private_key),
+            iv
+        }
+    }
+
+    /// Generates a random static key and IV.
+    pub fn generate() -> StaticKeys {
+        let mut iv = [0x00; IV_LENGTH];
+        rand::thread_rng().fill_bytes(&mut iv);
+
+        StaticKeys {
+            private_key: random_secret(),
+            iv
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; KEY_LENGTH] {
+        PublicKey::from(&self.private_key).to_bytes()
+    }
+
+    pub fn iv(&self) -> [u8; IV_LENGTH] {
+        self.iv
+    }
+
+    /// Returns an NTCP2 address that publishes the keys for a listener at `address`.
+    pub fn router_address(&self, cost: u8, address: SocketAddr) -> RouterAddress {
+        let mut options = Mapping::new();
+        let entries = [
+            ("host", address.ip().to_string()),
+            ("port", address.port().to_string()),
+            ("s", self.public_key().to_i2p_base64()),
+            ("i", self.iv.to_i2p_base64()),
+            ("v", PROTOCOL_VERSION.to_string())
+        ];
+        for &(key, ref value) in &entries {
+            options.insert(key, value).expect("NTCP2 address options are short");
+        }
+
+        RouterAddress::new(cost, NTCP2_STYLE, options).expect("NTCP2_STYLE is a valid transport style")
+    }
+}
+
+/// A `ReplayCache` remembers the ephemeral keys of recent SessionRequests, so that a
+/// responder refuses one that is sent again. It is shared by all inbound handshakes.
+pub struct ReplayCache {
+    keys: Mutex<DecayingBloomFilter>
+}
+
+impl ReplayCache {
+    pub fn new(now: I2pDate) -> ReplayCache {
+        ReplayCache {
+            keys: Mutex::new(DecayingBloomFilter::new(REPLAY_FILTER_BITS, REPLAY_PERIOD_MILLISECONDS, now))
+        }
+    }
+
+    /// Adds an ephemeral key. Returns `true` if it was, probably, seen before.
+    pub fn add(&self, key: &[u8; KEY_LENGTH], now: I2pDate) -> bool {
+        self.keys.lock().unwrap().add(key, now)
+    }
+}
+
+fn random_secret() -> StaticSecret {
+    let mut key = [0x00; KEY_LENGTH];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    StaticSecret::from(key)
+}
+
+fn random_padding() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut padding = vec![0x00; rng.gen_range(0, PADDING_LENGTH + 1)];
+    rng.fill_bytes(&mut padding);
+
+    padding
+}
+
+fn diffie_hellman(private_key: &StaticSecret, public_key: &[u8; KEY_LENGTH]) -> [u8; KEY_LENGTH] {
+    private_key.diffie_hellman(&PublicKey::from(*public_key)).to_bytes()
+}
+
+fn decode_base64<T>(text: &str) -> Option<T> where T: Default + AsMut<[u8]> {
+    let bytes = text.from_i2p_base64()?;
+    let mut value = T::default();
+    if bytes.len() != value.as_mut().len() {
+        return None;
+    }
+    value.as_mut().copy_from_slice(&bytes);
+
+    Some(value)
+}
+
+/// Returns the static key published in a router's NTCP2 address.
+fn static_key(router_info: &RouterInfo) -> Option<[u8; KEY_LENGTH]> {
+    decode_base64(router_info.address(NTCP2_STYLE)?.option("s")?)
+}
+
+/// Returns the IV published in a router's NTCP2 address. Routers that do not accept
+/// connections may leave it out.
+fn obfuscation_iv(router_info: &RouterInfo) -> Option<[u8; IV_LENGTH]> {
+    decode_base64(router_info.address(NTCP2_STYLE)?.option("i")?)
+}
+
+/// Encrypts an ephemeral key with AES-256-CBC, keyed by the responder's router hash.
+fn obfuscate(router_hash: &Hash256, iv: &[u8], key: &mut [u8; KEY_LENGTH]) {
+    Aes256CbcEncryptor::new(router_hash.as_ref().into(), iv.into())
+        .encrypt_padded_mut::<NoPadding>(key, KEY_LENGTH)
+        .expect("an X25519 key is a whole number of AES blocks");
+}
+
+fn deobfuscate(router_hash: &Hash256, iv: &[u8], key: &mut [u8; KEY_LENGTH]) {
+    Aes256CbcDecryptor::new(router_hash.as_ref().into(), iv.into())
+        .decrypt_padded_mut::<NoPadding>(key)
+        .expect("an X25519 key is a whole number of AES blocks");
+}
+
+/// The obfuscation of SessionCreated continues the CBC chain of SessionRequest: its
+/// IV is the last block of the obfuscated ephemeral key of the initiator.
+fn chained_iv(obfuscated_key: &[u8]) -> &[u8] {
+    &obfuscated_key[KEY_LENGTH - IV_LENGTH..KEY_LENGTH]
+}
+
+fn seconds(now: I2pDate) -> u32 {
+    (now.to_u64() / 1000) as u32
+}
+
+fn check_clock_skew(timestamp: u32, now: I2pDate) -> Result<(), Ntcp2Error> {
+    let now = now.to_u64() / 1000;
+    if (timestamp as u64).abs_diff(now) > MAX_CLOCK_SKEW {
+        return Err(Ntcp2Error::Handshake(TerminationReason::ClockSkew));
+    }
+
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 8) | (bytes[1] as usize)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+/// The keys of one direction of the data phase.
+struct DirectionKeys {
+    cipher: ChaChaCipher,
+    sip_keys: SipKeys
+}
+
+fn sip_keys(bytes: &[u8; 32]) -> SipKeys {
+    let mut keys = [0x00; 24];
+    keys.copy_from_slice(&bytes[..24]);
+
+    SipKeys::from_bytes(&keys)
+}
+
+/// Mixes the padding of SessionRequest and SessionCreated into the handshake hash.
+/// Empty padding is skipped.
+fn mix_padding(state: &mut SymmetricState, padding: &[u8]) {
+    if !padding.is_empty() {
+        state.mix_hash(padding);
+    }
+}
+
+/// Derives the cipher keys and the SipHash length keys of the data phase, from the
+/// initiator to the responder and back.
+fn split(state: &SymmetricState) -> (DirectionKeys, DirectionKeys) {
+    let temp_key = hmac_sha256(state.chaining_key(), &[]);
+    let key_ab = hmac_sha256(&temp_key, &[&[0x01]]);
+    let key_ba = hmac_sha256(&temp_key, &[&key_ab, &[0x02]]);
+
+    let ask_master = hmac_sha256(&temp_key, &[b"ask", &[0x01]]);
+    let temp_key = hmac_sha256(&ask_master, &[state.hash(), b"siphash"]);
+    let sip_master = hmac_sha256(&temp_key, &[&[0x01]]);
+    let temp_key = hmac_sha256(&sip_master, &[]);
+    let sip_ab = hmac_sha256(&temp_key, &[&[0x01]]);
+    let sip_ba = hmac_sha256(&temp_key, &[&sip_ab, &[0x02]]);
+
+    let initiator = DirectionKeys {
+        cipher: ChaChaCipher::new(key_ab),
+        sip_keys: sip_keys(&sip_ab)
+    };
+    let responder = DirectionKeys {
+        cipher: ChaChaCipher::new(key_ba),
+        sip_keys: sip_keys(&sip_ba)
+    };
+
+    (initiator, responder)
+}
+
+/// Performs the initiator side of the NTCP2 handshake with `peer` over `stream`, and
+/// returns the connection in its data phase. `keys` and `router_info` are ours; the
+/// RouterInfo is sent to the peer in SessionConfirmed.
+pub fn connect<S>(mut stream: S,
+                  keys: &StaticKeys,
+                  router_info: &RouterInfo,
+                  peer: &RouterInfo,
+                  now: I2pDate) -> Result<Connection<S, ChaChaCipher>, Ntcp2Error> where S: Read + Write {
+
+    let peer_static_key = static_key(peer).ok_or(Ntcp2Error::InvalidAddress)?;
+    let peer_iv = obfuscation_iv(peer).ok_or(Ntcp2Error::InvalidAddress)?;
+    let peer_hash = peer.identity().hash_sha256();
+
+    let payload = encode_blocks(&[
+        Block::RouterInfo { flood: false, router_info: router_info.to_bytes() },
+        Block::Padding(random_padding())
+    ]);
+    if STATIC_KEY_PART_LENGTH + payload.len() + MAC_LENGTH > MAX_FRAME_LENGTH {
+        return Err(Ntcp2Error::FrameTooLarge(payload.len() + MAC_LENGTH));
+    }
+
+    // SessionRequest: our obfuscated ephemeral key, then our options.
+    let mut state = SymmetricState::new(PROTOCOL_NAME, &peer_static_key);
+    let ephemeral_key = random_secret();
+    let ephemeral_public_key = PublicKey::from(&ephemeral_key).to_bytes();
+    state.mix_hash(&ephemeral_public_key);
+    state.mix_key(&diffie_hellman(&ephemeral_key, &peer_static_key));
+
+    let padding = random_padding();
+    let mut options = [0x00; OPTIONS_LENGTH];
+    options[0] = NETWORK_ID;
+    options[1] = PROTOCOL_VERSION;
+    options[2..4].copy_from_slice(&(padding.len() as u16).to_be_bytes());
+    options[4..6].copy_from_slice(&((payload.len() + MAC_LENGTH) as u16).to_be_bytes());
+    options[8..12].copy_from_slice(&seconds(now).to_be_bytes());
+
+    let mut obfuscated_key = ephemeral_public_key;
+    obfuscate(&peer_hash, &peer_iv, &mut obfuscated_key);
+    let mut request = obfuscated_key.to_vec();
+    request.extend_from_slice(&state.encrypt_and_hash(0, &options));
+    mix_padding(&mut state, &padding);
+    request.extend_from_slice(&padding);
+    stream.write_all(&request)?;
+    stream.flush()?;
+
+    // SessionCreated: the peer's ephemeral key and options.
+    let mut created = [0x00; MESSAGE_LENGTH];
+    stream.read_exact(&mut created)?;
+    let mut peer_ephemeral_key = [0x00; KEY_LENGTH];
+    peer_ephemeral_key.copy_from_slice(&created[..KEY_LENGTH]);
+    deobfuscate(&peer_hash, chained_iv(&obfuscated_key), &mut peer_ephemeral_key);
+    state.mix_hash(&peer_ephemeral_key);
+    state.mix_key(&diffie_hellman(&ephemeral_key, &peer_ephemeral_key));
+
+    let options = state.decrypt_and_hash(0, &created[KEY_LENGTH..])
+        .ok_or(Ntcp2Error::Handshake(TerminationReason::SessionCreatedError))?;
+    let padding_length = read_u16(&options[2..4]);
+    if padding_length > MAX_HANDSHAKE_PADDING {
+        return Err(Ntcp2Error::Handshake(TerminationReason::PaddingViolation));
+    }
+    check_clock_skew(read_u32(&options[8..12]), now)?;
+
+    let mut padding = vec![0x00; padding_length];
+    stream.read_exact(&mut padding)?;
+    mix_padding(&mut state, &padding);
+
+    // SessionConfirmed: our static key, then our RouterInfo.
+    let mut confirmed = state.encrypt_and_hash(1, &keys.public_key());
+    state.mix_key(&diffie_hellman(&keys.private_key, &peer_ephemeral_key));
+    confirmed.extend_from_slice(&state.encrypt_and_hash(0, &payload));
+    stream.write_all(&confirmed)?;
+    stream.flush()?;
+
+    let (send, receive) = split(&state);
+
+    Ok(Connection::new(stream, send.cipher, receive.cipher, send.sip_keys, receive.sip_keys))
+}
+
+/// Performs the responder side of the NTCP2 handshake over `stream`. `keys` and
+/// `router_info` are ours, and the peer must have obfuscated its ephemeral key with
+/// them. A SessionRequest whose ephemeral key is in `replays` is refused. Returns the connection in its data phase and the peer's RouterInfo, whose
+/// signature has been checked with `verifier` and whose static key matches the
+/// one the peer proved it holds.
+pub fn accept<S, V>(mut stream: S,
+                    keys: &StaticKeys,
+                    router_info: &RouterInfo,
+                    replays: &ReplayCache,
+                    verifier: &V,
+                    now: I2pDate) -> Result<(Connection<S, ChaChaCipher>, RouterInfo), Ntcp2Error>
+    where S: Read + Write, V: SignatureVerifier {
+
+    let router_hash = router_info.identity().hash_sha256();
+
+    // SessionRequest.
+    let mut request = [0x00; MESSAGE_LENGTH];
+    stream.read_exact(&mut request)?;
+    let mut peer_ephemeral_key = [0x00; KEY_LENGTH];
+    peer_ephemeral_key.copy_from_slice(&request[..KEY_LENGTH]);
+    deobfuscate(&router_hash, &keys.iv, &mut peer_ephemeral_key);
+
+    let mut state = SymmetricState::new(PROTOCOL_NAME, &keys.public_key());
+    state.mix_hash(&peer_ephemeral_key);
+    state.mix_key(&diffie_hellman(&keys.private_key, &peer_ephemeral_key));
+
+    let options = state.decrypt_and_hash(0, &request[KEY_LENGTH..])
+        .ok_or(Ntcp2Error::Handshake(TerminationReason::SessionRequestError))?;
+    if options[0] != NETWORK_ID || options[1] != PROTOCOL_VERSION {
+        return Err(Ntcp2Error::Handshake(TerminationReason::IncompatibleOptions));
+    }
+    let padding_length = read_u16(&options[2..4]);
+    if padding_length > MAX_HANDSHAKE_PADDING {
+        return Err(Ntcp2Error::Handshake(TerminationReason::PaddingViolation));
+    }
+    let confirmed_length = read_u16(&options[4..6]);
+    if confirmed_length <= MAC_LENGTH || STATIC_KEY_PART_LENGTH + confirmed_length > MAX_FRAME_LENGTH {
+        return Err(Ntcp2Error::Handshake(TerminationReason::SessionRequestError));
+    }
+    check_clock_skew(read_u32(&options[8..12]), now)?;
+    if replays.add(&peer_ephemeral_key, now) {
+        return Err(Ntcp2Error::Replay);
+    }
+
+    let mut padding = vec![0x00; padding_length];
+    stream.read_exact(&mut padding)?;
+    mix_padding(&mut state, &padding);
+
+    // SessionCreated.
+    let ephemeral_key = random_secret();
+    let ephemeral_public_key = PublicKey::from(&ephemeral_key).to_bytes();
+    state.mix_hash(&ephemeral_public_key);
+    state.mix_key(&diffie_hellman(&ephemeral_key, &peer_ephemeral_key));
+
+    let padding = random_padding();
+    let mut options = [0x00; OPTIONS_LENGTH];
+    options[2..4].copy_from_slice(&(padding.len() as u16).to_be_bytes());
+    options[8..12].copy_from_slice(&seconds(now).to_be_bytes());
+
+    let mut obfuscated_key = ephemeral_public_key;
+    obfuscate(&router_hash, chained_iv(&request), &mut obfuscated_key);
+    let mut created = obfuscated_key.to_vec();
+    created.extend_from_slice(&state.encrypt_and_hash(0, &options));
+    mix_padding(&mut state, &padding);
+    created.extend_from_slice(&padding);
+    stream.write_all(&created)?;
+    stream.flush()?;
+
+    // SessionConfirmed.
+    let mut confirmed = vec![0x00; STATIC_KEY_PART_LENGTH + confirmed_length];
+    stream.read_exact(&mut confirmed)?;
+    let peer_static_key = state.decrypt_and_hash(1, &confirmed[..STATIC_KEY_PART_LENGTH])
+        .ok_or(Ntcp2Error::Handshake(TerminationReason::SessionConfirmedError))?;
+    let mut peer_key = [0x00; KEY_LENGTH];
+    peer_key.copy_from_slice(&peer_static_key);
+    state.mix_key(&diffie_hellman(&ephemeral_key, &peer_key));
+
+    let payload = state.decrypt_and_hash(0, &confirmed[STATIC_KEY_PART_LENGTH..])
+        .ok_or(Ntcp2Error::Handshake(TerminationReason::SessionConfirmedError))?;
+    let peer = match decode_blocks(&payload) {
+        Ok(blocks) => match blocks.first() {
+            Some(Block::RouterInfo { router_info, .. }) => match RouterInfo::from_bytes(router_info) {
+                Some((peer, length)) if length == router_info.len() => peer,
+                _ => return Err(Ntcp2Error::Handshake(TerminationReason::SessionConfirmedError))
+            },
+            _ => return Err(Ntcp2Error::Handshake(TerminationReason::SessionConfirmedError))
+        },
+        Err(_) => return Err(Ntcp2Error::Handshake(TerminationReason::SessionConfirmedError))
+    };
+    if !peer.verify(verifier) {
+        return Err(Ntcp2Error::Handshake(TerminationReason::RouterInfoSignatureFailure));
+    }
+    if static_key(&peer) != Some(peer_key) {
+        return Err(Ntcp2Error::Handshake(TerminationReason::StaticKeyMismatch));
+    }
+
+    let (receive, send) = split(&state);
+    let connection = Connection::new(stream, send.cipher, receive.cipher, send.sip_keys, receive.sip_keys);
+
+    Ok((connection, peer))
+}
@@ -0,0 +1,70 @@
+use transport::ntcp2::siphash::siphash24;
+
+
+/// The SipHash keys and initial IV for obfuscating frame lengths in one direction,
+/// derived from the handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SipKeys {
+    pub k1: u64,
+    pub k2: u64,
+    pub iv: u64
+}
+
+impl SipKeys {
+    /// Reads the keys from the first 24 bytes of the key derivation output: `k1`, `k2`
+    /// and the IV, each as a little endian integer.
+    pub fn from_bytes(bytes: &[u8; 24]) -> SipKeys {
+        let mut words = [0u64; 3];
+        for (i, word) in words.iter_mut().enumerate() {
+            let mut data = [0x00; 8];
+            data.copy_from_slice(&bytes[8 * i..8 * (i + 1)]);
+            *word = u64::from_le_bytes(data);
+        }
+
+        SipKeys {
+            k1: words[0],
+            k2: words[1],
+            iv: words[2]
+        }
+    }
+}
+
+/// A `LengthObfuscator` masks the two byte length of each frame. Every frame advances
+/// the IV by one SipHash-2-4 round and XORs the length with the first two bytes of the
+/// new IV.
+#[derive(Clone, Debug)]
+pub struct LengthObfuscator {
+    keys: SipKeys,
+    iv: u64
+}
+
+impl LengthObfuscator {
+    pub fn new(keys: SipKeys) -> LengthObfuscator {
+        LengthObfuscator {
+            keys,
+            iv: keys.iv
+        }
+    }
+
+    fn next_mask(&mut self) -> [u8; 2] {
+        self.iv = siphash24(self.keys.k1, self.keys.k2, &self.iv.to_le_bytes());
+        let iv = self.iv.to_le_bytes();
+
+        [iv[0], iv[1]]
+    }
+
+    /// Masks the length of the next outgoing frame.
+    pub fn obfuscate(&mut self, length: u16) -> [u8; 2] {
+        let mask = self.next_mask();
+        let length = length.to_be_bytes();
+
+        [length[0] ^ mask[0], length[1] ^ mask[1]]
+    }
+
+    /// Unmasks the length of the next incoming frame.
+    pub fn deobfuscate(&mut self, bytes: [u8; 2]) -> u16 {
+        let mask = self.next_mask();
+
+        u16::from_be_bytes([bytes[0] ^ mask[0], bytes[1] ^ mask[1]])
+    }
+}
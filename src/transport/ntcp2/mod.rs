@@ -0,0 +1,19 @@
+pub use self::error::Ntcp2Error;
+pub use self::block::{Block, Options, TerminationReason};
+pub use self::block::{encode_blocks, decode_blocks};
+pub use self::length::{LengthObfuscator, SipKeys};
+pub use self::connection::{Connection, ConnectionStats, CipherState};
+pub use self::connection::{MAC_LENGTH, MAX_FRAME_LENGTH, MAX_PAYLOAD_LENGTH};
+pub use self::siphash::siphash24;
+pub use self::cipher::ChaChaCipher;
+pub use self::handshake::{StaticKeys, ReplayCache, connect, accept};
+pub use self::handshake::{NTCP2_STYLE, NETWORK_ID, MAX_CLOCK_SKEW, MAX_HANDSHAKE_PADDING};
+
+
+mod error;
+mod block;
+mod length;
+mod connection;
+mod siphash;
+mod cipher;
+mod handshake;
@@ -0,0 +1,60 @@
+/// Computes SipHash-2-4 of `data` with the 128-bit key `(k0, k1)`.
+pub fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v0 = k0 ^ 0x736f_6d65_7073_6575;
+    let mut v1 = k1 ^ 0x646f_7261_6e64_6f6d;
+    let mut v2 = k0 ^ 0x6c79_6765_6e65_7261;
+    let mut v3 = k1 ^ 0x7465_6462_7974_6573;
+
+    macro_rules! sipround {
+        () => {
+            v0 = v0.wrapping_add(v1); v1 = v1.rotate_left(13); v1 ^= v0; v0 = v0.rotate_left(32);
+            v2 = v2.wrapping_add(v3); v3 = v3.rotate_left(16); v3 ^= v2;
+            v0 = v0.wrapping_add(v3); v3 = v3.rotate_left(21); v3 ^= v0;
+            v2 = v2.wrapping_add(v1); v1 = v1.rotate_left(17); v1 ^= v2; v2 = v2.rotate_left(32);
+        }
+    }
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0x00; 8];
+        word.copy_from_slice(chunk);
+        let m = u64::from_le_bytes(word);
+        v3 ^= m;
+        sipround!();
+        sipround!();
+        v0 ^= m;
+    }
+
+    let mut last = (data.len() as u64) << 56;
+    for (i, byte) in chunks.remainder().iter().enumerate() {
+        last |= (*byte as u64) << (8 * i);
+    }
+    v3 ^= last;
+    sipround!();
+    sipround!();
+    v0 ^= last;
+
+    v2 ^= 0xFF;
+    sipround!();
+    sipround!();
+    sipround!();
+    sipround!();
+
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::siphash24;
+
+
+    #[test]
+    fn test_siphash_should_match_the_reference_test_vector() {
+        let k0 = u64::from_le_bytes([0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]);
+        let k1 = u64::from_le_bytes([0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F]);
+        let message: Vec<u8> = (0..15).collect();
+
+        assert_eq!(siphash24(k0, k1, &message), 0xa129_ca61_49be_45e5);
+    }
+}
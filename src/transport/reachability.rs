@@ -0,0 +1,101 @@
+/// How reachable the router is from the rest of the network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reachability {
+    /// Not tested yet, or the tests were inconclusive.
+    Unknown,
+    /// Other routers can contact us directly.
+    Ok,
+    /// Other routers can only reach us after we have contacted them.
+    Firewalled,
+    /// Our NAT maps each destination to a different port, so the address other
+    /// routers see for us depends on who they are.
+    SymmetricNat
+}
+
+/// What to publish in our `RouterAddress` for a transport.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressPublication {
+    /// Our IP address and port, so that routers connect to us directly.
+    Direct,
+    /// Introducers, so that routers ask them to relay the first contact.
+    Introducers
+}
+
+impl Reachability {
+    /// Returns what to publish in this state, or `None` to keep what is published.
+    pub fn publication(&self) -> Option<AddressPublication> {
+        match *self {
+            Reachability::Unknown => None,
+            Reachability::Ok => Some(AddressPublication::Direct),
+            Reachability::Firewalled | Reachability::SymmetricNat => Some(AddressPublication::Introducers)
+        }
+    }
+}
+
+/// Two agreeing test results are needed by default to change a known state.
+const DEFAULT_CONFIRMATIONS: usize = 2;
+
+/// A `ReachabilityTracker` turns peer test results into our reachability. The first
+/// conclusive result is taken as it is; after that a different state must be seen
+/// in `confirmations` consecutive results, so that a single misbehaving peer cannot
+/// flip what we publish.
+#[derive(Clone, Debug)]
+pub struct ReachabilityTracker {
+    state: Reachability,
+    candidate: Option<(Reachability, usize)>,
+    confirmations: usize
+}
+
+impl ReachabilityTracker {
+    pub fn new() -> ReachabilityTracker {
+        ReachabilityTracker {
+            state: Reachability::Unknown,
+            candidate: None,
+            confirmations: DEFAULT_CONFIRMATIONS
+        }
+    }
+
+    /// Sets the number of consecutive results needed to change a known state.
+    pub fn set_confirmations(&mut self, confirmations: usize) {
+        self.confirmations = confirmations;
+    }
+
+    pub fn state(&self) -> Reachability {
+        self.state
+    }
+
+    /// Returns what to publish in the current state.
+    pub fn publication(&self) -> Option<AddressPublication> {
+        self.state.publication()
+    }
+
+    /// Records the result of a peer test. Returns `true` if the state changed.
+    pub fn record(&mut self, result: Reachability) -> bool {
+        if result == Reachability::Unknown {
+            return false;
+        }
+        if result == self.state {
+            self.candidate = None;
+            return false;
+        }
+
+        let seen = match self.candidate {
+            Some((candidate, seen)) if candidate == result => seen + 1,
+            _ => 1
+        };
+        if self.state == Reachability::Unknown || seen >= self.confirmations {
+            self.state = result;
+            self.candidate = None;
+            return true;
+        }
+        self.candidate = Some((result, seen));
+
+        false
+    }
+}
+
+impl Default for ReachabilityTracker {
+    fn default() -> ReachabilityTracker {
+        ReachabilityTracker::new()
+    }
+}
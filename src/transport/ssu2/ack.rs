@@ -0,0 +1,128 @@
+use std::cmp;
+use std::collections::BTreeSet;
+
+
+/// The largest number of NACK/ACK range pairs we put in an ACK block.
+pub const MAX_ACK_RANGES: usize = 32;
+
+/// Packet numbers this far below the highest one received are no longer tracked.
+const ACK_WINDOW: u32 = 1024;
+
+/// An `Ack` is the content of an ACK block. It acknowledges `through`, the `count`
+/// packets directly below it, and then walks down through the ranges, each a number
+/// of packets not received followed by a number of packets received.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ack {
+    pub through: u32,
+    pub count: u8,
+    pub ranges: Vec<(u8, u8)>
+}
+
+impl Ack {
+    pub fn new(through: u32, count: u8, ranges: Vec<(u8, u8)>) -> Ack {
+        Ack {
+            through,
+            count,
+            ranges
+        }
+    }
+
+    /// Returns the acknowledged packet numbers, highest first.
+    pub fn acked(&self) -> Vec<u32> {
+        let mut acked = Vec::new();
+        let mut next = self.through as i64;
+        let runs = Some((0, self.count as u16 + 1)).into_iter()
+            .chain(self.ranges.iter().map(|&(nacks, acks)| (nacks, acks as u16)));
+
+        for (nacks, acks) in runs {
+            next -= nacks as i64;
+            for _ in 0..acks {
+                if next < 0 {
+                    return acked;
+                }
+                acked.push(next as u32);
+                next -= 1;
+            }
+        }
+
+        acked
+    }
+
+    /// Determines whether the packet number is acknowledged.
+    pub fn contains(&self, packet_number: u32) -> bool {
+        self.acked().contains(&packet_number)
+    }
+}
+
+/// An `AckTracker` records the packet numbers received on a session and builds the
+/// ACK blocks that acknowledge them.
+#[derive(Clone, Debug, Default)]
+pub struct AckTracker {
+    received: BTreeSet<u32>
+}
+
+impl AckTracker {
+    pub fn new() -> AckTracker {
+        AckTracker {
+            received: BTreeSet::new()
+        }
+    }
+
+    /// Records a received packet number. Returns `false` for a duplicate or for a
+    /// packet too old to track, either of which should be dropped.
+    pub fn receive(&mut self, packet_number: u32) -> bool {
+        if !self.received.insert(packet_number) {
+            return false;
+        }
+
+        let highest = *self.received.iter().next_back().unwrap();
+        if highest >= ACK_WINDOW {
+            let floor = highest - ACK_WINDOW + 1;
+            self.received = self.received.split_off(&floor);
+            return packet_number >= floor;
+        }
+
+        true
+    }
+
+    /// Builds an ACK for the packets received, or `None` if nothing was received.
+    /// When there are more gaps than `MAX_ACK_RANGES` the oldest are left out.
+    pub fn ack(&self) -> Option<Ack> {
+        let mut numbers = self.received.iter().rev();
+        let through = *numbers.next()?;
+
+        // Runs of missing then received packets, walking down from `through`.
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        let mut previous = through;
+        for &number in numbers {
+            let missing = previous - number - 1;
+            match runs.last_mut() {
+                Some(run) if missing == 0 => run.1 += 1,
+                _ => runs.push((missing, 1))
+            }
+            previous = number;
+        }
+
+        let mut count = 0;
+        let mut ranges = Vec::new();
+        for (i, (mut nacks, mut acks)) in runs.into_iter().enumerate() {
+            if i == 0 && nacks == 0 {
+                count = cmp::min(acks, 255);
+                acks -= count;
+            }
+            while nacks > 255 {
+                ranges.push((255, 0));
+                nacks -= 255;
+            }
+            while nacks > 0 || acks > 0 {
+                let acked = cmp::min(acks, 255);
+                ranges.push((nacks as u8, acked as u8));
+                nacks = 0;
+                acks -= acked;
+            }
+        }
+        ranges.truncate(MAX_ACK_RANGES);
+
+        Some(Ack::new(through, count as u8, ranges))
+    }
+}
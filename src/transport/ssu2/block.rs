@@ -0,0 +1,339 @@
+use common::{I2pDate, I2pInt64};
+use i2np::{I2npMessage, MessageType};
+use transport::ntcp2::TerminationReason;
+use std::net::SocketAddr;
+use transport::ssu2::ack::Ack;
+use transport::ssu2::endpoint::{endpoint_len, encode_endpoint, decode_endpoint};
+use transport::ssu2::error::Ssu2Error;
+use transport::ssu2::peer_test::PeerTest;
+use transport::ssu2::relay::{RelayRequest, RelayResponse, RelayIntro};
+
+
+/// Each block starts with a one byte type and a two byte big endian size.
+pub const BLOCK_HEADER_LENGTH: usize = 3;
+
+/// The short I2NP header of I2NP and First Fragment blocks: the type, the message
+/// ID and the expiration in seconds.
+pub const I2NP_SHORT_HEADER_LENGTH: usize = 9;
+
+/// The header of a Follow-on Fragment block: the fragment byte and the message ID.
+pub const FOLLOW_ON_HEADER_LENGTH: usize = 5;
+
+const DATE_TIME_LENGTH: usize = 4;
+const TERMINATION_LENGTH: usize = 9;
+const ACK_LENGTH: usize = 5;
+
+/// A RouterInfo block starts with a flag byte and a fragment byte. We only send
+/// unfragmented, uncompressed RouterInfos, so the fragment byte is always 0x01:
+/// fragment 0 of 1.
+const ROUTER_INFO_HEADER_LENGTH: usize = 2;
+const ROUTER_INFO_FLAG_FLOOD: u8 = 0x01;
+const ROUTER_INFO_FLAG_GZIP: u8 = 0x02;
+const ROUTER_INFO_SINGLE_FRAGMENT: u8 = 0x01;
+
+const BLOCK_DATE_TIME: u8 = 0;
+const BLOCK_ROUTER_INFO: u8 = 2;
+const BLOCK_I2NP: u8 = 3;
+const BLOCK_FIRST_FRAGMENT: u8 = 4;
+const BLOCK_FOLLOW_ON_FRAGMENT: u8 = 5;
+const BLOCK_TERMINATION: u8 = 6;
+const BLOCK_RELAY_REQUEST: u8 = 7;
+const BLOCK_RELAY_RESPONSE: u8 = 8;
+const BLOCK_RELAY_INTRO: u8 = 9;
+const BLOCK_PEER_TEST: u8 = 10;
+const BLOCK_ACK: u8 = 12;
+const BLOCK_ADDRESS: u8 = 13;
+const BLOCK_RELAY_TAG_REQUEST: u8 = 15;
+const BLOCK_RELAY_TAG: u8 = 16;
+const BLOCK_PATH_CHALLENGE: u8 = 18;
+const BLOCK_PATH_RESPONSE: u8 = 19;
+const BLOCK_PADDING: u8 = 254;
+
+/// A `Block` is one unit of the payload of an SSU2 data phase packet. Blocks this
+/// module does not interpret are kept as `Unknown`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// The current time in seconds since the UNIX epoch.
+    DateTime(u32),
+    /// A serialized RouterInfo, and whether the receiver should flood it.
+    RouterInfo { flood: bool, router_info: Vec<u8> },
+    /// A complete I2NP message.
+    I2np(I2npMessage),
+    /// The first fragment of an I2NP message, with the message's short header.
+    FirstFragment { message_type: MessageType, message_id: u32, expiration: I2pDate, data: Vec<u8> },
+    /// A later fragment of an I2NP message. Fragments are numbered from 1.
+    FollowOnFragment { fragment: u8, last: bool, message_id: u32, data: Vec<u8> },
+    /// The number of valid packets received, and why the session is closing.
+    Termination { valid_frames: u64, reason: TerminationReason },
+    RelayRequest(RelayRequest),
+    RelayResponse(RelayResponse),
+    RelayIntro(RelayIntro),
+    PeerTest(PeerTest),
+    Ack(Ack),
+    /// The address the sender sees us at.
+    Address(SocketAddr),
+    RelayTagRequest,
+    RelayTag(u32),
+    PathChallenge(Vec<u8>),
+    PathResponse(Vec<u8>),
+    /// Random padding. It must be the last block of a packet.
+    Padding(Vec<u8>),
+    Unknown(u8, Vec<u8>)
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    ((bytes[0] as u16) << 8) | (bytes[1] as u16)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+/// Converts an I2NP expiration to the whole seconds of the short header, rounding
+/// to the nearest second.
+fn expiration_seconds(expiration: I2pDate) -> u32 {
+    ((expiration.to_u64() + 500) / 1000) as u32
+}
+
+fn push_short_header(buf: &mut Vec<u8>, message_type: MessageType, message_id: u32, expiration: I2pDate) {
+    buf.push(message_type.code());
+    buf.extend_from_slice(&message_id.to_be_bytes());
+    buf.extend_from_slice(&expiration_seconds(expiration).to_be_bytes());
+}
+
+fn read_short_header(data: &[u8]) -> Result<(MessageType, u32, I2pDate), Ssu2Error> {
+    let seconds = read_u32(&data[5..9]) as u64;
+    match I2pDate::new(I2pInt64::new(seconds * 1000)) {
+        Ok(expiration) => Ok((MessageType::from_code(data[0]), read_u32(&data[1..5]), expiration)),
+        Err(_) => Err(Ssu2Error::InvalidBlock)
+    }
+}
+
+impl Block {
+    /// Returns the length of the block in bytes, including its header.
+    pub fn encoded_len(&self) -> usize {
+        BLOCK_HEADER_LENGTH + match *self {
+            Block::DateTime(_) => DATE_TIME_LENGTH,
+            Block::RouterInfo { ref router_info, .. } => ROUTER_INFO_HEADER_LENGTH + router_info.len(),
+            Block::I2np(ref message) => I2NP_SHORT_HEADER_LENGTH + message.payload.len(),
+            Block::FirstFragment { ref data, .. } => I2NP_SHORT_HEADER_LENGTH + data.len(),
+            Block::FollowOnFragment { ref data, .. } => FOLLOW_ON_HEADER_LENGTH + data.len(),
+            Block::Termination { .. } => TERMINATION_LENGTH,
+            Block::RelayRequest(ref request) => request.encoded_len(),
+            Block::RelayResponse(ref response) => response.encoded_len(),
+            Block::RelayIntro(ref intro) => intro.encoded_len(),
+            Block::PeerTest(ref test) => test.encoded_len(),
+            Block::RelayTagRequest => 0,
+            Block::RelayTag(_) => 4,
+            Block::Ack(ref ack) => ACK_LENGTH + 2 * ack.ranges.len(),
+            Block::Address(address) => endpoint_len(Some(address)) - 1,
+            Block::PathChallenge(ref data) | Block::PathResponse(ref data) => data.len(),
+            Block::Padding(ref padding) => padding.len(),
+            Block::Unknown(_, ref data) => data.len()
+        }
+    }
+
+    fn type_code(&self) -> u8 {
+        match *self {
+            Block::DateTime(_) => BLOCK_DATE_TIME,
+            Block::RouterInfo { .. } => BLOCK_ROUTER_INFO,
+            Block::I2np(_) => BLOCK_I2NP,
+            Block::FirstFragment { .. } => BLOCK_FIRST_FRAGMENT,
+            Block::FollowOnFragment { .. } => BLOCK_FOLLOW_ON_FRAGMENT,
+            Block::Termination { .. } => BLOCK_TERMINATION,
+            Block::RelayRequest(_) => BLOCK_RELAY_REQUEST,
+            Block::RelayResponse(_) => BLOCK_RELAY_RESPONSE,
+            Block::RelayIntro(_) => BLOCK_RELAY_INTRO,
+            Block::PeerTest(_) => BLOCK_PEER_TEST,
+            Block::RelayTagRequest => BLOCK_RELAY_TAG_REQUEST,
+            Block::RelayTag(_) => BLOCK_RELAY_TAG,
+            Block::Ack(_) => BLOCK_ACK,
+            Block::Address(_) => BLOCK_ADDRESS,
+            Block::PathChallenge(_) => BLOCK_PATH_CHALLENGE,
+            Block::PathResponse(_) => BLOCK_PATH_RESPONSE,
+            Block::Padding(_) => BLOCK_PADDING,
+            Block::Unknown(code, _) => code
+        }
+    }
+
+    /// Appends the encoded block to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.type_code());
+        buf.extend_from_slice(&((self.encoded_len() - BLOCK_HEADER_LENGTH) as u16).to_be_bytes());
+
+        match *self {
+            Block::DateTime(timestamp) => {
+                buf.extend_from_slice(&timestamp.to_be_bytes());
+            }
+            Block::RouterInfo { flood, ref router_info } => {
+                buf.push(if flood { ROUTER_INFO_FLAG_FLOOD } else { 0x00 });
+                buf.push(ROUTER_INFO_SINGLE_FRAGMENT);
+                buf.extend_from_slice(router_info);
+            }
+            Block::I2np(ref message) => {
+                push_short_header(buf, message.message_type, message.message_id, message.expiration);
+                buf.extend_from_slice(&message.payload);
+            }
+            Block::FirstFragment { message_type, message_id, expiration, ref data } => {
+                push_short_header(buf, message_type, message_id, expiration);
+                buf.extend_from_slice(data);
+            }
+            Block::FollowOnFragment { fragment, last, message_id, ref data } => {
+                buf.push((fragment << 1) | (last as u8));
+                buf.extend_from_slice(&message_id.to_be_bytes());
+                buf.extend_from_slice(data);
+            }
+            Block::Termination { valid_frames, reason } => {
+                buf.extend_from_slice(&valid_frames.to_be_bytes());
+                buf.push(reason.code());
+            }
+            Block::RelayRequest(ref request) => {
+                request.encode(buf);
+            }
+            Block::RelayResponse(ref response) => {
+                response.encode(buf);
+            }
+            Block::RelayIntro(ref intro) => {
+                intro.encode(buf);
+            }
+            Block::PeerTest(ref test) => {
+                test.encode(buf);
+            }
+            Block::RelayTagRequest => {}
+            Block::RelayTag(tag) => {
+                buf.extend_from_slice(&tag.to_be_bytes());
+            }
+            Block::Ack(ref ack) => {
+                buf.extend_from_slice(&ack.through.to_be_bytes());
+                buf.push(ack.count);
+                for &(nacks, acks) in ack.ranges.iter() {
+                    buf.extend_from_slice(&[nacks, acks]);
+                }
+            }
+            Block::Address(address) => {
+                let mut endpoint = Vec::with_capacity(endpoint_len(Some(address)));
+                encode_endpoint(&mut endpoint, Some(address));
+                buf.extend_from_slice(&endpoint[1..]);
+            }
+            Block::PathChallenge(ref data) | Block::PathResponse(ref data) => {
+                buf.extend_from_slice(data);
+            }
+            Block::Padding(ref padding) => {
+                buf.extend_from_slice(padding);
+            }
+            Block::Unknown(_, ref data) => {
+                buf.extend_from_slice(data);
+            }
+        }
+    }
+
+    fn decode(code: u8, data: &[u8]) -> Result<Block, Ssu2Error> {
+        let block = match code {
+            BLOCK_DATE_TIME if data.len() == DATE_TIME_LENGTH => {
+                Block::DateTime(read_u32(data))
+            }
+            BLOCK_ROUTER_INFO if data.len() > ROUTER_INFO_HEADER_LENGTH
+                && data[0] & ROUTER_INFO_FLAG_GZIP == 0
+                && data[1] == ROUTER_INFO_SINGLE_FRAGMENT => {
+                Block::RouterInfo {
+                    flood: data[0] & ROUTER_INFO_FLAG_FLOOD != 0,
+                    router_info: data[ROUTER_INFO_HEADER_LENGTH..].to_vec()
+                }
+            }
+            BLOCK_I2NP if data.len() >= I2NP_SHORT_HEADER_LENGTH => {
+                let (message_type, message_id, expiration) = read_short_header(data)?;
+                let payload = data[I2NP_SHORT_HEADER_LENGTH..].to_vec();
+                Block::I2np(I2npMessage::new(message_type, message_id, expiration, payload))
+            }
+            BLOCK_FIRST_FRAGMENT if data.len() >= I2NP_SHORT_HEADER_LENGTH => {
+                let (message_type, message_id, expiration) = read_short_header(data)?;
+                Block::FirstFragment {
+                    message_type,
+                    message_id,
+                    expiration,
+                    data: data[I2NP_SHORT_HEADER_LENGTH..].to_vec()
+                }
+            }
+            BLOCK_FOLLOW_ON_FRAGMENT if data.len() >= FOLLOW_ON_HEADER_LENGTH && data[0] >> 1 != 0 => {
+                Block::FollowOnFragment {
+                    fragment: data[0] >> 1,
+                    last: data[0] & 0x01 != 0,
+                    message_id: read_u32(&data[1..5]),
+                    data: data[FOLLOW_ON_HEADER_LENGTH..].to_vec()
+                }
+            }
+            BLOCK_TERMINATION if data.len() >= TERMINATION_LENGTH => {
+                let valid_frames = data[0..8].iter().fold(0, |value, byte| (value << 8) | (*byte as u64));
+                Block::Termination {
+                    valid_frames,
+                    reason: TerminationReason::from_code(data[8])
+                }
+            }
+            BLOCK_RELAY_REQUEST => Block::RelayRequest(RelayRequest::decode(data)?),
+            BLOCK_RELAY_RESPONSE => Block::RelayResponse(RelayResponse::decode(data)?),
+            BLOCK_RELAY_INTRO => Block::RelayIntro(RelayIntro::decode(data)?),
+            BLOCK_PEER_TEST => Block::PeerTest(PeerTest::decode(data)?),
+            BLOCK_RELAY_TAG_REQUEST => Block::RelayTagRequest,
+            BLOCK_RELAY_TAG if data.len() == 4 => Block::RelayTag(read_u32(data)),
+            BLOCK_ACK if data.len() >= ACK_LENGTH && (data.len() - ACK_LENGTH) & 1 == 0 => {
+                let ranges = data[ACK_LENGTH..].chunks(2).map(|range| (range[0], range[1])).collect();
+                Block::Ack(Ack::new(read_u32(&data[0..4]), data[4], ranges))
+            }
+            BLOCK_ADDRESS if data.len() == 6 || data.len() == 18 => {
+                let mut endpoint = vec![data.len() as u8];
+                endpoint.extend_from_slice(data);
+                match decode_endpoint(&endpoint)? {
+                    (Some(address), _) => Block::Address(address),
+                    (None, _) => return Err(Ssu2Error::InvalidBlock)
+                }
+            }
+            BLOCK_PATH_CHALLENGE => Block::PathChallenge(data.to_vec()),
+            BLOCK_PATH_RESPONSE => Block::PathResponse(data.to_vec()),
+            BLOCK_PADDING => Block::Padding(data.to_vec()),
+            BLOCK_DATE_TIME | BLOCK_ROUTER_INFO | BLOCK_I2NP | BLOCK_FIRST_FRAGMENT | BLOCK_FOLLOW_ON_FRAGMENT
+                | BLOCK_TERMINATION | BLOCK_ACK | BLOCK_ADDRESS | BLOCK_RELAY_TAG => {
+                return Err(Ssu2Error::InvalidBlock);
+            }
+            _ => Block::Unknown(code, data.to_vec())
+        };
+
+        Ok(block)
+    }
+}
+
+/// Encodes a sequence of blocks into a packet payload.
+pub fn encode_blocks(blocks: &[Block]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(blocks.iter().map(|block| block.encoded_len()).sum());
+    for block in blocks {
+        block.encode(&mut payload);
+    }
+
+    payload
+}
+
+/// Decodes a packet payload into its blocks. Padding must be the last block.
+pub fn decode_blocks(payload: &[u8]) -> Result<Vec<Block>, Ssu2Error> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+
+    while offset < payload.len() {
+        if let Some(&Block::Padding(_)) = blocks.last() {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+        if payload.len() - offset < BLOCK_HEADER_LENGTH {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+
+        let code = payload[offset];
+        let size = read_u16(&payload[offset + 1..offset + 3]) as usize;
+        let start = offset + BLOCK_HEADER_LENGTH;
+        if payload.len() - start < size {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+
+        blocks.push(Block::decode(code, &payload[start..start + size])?);
+        offset = start + size;
+    }
+
+    Ok(blocks)
+}
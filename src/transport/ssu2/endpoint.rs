@@ -0,0 +1,55 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use transport::ssu2::error::Ssu2Error;
+
+
+/// Returns the length of an encoded endpoint, including its size byte.
+pub fn endpoint_len(address: Option<SocketAddr>) -> usize {
+    1 + match address {
+        Some(SocketAddr::V4(_)) => 6,
+        Some(SocketAddr::V6(_)) => 18,
+        None => 0
+    }
+}
+
+/// Appends an endpoint as used by the peer test and relay blocks: a size byte of 0,
+/// 6 or 18, then the port and the IPv4 or IPv6 address.
+pub fn encode_endpoint(buf: &mut Vec<u8>, address: Option<SocketAddr>) {
+    buf.push((endpoint_len(address) - 1) as u8);
+    if let Some(address) = address {
+        buf.extend_from_slice(&address.port().to_be_bytes());
+        match address.ip() {
+            IpAddr::V4(ip) => buf.extend_from_slice(&ip.octets()),
+            IpAddr::V6(ip) => buf.extend_from_slice(&ip.octets())
+        }
+    }
+}
+
+/// Reads an endpoint from the start of `data`. Returns the endpoint and the number
+/// of bytes it takes.
+pub fn decode_endpoint(data: &[u8]) -> Result<(Option<SocketAddr>, usize), Ssu2Error> {
+    let size = match data.first() {
+        Some(&size) => size as usize,
+        None => return Err(Ssu2Error::InvalidBlock)
+    };
+    let endpoint = match data.get(1..1 + size) {
+        Some(endpoint) => endpoint,
+        None => return Err(Ssu2Error::InvalidBlock)
+    };
+
+    let port = || ((endpoint[0] as u16) << 8) | (endpoint[1] as u16);
+    let address = match size {
+        0 => None,
+        6 => {
+            let ip = Ipv4Addr::new(endpoint[2], endpoint[3], endpoint[4], endpoint[5]);
+            Some(SocketAddr::new(IpAddr::V4(ip), port()))
+        }
+        18 => {
+            let mut octets = [0x00; 16];
+            octets.copy_from_slice(&endpoint[2..18]);
+            Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port()))
+        }
+        _ => return Err(Ssu2Error::InvalidBlock)
+    };
+
+    Ok((address, 1 + size))
+}
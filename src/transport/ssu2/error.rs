@@ -0,0 +1,77 @@
+use std::error;
+use std::fmt;
+use std::io;
+use transport::ntcp2::TerminationReason;
+
+
+#[derive(Debug)]
+pub enum Ssu2Error {
+    Io(io::Error),
+    /// A packet payload does not follow the block format.
+    InvalidBlock,
+    /// A message of this many bytes needs more fragments than SSU2 allows.
+    MessageTooLarge(usize),
+    /// A packet is too short, fails authentication or belongs to no session.
+    InvalidPacket,
+    /// The handshake failed, for the reason that a Termination block would carry.
+    Handshake(TerminationReason),
+    /// The peer's RouterInfo has no SSU2 address with a static key and intro key.
+    InvalidAddress,
+    /// There is no session with the peer.
+    NoSession,
+}
+
+impl fmt::Display for Ssu2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Ssu2Error::Io(ref err) => {
+                writeln!(f, "Error: An I/O error occurred on an SSU2 socket: {}", err)
+            }
+            Ssu2Error::InvalidBlock => {
+                writeln!(f, "Error: An SSU2 packet contains an invalid block.")
+            }
+            Ssu2Error::MessageTooLarge(length) => {
+                writeln!(f, "Error: An I2NP message of {} bytes is too large to fragment.", length)
+            }
+            Ssu2Error::InvalidPacket => {
+                writeln!(f, "Error: An SSU2 packet is invalid or belongs to no session.")
+            }
+            Ssu2Error::Handshake(reason) => {
+                writeln!(f, "Error: The SSU2 handshake failed: {:?}.", reason)
+            }
+            Ssu2Error::InvalidAddress => {
+                writeln!(f, "Error: The router has no usable SSU2 address.")
+            }
+            Ssu2Error::NoSession => {
+                writeln!(f, "Error: There is no SSU2 session with the router.")
+            }
+        }
+    }
+}
+
+impl error::Error for Ssu2Error {
+    fn description(&self) -> &str {
+        match *self {
+            Ssu2Error::Io(_) => "An I/O error occurred on an SSU2 socket.",
+            Ssu2Error::InvalidBlock => "An SSU2 packet contains an invalid block.",
+            Ssu2Error::MessageTooLarge(_) => "An I2NP message is too large to fragment.",
+            Ssu2Error::InvalidPacket => "An SSU2 packet is invalid or belongs to no session.",
+            Ssu2Error::Handshake(_) => "The SSU2 handshake failed.",
+            Ssu2Error::InvalidAddress => "The router has no usable SSU2 address.",
+            Ssu2Error::NoSession => "There is no SSU2 session with the router.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Ssu2Error::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Ssu2Error {
+    fn from(err: io::Error) -> Ssu2Error {
+        Ssu2Error::Io(err)
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, HashMap};
+use common::I2pDate;
+use i2np::{I2npMessage, MessageType};
+use transport::ssu2::block::{Block, BLOCK_HEADER_LENGTH, I2NP_SHORT_HEADER_LENGTH, FOLLOW_ON_HEADER_LENGTH};
+use transport::ssu2::error::Ssu2Error;
+
+
+/// A message has at most one first fragment and 127 follow-on fragments, as the
+/// fragment number has seven bits.
+pub const MAX_FRAGMENTS: usize = 128;
+
+/// Incomplete messages are dropped after ten seconds by default.
+const DEFAULT_TIMEOUT_MILLISECONDS: u64 = 10 * 1000;
+
+/// Splits an I2NP message into blocks of at most `max_block_length` bytes: a single
+/// I2NP block if it fits, otherwise a First Fragment block followed by Follow-on
+/// Fragment blocks.
+pub fn fragment(message: &I2npMessage, max_block_length: usize) -> Result<Vec<Block>, Ssu2Error> {
+    let whole = Block::I2np(message.clone());
+    if whole.encoded_len() <= max_block_length {
+        return Ok(vec![whole]);
+    }
+    if max_block_length <= BLOCK_HEADER_LENGTH + I2NP_SHORT_HEADER_LENGTH {
+        return Err(Ssu2Error::MessageTooLarge(message.payload.len()));
+    }
+
+    let first_length = max_block_length - BLOCK_HEADER_LENGTH - I2NP_SHORT_HEADER_LENGTH;
+    let follow_on_length = max_block_length - BLOCK_HEADER_LENGTH - FOLLOW_ON_HEADER_LENGTH;
+    let rest = &message.payload[first_length..];
+    let follow_ons = rest.chunks(follow_on_length).count();
+    if follow_ons + 1 > MAX_FRAGMENTS {
+        return Err(Ssu2Error::MessageTooLarge(message.payload.len()));
+    }
+
+    let mut blocks = vec![Block::FirstFragment {
+        message_type: message.message_type,
+        message_id: message.message_id,
+        expiration: message.expiration,
+        data: message.payload[..first_length].to_vec()
+    }];
+    for (i, data) in rest.chunks(follow_on_length).enumerate() {
+        blocks.push(Block::FollowOnFragment {
+            fragment: (i + 1) as u8,
+            last: i + 1 == follow_ons,
+            message_id: message.message_id,
+            data: data.to_vec()
+        });
+    }
+
+    Ok(blocks)
+}
+
+/// The fragments received so far of one message. The first fragment is number 0.
+struct PartialMessage {
+    header: Option<(MessageType, I2pDate)>,
+    fragments: BTreeMap<u8, Vec<u8>>,
+    last: Option<u8>,
+    started: u64
+}
+
+impl PartialMessage {
+    fn new(now: I2pDate) -> PartialMessage {
+        PartialMessage {
+            header: None,
+            fragments: BTreeMap::new(),
+            last: None,
+            started: now.to_u64()
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        match (self.header, self.last) {
+            (Some(_), Some(last)) => {
+                self.fragments.len() == last as usize + 1 && self.fragments.keys().next_back() == Some(&last)
+            }
+            _ => false
+        }
+    }
+}
+
+/// A `Reassembler` collects fragments, which may arrive in any order, and returns
+/// each I2NP message once all of its fragments have arrived.
+pub struct Reassembler {
+    partial: HashMap<u32, PartialMessage>,
+    timeout: u64
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Reassembler {
+            partial: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT_MILLISECONDS
+        }
+    }
+
+    /// Sets how long, in milliseconds, an incomplete message is kept.
+    pub fn set_timeout(&mut self, milliseconds: u64) {
+        self.timeout = milliseconds;
+    }
+
+    /// Returns the number of incomplete messages.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Handles a message block. Returns the message if the block completes one;
+    /// complete I2NP blocks are returned as they are, other blocks are ignored.
+    pub fn receive(&mut self, block: Block, now: I2pDate) -> Option<I2npMessage> {
+        let message_id = match block {
+            Block::I2np(message) => return Some(message),
+            Block::FirstFragment { message_type, message_id, expiration, data } => {
+                let partial = self.partial.entry(message_id).or_insert_with(|| PartialMessage::new(now));
+                partial.header = Some((message_type, expiration));
+                partial.fragments.entry(0).or_insert(data);
+                message_id
+            }
+            Block::FollowOnFragment { fragment, last, message_id, data } => {
+                let partial = self.partial.entry(message_id).or_insert_with(|| PartialMessage::new(now));
+                if last {
+                    partial.last = Some(fragment);
+                }
+                partial.fragments.entry(fragment).or_insert(data);
+                message_id
+            }
+            _ => return None
+        };
+
+        if !self.partial[&message_id].is_complete() {
+            return None;
+        }
+
+        let partial = self.partial.remove(&message_id)?;
+        let (message_type, expiration) = partial.header?;
+        let payload = partial.fragments.into_values().flatten().collect();
+
+        Some(I2npMessage::new(message_type, message_id, expiration, payload))
+    }
+
+    /// Drops the incomplete messages whose first fragment arrived more than the
+    /// timeout before `now`. Returns the number of messages dropped.
+    pub fn expire(&mut self, now: I2pDate) -> usize {
+        let before = self.partial.len();
+        let timeout = self.timeout;
+        self.partial.retain(|_, partial| partial.started.saturating_add(timeout) >= now.to_u64());
+
+        before - self.partial.len()
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Reassembler {
+        Reassembler::new()
+    }
+}
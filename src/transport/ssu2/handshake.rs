@@ -0,0 +1,451 @@
+use std::net::SocketAddr;
+use rand;
+use rand::Rng;
+use x25519_dalek::{PublicKey, StaticSecret};
+use common::{FromI2pBase64, I2pDate, Mapping, ToI2pBase64};
+use common::{RouterAddress, RouterInfo, SignatureVerifier};
+use transport::noise::{SymmetricState, KEY_LENGTH, encrypt, decrypt, hkdf};
+use transport::ntcp2::TerminationReason;
+use transport::ssu2::block::{Block, encode_blocks, decode_blocks};
+use transport::ssu2::error::Ssu2Error;
+use transport::ssu2::header::{LongHeader, ShortHeader, protect};
+use transport::ssu2::header::{LONG_HEADER_LENGTH, SHORT_HEADER_LENGTH};
+use transport::ssu2::header::{SESSION_CREATED, SESSION_CONFIRMED, SESSION_REQUEST, RETRY, TOKEN_REQUEST};
+
+
+/// The transport style of SSU2 router addresses.
+pub const SSU2_STYLE: &str = "SSU2";
+
+/// The largest difference in seconds between a peer's clock and ours that the
+/// handshake accepts.
+pub const MAX_CLOCK_SKEW: u64 = 60;
+
+/// The most padding we add to each handshake message.
+const PADDING_LENGTH: usize = 32;
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XKchaobfse+hs1+hs2+hs3_25519_ChaChaPoly_SHA256";
+const PROTOCOL_VERSION: u8 = 2;
+
+/// The MAC length of ChaCha20-Poly1305.
+pub const MAC_LENGTH: usize = 16;
+
+/// SessionRequest and SessionCreated obfuscate their ephemeral key along with the
+/// end of the long header.
+pub const EPHEMERAL_KEY_END: usize = LONG_HEADER_LENGTH + KEY_LENGTH;
+
+/// SessionConfirmed starts with the encrypted static key of the initiator.
+const STATIC_KEY_PART_LENGTH: usize = KEY_LENGTH + MAC_LENGTH;
+
+/// The flags of an unfragmented SessionConfirmed: fragment 0 of 1.
+const CONFIRMED_FLAGS: [u8; 3] = [0x01, 0x00, 0x00];
+
+/// The `StaticKeys` of an SSU2 address: the X25519 static key of the Noise
+/// handshake, and the intro key that protects the headers of packets sent to us
+/// before a session exists. Both are published, in the `s` and `i` options.
+pub struct StaticKeys {
+    private_key: StaticSecret,
+    intro_key: [u8; KEY_LENGTH]
+}
+
+impl StaticKeys {
+    pub fn new(private_key: [u8; KEY_LENGTH], intro_key: [u8; KEY_LENGTH]) -> StaticKeys {
+        StaticKeys {
+            private_key: StaticSecret::from(private_key),
+            intro_key
+        }
+    }
+
+    /// Generates a random static key and intro key.
+    pub fn generate() -> StaticKeys {
+        let mut intro_key = [0x00; KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut intro_key);
+
+        StaticKeys {
+            private_key: random_secret(),
+            intro_key
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; KEY_LENGTH] {
+        PublicKey::from(&self.private_key).to_bytes()
+    }
+
+    pub fn intro_key(&self) -> &[u8; KEY_LENGTH] {
+        &self.intro_key
+    }
+
+    /// Returns an SSU2 address that publishes the keys for a socket at `address`.
+    pub fn router_address(&self, cost: u8, address: SocketAddr) -> RouterAddress {
+        let mut options = Mapping::new();
+        let entries = [
+            ("host", address.ip().to_string()),
+            ("port", address.port().to_string()),
+            ("s", self.public_key().to_i2p_base64()),
+            ("i", self.intro_key.to_i2p_base64()),
+            ("v", PROTOCOL_VERSION.to_string())
+        ];
+        for &(key, ref value) in &entries {
+            options.insert(key, value).expect("SSU2 address options are short");
+        }
+
+        RouterAddress::new(cost, SSU2_STYLE, options).expect("SSU2_STYLE is a valid transport style")
+    }
+}
+
+/// The keys a router publishes in its SSU2 address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeerKeys {
+    pub static_key: [u8; KEY_LENGTH],
+    pub intro_key: [u8; KEY_LENGTH]
+}
+
+impl PeerKeys {
+    /// Reads the static key and intro key from a router's SSU2 address.
+    pub fn from_router_info(router_info: &RouterInfo) -> Option<PeerKeys> {
+        let address = router_info.address(SSU2_STYLE)?;
+
+        Some(PeerKeys {
+            static_key: decode_key(address.option("s")?)?,
+            intro_key: decode_key(address.option("i")?)?
+        })
+    }
+}
+
+fn decode_key(text: &str) -> Option<[u8; KEY_LENGTH]> {
+    let bytes = text.from_i2p_base64()?;
+    if bytes.len() != KEY_LENGTH {
+        return None;
+    }
+    let mut key = [0x00; KEY_LENGTH];
+    key.copy_from_slice(&bytes);
+
+    Some(key)
+}
+
+/// The keys of one direction of the data phase: the ChaCha20-Poly1305 key of the
+/// payload and the key of the second header mask.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataKeys {
+    pub data_key: [u8; KEY_LENGTH],
+    pub header_key: [u8; KEY_LENGTH]
+}
+
+/// The outcome of a completed handshake.
+pub struct Established {
+    pub send: DataKeys,
+    pub receive: DataKeys
+}
+
+fn random_secret() -> StaticSecret {
+    let mut key = [0x00; KEY_LENGTH];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    StaticSecret::from(key)
+}
+
+fn padding() -> Block {
+    let mut rng = rand::thread_rng();
+    let mut padding = vec![0x00; rng.gen_range(0, PADDING_LENGTH + 1)];
+    rng.fill_bytes(&mut padding);
+
+    Block::Padding(padding)
+}
+
+fn diffie_hellman(private_key: &StaticSecret, public_key: &[u8; KEY_LENGTH]) -> [u8; KEY_LENGTH] {
+    private_key.diffie_hellman(&PublicKey::from(*public_key)).to_bytes()
+}
+
+fn read_key(bytes: &[u8]) -> [u8; KEY_LENGTH] {
+    let mut key = [0x00; KEY_LENGTH];
+    key.copy_from_slice(&bytes[..KEY_LENGTH]);
+
+    key
+}
+
+fn seconds(now: I2pDate) -> u32 {
+    (now.to_u64() / 1000) as u32
+}
+
+/// Checks the DateTime block that SessionRequest and SessionCreated must carry.
+fn check_clock_skew(blocks: &[Block], now: I2pDate, missing: TerminationReason) -> Result<(), Ssu2Error> {
+    let timestamp = blocks.iter().filter_map(|block| match *block {
+        Block::DateTime(timestamp) => Some(timestamp as u64),
+        _ => None
+    }).next();
+
+    match timestamp {
+        Some(timestamp) if timestamp.abs_diff(now.to_u64() / 1000) > MAX_CLOCK_SKEW => {
+            Err(Ssu2Error::Handshake(TerminationReason::ClockSkew))
+        }
+        Some(_) => Ok(()),
+        None => Err(Ssu2Error::Handshake(missing))
+    }
+}
+
+/// Returns the Address block of a handshake message, if there is one.
+fn address_block(blocks: &[Block]) -> Option<SocketAddr> {
+    blocks.iter().filter_map(|block| match *block {
+        Block::Address(address) => Some(address),
+        _ => None
+    }).next()
+}
+
+/// Derives the data phase keys, from the initiator to the responder and back.
+fn split(state: &SymmetricState) -> (DataKeys, DataKeys) {
+    let (key_ab, key_ba) = hkdf(state.chaining_key(), &[], &[]);
+    let (data_ab, header_ab) = hkdf(&key_ab, &[], b"HKDFSSU2DataKeys");
+    let (data_ba, header_ba) = hkdf(&key_ba, &[], b"HKDFSSU2DataKeys");
+
+    (DataKeys { data_key: data_ab, header_key: header_ab },
+     DataKeys { data_key: data_ba, header_key: header_ba })
+}
+
+/// Builds a packet sealed with an intro key, as TokenRequest and Retry are: the
+/// payload is encrypted with the key, the packet number as nonce and the header as
+/// associated data, and both header masks use the key.
+fn seal_with_intro_key(header: LongHeader, intro_key: &[u8; KEY_LENGTH], blocks: &[Block]) -> Vec<u8> {
+    let bytes = header.to_bytes();
+    let mut packet = bytes.to_vec();
+    packet.extend_from_slice(&encrypt(intro_key, header.packet_number as u64, &bytes, &encode_blocks(blocks)));
+    protect(&mut packet, intro_key, intro_key, LONG_HEADER_LENGTH);
+
+    packet
+}
+
+/// Opens a TokenRequest or Retry whose header protection has been removed.
+pub fn open_with_intro_key(packet: &[u8], intro_key: &[u8; KEY_LENGTH]) -> Result<(LongHeader, Vec<Block>), Ssu2Error> {
+    let header = LongHeader::from_bytes(packet).ok_or(Ssu2Error::InvalidPacket)?;
+    let payload = decrypt(intro_key, header.packet_number as u64, &packet[..LONG_HEADER_LENGTH], &packet[LONG_HEADER_LENGTH..])
+        .ok_or(Ssu2Error::InvalidPacket)?;
+
+    Ok((header, decode_blocks(&payload)?))
+}
+
+/// Builds a TokenRequest, which asks `peer` for the token a SessionRequest needs.
+pub fn token_request(header: LongHeader, peer: &PeerKeys, now: I2pDate) -> Vec<u8> {
+    let header = LongHeader { message_type: TOKEN_REQUEST, token: 0, ..header };
+
+    seal_with_intro_key(header, &peer.intro_key, &[Block::DateTime(seconds(now)), padding()])
+}
+
+/// Builds a Retry, which carries a token for the peer at `address` in its header.
+pub fn retry(header: LongHeader, keys: &StaticKeys, address: SocketAddr, now: I2pDate) -> Vec<u8> {
+    let header = LongHeader { message_type: RETRY, ..header };
+
+    seal_with_intro_key(header, &keys.intro_key, &reply_blocks(address, now))
+}
+
+/// The DateTime, Address and padding blocks of Retry and SessionCreated.
+fn reply_blocks(address: SocketAddr, now: I2pDate) -> Vec<Block> {
+    vec![Block::DateTime(seconds(now)), Block::Address(address), padding()]
+}
+
+/// The `Initiator` of a handshake, Alice, after sending SessionRequest.
+pub struct Initiator {
+    state: SymmetricState,
+    ephemeral_key: StaticSecret,
+    request: LongHeader,
+    created_header_key: [u8; KEY_LENGTH]
+}
+
+impl Initiator {
+    /// Builds a SessionRequest to `peer` with the connection IDs and token in
+    /// `request`. Returns the initiator awaiting SessionCreated and the packet.
+    pub fn session_request(request: LongHeader, peer: &PeerKeys, now: I2pDate) -> (Initiator, Vec<u8>) {
+        let request = LongHeader { message_type: SESSION_REQUEST, ..request };
+        let header = request.to_bytes();
+        let ephemeral_key = random_secret();
+        let ephemeral_public_key = PublicKey::from(&ephemeral_key).to_bytes();
+
+        let mut state = SymmetricState::new(PROTOCOL_NAME, &peer.static_key);
+        state.mix_hash(&header);
+        state.mix_hash(&ephemeral_public_key);
+        state.mix_key(&diffie_hellman(&ephemeral_key, &peer.static_key));
+
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&ephemeral_public_key);
+        packet.extend_from_slice(&state.encrypt_and_hash(0, &encode_blocks(&[Block::DateTime(seconds(now)), padding()])));
+        protect(&mut packet, &peer.intro_key, &peer.intro_key, EPHEMERAL_KEY_END);
+
+        let (created_header_key, _) = hkdf(state.chaining_key(), &[], b"SessCreateHeader");
+        let initiator = Initiator {
+            state,
+            ephemeral_key,
+            request,
+            created_header_key
+        };
+
+        (initiator, packet)
+    }
+
+    /// Returns the key of the second header mask of SessionCreated.
+    pub fn created_header_key(&self) -> &[u8; KEY_LENGTH] {
+        &self.created_header_key
+    }
+
+    /// Handles a SessionCreated whose header protection has been removed, and builds
+    /// SessionConfirmed with our static key and RouterInfo. Returns the packet, the
+    /// data phase keys and the address the peer sees us at.
+    pub fn session_created(&self,
+                           packet: &[u8],
+                           keys: &StaticKeys,
+                           router_info: &RouterInfo,
+                           peer: &PeerKeys,
+                           now: I2pDate) -> Result<(Vec<u8>, Established, Option<SocketAddr>), Ssu2Error> {
+
+        let failed = || Ssu2Error::Handshake(TerminationReason::SessionCreatedError);
+        if packet.len() < EPHEMERAL_KEY_END + MAC_LENGTH {
+            return Err(failed());
+        }
+        let header = LongHeader::from_bytes(packet).ok_or_else(failed)?;
+        if header.message_type != SESSION_CREATED || header.destination_id != self.request.source_id {
+            return Err(failed());
+        }
+
+        // The state only advances on a copy, so a forged SessionCreated leaves the
+        // initiator waiting for the real one.
+        let mut state = self.state.clone();
+        let peer_ephemeral_key = read_key(&packet[LONG_HEADER_LENGTH..]);
+        state.mix_hash(&packet[..LONG_HEADER_LENGTH]);
+        state.mix_hash(&peer_ephemeral_key);
+        state.mix_key(&diffie_hellman(&self.ephemeral_key, &peer_ephemeral_key));
+        let payload = state.decrypt_and_hash(0, &packet[EPHEMERAL_KEY_END..]).ok_or_else(failed)?;
+        let blocks = decode_blocks(&payload).map_err(|_| failed())?;
+        check_clock_skew(&blocks, now, TerminationReason::SessionCreatedError)?;
+
+        let (confirmed_header_key, _) = hkdf(state.chaining_key(), &[], b"SessionConfirmed");
+        let confirmed = ShortHeader {
+            destination_id: self.request.destination_id,
+            packet_number: 0,
+            message_type: SESSION_CONFIRMED,
+            flags: CONFIRMED_FLAGS
+        }.to_bytes();
+        state.mix_hash(&confirmed);
+
+        let mut reply = confirmed.to_vec();
+        reply.extend_from_slice(&state.encrypt_and_hash(1, &keys.public_key()));
+        state.mix_key(&diffie_hellman(&keys.private_key, &peer_ephemeral_key));
+        let payload = encode_blocks(&[
+            Block::RouterInfo { flood: false, router_info: router_info.to_bytes() },
+            padding()
+        ]);
+        reply.extend_from_slice(&state.encrypt_and_hash(0, &payload));
+        protect(&mut reply, &peer.intro_key, &confirmed_header_key, SHORT_HEADER_LENGTH);
+
+        let (send, receive) = split(&state);
+
+        Ok((reply, Established { send, receive }, address_block(&blocks)))
+    }
+}
+
+/// The `Responder` of a handshake, Bob, after sending SessionCreated.
+pub struct Responder {
+    state: SymmetricState,
+    ephemeral_key: StaticSecret,
+    confirmed_header_key: [u8; KEY_LENGTH]
+}
+
+impl Responder {
+    /// Handles a SessionRequest whose header protection has been removed, and builds
+    /// SessionCreated for a peer at `address`. The connection IDs of SessionCreated
+    /// are those of the request swapped. Returns the responder awaiting
+    /// SessionConfirmed and the packet.
+    pub fn session_request(packet: &[u8],
+                           keys: &StaticKeys,
+                           address: SocketAddr,
+                           now: I2pDate) -> Result<(Responder, Vec<u8>), Ssu2Error> {
+
+        let failed = || Ssu2Error::Handshake(TerminationReason::SessionRequestError);
+        if packet.len() < EPHEMERAL_KEY_END + MAC_LENGTH {
+            return Err(failed());
+        }
+        let request = LongHeader::from_bytes(packet).ok_or_else(failed)?;
+
+        let peer_ephemeral_key = read_key(&packet[LONG_HEADER_LENGTH..]);
+        let mut state = SymmetricState::new(PROTOCOL_NAME, &keys.public_key());
+        state.mix_hash(&packet[..LONG_HEADER_LENGTH]);
+        state.mix_hash(&peer_ephemeral_key);
+        state.mix_key(&diffie_hellman(&keys.private_key, &peer_ephemeral_key));
+        let payload = state.decrypt_and_hash(0, &packet[EPHEMERAL_KEY_END..]).ok_or_else(failed)?;
+        let blocks = decode_blocks(&payload).map_err(|_| failed())?;
+        check_clock_skew(&blocks, now, TerminationReason::SessionRequestError)?;
+
+        let (created_header_key, _) = hkdf(state.chaining_key(), &[], b"SessCreateHeader");
+        let header = LongHeader {
+            destination_id: request.source_id,
+            packet_number: rand::thread_rng().gen(),
+            message_type: SESSION_CREATED,
+            network_id: request.network_id,
+            source_id: request.destination_id,
+            token: 0
+        }.to_bytes();
+        let ephemeral_key = random_secret();
+        let ephemeral_public_key = PublicKey::from(&ephemeral_key).to_bytes();
+        state.mix_hash(&header);
+        state.mix_hash(&ephemeral_public_key);
+        state.mix_key(&diffie_hellman(&ephemeral_key, &peer_ephemeral_key));
+
+        let mut reply = header.to_vec();
+        reply.extend_from_slice(&ephemeral_public_key);
+        reply.extend_from_slice(&state.encrypt_and_hash(0, &encode_blocks(&reply_blocks(address, now))));
+        protect(&mut reply, &keys.intro_key, &created_header_key, EPHEMERAL_KEY_END);
+
+        let (confirmed_header_key, _) = hkdf(state.chaining_key(), &[], b"SessionConfirmed");
+        let responder = Responder {
+            state,
+            ephemeral_key,
+            confirmed_header_key
+        };
+
+        Ok((responder, reply))
+    }
+
+    /// Returns the key of the second header mask of SessionConfirmed.
+    pub fn confirmed_header_key(&self) -> &[u8; KEY_LENGTH] {
+        &self.confirmed_header_key
+    }
+
+    /// Handles a SessionConfirmed whose header protection has been removed. The
+    /// peer's RouterInfo must be signed, checked with `verifier`, and must publish
+    /// the static key the peer proved it holds. Returns the data phase keys, the
+    /// RouterInfo and the keys of its SSU2 address.
+    pub fn session_confirmed<V>(mut self, packet: &[u8], verifier: &V)
+        -> Result<(Established, RouterInfo, PeerKeys), Ssu2Error> where V: SignatureVerifier {
+
+        let failed = || Ssu2Error::Handshake(TerminationReason::SessionConfirmedError);
+        if packet.len() < SHORT_HEADER_LENGTH + STATIC_KEY_PART_LENGTH + MAC_LENGTH {
+            return Err(failed());
+        }
+        let header = ShortHeader::from_bytes(packet).ok_or_else(failed)?;
+        if header.message_type != SESSION_CONFIRMED || header.flags[0] != CONFIRMED_FLAGS[0] {
+            return Err(failed());
+        }
+
+        self.state.mix_hash(&packet[..SHORT_HEADER_LENGTH]);
+        let static_part = &packet[SHORT_HEADER_LENGTH..SHORT_HEADER_LENGTH + STATIC_KEY_PART_LENGTH];
+        let peer_static_key = read_key(&self.state.decrypt_and_hash(1, static_part).ok_or_else(failed)?);
+        self.state.mix_key(&diffie_hellman(&self.ephemeral_key, &peer_static_key));
+        let payload = self.state.decrypt_and_hash(0, &packet[SHORT_HEADER_LENGTH + STATIC_KEY_PART_LENGTH..])
+            .ok_or_else(failed)?;
+
+        let blocks = decode_blocks(&payload).map_err(|_| failed())?;
+        let peer = match blocks.first() {
+            Some(Block::RouterInfo { router_info, .. }) => match RouterInfo::from_bytes(router_info) {
+                Some((peer, length)) if length == router_info.len() => peer,
+                _ => return Err(failed())
+            },
+            _ => return Err(failed())
+        };
+        if !peer.verify(verifier) {
+            return Err(Ssu2Error::Handshake(TerminationReason::RouterInfoSignatureFailure));
+        }
+        let peer_keys = match PeerKeys::from_router_info(&peer) {
+            Some(peer_keys) if peer_keys.static_key == peer_static_key => peer_keys,
+            _ => return Err(Ssu2Error::Handshake(TerminationReason::StaticKeyMismatch))
+        };
+
+        let (receive, send) = split(&self.state);
+
+        Ok((Established { send, receive }, peer, peer_keys))
+    }
+}
@@ -0,0 +1,164 @@
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+
+
+/// The long header of SessionRequest, SessionCreated, Retry and TokenRequest.
+pub const LONG_HEADER_LENGTH: usize = 32;
+
+/// The short header of SessionConfirmed and data packets.
+pub const SHORT_HEADER_LENGTH: usize = 16;
+
+/// Header protection takes its nonces from the last 24 bytes of the packet, which
+/// must not overlap the 16 bytes it protects.
+pub const MIN_PACKET_LENGTH: usize = SHORT_HEADER_LENGTH + 24;
+
+pub const PROTOCOL_VERSION: u8 = 2;
+
+pub const SESSION_REQUEST: u8 = 0;
+pub const SESSION_CREATED: u8 = 1;
+pub const SESSION_CONFIRMED: u8 = 2;
+pub const DATA: u8 = 6;
+pub const RETRY: u8 = 9;
+pub const TOKEN_REQUEST: u8 = 10;
+
+const NONCE_LENGTH: usize = 12;
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    bytes[0..8].iter().fold(0, |value, byte| (value << 8) | (*byte as u64))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+/// Returns the destination connection ID of a packet whose first header mask has
+/// been removed.
+pub fn connection_id(packet: &[u8]) -> u64 {
+    read_u64(packet)
+}
+
+/// A `LongHeader` starts the handshake packets sent before a session exists. Each
+/// side picks the connection ID the other puts in `destination_id`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LongHeader {
+    pub destination_id: u64,
+    pub packet_number: u32,
+    pub message_type: u8,
+    pub network_id: u8,
+    pub source_id: u64,
+    /// The token from a Retry, or zero when the sender has none.
+    pub token: u64
+}
+
+impl LongHeader {
+    pub fn to_bytes(&self) -> [u8; LONG_HEADER_LENGTH] {
+        let mut bytes = [0x00; LONG_HEADER_LENGTH];
+        bytes[0..8].copy_from_slice(&self.destination_id.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.packet_number.to_be_bytes());
+        bytes[12] = self.message_type;
+        bytes[13] = PROTOCOL_VERSION;
+        bytes[14] = self.network_id;
+        bytes[16..24].copy_from_slice(&self.source_id.to_be_bytes());
+        bytes[24..32].copy_from_slice(&self.token.to_be_bytes());
+
+        bytes
+    }
+
+    /// Parses an unprotected long header. Returns `None` for other protocol versions.
+    pub fn from_bytes(bytes: &[u8]) -> Option<LongHeader> {
+        if bytes.len() < LONG_HEADER_LENGTH || bytes[13] != PROTOCOL_VERSION {
+            return None;
+        }
+
+        Some(LongHeader {
+            destination_id: read_u64(&bytes[0..8]),
+            packet_number: read_u32(&bytes[8..12]),
+            message_type: bytes[12],
+            network_id: bytes[14],
+            source_id: read_u64(&bytes[16..24]),
+            token: read_u64(&bytes[24..32])
+        })
+    }
+}
+
+/// A `ShortHeader` starts SessionConfirmed and every packet of the data phase.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ShortHeader {
+    pub destination_id: u64,
+    pub packet_number: u32,
+    pub message_type: u8,
+    pub flags: [u8; 3]
+}
+
+impl ShortHeader {
+    pub fn to_bytes(&self) -> [u8; SHORT_HEADER_LENGTH] {
+        let mut bytes = [0x00; SHORT_HEADER_LENGTH];
+        bytes[0..8].copy_from_slice(&self.destination_id.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.packet_number.to_be_bytes());
+        bytes[12] = self.message_type;
+        bytes[13..16].copy_from_slice(&self.flags);
+
+        bytes
+    }
+
+    /// Parses an unprotected short header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ShortHeader> {
+        if bytes.len() < SHORT_HEADER_LENGTH {
+            return None;
+        }
+
+        let mut flags = [0x00; 3];
+        flags.copy_from_slice(&bytes[13..16]);
+
+        Some(ShortHeader {
+            destination_id: read_u64(&bytes[0..8]),
+            packet_number: read_u32(&bytes[8..12]),
+            message_type: bytes[12],
+            flags
+        })
+    }
+}
+
+fn apply_chacha20(key: &[u8; 32], nonce: &[u8; NONCE_LENGTH], data: &mut [u8]) {
+    ChaCha20::new(key.into(), nonce.into()).apply_keystream(data);
+}
+
+fn tail_nonce(packet: &[u8], from_end: usize) -> [u8; NONCE_LENGTH] {
+    let start = packet.len() - from_end;
+    let mut nonce = [0x00; NONCE_LENGTH];
+    nonce.copy_from_slice(&packet[start..start + NONCE_LENGTH]);
+
+    nonce
+}
+
+/// Masks or unmasks the destination connection ID, the first 8 bytes of the header,
+/// with ChaCha20 under `key` and a nonce from bytes -24 to -12 of the packet. The key
+/// is always the receiver's intro key, or Bob's during the handshake, so the
+/// receiver can find the session a packet belongs to.
+pub fn mask_connection_id(packet: &mut [u8], key: &[u8; 32]) {
+    let nonce = tail_nonce(packet, 2 * NONCE_LENGTH);
+    apply_chacha20(key, &nonce, &mut packet[0..8]);
+}
+
+/// Masks or unmasks header bytes 8 to 16, which hold the packet number and type,
+/// with ChaCha20 under `key` and a nonce from the last 12 bytes of the packet. The
+/// key depends on the message and, in the data phase, on the direction.
+pub fn mask_header(packet: &mut [u8], key: &[u8; 32]) {
+    let nonce = tail_nonce(packet, NONCE_LENGTH);
+    apply_chacha20(key, &nonce, &mut packet[8..16]);
+}
+
+/// Encrypts or decrypts bytes 16 to `end` of a long header packet, which hold the
+/// source connection ID, the token and, in SessionRequest and SessionCreated, the
+/// ephemeral key. The cipher is ChaCha20 under `key` with a zero nonce.
+pub fn obfuscate_long_header(packet: &mut [u8], key: &[u8; 32], end: usize) {
+    apply_chacha20(key, &[0x00; NONCE_LENGTH], &mut packet[SHORT_HEADER_LENGTH..end]);
+}
+
+/// Applies all header protection to a finished packet. `end` is where the
+/// obfuscated part of a long header ends, or `SHORT_HEADER_LENGTH` for short ones.
+pub fn protect(packet: &mut [u8], key_1: &[u8; 32], key_2: &[u8; 32], end: usize) {
+    obfuscate_long_header(packet, key_2, end);
+    mask_header(packet, key_2);
+    mask_connection_id(packet, key_1);
+}
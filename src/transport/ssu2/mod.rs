@@ -0,0 +1,28 @@
+pub use self::error::Ssu2Error;
+pub use self::block::{Block, encode_blocks, decode_blocks};
+pub use self::ack::{Ack, AckTracker, MAX_ACK_RANGES};
+pub use self::fragment::{Reassembler, fragment, MAX_FRAGMENTS};
+pub use self::path::PathValidator;
+pub use self::peer_test::{PeerTest, PeerTester, PeerTestNetwork};
+pub use self::peer_test::{PEER_TEST_ACCEPT, BOB_REJECT_NO_CHARLIE, CHARLIE_REJECT_ADDRESS};
+pub use self::relay::{RelayRequest, RelayResponse, RelayIntro, RelayOutcome, RelayManager, RelayNetwork};
+pub use self::relay::{Introducer, introducer_options, parse_introducers, MAX_INTRODUCERS};
+pub use self::relay::{RELAY_ACCEPT, BOB_REJECT_TAG_NOT_FOUND, CHARLIE_REJECT_UNSPECIFIED, CHARLIE_REJECT_SIGNATURE};
+pub use self::relay::CHARLIE_REJECT_UNKNOWN_ALICE;
+pub use self::header::{LongHeader, ShortHeader, mask_connection_id, mask_header, obfuscate_long_header, protect};
+pub use self::header::{LONG_HEADER_LENGTH, SHORT_HEADER_LENGTH, MIN_PACKET_LENGTH};
+pub use self::handshake::{StaticKeys, PeerKeys, DataKeys, SSU2_STYLE, MAX_CLOCK_SKEW};
+pub use self::udp::{UdpEndpoint, Event, MAX_PACKET_LENGTH};
+
+
+mod error;
+mod block;
+mod ack;
+mod fragment;
+mod path;
+mod peer_test;
+mod relay;
+mod endpoint;
+mod header;
+mod handshake;
+mod udp;
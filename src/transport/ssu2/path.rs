@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use rand;
+use rand::Rng;
+use transport::ssu2::block::Block;
+
+
+/// The number of random bytes in the Path Challenges we send.
+pub const PATH_CHALLENGE_LENGTH: usize = 8;
+
+/// A `PathValidator` checks that a peer is reachable at a new address before a
+/// session moves to it. We send random data in a Path Challenge to the address and
+/// accept the address once a Path Response from it echoes that data.
+#[derive(Clone, Debug, Default)]
+pub struct PathValidator {
+    pending: HashMap<SocketAddr, Vec<u8>>
+}
+
+impl PathValidator {
+    pub fn new() -> PathValidator {
+        PathValidator {
+            pending: HashMap::new()
+        }
+    }
+
+    /// Builds the Path Challenge to send to `address`, replacing any earlier one.
+    pub fn challenge(&mut self, address: SocketAddr) -> Block {
+        let mut data = vec![0x00; PATH_CHALLENGE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut data);
+        self.pending.insert(address, data.clone());
+
+        Block::PathChallenge(data)
+    }
+
+    /// Builds the Path Response to a Path Challenge from the peer.
+    pub fn response(challenge: &[u8]) -> Block {
+        Block::PathResponse(challenge.to_vec())
+    }
+
+    /// Determines whether a challenge sent to `address` is still unanswered.
+    pub fn is_pending(&self, address: &SocketAddr) -> bool {
+        self.pending.contains_key(address)
+    }
+
+    /// Handles a Path Response received from `address`. Returns `true` if it
+    /// answers the challenge sent there, which validates the address.
+    pub fn validate(&mut self, address: &SocketAddr, response: &[u8]) -> bool {
+        let valid = match self.pending.get(address) {
+            Some(data) => data.as_slice() == response,
+            None => false
+        };
+        if valid {
+            self.pending.remove(address);
+        }
+
+        valid
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use rand;
+use rand::Rng;
+use common::{Hash256, I2pDate};
+use common::{SessionSigner, Signature, SignatureVerifier, SigningPublicKey};
+use transport::reachability::Reachability;
+use transport::ssu2::endpoint::{endpoint_len, encode_endpoint, decode_endpoint};
+use transport::ssu2::error::Ssu2Error;
+use transport::ssu2::relay::{CHARLIE_REJECT_SIGNATURE, CHARLIE_REJECT_UNKNOWN_ALICE};
+
+
+/// The code of a Peer Test message that accepts the test.
+pub const PEER_TEST_ACCEPT: u8 = 0;
+
+/// Bob rejects the test because he has no router to act as Charlie.
+pub const BOB_REJECT_NO_CHARLIE: u8 = 2;
+
+/// Charlie rejects the test because Alice's address is missing or unusable.
+pub const CHARLIE_REJECT_ADDRESS: u8 = 66;
+
+/// Peer tests that have not finished after this many milliseconds are dropped.
+const DEFAULT_TIMEOUT_MILLISECONDS: u64 = 20 * 1000;
+
+/// Codes from this one up are Charlie's; the ones below it other than
+/// `PEER_TEST_ACCEPT` are Bob's.
+const CHARLIE_CODES: u8 = 64;
+
+/// The prologue of the data signed in messages 1 to 4.
+const PEER_TEST_PROLOGUE: &[u8] = b"PeerTestValidate";
+
+const PEER_TEST_VERSION: u8 = 2;
+const PEER_TEST_HEADER_LENGTH: usize = 3;
+const PEER_TEST_DATA_LENGTH: usize = 9;
+const HASH_LENGTH: usize = 32;
+
+/// A `PeerTest` is the content of a Peer Test block, one of the seven messages of a
+/// peer test. Messages 1 to 4 travel inside sessions, messages 5 to 7 outside of
+/// any session. The router hash is only present in messages 2 and 4. Messages 1 and
+/// 2 carry Alice's signature, messages 3 and 4 Charlie's, or Bob's when he rejects
+/// the test; messages 5 to 7 are not signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerTest {
+    pub message: u8,
+    pub code: u8,
+    pub router: Option<Hash256>,
+    pub nonce: u32,
+    pub timestamp: u32,
+    pub address: Option<SocketAddr>,
+    pub signature: Vec<u8>
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+fn has_router(message: u8) -> bool {
+    message == 2 || message == 4
+}
+
+fn verify_signature<V>(verifier: &V, key: &SigningPublicKey, message: &[u8], signature: &[u8]) -> bool
+    where V: SignatureVerifier
+{
+    match Signature::from_bytes(key.signature_type(), signature) {
+        Some(signature) => verifier.verify(key, message, &signature),
+        None => false
+    }
+}
+
+impl PeerTest {
+    pub fn new(message: u8, code: u8, nonce: u32, timestamp: u32, address: Option<SocketAddr>) -> PeerTest {
+        PeerTest {
+            message,
+            code,
+            router: None,
+            nonce,
+            timestamp,
+            address,
+            signature: Vec::new()
+        }
+    }
+
+    /// Returns a copy of the test as a different message, keeping the signed data.
+    fn relay(&self, message: u8, code: u8, router: Option<Hash256>) -> PeerTest {
+        PeerTest {
+            message,
+            code,
+            router,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the data signed for messages 1 to 4: a prologue, Bob's hash, Alice's
+    /// hash in messages 3 and 4, and the test from its version to its address.
+    pub fn signed_bytes(&self, bob: &Hash256, alice: Option<&Hash256>) -> Vec<u8> {
+        let mut bytes = PEER_TEST_PROLOGUE.to_vec();
+        bytes.extend_from_slice(bob.as_ref());
+        if let Some(alice) = alice {
+            bytes.extend_from_slice(alice.as_ref());
+        }
+        bytes.push(PEER_TEST_VERSION);
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        encode_endpoint(&mut bytes, self.address);
+
+        bytes
+    }
+
+    /// Returns the length of the encoded test, without the block header.
+    pub fn encoded_len(&self) -> usize {
+        let router = if has_router(self.message) { HASH_LENGTH } else { 0 };
+
+        PEER_TEST_HEADER_LENGTH + router + PEER_TEST_DATA_LENGTH + endpoint_len(self.address) + self.signature.len()
+    }
+
+    /// Appends the encoded test to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&[self.message, self.code, 0x00]);
+        if has_router(self.message) {
+            match self.router {
+                Some(ref router) => buf.extend_from_slice(router.as_ref()),
+                None => buf.extend_from_slice(&[0x00; HASH_LENGTH])
+            }
+        }
+
+        buf.push(PEER_TEST_VERSION);
+        buf.extend_from_slice(&self.nonce.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        encode_endpoint(buf, self.address);
+        buf.extend_from_slice(&self.signature);
+    }
+
+    /// Decodes a test from the content of a Peer Test block.
+    pub fn decode(data: &[u8]) -> Result<PeerTest, Ssu2Error> {
+        if data.len() < PEER_TEST_HEADER_LENGTH {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+        let (message, code) = (data[0], data[1]);
+        let mut offset = PEER_TEST_HEADER_LENGTH;
+
+        let router = if has_router(message) {
+            let hash = match data.get(offset..offset + HASH_LENGTH) {
+                Some(hash) => hash,
+                None => return Err(Ssu2Error::InvalidBlock)
+            };
+            let mut bytes = [0x00; HASH_LENGTH];
+            bytes.copy_from_slice(hash);
+            offset += HASH_LENGTH;
+            Some(Hash256::from(bytes))
+        } else {
+            None
+        };
+
+        let fields = match data.get(offset..offset + PEER_TEST_DATA_LENGTH) {
+            Some(fields) if fields[0] == PEER_TEST_VERSION => fields,
+            _ => return Err(Ssu2Error::InvalidBlock)
+        };
+        offset += PEER_TEST_DATA_LENGTH;
+
+        let (address, length) = decode_endpoint(&data[offset..])?;
+        offset += length;
+
+        Ok(PeerTest {
+            message,
+            code,
+            router,
+            nonce: read_u32(&fields[1..5]),
+            timestamp: read_u32(&fields[5..9]),
+            address,
+            signature: data[offset..].to_vec()
+        })
+    }
+}
+
+/// The `PeerTestNetwork` trait is the view of the SSU2 transport that peer tests need.
+pub trait PeerTestNetwork {
+    /// Sends a Peer Test block in the session with `peer`. Returns `false` if there
+    /// is no session with the peer.
+    fn send_in_session(&mut self, peer: &Hash256, test: PeerTest) -> bool;
+
+    /// Sends a Peer Test block to `address` outside of any session.
+    fn send_out_of_session(&mut self, address: SocketAddr, test: PeerTest);
+
+    /// Returns the SSU2 address a router publishes.
+    fn address_of(&self, router: &Hash256) -> Option<SocketAddr>;
+
+    /// Picks a router we have a session with to act as Charlie.
+    fn choose_charlie(&self, exclude: &[Hash256]) -> Option<Hash256>;
+
+    /// Returns the signing key in the RouterInfo of `router`, if we have it.
+    fn signing_key(&self, router: &Hash256) -> Option<SigningPublicKey>;
+}
+
+/// A test we run as Alice.
+struct AliceTest {
+    bob: Hash256,
+    address: SocketAddr,
+    accepted: bool,
+    reachable: bool,
+    started: u64
+}
+
+/// A test we relay as Bob.
+struct BobTest {
+    alice: Hash256,
+    charlie: Hash256,
+    started: u64
+}
+
+/// A test we take part in as Charlie.
+struct CharlieTest {
+    started: u64
+}
+
+/// A `PeerTester` runs the SSU2 peer test protocol in all three roles. As Alice we
+/// ask Bob to find a Charlie; Charlie then contacts us at the address Bob sees for
+/// us (message 5) and tells us the address he sees for us (message 7). Whether these
+/// messages arrive and whether the addresses agree tells us how reachable we are.
+/// Messages 1 to 4 are signed with our router's signing key.
+pub struct PeerTester<S, V> {
+    local: Hash256,
+    signer: S,
+    verifier: V,
+    alice: HashMap<u32, AliceTest>,
+    bob: HashMap<u32, BobTest>,
+    charlie: HashMap<u32, CharlieTest>,
+    timeout: u64
+}
+
+fn seconds(now: I2pDate) -> u32 {
+    (now.to_u64() / 1000) as u32
+}
+
+impl<S, V> PeerTester<S, V> where S: SessionSigner, V: SignatureVerifier {
+    /// Creates a tester for our router, `local`, which signs with `signer`.
+    pub fn new(local: Hash256, signer: S, verifier: V) -> PeerTester<S, V> {
+        PeerTester {
+            local,
+            signer,
+            verifier,
+            alice: HashMap::new(),
+            bob: HashMap::new(),
+            charlie: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT_MILLISECONDS
+        }
+    }
+
+    /// Sets how long, in milliseconds, a test may take.
+    pub fn set_timeout(&mut self, milliseconds: u64) {
+        self.timeout = milliseconds;
+    }
+
+    pub fn local(&self) -> &Hash256 {
+        &self.local
+    }
+
+    /// Signs `test` over the hashes of Bob and, in messages 3 and 4, Alice.
+    fn sign(&self, mut test: PeerTest, bob: &Hash256, alice: Option<&Hash256>) -> PeerTest {
+        test.signature = self.signer.sign(&test.signed_bytes(bob, alice)).as_ref().to_vec();
+        test
+    }
+
+    /// Checks the signature of `test` against the key of `signer`.
+    fn verify<N>(&self, test: &PeerTest, signer: &Hash256, bob: &Hash256, alice: Option<&Hash256>, network: &N) -> Option<bool>
+        where N: PeerTestNetwork
+    {
+        let key = network.signing_key(signer)?;
+        Some(verify_signature(&self.verifier, &key, &test.signed_bytes(bob, alice), &test.signature))
+    }
+
+    /// Starts a test as Alice with Bob, a router we have a session with. `address`
+    /// is our address as Bob sees it. Returns the nonce of the test, or `None` if the
+    /// first message could not be sent.
+    pub fn start<N>(&mut self, bob: &Hash256, address: SocketAddr, now: I2pDate, network: &mut N) -> Option<u32>
+        where N: PeerTestNetwork
+    {
+        let nonce = rand::thread_rng().gen::<u32>();
+        let test = PeerTest::new(1, PEER_TEST_ACCEPT, nonce, seconds(now), Some(address));
+        let test = self.sign(test, bob, None);
+        if !network.send_in_session(bob, test) {
+            return None;
+        }
+
+        self.alice.insert(nonce, AliceTest {
+            bob: bob.clone(),
+            address,
+            accepted: false,
+            reachable: false,
+            started: now.to_u64()
+        });
+
+        Some(nonce)
+    }
+
+    /// Handles a Peer Test block received in the session with `from`. Returns the
+    /// result when the block ends one of our own tests.
+    pub fn handle_in_session<N>(&mut self, from: &Hash256, test: PeerTest, now: I2pDate, network: &mut N)
+        -> Option<Reachability> where N: PeerTestNetwork
+    {
+        match test.message {
+            1 => {
+                let charlie = match network.choose_charlie(&[from.clone(), self.local.clone()]) {
+                    Some(charlie) => charlie,
+                    None => {
+                        let reply = test.relay(4, BOB_REJECT_NO_CHARLIE, None);
+                        let reply = self.sign(reply, &self.local, Some(from));
+                        network.send_in_session(from, reply);
+                        return None;
+                    }
+                };
+                if network.send_in_session(&charlie, test.relay(2, PEER_TEST_ACCEPT, Some(from.clone()))) {
+                    self.bob.insert(test.nonce, BobTest { alice: from.clone(), charlie, started: now.to_u64() });
+                }
+                None
+            }
+            2 => {
+                let alice = test.router.clone()?;
+                let code = match (self.verify(&test, &alice, from, None, network), test.address) {
+                    (None, _) => CHARLIE_REJECT_UNKNOWN_ALICE,
+                    (Some(false), _) => CHARLIE_REJECT_SIGNATURE,
+                    (Some(true), None) => CHARLIE_REJECT_ADDRESS,
+                    (Some(true), Some(_)) => PEER_TEST_ACCEPT
+                };
+                let address = match test.address {
+                    Some(address) if code == PEER_TEST_ACCEPT => address,
+                    _ => {
+                        let reply = self.sign(test.relay(3, code, None), from, Some(&alice));
+                        network.send_in_session(from, reply);
+                        return None;
+                    }
+                };
+                let reply = PeerTest::new(3, PEER_TEST_ACCEPT, test.nonce, seconds(now), Some(address));
+                network.send_in_session(from, self.sign(reply, from, Some(&alice)));
+                network.send_out_of_session(address, PeerTest::new(5, PEER_TEST_ACCEPT, test.nonce, seconds(now), Some(address)));
+                self.charlie.insert(test.nonce, CharlieTest { started: now.to_u64() });
+                None
+            }
+            3 => {
+                let relay = match self.bob.get(&test.nonce) {
+                    Some(relay) if relay.charlie == *from => self.bob.remove(&test.nonce)?,
+                    _ => return None
+                };
+                network.send_in_session(&relay.alice, test.relay(4, test.code, Some(relay.charlie)));
+                None
+            }
+            4 => {
+                match self.alice.get(&test.nonce) {
+                    Some(alice) if alice.bob == *from => {}
+                    _ => return None
+                }
+                let signer = match test.router {
+                    _ if test.code != PEER_TEST_ACCEPT && test.code < CHARLIE_CODES => from.clone(),
+                    Some(ref charlie) => charlie.clone(),
+                    None => return None
+                };
+                match self.verify(&test, &signer, from, Some(&self.local), network) {
+                    Some(false) => return None,
+                    Some(true) => {}
+                    None => {
+                        self.alice.remove(&test.nonce);
+                        return Some(Reachability::Unknown);
+                    }
+                }
+                let charlie_address = test.router.as_ref().and_then(|charlie| network.address_of(charlie));
+                match charlie_address {
+                    Some(charlie_address) if test.code == PEER_TEST_ACCEPT => {
+                        let alice = self.alice.get_mut(&test.nonce)?;
+                        alice.accepted = true;
+                        let message = PeerTest::new(6, PEER_TEST_ACCEPT, test.nonce, seconds(now), Some(alice.address));
+                        network.send_out_of_session(charlie_address, message);
+                        None
+                    }
+                    _ => {
+                        self.alice.remove(&test.nonce);
+                        Some(Reachability::Unknown)
+                    }
+                }
+            }
+            _ => None
+        }
+    }
+
+    /// Handles a Peer Test block received outside of any session from `from`.
+    /// Returns the result when the block ends one of our own tests.
+    pub fn handle_out_of_session<N>(&mut self, from: SocketAddr, test: PeerTest, now: I2pDate, network: &mut N)
+        -> Option<Reachability> where N: PeerTestNetwork
+    {
+        match test.message {
+            5 => {
+                if let Some(alice) = self.alice.get_mut(&test.nonce) {
+                    alice.reachable = true;
+                }
+                None
+            }
+            6 => {
+                if self.charlie.remove(&test.nonce).is_some() {
+                    network.send_out_of_session(from, PeerTest::new(7, PEER_TEST_ACCEPT, test.nonce, seconds(now), Some(from)));
+                }
+                None
+            }
+            7 => {
+                match self.alice.get(&test.nonce) {
+                    Some(alice) if alice.accepted => {}
+                    _ => return None
+                }
+                let alice = self.alice.remove(&test.nonce)?;
+                let reachability = match test.address {
+                    Some(address) if address != alice.address => Reachability::SymmetricNat,
+                    _ if alice.reachable => Reachability::Ok,
+                    _ => Reachability::Firewalled
+                };
+                Some(reachability)
+            }
+            _ => None
+        }
+    }
+
+    /// Drops the tests that have run longer than the timeout, and returns the results
+    /// of our own. A test in which Charlie reached us is still a success.
+    pub fn expire(&mut self, now: I2pDate) -> Vec<Reachability> {
+        let timeout = self.timeout;
+        let expired = |started: u64| started.saturating_add(timeout) < now.to_u64();
+
+        self.bob.retain(|_, test| !expired(test.started));
+        self.charlie.retain(|_, test| !expired(test.started));
+
+        let nonces: Vec<u32> = self.alice.iter()
+            .filter(|&(_, test)| expired(test.started))
+            .map(|(nonce, _)| *nonce)
+            .collect();
+        nonces.into_iter()
+            .filter_map(|nonce| self.alice.remove(&nonce))
+            .map(|test| if test.reachable { Reachability::Ok } else { Reachability::Unknown })
+            .collect()
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use rand;
+use rand::Rng;
+use common::{Hash256, I2pDate, ToI2pBase64, FromI2pBase64};
+use common::{SessionSigner, Signature, SignatureVerifier, SigningPublicKey};
+use transport::ssu2::block::Block;
+use transport::ssu2::endpoint::{endpoint_len, encode_endpoint, decode_endpoint};
+use transport::ssu2::error::Ssu2Error;
+
+
+/// The code of a Relay Response that accepts the introduction.
+pub const RELAY_ACCEPT: u8 = 0;
+
+/// Bob rejects the introduction because no router holds the relay tag.
+pub const BOB_REJECT_TAG_NOT_FOUND: u8 = 5;
+
+/// Charlie rejects the introduction for a reason without a code of its own, such as
+/// not knowing his own address.
+pub const CHARLIE_REJECT_UNSPECIFIED: u8 = 64;
+
+/// Charlie rejects the introduction because Alice's address is missing or unusable.
+pub const CHARLIE_REJECT_ADDRESS: u8 = 65;
+
+/// Charlie rejects the introduction because Alice's signature does not verify.
+pub const CHARLIE_REJECT_SIGNATURE: u8 = 67;
+
+/// Charlie rejects the introduction because he does not have Alice's RouterInfo.
+pub const CHARLIE_REJECT_UNKNOWN_ALICE: u8 = 70;
+
+/// The most introducers we publish.
+pub const MAX_INTRODUCERS: usize = 3;
+
+/// Introducers are published as valid for an hour.
+const INTRODUCER_LIFETIME_SECONDS: u32 = 60 * 60;
+
+/// Relays and requests that have not finished after this many milliseconds are dropped.
+const DEFAULT_TIMEOUT_MILLISECONDS: u64 = 20 * 1000;
+
+const RELAY_VERSION: u8 = 2;
+
+/// The prologues of the signed data of requests and responses.
+const REQUEST_PROLOGUE: &[u8] = b"RelayRequestData";
+const RESPONSE_PROLOGUE: &[u8] = b"RelayAgreementOK";
+const HASH_LENGTH: usize = 32;
+const TOKEN_LENGTH: usize = 8;
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32))
+}
+
+fn seconds(now: I2pDate) -> u32 {
+    (now.to_u64() / 1000) as u32
+}
+
+fn verify_signature<V>(verifier: &V, key: &SigningPublicKey, message: &[u8], signature: &[u8]) -> bool
+    where V: SignatureVerifier
+{
+    match Signature::from_bytes(key.signature_type(), signature) {
+        Some(signature) => verifier.verify(key, message, &signature),
+        None => false
+    }
+}
+
+/// A `RelayRequest` is sent by Alice to Bob, asking him to introduce her to the
+/// router that holds `tag`. Alice signs it for Charlie, who checks the signature
+/// against her RouterInfo.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayRequest {
+    pub nonce: u32,
+    pub tag: u32,
+    pub timestamp: u32,
+    pub address: Option<SocketAddr>,
+    pub signature: Vec<u8>
+}
+
+/// A `RelayIntro` is a relay request forwarded by Bob to Charlie, along with the
+/// hash of Alice's router.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayIntro {
+    pub alice: Hash256,
+    pub nonce: u32,
+    pub tag: u32,
+    pub timestamp: u32,
+    pub address: Option<SocketAddr>,
+    pub signature: Vec<u8>
+}
+
+/// A `RelayResponse` answers a relay request. When Charlie accepts it carries his
+/// address and a token for Alice's SessionRequest. Charlie sends it to Bob, who
+/// forwards it to Alice, and to Alice directly as the hole punch. It is signed by
+/// Charlie, or by Bob when Bob rejects the request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayResponse {
+    pub code: u8,
+    pub nonce: u32,
+    pub timestamp: u32,
+    pub address: Option<SocketAddr>,
+    pub signature: Vec<u8>,
+    pub token: Option<u64>
+}
+
+impl RelayRequest {
+    pub fn encoded_len(&self) -> usize {
+        1 + 12 + 1 + endpoint_len(self.address) + self.signature.len()
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(0x00);
+        buf.extend_from_slice(&self.nonce.to_be_bytes());
+        buf.extend_from_slice(&self.tag.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.push(RELAY_VERSION);
+        encode_endpoint(buf, self.address);
+        buf.extend_from_slice(&self.signature);
+    }
+
+    /// Returns the data Alice signs: a prologue, the hashes of Bob and Charlie, and
+    /// the request from its nonce to Alice's address.
+    pub fn signed_bytes(&self, bob: &Hash256, charlie: &Hash256) -> Vec<u8> {
+        signed_request_bytes(bob, charlie, self.nonce, self.tag, self.timestamp, self.address)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<RelayRequest, Ssu2Error> {
+        if data.len() < 14 || data[13] != RELAY_VERSION {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+        let (address, length) = decode_endpoint(&data[14..])?;
+
+        Ok(RelayRequest {
+            nonce: read_u32(&data[1..5]),
+            tag: read_u32(&data[5..9]),
+            timestamp: read_u32(&data[9..13]),
+            address,
+            signature: data[14 + length..].to_vec()
+        })
+    }
+}
+
+fn signed_request_bytes(bob: &Hash256, charlie: &Hash256, nonce: u32, tag: u32, timestamp: u32,
+                        address: Option<SocketAddr>) -> Vec<u8> {
+    let mut bytes = REQUEST_PROLOGUE.to_vec();
+    bytes.extend_from_slice(bob.as_ref());
+    bytes.extend_from_slice(charlie.as_ref());
+    bytes.extend_from_slice(&nonce.to_be_bytes());
+    bytes.extend_from_slice(&tag.to_be_bytes());
+    bytes.extend_from_slice(&timestamp.to_be_bytes());
+    bytes.push(RELAY_VERSION);
+    encode_endpoint(&mut bytes, address);
+
+    bytes
+}
+
+impl RelayIntro {
+    fn from_request(alice: Hash256, request: RelayRequest) -> RelayIntro {
+        RelayIntro {
+            alice,
+            nonce: request.nonce,
+            tag: request.tag,
+            timestamp: request.timestamp,
+            address: request.address,
+            signature: request.signature
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        1 + HASH_LENGTH + 12 + 1 + endpoint_len(self.address) + self.signature.len()
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(0x00);
+        buf.extend_from_slice(self.alice.as_ref());
+        buf.extend_from_slice(&self.nonce.to_be_bytes());
+        buf.extend_from_slice(&self.tag.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.push(RELAY_VERSION);
+        encode_endpoint(buf, self.address);
+        buf.extend_from_slice(&self.signature);
+    }
+
+    /// Returns the data Alice signed, as in `RelayRequest::signed_bytes`.
+    pub fn signed_bytes(&self, bob: &Hash256, charlie: &Hash256) -> Vec<u8> {
+        signed_request_bytes(bob, charlie, self.nonce, self.tag, self.timestamp, self.address)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<RelayIntro, Ssu2Error> {
+        let start = 1 + HASH_LENGTH;
+        if data.len() < start + 13 || data[start + 12] != RELAY_VERSION {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+        let mut alice = [0x00; HASH_LENGTH];
+        alice.copy_from_slice(&data[1..start]);
+        let (address, length) = decode_endpoint(&data[start + 13..])?;
+
+        Ok(RelayIntro {
+            alice: Hash256::from(alice),
+            nonce: read_u32(&data[start..start + 4]),
+            tag: read_u32(&data[start + 4..start + 8]),
+            timestamp: read_u32(&data[start + 8..start + 12]),
+            address,
+            signature: data[start + 13 + length..].to_vec()
+        })
+    }
+}
+
+impl RelayResponse {
+    pub fn new(code: u8, nonce: u32, timestamp: u32, address: Option<SocketAddr>, token: Option<u64>) -> RelayResponse {
+        RelayResponse {
+            code,
+            nonce,
+            timestamp,
+            address,
+            signature: Vec::new(),
+            token
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let token = if self.token.is_some() { TOKEN_LENGTH } else { 0 };
+
+        2 + 8 + 1 + endpoint_len(self.address) + self.signature.len() + token
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&[0x00, self.code]);
+        buf.extend_from_slice(&self.nonce.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.push(RELAY_VERSION);
+        encode_endpoint(buf, self.address);
+        buf.extend_from_slice(&self.signature);
+        if let Some(token) = self.token {
+            buf.extend_from_slice(&token.to_be_bytes());
+        }
+    }
+
+    /// Returns the data the sender signs: a prologue, Bob's hash, and the response
+    /// from its nonce to Charlie's address.
+    pub fn signed_bytes(&self, bob: &Hash256) -> Vec<u8> {
+        let mut bytes = RESPONSE_PROLOGUE.to_vec();
+        bytes.extend_from_slice(bob.as_ref());
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.push(RELAY_VERSION);
+        encode_endpoint(&mut bytes, self.address);
+
+        bytes
+    }
+
+    /// Returns whether Bob rather than Charlie sent the response.
+    pub fn from_bob(&self) -> bool {
+        self.code != RELAY_ACCEPT && self.code < CHARLIE_REJECT_UNSPECIFIED
+    }
+
+    /// Decodes a response. Only accepting responses carry a token, in their last
+    /// eight bytes.
+    pub fn decode(data: &[u8]) -> Result<RelayResponse, Ssu2Error> {
+        if data.len() < 11 || data[10] != RELAY_VERSION {
+            return Err(Ssu2Error::InvalidBlock);
+        }
+        let code = data[1];
+        let (address, length) = decode_endpoint(&data[11..])?;
+        let rest = &data[11 + length..];
+        let (signature, token) = if code == RELAY_ACCEPT {
+            if rest.len() < TOKEN_LENGTH {
+                return Err(Ssu2Error::InvalidBlock);
+            }
+            let (signature, token) = rest.split_at(rest.len() - TOKEN_LENGTH);
+            let token = token.iter().fold(0, |value, byte| (value << 8) | (*byte as u64));
+            (signature, Some(token))
+        } else {
+            (rest, None)
+        };
+
+        Ok(RelayResponse {
+            code,
+            nonce: read_u32(&data[2..6]),
+            timestamp: read_u32(&data[6..10]),
+            address,
+            signature: signature.to_vec(),
+            token
+        })
+    }
+}
+
+/// An `Introducer` is a router that holds a relay tag for us and will introduce
+/// other routers to us. Its expiration is in seconds since the UNIX epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Introducer {
+    pub router: Hash256,
+    pub tag: u32,
+    pub expiration: u32
+}
+
+/// Returns the `ihN`, `itagN` and `iexpN` options that publish the introducers in
+/// an SSU2 `RouterAddress`.
+pub fn introducer_options(introducers: &[Introducer]) -> Vec<(String, String)> {
+    let mut options = Vec::new();
+    for (i, introducer) in introducers.iter().enumerate() {
+        options.push((format!("ih{}", i), introducer.router.as_ref().to_i2p_base64()));
+        options.push((format!("itag{}", i), introducer.tag.to_string()));
+        options.push((format!("iexp{}", i), introducer.expiration.to_string()));
+    }
+
+    options
+}
+
+/// Reads the introducers published in the options of an SSU2 `RouterAddress`.
+/// Incomplete or malformed introducers are skipped.
+pub fn parse_introducers(options: &[(String, String)]) -> Vec<Introducer> {
+    let option = |name: String| options.iter().find(|option| option.0 == name).map(|option| &option.1);
+
+    (0..MAX_INTRODUCERS)
+        .filter_map(|i| {
+            let hash = option(format!("ih{}", i))?.as_str().from_i2p_base64()?;
+            if hash.len() != HASH_LENGTH {
+                return None;
+            }
+            let mut router = [0x00; HASH_LENGTH];
+            router.copy_from_slice(&hash);
+
+            Some(Introducer {
+                router: Hash256::from(router),
+                tag: option(format!("itag{}", i))?.parse().ok()?,
+                expiration: option(format!("iexp{}", i))?.parse().ok()?
+            })
+        })
+        .collect()
+}
+
+/// The outcome of a relay request we made as Alice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayOutcome {
+    /// Charlie accepted: connect to him with the token.
+    Accepted { charlie: SocketAddr, token: u64 },
+    /// Bob or Charlie refused with this code.
+    Rejected(u8)
+}
+
+/// The `RelayNetwork` trait is the view of the SSU2 transport that relaying needs.
+pub trait RelayNetwork {
+    /// Sends a block in the session with `peer`. Returns `false` if there is no
+    /// session with the peer.
+    fn send_in_session(&mut self, peer: &Hash256, block: Block) -> bool;
+
+    /// Sends the hole punch to Alice, outside of any session.
+    fn send_hole_punch(&mut self, address: SocketAddr, response: RelayResponse);
+
+    /// Returns the signing key in the RouterInfo of `router`, if we have it.
+    fn signing_key(&self, router: &Hash256) -> Option<SigningPublicKey>;
+}
+
+/// A relay request we made as Alice.
+struct AliceRequest {
+    bob: Hash256,
+    charlie: Hash256,
+    started: u64
+}
+
+/// An introduction we are relaying as Bob.
+struct PendingRelay {
+    alice: Hash256,
+    started: u64
+}
+
+/// A `RelayManager` handles relaying in all three roles. As a firewalled Charlie it
+/// collects relay tags from introducers and keeps the set it publishes up to date;
+/// as Bob it hands out relay tags and passes introductions between Alice and
+/// Charlie; as Alice it asks an introducer to introduce it to Charlie. Requests and
+/// responses are signed with our router's signing key.
+pub struct RelayManager<S, V> {
+    local: Hash256,
+    address: Option<SocketAddr>,
+    signer: S,
+    verifier: V,
+    introducers: Vec<Introducer>,
+    requested: Vec<Hash256>,
+    changed: bool,
+    tags: HashMap<u32, Hash256>,
+    relays: HashMap<u32, PendingRelay>,
+    requests: HashMap<u32, AliceRequest>,
+    timeout: u64
+}
+
+impl<S, V> RelayManager<S, V> where S: SessionSigner, V: SignatureVerifier {
+    /// Creates a manager for our router, `local`, which signs with `signer`.
+    pub fn new(local: Hash256, signer: S, verifier: V) -> RelayManager<S, V> {
+        RelayManager {
+            local,
+            address: None,
+            signer,
+            verifier,
+            introducers: Vec::new(),
+            requested: Vec::new(),
+            changed: false,
+            tags: HashMap::new(),
+            relays: HashMap::new(),
+            requests: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT_MILLISECONDS
+        }
+    }
+
+    /// Sets how long, in milliseconds, relays and requests may take.
+    pub fn set_timeout(&mut self, milliseconds: u64) {
+        self.timeout = milliseconds;
+    }
+
+    /// Sets our address as other routers see it. As Charlie we send it to Alice,
+    /// and cannot accept introductions without it.
+    pub fn set_address(&mut self, address: Option<SocketAddr>) {
+        self.address = address;
+    }
+
+    fn signature(&self, message: &[u8]) -> Vec<u8> {
+        self.signer.sign(message).as_ref().to_vec()
+    }
+
+    /// Signs a response to the request with `nonce`, relayed by `bob`.
+    fn response(&self, bob: &Hash256, code: u8, nonce: u32, now: I2pDate, token: Option<u64>) -> RelayResponse {
+        let address = if code == RELAY_ACCEPT { self.address } else { None };
+        let mut response = RelayResponse::new(code, nonce, seconds(now), address, token);
+        response.signature = self.signature(&response.signed_bytes(bob));
+
+        response
+    }
+
+    /// Returns the introducers to publish.
+    pub fn introducers(&self) -> &[Introducer] {
+        self.introducers.as_ref()
+    }
+
+    /// Returns whether the introducers changed since the last call, in which case
+    /// our RouterInfo must be published again.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+
+        changed
+    }
+
+    /// Returns the router holding a relay tag we handed out.
+    pub fn tag_holder(&self, tag: u32) -> Option<&Hash256> {
+        self.tags.get(&tag)
+    }
+
+    /// Asks routers we have sessions with for relay tags, until enough introducers
+    /// are published or asked. Returns the number of requests sent.
+    pub fn request_tags<N: RelayNetwork>(&mut self, candidates: &[Hash256], network: &mut N) -> usize {
+        let mut sent = 0;
+        for candidate in candidates {
+            if self.introducers.len() + self.requested.len() >= MAX_INTRODUCERS {
+                break;
+            }
+            let known = self.introducers.iter().any(|introducer| introducer.router == *candidate);
+            if known || self.requested.contains(candidate) {
+                continue;
+            }
+            if network.send_in_session(candidate, Block::RelayTagRequest) {
+                self.requested.push(candidate.clone());
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    /// Asks an introducer of `charlie` to introduce us, at `address`, to him.
+    /// Returns the nonce of the request, or `None` if it could not be sent.
+    pub fn request_relay<N>(&mut self, charlie: &Hash256, introducer: &Introducer, address: SocketAddr, now: I2pDate,
+                            network: &mut N) -> Option<u32> where N: RelayNetwork
+    {
+        let mut request = RelayRequest {
+            nonce: rand::thread_rng().gen::<u32>(),
+            tag: introducer.tag,
+            timestamp: seconds(now),
+            address: Some(address),
+            signature: Vec::new()
+        };
+        request.signature = self.signature(&request.signed_bytes(&introducer.router, charlie));
+        let nonce = request.nonce;
+        if !network.send_in_session(&introducer.router, Block::RelayRequest(request)) {
+            return None;
+        }
+        self.requests.insert(nonce, AliceRequest {
+            bob: introducer.router.clone(),
+            charlie: charlie.clone(),
+            started: now.to_u64()
+        });
+
+        Some(nonce)
+    }
+
+    /// Checks a response to one of our requests against the key of its signer.
+    fn verify_response<N: RelayNetwork>(&self, request: &AliceRequest, response: &RelayResponse, network: &N) -> bool {
+        let signer = if response.from_bob() { &request.bob } else { &request.charlie };
+        match network.signing_key(signer) {
+            Some(key) => verify_signature(&self.verifier, &key, &response.signed_bytes(&request.bob), &response.signature),
+            None => false
+        }
+    }
+
+    /// Returns the outcome of an accepted response to one of our requests. Returns
+    /// `None` if the response is not for us, does not verify or is incomplete.
+    fn accept<N: RelayNetwork>(&mut self, response: &RelayResponse, network: &N) -> Option<RelayOutcome> {
+        let request = self.requests.get(&response.nonce)?;
+        if !self.verify_response(request, response, network) {
+            return None;
+        }
+        let outcome = match (response.address, response.token) {
+            (Some(charlie), Some(token)) => RelayOutcome::Accepted { charlie, token },
+            _ => return None
+        };
+        self.requests.remove(&response.nonce);
+
+        Some(outcome)
+    }
+
+    /// Handles a relay block received in the session with `from`. Returns the
+    /// outcome when the block answers one of our own requests.
+    pub fn handle_in_session<N>(&mut self, from: &Hash256, block: Block, now: I2pDate, network: &mut N)
+        -> Option<RelayOutcome> where N: RelayNetwork
+    {
+        match block {
+            Block::RelayTagRequest => {
+                let mut rng = rand::thread_rng();
+                let mut tag = 0;
+                while tag == 0 || self.tags.contains_key(&tag) {
+                    tag = rng.gen::<u32>();
+                }
+                self.tags.insert(tag, from.clone());
+                network.send_in_session(from, Block::RelayTag(tag));
+                None
+            }
+            Block::RelayTag(tag) => {
+                let index = self.requested.iter().position(|router| router == from)?;
+                self.requested.remove(index);
+                self.introducers.push(Introducer {
+                    router: from.clone(),
+                    tag,
+                    expiration: seconds(now) + INTRODUCER_LIFETIME_SECONDS
+                });
+                self.changed = true;
+                None
+            }
+            Block::RelayRequest(request) => {
+                let charlie = match self.tags.get(&request.tag) {
+                    Some(charlie) if charlie != from => charlie.clone(),
+                    _ => {
+                        let response = self.response(&self.local, BOB_REJECT_TAG_NOT_FOUND, request.nonce, now, None);
+                        network.send_in_session(from, Block::RelayResponse(response));
+                        return None;
+                    }
+                };
+                let nonce = request.nonce;
+                if network.send_in_session(&charlie, Block::RelayIntro(RelayIntro::from_request(from.clone(), request))) {
+                    self.relays.insert(nonce, PendingRelay { alice: from.clone(), started: now.to_u64() });
+                }
+                None
+            }
+            Block::RelayIntro(intro) => {
+                let signed = intro.signed_bytes(from, &self.local);
+                let code = match (network.signing_key(&intro.alice), intro.address, self.address) {
+                    (None, _, _) => CHARLIE_REJECT_UNKNOWN_ALICE,
+                    (Some(ref key), _, _) if !verify_signature(&self.verifier, key, &signed, &intro.signature) => {
+                        CHARLIE_REJECT_SIGNATURE
+                    }
+                    (_, None, _) => CHARLIE_REJECT_ADDRESS,
+                    (_, _, None) => CHARLIE_REJECT_UNSPECIFIED,
+                    _ => RELAY_ACCEPT
+                };
+                if code != RELAY_ACCEPT {
+                    let response = self.response(from, code, intro.nonce, now, None);
+                    network.send_in_session(from, Block::RelayResponse(response));
+                    return None;
+                }
+
+                let token = rand::thread_rng().gen::<u64>();
+                let response = self.response(from, RELAY_ACCEPT, intro.nonce, now, Some(token));
+                network.send_in_session(from, Block::RelayResponse(response.clone()));
+                network.send_hole_punch(intro.address?, response);
+                None
+            }
+            Block::RelayResponse(response) => {
+                if let Some(relay) = self.relays.remove(&response.nonce) {
+                    network.send_in_session(&relay.alice, Block::RelayResponse(response));
+                    return None;
+                }
+                match self.requests.get(&response.nonce) {
+                    Some(request) if request.bob == *from => {}
+                    _ => return None
+                }
+                if response.code == RELAY_ACCEPT {
+                    return self.accept(&response, network);
+                }
+                if !self.verify_response(&self.requests[&response.nonce], &response, network) {
+                    return None;
+                }
+                self.requests.remove(&response.nonce);
+                Some(RelayOutcome::Rejected(response.code))
+            }
+            _ => None
+        }
+    }
+
+    /// Handles a hole punch from Charlie, received at `from` outside of any
+    /// session. Returns the outcome if it answers one of our requests before the
+    /// response Bob forwards. Charlie is reached at the address the hole punch came
+    /// from, which is the one that passes his NAT.
+    pub fn handle_hole_punch<N>(&mut self, from: SocketAddr, mut response: RelayResponse, network: &N) -> Option<RelayOutcome>
+        where N: RelayNetwork
+    {
+        if response.code != RELAY_ACCEPT {
+            return None;
+        }
+        response.address = response.address.or(Some(from));
+        match self.accept(&response, network)? {
+            RelayOutcome::Accepted { token, .. } => Some(RelayOutcome::Accepted { charlie: from, token }),
+            outcome => Some(outcome)
+        }
+    }
+
+    /// Forgets the relay tags and introducers of a router whose session closed.
+    pub fn session_closed(&mut self, peer: &Hash256) {
+        self.tags.retain(|_, holder| holder != peer);
+        self.requested.retain(|router| router != peer);
+
+        let before = self.introducers.len();
+        self.introducers.retain(|introducer| introducer.router != *peer);
+        if self.introducers.len() != before {
+            self.changed = true;
+        }
+    }
+
+    /// Drops expired introducers and relays and requests that have run longer than
+    /// the timeout.
+    pub fn expire(&mut self, now: I2pDate) {
+        let before = self.introducers.len();
+        self.introducers.retain(|introducer| introducer.expiration > seconds(now));
+        if self.introducers.len() != before {
+            self.changed = true;
+        }
+
+        let deadline = now.to_u64().saturating_sub(self.timeout);
+        self.relays.retain(|_, relay| relay.started >= deadline);
+        self.requests.retain(|_, request| request.started >= deadline);
+    }
+}
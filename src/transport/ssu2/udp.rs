@@ -0,0 +1,688 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use rand;
+use rand::Rng;
+use common::{Hash256, Hashable256, I2pDate, RouterInfo, SignatureVerifier};
+use i2np::I2npMessage;
+use transport::noise::{KEY_LENGTH, encrypt, decrypt};
+use transport::ntcp2::{NETWORK_ID, TerminationReason};
+use transport::ssu2::ack::{AckTracker, MAX_ACK_RANGES};
+use transport::ssu2::block::{Block, BLOCK_HEADER_LENGTH, encode_blocks, decode_blocks};
+use transport::ssu2::error::Ssu2Error;
+use transport::ssu2::fragment::{Reassembler, fragment};
+use transport::ssu2::handshake::{Established, Initiator, PeerKeys, Responder, StaticKeys, SSU2_STYLE};
+use transport::ssu2::handshake::{EPHEMERAL_KEY_END, MAC_LENGTH, open_with_intro_key, retry, token_request};
+use transport::ssu2::header::{LongHeader, ShortHeader, connection_id, mask_connection_id, mask_header};
+use transport::ssu2::header::{obfuscate_long_header, protect};
+use transport::ssu2::header::{LONG_HEADER_LENGTH, SHORT_HEADER_LENGTH, MIN_PACKET_LENGTH, PROTOCOL_VERSION};
+use transport::ssu2::header::{SESSION_REQUEST, SESSION_CREATED, SESSION_CONFIRMED, DATA, RETRY, TOKEN_REQUEST};
+use transport::ssu2::path::PathValidator;
+
+
+/// The largest packet we send, which fits an IPv4 UDP datagram in a 1500 byte MTU.
+pub const MAX_PACKET_LENGTH: usize = 1472;
+
+/// The room for blocks in a data packet.
+const MAX_PAYLOAD_LENGTH: usize = MAX_PACKET_LENGTH - SHORT_HEADER_LENGTH - MAC_LENGTH;
+
+/// The room kept in every data packet for an ACK block with all its ranges.
+const ACK_RESERVE: usize = BLOCK_HEADER_LENGTH + 5 + 2 * MAX_ACK_RANGES;
+
+/// Header protection reads its nonces from the last 24 bytes of a packet, so data
+/// packets carry at least this much payload.
+const MIN_PAYLOAD_LENGTH: usize = MIN_PACKET_LENGTH - SHORT_HEADER_LENGTH - MAC_LENGTH;
+
+/// The tokens we hand out in Retry messages are valid for one minute.
+const TOKEN_LIFETIME_MILLISECONDS: u64 = 60 * 1000;
+
+/// The most tokens outstanding at once by default. Source addresses can be
+/// spoofed, so the oldest tokens make room for new ones beyond this.
+const DEFAULT_MAX_ISSUED_TOKENS: usize = 4096;
+
+/// Unacknowledged packets are sent again after one second.
+const RESEND_TIMEOUT_MILLISECONDS: u64 = 1000;
+
+/// Sessions are terminated once a packet goes unacknowledged after this many
+/// retransmissions.
+const DEFAULT_MAX_RETRANSMISSIONS: u32 = 8;
+
+/// Packet numbers do not wrap. The last one is kept for the Termination block of a
+/// session that runs out of the others.
+const LAST_PACKET_NUMBER: u32 = u32::MAX;
+
+/// An `Event` is what `UdpEndpoint::receive` reports to the router.
+#[derive(Debug)]
+pub enum Event {
+    /// A session is established with the router whose RouterInfo this is, whichever
+    /// side started it.
+    Established(RouterInfo),
+    /// A complete I2NP message from a peer.
+    Message { peer: Hash256, message: I2npMessage },
+    /// A block the endpoint leaves to other components, such as the Peer Test and
+    /// Relay blocks.
+    Block { peer: Hash256, block: Block },
+    /// A peer moved to a new address, which answered our Path Challenge.
+    PathValidated { peer: Hash256, address: SocketAddr },
+    /// The session ended, because the peer closed it, or because we did when it ran
+    /// out of packet numbers or the peer stopped acknowledging our packets.
+    Terminated { peer: Hash256, reason: TerminationReason }
+}
+
+/// The data phase of an established session.
+struct Session {
+    peer: Hash256,
+    address: SocketAddr,
+    destination_id: u64,
+    peer_intro_key: [u8; KEY_LENGTH],
+    keys: Established,
+    next_packet_number: u32,
+    received: u64,
+    acks: AckTracker,
+    reassembler: Reassembler,
+    paths: PathValidator,
+    unacked: BTreeMap<u32, Unacked>
+}
+
+/// The blocks of a packet the peer has not acknowledged.
+struct Unacked {
+    blocks: Vec<Block>,
+    sent: u64,
+    transmissions: u32
+}
+
+impl Session {
+    fn new(peer: Hash256, address: SocketAddr, destination_id: u64, peer_intro_key: [u8; KEY_LENGTH],
+           keys: Established, next_packet_number: u32) -> Session {
+        Session {
+            peer,
+            address,
+            destination_id,
+            peer_intro_key,
+            keys,
+            next_packet_number,
+            received: 0,
+            acks: AckTracker::new(),
+            reassembler: Reassembler::new(),
+            paths: PathValidator::new(),
+            unacked: BTreeMap::new()
+        }
+    }
+
+    /// Determines whether only the packet number for the Termination block is left.
+    fn exhausted(&self) -> bool {
+        self.next_packet_number == LAST_PACKET_NUMBER
+    }
+
+    /// Builds a data packet with an ACK block and `blocks`. Packets with blocks are
+    /// kept until the peer acknowledges them. Callers check `exhausted` first, as
+    /// only a Termination block may take the last packet number.
+    fn seal(&mut self, blocks: Vec<Block>, now: I2pDate) -> Vec<u8> {
+        self.seal_transmission(blocks, 1, now)
+    }
+
+    /// Builds a packet like `seal`, for blocks sent `transmissions` times so far.
+    fn seal_transmission(&mut self, blocks: Vec<Block>, transmissions: u32, now: I2pDate) -> Vec<u8> {
+        let packet_number = self.next_packet_number;
+        self.next_packet_number = packet_number.saturating_add(1);
+
+        let mut payload_blocks: Vec<Block> = self.acks.ack().map(Block::Ack).into_iter().collect();
+        payload_blocks.extend(blocks.iter().cloned());
+        let length = payload_blocks.iter().map(|block| block.encoded_len()).sum::<usize>();
+        if length < MIN_PAYLOAD_LENGTH {
+            let mut padding = vec![0x00; MIN_PAYLOAD_LENGTH - length];
+            rand::thread_rng().fill_bytes(&mut padding);
+            payload_blocks.push(Block::Padding(padding));
+        }
+
+        let header = ShortHeader {
+            destination_id: self.destination_id,
+            packet_number,
+            message_type: DATA,
+            flags: [0x00; 3]
+        }.to_bytes();
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&encrypt(&self.keys.send.data_key, packet_number as u64, &header,
+                                          &encode_blocks(&payload_blocks)));
+        protect(&mut packet, &self.peer_intro_key, &self.keys.send.header_key, SHORT_HEADER_LENGTH);
+
+        if !blocks.is_empty() {
+            self.unacked.insert(packet_number, Unacked { blocks, sent: now.to_u64(), transmissions });
+        }
+
+        packet
+    }
+}
+
+/// A handshake we started, keyed by the peer's address.
+struct Outbound {
+    peer: RouterInfo,
+    keys: PeerKeys,
+    source_id: u64,
+    destination_id: u64,
+    initiator: Option<Initiator>
+}
+
+/// A handshake a peer started, keyed by the connection ID it picked for us.
+struct Inbound {
+    responder: Responder,
+    address: SocketAddr,
+    destination_id: u64
+}
+
+/// Returns the socket address published in a router's SSU2 address.
+fn peer_address(router_info: &RouterInfo) -> Option<SocketAddr> {
+    let address = router_info.address(SSU2_STYLE)?;
+    let ip = address.option("host")?.parse::<IpAddr>().ok()?;
+    let port = address.option("port")?.parse::<u16>().ok()?;
+
+    Some(SocketAddr::new(ip, port))
+}
+
+/// A `UdpEndpoint` runs SSU2 on one UDP socket: the token and Noise XK handshake
+/// in both directions, and the data phase of every session it establishes. It is
+/// driven by `receive`, which handles one packet at a time, and `retransmit`.
+pub struct UdpEndpoint<V> {
+    socket: UdpSocket,
+    keys: StaticKeys,
+    router_info: RouterInfo,
+    verifier: V,
+    outbound: HashMap<SocketAddr, Outbound>,
+    inbound: HashMap<u64, Inbound>,
+    sessions: HashMap<u64, Session>,
+    peers: HashMap<Hash256, u64>,
+    issued: HashMap<SocketAddr, (u64, u64)>,
+    events: VecDeque<Event>,
+    max_retransmissions: u32,
+    max_issued_tokens: usize
+}
+
+impl<V> UdpEndpoint<V> where V: SignatureVerifier {
+    /// Runs SSU2 on `socket`. `router_info` is ours, and its SSU2 address must
+    /// publish `keys`, as peers take our static key and intro key from it.
+    pub fn new(socket: UdpSocket, keys: StaticKeys, router_info: RouterInfo, verifier: V) -> UdpEndpoint<V> {
+        UdpEndpoint {
+            socket,
+            keys,
+            router_info,
+            verifier,
+            outbound: HashMap::new(),
+            inbound: HashMap::new(),
+            sessions: HashMap::new(),
+            peers: HashMap::new(),
+            issued: HashMap::new(),
+            events: VecDeque::new(),
+            max_retransmissions: DEFAULT_MAX_RETRANSMISSIONS,
+            max_issued_tokens: DEFAULT_MAX_ISSUED_TOKENS
+        }
+    }
+
+    /// Sets how many times a packet is sent again before its session is terminated.
+    pub fn set_max_retransmissions(&mut self, retransmissions: u32) {
+        self.max_retransmissions = retransmissions;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_max_issued_tokens(&mut self, max_issued_tokens: usize) {
+        self.max_issued_tokens = max_issued_tokens;
+    }
+
+    /// Moves the packet numbering of the session with `peer` to `packet_number`.
+    #[cfg(test)]
+    pub(crate) fn set_next_packet_number(&mut self, peer: &Hash256, packet_number: u32) {
+        let id = self.peers[peer];
+        self.sessions.get_mut(&id).expect("the peer has a session").next_packet_number = packet_number;
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sets how long `receive` waits for a packet.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    pub fn is_connected(&self, peer: &Hash256) -> bool {
+        self.peers.contains_key(peer)
+    }
+
+    /// Returns the number of packets sent to `peer` that it has not acknowledged.
+    pub fn unacked(&self, peer: &Hash256) -> usize {
+        self.session(peer).map_or(0, |session| session.unacked.len())
+    }
+
+    fn session(&self, peer: &Hash256) -> Option<&Session> {
+        self.peers.get(peer).and_then(|id| self.sessions.get(id))
+    }
+
+    /// Starts a handshake with `peer` by asking it for a token. The handshake then
+    /// continues in `receive`, with a SessionRequest once the Retry arrives.
+    pub fn connect(&mut self, peer: &RouterInfo, now: I2pDate) -> Result<(), Ssu2Error> {
+        let keys = PeerKeys::from_router_info(peer).ok_or(Ssu2Error::InvalidAddress)?;
+        let address = peer_address(peer).ok_or(Ssu2Error::InvalidAddress)?;
+
+        let mut rng = rand::thread_rng();
+        let header = LongHeader {
+            destination_id: rng.gen(),
+            packet_number: rng.gen(),
+            message_type: TOKEN_REQUEST,
+            network_id: NETWORK_ID,
+            source_id: rng.gen(),
+            token: 0
+        };
+        self.socket.send_to(&token_request(header, &keys, now), address)?;
+
+        self.outbound.insert(address, Outbound {
+            peer: peer.clone(),
+            keys,
+            source_id: header.source_id,
+            destination_id: header.destination_id,
+            initiator: None
+        });
+
+        Ok(())
+    }
+
+    /// Receives and handles one packet. Returns the event it caused, if any. Events
+    /// queued by an earlier packet are returned first, without reading the socket.
+    pub fn receive(&mut self, now: I2pDate) -> Result<Option<Event>, Ssu2Error> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        let mut buffer = [0x00; MAX_PACKET_LENGTH];
+        let (length, address) = self.socket.recv_from(&mut buffer)?;
+        if length < MIN_PACKET_LENGTH {
+            return Err(Ssu2Error::InvalidPacket);
+        }
+
+        let mut packet = buffer[..length].to_vec();
+        mask_connection_id(&mut packet, self.keys.intro_key());
+        let id = connection_id(&packet);
+        if self.sessions.contains_key(&id) {
+            self.receive_data(id, packet, address, now)?;
+        } else if self.inbound.contains_key(&id) {
+            self.receive_session_confirmed(id, packet)?;
+        } else if let Some(reply) = self.outbound_reply(&buffer[..length], address) {
+            self.receive_reply(reply, address, now)?;
+        } else {
+            self.receive_new(packet, address, now)?;
+        }
+
+        Ok(self.events.pop_front())
+    }
+
+    /// Checks whether a packet from `address` answers a handshake we started.
+    /// Returns it with the connection ID unmasked if it does.
+    fn outbound_reply(&self, packet: &[u8], address: SocketAddr) -> Option<Vec<u8>> {
+        let outbound = self.outbound.get(&address)?;
+        let mut reply = packet.to_vec();
+        mask_connection_id(&mut reply, &outbound.keys.intro_key);
+
+        if connection_id(&reply) == outbound.source_id {
+            Some(reply)
+        } else {
+            None
+        }
+    }
+
+    /// Determines whether an unprotected long header has the message type, version
+    /// and network we expect.
+    fn is_long_header(packet: &[u8], message_type: u8) -> bool {
+        packet.len() >= LONG_HEADER_LENGTH && packet[12] == message_type
+            && packet[13] == PROTOCOL_VERSION && packet[14] == NETWORK_ID
+    }
+
+    /// Handles a SessionCreated or Retry from a peer we are connecting to.
+    fn receive_reply(&mut self, packet: Vec<u8>, address: SocketAddr, now: I2pDate) -> Result<(), Ssu2Error> {
+        let mut outbound = match self.outbound.remove(&address) {
+            Some(outbound) => outbound,
+            None => return Err(Ssu2Error::InvalidPacket)
+        };
+
+        let created = outbound.initiator.as_ref().map(|initiator| {
+            let mut created = packet.clone();
+            mask_header(&mut created, initiator.created_header_key());
+            obfuscate_long_header(&mut created, initiator.created_header_key(), EPHEMERAL_KEY_END);
+            created
+        });
+        if let Some(created) = created {
+            if Self::is_long_header(&created, SESSION_CREATED) {
+                // A forged or corrupt SessionCreated leaves the handshake waiting for
+                // the real one.
+                let result = outbound.initiator.as_ref()
+                    .expect("SessionCreated answers a SessionRequest")
+                    .session_created(&created, &self.keys, &self.router_info, &outbound.keys, now);
+                let (confirmed, keys, _) = match result {
+                    Ok(established) => established,
+                    Err(err) => {
+                        self.outbound.insert(address, outbound);
+                        return Err(err);
+                    }
+                };
+                self.socket.send_to(&confirmed, address)?;
+
+                let session = Session::new(outbound.peer.identity().hash_sha256(), address,
+                                           outbound.destination_id, outbound.keys.intro_key, keys, 1);
+                self.add_session(outbound.source_id, session);
+                self.events.push_back(Event::Established(outbound.peer));
+
+                return Ok(());
+            }
+        }
+
+        let mut reply = packet;
+        mask_header(&mut reply, &outbound.keys.intro_key);
+        if !Self::is_long_header(&reply, RETRY) || reply.len() < LONG_HEADER_LENGTH + MAC_LENGTH {
+            self.outbound.insert(address, outbound);
+            return Err(Ssu2Error::InvalidPacket);
+        }
+        obfuscate_long_header(&mut reply, &outbound.keys.intro_key, LONG_HEADER_LENGTH);
+        let header = match open_with_intro_key(&reply, &outbound.keys.intro_key) {
+            Ok((header, _)) if header.token != 0 => header,
+            _ => {
+                self.outbound.insert(address, outbound);
+                return Err(Ssu2Error::InvalidPacket);
+            }
+        };
+
+        let request = LongHeader {
+            destination_id: outbound.destination_id,
+            packet_number: rand::thread_rng().gen(),
+            message_type: SESSION_REQUEST,
+            network_id: NETWORK_ID,
+            source_id: outbound.source_id,
+            token: header.token
+        };
+        let (initiator, packet) = Initiator::session_request(request, &outbound.keys, now);
+        self.socket.send_to(&packet, address)?;
+        outbound.initiator = Some(initiator);
+        self.outbound.insert(address, outbound);
+
+        Ok(())
+    }
+
+    /// Handles a TokenRequest or SessionRequest from a peer without a session.
+    fn receive_new(&mut self, packet: Vec<u8>, address: SocketAddr, now: I2pDate) -> Result<(), Ssu2Error> {
+        let intro_key = *self.keys.intro_key();
+        let mut packet = packet;
+        mask_header(&mut packet, &intro_key);
+
+        if Self::is_long_header(&packet, TOKEN_REQUEST) {
+            obfuscate_long_header(&mut packet, &intro_key, LONG_HEADER_LENGTH);
+            let (header, _) = open_with_intro_key(&packet, &intro_key)?;
+
+            return self.send_retry(header, address, now);
+        }
+        if !Self::is_long_header(&packet, SESSION_REQUEST) || packet.len() < EPHEMERAL_KEY_END {
+            return Err(Ssu2Error::InvalidPacket);
+        }
+
+        obfuscate_long_header(&mut packet, &intro_key, EPHEMERAL_KEY_END);
+        let header = LongHeader::from_bytes(&packet).ok_or(Ssu2Error::InvalidPacket)?;
+        let valid_token = match self.issued.get(&address) {
+            Some(&(token, expires)) => token == header.token && now.to_u64() <= expires,
+            None => false
+        };
+        if !valid_token {
+            return self.send_retry(header, address, now);
+        }
+        self.issued.remove(&address);
+
+        let (responder, created) = Responder::session_request(&packet, &self.keys, address, now)?;
+        self.socket.send_to(&created, address)?;
+        self.inbound.insert(header.destination_id, Inbound {
+            responder,
+            address,
+            destination_id: header.source_id
+        });
+
+        Ok(())
+    }
+
+    /// Answers a TokenRequest, or a SessionRequest without a valid token, with a
+    /// Retry that carries a new token for `address`.
+    fn send_retry(&mut self, request: LongHeader, address: SocketAddr, now: I2pDate) -> Result<(), Ssu2Error> {
+        let mut rng = rand::thread_rng();
+        let token = rng.gen_range(1, u64::MAX);
+        self.prune_tokens(now);
+        self.issued.insert(address, (token, now.to_u64() + TOKEN_LIFETIME_MILLISECONDS));
+
+        let header = LongHeader {
+            destination_id: request.source_id,
+            packet_number: rng.gen(),
+            message_type: RETRY,
+            network_id: NETWORK_ID,
+            source_id: request.destination_id,
+            token
+        };
+        self.socket.send_to(&retry(header, &self.keys, address, now), address)?;
+
+        Ok(())
+    }
+
+    /// Drops expired tokens once the map is full, and the oldest token if none has
+    /// expired.
+    fn prune_tokens(&mut self, now: I2pDate) {
+        if self.issued.len() < self.max_issued_tokens {
+            return;
+        }
+        self.issued.retain(|_, &mut (_, expires)| expires >= now.to_u64());
+        if self.issued.len() >= self.max_issued_tokens {
+            let oldest = self.issued.iter().min_by_key(|&(_, &(_, expires))| expires).map(|(address, _)| *address);
+            if let Some(oldest) = oldest {
+                self.issued.remove(&oldest);
+            }
+        }
+    }
+
+    /// Returns the number of tokens outstanding.
+    pub fn issued_tokens(&self) -> usize {
+        self.issued.len()
+    }
+
+    /// Handles the SessionConfirmed of a handshake a peer started.
+    fn receive_session_confirmed(&mut self, id: u64, packet: Vec<u8>) -> Result<(), Ssu2Error> {
+        let inbound = match self.inbound.remove(&id) {
+            Some(inbound) => inbound,
+            None => return Err(Ssu2Error::InvalidPacket)
+        };
+        let mut packet = packet;
+        mask_header(&mut packet, inbound.responder.confirmed_header_key());
+        if packet[12] != SESSION_CONFIRMED {
+            self.inbound.insert(id, inbound);
+            return Err(Ssu2Error::InvalidPacket);
+        }
+
+        let (keys, peer, peer_keys) = inbound.responder.session_confirmed(&packet, &self.verifier)?;
+        let session = Session::new(peer.identity().hash_sha256(), inbound.address, inbound.destination_id,
+                                   peer_keys.intro_key, keys, 0);
+        self.add_session(id, session);
+        self.events.push_back(Event::Established(peer));
+
+        Ok(())
+    }
+
+    /// Adds a session under our connection ID `id`, replacing any earlier session
+    /// with the same peer.
+    fn add_session(&mut self, id: u64, session: Session) {
+        if let Some(old) = self.peers.insert(session.peer.clone(), id) {
+            self.sessions.remove(&old);
+        }
+        self.outbound.remove(&session.address);
+        self.sessions.insert(id, session);
+    }
+
+    /// Handles a data packet of the session with our connection ID `id`.
+    fn receive_data(&mut self, id: u64, packet: Vec<u8>, address: SocketAddr, now: I2pDate) -> Result<(), Ssu2Error> {
+        let mut packet = packet;
+        let session = self.sessions.get_mut(&id).expect("the caller checked the session exists");
+        mask_header(&mut packet, &session.keys.receive.header_key);
+        let header = ShortHeader::from_bytes(&packet).ok_or(Ssu2Error::InvalidPacket)?;
+        if header.message_type != DATA {
+            return Err(Ssu2Error::InvalidPacket);
+        }
+        let payload = decrypt(&session.keys.receive.data_key, header.packet_number as u64,
+                              &packet[..SHORT_HEADER_LENGTH], &packet[SHORT_HEADER_LENGTH..])
+            .ok_or(Ssu2Error::InvalidPacket)?;
+        if !session.acks.receive(header.packet_number) {
+            return Ok(());
+        }
+        session.received += 1;
+
+        let peer = session.peer.clone();
+        let mut replies = Vec::new();
+        let mut ack_eliciting = false;
+        let mut validated = false;
+        for block in decode_blocks(&payload)? {
+            match block {
+                Block::Ack(ack) => {
+                    for packet_number in ack.acked() {
+                        session.unacked.remove(&packet_number);
+                    }
+                }
+                Block::DateTime(_) | Block::Padding(_) => {}
+                Block::I2np(_) | Block::FirstFragment { .. } | Block::FollowOnFragment { .. } => {
+                    ack_eliciting = true;
+                    if let Some(message) = session.reassembler.receive(block, now) {
+                        self.events.push_back(Event::Message { peer: peer.clone(), message });
+                    }
+                }
+                Block::PathChallenge(data) => replies.push(PathValidator::response(&data)),
+                Block::PathResponse(data) => {
+                    if session.paths.validate(&address, &data) {
+                        session.address = address;
+                        validated = true;
+                        self.events.push_back(Event::PathValidated { peer: peer.clone(), address });
+                    }
+                }
+                Block::Termination { reason, .. } => {
+                    self.sessions.remove(&id);
+                    self.peers.remove(&peer);
+                    self.events.push_back(Event::Terminated { peer, reason });
+
+                    return Ok(());
+                }
+                block => {
+                    ack_eliciting = true;
+                    self.events.push_back(Event::Block { peer: peer.clone(), block });
+                }
+            }
+        }
+
+        if address != session.address && !validated && !session.paths.is_pending(&address) {
+            replies.push(session.paths.challenge(address));
+        }
+        if session.exhausted() {
+            return self.terminate(id, TerminationReason::NormalClose, now);
+        }
+        if ack_eliciting || !replies.is_empty() {
+            let packet = session.seal(replies, now);
+            self.socket.send_to(&packet, address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends the session with our connection ID `id` with a Termination block, and
+    /// reports it as an event.
+    fn terminate(&mut self, id: u64, reason: TerminationReason, now: I2pDate) -> Result<(), Ssu2Error> {
+        let mut session = self.sessions.remove(&id).ok_or(Ssu2Error::NoSession)?;
+        self.peers.remove(&session.peer);
+        let packet = session.seal(vec![Block::Termination { valid_frames: session.received, reason }], now);
+        self.events.push_back(Event::Terminated { peer: session.peer, reason });
+        self.socket.send_to(&packet, session.address)?;
+
+        Ok(())
+    }
+
+    /// Sends I2NP messages to a connected peer, fragmenting those that do not fit in
+    /// one packet. Returns the number of packets sent.
+    pub fn send_messages(&mut self, peer: &Hash256, messages: &[I2npMessage], now: I2pDate) -> Result<usize, Ssu2Error> {
+        let max_length = MAX_PAYLOAD_LENGTH - ACK_RESERVE;
+        let mut packets = vec![Vec::new()];
+        let mut length = 0;
+        for message in messages {
+            for block in fragment(message, max_length)? {
+                if length + block.encoded_len() > max_length {
+                    packets.push(Vec::new());
+                    length = 0;
+                }
+                length += block.encoded_len();
+                packets.last_mut().expect("there is always a packet").push(block);
+            }
+        }
+
+        let mut sent = 0;
+        for blocks in packets.into_iter().filter(|blocks| !blocks.is_empty()) {
+            self.send_blocks(peer, blocks, now)?;
+            sent += 1;
+        }
+
+        Ok(sent)
+    }
+
+    /// Sends blocks to a connected peer in one packet, such as the Peer Test and
+    /// Relay blocks, which other components build. A session that has run out of
+    /// packet numbers is terminated instead.
+    pub fn send_blocks(&mut self, peer: &Hash256, blocks: Vec<Block>, now: I2pDate) -> Result<(), Ssu2Error> {
+        let id = *self.peers.get(peer).ok_or(Ssu2Error::NoSession)?;
+        let session = self.sessions.get_mut(&id).ok_or(Ssu2Error::NoSession)?;
+        if session.exhausted() {
+            self.terminate(id, TerminationReason::NormalClose, now)?;
+            return Err(Ssu2Error::NoSession);
+        }
+        let packet = session.seal(blocks, now);
+        self.socket.send_to(&packet, session.address)?;
+
+        Ok(())
+    }
+
+    /// Sends again, in new packets, the blocks of packets that have gone
+    /// unacknowledged for too long. Sessions with a packet that has been sent too
+    /// many times, or that run out of packet numbers, are terminated and reported in
+    /// `receive`. Returns the number of packets sent.
+    pub fn retransmit(&mut self, now: I2pDate) -> Result<usize, Ssu2Error> {
+        let mut sent = 0;
+        let mut terminated = Vec::new();
+        let max_retransmissions = self.max_retransmissions;
+        for (&id, session) in self.sessions.iter_mut() {
+            let expired: Vec<u32> = session.unacked.iter()
+                .filter(|&(_, unacked)| unacked.sent + RESEND_TIMEOUT_MILLISECONDS <= now.to_u64())
+                .map(|(&packet_number, _)| packet_number)
+                .collect();
+            if expired.iter().any(|packet_number| session.unacked[packet_number].transmissions > max_retransmissions) {
+                terminated.push((id, TerminationReason::IdleTimeout));
+                continue;
+            }
+            for packet_number in expired {
+                if session.exhausted() {
+                    terminated.push((id, TerminationReason::NormalClose));
+                    break;
+                }
+                if let Some(unacked) = session.unacked.remove(&packet_number) {
+                    let packet = session.seal_transmission(unacked.blocks, unacked.transmissions + 1, now);
+                    self.socket.send_to(&packet, session.address)?;
+                    sent += 1;
+                }
+            }
+        }
+        for (id, reason) in terminated {
+            self.terminate(id, reason, now)?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Sends a Termination block to a connected peer and drops the session.
+    pub fn close(&mut self, peer: &Hash256, reason: TerminationReason, now: I2pDate) -> Result<(), Ssu2Error> {
+        let id = self.peers.remove(peer).ok_or(Ssu2Error::NoSession)?;
+        let mut session = self.sessions.remove(&id).ok_or(Ssu2Error::NoSession)?;
+        let packet = session.seal(vec![Block::Termination { valid_frames: session.received, reason }], now);
+        self.socket.send_to(&packet, session.address)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use common::I2pDate;
+
+
+/// The number of bits set for each entry.
+const HASH_COUNT: u64 = 4;
+
+/// A `DecayingBloomFilter` remembers entries for between one and two decay periods.
+/// It keeps two bit arrays and, each period, drops the older one and starts a new
+/// one. Like any Bloom filter it may report an entry it has not seen, but never
+/// misses one it has.
+pub struct DecayingBloomFilter {
+    current: Vec<u64>,
+    previous: Vec<u64>,
+    bits: u64,
+    period: u64,
+    rotated: u64,
+    hashers: (RandomState, RandomState)
+}
+
+impl DecayingBloomFilter {
+    /// Creates a filter of `bits` bits per array that forgets entries after
+    /// `period` to `2 * period` milliseconds.
+    pub fn new(bits: usize, period: u64, now: I2pDate) -> DecayingBloomFilter {
+        let words = bits.div_ceil(64);
+
+        DecayingBloomFilter {
+            current: vec![0; words],
+            previous: vec![0; words],
+            bits: (words * 64) as u64,
+            period,
+            rotated: now.to_u64(),
+            hashers: (RandomState::new(), RandomState::new())
+        }
+    }
+
+    /// Returns the bit positions of an entry, by double hashing.
+    fn positions(&self, entry: &[u8]) -> Vec<u64> {
+        let first = self.hashers.0.hash_one(entry);
+        let second = self.hashers.1.hash_one(entry) | 1;
+
+        (0..HASH_COUNT).map(|i| first.wrapping_add(i.wrapping_mul(second)) % self.bits).collect()
+    }
+
+    fn decay(&mut self, now: I2pDate) {
+        let elapsed = now.to_u64().saturating_sub(self.rotated);
+        if elapsed < self.period {
+            return;
+        }
+
+        if elapsed >= 2 * self.period {
+            for word in self.previous.iter_mut() {
+                *word = 0;
+            }
+        } else {
+            self.previous.copy_from_slice(&self.current);
+        }
+        for word in self.current.iter_mut() {
+            *word = 0;
+        }
+        self.rotated = now.to_u64();
+    }
+
+    /// Adds an entry. Returns `true` if the entry was, probably, already present.
+    pub fn add(&mut self, entry: &[u8], now: I2pDate) -> bool {
+        self.decay(now);
+
+        let positions = self.positions(entry);
+        let is_set = |words: &[u64], position: u64| words[(position / 64) as usize] & (1 << (position % 64)) != 0;
+        let present = positions.iter().all(|&position| is_set(&self.current, position))
+            || positions.iter().all(|&position| is_set(&self.previous, position));
+
+        for position in positions {
+            self.current[(position / 64) as usize] |= 1 << (position % 64);
+        }
+
+        present
+    }
+}
@@ -0,0 +1,50 @@
+use std::error;
+use std::fmt;
+use tunnel::tunnel_id::TunnelId;
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TunnelError {
+    /// A TunnelData message is not 1028 bytes long or has the wrong type.
+    InvalidMessage,
+    /// No tunnel we take part in has this receive tunnel ID.
+    UnknownTunnel(TunnelId),
+    /// The IV of a TunnelData message was seen before, so it is a replay.
+    DuplicateIv,
+    /// A tunnel option is malformed or out of range.
+    InvalidOption(String),
+}
+
+impl fmt::Display for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TunnelError::InvalidMessage => {
+                writeln!(f, "Error: The TunnelData message is malformed.")
+            }
+            TunnelError::UnknownTunnel(tunnel_id) => {
+                writeln!(f, "Error: No tunnel with the ID {} is known.", tunnel_id)
+            }
+            TunnelError::DuplicateIv => {
+                writeln!(f, "Error: The TunnelData message repeats an IV seen before.")
+            }
+            TunnelError::InvalidOption(ref option) => {
+                writeln!(f, "Error: The tunnel option {} is invalid.", option)
+            }
+        }
+    }
+}
+
+impl error::Error for TunnelError {
+    fn description(&self) -> &str {
+        match *self {
+            TunnelError::InvalidMessage => "The TunnelData message is malformed.",
+            TunnelError::UnknownTunnel(_) => "No tunnel with the ID is known.",
+            TunnelError::DuplicateIv => "The TunnelData message repeats an IV seen before.",
+            TunnelError::InvalidOption(_) => "A tunnel option is invalid.",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
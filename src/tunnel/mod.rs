@@ -0,0 +1,14 @@
+pub use self::tunnel_id::TunnelId;
+pub use self::error::TunnelError;
+pub use self::bloom::DecayingBloomFilter;
+pub use self::transit::{TransitManager, TransitConfig, HopConfig, HopRole, BlockCipher, AesCipher, TransitAction};
+pub use self::transit::{BuildReply, TUNNEL_LIFETIME_MILLISECONDS, TUNNEL_DATA_LENGTH};
+pub use self::pool::{TunnelPool, ClientTunnels, PoolSettings, PooledTunnel, TunnelDirection};
+pub use self::pool::{TunnelBuilder, TestSender, tunnel_expiration, MAX_TEST_FAILURES, REBUILD_MARGIN_MILLISECONDS};
+
+
+mod tunnel_id;
+mod error;
+mod bloom;
+mod transit;
+mod pool;
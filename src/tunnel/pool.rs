@@ -0,0 +1,412 @@
+use std::cmp;
+use std::collections::HashMap;
+use rand;
+use rand::Rng;
+use common::{Hash256, I2pDate, I2pInt64, Lease, Mapping};
+use i2np::{I2npMessage, MessageType};
+use tunnel::error::TunnelError;
+use tunnel::transit::TUNNEL_LIFETIME_MILLISECONDS;
+use tunnel::tunnel_id::TunnelId;
+
+
+/// Tunnels are replaced when they have less than this many milliseconds to live.
+pub const REBUILD_MARGIN_MILLISECONDS: u64 = 2 * 60 * 1000;
+
+/// A tunnel is retired after failing this many tests in a row.
+pub const MAX_TEST_FAILURES: u8 = 2;
+
+/// A test fails if its DeliveryStatus message has not come back in time.
+const TEST_TIMEOUT_MILLISECONDS: u64 = 10 * 1000;
+
+/// A LeaseSet holds at most 16 leases.
+const MAX_LEASES: usize = 16;
+
+const MAX_LENGTH: u8 = 7;
+const MAX_QUANTITY: u8 = 16;
+
+/// The direction of the tunnels in a pool.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TunnelDirection {
+    Inbound,
+    Outbound
+}
+
+impl TunnelDirection {
+    /// Returns the prefix of the I2CP options for the direction.
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            TunnelDirection::Inbound => "inbound",
+            TunnelDirection::Outbound => "outbound"
+        }
+    }
+}
+
+/// The shape of the tunnels in a pool, set by the `inbound.*` and `outbound.*`
+/// I2CP options.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoolSettings {
+    /// The number of hops, not counting ourselves.
+    pub length: u8,
+    /// The number of tunnels to keep in use.
+    pub quantity: u8,
+    /// A positive variance adds 0 to `length_variance` hops to each tunnel; a
+    /// negative one adds between `length_variance` and `-length_variance` hops.
+    pub length_variance: i8,
+    /// The number of spare tunnels to keep.
+    pub backup_quantity: u8
+}
+
+impl Default for PoolSettings {
+    fn default() -> PoolSettings {
+        PoolSettings {
+            length: 3,
+            quantity: 2,
+            length_variance: 0,
+            backup_quantity: 0
+        }
+    }
+}
+
+fn parse_option<T: ::std::str::FromStr>(options: &Mapping, key: &str, default: T) -> Result<T, TunnelError> {
+    match options.get(key) {
+        Some(value) => value.trim().parse().map_err(|_| TunnelError::InvalidOption(key.to_string())),
+        None => Ok(default)
+    }
+}
+
+impl PoolSettings {
+    /// Reads the settings for a direction from I2CP options, such as
+    /// `inbound.length` or `outbound.backupQuantity`. Missing options keep their
+    /// defaults.
+    pub fn from_options(direction: TunnelDirection, options: &Mapping) -> Result<PoolSettings, TunnelError> {
+        let defaults = PoolSettings::default();
+        let key = |name: &str| format!("{}.{}", direction.prefix(), name);
+
+        let settings = PoolSettings {
+            length: parse_option(options, &key("length"), defaults.length)?,
+            quantity: parse_option(options, &key("quantity"), defaults.quantity)?,
+            length_variance: parse_option(options, &key("lengthVariance"), defaults.length_variance)?,
+            backup_quantity: parse_option(options, &key("backupQuantity"), defaults.backup_quantity)?
+        };
+
+        if settings.length > MAX_LENGTH {
+            return Err(TunnelError::InvalidOption(key("length")));
+        }
+        if settings.quantity == 0 || settings.quantity > MAX_QUANTITY {
+            return Err(TunnelError::InvalidOption(key("quantity")));
+        }
+        if settings.length_variance.unsigned_abs() > MAX_LENGTH {
+            return Err(TunnelError::InvalidOption(key("lengthVariance")));
+        }
+        if settings.backup_quantity > MAX_QUANTITY {
+            return Err(TunnelError::InvalidOption(key("backupQuantity")));
+        }
+
+        Ok(settings)
+    }
+
+    /// Picks the length of a new tunnel, applying the variance.
+    pub fn pick_length(&self) -> u8 {
+        let variance = self.length_variance as i16;
+        let offset = match variance {
+            0 => 0,
+            v if v > 0 => rand::thread_rng().gen_range(0, v + 1),
+            v => rand::thread_rng().gen_range(v, -v + 1)
+        };
+
+        cmp::max(0, cmp::min(MAX_LENGTH as i16, self.length as i16 + offset)) as u8
+    }
+}
+
+/// A `PooledTunnel` is one of our own tunnels. For an inbound tunnel the gateway is
+/// its first hop and `gateway_id` the tunnel ID other routers send to; for an
+/// outbound tunnel they are where we send messages into the tunnel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PooledTunnel {
+    pub gateway: Hash256,
+    pub gateway_id: TunnelId,
+    pub hops: Vec<Hash256>,
+    pub expiration: I2pDate,
+    failures: u8
+}
+
+impl PooledTunnel {
+    pub fn new(gateway: Hash256, gateway_id: TunnelId, hops: Vec<Hash256>, expiration: I2pDate) -> PooledTunnel {
+        PooledTunnel {
+            gateway,
+            gateway_id,
+            hops,
+            expiration,
+            failures: 0
+        }
+    }
+
+    /// Returns the number of tests this tunnel has failed in a row.
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+}
+
+/// The `TunnelBuilder` trait starts tunnel builds. Builds finish asynchronously,
+/// through `TunnelPool::tunnel_built` or `TunnelPool::build_failed`.
+pub trait TunnelBuilder {
+    /// Starts building a tunnel of `length` hops. Returns `false` if the build
+    /// could not be started.
+    fn build(&mut self, direction: TunnelDirection, length: u8) -> bool;
+}
+
+/// A `TunnelPool` keeps `quantity` plus `backup_quantity` tunnels of one direction
+/// for a client, building replacements before they expire.
+pub struct TunnelPool {
+    direction: TunnelDirection,
+    settings: PoolSettings,
+    tunnels: Vec<PooledTunnel>,
+    pending: usize
+}
+
+impl TunnelPool {
+    pub fn new(direction: TunnelDirection, settings: PoolSettings) -> TunnelPool {
+        TunnelPool {
+            direction,
+            settings,
+            tunnels: Vec::new(),
+            pending: 0
+        }
+    }
+
+    pub fn direction(&self) -> TunnelDirection {
+        self.direction
+    }
+
+    pub fn settings(&self) -> &PoolSettings {
+        &self.settings
+    }
+
+    pub fn tunnels(&self) -> &[PooledTunnel] {
+        self.tunnels.as_ref()
+    }
+
+    /// Returns the number of builds in progress.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Drops expired tunnels and starts builds until the tunnels that are not about
+    /// to expire, plus the builds in progress, make up the wanted quantity. Returns
+    /// the number of builds started.
+    pub fn maintain<B: TunnelBuilder>(&mut self, now: I2pDate, builder: &mut B) -> usize {
+        self.tunnels.retain(|tunnel| tunnel.expiration > now);
+
+        let fresh = self.tunnels.iter()
+            .filter(|tunnel| tunnel.expiration.to_u64() > now.to_u64() + REBUILD_MARGIN_MILLISECONDS)
+            .count();
+        let wanted = (self.settings.quantity + self.settings.backup_quantity) as usize;
+
+        let mut started = 0;
+        while fresh + self.pending < wanted {
+            if !builder.build(self.direction, self.settings.pick_length()) {
+                break;
+            }
+            self.pending += 1;
+            started += 1;
+        }
+
+        started
+    }
+
+    /// Adds a tunnel whose build succeeded.
+    pub fn tunnel_built(&mut self, tunnel: PooledTunnel) {
+        self.pending = self.pending.saturating_sub(1);
+        self.tunnels.push(tunnel);
+    }
+
+    /// Records a build that failed; the next `maintain` starts another.
+    pub fn build_failed(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    /// Picks a random tunnel to use, preferring those that have passed their tests.
+    pub fn select(&self) -> Option<&PooledTunnel> {
+        let healthy: Vec<&PooledTunnel> = self.tunnels.iter().filter(|tunnel| tunnel.failures == 0).collect();
+        let candidates: Vec<&PooledTunnel> = if healthy.is_empty() { self.tunnels.iter().collect() } else { healthy };
+
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[rand::thread_rng().gen_range(0, candidates.len())])
+    }
+
+    /// Records the result of a test of the tunnel with the gateway and gateway
+    /// tunnel ID. Tunnel IDs are chosen by each gateway, so only the pair tells
+    /// tunnels apart. Returns `true` if the tunnel failed too often and was retired.
+    pub fn record_test(&mut self, gateway: &Hash256, gateway_id: TunnelId, success: bool) -> bool {
+        let index = match self.tunnels.iter().position(|tunnel| tunnel.gateway == *gateway && tunnel.gateway_id == gateway_id) {
+            Some(index) => index,
+            None => return false
+        };
+
+        if success {
+            self.tunnels[index].failures = 0;
+            return false;
+        }
+        self.tunnels[index].failures += 1;
+        if self.tunnels[index].failures >= MAX_TEST_FAILURES {
+            self.tunnels.remove(index);
+            return true;
+        }
+
+        false
+    }
+
+    /// Returns the leases of the inbound tunnels, most recent first, for the
+    /// destination's LeaseSet.
+    pub fn leases(&self, now: I2pDate) -> Vec<Lease> {
+        if self.direction != TunnelDirection::Inbound {
+            return Vec::new();
+        }
+
+        let mut tunnels: Vec<&PooledTunnel> = self.tunnels.iter().filter(|tunnel| tunnel.expiration > now).collect();
+        tunnels.sort_by_key(|tunnel| cmp::Reverse(tunnel.expiration.to_u64()));
+
+        tunnels.into_iter()
+            .take(MAX_LEASES)
+            .map(|tunnel| Lease::new(tunnel.gateway.clone(), tunnel.gateway_id, tunnel.expiration))
+            .collect()
+    }
+}
+
+/// The `TestSender` trait sends a message out through one of our outbound tunnels
+/// to the gateway of an inbound tunnel.
+pub trait TestSender {
+    fn send(&mut self, outbound: &PooledTunnel, gateway: &Hash256, gateway_id: TunnelId, message: I2npMessage) -> bool;
+}
+
+struct PendingTest {
+    outbound: (Hash256, TunnelId),
+    inbound: (Hash256, TunnelId),
+    sent: u64
+}
+
+/// `ClientTunnels` are the inbound and outbound pools of a client destination.
+/// Tunnels are tested in pairs: a DeliveryStatus message goes out through an
+/// outbound tunnel and comes back through an inbound one. A pair that does not
+/// deliver it counts as a failure for both tunnels.
+pub struct ClientTunnels {
+    inbound: TunnelPool,
+    outbound: TunnelPool,
+    tests: HashMap<u32, PendingTest>
+}
+
+impl ClientTunnels {
+    pub fn new(inbound: PoolSettings, outbound: PoolSettings) -> ClientTunnels {
+        ClientTunnels {
+            inbound: TunnelPool::new(TunnelDirection::Inbound, inbound),
+            outbound: TunnelPool::new(TunnelDirection::Outbound, outbound),
+            tests: HashMap::new()
+        }
+    }
+
+    /// Creates the pools from the `inbound.*` and `outbound.*` I2CP options.
+    pub fn from_options(options: &Mapping) -> Result<ClientTunnels, TunnelError> {
+        Ok(ClientTunnels::new(PoolSettings::from_options(TunnelDirection::Inbound, options)?,
+                              PoolSettings::from_options(TunnelDirection::Outbound, options)?))
+    }
+
+    pub fn inbound(&self) -> &TunnelPool {
+        &self.inbound
+    }
+
+    pub fn inbound_mut(&mut self) -> &mut TunnelPool {
+        &mut self.inbound
+    }
+
+    pub fn outbound(&self) -> &TunnelPool {
+        &self.outbound
+    }
+
+    pub fn outbound_mut(&mut self) -> &mut TunnelPool {
+        &mut self.outbound
+    }
+
+    /// Maintains both pools. Returns the number of builds started.
+    pub fn maintain<B: TunnelBuilder>(&mut self, now: I2pDate, builder: &mut B) -> usize {
+        self.inbound.maintain(now, builder) + self.outbound.maintain(now, builder)
+    }
+
+    /// Tests every tunnel at least once, pairing the tunnels of the two pools in
+    /// turn. Returns the number of tests sent.
+    pub fn test<S: TestSender>(&mut self, now: I2pDate, sender: &mut S) -> usize {
+        let (inbound, outbound) = (self.inbound.tunnels(), self.outbound.tunnels());
+        if inbound.is_empty() || outbound.is_empty() {
+            return 0;
+        }
+
+        let mut sent = 0;
+        let mut rng = rand::thread_rng();
+        for i in 0..cmp::max(inbound.len(), outbound.len()) {
+            let (out_tunnel, in_tunnel) = (&outbound[i % outbound.len()], &inbound[i % inbound.len()]);
+            let message_id = rng.gen::<u32>();
+            let mut payload = message_id.to_be_bytes().to_vec();
+            payload.extend_from_slice(&now.to_u64().to_be_bytes());
+            let expiration = I2pDate::new(I2pInt64::new(now.to_u64() + TEST_TIMEOUT_MILLISECONDS)).unwrap();
+            let message = I2npMessage::new(MessageType::DeliveryStatus, message_id, expiration, payload);
+
+            if sender.send(out_tunnel, &in_tunnel.gateway, in_tunnel.gateway_id, message) {
+                self.tests.insert(message_id, PendingTest {
+                    outbound: (out_tunnel.gateway.clone(), out_tunnel.gateway_id),
+                    inbound: (in_tunnel.gateway.clone(), in_tunnel.gateway_id),
+                    sent: now.to_u64()
+                });
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    /// Handles a DeliveryStatus message that came back through an inbound tunnel.
+    /// Returns `true` if it completes one of our tests.
+    pub fn handle_delivery_status(&mut self, message: &I2npMessage) -> bool {
+        if message.message_type != MessageType::DeliveryStatus || message.payload.len() < 4 {
+            return false;
+        }
+        let message_id = message.payload[0..4].iter().fold(0, |value, byte| (value << 8) | (*byte as u32));
+
+        match self.tests.remove(&message_id) {
+            Some(test) => {
+                self.outbound.record_test(&test.outbound.0, test.outbound.1, true);
+                self.inbound.record_test(&test.inbound.0, test.inbound.1, true);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Fails the tests that have not come back in time. Returns the number of
+    /// tunnels retired.
+    pub fn expire_tests(&mut self, now: I2pDate) -> usize {
+        let expired: Vec<u32> = self.tests.iter()
+            .filter(|&(_, test)| test.sent + TEST_TIMEOUT_MILLISECONDS < now.to_u64())
+            .map(|(message_id, _)| *message_id)
+            .collect();
+
+        let mut retired = 0;
+        for message_id in expired {
+            let test = self.tests.remove(&message_id).unwrap();
+            retired += self.outbound.record_test(&test.outbound.0, test.outbound.1, false) as usize;
+            retired += self.inbound.record_test(&test.inbound.0, test.inbound.1, false) as usize;
+        }
+
+        retired
+    }
+
+    /// Returns the leases of the inbound tunnels for the destination's LeaseSet.
+    pub fn leases(&self, now: I2pDate) -> Vec<Lease> {
+        self.inbound.leases(now)
+    }
+}
+
+/// Returns the expiration of a tunnel built at `now`.
+pub fn tunnel_expiration(now: I2pDate) -> I2pDate {
+    I2pDate::new(I2pInt64::new(now.to_u64() + TUNNEL_LIFETIME_MILLISECONDS)).unwrap()
+}
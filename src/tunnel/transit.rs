@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use aes::Aes256;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use rand;
+use rand::Rng;
+use common::{Hash256, I2pDate, SessionKey};
+use i2np::{I2npMessage, MessageType};
+use tunnel::bloom::DecayingBloomFilter;
+use tunnel::error::TunnelError;
+use tunnel::tunnel_id::TunnelId;
+
+
+/// Tunnels live for ten minutes after they are built.
+pub const TUNNEL_LIFETIME_MILLISECONDS: u64 = 10 * 60 * 1000;
+
+/// A TunnelData message is the tunnel ID, a 16 byte IV and 1008 bytes of data.
+pub const TUNNEL_DATA_LENGTH: usize = 1028;
+
+const TUNNEL_ID_LENGTH: usize = 4;
+const IV_LENGTH: usize = 16;
+
+/// The number of bits in each array of the IV filter.
+const IV_FILTER_BITS: usize = 1 << 20;
+
+/// The `BlockCipher` trait encrypts a single block with AES-256, which is all the
+/// layer processing of a tunnel hop needs.
+pub trait BlockCipher {
+    fn encrypt_block(&self, key: &SessionKey, block: &mut [u8; 16]);
+}
+
+/// An `AesCipher` is the AES-256 block cipher of the `aes` crate.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AesCipher;
+
+impl BlockCipher for AesCipher {
+    fn encrypt_block(&self, key: &SessionKey, block: &mut [u8; 16]) {
+        let cipher = Aes256::new(key.as_ref().into());
+        cipher.encrypt_block(block.into());
+    }
+}
+
+/// The position of our router in a tunnel we take part in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HopRole {
+    /// We apply our layer and forward the message to the next hop.
+    Participant,
+    /// We apply our layer and deliver the message contents ourselves.
+    OutboundEndpoint
+}
+
+/// A `HopConfig` is what a build request asks of us as one hop of a tunnel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HopConfig {
+    pub receive_id: TunnelId,
+    pub next_id: TunnelId,
+    pub next_router: Hash256,
+    pub layer_key: SessionKey,
+    pub iv_key: SessionKey,
+    pub role: HopRole
+}
+
+/// The answer to a tunnel build request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildReply {
+    Accept,
+    ProbabilisticReject,
+    TransientOverload,
+    Bandwidth,
+    Critical
+}
+
+impl BuildReply {
+    /// Returns the reply code of the build response record.
+    pub fn code(&self) -> u8 {
+        match *self {
+            BuildReply::Accept              => 0,
+            BuildReply::ProbabilisticReject => 10,
+            BuildReply::TransientOverload   => 20,
+            BuildReply::Bandwidth           => 30,
+            BuildReply::Critical            => 50
+        }
+    }
+}
+
+/// What to do with a TunnelData message after applying our layer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransitAction {
+    /// Send the message to the next hop.
+    Forward { router: Hash256, message: I2npMessage },
+    /// We are the outbound endpoint: the IV and data are ready to be reassembled
+    /// and delivered.
+    Endpoint { tunnel_id: TunnelId, data: Vec<u8> }
+}
+
+/// The limits on the tunnels we participate in. `share_rate` is the bandwidth we
+/// share, in bytes per second, and `tunnel_bandwidth` the bandwidth we expect each
+/// tunnel to use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransitConfig {
+    pub max_tunnels: usize,
+    pub share_rate: u64,
+    pub tunnel_bandwidth: u64
+}
+
+impl Default for TransitConfig {
+    fn default() -> TransitConfig {
+        TransitConfig {
+            max_tunnels: 5000,
+            share_rate: 32 * 1024,
+            tunnel_bandwidth: 256
+        }
+    }
+}
+
+struct TransitTunnel {
+    config: HopConfig,
+    created: u64
+}
+
+/// A `TransitManager` runs the tunnels other routers build through us. It accepts
+/// build requests while we are below the tunnel limit and the bandwidth we share,
+/// applies our layer to their TunnelData messages, and drops replayed messages by
+/// remembering the IVs it has seen.
+pub struct TransitManager<C> {
+    config: TransitConfig,
+    cipher: C,
+    tunnels: HashMap<TunnelId, TransitTunnel>,
+    ivs: DecayingBloomFilter
+}
+
+impl<C> TransitManager<C> where C: BlockCipher {
+    pub fn new(config: TransitConfig, cipher: C, now: I2pDate) -> TransitManager<C> {
+        TransitManager {
+            config,
+            cipher,
+            tunnels: HashMap::new(),
+            ivs: DecayingBloomFilter::new(IV_FILTER_BITS, TUNNEL_LIFETIME_MILLISECONDS, now)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tunnels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tunnels.is_empty()
+    }
+
+    /// Returns the hop configuration of the tunnel with the receive tunnel ID.
+    pub fn get(&self, receive_id: TunnelId) -> Option<&HopConfig> {
+        self.tunnels.get(&receive_id).map(|tunnel| &tunnel.config)
+    }
+
+    /// Decides on a build request, and stores the hop if we accept it.
+    pub fn handle_build_request(&mut self, config: HopConfig, now: I2pDate) -> BuildReply {
+        if self.tunnels.contains_key(&config.receive_id) {
+            return BuildReply::Critical;
+        }
+        // Both limits answer with a bandwidth rejection, as other routers do, so as
+        // not to reveal which one we hit.
+        if self.tunnels.len() >= self.config.max_tunnels {
+            return BuildReply::Bandwidth;
+        }
+        let bandwidth = (self.tunnels.len() as u64 + 1) * self.config.tunnel_bandwidth;
+        if bandwidth > self.config.share_rate {
+            return BuildReply::Bandwidth;
+        }
+
+        self.tunnels.insert(config.receive_id, TransitTunnel { config, created: now.to_u64() });
+
+        BuildReply::Accept
+    }
+
+    /// Encrypts the IV and data of a TunnelData message with our layer: the IV is
+    /// encrypted with the IV key, the data with the layer key in CBC mode under that
+    /// IV, and the IV is encrypted once more.
+    fn apply_layer(&self, config: &HopConfig, iv: &mut [u8; 16], data: &mut [u8]) {
+        self.cipher.encrypt_block(&config.iv_key, iv);
+
+        let mut previous = *iv;
+        for chunk in data.chunks_mut(IV_LENGTH) {
+            let mut block = [0x00; 16];
+            block.copy_from_slice(chunk);
+            for (byte, previous) in block.iter_mut().zip(previous.iter()) {
+                *byte ^= *previous;
+            }
+            self.cipher.encrypt_block(&config.layer_key, &mut block);
+            chunk.copy_from_slice(&block);
+            previous = block;
+        }
+
+        self.cipher.encrypt_block(&config.iv_key, iv);
+    }
+
+    /// Processes a TunnelData message received for one of our transit tunnels.
+    pub fn handle_tunnel_data(&mut self, message: &I2npMessage, now: I2pDate) -> Result<TransitAction, TunnelError> {
+        if message.message_type != MessageType::TunnelData || message.payload.len() != TUNNEL_DATA_LENGTH {
+            return Err(TunnelError::InvalidMessage);
+        }
+
+        let receive_id = TunnelId::from_bytes_be(&message.payload[..TUNNEL_ID_LENGTH]).unwrap();
+        let config = match self.tunnels.get(&receive_id) {
+            Some(tunnel) => tunnel.config.clone(),
+            None => return Err(TunnelError::UnknownTunnel(receive_id))
+        };
+
+        let iv_end = TUNNEL_ID_LENGTH + IV_LENGTH;
+        if self.ivs.add(&message.payload[TUNNEL_ID_LENGTH..iv_end], now) {
+            return Err(TunnelError::DuplicateIv);
+        }
+
+        let mut iv = [0x00; 16];
+        iv.copy_from_slice(&message.payload[TUNNEL_ID_LENGTH..iv_end]);
+        let mut data = message.payload[iv_end..].to_vec();
+        self.apply_layer(&config, &mut iv, &mut data);
+
+        let mut payload = Vec::with_capacity(TUNNEL_DATA_LENGTH);
+        match config.role {
+            HopRole::Participant => {
+                payload.extend_from_slice(&config.next_id.to_bytes_be());
+                payload.extend_from_slice(&iv);
+                payload.extend_from_slice(&data);
+                let message_id = rand::thread_rng().gen::<u32>();
+                let message = I2npMessage::new(MessageType::TunnelData, message_id, message.expiration, payload);
+
+                Ok(TransitAction::Forward { router: config.next_router, message })
+            }
+            HopRole::OutboundEndpoint => {
+                payload.extend_from_slice(&iv);
+                payload.extend_from_slice(&data);
+
+                Ok(TransitAction::Endpoint { tunnel_id: receive_id, data: payload })
+            }
+        }
+    }
+
+    /// Drops the tunnels built more than ten minutes before `now`. Returns the
+    /// number dropped.
+    pub fn expire(&mut self, now: I2pDate) -> usize {
+        let before = self.tunnels.len();
+        self.tunnels.retain(|_, tunnel| tunnel.created.saturating_add(TUNNEL_LIFETIME_MILLISECONDS) > now.to_u64());
+
+        before - self.tunnels.len()
+    }
+}